@@ -40,8 +40,10 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         };
 
         // All attributes are mutually exclusive. If we encounter multiple or duplicate attributes,
-        // then we'll error out.
+        // then we'll error out. The only exception is `#[legacy_ids(...)]`, which can be added to
+        // fields with an `#[id = "..."]` attribute.
         let mut processed_attribute = false;
+        let mut legacy_ids: Option<(Vec<syn::LitStr>, proc_macro2::Span)> = None;
         for attr in &field.attrs {
             if attr.path.is_ident("id") {
                 match attr.parse_meta() {
@@ -76,6 +78,7 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                         params.push(Param::Single {
                             id: s,
                             field: field_name.clone(),
+                            legacy_ids: Vec::new(),
                         });
 
                         processed_attribute = true;
@@ -90,6 +93,37 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                         .into()
                     }
                 };
+            } else if attr.path.is_ident("legacy_ids") {
+                let parsed_ids = match attr.parse_meta() {
+                    Ok(syn::Meta::List(syn::MetaList { nested, .. })) if !nested.is_empty() => {
+                        nested
+                            .into_iter()
+                            .map(|nested_meta| match nested_meta {
+                                syn::NestedMeta::Lit(syn::Lit::Str(s)) => Some(s),
+                                _ => None,
+                            })
+                            .collect::<Option<Vec<_>>>()
+                    }
+                    _ => None,
+                };
+
+                match parsed_ids {
+                    Some(ids) if legacy_ids.is_none() => legacy_ids = Some((ids, attr.span())),
+                    Some(_) => {
+                        return syn::Error::new(attr.span(), "Duplicate legacy_ids attribute found")
+                            .to_compile_error()
+                            .into()
+                    }
+                    None => {
+                        return syn::Error::new(
+                            attr.span(),
+                            "The legacy_ids attribute should be a list of strings: \
+                             #[legacy_ids(\"old_id\", \"older_id\")]",
+                        )
+                        .to_compile_error()
+                        .into()
+                    }
+                }
             } else if attr.path.is_ident("persist") {
                 match attr.parse_meta() {
                     Ok(syn::Meta::NameValue(syn::MetaNameValue {
@@ -244,6 +278,51 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 };
             }
         }
+
+        if let Some((ids, span)) = legacy_ids {
+            match params.last_mut() {
+                Some(Param::Single {
+                    field,
+                    legacy_ids: param_legacy_ids,
+                    ..
+                }) if *field == *field_name => *param_legacy_ids = ids,
+                _ => {
+                    return syn::Error::new(
+                        span,
+                        "The legacy_ids attribute can only be used together with an id attribute",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+            }
+        }
+    }
+
+    // Legacy IDs may not be used as the current ID of another parameter on this struct, and they
+    // cannot be shared between parameters
+    let mut seen_legacy_ids: Vec<&syn::LitStr> = Vec::new();
+    for param in &params {
+        if let Param::Single { legacy_ids, .. } = param {
+            for legacy_id in legacy_ids {
+                let conflicts_with_id = params.iter().any(|p| match p {
+                    Param::Single { id, .. } => id.value() == legacy_id.value(),
+                    _ => false,
+                });
+                let conflicts_with_legacy_id = seen_legacy_ids
+                    .iter()
+                    .any(|s| s.value() == legacy_id.value());
+                if conflicts_with_id || conflicts_with_legacy_id {
+                    return syn::Error::new(
+                        legacy_id.span(),
+                        "This legacy ID is already used by another parameter",
+                    )
+                    .to_compile_error()
+                    .into();
+                }
+
+                seen_legacy_ids.push(legacy_id);
+            }
+        }
     }
 
     // The next step is build the gathered information into tokens that can be spliced into a
@@ -263,6 +342,18 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
         }
     };
 
+    let legacy_param_ids_tokens = {
+        let legacy_id_mapping_tokens = params.iter().filter_map(|p| p.legacy_param_ids_tokens());
+
+        quote! {
+            #[allow(unused_mut)]
+            let mut legacy_ids = Vec::new();
+            #(legacy_ids.extend(#legacy_id_mapping_tokens); )*
+
+            legacy_ids
+        }
+    };
+
     let (serialize_fields_tokens, deserialize_fields_tokens) = {
         // Like with `param_map()`, we'll try to do the serialization for this struct and then
         // recursively call the child parameter structs. We don't know anything about the actual
@@ -416,6 +507,10 @@ pub fn derive_params(input: TokenStream) -> TokenStream {
                 #param_map_tokens
             }
 
+            fn legacy_param_ids(&self) -> Vec<(String, String)> {
+                #legacy_param_ids_tokens
+            }
+
             fn serialize_fields(&self) -> ::std::collections::BTreeMap<String, String> {
                 #serialize_fields_tokens
             }
@@ -438,6 +533,8 @@ enum Param {
         field: syn::Ident,
         /// The parameter's unique ID.
         id: syn::LitStr,
+        /// IDs this parameter used to have, from the `#[legacy_ids(...)]` attribute.
+        legacy_ids: Vec<syn::LitStr>,
     },
     /// Another struct also implementing `Params`. This object's parameters are inlined in the
     /// parameter list.
@@ -449,12 +546,29 @@ impl Param {
     /// parameter map.
     fn param_map_tokens(&self) -> proc_macro2::TokenStream {
         match self {
-            Param::Single { field, id } => {
+            Param::Single { field, id, .. } => {
                 quote! { [(String::from(#id), self.#field.as_ptr(), String::new())] }
             }
             Param::Nested(params) => params.param_map_tokens(),
         }
     }
+
+    /// Generate the tokens needed to add the `(legacy_id, current_id)` pairs for a field (or nested
+    /// parameter struct) to the list of legacy parameter IDs. Returns `None` for parameters without
+    /// any legacy IDs.
+    fn legacy_param_ids_tokens(&self) -> Option<proc_macro2::TokenStream> {
+        match self {
+            Param::Single { legacy_ids, .. } if legacy_ids.is_empty() => None,
+            Param::Single { id, legacy_ids, .. } => {
+                let pairs = legacy_ids
+                    .iter()
+                    .map(|legacy_id| quote! { (String::from(#legacy_id), String::from(#id)) });
+
+                Some(quote! { [#(#pairs),*] })
+            }
+            Param::Nested(params) => Some(params.legacy_param_ids_tokens()),
+        }
+    }
 }
 
 /// A field containing data that must be stored in the plugin's state.
@@ -492,6 +606,36 @@ enum NestedParams {
 }
 
 impl NestedParams {
+    /// The same as [`param_map_tokens()`][Self::param_map_tokens()], but for the nested object's
+    /// legacy parameter IDs. Both the legacy and the current IDs get the same prefixes and
+    /// suffixes as the regular parameter IDs.
+    fn legacy_param_ids_tokens(&self) -> proc_macro2::TokenStream {
+        match self {
+            NestedParams::Inline { field, .. } => quote! {
+                self.#field.legacy_param_ids()
+            },
+            NestedParams::Prefixed {
+                field, id_prefix, ..
+            } => quote! {
+                self.#field.legacy_param_ids().into_iter().map(|(legacy_id, param_id)| {
+                    (
+                        format!("{}_{}", #id_prefix, legacy_id),
+                        format!("{}_{}", #id_prefix, param_id),
+                    )
+                })
+            },
+            NestedParams::Array { field, .. } => quote! {
+                self.#field.iter().enumerate().flat_map(|(idx, params)| {
+                    let idx = idx + 1;
+
+                    params.legacy_param_ids().into_iter().map(move |(legacy_id, param_id)| {
+                        (format!("{}_{}", legacy_id, idx), format!("{}_{}", param_id, idx))
+                    })
+                })
+            },
+        }
+    }
+
     /// Constrruct an iterator that iterates over all parmaeters of a nested parameter object. This
     /// takes ID prefixes and suffixes into account, and prefixes the group to the parameter's
     /// existing groups if the `group` attribute on the `#[nested]` macro was specified.
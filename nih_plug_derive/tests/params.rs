@@ -102,6 +102,43 @@ impl Default for NestedArrayParams {
     }
 }
 
+#[derive(Params)]
+struct RenamedParams {
+    #[id = "speed"]
+    #[legacy_ids("tape_speed", "spd")]
+    pub speed: FloatParam,
+
+    #[id = "mix"]
+    pub mix: FloatParam,
+}
+
+impl Default for RenamedParams {
+    fn default() -> Self {
+        RenamedParams {
+            speed: FloatParam::new("speed", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 }),
+            mix: FloatParam::new("mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+        }
+    }
+}
+
+#[derive(Params)]
+struct NestedRenamedParams {
+    #[nested(id_prefix = "deck")]
+    pub deck: RenamedParams,
+
+    #[nested(array)]
+    pub tapes: [RenamedParams; 2],
+}
+
+impl Default for NestedRenamedParams {
+    fn default() -> Self {
+        NestedRenamedParams {
+            deck: RenamedParams::default(),
+            tapes: [RenamedParams::default(), RenamedParams::default()],
+        }
+    }
+}
+
 mod param_order {
     use super::*;
 
@@ -290,3 +327,50 @@ mod param_groups {
         );
     }
 }
+
+mod legacy_ids {
+    use super::*;
+
+    #[test]
+    fn flat() {
+        let p = RenamedParams::default();
+
+        // Only the current IDs are part of the parameter map
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        assert_eq!(param_ids, ["speed", "mix"]);
+
+        let legacy_ids = p.legacy_param_ids();
+        assert_eq!(
+            legacy_ids,
+            [
+                (String::from("tape_speed"), String::from("speed")),
+                (String::from("spd"), String::from("speed")),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested() {
+        let p = NestedRenamedParams::default();
+
+        // The legacy IDs get the same prefixes and suffixes as the parameter they belong to
+        let legacy_ids: Vec<(String, String)> = p.legacy_param_ids();
+        assert_eq!(
+            legacy_ids,
+            [
+                ("deck_tape_speed", "deck_speed"),
+                ("deck_spd", "deck_speed"),
+                ("tape_speed_1", "speed_1"),
+                ("spd_1", "speed_1"),
+                ("tape_speed_2", "speed_2"),
+                ("spd_2", "speed_2"),
+            ]
+            .map(|(legacy_id, id)| (String::from(legacy_id), String::from(id)))
+        );
+
+        let param_ids: Vec<String> = p.param_map().into_iter().map(|(id, _, _)| id).collect();
+        for (_, id) in legacy_ids {
+            assert!(param_ids.contains(&id));
+        }
+    }
+}
@@ -6,32 +6,27 @@ use crossbeam::atomic::AtomicCell;
 use egui::Context;
 use egui_baseview::EguiWindow;
 use nih_plug::prelude::{Editor, GuiContext, ParamSetter, ParentWindowHandle};
-use parking_lot::RwLock;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use crate::EguiState;
 
 /// An [`Editor`] implementation that calls an egui draw loop.
-pub(crate) struct EguiEditor<T> {
+pub(crate) struct EguiEditor {
     pub(crate) egui_state: Arc<EguiState>,
-    /// The plugin's state. This is kept in between editor openenings.
-    pub(crate) user_state: Arc<RwLock<T>>,
 
-    /// The user's build function. Applied once at the start of the application.
-    pub(crate) build: Arc<dyn Fn(&Context, &mut T) + 'static + Send + Sync>,
+    /// The user's build function. Applied once at the start of the application. This and the
+    /// update function both capture the user state, which is kept in between editor openings.
+    pub(crate) build: Arc<dyn Fn(&Context) + 'static + Send + Sync>,
     /// The user's update function.
-    pub(crate) update: Arc<dyn Fn(&Context, &ParamSetter, &mut T) + 'static + Send + Sync>,
+    pub(crate) update: Arc<dyn Fn(&Context, &ParamSetter) + 'static + Send + Sync>,
 
     /// The scaling factor reported by the host, if any. On macOS this will never be set and we
     /// should use the system scaling factor instead.
     pub(crate) scaling_factor: AtomicCell<Option<f32>>,
 }
 
-impl<T> Editor for EguiEditor<T>
-where
-    T: 'static + Send + Sync,
-{
+impl Editor for EguiEditor {
     fn spawn(
         &self,
         parent: ParentWindowHandle,
@@ -39,7 +34,6 @@ where
     ) -> Box<dyn std::any::Any + Send> {
         let build = self.build.clone();
         let update = self.update.clone();
-        let state = self.user_state.clone();
 
         let (unscaled_width, unscaled_height) = self.egui_state.size();
         let scaling_factor = self.scaling_factor.load();
@@ -71,17 +65,19 @@ where
                     ..Default::default()
                 }),
             },
-            state,
-            move |egui_ctx, _queue, state| build(egui_ctx, &mut state.write()),
-            move |egui_ctx, _queue, state| {
+            (),
+            move |egui_ctx, _queue, _state| build(egui_ctx),
+            move |egui_ctx, _queue, _state| {
                 let setter = ParamSetter::new(context.as_ref());
 
-                // For now, just always redraw. Most plugin GUIs have meters, and those almost always
-                // need a redraw. Later we can try to be a bit more sophisticated about this. Without
-                // this we would also have a blank GUI when it gets first opened because most DAWs open
-                // their GUI while the window is still unmapped.
-                egui_ctx.request_repaint();
-                (update)(egui_ctx, &setter, &mut state.write());
+                // By default this returns `true` on every frame. Most plugin GUIs have meters, and
+                // those almost always need a redraw. Without this we would also have a blank GUI
+                // when it gets first opened because most DAWs open their GUI while the window is
+                // still unmapped. Editors can change this using `GuiContext::set_repaint_policy()`.
+                if context.poll_repaint() {
+                    egui_ctx.request_repaint();
+                }
+                (update)(egui_ctx, &setter);
             },
         );
 
@@ -102,9 +98,11 @@ where
     }
 
     fn param_values_changed(&self) {
-        // As mentioned above, for now we'll always force a redraw to allow meter widgets to work
-        // correctly. In the future we can use an `Arc<AtomicBool>` and only force a redraw when
-        // that boolean is set.
+        // The wrapper already requests a repaint when the parameter values change
+    }
+
+    fn as_any(&self) -> Option<&dyn std::any::Any> {
+        Some(self)
     }
 }
 
@@ -21,6 +21,7 @@ compile_error!("There's currently no software rendering support for egui");
 pub use egui;
 
 mod editor;
+pub mod offscreen;
 pub mod widgets;
 
 /// Create an [`Editor`] instance using an [`egui`][::egui] GUI. Using the user state parameter is
@@ -44,11 +45,18 @@ where
     B: Fn(&Context, &mut T) + 'static + Send + Sync,
     U: Fn(&Context, &ParamSetter, &mut T) + 'static + Send + Sync,
 {
+    // The user state is captured by the callbacks so the editor itself doesn't need to be generic,
+    // which allows `offscreen` to recover it from a `Box<dyn Editor>`
+    let user_state = Arc::new(RwLock::new(user_state));
     Some(Box::new(editor::EguiEditor {
         egui_state,
-        user_state: Arc::new(RwLock::new(user_state)),
-        build: Arc::new(build),
-        update: Arc::new(update),
+        build: Arc::new({
+            let user_state = user_state.clone();
+            move |egui_ctx: &Context| build(egui_ctx, &mut user_state.write())
+        }),
+        update: Arc::new(move |egui_ctx: &Context, setter: &ParamSetter| {
+            update(egui_ctx, setter, &mut user_state.write())
+        }),
 
         // TODO: We can't get the size of the window when baseview does its own scaling, so if the
         //       host does not set a scale factor on Windows or Linux we should just use a factor of
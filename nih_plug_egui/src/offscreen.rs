@@ -0,0 +1,835 @@
+//! Render egui editors offscreen, without a window or a graphics API. This is intended for visual
+//! regression tests: create the plugin's editor, pass it to [`OffscreenEditor::new()`] together
+//! with a [`HeadlessGuiContext`][nih_plug::context::headless::HeadlessGuiContext] for the plugin's
+//! parameters, simulate some input, and compare the rendered [`RgbaImage`] to a previously saved
+//! golden image. The events egui emits for screen readers can be inspected through
+//! [`OffscreenEditor::output_events()`].
+//!
+//! Frames are rasterized in software from egui's tessellated meshes. The result is close to, but
+//! not pixel-identical with, what the OpenGL backend draws. Custom paint callbacks are not
+//! rendered.
+
+use egui::epaint::{ClippedPrimitive, ImageData, ImageDelta, Mesh, Primitive, TextureId, Vertex};
+use egui::output::OutputEvent;
+use egui::{Color32, Context, Event, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, Vec2};
+use nih_plug::prelude::{Editor, GuiContext, ParamSetter};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::editor::EguiEditor;
+
+/// The frame rate used to advance egui's clock between frames.
+const FRAME_RATE: f64 = 60.0;
+
+/// An egui editor that is rendered to an image instead of to a window. The editor's build function
+/// is called when this object is created, and the update function is called once for every
+/// [`run_frame()`][Self::run_frame()]. Input events added through the other functions are handed to
+/// egui on the next frame. All positions are in logical pixels.
+pub struct OffscreenEditor {
+    egui_ctx: Context,
+    update: Arc<dyn Fn(&Context, &ParamSetter) + 'static + Send + Sync>,
+    gui_context: Arc<dyn GuiContext>,
+
+    /// The editor's size in logical pixels.
+    size: (u32, u32),
+    scale_factor: f32,
+    num_frames: u64,
+
+    /// Input events for the next frame.
+    pending_events: Vec<Event>,
+    /// The last position passed to [`pointer_move()`][Self::pointer_move()]. Button events are sent
+    /// at this position.
+    pointer_pos: Pos2,
+
+    /// The textures egui has uploaded so far.
+    textures: HashMap<TextureId, Texture>,
+    /// The tessellated output from the last frame.
+    primitives: Vec<ClippedPrimitive>,
+    /// The accessibility events from the last frame.
+    output_events: Vec<OutputEvent>,
+}
+
+/// An 8-bit RGBA image with non-premultiplied alpha, stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// A texture uploaded by egui. The pixels are stored as premultiplied sRGB colors.
+struct Texture {
+    size: [usize; 2],
+    pixels: Vec<Color32>,
+}
+
+/// The image meshes are rasterized to, with premultiplied colors in the `[0, 1]` range.
+struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<[f32; 4]>,
+}
+
+/// Render an egui editor offscreen. This is a shorthand for [`OffscreenEditor::new()`] followed by
+/// running `num_frames` frames and rendering the last one. egui needs a couple frames to lay out
+/// some widgets, so `num_frames` should be at least two. Returns `None` if `editor` is not an egui
+/// editor created using [`create_egui_editor()`][crate::create_egui_editor()].
+pub fn render_editor(
+    editor: &dyn Editor,
+    context: Arc<dyn GuiContext>,
+    size: (u32, u32),
+    scale_factor: f32,
+    num_frames: usize,
+) -> Option<RgbaImage> {
+    let mut offscreen = OffscreenEditor::new(editor, context, size, scale_factor)?;
+    offscreen.run_frames(num_frames);
+
+    Some(offscreen.render())
+}
+
+impl OffscreenEditor {
+    /// Instantiate an editor created using [`create_egui_editor()`][crate::create_egui_editor()]
+    /// with a logical size and a DPI scale factor. Returns `None` if `editor` is not an egui
+    /// editor. The editor's user state is shared with any windows spawned from the same editor.
+    pub fn new(
+        editor: &dyn Editor,
+        context: Arc<dyn GuiContext>,
+        size: (u32, u32),
+        scale_factor: f32,
+    ) -> Option<Self> {
+        let editor = editor.as_any()?.downcast_ref::<EguiEditor>()?;
+
+        let egui_ctx = Context::default();
+        (editor.build)(&egui_ctx);
+
+        Some(Self {
+            egui_ctx,
+            update: editor.update.clone(),
+            gui_context: context,
+
+            size,
+            scale_factor,
+            num_frames: 0,
+
+            pending_events: Vec::new(),
+            pointer_pos: Pos2::ZERO,
+
+            textures: HashMap::new(),
+            primitives: Vec::new(),
+            output_events: Vec::new(),
+        })
+    }
+
+    /// The egui context used by the editor.
+    pub fn egui_context(&self) -> &Context {
+        &self.egui_ctx
+    }
+
+    /// Run a single frame with all input events added since the last frame.
+    pub fn run_frame(&mut self) {
+        let raw_input = RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(self.size.0 as f32, self.size.1 as f32),
+            )),
+            pixels_per_point: Some(self.scale_factor),
+            time: Some(self.num_frames as f64 / FRAME_RATE),
+            predicted_dt: (1.0 / FRAME_RATE) as f32,
+            events: std::mem::take(&mut self.pending_events),
+            ..Default::default()
+        };
+
+        let setter = ParamSetter::new(self.gui_context.as_ref());
+        let update = &self.update;
+        let output = self
+            .egui_ctx
+            .run(raw_input, |egui_ctx| update(egui_ctx, &setter));
+        self.num_frames += 1;
+
+        for (texture_id, delta) in output.textures_delta.set {
+            self.set_texture(texture_id, delta);
+        }
+        self.primitives = self.egui_ctx.tessellate(output.shapes);
+        for texture_id in output.textures_delta.free {
+            self.textures.remove(&texture_id);
+        }
+        self.output_events = output.platform_output.events;
+    }
+
+    /// Run `num_frames` frames. Any pending input events are handled in the first frame.
+    pub fn run_frames(&mut self, num_frames: usize) {
+        for _ in 0..num_frames {
+            self.run_frame();
+        }
+    }
+
+    /// The events egui emitted for screen readers during the last frame, like a widget gaining
+    /// keyboard focus or a widget's value changing. These contain the widgets' accessibility
+    /// labels.
+    pub fn output_events(&self) -> &[OutputEvent] {
+        &self.output_events
+    }
+
+    /// Add a raw egui input event for the next frame.
+    pub fn push_event(&mut self, event: Event) {
+        self.pending_events.push(event);
+    }
+
+    /// Move the mouse pointer.
+    pub fn pointer_move(&mut self, pos: Pos2) {
+        self.pointer_pos = pos;
+        self.push_event(Event::PointerMoved(pos));
+    }
+
+    /// Press or release the primary mouse button at the pointer's current position.
+    pub fn pointer_button(&mut self, pressed: bool) {
+        self.push_event(Event::PointerButton {
+            pos: self.pointer_pos,
+            button: PointerButton::Primary,
+            pressed,
+            modifiers: Modifiers::default(),
+        });
+    }
+
+    /// Click at `pos`. The button press and release are handled in two separate frames, so this
+    /// runs two frames.
+    pub fn click(&mut self, pos: Pos2) {
+        self.pointer_move(pos);
+        self.pointer_button(true);
+        self.run_frame();
+        self.pointer_button(false);
+        self.run_frame();
+    }
+
+    /// Press the mouse button at `from`, drag to `to`, and release the button there. This runs
+    /// three frames.
+    pub fn drag(&mut self, from: Pos2, to: Pos2) {
+        self.pointer_move(from);
+        self.pointer_button(true);
+        self.run_frame();
+        self.pointer_move(to);
+        self.run_frame();
+        self.pointer_button(false);
+        self.run_frame();
+    }
+
+    /// Press and release a key on the next frame.
+    pub fn key_press(&mut self, key: Key) {
+        for pressed in [true, false] {
+            self.push_event(Event::Key {
+                key,
+                pressed,
+                modifiers: Modifiers::default(),
+            });
+        }
+    }
+
+    /// Type some text on the next frame.
+    pub fn text(&mut self, text: &str) {
+        self.push_event(Event::Text(text.to_owned()));
+    }
+
+    /// Rasterize the output from the last frame. The image's size is the editor's logical size
+    /// multiplied by the scale factor. Areas the editor didn't draw to are transparent.
+    pub fn render(&self) -> RgbaImage {
+        let mut canvas = Canvas::new(
+            (self.size.0 as f32 * self.scale_factor).round() as usize,
+            (self.size.1 as f32 * self.scale_factor).round() as usize,
+        );
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in &self.primitives
+        {
+            match primitive {
+                Primitive::Mesh(mesh) => {
+                    if let Some(texture) = self.textures.get(&mesh.texture_id) {
+                        canvas.draw_mesh(mesh, texture, *clip_rect, self.scale_factor);
+                    }
+                }
+                // These need a graphics API to draw anything
+                Primitive::Callback(_) => (),
+            }
+        }
+
+        canvas.into_image()
+    }
+
+    fn set_texture(&mut self, texture_id: TextureId, delta: ImageDelta) {
+        let (size, pixels) = match delta.image {
+            ImageData::Color(image) => (image.size, image.pixels),
+            ImageData::Font(image) => (image.size, image.srgba_pixels(1.0).collect()),
+        };
+
+        match delta.pos {
+            // This is a partial update of an existing texture
+            Some([x, y]) => {
+                if let Some(texture) = self.textures.get_mut(&texture_id) {
+                    for (row_idx, row) in pixels.chunks_exact(size[0]).enumerate() {
+                        let start = (y + row_idx) * texture.size[0] + x;
+                        texture.pixels[start..start + size[0]].copy_from_slice(row);
+                    }
+                }
+            }
+            None => {
+                self.textures.insert(texture_id, Texture { size, pixels });
+            }
+        }
+    }
+}
+
+impl RgbaImage {
+    /// Create a fully transparent image.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Create an image from RGBA pixel data. Returns `None` if the data doesn't match the size.
+    pub fn from_raw(width: u32, height: u32, pixels: Vec<u8>) -> Option<Self> {
+        if pixels.len() == width as usize * height as usize * 4 {
+            Some(Self {
+                width,
+                height,
+                pixels,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The raw RGBA pixel data, stored row by row.
+    pub fn as_raw(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Get the RGBA value for a pixel. Panics if the coordinates are out of bounds.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        assert!(x < self.width && y < self.height);
+
+        let start = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[start..start + 4].try_into().unwrap()
+    }
+
+    /// Save the image as a PNG file.
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
+
+    /// Load an 8-bit RGBA PNG file, like the ones written by [`save_png()`][Self::save_png()].
+    pub fn load_png(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid_data = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+        let decoder = png::Decoder::new(File::open(path)?);
+        let mut reader = decoder.read_info().map_err(invalid_data)?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).map_err(invalid_data)?;
+        if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Only 8-bit RGBA images are supported",
+            ));
+        }
+
+        pixels.truncate(info.buffer_size());
+        Self::from_raw(info.width, info.height, pixels)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unexpected image size"))
+    }
+
+    /// Compare this image to another image, returning the fraction of pixels that differ
+    /// noticeably between the two images. Two pixels are considered different if their
+    /// luminance-weighted color distance or their alpha difference, both in the `[0, 1]` range, is
+    /// larger than `threshold`. A threshold of around `0.05` ignores small antialiasing
+    /// differences. Images with different sizes are considered completely different.
+    pub fn difference(&self, other: &RgbaImage, threshold: f32) -> f32 {
+        if self.width != other.width || self.height != other.height {
+            return 1.0;
+        }
+        if self.pixels.is_empty() {
+            return 0.0;
+        }
+
+        let num_different_pixels = self
+            .pixels
+            .chunks_exact(4)
+            .zip(other.pixels.chunks_exact(4))
+            .filter(|(lhs, rhs)| {
+                let delta = |channel: usize| (lhs[channel] as f32 - rhs[channel] as f32) / 255.0;
+                let color_distance = (0.299 * delta(0).powi(2)
+                    + 0.587 * delta(1).powi(2)
+                    + 0.114 * delta(2).powi(2))
+                .sqrt();
+
+                color_distance > threshold || delta(3).abs() > threshold
+            })
+            .count();
+
+        num_different_pixels as f32 / (self.pixels.len() / 4) as f32
+    }
+}
+
+impl Texture {
+    /// Sample the texture at normalized texture coordinates using bilinear filtering. Returns a
+    /// premultiplied color in the `[0, 1]` range.
+    fn sample(&self, uv: Pos2) -> [f32; 4] {
+        let [width, height] = self.size;
+        if width == 0 || height == 0 {
+            return [0.0; 4];
+        }
+
+        let x = uv.x * width as f32 - 0.5;
+        let y = uv.y * height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (t_x, t_y) = (x - x0, y - y0);
+
+        let texel = |x: f32, y: f32| {
+            let x = (x as isize).clamp(0, width as isize - 1) as usize;
+            let y = (y as isize).clamp(0, height as isize - 1) as usize;
+            color_to_f32(self.pixels[y * width + x])
+        };
+        let top = lerp(texel(x0, y0), texel(x0 + 1.0, y0), t_x);
+        let bottom = lerp(texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0), t_x);
+
+        lerp(top, bottom, t_y)
+    }
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+        }
+    }
+
+    /// Draw an egui mesh. The mesh's vertices and the clip rectangle are in logical pixels.
+    fn draw_mesh(
+        &mut self,
+        mesh: &Mesh,
+        texture: &Texture,
+        clip_rect: Rect,
+        pixels_per_point: f32,
+    ) {
+        // This matches how egui's own backends round the clip rectangle
+        let clip_x = (
+            ((clip_rect.min.x * pixels_per_point).round().max(0.0) as usize).min(self.width),
+            ((clip_rect.max.x * pixels_per_point).round().max(0.0) as usize).min(self.width),
+        );
+        let clip_y = (
+            ((clip_rect.min.y * pixels_per_point).round().max(0.0) as usize).min(self.height),
+            ((clip_rect.max.y * pixels_per_point).round().max(0.0) as usize).min(self.height),
+        );
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            self.draw_triangle(
+                [
+                    &mesh.vertices[triangle[0] as usize],
+                    &mesh.vertices[triangle[1] as usize],
+                    &mesh.vertices[triangle[2] as usize],
+                ],
+                texture,
+                clip_x,
+                clip_y,
+                pixels_per_point,
+            );
+        }
+    }
+
+    /// Draw a triangle by sampling at every pixel's center. Pixels whose center lies exactly on an
+    /// edge are only drawn by one of the two triangles sharing that edge, so the two halves of a
+    /// quad don't overlap.
+    fn draw_triangle(
+        &mut self,
+        mut vertices: [&Vertex; 3],
+        texture: &Texture,
+        clip_x: (usize, usize),
+        clip_y: (usize, usize),
+        pixels_per_point: f32,
+    ) {
+        let mut positions = vertices.map(|vertex| vertex.pos.to_vec2() * pixels_per_point);
+        let mut area = edge_function(positions[0], positions[1], positions[2]);
+        if area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            vertices.swap(1, 2);
+            positions.swap(1, 2);
+            area = -area;
+        }
+
+        let [p0, p1, p2] = positions;
+        let min_x = (p0.x.min(p1.x).min(p2.x).floor().max(0.0) as usize).max(clip_x.0);
+        let max_x = (p0.x.max(p1.x).max(p2.x).ceil().max(0.0) as usize).min(clip_x.1);
+        let min_y = (p0.y.min(p1.y).min(p2.y).floor().max(0.0) as usize).max(clip_y.0);
+        let max_y = (p0.y.max(p1.y).max(p2.y).ceil().max(0.0) as usize).min(clip_y.1);
+
+        let colors = vertices.map(|vertex| color_to_f32(vertex.color));
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge_function(p1, p2, p);
+                let w1 = edge_function(p2, p0, p);
+                let w2 = edge_function(p0, p1, p);
+                if !(covers(w0, p1, p2) && covers(w1, p2, p0) && covers(w2, p0, p1)) {
+                    continue;
+                }
+
+                let weights = [w0 / area, w1 / area, w2 / area];
+                let mut uv = Vec2::ZERO;
+                let mut color = [0.0; 4];
+                for ((vertex, vertex_color), weight) in
+                    vertices.iter().zip(colors.iter()).zip(weights)
+                {
+                    uv += vertex.uv.to_vec2() * weight;
+                    for (channel, vertex_channel) in color.iter_mut().zip(vertex_color) {
+                        *channel += vertex_channel * weight;
+                    }
+                }
+
+                let texel = texture.sample(uv.to_pos2());
+                let source = [
+                    color[0] * texel[0],
+                    color[1] * texel[1],
+                    color[2] * texel[2],
+                    color[3] * texel[3],
+                ];
+
+                // Premultiplied alpha blending
+                let destination = &mut self.pixels[y * self.width + x];
+                for (destination_channel, source_channel) in destination.iter_mut().zip(source) {
+                    *destination_channel =
+                        source_channel + *destination_channel * (1.0 - source[3]);
+                }
+            }
+        }
+    }
+
+    fn into_image(self) -> RgbaImage {
+        let mut pixels = Vec::with_capacity(self.pixels.len() * 4);
+        for [r, g, b, a] in self.pixels {
+            let a = a.clamp(0.0, 1.0);
+            let unpremultiply = |channel: f32| {
+                if a > 0.0 {
+                    ((channel / a).clamp(0.0, 1.0) * 255.0).round() as u8
+                } else {
+                    0
+                }
+            };
+
+            pixels.extend([
+                unpremultiply(r),
+                unpremultiply(g),
+                unpremultiply(b),
+                (a * 255.0).round() as u8,
+            ]);
+        }
+
+        RgbaImage {
+            width: self.width as u32,
+            height: self.height as u32,
+            pixels,
+        }
+    }
+}
+
+/// Twice the signed area of the triangle `(a, b, c)`. Positive if the triangle is wound clockwise
+/// in screen coordinates.
+fn edge_function(a: Vec2, b: Vec2, c: Vec2) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Whether a point with edge function value `w` for the edge `from -> to` lies inside of that edge.
+/// Points exactly on the edge only count for one of the edge's two directions.
+fn covers(w: f32, from: Vec2, to: Vec2) -> bool {
+    let delta = to - from;
+    w > 0.0 || (w == 0.0 && (delta.y > 0.0 || (delta.y == 0.0 && delta.x < 0.0)))
+}
+
+fn color_to_f32(color: Color32) -> [f32; 4] {
+    color.to_array().map(|channel| channel as f32 / 255.0)
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use nih_plug::context::headless::{GuiContextCall, HeadlessGuiContext};
+    use nih_plug::prelude::*;
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::widgets::ParamSlider;
+    use crate::{create_egui_editor, EguiState};
+
+    const EDITOR_SIZE: (u32, u32) = (200, 50);
+
+    #[derive(Params)]
+    struct TestParams {
+        #[id = "gain"]
+        gain: FloatParam,
+    }
+
+    impl Default for TestParams {
+        fn default() -> Self {
+            Self {
+                gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct TestPlugin {
+        params: Arc<TestParams>,
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Offscreen Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    /// An editor containing a single slider for the gain parameter. The slider's last position is
+    /// written to `slider_rect`.
+    fn slider_editor(params: Arc<TestParams>, slider_rect: Arc<Mutex<Rect>>) -> Box<dyn Editor> {
+        create_egui_editor(
+            EguiState::from_size(EDITOR_SIZE.0, EDITOR_SIZE.1),
+            (),
+            |_, _| {},
+            move |egui_ctx, setter, _| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    *slider_rect.lock() = ui
+                        .add(ParamSlider::for_param(&params.gain, setter).without_value())
+                        .rect;
+                });
+            },
+        )
+        .unwrap()
+    }
+
+    fn white_texture() -> Texture {
+        Texture {
+            size: [1, 1],
+            pixels: vec![Color32::WHITE],
+        }
+    }
+
+    #[test]
+    fn quad_halves_do_not_overlap() {
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(
+            Rect::from_min_max(Pos2::new(1.0, 1.0), Pos2::new(5.0, 5.0)),
+            Color32::from_black_alpha(128),
+        );
+
+        // The rectangle covers pixels 2 through 9 at this scale
+        let mut canvas = Canvas::new(12, 12);
+        canvas.draw_mesh(&mesh, &white_texture(), Rect::EVERYTHING, 2.0);
+        let image = canvas.into_image();
+        for y in 0..12 {
+            for x in 0..12 {
+                let inside = (2..10).contains(&x) && (2..10).contains(&y);
+                let expected = if inside { [0, 0, 0, 128] } else { [0; 4] };
+                assert_eq!(image.pixel(x, y), expected, "pixel ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn clip_rect() {
+        let mut mesh = Mesh::default();
+        mesh.add_colored_rect(
+            Rect::from_min_max(Pos2::ZERO, Pos2::new(10.0, 10.0)),
+            Color32::RED,
+        );
+
+        let mut canvas = Canvas::new(10, 10);
+        canvas.draw_mesh(
+            &mesh,
+            &white_texture(),
+            Rect::from_min_max(Pos2::new(2.0, 0.0), Pos2::new(5.0, 10.0)),
+            1.0,
+        );
+        let image = canvas.into_image();
+        assert_eq!(image.pixel(1, 5), [0; 4]);
+        assert_eq!(image.pixel(2, 5), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(4, 5), [255, 0, 0, 255]);
+        assert_eq!(image.pixel(5, 5), [0; 4]);
+    }
+
+    #[test]
+    fn render_at_scale_factors() {
+        let params = Arc::new(TestParams::default());
+        let editor = slider_editor(params.clone(), Arc::new(Mutex::new(Rect::NOTHING)));
+        let context = Arc::new(HeadlessGuiContext::<TestPlugin>::new(params));
+
+        let image = render_editor(editor.as_ref(), context.clone(), EDITOR_SIZE, 1.0, 2).unwrap();
+        assert_eq!((image.width(), image.height()), EDITOR_SIZE);
+        // The central panel fills the entire editor
+        assert_eq!(image.pixel(0, 0)[3], 255);
+        assert_eq!(image.difference(&image, 0.0), 0.0);
+
+        let scaled_image = render_editor(editor.as_ref(), context, EDITOR_SIZE, 2.0, 2).unwrap();
+        assert_eq!(
+            (scaled_image.width(), scaled_image.height()),
+            (EDITOR_SIZE.0 * 2, EDITOR_SIZE.1 * 2)
+        );
+        assert_eq!(image.difference(&scaled_image, 0.05), 1.0);
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let mut pixels = vec![0; 3 * 2 * 4];
+        pixels[4..8].copy_from_slice(&[255, 128, 0, 200]);
+        let image = RgbaImage::from_raw(3, 2, pixels).unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "nih_plug_egui_offscreen_test_{}.png",
+            std::process::id()
+        ));
+        image.save_png(&path).unwrap();
+        let loaded = RgbaImage::load_png(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), image);
+    }
+
+    #[test]
+    fn dragging_a_slider() {
+        let params = Arc::new(TestParams::default());
+        let slider_rect = Arc::new(Mutex::new(Rect::NOTHING));
+        let editor = slider_editor(params.clone(), slider_rect.clone());
+        let context = Arc::new(HeadlessGuiContext::<TestPlugin>::new(params.clone()));
+
+        let mut offscreen =
+            OffscreenEditor::new(editor.as_ref(), context.clone(), EDITOR_SIZE, 1.0).unwrap();
+        offscreen.run_frames(2);
+        let before = offscreen.render();
+
+        let slider_rect = *slider_rect.lock();
+        offscreen.drag(
+            slider_rect.center(),
+            Pos2::new(slider_rect.right() + 10.0, slider_rect.center().y),
+        );
+        assert_eq!(params.gain.value(), 1.0);
+
+        let gain = params.gain.as_ptr();
+        let calls = context.take_calls();
+        assert_eq!(
+            calls.first(),
+            Some(&GuiContextCall::BeginSetParameter(gain))
+        );
+        assert_eq!(calls.last(), Some(&GuiContextCall::EndSetParameter(gain)));
+        assert!(calls[1..calls.len() - 1]
+            .iter()
+            .all(|call| matches!(call, GuiContextCall::SetParameterNormalized(param, _) if *param == gain)));
+        assert_eq!(
+            calls[calls.len() - 2],
+            GuiContextCall::SetParameterNormalized(gain, 1.0)
+        );
+
+        // The slider should now be drawn completely filled
+        offscreen.run_frame();
+        assert!(offscreen.render().difference(&before, 0.05) > 0.0);
+    }
+
+    #[test]
+    fn keyboard_navigation() {
+        let params = Arc::new(TestParams::default());
+        let editor = slider_editor(params.clone(), Arc::new(Mutex::new(Rect::NOTHING)));
+        let context = Arc::new(HeadlessGuiContext::<TestPlugin>::new(params.clone()));
+
+        let mut offscreen =
+            OffscreenEditor::new(editor.as_ref(), context.clone(), EDITOR_SIZE, 1.0).unwrap();
+        offscreen.run_frames(2);
+
+        // Tabbing to the slider should announce the parameter's name and value
+        offscreen.key_press(Key::Tab);
+        offscreen.run_frame();
+        let label = format!("Gain, {}", params.gain);
+        assert!(
+            offscreen.output_events().iter().any(|event| matches!(
+                event,
+                OutputEvent::FocusGained(info) if info.label.as_ref() == Some(&label)
+            )),
+            "{:?}",
+            offscreen.output_events()
+        );
+        assert!(context.take_calls().is_empty());
+
+        // Every key press is its own gesture
+        let gain = params.gain.as_ptr();
+        offscreen.key_press(Key::ArrowUp);
+        offscreen.run_frame();
+        assert_eq!(params.gain.value(), 0.01);
+        assert_eq!(
+            context.take_calls(),
+            [
+                GuiContextCall::BeginSetParameter(gain),
+                GuiContextCall::SetParameterNormalized(gain, 0.01),
+                GuiContextCall::EndSetParameter(gain),
+            ]
+        );
+        let label = format!("Gain, {}", params.gain);
+        assert!(offscreen.output_events().iter().any(|event| matches!(
+            event,
+            OutputEvent::ValueChanged(info) if info.label.as_ref() == Some(&label)
+        )));
+
+        offscreen.key_press(Key::End);
+        offscreen.run_frame();
+        assert_eq!(params.gain.value(), 1.0);
+        offscreen.key_press(Key::PageDown);
+        offscreen.run_frame();
+        assert_eq!(params.gain.value(), 0.9);
+        offscreen.key_press(Key::Home);
+        offscreen.run_frame();
+        assert_eq!(params.gain.value(), 0.0);
+    }
+}
@@ -6,7 +6,23 @@
 //! to copy the widgets and modify them to your personal taste.
 
 pub mod generic_ui;
+mod last_touched_param;
+mod modulation_matrix;
+mod oscilloscope;
+mod param_interaction;
+mod param_knob;
 mod param_slider;
+mod spectrum;
+mod transport_display;
 pub mod util;
 
+pub use last_touched_param::LastTouchedParam;
+pub use modulation_matrix::ModulationMatrixGrid;
+pub use oscilloscope::{Oscilloscope, OscilloscopeState, OscilloscopeTrigger};
+pub use param_knob::ParamKnob;
 pub use param_slider::ParamSlider;
+pub use spectrum::SpectrumWidget;
+pub use transport_display::{
+    MusicalTime, TempoTap, TransportDisplay, TransportSnapshot, TransportSnapshotCell,
+    TICKS_PER_BEAT,
+};
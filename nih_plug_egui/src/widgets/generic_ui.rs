@@ -1,12 +1,15 @@
 //! A simple generic UI widget that renders all parameters in a [`Params`] object as a scrollable
 //! list of sliders and labels.
 
+use std::ops::Range;
 use std::sync::Arc;
 
-use egui::{TextStyle, Ui, Vec2};
+use egui::text::LayoutJob;
+use egui::{Event, Id, Key, TextEdit, TextFormat, TextStyle, Ui, Vec2};
 use nih_plug::prelude::{Param, ParamFlags, ParamPtr, ParamSetter, Params};
+use parking_lot::Mutex;
 
-use super::ParamSlider;
+use super::{ParamKnob, ParamSlider};
 
 /// A widget that can be used to create a generic UI with. This is used in conjuction with empty
 /// structs to emulate existential types.
@@ -31,42 +34,401 @@ pub trait ParamWidget {
 /// Create a generic UI using [`ParamSlider`]s.
 pub struct GenericSlider;
 
+/// Create a generic UI using [`ParamKnob`]s.
+pub struct GenericKnob;
+
+/// Options for [`create_with_options()`]. The default options result in the same UI as
+/// [`create()`].
+#[derive(Debug, Clone, Default)]
+pub struct GenericUiOptions {
+    /// Show a search box and a "Modified only" toggle above the parameter list. The search box
+    /// filters parameters by a case-insensitive substring of their name, their group's name, or
+    /// their ID, and it can be focused with Ctrl+F (Cmd+F on macOS). The filter is stored in egui's
+    /// memory, so it is not part of the plugin's state.
+    pub searchable: bool,
+}
+
+/// The search box's contents and the "Modified only" toggle, stored in egui's memory.
+#[derive(Debug, Default)]
+struct SearchState {
+    query: String,
+    modified_only: bool,
+}
+
 /// Create a scrollable generic UI using the specified widget. Takes up all the remaining vertical
-/// space.
+/// space. Parameters are shown in the order they were declared in, which is also the order they are
+/// focused in when using Tab to navigate through the UI. Parameters in nested groups are preceded
+/// by a heading with the group's name.
 pub fn create(
     ui: &mut Ui,
     params: Arc<impl Params>,
     setter: &ParamSetter,
     widget: impl ParamWidget,
 ) {
+    create_with_options(ui, params, setter, widget, GenericUiOptions::default())
+}
+
+/// The same as [`create()`], but with additional [`GenericUiOptions`]. Setting
+/// [`searchable`][GenericUiOptions::searchable] adds a search box to the top of the UI, which can
+/// be useful for plugins with a lot of parameters.
+pub fn create_with_options(
+    ui: &mut Ui,
+    params: Arc<impl Params>,
+    setter: &ParamSetter,
+    widget: impl ParamWidget,
+    options: GenericUiOptions,
+) {
+    let search_state = if options.searchable {
+        Some(search_ui(ui))
+    } else {
+        None
+    };
+    let search_state = search_state.as_ref().map(|state| state.lock());
+
     let padding = Vec2::splat(ui.text_style_height(&TextStyle::Body) * 0.2);
     egui::containers::ScrollArea::vertical()
         // Take up all remaining space, use a wrapper container to adjust how much space that is
         .auto_shrink([false, false])
         .show(ui, |ui| {
             let mut first_widget = true;
-            for (_, param_ptr, _) in params.param_map().into_iter() {
+            let mut current_group = String::new();
+            for (id, param_ptr, group) in params.param_map().into_iter() {
                 let flags = unsafe { param_ptr.flags() };
-                if flags.contains(ParamFlags::HIDE_IN_GENERIC_UI) {
+                if flags.intersects(ParamFlags::HIDDEN | ParamFlags::HIDE_IN_GENERIC_UI) {
                     continue;
                 }
 
+                let name = unsafe { param_ptr.name() };
+                let mut name_match = None;
+                if let Some(search_state) = &search_state {
+                    if search_state.modified_only
+                        && unsafe {
+                            param_ptr.unmodulated_normalized_value()
+                                == param_ptr.default_normalized_value()
+                        }
+                    {
+                        continue;
+                    }
+
+                    name_match = find_case_insensitive(name, &search_state.query);
+                    if name_match.is_none()
+                        && find_case_insensitive(&group, &search_state.query).is_none()
+                        && find_case_insensitive(&id, &search_state.query).is_none()
+                    {
+                        continue;
+                    }
+                }
+
                 // This list looks weird without a little padding
                 if !first_widget {
                     ui.allocate_space(padding);
                 }
 
-                ui.label(unsafe { param_ptr.name() });
+                if group != current_group {
+                    if !group.is_empty() {
+                        ui.heading(&group);
+                    }
+                    current_group = group;
+                }
+
+                match name_match {
+                    Some(range) if !range.is_empty() => {
+                        ui.label(highlighted_text(ui, name, range));
+                    }
+                    _ => {
+                        ui.label(name);
+                    }
+                }
                 unsafe { widget.add_widget_raw(ui, &param_ptr, setter) };
 
                 first_widget = false;
             }
+
+            if first_widget && search_state.is_some() {
+                ui.weak("No matching parameters");
+            }
         });
 }
 
+/// Draw the search box and the "Modified only" toggle, and return the current search state.
+fn search_ui(ui: &mut Ui) -> Arc<Mutex<SearchState>> {
+    let search_id = ui.make_persistent_id("generic_ui_search");
+    let search_state = ui
+        .memory()
+        .data
+        .get_temp_mut_or_default::<Arc<Mutex<SearchState>>>(search_id)
+        .clone();
+
+    // The modifiers are checked on the event itself since not every host forwards modifier
+    // changes separately
+    let focus_requested = ui.input().events.iter().any(|event| {
+        matches!(
+            event,
+            Event::Key {
+                key: Key::F,
+                pressed: true,
+                modifiers,
+            } if modifiers.command
+        )
+    });
+    if focus_requested {
+        ui.memory().request_focus(search_id);
+    }
+
+    ui.horizontal(|ui| {
+        let mut search_state = search_state.lock();
+        ui.add(
+            TextEdit::singleline(&mut search_state.query)
+                .id(search_id)
+                .hint_text("Search parameters"),
+        );
+        ui.checkbox(&mut search_state.modified_only, "Modified only");
+    });
+
+    search_state
+}
+
+/// Find the first case-insensitive occurrence of `needle` in `haystack`, and return its byte range
+/// within `haystack`. An empty needle matches the start of the string.
+fn find_case_insensitive(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    haystack
+        .char_indices()
+        .map(|(start, _)| start)
+        .chain(std::iter::once(haystack.len()))
+        .find_map(|start| {
+            let mut end = start;
+            let mut haystack_chars = haystack[start..].chars();
+            for needle_char in needle.chars() {
+                let haystack_char = haystack_chars.next()?;
+                if !haystack_char.to_lowercase().eq(needle_char.to_lowercase()) {
+                    return None;
+                }
+
+                end += haystack_char.len_utf8();
+            }
+
+            Some(start..end)
+        })
+}
+
+/// Lay out `text` in the body text style with `highlight` drawn on top of the selection color.
+fn highlighted_text(ui: &Ui, text: &str, highlight: Range<usize>) -> LayoutJob {
+    let format = TextFormat {
+        font_id: TextStyle::Body.resolve(ui.style()),
+        color: ui.visuals().text_color(),
+        ..Default::default()
+    };
+    let highlight_format = TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        ..format.clone()
+    };
+
+    let mut job = LayoutJob::default();
+    job.append(&text[..highlight.start], 0.0, format.clone());
+    job.append(&text[highlight.clone()], 0.0, highlight_format);
+    job.append(&text[highlight.end..], 0.0, format);
+
+    job
+}
+
 impl ParamWidget for GenericSlider {
     fn add_widget<P: Param>(&self, ui: &mut Ui, param: &P, setter: &ParamSetter) {
         // Make these sliders a bit wider, else they look a bit odd
         ui.add(ParamSlider::for_param(param, setter).with_width(100.0));
     }
 }
+
+impl ParamWidget for GenericKnob {
+    fn add_widget<P: Param>(&self, ui: &mut Ui, param: &P, setter: &ParamSetter) {
+        // The generic UI already draws the parameter's name above the widget
+        ui.add(ParamKnob::for_param(param, setter).without_name());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Modifiers;
+    use nih_plug::context::headless::HeadlessGuiContext;
+    use nih_plug::prelude::*;
+
+    use super::*;
+    use crate::offscreen::OffscreenEditor;
+    use crate::{create_egui_editor, EguiState};
+
+    const EDITOR_SIZE: (u32, u32) = (300, 300);
+    const ALL_PARAMS: [&str; 3] = ["Gain", "Cutoff", "Resonance"];
+
+    #[derive(Params)]
+    struct TestParams {
+        #[id = "gain"]
+        gain: FloatParam,
+        #[nested(group = "Filter")]
+        filter: FilterParams,
+    }
+
+    #[derive(Params)]
+    struct FilterParams {
+        #[id = "fc"]
+        cutoff: FloatParam,
+        #[id = "res"]
+        resonance: FloatParam,
+    }
+
+    impl Default for TestParams {
+        fn default() -> Self {
+            Self {
+                gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+                filter: FilterParams {
+                    cutoff: FloatParam::new(
+                        "Cutoff",
+                        1000.0,
+                        FloatRange::Linear {
+                            min: 20.0,
+                            max: 20000.0,
+                        },
+                    ),
+                    resonance: FloatParam::new(
+                        "Resonance",
+                        0.5,
+                        FloatRange::Linear { min: 0.0, max: 1.0 },
+                    ),
+                },
+            }
+        }
+    }
+
+    #[derive(Default)]
+    struct TestPlugin {
+        params: Arc<TestParams>,
+    }
+
+    impl Plugin for TestPlugin {
+        const NAME: &'static str = "Generic UI Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    /// Records the names of the parameters the generic UI added widgets for.
+    #[derive(Clone, Default)]
+    struct RecordingWidget(Arc<Mutex<Vec<String>>>);
+
+    impl ParamWidget for RecordingWidget {
+        fn add_widget<P: Param>(&self, _ui: &mut Ui, param: &P, _setter: &ParamSetter) {
+            self.0.lock().push(param.name().to_owned());
+        }
+    }
+
+    fn search_editor(params: Arc<TestParams>, widget: RecordingWidget) -> Box<dyn Editor> {
+        create_egui_editor(
+            EguiState::from_size(EDITOR_SIZE.0, EDITOR_SIZE.1),
+            (),
+            |_, _| {},
+            move |egui_ctx, setter, _| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    create_with_options(
+                        ui,
+                        params.clone(),
+                        setter,
+                        widget.clone(),
+                        GenericUiOptions { searchable: true },
+                    );
+                });
+            },
+        )
+        .unwrap()
+    }
+
+    /// Run a frame and return the names of the parameters that were shown during it.
+    fn visible_params(offscreen: &mut OffscreenEditor, widget: &RecordingWidget) -> Vec<String> {
+        widget.0.lock().clear();
+        offscreen.run_frame();
+        widget.0.lock().clone()
+    }
+
+    /// Replace the contents of the focused search box with `query`.
+    fn search(
+        offscreen: &mut OffscreenEditor,
+        widget: &RecordingWidget,
+        query: &str,
+    ) -> Vec<String> {
+        for _ in 0..32 {
+            offscreen.key_press(Key::Backspace);
+        }
+        offscreen.text(query);
+        visible_params(offscreen, widget)
+    }
+
+    #[test]
+    fn case_insensitive_matches() {
+        assert_eq!(find_case_insensitive("Cutoff", "OFF"), Some(3..6));
+        assert_eq!(find_case_insensitive("Cutoff", ""), Some(0..0));
+        assert_eq!(find_case_insensitive("Cutoff", "offs"), None);
+        assert_eq!(find_case_insensitive("", "a"), None);
+        // Ranges are in bytes in the original string
+        assert_eq!(find_case_insensitive("Überdrive", "DRIVE"), Some(5..10));
+        assert_eq!(find_case_insensitive("Überdrive", "üb"), Some(0..3));
+    }
+
+    #[test]
+    fn search_filters_parameters() {
+        let params = Arc::new(TestParams::default());
+        let widget = RecordingWidget::default();
+        let editor = search_editor(params.clone(), widget.clone());
+        let context = Arc::new(HeadlessGuiContext::<TestPlugin>::new(params.clone()));
+
+        let mut offscreen =
+            OffscreenEditor::new(editor.as_ref(), context.clone(), EDITOR_SIZE, 1.0).unwrap();
+        assert_eq!(visible_params(&mut offscreen, &widget), ALL_PARAMS);
+
+        // Ctrl+F focuses the search box
+        offscreen.push_event(Event::Key {
+            key: Key::F,
+            pressed: true,
+            modifiers: Modifiers {
+                ctrl: true,
+                command: true,
+                ..Default::default()
+            },
+        });
+        offscreen.run_frame();
+
+        assert_eq!(search(&mut offscreen, &widget, "GAIN"), ["Gain"]);
+        // Matching a group's name shows the entire group
+        assert_eq!(
+            search(&mut offscreen, &widget, "filt"),
+            ["Cutoff", "Resonance"]
+        );
+        // And parameters can also be found by their ID
+        assert_eq!(search(&mut offscreen, &widget, "fc"), ["Cutoff"]);
+        assert!(search(&mut offscreen, &widget, "xyz").is_empty());
+        assert_eq!(search(&mut offscreen, &widget, ""), ALL_PARAMS);
+
+        // The "Modified only" toggle directly follows the search box
+        ParamSetter::new(context.as_ref()).set_parameter(&params.filter.cutoff, 5000.0);
+        offscreen.key_press(Key::Tab);
+        offscreen.run_frame();
+        offscreen.key_press(Key::Space);
+        assert_eq!(visible_params(&mut offscreen, &widget), ["Cutoff"]);
+
+        // None of this should have touched the other parameters
+        assert_eq!(params.gain.value(), 0.0);
+        assert_eq!(params.filter.resonance.value(), 0.5);
+    }
+}
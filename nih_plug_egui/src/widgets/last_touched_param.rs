@@ -0,0 +1,58 @@
+use egui::{Response, Ui, Widget};
+use nih_plug::prelude::{ParamChange, ParamChangeSource, ParamChangeSubscription};
+
+/// Displays the name and value of the parameter that was changed most recently, along with where
+/// that change came from. Changes caused by loading presets or state are ignored. This can be used
+/// as the basis for a MIDI learn function, where the user touches a parameter and then moves a
+/// controller to map it to that parameter.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct LastTouchedParam {
+    change: Option<ParamChange>,
+}
+
+impl LastTouchedParam {
+    /// Show the last touched parameter from a subscription created using
+    /// [`GuiContext::subscribe_param_changes()`][nih_plug::prelude::GuiContext::subscribe_param_changes()].
+    pub fn for_subscription(subscription: &ParamChangeSubscription) -> Self {
+        Self {
+            change: subscription.last_touched(),
+        }
+    }
+}
+
+impl Widget for LastTouchedParam {
+    fn ui(self, ui: &mut Ui) -> Response {
+        match self.change {
+            Some(change) => {
+                // SAFETY: The parameter pointers come from the plugin's `Params` object, which
+                //         outlives the editor
+                let (name, value) = unsafe {
+                    (
+                        change.param.name(),
+                        change
+                            .param
+                            .normalized_value_to_string(change.normalized_value, true),
+                    )
+                };
+
+                ui.horizontal(|ui| {
+                    ui.strong(name);
+                    ui.label(value);
+                    ui.weak(source_label(change.source));
+                })
+                .response
+            }
+            None => ui.weak("No parameter touched yet"),
+        }
+    }
+}
+
+/// A short description of where a parameter change came from.
+fn source_label(source: ParamChangeSource) -> &'static str {
+    match source {
+        ParamChangeSource::HostAutomation => "(automation)",
+        ParamChangeSource::HostStateLoad => "(preset)",
+        ParamChangeSource::GuiGesture => "(editor)",
+        ParamChangeSource::Internal => "(plugin)",
+    }
+}
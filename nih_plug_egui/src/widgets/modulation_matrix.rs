@@ -0,0 +1,66 @@
+use egui::{ComboBox, Grid, Response, Ui, Widget};
+use nih_plug::prelude::{IntParam, Param, ParamSetter};
+use nih_plug::util::ModulationRouteParams;
+
+use super::ParamSlider;
+
+/// A grid for editing the routes of a [`ModulationMatrix`][nih_plug::util::ModulationMatrix]. Every
+/// route gets a row with drop-down menus for its source and target, and a slider for its depth.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct ModulationMatrixGrid<'a> {
+    routes: &'a [ModulationRouteParams],
+    setter: &'a ParamSetter<'a>,
+}
+
+impl<'a> ModulationMatrixGrid<'a> {
+    /// Create a grid for `routes`, typically the plugin's nested array of route parameters.
+    pub fn for_routes(routes: &'a [ModulationRouteParams], setter: &'a ParamSetter<'a>) -> Self {
+        Self { routes, setter }
+    }
+}
+
+impl Widget for ModulationMatrixGrid<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        Grid::new("modulation_matrix")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Source");
+                ui.strong("Target");
+                ui.strong("Depth");
+                ui.end_row();
+
+                for (route_idx, route) in self.routes.iter().enumerate() {
+                    choice_combo_box(ui, ("mod_src", route_idx), &route.source, self.setter);
+                    choice_combo_box(ui, ("mod_dst", route_idx), &route.target, self.setter);
+                    ui.add(ParamSlider::for_param(&route.depth, self.setter));
+                    ui.end_row();
+                }
+            })
+            .response
+    }
+}
+
+/// A drop-down menu for one of a route's source or target parameters, listing all of the
+/// parameter's values by name.
+fn choice_combo_box(
+    ui: &mut Ui,
+    id_source: impl std::hash::Hash,
+    param: &IntParam,
+    setter: &ParamSetter,
+) {
+    let current = param.value();
+    let num_choices = param.step_count().unwrap_or(0) + 1;
+
+    ComboBox::from_id_source(id_source)
+        .selected_text(param.to_string())
+        .show_ui(ui, |ui| {
+            for choice in 0..num_choices as i32 {
+                let name =
+                    param.normalized_value_to_string(param.preview_normalized(choice), false);
+                if ui.selectable_label(choice == current, name).clicked() && choice != current {
+                    setter.set_parameter_with_gesture(param, choice);
+                }
+            }
+        });
+}
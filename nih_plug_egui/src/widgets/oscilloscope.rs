@@ -0,0 +1,385 @@
+use egui::{pos2, vec2, Rect, Response, Sense, Shape, Stroke, Ui, Widget};
+use nih_plug::util::WaveformReader;
+
+/// The shortest time range that can be selected, in milliseconds.
+const MIN_TIME_RANGE_MS: f32 = 1.0;
+/// The default time range shown by the oscilloscope, in milliseconds.
+const DEFAULT_TIME_RANGE_MS: f32 = 20.0;
+
+/// How the [`Oscilloscope`] decides which part of the recent audio to show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscilloscopeTrigger {
+    /// Always show the most recent audio.
+    Free,
+    /// Start the view at the most recent rising zero crossing in the first channel. This keeps
+    /// periodic signals stable. Falls back to showing the most recent audio if there is no zero
+    /// crossing.
+    ZeroCrossing,
+    /// Start the view at the position of the last
+    /// [`WaveformBuffer::trigger()`][nih_plug::util::WaveformBuffer::trigger()] call. Falls back
+    /// to showing the most recent audio if there was no trigger, or if the audio following it has
+    /// already been overwritten.
+    External,
+}
+
+/// The oscilloscope's settings and the audio it's currently showing. Store this in the editor's
+/// user state so the settings are kept between frames.
+#[derive(Debug, Clone)]
+pub struct OscilloscopeState {
+    /// The amount of audio shown, in milliseconds. This is limited by the waveform buffer's
+    /// capacity.
+    pub time_range_ms: f32,
+    /// When set, the oscilloscope keeps showing the same audio.
+    pub frozen: bool,
+    pub trigger: OscilloscopeTrigger,
+
+    /// The samples currently shown for each channel. Only updated while not frozen.
+    samples: Vec<Vec<f32>>,
+    /// Scratch space for finding zero crossings.
+    search_buffer: Vec<f32>,
+}
+
+/// Draws the most recent audio written to a
+/// [`WaveformBuffer`][nih_plug::util::WaveformBuffer], with every channel getting its own lane.
+/// When a lane has more samples than pixels, every pixel column shows the range between the
+/// column's smallest and largest samples so peaks don't disappear at long time ranges. The widget
+/// includes controls for freezing the display, for the time range, and for the trigger mode,
+/// which can be hidden with [`without_controls()`][Self::without_controls()].
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct Oscilloscope<'a> {
+    reader: &'a WaveformReader,
+    state: &'a mut OscilloscopeState,
+
+    size: Option<egui::Vec2>,
+    show_controls: bool,
+}
+
+impl Default for OscilloscopeState {
+    fn default() -> Self {
+        Self::new(DEFAULT_TIME_RANGE_MS)
+    }
+}
+
+impl OscilloscopeState {
+    /// Create the state for an oscilloscope showing `time_range_ms` milliseconds of audio, using
+    /// zero crossings to stabilize the view.
+    pub fn new(time_range_ms: f32) -> Self {
+        Self {
+            time_range_ms,
+            frozen: false,
+            trigger: OscilloscopeTrigger::ZeroCrossing,
+
+            samples: Vec::new(),
+            search_buffer: Vec::new(),
+        }
+    }
+
+    /// Read the window selected by the trigger mode from `reader`, unless the display is frozen.
+    fn update(&mut self, reader: &WaveformReader) {
+        if self.frozen {
+            return;
+        }
+
+        let sample_rate = reader.sample_rate();
+        if sample_rate <= 0.0 || reader.num_channels() == 0 {
+            self.samples.clear();
+            return;
+        }
+
+        let capacity = reader.capacity();
+        let window_len =
+            ((self.time_range_ms / 1000.0 * sample_rate).round() as usize).clamp(1, capacity);
+        let write_pos = reader.write_position();
+        let latest_start_pos = write_pos.saturating_sub(window_len as u64);
+
+        let start_pos = match self.trigger {
+            OscilloscopeTrigger::Free => latest_start_pos,
+            OscilloscopeTrigger::ZeroCrossing => {
+                // Search at most one window back for a zero crossing, since the view would
+                // otherwise get stuck on old audio if the signal is very low pitched
+                let search_len = window_len.min(capacity - window_len) as u64;
+                let search_start_pos = latest_start_pos.saturating_sub(search_len);
+                self.search_buffer
+                    .resize((latest_start_pos - search_start_pos) as usize + 1, 0.0);
+                reader.read(0, search_start_pos, &mut self.search_buffer);
+
+                match latest_rising_zero_crossing(&self.search_buffer) {
+                    Some(idx) => search_start_pos + idx as u64,
+                    None => latest_start_pos,
+                }
+            }
+            OscilloscopeTrigger::External => match reader.trigger_position() {
+                Some(trigger_pos)
+                    if trigger_pos <= latest_start_pos
+                        && write_pos - trigger_pos <= capacity as u64 =>
+                {
+                    trigger_pos
+                }
+                _ => latest_start_pos,
+            },
+        };
+
+        self.samples.resize_with(reader.num_channels(), Vec::new);
+        for (channel_idx, samples) in self.samples.iter_mut().enumerate() {
+            samples.resize(window_len, 0.0);
+            reader.read(channel_idx, start_pos, samples);
+        }
+    }
+}
+
+impl<'a> Oscilloscope<'a> {
+    /// Create an oscilloscope for the audio in `reader`. The settings are stored in `state`.
+    pub fn new(reader: &'a WaveformReader, state: &'a mut OscilloscopeState) -> Self {
+        Self {
+            reader,
+            state,
+
+            size: None,
+            show_controls: true,
+        }
+    }
+
+    /// Set a custom size for the waveform display, excluding the controls.
+    pub fn with_size(mut self, size: egui::Vec2) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Don't draw the freeze, time range, and trigger controls.
+    pub fn without_controls(mut self) -> Self {
+        self.show_controls = false;
+        self
+    }
+
+    fn draw_controls(&mut self, ui: &mut Ui) {
+        let sample_rate = self.reader.sample_rate();
+        let max_time_range_ms = if sample_rate > 0.0 {
+            self.reader.capacity() as f32 / sample_rate * 1000.0
+        } else {
+            DEFAULT_TIME_RANGE_MS
+        };
+
+        ui.horizontal(|ui| {
+            if ui.selectable_label(self.state.frozen, "Freeze").clicked() {
+                self.state.frozen = !self.state.frozen;
+            }
+
+            ui.add(
+                egui::Slider::new(
+                    &mut self.state.time_range_ms,
+                    MIN_TIME_RANGE_MS..=max_time_range_ms.max(MIN_TIME_RANGE_MS),
+                )
+                .logarithmic(true)
+                .max_decimals(1)
+                .suffix(" ms"),
+            );
+
+            ui.selectable_value(&mut self.state.trigger, OscilloscopeTrigger::Free, "Free");
+            ui.selectable_value(
+                &mut self.state.trigger,
+                OscilloscopeTrigger::ZeroCrossing,
+                "Zero",
+            );
+            ui.selectable_value(
+                &mut self.state.trigger,
+                OscilloscopeTrigger::External,
+                "Trigger",
+            );
+        });
+    }
+
+    fn draw_waveforms(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter_at(rect);
+        let grid_stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+        let waveform_stroke = Stroke::new(1.0, ui.visuals().selection.bg_fill);
+
+        let num_lanes = self.state.samples.len();
+        if num_lanes == 0 {
+            return;
+        }
+
+        let lane_height = rect.height() / num_lanes as f32;
+        let num_columns = rect.width().floor().max(1.0) as usize;
+        for (lane_idx, samples) in self.state.samples.iter().enumerate() {
+            let lane_rect = Rect::from_min_size(
+                pos2(rect.left(), rect.top() + lane_idx as f32 * lane_height),
+                vec2(rect.width(), lane_height),
+            );
+            let sample_to_y = |sample: f32| {
+                egui::emath::remap(
+                    sample.clamp(-1.0, 1.0),
+                    -1.0..=1.0,
+                    lane_rect.bottom()..=lane_rect.top(),
+                )
+            };
+
+            painter.line_segment(
+                [
+                    pos2(lane_rect.left(), lane_rect.center().y),
+                    pos2(lane_rect.right(), lane_rect.center().y),
+                ],
+                grid_stroke,
+            );
+
+            if samples.len() <= num_columns {
+                // With fewer samples than pixels the samples themselves can be connected
+                let x_step = lane_rect.width() / (samples.len().max(2) - 1) as f32;
+                let points = samples
+                    .iter()
+                    .enumerate()
+                    .map(|(sample_idx, sample)| {
+                        pos2(
+                            lane_rect.left() + sample_idx as f32 * x_step,
+                            sample_to_y(*sample),
+                        )
+                    })
+                    .collect();
+                painter.add(Shape::line(points, waveform_stroke));
+            } else {
+                let shapes: Vec<Shape> = min_max_peaks(samples, num_columns)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(column_idx, (min, max))| {
+                        let x = lane_rect.left() + column_idx as f32 + 0.5;
+                        // Make sure columns with constant values are still visible
+                        let (min_y, max_y) = (sample_to_y(min), sample_to_y(max));
+                        Shape::line_segment(
+                            [pos2(x, min_y + 0.5), pos2(x, max_y - 0.5)],
+                            waveform_stroke,
+                        )
+                    })
+                    .collect();
+                painter.extend(shapes);
+            }
+        }
+    }
+}
+
+impl Widget for Oscilloscope<'_> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        ui.vertical(|ui| {
+            if self.show_controls {
+                self.draw_controls(ui);
+            }
+            self.state.update(self.reader);
+
+            let size = self
+                .size
+                .unwrap_or_else(|| vec2(ui.available_width(), ui.available_width() * 0.4));
+            let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+            if ui.is_rect_visible(rect) {
+                ui.painter()
+                    .rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+                self.draw_waveforms(ui, rect);
+            }
+
+            response
+        })
+        .inner
+    }
+}
+
+/// Downsample `samples` to `num_columns` columns, returning the smallest and the largest sample in
+/// every column. The samples are divided evenly over the columns. If there are fewer samples than
+/// columns, then some samples will be repeated so every column still contains at least one sample.
+fn min_max_peaks(samples: &[f32], num_columns: usize) -> Vec<(f32, f32)> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    (0..num_columns)
+        .map(|column_idx| {
+            let start = column_idx * samples.len() / num_columns;
+            let end = ((column_idx + 1) * samples.len() / num_columns).max(start + 1);
+
+            samples[start..end]
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), sample| {
+                    (min.min(*sample), max.max(*sample))
+                })
+        })
+        .collect()
+}
+
+/// The index of the last sample in `samples` that is non-negative while the sample before it was
+/// negative.
+fn latest_rising_zero_crossing(samples: &[f32]) -> Option<usize> {
+    (1..samples.len())
+        .rev()
+        .find(|&idx| samples[idx - 1] < 0.0 && samples[idx] >= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_max_peaks_square_wave() {
+        // A square wave with a period of eight samples. Every column covers an entire period.
+        let samples: Vec<f32> = (0..64)
+            .map(|idx| if idx % 8 < 4 { 1.0 } else { -1.0 })
+            .collect();
+
+        assert_eq!(min_max_peaks(&samples, 8), vec![(-1.0, 1.0); 8]);
+        // With half a period per column every column is constant
+        assert_eq!(
+            min_max_peaks(&samples, 16),
+            [(1.0, 1.0), (-1.0, -1.0)].repeat(8)
+        );
+    }
+
+    #[test]
+    fn min_max_peaks_ramp() {
+        let samples: Vec<f32> = (0..100).map(|idx| idx as f32).collect();
+
+        let peaks = min_max_peaks(&samples, 10);
+        assert_eq!(peaks.len(), 10);
+        for (column_idx, (min, max)) in peaks.into_iter().enumerate() {
+            assert_eq!(min, column_idx as f32 * 10.0);
+            assert_eq!(max, column_idx as f32 * 10.0 + 9.0);
+        }
+    }
+
+    #[test]
+    fn min_max_peaks_long_sine() {
+        // Ten seconds of a 1 kHz sine wave squeezed into 200 columns should still show the full
+        // amplitude in every column, while naive decimation would alias
+        let samples: Vec<f32> = (0..441_000)
+            .map(|idx| {
+                ((idx as f64 / 44_100.0 * 1_000.0 * std::f64::consts::TAU).sin() * 0.5) as f32
+            })
+            .collect();
+
+        for (min, max) in min_max_peaks(&samples, 200) {
+            assert!((min + 0.5).abs() < 1e-3, "{min}");
+            assert!((max - 0.5).abs() < 1e-3, "{max}");
+        }
+    }
+
+    #[test]
+    fn min_max_peaks_fewer_samples_than_columns() {
+        assert_eq!(
+            min_max_peaks(&[1.0, 2.0, 3.0, 4.0], 8),
+            vec![
+                (1.0, 1.0),
+                (1.0, 1.0),
+                (2.0, 2.0),
+                (2.0, 2.0),
+                (3.0, 3.0),
+                (3.0, 3.0),
+                (4.0, 4.0),
+                (4.0, 4.0)
+            ]
+        );
+        assert_eq!(min_max_peaks(&[], 8), vec![]);
+    }
+
+    #[test]
+    fn rising_zero_crossings() {
+        assert_eq!(
+            latest_rising_zero_crossing(&[-1.0, 1.0, -1.0, 0.0, 1.0, -1.0]),
+            Some(3)
+        );
+        assert_eq!(latest_rising_zero_crossing(&[1.0, 0.5, -1.0]), None);
+    }
+}
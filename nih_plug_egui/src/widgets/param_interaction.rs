@@ -0,0 +1,614 @@
+//! The input handling and text entry shared by [`ParamSlider`][super::ParamSlider] and
+//! [`ParamKnob`][super::ParamKnob]. Keeping this in one place makes sure the widgets behave the
+//! same way.
+
+use std::sync::Arc;
+
+use egui::{
+    Button, Key, Pos2, Rect, Response, Sense, TextEdit, TextStyle, Ui, Vec2, WidgetInfo,
+    WidgetText, WidgetType,
+};
+use lazy_static::lazy_static;
+use nih_plug::prelude::{
+    ContextMenuItem, ContextMenuItemKind, HostContextMenu, Param, ParamSetter,
+};
+use parking_lot::Mutex;
+
+/// When shift+dragging a parameter, one pixel dragged corresponds to this much change in the
+/// noramlized parameter.
+const GRANULAR_DRAG_MULTIPLIER: f32 = 0.0015;
+/// When dragging a knob, one pixel dragged corresponds to this much change in the normalized
+/// parameter.
+const KNOB_DRAG_MULTIPLIER: f32 = 0.005;
+/// The default change in the normalized value for a single arrow key press on a continuous
+/// parameter.
+pub const DEFAULT_KEYBOARD_STEP: f32 = 0.01;
+/// The number of arrow key steps a single Page Up or Page Down press corresponds to.
+const PAGE_KEYBOARD_STEPS: i32 = 10;
+
+lazy_static! {
+    static ref DRAG_NORMALIZED_VALUE_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
+    static ref VALUE_ENTRY_MEMORY_ID: egui::Id = egui::Id::new((file!(), 1));
+    static ref HOST_CONTEXT_MENU_MEMORY_ID: egui::Id = egui::Id::new((file!(), 2));
+}
+
+/// The direction a widget is laid out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+/// How dragging a widget changes the parameter's value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DragStyle {
+    /// The value follows the pointer's position along the widget's axis, like with a slider. The
+    /// bottom of a vertical widget corresponds to the parameter's minimum value.
+    Absolute(Orientation),
+    /// Dragging upwards increases the value, like with a knob.
+    Relative,
+}
+
+impl DragStyle {
+    /// The distance in pixels a drag moved the value towards the parameter's maximum value.
+    fn drag_distance(&self, drag_delta: Vec2) -> f32 {
+        match self {
+            DragStyle::Absolute(Orientation::Horizontal) => drag_delta.x,
+            DragStyle::Absolute(Orientation::Vertical) | DragStyle::Relative => -drag_delta.y,
+        }
+    }
+
+    /// The normalized value for the pointer's position within the widget's rectangle. Only used
+    /// for absolute dragging.
+    fn pointer_proportion(orientation: Orientation, pointer_pos: Pos2, rect: Rect) -> f32 {
+        match orientation {
+            Orientation::Horizontal => {
+                egui::emath::remap_clamp(pointer_pos.x, rect.x_range(), 0.0..=1.0)
+            }
+            Orientation::Vertical => {
+                1.0 - egui::emath::remap_clamp(pointer_pos.y, rect.y_range(), 0.0..=1.0)
+            }
+        }
+    }
+}
+
+/// Handles mouse and keyboard interaction for a parameter widget. The widget only needs to allocate
+/// its response and draw itself.
+pub struct ParamInteraction<'a, P: Param> {
+    pub param: &'a P,
+    pub setter: &'a ParamSetter<'a>,
+
+    /// Will be set in the widget's `ui()` function so we can request keyboard input focus for the
+    /// value entry field.
+    pub keyboard_focus_id: Option<egui::Id>,
+    /// How much a single arrow key press changes the normalized value of a continuous parameter.
+    /// Stepped parameters always move by a single step.
+    pub keyboard_step: f32,
+}
+
+impl<'a, P: Param> ParamInteraction<'a, P> {
+    pub fn new(param: &'a P, setter: &'a ParamSetter<'a>) -> Self {
+        Self {
+            param,
+            setter,
+
+            keyboard_focus_id: None,
+            keyboard_step: DEFAULT_KEYBOARD_STEP,
+        }
+    }
+
+    pub fn plain_value(&self) -> P::Plain {
+        self.param.modulated_plain_value()
+    }
+
+    pub fn normalized_value(&self) -> f32 {
+        self.param.modulated_normalized_value()
+    }
+
+    pub fn string_value(&self) -> String {
+        self.param.to_string()
+    }
+
+    /// Enable the keyboard entry part of the widget.
+    fn begin_keyboard_entry(&self, ui: &Ui) {
+        ui.memory().request_focus(self.keyboard_focus_id.unwrap());
+
+        // Always initialize the field to the current value, that seems nicer than having to
+        // being typing from scratch
+        let value_entry_mutex = ui
+            .memory()
+            .data
+            .get_temp_mut_or_default::<Arc<Mutex<String>>>(*VALUE_ENTRY_MEMORY_ID)
+            .clone();
+        *value_entry_mutex.lock() = self.string_value();
+    }
+
+    fn keyboard_entry_active(&self, ui: &Ui) -> bool {
+        ui.memory().has_focus(self.keyboard_focus_id.unwrap())
+    }
+
+    fn begin_drag(&self) {
+        self.setter.begin_set_parameter(self.param);
+    }
+
+    fn set_normalized_value(&self, normalized: f32) {
+        // This snaps to the nearest plain value if the parameter is stepped in some way.
+        // TODO: As an optimization, we could add a `const CONTINUOUS: bool` to the parameter to
+        //       avoid this normalized->plain->normalized conversion for parameters that don't need
+        //       it
+        let value = self.param.preview_plain(normalized);
+        if value != self.plain_value() {
+            self.setter.set_parameter(self.param, value);
+        }
+    }
+
+    /// Begin and end drag still need to be called when using this. Returns `false` if the string
+    /// could no tbe parsed.
+    fn set_from_string(&self, string: &str) -> bool {
+        match self.param.string_to_normalized_value(string) {
+            Some(normalized_value) => {
+                self.set_normalized_value(normalized_value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Begin and end drag still need to be called when using this..
+    fn reset_param(&self) {
+        self.setter
+            .set_parameter(self.param, self.param.default_plain_value());
+    }
+
+    /// Move the value by `distance * multiplier`. The unsnapped value is kept in egui's memory so
+    /// stepped parameters can still be changed with small drags, and so switching between normal
+    /// and granular dragging halfway through a drag does not cause the value to jump.
+    fn relative_drag(&self, ui: &Ui, distance: f32, multiplier: f32) {
+        let start_value =
+            Self::get_drag_normalized_value_memory(ui).unwrap_or_else(|| self.normalized_value());
+        let new_value = (start_value + (distance * multiplier)).clamp(0.0, 1.0);
+        Self::set_drag_normalized_value_memory(ui, Some(new_value));
+
+        self.set_normalized_value(new_value);
+    }
+
+    fn end_drag(&self) {
+        self.setter.end_set_parameter(self.param);
+    }
+
+    fn get_drag_normalized_value_memory(ui: &Ui) -> Option<f32> {
+        ui.memory()
+            .data
+            .get_temp(*DRAG_NORMALIZED_VALUE_MEMORY_ID)
+            .flatten()
+    }
+
+    fn set_drag_normalized_value_memory(ui: &Ui, value: Option<f32>) {
+        ui.memory()
+            .data
+            .insert_temp(*DRAG_NORMALIZED_VALUE_MEMORY_ID, value);
+    }
+
+    /// Handle the user's input for a widget that was allocated with `response`. This supports
+    /// double click and control click to reset, and shift+drag for granular dragging. Normal
+    /// dragging behaves according to `drag_style`.
+    pub fn handle_input(&self, ui: &Ui, response: &mut Response, drag_style: DragStyle) {
+        // TODO: Optionally (since it can be annoying) add scrolling behind a builder option
+        if response.drag_started() {
+            // When beginning a drag, reset the memory used to keep track of relative drags
+            self.begin_drag();
+            Self::set_drag_normalized_value_memory(ui, None);
+        }
+        // Right clicking opens the context menu instead, see `handle_context_menu()`
+        let pointer_pos = response
+            .interact_pointer_pos()
+            .filter(|_| !ui.input().pointer.secondary_down());
+        if let Some(pointer_pos) = pointer_pos {
+            let drag_distance = drag_style.drag_distance(response.drag_delta());
+            if ui.input().modifiers.command {
+                // Like double clicking, Ctrl+Click should reset the parameter
+                self.reset_param();
+                response.mark_changed();
+            // // FIXME: This releases the focus again when you release the mouse button without
+            // //        moving the mouse a bit for some reason
+            // } else if ui.input().modifiers.alt && self.draw_value {
+            //     // Allow typing in the value on an Alt+Click. Right now this is shown as part of the
+            //     // value field, so it only makes sense when we're drawing that.
+            //     self.begin_keyboard_entry(ui);
+            } else if ui.input().modifiers.shift {
+                // And shift dragging should switch to a more granulra input method
+                self.relative_drag(ui, drag_distance, GRANULAR_DRAG_MULTIPLIER);
+                response.mark_changed();
+            } else {
+                match drag_style {
+                    DragStyle::Absolute(orientation) => {
+                        self.set_normalized_value(DragStyle::pointer_proportion(
+                            orientation,
+                            pointer_pos,
+                            response.rect,
+                        ));
+                        Self::set_drag_normalized_value_memory(ui, None);
+                    }
+                    DragStyle::Relative => {
+                        self.relative_drag(ui, drag_distance, KNOB_DRAG_MULTIPLIER)
+                    }
+                }
+                response.mark_changed();
+            }
+        }
+        if response.double_clicked() {
+            self.reset_param();
+            response.mark_changed();
+        }
+        if response.drag_released() {
+            self.end_drag();
+        }
+    }
+
+    /// Handle keyboard input for a widget that was allocated with `response` while it has keyboard
+    /// focus. The arrow keys move the value by a single step, Page Up and Page Down move it by ten
+    /// steps, and Home and End set it to the parameter's minimum and maximum values. Every key
+    /// press is sent to the host as a separate gesture. If `text_entry` is set, then pressing Enter
+    /// opens the value entry field drawn by [`value_ui()`][Self::value_ui()].
+    pub fn handle_keyboard_input(&self, ui: &Ui, response: &mut Response, text_entry: bool) {
+        if !response.has_focus() {
+            return;
+        }
+
+        let input = ui.input();
+        let target = if input.key_pressed(Key::Home) {
+            Some(0.0)
+        } else if input.key_pressed(Key::End) {
+            Some(1.0)
+        } else {
+            let steps = [
+                (Key::ArrowUp, 1),
+                (Key::ArrowRight, 1),
+                (Key::ArrowDown, -1),
+                (Key::ArrowLeft, -1),
+                (Key::PageUp, PAGE_KEYBOARD_STEPS),
+                (Key::PageDown, -PAGE_KEYBOARD_STEPS),
+            ]
+            .into_iter()
+            .filter(|(key, _)| input.key_pressed(*key))
+            .map(|(_, steps)| steps)
+            .sum::<i32>();
+
+            if steps != 0 {
+                Some(keyboard_step(
+                    self.param.unmodulated_normalized_value(),
+                    self.param.step_count(),
+                    self.keyboard_step,
+                    steps,
+                ))
+            } else {
+                None
+            }
+        };
+        let enter_pressed = input.key_pressed(Key::Enter);
+        // The input state is locked while `input` is alive
+        drop(input);
+
+        if let Some(target) = target {
+            let _drag = self.setter.begin_drag(self.param);
+            self.set_normalized_value(target);
+            response.mark_changed();
+        }
+        if enter_pressed && text_entry {
+            self.begin_keyboard_entry(ui);
+        }
+    }
+
+    /// Show a context menu when the widget allocated with `response` gets right clicked. If the host
+    /// provides a context menu for the parameter then the host's items are listed first. Those are
+    /// followed by the plugin's own items for resetting the parameter and, if `text_entry` is set,
+    /// for entering a new value with the keyboard.
+    pub fn handle_context_menu(&self, ui: &Ui, response: &mut Response, text_entry: bool) {
+        // Only one context menu can be open at a time, so the host's menu is requested when the
+        // menu gets opened and it's then kept in egui's memory until the next menu gets opened
+        let host_menu_mutex = ui
+            .memory()
+            .data
+            .get_temp_mut_or_default::<Arc<Mutex<Option<HostContextMenu>>>>(
+                *HOST_CONTEXT_MENU_MEMORY_ID,
+            )
+            .clone();
+        if response.secondary_clicked() {
+            *host_menu_mutex.lock() = self.setter.context_menu(self.param);
+        }
+
+        let mut changed = false;
+        *response = response.clone().context_menu(|ui| {
+            let mut host_menu = host_menu_mutex.lock();
+            if let Some(menu) = &*host_menu {
+                if let Some(index) = host_menu_ui(ui, menu.items(), 0) {
+                    menu.execute(index);
+                    // The host's menu is no longer needed after one of its items has been selected
+                    *host_menu = None;
+                    ui.close_menu();
+                }
+
+                ui.separator();
+            }
+            drop(host_menu);
+
+            if ui.button("Reset to default").clicked() {
+                self.setter.reset_parameter(self.param);
+                changed = true;
+                ui.close_menu();
+            }
+            if text_entry && ui.button("Enter value").clicked() {
+                self.begin_keyboard_entry(ui);
+                ui.close_menu();
+            }
+        });
+
+        if changed {
+            response.mark_changed();
+        }
+    }
+
+    /// Describe the widget to screen readers as a slider with the parameter's name and its current
+    /// value. This should be called after handling the input so the value is up to date.
+    pub fn add_widget_info(&self, response: &Response) {
+        response.widget_info(|| {
+            WidgetInfo::labeled(
+                WidgetType::Slider,
+                accessibility_label(self.param.name(), &self.string_value()),
+            )
+        });
+    }
+
+    /// Draw the parameter's current value as text, or a text entry field if the value has been
+    /// clicked on.
+    pub fn value_ui(&self, ui: &mut Ui) {
+        let visuals = ui.visuals().widgets.inactive;
+        let should_draw_frame = ui.visuals().button_frame;
+        let padding = ui.spacing().button_padding;
+
+        // Either show the parameter's label, or show a text entry field if the parameter's label
+        // has been clicked on
+        let keyboard_focus_id = self.keyboard_focus_id.unwrap();
+        if self.keyboard_entry_active(ui) {
+            let value_entry_mutex = ui
+                .memory()
+                .data
+                .get_temp_mut_or_default::<Arc<Mutex<String>>>(*VALUE_ENTRY_MEMORY_ID)
+                .clone();
+            let mut value_entry = value_entry_mutex.lock();
+
+            ui.add(
+                TextEdit::singleline(&mut *value_entry)
+                    .id(keyboard_focus_id)
+                    .font(TextStyle::Monospace),
+            );
+            if ui.input().key_pressed(Key::Escape) {
+                // Cancel when pressing escape
+                ui.memory().surrender_focus(keyboard_focus_id);
+            } else if ui.input().key_pressed(Key::Enter) {
+                // And try to set the value by string when pressing enter
+                let _drag = self.setter.begin_drag(self.param);
+                self.set_from_string(&value_entry);
+
+                ui.memory().surrender_focus(keyboard_focus_id);
+            }
+        } else {
+            let text = WidgetText::from(self.string_value()).into_galley(
+                ui,
+                None,
+                ui.available_width() - (padding.x * 2.0),
+                TextStyle::Button,
+            );
+
+            // The value is already changed through the widget itself when using the keyboard, so
+            // this doesn't need to be a separate tab stop
+            let response = ui.allocate_response(
+                text.size() + (padding * 2.0),
+                Sense {
+                    click: true,
+                    drag: false,
+                    focusable: false,
+                },
+            );
+            if response.clicked() {
+                self.begin_keyboard_entry(ui);
+            }
+
+            if ui.is_rect_visible(response.rect) {
+                if should_draw_frame {
+                    let fill = visuals.bg_fill;
+                    let stroke = visuals.bg_stroke;
+                    ui.painter().rect(
+                        response.rect.expand(visuals.expansion),
+                        visuals.rounding,
+                        fill,
+                        stroke,
+                    );
+                }
+
+                let text_pos = ui
+                    .layout()
+                    .align_size_within_rect(text.size(), response.rect.shrink2(padding))
+                    .min;
+                text.paint_with_visuals(ui.painter(), text_pos, &visuals);
+            }
+        }
+    }
+}
+
+/// The normalized value after moving `steps` arrow key presses away from `normalized`. Stepped
+/// parameters move by a single step at a time, and continuous parameters move by `step_size`.
+fn keyboard_step(normalized: f32, step_count: Option<usize>, step_size: f32, steps: i32) -> f32 {
+    match step_count {
+        Some(step_count) if step_count > 0 => {
+            let step_count = step_count as f32;
+            let current_step = (normalized * step_count).round();
+            (current_step + steps as f32).clamp(0.0, step_count) / step_count
+        }
+        _ => (normalized + (steps as f32 * step_size)).clamp(0.0, 1.0),
+    }
+}
+
+/// Draw the host's context menu items. Submenus are drawn as nested menus. `offset` is the index of
+/// `items[0]` in the host's menu. Returns the index of the item that was clicked on, if any.
+fn host_menu_ui(ui: &mut Ui, items: &[ContextMenuItem], offset: usize) -> Option<usize> {
+    let mut clicked = None;
+    let mut index = 0;
+    while index < items.len() {
+        let item = &items[index];
+        match item.kind {
+            ContextMenuItemKind::Action => {
+                let label = if item.checked {
+                    format!("✔ {}", item.name)
+                } else {
+                    item.name.clone()
+                };
+                if ui.add_enabled(item.enabled, Button::new(label)).clicked() {
+                    clicked = Some(offset + index);
+                }
+            }
+            ContextMenuItemKind::Separator => {
+                ui.separator();
+            }
+            ContextMenuItemKind::SubmenuStart => {
+                let submenu_len = submenu_len(&items[index + 1..]);
+                let submenu = &items[index + 1..index + 1 + submenu_len];
+                ui.menu_button(item.name.as_str(), |ui| {
+                    if let Some(submenu_index) = host_menu_ui(ui, submenu, offset + index + 1) {
+                        clicked = Some(submenu_index);
+                    }
+                });
+
+                // This skips over the submenu's items and its end marker
+                index += submenu_len + 1;
+            }
+            // Unbalanced end markers are ignored
+            ContextMenuItemKind::SubmenuEnd => (),
+        }
+
+        index += 1;
+    }
+
+    clicked
+}
+
+/// The number of items in a submenu whose items start at `items[0]`, not including the submenu's
+/// end marker. Nested submenus are included in the count.
+fn submenu_len(items: &[ContextMenuItem]) -> usize {
+    let mut depth = 0;
+    for (index, item) in items.iter().enumerate() {
+        match item.kind {
+            ContextMenuItemKind::SubmenuStart => depth += 1,
+            ContextMenuItemKind::SubmenuEnd if depth == 0 => return index,
+            ContextMenuItemKind::SubmenuEnd => depth -= 1,
+            ContextMenuItemKind::Action | ContextMenuItemKind::Separator => (),
+        }
+    }
+
+    items.len()
+}
+
+/// The label screen readers announce for a parameter widget, e.g. `"Gain, -6.0 dB"`.
+fn accessibility_label(name: &str, value: &str) -> String {
+    format!("{name}, {value}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::{pos2, vec2};
+
+    #[test]
+    fn vertical_drags_increase_upwards() {
+        let drag_up = vec2(3.0, -10.0);
+        assert_eq!(DragStyle::Relative.drag_distance(drag_up), 10.0);
+        assert_eq!(
+            DragStyle::Absolute(Orientation::Vertical).drag_distance(drag_up),
+            10.0
+        );
+        assert_eq!(
+            DragStyle::Absolute(Orientation::Horizontal).drag_distance(drag_up),
+            3.0
+        );
+    }
+
+    #[test]
+    fn pointer_proportion() {
+        let rect = Rect::from_min_size(pos2(10.0, 20.0), vec2(100.0, 200.0));
+        let proportion =
+            |orientation, x, y| DragStyle::pointer_proportion(orientation, pos2(x, y), rect);
+
+        assert_eq!(proportion(Orientation::Horizontal, 10.0, 0.0), 0.0);
+        assert_eq!(proportion(Orientation::Horizontal, 60.0, 0.0), 0.5);
+        assert_eq!(proportion(Orientation::Horizontal, 500.0, 0.0), 1.0);
+
+        // Vertical sliders have their minimum value at the bottom
+        assert_eq!(proportion(Orientation::Vertical, 0.0, 220.0), 0.0);
+        assert_eq!(proportion(Orientation::Vertical, 0.0, 120.0), 0.5);
+        assert_eq!(proportion(Orientation::Vertical, 0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn keyboard_steps_continuous() {
+        assert_eq!(keyboard_step(0.5, None, 0.01, 1), 0.51);
+        assert_eq!(keyboard_step(0.5, None, 0.01, -1), 0.49);
+        assert_eq!(keyboard_step(0.5, None, 0.1, 2), 0.7);
+        assert_eq!(keyboard_step(0.5, None, 0.01, 10), 0.6);
+
+        // The value never leaves the parameter's range
+        assert_eq!(keyboard_step(0.995, None, 0.01, 1), 1.0);
+        assert_eq!(keyboard_step(0.0, None, 0.01, -10), 0.0);
+    }
+
+    #[test]
+    fn keyboard_steps_stepped() {
+        // An integer parameter with five values, or an enum with five variants
+        assert_eq!(keyboard_step(0.5, Some(4), 0.01, 1), 0.75);
+        assert_eq!(keyboard_step(0.5, Some(4), 0.01, -1), 0.25);
+        assert_eq!(keyboard_step(0.0, Some(4), 0.01, 10), 1.0);
+        assert_eq!(keyboard_step(1.0, Some(4), 0.01, -1), 0.75);
+
+        // The configured step size is ignored for stepped parameters, and values in between two
+        // steps are snapped to the nearest step first
+        assert_eq!(keyboard_step(0.5, Some(4), 0.5, 1), 0.75);
+        assert_eq!(keyboard_step(0.3, Some(4), 0.01, 1), 0.5);
+
+        // A boolean parameter has a single step
+        assert_eq!(keyboard_step(0.0, Some(1), 0.01, 1), 1.0);
+        assert_eq!(keyboard_step(1.0, Some(1), 0.01, 1), 1.0);
+    }
+
+    #[test]
+    fn submenu_lengths() {
+        let item = |kind| ContextMenuItem {
+            name: String::new(),
+            kind,
+            enabled: true,
+            checked: false,
+        };
+        let items = [
+            item(ContextMenuItemKind::Action),
+            item(ContextMenuItemKind::SubmenuStart),
+            item(ContextMenuItemKind::Action),
+            item(ContextMenuItemKind::SubmenuEnd),
+            item(ContextMenuItemKind::Separator),
+            item(ContextMenuItemKind::SubmenuEnd),
+            item(ContextMenuItemKind::Action),
+        ];
+
+        // Nested submenus are part of the outer submenu
+        assert_eq!(submenu_len(&items), 5);
+        assert_eq!(submenu_len(&items[2..]), 1);
+        // A submenu without an end marker extends to the end of the menu
+        assert_eq!(submenu_len(&items[..3]), 3);
+        assert_eq!(submenu_len(&[]), 0);
+    }
+
+    #[test]
+    fn accessibility_labels() {
+        assert_eq!(
+            accessibility_label("Tape speed", "40 in/s"),
+            "Tape speed, 40 in/s"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::f32::consts::PI;
+
+use egui::{pos2, vec2, Align, Layout, Pos2, Response, Sense, Shape, Stroke, Ui, Widget};
+use nih_plug::prelude::{Param, ParamSetter};
+
+use super::param_interaction::{DragStyle, ParamInteraction};
+use super::util;
+
+/// The default diameter of a knob in points.
+const DEFAULT_DIAMETER: f32 = 40.0;
+/// The width of the knob's value arc in points.
+const ARC_WIDTH: f32 = 4.0;
+/// The angle of the knob's arc at the parameter's minimum value. Angles are in radians, measured
+/// clockwise from the positive x-axis in screen space. This places the minimum at the bottom left.
+const START_ANGLE: f32 = 0.75 * PI;
+/// The angle the knob's arc covers between the parameter's minimum and maximum values. The
+/// maximum ends up at the bottom right, and the center is straight up.
+const SWEEP_ANGLE: f32 = 1.5 * PI;
+/// The maximum angle in radians covered by a single line segment when drawing arcs.
+const ARC_SEGMENT_ANGLE: f32 = PI / 32.0;
+
+/// A rotary knob for a parameter. The knob's arc shows the parameter's current value. If the
+/// parameter is being modulated, then the modulation is shown as a second arc inside of the main
+/// arc. Dragging upwards increases the value. The knob supports the same double click and control
+/// click to reset, shift+drag for granular dragging, and text value entry by clicking on the value
+/// text as [`ParamSlider`][super::ParamSlider]. The parameter's name and value are drawn below the
+/// knob. Keyboard input and screen reader support also work the same way as for the slider.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct ParamKnob<'a, P: Param> {
+    interaction: ParamInteraction<'a, P>,
+
+    draw_name: bool,
+    draw_value: bool,
+    diameter: Option<f32>,
+}
+
+impl<'a, P: Param> ParamKnob<'a, P> {
+    /// Create a new knob for a parameter. Use the other methods to modify the knob before passing
+    /// it to [`Ui::add()`].
+    pub fn for_param(param: &'a P, setter: &'a ParamSetter<'a>) -> Self {
+        Self {
+            interaction: ParamInteraction::new(param, setter),
+
+            draw_name: true,
+            draw_value: true,
+            diameter: None,
+        }
+    }
+
+    /// Don't draw the parameter's name below the knob.
+    pub fn without_name(mut self) -> Self {
+        self.draw_name = false;
+        self
+    }
+
+    /// Don't draw the parameter's current value below the knob.
+    pub fn without_value(mut self) -> Self {
+        self.draw_value = false;
+        self
+    }
+
+    /// Set a custom diameter for the knob.
+    pub fn with_diameter(mut self, diameter: f32) -> Self {
+        self.diameter = Some(diameter);
+        self
+    }
+
+    /// Set how much a single arrow key press changes the parameter's normalized value. See
+    /// [`ParamSlider::with_keyboard_step()`][super::ParamSlider::with_keyboard_step()].
+    pub fn with_keyboard_step(mut self, step: f32) -> Self {
+        self.interaction.keyboard_step = step;
+        self
+    }
+
+    fn knob_ui(&self, ui: &mut Ui, response: &mut Response) {
+        // Handle user input
+        self.interaction
+            .handle_input(ui, response, DragStyle::Relative);
+        self.interaction
+            .handle_keyboard_input(ui, response, self.draw_value);
+        self.interaction
+            .handle_context_menu(ui, response, self.draw_value);
+        self.interaction.add_widget_info(response);
+
+        // And finally draw the thing
+        if ui.is_rect_visible(response.rect) {
+            let center = response.rect.center();
+            let radius = (response.rect.width().min(response.rect.height()) - ARC_WIDTH) / 2.0;
+            let painter = ui.painter();
+
+            if response.has_focus() {
+                painter.rect_stroke(response.rect, 2.0, ui.visuals().selection.stroke);
+            }
+
+            // The full range of the knob is drawn in the background, with the parameter's
+            // unmodulated value drawn on top of that
+            painter.add(arc_shape(
+                center,
+                radius,
+                0.0,
+                1.0,
+                Stroke::new(ARC_WIDTH, ui.visuals().widgets.inactive.bg_fill),
+            ));
+
+            let value = self.interaction.param.unmodulated_normalized_value();
+            let value_color = if response.dragged() {
+                util::add_hsv(ui.visuals().selection.bg_fill, 0.0, -0.1, 0.1)
+            } else {
+                ui.visuals().selection.bg_fill
+            };
+            if value > 0.0 {
+                painter.add(arc_shape(
+                    center,
+                    radius,
+                    0.0,
+                    value,
+                    Stroke::new(ARC_WIDTH, value_color),
+                ));
+            }
+
+            // Modulation is shown as a thinner arc inside of the main arc going from the
+            // unmodulated value to the modulated value
+            let modulated_value = self.interaction.normalized_value();
+            if modulated_value != value {
+                painter.add(arc_shape(
+                    center,
+                    radius - (ARC_WIDTH * 1.5),
+                    value,
+                    modulated_value,
+                    Stroke::new(ARC_WIDTH / 2.0, util::add_hsv(value_color, 0.5, 0.0, 0.0)),
+                ));
+            }
+
+            // A line pointing at the knob's current value makes it easier to see the value at a
+            // glance
+            let pointer_angle = normalized_to_angle(modulated_value);
+            painter.line_segment(
+                [
+                    arc_point(center, radius * 0.25, pointer_angle),
+                    arc_point(center, radius * 0.7, pointer_angle),
+                ],
+                Stroke::new(2.0, ui.visuals().widgets.active.fg_stroke.color),
+            );
+        }
+    }
+}
+
+impl<P: Param> Widget for ParamKnob<'_, P> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        let diameter = self.diameter.unwrap_or(DEFAULT_DIAMETER);
+        // The name and value labels are usually wider than the knob itself
+        let width = diameter + ui.spacing().interact_size.x;
+
+        ui.allocate_ui_with_layout(
+            vec2(width, ui.available_height()),
+            Layout::top_down(Align::Center),
+            |ui| {
+                let mut response =
+                    ui.allocate_response(vec2(diameter, diameter), Sense::click_and_drag());
+                // The value entry field needs its own ID for keeping track of keyboard focus
+                self.interaction.keyboard_focus_id = Some(response.id.with("value_entry"));
+
+                self.knob_ui(ui, &mut response);
+                if self.draw_name {
+                    ui.label(self.interaction.param.name());
+                }
+                if self.draw_value {
+                    self.interaction.value_ui(ui);
+                }
+
+                response
+            },
+        )
+        .inner
+    }
+}
+
+/// The angle of the knob's arc at a normalized value. See [`START_ANGLE`].
+fn normalized_to_angle(normalized: f32) -> f32 {
+    START_ANGLE + (normalized.clamp(0.0, 1.0) * SWEEP_ANGLE)
+}
+
+/// The point at `angle` on a circle around `center`.
+fn arc_point(center: Pos2, radius: f32, angle: f32) -> Pos2 {
+    pos2(
+        center.x + (radius * angle.cos()),
+        center.y + (radius * angle.sin()),
+    )
+}
+
+/// The points for an arc on a knob going from normalized value `from` to normalized value `to`.
+/// The arc is split into enough line segments to look smooth.
+fn arc_points(center: Pos2, radius: f32, from: f32, to: f32) -> Vec<Pos2> {
+    let start_angle = normalized_to_angle(from);
+    let end_angle = normalized_to_angle(to);
+    let num_segments = ((end_angle - start_angle).abs() / ARC_SEGMENT_ANGLE)
+        .ceil()
+        .max(1.0) as usize;
+
+    (0..=num_segments)
+        .map(|segment_idx| {
+            let t = segment_idx as f32 / num_segments as f32;
+            arc_point(
+                center,
+                radius,
+                start_angle + ((end_angle - start_angle) * t),
+            )
+        })
+        .collect()
+}
+
+fn arc_shape(center: Pos2, radius: f32, from: f32, to: f32, stroke: Stroke) -> Shape {
+    Shape::line(arc_points(center, radius, from, to), stroke)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_pos_eq(actual: Pos2, expected: Pos2) {
+        assert!(
+            (actual - expected).length() < 1e-4,
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn arc_angles() {
+        assert_eq!(normalized_to_angle(0.0), 0.75 * PI);
+        assert_eq!(normalized_to_angle(0.5), 1.5 * PI);
+        assert_eq!(normalized_to_angle(1.0), 2.25 * PI);
+
+        // Values outside of the parameter's range are clamped to the knob's range
+        assert_eq!(normalized_to_angle(-1.0), normalized_to_angle(0.0));
+        assert_eq!(normalized_to_angle(2.0), normalized_to_angle(1.0));
+    }
+
+    #[test]
+    fn arc_positions() {
+        // Screen space coordinates have the y-axis pointing down, so the minimum is at the bottom
+        // left, the center is straight up, and the maximum is at the bottom right
+        let center = pos2(50.0, 50.0);
+        let diagonal = 10.0 * std::f32::consts::FRAC_1_SQRT_2;
+        assert_pos_eq(
+            arc_point(center, 10.0, normalized_to_angle(0.0)),
+            pos2(50.0 - diagonal, 50.0 + diagonal),
+        );
+        assert_pos_eq(
+            arc_point(center, 10.0, normalized_to_angle(0.5)),
+            pos2(50.0, 40.0),
+        );
+        assert_pos_eq(
+            arc_point(center, 10.0, normalized_to_angle(1.0)),
+            pos2(50.0 + diagonal, 50.0 + diagonal),
+        );
+    }
+
+    #[test]
+    fn arc_segments() {
+        let center = pos2(0.0, 0.0);
+        let points = arc_points(center, 1.0, 0.0, 1.0);
+        assert_eq!(points.len(), 49);
+        assert_pos_eq(points[0], arc_point(center, 1.0, normalized_to_angle(0.0)));
+        assert_pos_eq(
+            *points.last().unwrap(),
+            arc_point(center, 1.0, normalized_to_angle(1.0)),
+        );
+
+        // Empty arcs and arcs going backwards still result in a valid line
+        assert_eq!(arc_points(center, 1.0, 0.5, 0.5).len(), 2);
+        let backwards = arc_points(center, 1.0, 0.75, 0.25);
+        assert_pos_eq(
+            backwards[0],
+            arc_point(center, 1.0, normalized_to_angle(0.75)),
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use egui::{pos2, vec2, Align2, FontId, Rect, Response, Sense, Shape, Stroke, Ui, Widget};
+use nih_plug::util::SpectrumFrame;
+use std::ops::RangeInclusive;
+
+/// The default frequency range shown by the widget, in Hertz.
+const DEFAULT_FREQUENCY_RANGE: RangeInclusive<f32> = 20.0..=20_000.0;
+/// The default decibel range shown by the widget.
+const DEFAULT_DB_RANGE: RangeInclusive<f32> = -84.0..=0.0;
+/// The distance between two horizontal gridlines, in decibels.
+const DB_GRIDLINE_INTERVAL: f32 = 12.0;
+/// The frequencies vertical gridlines are drawn at, along with their labels.
+const FREQUENCY_GRIDLINES: [(f32, &str); 10] = [
+    (20.0, "20"),
+    (50.0, "50"),
+    (100.0, "100"),
+    (200.0, "200"),
+    (500.0, "500"),
+    (1_000.0, "1k"),
+    (2_000.0, "2k"),
+    (5_000.0, "5k"),
+    (10_000.0, "10k"),
+    (20_000.0, "20k"),
+];
+
+/// Draws a [`SpectrumFrame`] computed by a
+/// [`SpectrumAnalyzer`][nih_plug::util::SpectrumAnalyzer] on a logarithmic frequency axis with
+/// decibel gridlines. The frequencies are computed from the sample rate stored in the frame. The
+/// widget takes up the available width by default.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct SpectrumWidget<'a> {
+    frame: &'a SpectrumFrame,
+
+    size: Option<egui::Vec2>,
+    frequency_range: RangeInclusive<f32>,
+    db_range: RangeInclusive<f32>,
+}
+
+impl<'a> SpectrumWidget<'a> {
+    /// Create a widget for a spectrum, usually obtained from
+    /// [`SpectrumOutput::read()`][nih_plug::util::SpectrumOutput::read()].
+    pub fn new(frame: &'a SpectrumFrame) -> Self {
+        Self {
+            frame,
+
+            size: None,
+            frequency_range: DEFAULT_FREQUENCY_RANGE,
+            db_range: DEFAULT_DB_RANGE,
+        }
+    }
+
+    /// Set a custom size for the widget.
+    pub fn with_size(mut self, size: egui::Vec2) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Change the displayed frequency range, in Hertz.
+    pub fn with_frequency_range(mut self, range: RangeInclusive<f32>) -> Self {
+        self.frequency_range = range;
+        self
+    }
+
+    /// Change the displayed decibel range.
+    pub fn with_db_range(mut self, range: RangeInclusive<f32>) -> Self {
+        self.db_range = range;
+        self
+    }
+
+    fn draw_grid(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter_at(rect);
+        let grid_stroke = ui.visuals().widgets.noninteractive.bg_stroke;
+        let label_color = ui.visuals().weak_text_color();
+        let font = FontId::proportional(10.0);
+
+        let mut db = (self.db_range.end() / DB_GRIDLINE_INTERVAL).floor() * DB_GRIDLINE_INTERVAL;
+        while db >= *self.db_range.start() {
+            let y = db_to_y(db, &self.db_range, rect);
+            painter.line_segment([pos2(rect.left(), y), pos2(rect.right(), y)], grid_stroke);
+            painter.text(
+                pos2(rect.left() + 2.0, y),
+                Align2::LEFT_TOP,
+                format!("{db:.0} dB"),
+                font.clone(),
+                label_color,
+            );
+
+            db -= DB_GRIDLINE_INTERVAL;
+        }
+
+        for (frequency, label) in FREQUENCY_GRIDLINES {
+            if !self.frequency_range.contains(&frequency) {
+                continue;
+            }
+
+            let x = frequency_to_x(frequency, &self.frequency_range, rect);
+            painter.line_segment([pos2(x, rect.top()), pos2(x, rect.bottom())], grid_stroke);
+            painter.text(
+                pos2(x + 2.0, rect.bottom()),
+                Align2::LEFT_BOTTOM,
+                label,
+                font.clone(),
+                label_color,
+            );
+        }
+    }
+
+    fn draw_spectrum(&self, ui: &Ui, rect: Rect) {
+        // The analyzer won't have a sample rate until it has been initialized
+        if self.frame.sample_rate <= 0.0 {
+            return;
+        }
+
+        // The DC bin can't be drawn on a logarithmic axis
+        let points: Vec<_> = (1..self.frame.num_bins())
+            .map(|bin_idx| (self.frame.bin_frequency(bin_idx), bin_idx))
+            .filter(|(frequency, _)| self.frequency_range.contains(frequency))
+            .map(|(frequency, bin_idx)| {
+                let magnitude_db = self.frame.magnitudes_db[bin_idx]
+                    .clamp(*self.db_range.start(), *self.db_range.end());
+                pos2(
+                    frequency_to_x(frequency, &self.frequency_range, rect),
+                    db_to_y(magnitude_db, &self.db_range, rect),
+                )
+            })
+            .collect();
+
+        ui.painter_at(rect).add(Shape::line(
+            points,
+            Stroke::new(1.5, ui.visuals().selection.bg_fill),
+        ));
+    }
+}
+
+impl Widget for SpectrumWidget<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let size = self
+            .size
+            .unwrap_or_else(|| vec2(ui.available_width(), ui.available_width() * 0.4));
+        let (rect, response) = ui.allocate_exact_size(size, Sense::hover());
+        if ui.is_rect_visible(rect) {
+            ui.painter()
+                .rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+            self.draw_grid(ui, rect);
+            self.draw_spectrum(ui, rect);
+        }
+
+        response
+    }
+}
+
+/// The horizontal position for a frequency on a logarithmic axis spanning `rect`'s width.
+fn frequency_to_x(frequency: f32, frequency_range: &RangeInclusive<f32>, rect: Rect) -> f32 {
+    let (min, max) = (frequency_range.start().log2(), frequency_range.end().log2());
+    egui::emath::remap(frequency.log2(), min..=max, rect.x_range())
+}
+
+/// The vertical position for a decibel value, with the top of the range at the top of `rect`.
+fn db_to_y(db: f32, db_range: &RangeInclusive<f32>, rect: Rect) -> f32 {
+    egui::emath::remap(db, db_range.clone(), rect.bottom()..=rect.top())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logarithmic_frequency_axis() {
+        let rect = Rect::from_min_max(pos2(10.0, 0.0), pos2(310.0, 100.0));
+
+        // Every decade takes up a third of the width
+        assert_eq!(frequency_to_x(20.0, &DEFAULT_FREQUENCY_RANGE, rect), 10.0);
+        assert!((frequency_to_x(200.0, &DEFAULT_FREQUENCY_RANGE, rect) - 110.0).abs() < 1e-3);
+        assert!((frequency_to_x(2_000.0, &DEFAULT_FREQUENCY_RANGE, rect) - 210.0).abs() < 1e-3);
+        assert_eq!(
+            frequency_to_x(20_000.0, &DEFAULT_FREQUENCY_RANGE, rect),
+            310.0
+        );
+    }
+
+    #[test]
+    fn db_axis() {
+        let rect = Rect::from_min_max(pos2(0.0, 10.0), pos2(100.0, 94.0));
+
+        assert_eq!(db_to_y(0.0, &DEFAULT_DB_RANGE, rect), 10.0);
+        assert_eq!(db_to_y(-42.0, &DEFAULT_DB_RANGE, rect), 52.0);
+        assert_eq!(db_to_y(-84.0, &DEFAULT_DB_RANGE, rect), 94.0);
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::Arc;
+
+use crossbeam::atomic::AtomicCell;
+use egui::{Response, Ui, Widget};
+use lazy_static::lazy_static;
+use nih_plug::context::process::Transport;
+use nih_plug::prelude::{FloatParam, ParamSetter};
+use parking_lot::Mutex;
+
+/// The resolution used for the tick part of [`MusicalTime`], in ticks per time signature beat.
+pub const TICKS_PER_BEAT: i64 = 960;
+
+/// Taps that are further apart than this many seconds start a new tempo measurement.
+const TAP_TIMEOUT_SECONDS: f64 = 2.0;
+/// The maximum number of taps that are averaged when computing the tapped tempo.
+const MAX_TAPS: usize = 8;
+
+lazy_static! {
+    static ref TAP_TIMES_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
+}
+
+/// The parts of the [`Transport`] needed to display the host's position, copied out of
+/// [`ProcessContext::transport()`][nih_plug::prelude::ProcessContext::transport()] so it can be
+/// sent to the editor through a [`TransportSnapshotCell`]. The plugin needs to include
+/// `TransportRequirements::TIME_SIGNATURE` in its
+/// [`Plugin::REQUIRED_TRANSPORT`][nih_plug::prelude::Plugin::REQUIRED_TRANSPORT] to be able to show
+/// the time signature.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TransportSnapshot {
+    /// Whether the transport is currently running.
+    pub playing: bool,
+    /// The project's tempo in beats per minute, if known.
+    pub tempo: Option<f64>,
+    /// The time signature's numerator and denominator, if known.
+    pub time_sig: Option<(i32, i32)>,
+    /// The position in the song in quarter notes, if known.
+    pub pos_beats: Option<f64>,
+}
+
+/// A position in musical time. Bars and beats are counted from one like in most DAWs, and beats are
+/// measured in the time signature's denominator, so a beat is an eighth note in 7/8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusicalTime {
+    pub bar: i64,
+    pub beat: i64,
+    /// The tick within the beat, in `[0, TICKS_PER_BEAT)`.
+    pub tick: i64,
+}
+
+/// A cell the audio thread can store a [`TransportSnapshot`] in every block, and that the editor can
+/// read the latest snapshot from without blocking the audio thread for any meaningful amount of
+/// time. Store this in an `Arc` shared by the plugin and its editor.
+#[derive(Debug, Default)]
+pub struct TransportSnapshotCell {
+    snapshot: AtomicCell<TransportSnapshot>,
+}
+
+/// Displays the host's play state, position in bars, beats, and ticks, tempo, and time signature
+/// based on a [`TransportSnapshot`]. Any information the host doesn't provide is shown as dashes.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct TransportDisplay {
+    snapshot: TransportSnapshot,
+}
+
+/// A button that sets a tempo parameter to the tempo the user taps in. Useful for the standalone
+/// target or hosts that don't provide a tempo.
+#[must_use = "You should put this widget in an ui with `ui.add(widget);`"]
+pub struct TempoTap<'a> {
+    param: &'a FloatParam,
+    setter: &'a ParamSetter<'a>,
+}
+
+impl TransportSnapshot {
+    /// Copy the relevant information out of the host's transport information.
+    pub fn from_transport(transport: &Transport) -> Self {
+        Self {
+            playing: transport.playing,
+            tempo: transport.tempo,
+            time_sig: transport
+                .time_sig_numerator
+                .zip(transport.time_sig_denominator),
+            pos_beats: transport.pos_beats(),
+        }
+    }
+
+    /// The current position in musical time, or `None` if the host didn't provide a position. This
+    /// assumes a 4/4 time signature if the host didn't provide one.
+    pub fn musical_time(&self) -> Option<MusicalTime> {
+        let (numerator, denominator) = self.time_sig.unwrap_or((4, 4));
+        if numerator <= 0 || denominator <= 0 {
+            return None;
+        }
+
+        // Rounding to whole ticks first avoids positions like 3.9999999 beats showing up as the end
+        // of the previous beat
+        let beat_length = 4.0 / denominator as f64;
+        let total_ticks = (self.pos_beats? / beat_length * TICKS_PER_BEAT as f64).round() as i64;
+        let total_beats = total_ticks.div_euclid(TICKS_PER_BEAT);
+
+        Some(MusicalTime {
+            bar: total_beats.div_euclid(numerator as i64) + 1,
+            beat: total_beats.rem_euclid(numerator as i64) + 1,
+            tick: total_ticks.rem_euclid(TICKS_PER_BEAT),
+        })
+    }
+}
+
+impl TransportSnapshotCell {
+    /// Store the latest transport information. Call this from the audio thread.
+    pub fn store(&self, snapshot: TransportSnapshot) {
+        self.snapshot.store(snapshot);
+    }
+
+    /// Get the latest transport information. Call this from the editor.
+    pub fn load(&self) -> TransportSnapshot {
+        self.snapshot.load()
+    }
+}
+
+impl TransportDisplay {
+    pub fn new(snapshot: TransportSnapshot) -> Self {
+        Self { snapshot }
+    }
+}
+
+impl Widget for TransportDisplay {
+    fn ui(self, ui: &mut Ui) -> Response {
+        ui.horizontal(|ui| {
+            ui.label(if self.snapshot.playing { "▶" } else { "■" });
+            ui.monospace(match self.snapshot.musical_time() {
+                Some(MusicalTime { bar, beat, tick }) => format!("{bar}.{beat}.{tick:03}"),
+                None => String::from("-.-.---"),
+            });
+            ui.label(match self.snapshot.tempo {
+                Some(tempo) => format!("{tempo:.2} BPM"),
+                None => String::from("--- BPM"),
+            });
+            ui.label(match self.snapshot.time_sig {
+                Some((numerator, denominator)) => format!("{numerator}/{denominator}"),
+                None => String::from("-/-"),
+            });
+        })
+        .response
+    }
+}
+
+impl<'a> TempoTap<'a> {
+    /// Create a tap tempo button that writes the tapped tempo in beats per minute to `param`.
+    pub fn for_param(param: &'a FloatParam, setter: &'a ParamSetter<'a>) -> Self {
+        Self { param, setter }
+    }
+}
+
+impl Widget for TempoTap<'_> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let response = ui.button("Tap");
+        if response.clicked() {
+            let now = ui.input().time;
+            let tap_times = ui
+                .memory()
+                .data
+                .get_temp_mut_or_default::<Arc<Mutex<Vec<f64>>>>(
+                    response.id.with(*TAP_TIMES_MEMORY_ID),
+                )
+                .clone();
+
+            let mut tap_times = tap_times.lock();
+            if matches!(tap_times.last(), Some(last_tap) if now - last_tap > TAP_TIMEOUT_SECONDS) {
+                tap_times.clear();
+            }
+            if tap_times.len() == MAX_TAPS {
+                tap_times.remove(0);
+            }
+            tap_times.push(now);
+
+            if let Some(tempo) = tempo_from_taps(&tap_times) {
+                self.setter
+                    .set_parameter_with_gesture(self.param, tempo as f32);
+            }
+        }
+
+        response.on_hover_text(format!("Current tempo: {}", self.param))
+    }
+}
+
+/// Compute the average tempo in beats per minute from a list of tap times in seconds, oldest first.
+/// Returns `None` if there are fewer than two taps.
+fn tempo_from_taps(tap_times: &[f64]) -> Option<f64> {
+    let (first_tap, last_tap) = (tap_times.first()?, tap_times.last()?);
+    let average_interval = (last_tap - first_tap) / (tap_times.len() - 1) as f64;
+    if average_interval > 0.0 {
+        Some(60.0 / average_interval)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(time_sig: (i32, i32), pos_beats: f64) -> TransportSnapshot {
+        TransportSnapshot {
+            playing: true,
+            tempo: None,
+            time_sig: Some(time_sig),
+            pos_beats: Some(pos_beats),
+        }
+    }
+
+    fn musical_time(bar: i64, beat: i64, tick: i64) -> Option<MusicalTime> {
+        Some(MusicalTime { bar, beat, tick })
+    }
+
+    #[test]
+    fn seven_eight() {
+        // A bar of 7/8 is 3.5 quarter notes long, and a beat is half a quarter note
+        assert_eq!(snapshot((7, 8), 0.0).musical_time(), musical_time(1, 1, 0));
+        assert_eq!(snapshot((7, 8), 3.0).musical_time(), musical_time(1, 7, 0));
+        assert_eq!(snapshot((7, 8), 3.5).musical_time(), musical_time(2, 1, 0));
+        assert_eq!(
+            snapshot((7, 8), 3.75).musical_time(),
+            musical_time(2, 1, 480)
+        );
+        assert_eq!(snapshot((7, 8), 10.5).musical_time(), musical_time(4, 1, 0));
+    }
+
+    #[test]
+    fn five_four() {
+        assert_eq!(snapshot((5, 4), 4.0).musical_time(), musical_time(1, 5, 0));
+        assert_eq!(snapshot((5, 4), 5.0).musical_time(), musical_time(2, 1, 0));
+        assert_eq!(
+            snapshot((5, 4), 7.25).musical_time(),
+            musical_time(2, 3, 240)
+        );
+    }
+
+    #[test]
+    fn long_decimal_tempo() {
+        // 37 bars of 5/4 at an awkward tempo, computed from a position in seconds like the wrappers
+        // would do when the host only reports the position in seconds
+        const TEMPO: f64 = 133.333_333_333_333;
+        let pos_seconds = (37.0 * 5.0) / TEMPO * 60.0;
+        let pos_beats = pos_seconds / 60.0 * TEMPO;
+
+        assert_eq!(
+            snapshot((5, 4), pos_beats).musical_time(),
+            musical_time(38, 1, 0)
+        );
+        assert_eq!(
+            snapshot((5, 4), pos_beats - 1e-9).musical_time(),
+            musical_time(38, 1, 0)
+        );
+    }
+
+    #[test]
+    fn negative_position() {
+        // Pre-roll positions end up in bar zero or earlier
+        assert_eq!(snapshot((4, 4), -1.0).musical_time(), musical_time(0, 4, 0));
+    }
+
+    #[test]
+    fn unknown_position() {
+        let snapshot = TransportSnapshot {
+            time_sig: Some((3, 4)),
+            ..TransportSnapshot::default()
+        };
+        assert_eq!(snapshot.musical_time(), None);
+    }
+
+    #[test]
+    fn unknown_time_signature() {
+        let snapshot = TransportSnapshot {
+            pos_beats: Some(4.5),
+            ..TransportSnapshot::default()
+        };
+        assert_eq!(snapshot.musical_time(), musical_time(2, 1, 480));
+    }
+
+    #[test]
+    fn tapped_tempo() {
+        assert_eq!(tempo_from_taps(&[]), None);
+        assert_eq!(tempo_from_taps(&[1.0]), None);
+        assert_eq!(tempo_from_taps(&[1.0, 1.5, 2.0, 2.5]), Some(120.0));
+    }
+}
@@ -1,6 +1,7 @@
 //! Utilities for creating these widgets.
 
 use egui::Color32;
+use nih_plug::prelude::ChannelColor;
 
 /// Additively modify the hue, saturation, and lightness [0, 1] values of a color.
 pub fn add_hsv(color: Color32, h: f32, s: f32, v: f32) -> Color32 {
@@ -19,3 +20,10 @@ pub fn scale_hsv(color: Color32, h: f32, s: f32, v: f32) -> Color32 {
     hsv.v *= v;
     hsv.into()
 }
+
+/// Convert a track color from
+/// [`GuiContext::channel_info()`][nih_plug::prelude::GuiContext::channel_info()] to an egui color,
+/// for instance to use as the editor's accent color.
+pub fn channel_color(color: ChannelColor) -> Color32 {
+    Color32::from_rgba_unmultiplied(color.r, color.g, color.b, color.a)
+}
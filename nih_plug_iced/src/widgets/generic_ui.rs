@@ -161,7 +161,7 @@ where
         let param_map = self.params.param_map();
         for (_, param_ptr, _) in &param_map {
             let flags = unsafe { param_ptr.flags() };
-            if flags.contains(ParamFlags::HIDE_IN_GENERIC_UI) {
+            if flags.intersects(ParamFlags::HIDDEN | ParamFlags::HIDE_IN_GENERIC_UI) {
                 continue;
             }
 
@@ -172,7 +172,7 @@ where
 
         for (_, param_ptr, _) in param_map {
             let flags = unsafe { param_ptr.flags() };
-            if flags.contains(ParamFlags::HIDE_IN_GENERIC_UI) {
+            if flags.intersects(ParamFlags::HIDDEN | ParamFlags::HIDE_IN_GENERIC_UI) {
                 continue;
             }
 
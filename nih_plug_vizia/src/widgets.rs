@@ -129,6 +129,9 @@ impl Model for WindowModel {
                         height: old_logical_height,
                     });
                     cx.set_user_scale_factor(old_user_scale_factor);
+                } else {
+                    // The editor's size is persisted as part of the plugin's state
+                    self.context.mark_state_dirty();
                 }
             }
         });
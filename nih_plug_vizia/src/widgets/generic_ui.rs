@@ -61,7 +61,7 @@ impl GenericUi {
             let param_map = params.map(|params| params.as_ref().param_map()).get(cx);
             for (_, param_ptr, _) in param_map {
                 let flags = unsafe { param_ptr.flags() };
-                if flags.contains(ParamFlags::HIDE_IN_GENERIC_UI) {
+                if flags.intersects(ParamFlags::HIDDEN | ParamFlags::HIDE_IN_GENERIC_UI) {
                     continue;
                 }
 
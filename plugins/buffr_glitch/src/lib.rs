@@ -234,7 +234,7 @@ impl ClapPlugin for BuffrGlitch {
 
 impl Vst3Plugin for BuffrGlitch {
     const VST3_CLASS_ID: [u8; 16] = *b"BuffrGlitch.RvdH";
-    const VST3_CATEGORIES: &'static str = "Fx";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
 }
 
 nih_export_clap!(BuffrGlitch);
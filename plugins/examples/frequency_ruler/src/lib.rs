@@ -0,0 +1,263 @@
+use nih_plug::prelude::*;
+use nih_plug_egui::egui::{self, Align2, Color32, FontId, Pos2, Sense, Shape, Stroke};
+use nih_plug_egui::{create_egui_editor, widgets, EguiState};
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+/// The width of the cutoff slider and the ruler below it.
+const RULER_WIDTH: f32 = 360.0;
+/// The height of the ruler, including the labels.
+const RULER_HEIGHT: f32 = 100.0;
+/// The number of points used to draw the cutoff parameter's response curve.
+const CURVE_POINTS: usize = 128;
+/// The frequencies the ruler has labeled ticks for.
+const TICK_FREQUENCIES: [f32; 10] = [
+    20.0, 50.0, 100.0, 200.0, 500.0, 1_000.0, 2_000.0, 5_000.0, 10_000.0, 20_000.0,
+];
+
+/// A simple one-pole low-pass filter. The editor draws a frequency ruler below the cutoff slider,
+/// along with the cutoff parameter's response curve. Both are computed from the parameter's own
+/// range so they always line up with the slider, regardless of how the range is skewed.
+pub struct FrequencyRuler {
+    params: Arc<FrequencyRulerParams>,
+
+    sample_rate: f32,
+    /// The filter's state for each channel.
+    filter_states: Vec<f32>,
+}
+
+#[derive(Params)]
+pub struct FrequencyRulerParams {
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
+
+    #[id = "cutoff"]
+    pub cutoff: FloatParam,
+}
+
+/// Buffers for drawing the ruler, so they don't need to be reallocated on every frame.
+struct RulerState {
+    /// The cutoff parameter's plain values at evenly spaced normalized values.
+    curve: [f32; CURVE_POINTS],
+    /// The normalized values for [`TICK_FREQUENCIES`].
+    tick_positions: [f32; TICK_FREQUENCIES.len()],
+}
+
+impl Default for FrequencyRuler {
+    fn default() -> Self {
+        Self {
+            params: Arc::new(FrequencyRulerParams::default()),
+
+            sample_rate: 1.0,
+            filter_states: Vec::new(),
+        }
+    }
+}
+
+impl Default for FrequencyRulerParams {
+    fn default() -> Self {
+        Self {
+            editor_state: EguiState::from_size(400, 200),
+
+            cutoff: FloatParam::new(
+                "Cutoff",
+                1_000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_smoother(SmoothingStyle::Logarithmic(20.0))
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+        }
+    }
+}
+
+impl Default for RulerState {
+    fn default() -> Self {
+        Self {
+            curve: [0.0; CURVE_POINTS],
+            tick_positions: [0.0; TICK_FREQUENCIES.len()],
+        }
+    }
+}
+
+impl Plugin for FrequencyRuler {
+    const NAME: &'static str = "Frequency Ruler";
+    const VENDOR: &'static str = "Moist Plugins GmbH";
+    const URL: &'static str = "https://youtu.be/dQw4w9WgXcQ";
+    const EMAIL: &'static str = "info@example.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 2;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 2;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        let params = self.params.clone();
+        create_egui_editor(
+            self.params.editor_state.clone(),
+            RulerState::default(),
+            |_, _| {},
+            move |egui_ctx, setter, state| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    ui.label("Cutoff");
+                    ui.add(
+                        widgets::ParamSlider::for_param(&params.cutoff, setter)
+                            .with_width(RULER_WIDTH),
+                    );
+
+                    ui.allocate_space(egui::Vec2::splat(4.0));
+                    draw_ruler(ui, &params.cutoff, setter, state);
+                });
+            },
+        )
+    }
+
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // This works with any symmetrical IO layout
+        config.num_input_channels == config.num_output_channels && config.num_input_channels > 0
+    }
+
+    fn initialize(
+        &mut self,
+        bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        self.filter_states
+            .resize(bus_config.num_output_channels as usize, 0.0);
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.filter_states.fill(0.0);
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            let cutoff = self.params.cutoff.smoothed.next();
+            let coefficient = 1.0 - (-TAU * cutoff / self.sample_rate).exp();
+
+            for (sample, state) in channel_samples.into_iter().zip(&mut self.filter_states) {
+                *state += coefficient * (*sample - *state);
+                *sample = *state;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+/// Draw a ruler with labeled ticks at [`TICK_FREQUENCIES`] that lines up with the slider for
+/// `cutoff`, together with the parameter's response curve and its current value.
+fn draw_ruler(
+    ui: &mut egui::Ui,
+    cutoff: &FloatParam,
+    setter: &ParamSetter,
+    state: &mut RulerState,
+) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(RULER_WIDTH, RULER_HEIGHT), Sense::hover());
+    let rect = response.rect;
+    let label_height = 14.0;
+    let curve_bottom = rect.bottom() - label_height;
+
+    let foreground = ui.visuals().text_color();
+    let grid = ui.visuals().widgets.noninteractive.bg_stroke;
+    painter.rect_stroke(rect, 0.0, grid);
+
+    // The curve's height shows the plain value on a logarithmic scale, so for a skewed range it
+    // shows how much of the slider is used for each part of the frequency range
+    setter.sample_plain_curve(cutoff, &mut state.curve);
+    let min_log = cutoff.min_plain_value().ln();
+    let max_log = cutoff.max_plain_value().ln();
+    let log_proportion = |frequency: f32| (frequency.ln() - min_log) / (max_log - min_log);
+    let curve_points: Vec<Pos2> = state
+        .curve
+        .iter()
+        .enumerate()
+        .map(|(idx, frequency)| {
+            let normalized = idx as f32 / (CURVE_POINTS - 1) as f32;
+            Pos2::new(
+                rect.left() + (rect.width() * normalized),
+                curve_bottom - ((curve_bottom - rect.top()) * log_proportion(*frequency)),
+            )
+        })
+        .collect();
+    painter.add(Shape::line(curve_points, Stroke::new(1.5, foreground)));
+
+    setter.normalized_for_plain_slice(cutoff, &TICK_FREQUENCIES, &mut state.tick_positions);
+    for (frequency, normalized) in TICK_FREQUENCIES.iter().zip(state.tick_positions) {
+        let x = rect.left() + (rect.width() * normalized);
+        painter.line_segment([Pos2::new(x, rect.top()), Pos2::new(x, curve_bottom)], grid);
+
+        let label = if *frequency >= 1000.0 {
+            format!("{}k", frequency / 1000.0)
+        } else {
+            format!("{}", frequency)
+        };
+        // The labels at the edges are aligned so they don't get cut off
+        let align = if normalized <= 0.0 {
+            Align2::LEFT_BOTTOM
+        } else if normalized >= 1.0 {
+            Align2::RIGHT_BOTTOM
+        } else {
+            Align2::CENTER_BOTTOM
+        };
+        painter.text(
+            Pos2::new(x, rect.bottom()),
+            align,
+            label,
+            FontId::proportional(11.0),
+            foreground,
+        );
+    }
+
+    let current_x = rect.left() + (rect.width() * cutoff.modulated_normalized_value());
+    painter.line_segment(
+        [
+            Pos2::new(current_x, rect.top()),
+            Pos2::new(current_x, curve_bottom),
+        ],
+        Stroke::new(2.0, Color32::from_rgb(0xe0, 0x6c, 0x3c)),
+    );
+}
+
+impl ClapPlugin for FrequencyRuler {
+    const CLAP_ID: &'static str = "com.moist-plugins-gmbh.frequency-ruler";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("A low-pass filter with a frequency ruler in its GUI");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Mono,
+        ClapFeature::Filter,
+    ];
+}
+
+impl Vst3Plugin for FrequencyRuler {
+    const VST3_CLASS_ID: [u8; 16] = *b"FrequencyRulerrr";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Filter];
+}
+
+nih_export_clap!(FrequencyRuler);
+nih_export_vst3!(FrequencyRuler);
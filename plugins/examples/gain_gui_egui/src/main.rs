@@ -3,5 +3,7 @@ use nih_plug::prelude::*;
 use gain_gui_egui::Gain;
 
 fn main() {
-    nih_export_standalone::<Gain>();
+    if !nih_export_standalone::<Gain>() {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,183 @@
+use nih_plug::prelude::*;
+use nih_plug_egui::{create_egui_editor, egui, widgets, EguiState};
+use std::sync::Arc;
+
+/// The number of samples per channel kept around for the oscilloscope. This is a bit over a second
+/// at 48 kHz.
+const WAVEFORM_CAPACITY: usize = 1 << 16;
+/// The editor's default frame rate limit.
+const DEFAULT_MAX_REPAINT_RATE: f32 = 60.0;
+
+/// Passes audio through unchanged and shows it on a scrolling oscilloscope. The process function
+/// writes the audio to a lock-free ring buffer and asks for the editor to be redrawn, and the
+/// editor only redraws when that happens, at most [`DEFAULT_MAX_REPAINT_RATE`] times per second by
+/// default. The editor stops redrawing entirely when the audio stops.
+pub struct Oscilloscope {
+    params: Arc<OscilloscopeParams>,
+
+    /// The audio thread's end of the ring buffer. Only the first two channels are recorded.
+    waveform_buffer: util::WaveformBuffer,
+    /// The editor's end of the ring buffer.
+    waveform_reader: Arc<util::WaveformReader>,
+}
+
+#[derive(Params)]
+pub struct OscilloscopeParams {
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
+}
+
+/// The editor's own state, kept between frames.
+struct EditorState {
+    oscilloscope: widgets::OscilloscopeState,
+    /// The frame rate limit selected in the editor.
+    max_repaint_rate: f32,
+    /// The repaint policy last passed to the wrapper, so it's only updated when it changes.
+    repaint_policy: Option<RepaintPolicy>,
+}
+
+impl Default for Oscilloscope {
+    fn default() -> Self {
+        let (waveform_buffer, waveform_reader) = util::WaveformBuffer::new(2, WAVEFORM_CAPACITY);
+
+        Self {
+            params: Arc::new(OscilloscopeParams::default()),
+
+            waveform_buffer,
+            waveform_reader: Arc::new(waveform_reader),
+        }
+    }
+}
+
+impl Default for OscilloscopeParams {
+    fn default() -> Self {
+        Self {
+            editor_state: EguiState::from_size(500, 300),
+        }
+    }
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        let mut oscilloscope = widgets::OscilloscopeState::new(100.0);
+        // Always showing the most recent audio makes the waveform scroll
+        oscilloscope.trigger = widgets::OscilloscopeTrigger::Free;
+
+        Self {
+            oscilloscope,
+            max_repaint_rate: DEFAULT_MAX_REPAINT_RATE,
+            repaint_policy: None,
+        }
+    }
+}
+
+impl Plugin for Oscilloscope {
+    const NAME: &'static str = "Oscilloscope";
+    const VENDOR: &'static str = "Moist Plugins GmbH";
+    const URL: &'static str = "https://youtu.be/dQw4w9WgXcQ";
+    const EMAIL: &'static str = "info@example.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 2;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 2;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        let waveform_reader = self.waveform_reader.clone();
+        create_egui_editor(
+            self.params.editor_state.clone(),
+            EditorState::default(),
+            |_, _| {},
+            move |egui_ctx, setter, state| {
+                // The editor only needs to be redrawn when the process function has written new
+                // audio to the ring buffer. Input events still cause egui to redraw as usual.
+                let repaint_policy = RepaintPolicy::on_request(state.max_repaint_rate);
+                if state.repaint_policy != Some(repaint_policy) {
+                    setter.raw_context.set_repaint_policy(repaint_policy);
+                    state.repaint_policy = Some(repaint_policy);
+                }
+
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Frame rate limit");
+                        ui.add(
+                            egui::Slider::new(&mut state.max_repaint_rate, 10.0..=240.0)
+                                .suffix(" Hz"),
+                        );
+                    });
+
+                    ui.allocate_space(egui::Vec2::splat(2.0));
+                    ui.add(widgets::Oscilloscope::new(
+                        &waveform_reader,
+                        &mut state.oscilloscope,
+                    ));
+                });
+            },
+        )
+    }
+
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // This works with any symmetrical IO layout
+        config.num_input_channels == config.num_output_channels && config.num_input_channels > 0
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.waveform_buffer
+            .set_sample_rate(buffer_config.sample_rate);
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.waveform_buffer.reset();
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        if self.params.editor_state.is_open() {
+            // Neither of these allocate or block, so this can safely be done for every block
+            self.waveform_buffer.push_buffer(buffer);
+            context.request_editor_repaint();
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl ClapPlugin for Oscilloscope {
+    const CLAP_ID: &'static str = "com.moist-plugins-gmbh.oscilloscope";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("A pass-through plugin with a scrolling oscilloscope");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Mono,
+        ClapFeature::Analyzer,
+    ];
+}
+
+impl Vst3Plugin for Oscilloscope {
+    const VST3_CLASS_ID: [u8; 16] = *b"OscilloscopeNihP";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Analyzer];
+}
+
+nih_export_clap!(Oscilloscope);
+nih_export_vst3!(Oscilloscope);
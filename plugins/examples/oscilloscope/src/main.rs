@@ -0,0 +1,9 @@
+use nih_plug::prelude::*;
+
+use oscilloscope::Oscilloscope;
+
+fn main() {
+    if !nih_export_standalone::<Oscilloscope>() {
+        std::process::exit(1);
+    }
+}
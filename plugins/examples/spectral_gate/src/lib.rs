@@ -0,0 +1,146 @@
+use nih_plug::prelude::*;
+use realfft::num_complex::Complex32;
+use std::sync::Arc;
+
+/// The size of the windows we'll process at a time.
+const WINDOW_SIZE: usize = 2048;
+/// The number of overlapping windows. With a Hann window, 4x overlap avoids most of the artifacts
+/// caused by changing the gain of a bin from one window to the next.
+const OVERLAP: usize = 4;
+
+/// A sine wave with amplitude 1.0 that lies exactly on a bin has a magnitude of `WINDOW_SIZE / 4`
+/// after applying a Hann window. Multiplying the magnitudes by this value makes the threshold
+/// relative to a full scale sine wave.
+const MAGNITUDE_NORMALIZATION: f32 = 4.0 / WINDOW_SIZE as f32;
+
+struct SpectralGate {
+    params: Arc<SpectralGateParams>,
+
+    /// Does the buffering, windowing, FFTs, and overlap-add for us, and gives us the spectra of both
+    /// channels at the same time.
+    spectral_processor: util::SpectralProcessor,
+}
+
+#[derive(Params)]
+struct SpectralGateParams {
+    /// Bins whose magnitude is below this threshold are attenuated.
+    #[id = "threshold"]
+    pub threshold: FloatParam,
+    /// The gain applied to bins below the threshold.
+    #[id = "reduction"]
+    pub reduction: FloatParam,
+}
+
+impl Default for SpectralGate {
+    fn default() -> Self {
+        Self {
+            params: Arc::new(SpectralGateParams::default()),
+
+            spectral_processor: util::SpectralProcessor::new(
+                2,
+                util::SpectralProcessorConfig {
+                    window_size: WINDOW_SIZE,
+                    overlap: OVERLAP,
+                    window: util::SpectrumWindow::Hann,
+                    padding: 0,
+                },
+            ),
+        }
+    }
+}
+
+impl Default for SpectralGateParams {
+    fn default() -> Self {
+        Self {
+            threshold: FloatParam::new_gain_db("Threshold", -60.0, -100.0, 0.0),
+            reduction: FloatParam::new_gain_db("Reduction", -100.0, -100.0, 0.0),
+        }
+    }
+}
+
+impl Plugin for SpectralGate {
+    const NAME: &'static str = "Spectral Gate Example";
+    const VENDOR: &'static str = "Moist Plugins GmbH";
+    const URL: &'static str = "https://youtu.be/dQw4w9WgXcQ";
+    const EMAIL: &'static str = "info@example.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 2;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 2;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // We'll only do stereo for simplicity's sake
+        config.num_input_channels == config.num_output_channels && config.num_input_channels == 2
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        _buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        context.set_latency_samples(self.spectral_processor.latency_samples());
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.spectral_processor.reset();
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let params = &self.params;
+        self.spectral_processor
+            .process(buffer, |spectra: &mut [&mut [Complex32]]| {
+                let threshold = params.threshold.value();
+                let reduction = params.reduction.value();
+
+                // The gate's decision is made on the loudest channel so the stereo image stays
+                // intact. This is why the processor hands us all channels at once.
+                let (left, right) = spectra.split_at_mut(1);
+                for (left_bin, right_bin) in left[0].iter_mut().zip(right[0].iter_mut()) {
+                    let magnitude = left_bin.norm().max(right_bin.norm()) * MAGNITUDE_NORMALIZATION;
+                    if magnitude < threshold {
+                        *left_bin *= reduction;
+                        *right_bin *= reduction;
+                    }
+                }
+            });
+
+        ProcessStatus::Normal
+    }
+}
+
+impl ClapPlugin for SpectralGate {
+    const CLAP_ID: &'static str = "com.moist-plugins-gmbh.spectral-gate";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("An example plugin using the spectral processor");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Utility,
+    ];
+}
+
+impl Vst3Plugin for SpectralGate {
+    const VST3_CLASS_ID: [u8; 16] = *b"SpecGateMoistPlg";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
+}
+
+nih_export_clap!(SpectralGate);
+nih_export_vst3!(SpectralGate);
@@ -0,0 +1,148 @@
+use nih_plug::prelude::*;
+use nih_plug_egui::{create_egui_editor, egui, widgets, EguiState};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+/// The spectrum analyzer's settings.
+const SPECTRUM_ANALYZER_CONFIG: util::SpectrumAnalyzerConfig = util::SpectrumAnalyzerConfig {
+    fft_size: 2048,
+    overlap: 2,
+    window: util::SpectrumWindow::Hann,
+    averaging: util::SpectrumAveraging::Exponential { decay_ms: 100.0 },
+};
+
+/// Passes audio through unchanged and shows its spectrum. The spectrum is computed on the audio
+/// thread and sent to the editor through a lock-free triple buffer, so neither side ever has to
+/// wait for the other.
+pub struct SpectrumAnalyzer {
+    params: Arc<SpectrumAnalyzerParams>,
+
+    /// Computes the spectrum of the plugin's input on the audio thread.
+    spectrum_analyzer: util::SpectrumAnalyzer,
+    /// The editor's end of the triple buffer. This is moved into the editor when it gets created,
+    /// after which the editor is the only one reading from it. Reading the latest spectrum
+    /// requires mutable access, so sharing this between the plugin and the editor would require a
+    /// lock.
+    spectrum_output: RefCell<Option<util::SpectrumOutput>>,
+}
+
+#[derive(Params)]
+pub struct SpectrumAnalyzerParams {
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        let (spectrum_analyzer, spectrum_output) =
+            util::SpectrumAnalyzer::new(SPECTRUM_ANALYZER_CONFIG);
+
+        Self {
+            params: Arc::new(SpectrumAnalyzerParams::default()),
+
+            spectrum_analyzer,
+            spectrum_output: RefCell::new(Some(spectrum_output)),
+        }
+    }
+}
+
+impl Default for SpectrumAnalyzerParams {
+    fn default() -> Self {
+        Self {
+            editor_state: EguiState::from_size(400, 300),
+        }
+    }
+}
+
+impl Plugin for SpectrumAnalyzer {
+    const NAME: &'static str = "Spectrum Analyzer";
+    const VENDOR: &'static str = "Moist Plugins GmbH";
+    const URL: &'static str = "https://youtu.be/dQw4w9WgXcQ";
+    const EMAIL: &'static str = "info@example.com";
+
+    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 2;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 2;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn editor(&self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        // The wrappers only call this function once, so the output is always still here
+        let spectrum_output = self.spectrum_output.take()?;
+        create_egui_editor(
+            self.params.editor_state.clone(),
+            spectrum_output,
+            |_, _| {},
+            move |egui_ctx, _setter, spectrum_output| {
+                egui::CentralPanel::default().show(egui_ctx, |ui| {
+                    ui.add(widgets::SpectrumWidget::new(spectrum_output.read()));
+                });
+            },
+        )
+    }
+
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // This works with any symmetrical IO layout
+        config.num_input_channels == config.num_output_channels && config.num_input_channels > 0
+    }
+
+    fn initialize(
+        &mut self,
+        bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.spectrum_analyzer.initialize(
+            bus_config.num_output_channels as usize,
+            buffer_config.sample_rate,
+        );
+
+        true
+    }
+
+    fn reset(&mut self) {
+        self.spectrum_analyzer.reset();
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        // There's no need to compute the spectrum when nobody is looking at it
+        if self.params.editor_state.is_open() {
+            self.spectrum_analyzer.process_buffer(buffer);
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl ClapPlugin for SpectrumAnalyzer {
+    const CLAP_ID: &'static str = "com.moist-plugins-gmbh.spectrum-analyzer";
+    const CLAP_DESCRIPTION: Option<&'static str> =
+        Some("A pass-through plugin that shows the spectrum of its input");
+    const CLAP_MANUAL_URL: Option<&'static str> = Some(Self::URL);
+    const CLAP_SUPPORT_URL: Option<&'static str> = None;
+    const CLAP_FEATURES: &'static [ClapFeature] = &[
+        ClapFeature::AudioEffect,
+        ClapFeature::Stereo,
+        ClapFeature::Mono,
+        ClapFeature::Analyzer,
+    ];
+}
+
+impl Vst3Plugin for SpectrumAnalyzer {
+    const VST3_CLASS_ID: [u8; 16] = *b"SpectrumAnalyzer";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Analyzer];
+}
+
+nih_export_clap!(SpectrumAnalyzer);
+nih_export_vst3!(SpectrumAnalyzer);
@@ -0,0 +1,9 @@
+use nih_plug::prelude::*;
+
+use spectrum_analyzer::SpectrumAnalyzer;
+
+fn main() {
+    if !nih_export_standalone::<SpectrumAnalyzer>() {
+        std::process::exit(1);
+    }
+}
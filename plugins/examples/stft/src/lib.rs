@@ -178,7 +178,8 @@ impl ClapPlugin for Stft {
 
 impl Vst3Plugin for Stft {
     const VST3_CLASS_ID: [u8; 16] = *b"StftMoistestPlug";
-    const VST3_CATEGORIES: &'static str = "Fx|Tools";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
 }
 
 nih_export_clap!(Stft);
@@ -260,7 +260,11 @@ impl ClapPlugin for LoudnessWarWinner {
 
 impl Vst3Plugin for LoudnessWarWinner {
     const VST3_CLASS_ID: [u8; 16] = *b"LoudnessWar.RvdH";
-    const VST3_CATEGORIES: &'static str = "Fx|Dynamics|Distortion";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
+        Vst3SubCategory::Fx,
+        Vst3SubCategory::Dynamics,
+        Vst3SubCategory::Distortion,
+    ];
 }
 
 nih_export_clap!(LoudnessWarWinner);
@@ -318,7 +318,8 @@ impl ClapPlugin for SafetyLimiter {
 
 impl Vst3Plugin for SafetyLimiter {
     const VST3_CLASS_ID: [u8; 16] = *b"SafetyLimtrRvdH.";
-    const VST3_CATEGORIES: &'static str = "Fx|Tools";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
 }
 
 nih_export_clap!(SafetyLimiter);
@@ -1,10 +1,31 @@
 //! Different contexts the plugin can use to make callbacks to the host in different...contexts.
+//!
+//! Each context belongs to a specific thread. The [`ProcessContext`][process::ProcessContext] is
+//! only ever used from the audio thread during the process call, while the
+//! [`GuiContext`][gui::GuiContext] and the [`ParamSetter`][gui::ParamSetter] built on top of it
+//! should only be used from the GUI thread. The [`InitContext`][init::InitContext] is only valid
+//! while [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] is being called.
+//!
+//! The wrappers mark the thread they call the plugin's processing functions from as the audio
+//! thread, and the thread they run GUI tasks on as the GUI thread. See
+//! [`util::is_audio_thread()`][crate::util::is_audio_thread()] and
+//! [`util::is_gui_thread()`][crate::util::is_gui_thread()]. The plugin's editor may run on its
+//! own thread depending on the platform, so the editor-facing APIs only check that they're not
+//! called from the audio thread. Debug builds check these contracts with the
+//! [`nih_debug_assert_audio_thread!()`][crate::debug::nih_debug_assert_audio_thread],
+//! [`nih_debug_assert_not_audio_thread!()`][crate::debug::nih_debug_assert_not_audio_thread], and
+//! [`nih_debug_assert_gui_thread!()`][crate::debug::nih_debug_assert_gui_thread] macros. In
+//! release builds these checks compile to nothing. Enabling the `audio_thread_alloc_guard` feature
+//! additionally reports any allocations made on the audio thread.
 
 use std::fmt::Display;
 
 pub mod gui;
+pub mod headless;
 pub mod init;
+pub mod messages;
 pub mod process;
+pub mod repaint;
 
 /// The currently active plugin API. This may be useful to display in an about screen in the
 /// plugin's GUI for debugging purposes.
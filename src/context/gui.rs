@@ -2,7 +2,9 @@
 
 use std::sync::Arc;
 
+use super::repaint::RepaintPolicy;
 use super::PluginApi;
+use crate::params::changes::{ParamChange, ParamChangeSubscription, ParamChangeTracker};
 use crate::params::internals::ParamPtr;
 use crate::params::Param;
 use crate::plugin::Plugin;
@@ -25,10 +27,40 @@ pub trait GuiContext: Send + Sync + 'static {
     /// [`Editor::size()`][crate::prelude::Editor::size()]. This will return false if the host
     /// somehow didn't like this and rejected the resize, in which case the window should revert to
     /// its old size. You should only actually resize your embedded window once this returns `true`.
+    /// This also returns false if the editor is not currently open, for instance when a parameter
+    /// change from the host causes the editor to resize itself after its window has been closed.
     ///
     /// TODO: Host->Plugin resizing has not been implemented yet
     fn request_resize(&self) -> bool;
 
+    /// Ask for the editor to be redrawn on one of the next frames, for instance after the editor
+    /// received new data to display. This is only needed when the editor's
+    /// [`RepaintPolicy`] is not continuous. Use
+    /// [`ProcessContext::request_editor_repaint()`][crate::prelude::ProcessContext::request_editor_repaint()]
+    /// to request a repaint from the process function.
+    ///
+    /// The default implementation does nothing.
+    fn request_repaint(&self) {}
+
+    /// Change how often the editor is redrawn. Editors that only change when new data arrives can
+    /// use this to stop redrawing on every frame, and meters can cap their frame rate so they don't
+    /// redraw thousands of times per second on fast machines. The policy stays in effect when the
+    /// editor is closed and reopened. Editors are redrawn continuously without a rate limit by
+    /// default.
+    ///
+    /// The default implementation does nothing.
+    fn set_repaint_policy(&self, policy: RepaintPolicy) {}
+
+    /// Whether the editor should be redrawn during the current frame, based on the editor's
+    /// [`RepaintPolicy`] and any outstanding repaint requests. GUI adapters like `nih_plug_egui`
+    /// call this at the start of every frame, so editors don't need to call this themselves. A
+    /// repaint request is consumed when this returns `true`.
+    ///
+    /// The default implementation always returns `true`.
+    fn poll_repaint(&self) -> bool {
+        true
+    }
+
     /// Inform the host a parameter will be automated. Create a [`ParamSetter`] and use
     /// [`ParamSetter::begin_set_parameter()`] instead for a safe, user friendly API.
     ///
@@ -57,15 +89,150 @@ pub trait GuiContext: Send + Sync + 'static {
     /// mostly marked as unsafe for API reasons.
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr);
 
+    /// Start a batch of parameter changes. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::with_batch()`] instead for a safe, user friendly API.
+    ///
+    /// All parameter changes and gestures made until the matching
+    /// [`raw_end_batch()`][Self::raw_end_batch()] call are sent to the host as a single
+    /// interaction, and the new values are applied to the plugin at the same block boundary.
+    /// Batches can be nested, in which case everything is sent when the outermost batch ends.
+    ///
+    /// The default implementation does nothing, in which case the batched changes are sent to the
+    /// host one by one.
+    fn raw_begin_batch(&self) {}
+
+    /// End a batch of parameter changes started with
+    /// [`raw_begin_batch()`][Self::raw_begin_batch()]. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::with_batch()`] instead for a safe, user friendly API.
+    fn raw_end_batch(&self) {}
+
     /// Serialize the plugin's current state to a serde-serializable object. Useful for implementing
-    /// preset handling within a plugin's GUI.
+    /// preset handling within a plugin's GUI. This allocates, so it may not be called from the
+    /// audio thread.
     fn get_state(&self) -> PluginState;
 
     /// Restore the state from a previously serialized state object. This will block the GUI thread
     /// until the state has been restored and a parameter value rescan has been requested from the
     /// host. If the plugin is currently processing audio, then the parameter values will be
-    /// restored at the end of the current processing cycle.
+    /// restored at the end of the current processing cycle. Since this waits for the audio thread
+    /// to pick up the new state, calling it from the audio thread would deadlock. Debug builds
+    /// check both of these contracts.
     fn set_state(&self, state: PluginState);
+
+    /// Start or stop recording the plugin's output to a WAV file. This is only supported by the
+    /// standalone wrapper when it was started with the `--record-dir` option, so a plugin can use
+    /// this to offer a record button in its editor. Returns whether the plugin's output is being
+    /// recorded after this call.
+    fn set_recording(&self, _recording: bool) -> bool {
+        false
+    }
+
+    /// Whether the plugin's output is currently being recorded. See
+    /// [`set_recording()`][Self::set_recording()].
+    fn is_recording(&self) -> bool {
+        false
+    }
+
+    /// Get the last change made to a parameter, including where that change came from. Returns
+    /// `None` if the parameter has not been changed since the plugin was created. Create a
+    /// [`ParamSetter`] and use [`ParamSetter::last_change()`] instead for a more convenient API.
+    ///
+    /// The default implementation does not track any changes and always returns `None`.
+    fn last_param_change(&self, _param: ParamPtr) -> Option<ParamChange> {
+        None
+    }
+
+    /// Subscribe to parameter changes. The editor can periodically poll the returned object to find
+    /// out which parameters have changed and whether those changes came from automation, from
+    /// loading state, or from the GUI. This can be used to implement a MIDI learn function or a
+    /// 'last touched parameter' display.
+    ///
+    /// The default implementation returns a subscription that never reports any changes.
+    fn subscribe_param_changes(&self) -> ParamChangeSubscription {
+        ParamChangeSubscription::new(Arc::new(ParamChangeTracker::new([])))
+    }
+
+    /// Tell the host that the plugin's state has changed in a way that isn't reflected in its
+    /// parameters, for instance after changing a `#[persist]` field from the editor. Some hosts
+    /// only save a plugin's state when it has been marked as dirty. Parameter changes don't need
+    /// this. This is cheap to call repeatedly, as the host is only notified of the first change
+    /// after it last saved or loaded the plugin's state.
+    ///
+    /// The default implementation does nothing.
+    fn mark_state_dirty(&self) {}
+
+    /// Ask the host for a parameter's context menu. Returns `None` if the host does not support
+    /// this, or if the editor is not currently open. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::context_menu()`] instead for a more convenient API.
+    ///
+    /// The default implementation returns `None`.
+    fn param_context_menu(&self, _param: ParamPtr) -> Option<HostContextMenu> {
+        None
+    }
+
+    /// Get information about the track or mixer channel the plugin is inserted on, like its name
+    /// and color. Returns `None` if the host has not provided this information. Compare
+    /// [`ChannelInfo::change_count`] with the last value the editor has seen to find out when the
+    /// user renamed or recolored the track.
+    ///
+    /// This is currently only supported by the VST3 wrapper for hosts that implement
+    /// `IInfoListener`. The standalone wrapper returns its own information. The default
+    /// implementation returns `None`.
+    fn channel_info(&self) -> Option<ChannelInfo> {
+        None
+    }
+
+    /// Start or stop writing a parameter's modulated value to the host as automation. Returns
+    /// whether the parameter is armed after this call. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::set_modulation_write()`] instead for a more convenient API.
+    ///
+    /// The default implementation does not support this and always returns `false`.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to be one of the plugin's own parameters.
+    unsafe fn raw_set_modulation_write(&self, _param: ParamPtr, _armed: bool) -> bool {
+        false
+    }
+
+    /// Schedule a change to a parameter's normalized value that the audio thread applies at the
+    /// next beat or bar boundary. Returns `false` if the change could not be scheduled, in which
+    /// case the caller should set the parameter immediately instead. Create a [`ParamSetter`] and
+    /// use [`ParamSetter::set_parameter_at_musical()`] instead for a safe, user friendly API.
+    ///
+    /// The default implementation does not support this and always returns `false`.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to be one of the plugin's own parameters.
+    unsafe fn raw_set_parameter_at_musical(
+        &self,
+        _param: ParamPtr,
+        _normalized: f32,
+        _quantize: MusicalQuantize,
+    ) -> bool {
+        false
+    }
+
+    /// Cancel a change scheduled with
+    /// [`raw_set_parameter_at_musical()`][Self::raw_set_parameter_at_musical()] if it has not been
+    /// applied yet. Returns whether a pending change was cancelled. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::cancel_parameter_at_musical()`] instead for a safe, user friendly API.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to be one of the plugin's own parameters.
+    unsafe fn raw_cancel_parameter_at_musical(&self, _param: ParamPtr) -> bool {
+        false
+    }
+
+    /// The normalized value of the change scheduled for a parameter with
+    /// [`raw_set_parameter_at_musical()`][Self::raw_set_parameter_at_musical()], if that change has
+    /// not been applied yet. Create a [`ParamSetter`] and use
+    /// [`ParamSetter::pending_parameter_at_musical()`] instead for a more convenient API.
+    fn pending_parameter_at_musical(&self, _param: ParamPtr) -> Option<f32> {
+        None
+    }
 }
 
 /// An way to run background tasks from the plugin's GUI, equivalent to the
@@ -105,6 +272,120 @@ pub struct ParamSetter<'a> {
     pub raw_context: &'a dyn GuiContext,
 }
 
+/// An automation gesture for a single parameter, created using
+/// [`ParamSetter::begin_drag()`]. The gesture is ended when this object is dropped, so the host
+/// always gets an end of gesture notification even if the widget returns early or panics halfway
+/// through an interaction.
+#[must_use = "The gesture is ended as soon as this guard is dropped"]
+pub struct ParamDragGuard<'a, P: Param> {
+    setter: &'a ParamSetter<'a>,
+    param: &'a P,
+}
+
+/// Ends a batch of parameter changes started by [`ParamSetter::with_batch()`] when dropped, so the
+/// batch is also ended when the closure panics.
+struct ParamBatchGuard<'a> {
+    setter: &'a ParamSetter<'a>,
+}
+
+/// A parameter's context menu as provided by the host, obtained through
+/// [`ParamSetter::context_menu()`]. Hosts use these menus to offer options like MIDI learn or
+/// showing the parameter's automation lane. The editor can either draw the menu's
+/// [`items()`][Self::items()] itself and [`execute()`][Self::execute()] the item the user selected,
+/// or it can let the host show the menu with [`popup()`][Self::popup()]. The plugin can add its own
+/// items with [`add_item()`][Self::add_item()]. The host's menu is released when this object is
+/// dropped.
+///
+/// This is currently only supported by the VST3 wrapper for hosts that implement
+/// `IComponentHandler3`. Like everything else related to the [`GuiContext`], this may only be used
+/// from the GUI thread.
+pub struct HostContextMenu {
+    items: Vec<ContextMenuItem>,
+    /// The callbacks for the items added through [`add_item()`][Self::add_item()]. These have the
+    /// same indices as `items`, and they are `None` for the host's own items.
+    callbacks: Vec<Option<Arc<dyn Fn() + Send + Sync>>>,
+    backend: Box<dyn ContextMenuBackend>,
+}
+
+/// Where on the host's musical grid a parameter change scheduled with
+/// [`ParamSetter::set_parameter_at_musical()`] is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MusicalQuantize {
+    /// Apply the change immediately, like [`ParamSetter::set_parameter_with_gesture()`].
+    Off,
+    /// Apply the change at the start of the next beat, as defined by the time signature's
+    /// denominator.
+    Beat,
+    /// Apply the change at the start of the next bar.
+    Bar,
+}
+
+/// Information about the track or mixer channel the plugin is inserted on. See
+/// [`GuiContext::channel_info()`]. Hosts may leave out any of these fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelInfo {
+    /// The track's name.
+    pub name: Option<String>,
+    /// The track's color.
+    pub color: Option<ChannelColor>,
+    /// The track's position in the host's mixer.
+    pub index: Option<i64>,
+    /// Incremented every time the host sends new channel information, for instance when the user
+    /// renames the track.
+    pub change_count: u32,
+}
+
+/// A track's color, as provided by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// A single item in a [`HostContextMenu`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextMenuItem {
+    /// The item's label. This is empty for separators.
+    pub name: String,
+    pub kind: ContextMenuItemKind,
+    /// Whether the item can be selected.
+    pub enabled: bool,
+    /// Whether the item should be drawn with a check mark.
+    pub checked: bool,
+}
+
+/// The different kinds of items in a [`HostContextMenu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextMenuItemKind {
+    /// A regular item that does something when it's selected.
+    Action,
+    /// A horizontal divider.
+    Separator,
+    /// The start of a submenu. The item's name is the submenu's label, and all items up to the
+    /// matching [`SubmenuEnd`][Self::SubmenuEnd] are part of the submenu.
+    SubmenuStart,
+    /// The end of the current submenu.
+    SubmenuEnd,
+}
+
+/// The wrapper specific part of a [`HostContextMenu`]. Implementations own the host's menu and
+/// release it when they're dropped.
+pub(crate) trait ContextMenuBackend: Send {
+    /// Execute the host's item at `index`. This is never called for items added through
+    /// [`add_item()`][Self::add_item()].
+    fn execute(&self, index: usize);
+
+    /// Add an item to the host's menu so it is included when the host shows the menu. `callback`
+    /// should be called when the user selects the item from that menu.
+    fn add_item(&mut self, item: &ContextMenuItem, callback: Arc<dyn Fn() + Send + Sync>);
+
+    /// Show the host's menu at the specified position, in logical pixels relative to the editor's
+    /// top left corner. Returns `false` if the menu could not be shown.
+    fn popup(&self, x: f32, y: f32) -> bool;
+}
+
 impl<P: Plugin> AsyncExecutor<P> {
     /// Execute a task on a background thread using `[Plugin::task_executor]`. This allows you to
     /// defer expensive tasks for later without blocking either the process function or the GUI
@@ -141,6 +422,7 @@ impl<'a> ParamSetter<'a> {
     /// Inform the host that you will start automating a parameter. This needs to be called before
     /// calling [`set_parameter()`][Self::set_parameter()] for the specified parameter.
     pub fn begin_set_parameter<P: Param>(&self, param: &P) {
+        nih_debug_assert_not_audio_thread!();
         unsafe { self.raw_context.raw_begin_set_parameter(param.as_ptr()) };
     }
 
@@ -154,12 +436,7 @@ impl<'a> ParamSetter<'a> {
     /// This function assumes you're already calling this from a GUI thread. Calling any of these
     /// functions from any other thread may result in unexpected behavior.
     pub fn set_parameter<P: Param>(&self, param: &P, value: P::Plain) {
-        let ptr = param.as_ptr();
-        let normalized = param.preview_normalized(value);
-        unsafe {
-            self.raw_context
-                .raw_set_parameter_normalized(ptr, normalized)
-        };
+        self.set_parameter_normalized(param, param.preview_normalized(value));
     }
 
     /// Set a parameter to an already normalized value. Works exactly the same as
@@ -168,9 +445,21 @@ impl<'a> ParamSetter<'a> {
     ///
     /// This does not perform any snapping. Consider converting the normalized value to a plain
     /// value and setting that with [`set_parameter()`][Self::set_parameter()] instead so the
-    /// normalized value known to the host matches `param.normalized_value()`.
+    /// normalized value known to the host matches `param.normalized_value()`. Values outside of
+    /// `[0, 1]` are clamped, and NaN and infinite values are ignored.
     pub fn set_parameter_normalized<P: Param>(&self, param: &P, normalized: f32) {
+        nih_debug_assert_not_audio_thread!();
+        if !normalized.is_finite() {
+            nih_debug_assert_failure!(
+                "Ignoring invalid value {} for parameter \"{}\"",
+                normalized,
+                param.name()
+            );
+            return;
+        }
+
         let ptr = param.as_ptr();
+        let normalized = normalized.clamp(0.0, 1.0);
         unsafe {
             self.raw_context
                 .raw_set_parameter_normalized(ptr, normalized)
@@ -181,6 +470,578 @@ impl<'a> ParamSetter<'a> {
     /// or more [`set_parameter()`][Self::set_parameter()] calls for a parameter so the host knows
     /// the automation gesture has finished.
     pub fn end_set_parameter<P: Param>(&self, param: &P) {
+        nih_debug_assert_not_audio_thread!();
         unsafe { self.raw_context.raw_end_set_parameter(param.as_ptr()) };
     }
+
+    /// Set a parameter to the specified parameter value as a single automation gesture. This is the
+    /// same as calling [`begin_set_parameter()`][Self::begin_set_parameter()],
+    /// [`set_parameter()`][Self::set_parameter()], and
+    /// [`end_set_parameter()`][Self::end_set_parameter()] in a row, and it's useful for one-off
+    /// changes like resetting a parameter or entering a value with the keyboard.
+    pub fn set_parameter_with_gesture<P: Param>(&self, param: &P, value: P::Plain) {
+        self.begin_drag(param).drag(value);
+    }
+
+    /// The same as [`set_parameter_with_gesture()`][Self::set_parameter_with_gesture()], but for
+    /// normalized values. See [`set_parameter_normalized()`][Self::set_parameter_normalized()].
+    pub fn set_parameter_normalized_with_gesture<P: Param>(&self, param: &P, normalized: f32) {
+        self.begin_drag(param).drag_normalized(normalized);
+    }
+
+    /// Reset a parameter to its default value as a single automation gesture. The default is the
+    /// value the parameter was constructed with, regardless of any changes made since then.
+    pub fn reset_parameter<P: Param>(&self, param: &P) {
+        self.set_parameter_normalized_with_gesture(param, param.default_normalized_value());
+    }
+
+    /// Begin an automation gesture for a continuous interaction like dragging a slider. The
+    /// returned guard can be used to change the parameter's value, and the gesture will be ended
+    /// when the guard is dropped.
+    pub fn begin_drag<'p, P: Param>(&'p self, param: &'p P) -> ParamDragGuard<'p, P> {
+        self.begin_set_parameter(param);
+        ParamDragGuard {
+            setter: self,
+            param,
+        }
+    }
+
+    /// Start a batch of parameter changes. Everything set between this call and the matching
+    /// [`end_batch()`][Self::end_batch()] call is sent to the host as a single interaction, which
+    /// results in a single undo point in hosts that support it, and all of the new values are
+    /// applied to the plugin at the same block boundary. This is useful when setting many
+    /// parameters at once, for instance when loading a preset or randomizing the plugin's
+    /// parameters from the editor. The gestures for the parameters still need to be started and
+    /// ended as usual.
+    ///
+    /// Batches can be nested. Nested batches are flattened into the outermost batch. Consider
+    /// using [`with_batch()`][Self::with_batch()] instead so the batch always gets ended.
+    pub fn begin_batch(&self) {
+        nih_debug_assert_not_audio_thread!();
+        self.raw_context.raw_begin_batch();
+    }
+
+    /// End a batch of parameter changes started with [`begin_batch()`][Self::begin_batch()].
+    pub fn end_batch(&self) {
+        nih_debug_assert_not_audio_thread!();
+        self.raw_context.raw_end_batch();
+    }
+
+    /// Run `f` inside of a batch of parameter changes. See [`begin_batch()`][Self::begin_batch()].
+    /// The batch is ended when `f` returns, or when it panics.
+    pub fn with_batch<R>(&self, f: impl FnOnce(&Self) -> R) -> R {
+        self.begin_batch();
+        let _guard = ParamBatchGuard { setter: self };
+
+        f(self)
+    }
+
+    /// Get the last change made to a parameter, including where that change came from. Returns
+    /// `None` if the parameter has not been changed since the plugin was created.
+    pub fn last_change<P: Param>(&self, param: &P) -> Option<ParamChange> {
+        self.raw_context.last_param_change(param.as_ptr())
+    }
+
+    /// Ask the host for the parameter's context menu. Returns `None` if the host does not support
+    /// this. See [`HostContextMenu`] for more information. Editors should fall back to their own
+    /// menu in that case.
+    pub fn context_menu<P: Param>(&self, param: &P) -> Option<HostContextMenu> {
+        nih_debug_assert_not_audio_thread!();
+        self.raw_context.param_context_menu(param.as_ptr())
+    }
+
+    /// Write the parameter's modulated value to the host as automation while `armed` is set, for
+    /// instance to print the motion of an internal
+    /// [`ModulationMatrix`][crate::util::ModulationMatrix] to an automation lane. Arming the
+    /// parameter starts a gesture, and the value is sent to the host at most once per process
+    /// call whenever it changes. Disarming the parameter ends the gesture again. Hosts only record
+    /// these values while their automation is in touch, latch, or write mode. While a parameter
+    /// is not armed, its modulation is invisible to the host.
+    ///
+    /// Returns whether the parameter is armed after this call. This is currently only supported by
+    /// the VST3 wrapper, and read-only parameters can't be armed.
+    pub fn set_modulation_write<P: Param>(&self, param: &P, armed: bool) -> bool {
+        nih_debug_assert_not_audio_thread!();
+        unsafe {
+            self.raw_context
+                .raw_set_modulation_write(param.as_ptr(), armed)
+        }
+    }
+
+    /// Set a parameter to a new value at the start of the next beat or bar of the host's
+    /// transport instead of right away, for instance so a freeze or reverse switch lands exactly
+    /// on the beat during a live performance. The audio thread applies the change at the first
+    /// sample of the next beat or bar, splitting the block there if the plugin uses
+    /// [`SAMPLE_ACCURATE_AUTOMATION`][crate::prelude::Plugin::SAMPLE_ACCURATE_AUTOMATION]. The
+    /// parameter's smoother then handles the change like any other change. If the transport is
+    /// not playing, or if the host does not provide the song position and tempo, then the change
+    /// is applied at the start of the next block instead. Plugins using this should request
+    /// [`TransportRequirements::TEMPO`][crate::prelude::TransportRequirements::TEMPO] and
+    /// [`PROJECT_TIME`][crate::prelude::TransportRequirements::PROJECT_TIME], and also
+    /// `TIME_SIGNATURE` and `BAR_POSITION` for quantizing to bars.
+    ///
+    /// Scheduling another change for the same parameter replaces the pending change. Use
+    /// [`pending_parameter_at_musical()`][Self::pending_parameter_at_musical()] to show that the
+    /// parameter is armed, and
+    /// [`cancel_parameter_at_musical()`][Self::cancel_parameter_at_musical()] to cancel the change
+    /// before it fires.
+    ///
+    /// This is currently only supported by the VST3 wrapper while the plugin is processing audio.
+    /// In every other case, and with [`MusicalQuantize::Off`], the parameter is set immediately
+    /// using [`set_parameter_with_gesture()`][Self::set_parameter_with_gesture()]. Returns whether
+    /// the change was scheduled.
+    pub fn set_parameter_at_musical<P: Param>(
+        &self,
+        param: &P,
+        value: P::Plain,
+        quantize: MusicalQuantize,
+    ) -> bool {
+        nih_debug_assert_not_audio_thread!();
+        let normalized = param.preview_normalized(value);
+        let scheduled = quantize != MusicalQuantize::Off
+            && unsafe {
+                self.raw_context
+                    .raw_set_parameter_at_musical(param.as_ptr(), normalized, quantize)
+            };
+        if !scheduled {
+            self.cancel_parameter_at_musical(param);
+            self.set_parameter_normalized_with_gesture(param, normalized);
+        }
+
+        scheduled
+    }
+
+    /// Cancel a change scheduled with
+    /// [`set_parameter_at_musical()`][Self::set_parameter_at_musical()] before it fires. Returns
+    /// whether there was a pending change.
+    pub fn cancel_parameter_at_musical<P: Param>(&self, param: &P) -> bool {
+        nih_debug_assert_not_audio_thread!();
+        unsafe {
+            self.raw_context
+                .raw_cancel_parameter_at_musical(param.as_ptr())
+        }
+    }
+
+    /// The value of the change scheduled with
+    /// [`set_parameter_at_musical()`][Self::set_parameter_at_musical()], if it has not fired yet.
+    /// Editors can poll this to show an armed indicator on the parameter's widget.
+    pub fn pending_parameter_at_musical<P: Param>(&self, param: &P) -> Option<P::Plain> {
+        self.raw_context
+            .pending_parameter_at_musical(param.as_ptr())
+            .map(|normalized| param.preview_plain(normalized))
+    }
+
+    /// Get the parameter's default value as a normalized value. Useful for resetting parameters.
+    pub fn default_normalized_value<P: Param>(&self, param: &P) -> f32 {
+        param.default_normalized_value()
+    }
+
+    /// Get the normalized value for a plain value using the parameter's range. Useful for drawing
+    /// values that differ from the parameter's current value.
+    pub fn preview_normalized<P: Param>(&self, param: &P, plain: P::Plain) -> f32 {
+        param.preview_normalized(plain)
+    }
+
+    /// Get the plain value for a normalized value using the parameter's range, with any stepping
+    /// applied. Useful for snapping a widget's position to the values the parameter can take.
+    pub fn preview_plain<P: Param>(&self, param: &P, normalized: f32) -> P::Plain {
+        param.preview_plain(normalized)
+    }
+
+    /// Fill `out` with the parameter's plain values at evenly spaced normalized values. See
+    /// [`Param::sample_plain_curve()`]. Useful for drawing a parameter's response curve.
+    pub fn sample_plain_curve<P: Param>(&self, param: &P, out: &mut [P::Plain]) {
+        param.sample_plain_curve(out)
+    }
+
+    /// Fill `out` with the normalized values for the plain values in `plain`. See
+    /// [`Param::normalized_for_plain_slice()`].
+    pub fn normalized_for_plain_slice<P: Param>(
+        &self,
+        param: &P,
+        plain: &[P::Plain],
+        out: &mut [f32],
+    ) where
+        P::Plain: Clone,
+    {
+        param.normalized_for_plain_slice(plain, out)
+    }
+}
+
+impl<P: Param> ParamDragGuard<'_, P> {
+    /// Set the parameter to the specified plain value. See [`ParamSetter::set_parameter()`].
+    pub fn drag(&self, value: P::Plain) {
+        self.setter.set_parameter(self.param, value);
+    }
+
+    /// Set the parameter to the specified normalized value. See
+    /// [`ParamSetter::set_parameter_normalized()`].
+    pub fn drag_normalized(&self, normalized: f32) {
+        self.setter.set_parameter_normalized(self.param, normalized);
+    }
+
+    /// End the gesture. This is the same as dropping the guard.
+    pub fn end_drag(self) {}
+}
+
+impl<P: Param> Drop for ParamDragGuard<'_, P> {
+    fn drop(&mut self) {
+        self.setter.end_set_parameter(self.param);
+    }
+}
+
+impl Drop for ParamBatchGuard<'_> {
+    fn drop(&mut self) {
+        self.setter.end_batch();
+    }
+}
+
+impl ChannelColor {
+    /// Unpack a color stored as `0xAARRGGBB`.
+    pub fn from_argb(argb: u32) -> Self {
+        Self {
+            r: (argb >> 16) as u8,
+            g: (argb >> 8) as u8,
+            b: argb as u8,
+            a: (argb >> 24) as u8,
+        }
+    }
+}
+
+impl HostContextMenu {
+    /// Wrap a host's menu. `items` are the menu's current items.
+    pub(crate) fn new(items: Vec<ContextMenuItem>, backend: Box<dyn ContextMenuBackend>) -> Self {
+        Self {
+            callbacks: vec![None; items.len()],
+            items,
+            backend,
+        }
+    }
+
+    /// The menu's items, including the ones added through [`add_item()`][Self::add_item()].
+    pub fn items(&self) -> &[ContextMenuItem] {
+        &self.items
+    }
+
+    /// Run the action for the item at `index` in [`items()`][Self::items()]. Selecting separators,
+    /// submenus, or disabled items does nothing.
+    pub fn execute(&self, index: usize) {
+        nih_debug_assert_not_audio_thread!();
+
+        match self.items.get(index) {
+            Some(item) if item.kind == ContextMenuItemKind::Action && item.enabled => {
+                match &self.callbacks[index] {
+                    Some(callback) => callback(),
+                    None => self.backend.execute(index),
+                }
+            }
+            Some(_) => (),
+            None => nih_debug_assert_failure!("Context menu item index {} out of range", index),
+        }
+    }
+
+    /// Add an item to the end of the menu. `callback` is run when the item is selected, either
+    /// through [`execute()`][Self::execute()] or from the host's own menu.
+    pub fn add_item(
+        &mut self,
+        name: impl Into<String>,
+        callback: impl Fn() + Send + Sync + 'static,
+    ) {
+        nih_debug_assert_not_audio_thread!();
+
+        let item = ContextMenuItem {
+            name: name.into(),
+            kind: ContextMenuItemKind::Action,
+            enabled: true,
+            checked: false,
+        };
+        let callback: Arc<dyn Fn() + Send + Sync> = Arc::new(callback);
+        self.backend.add_item(&item, callback.clone());
+
+        self.items.push(item);
+        self.callbacks.push(Some(callback));
+    }
+
+    /// Let the host show its own menu at the specified position, in logical pixels relative to the
+    /// editor's top left corner. This blocks until the menu is closed, and the host runs the
+    /// selected item's action itself. Returns `false` if the host could not show the menu.
+    pub fn popup(&self, x: f32, y: f32) -> bool {
+        nih_debug_assert_not_audio_thread!();
+        self.backend.popup(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::params::range::FloatRange;
+    use crate::params::FloatParam;
+    use crate::wrapper::thread_role;
+
+    /// The calls made to a [`RecordingGuiContext`].
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        Begin,
+        Set(f32),
+        End,
+        BeginBatch,
+        EndBatch,
+    }
+
+    /// A [`GuiContext`] that records all parameter changes made through it.
+    #[derive(Default)]
+    struct RecordingGuiContext {
+        calls: Mutex<Vec<Call>>,
+    }
+
+    impl GuiContext for RecordingGuiContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Standalone
+        }
+
+        fn request_resize(&self) -> bool {
+            false
+        }
+
+        unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {
+            self.calls.lock().push(Call::Begin);
+        }
+
+        unsafe fn raw_set_parameter_normalized(&self, _param: ParamPtr, normalized: f32) {
+            self.calls.lock().push(Call::Set(normalized));
+        }
+
+        unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {
+            self.calls.lock().push(Call::End);
+        }
+
+        fn raw_begin_batch(&self) {
+            self.calls.lock().push(Call::BeginBatch);
+        }
+
+        fn raw_end_batch(&self) {
+            self.calls.lock().push(Call::EndBatch);
+        }
+
+        fn get_state(&self) -> PluginState {
+            unimplemented!()
+        }
+
+        fn set_state(&self, _state: PluginState) {
+            unimplemented!()
+        }
+    }
+
+    fn param() -> FloatParam {
+        FloatParam::new(
+            "Param",
+            0.0,
+            FloatRange::Linear {
+                min: -10.0,
+                max: 10.0,
+            },
+        )
+    }
+
+    #[test]
+    fn set_parameter_with_gesture() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = param();
+
+        setter.set_parameter_with_gesture(&param, 5.0);
+        setter.set_parameter_normalized_with_gesture(&param, 0.25);
+        assert_eq!(
+            *context.calls.lock(),
+            [
+                Call::Begin,
+                Call::Set(0.75),
+                Call::End,
+                Call::Begin,
+                Call::Set(0.25),
+                Call::End
+            ]
+        );
+    }
+
+    #[test]
+    fn reset_parameter() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = FloatParam::new(
+            "Param",
+            5.0,
+            FloatRange::Linear {
+                min: -10.0,
+                max: 10.0,
+            },
+        );
+
+        setter.reset_parameter(&param);
+        assert_eq!(
+            *context.calls.lock(),
+            [Call::Begin, Call::Set(0.75), Call::End]
+        );
+    }
+
+    #[test]
+    fn parameter_changes_from_audio_thread() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = param();
+        thread_role::take_assertion_failures();
+
+        setter.set_parameter_with_gesture(&param, 5.0);
+        assert_eq!(thread_role::take_assertion_failures(), 0);
+
+        // This is still forwarded to the context, but it should trip the debug assertions
+        let _audio_thread_guard = thread_role::enter_audio_thread();
+        setter.set_parameter_with_gesture(&param, 5.0);
+        assert_eq!(thread_role::take_assertion_failures(), 3);
+    }
+
+    #[test]
+    fn drag_guard() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = param();
+
+        let drag = setter.begin_drag(&param);
+        drag.drag(-5.0);
+        drag.drag_normalized(0.5);
+        assert_eq!(
+            *context.calls.lock(),
+            [Call::Begin, Call::Set(0.25), Call::Set(0.5)]
+        );
+
+        drag.end_drag();
+        assert_eq!(context.calls.lock().last(), Some(&Call::End));
+    }
+
+    #[test]
+    fn drag_guard_panic() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = param();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let drag = setter.begin_drag(&param);
+            drag.drag(10.0);
+            panic!("Oh no");
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            *context.calls.lock(),
+            [Call::Begin, Call::Set(1.0), Call::End]
+        );
+    }
+
+    #[test]
+    fn with_batch() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+        let param = param();
+
+        let result = setter.with_batch(|setter| {
+            setter.set_parameter_with_gesture(&param, 5.0);
+            setter.with_batch(|setter| setter.set_parameter_with_gesture(&param, -5.0));
+
+            42
+        });
+        assert_eq!(result, 42);
+        assert_eq!(
+            *context.calls.lock(),
+            [
+                Call::BeginBatch,
+                Call::Begin,
+                Call::Set(0.75),
+                Call::End,
+                Call::BeginBatch,
+                Call::Begin,
+                Call::Set(0.25),
+                Call::End,
+                Call::EndBatch,
+                Call::EndBatch,
+            ]
+        );
+    }
+
+    #[test]
+    fn with_batch_panic() {
+        let context = RecordingGuiContext::default();
+        let setter = ParamSetter::new(&context);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            setter.with_batch(|_| panic!("Oh no"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(*context.calls.lock(), [Call::BeginBatch, Call::EndBatch]);
+    }
+
+    /// A [`ContextMenuBackend`] that records the host items that were executed.
+    #[derive(Default)]
+    struct RecordingMenuBackend {
+        executed: Arc<Mutex<Vec<usize>>>,
+        added: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl ContextMenuBackend for RecordingMenuBackend {
+        fn execute(&self, index: usize) {
+            self.executed.lock().push(index);
+        }
+
+        fn add_item(&mut self, item: &ContextMenuItem, _callback: Arc<dyn Fn() + Send + Sync>) {
+            self.added.lock().push(item.name.clone());
+        }
+
+        fn popup(&self, _x: f32, _y: f32) -> bool {
+            false
+        }
+    }
+
+    fn menu_item(name: &str, kind: ContextMenuItemKind, enabled: bool) -> ContextMenuItem {
+        ContextMenuItem {
+            name: String::from(name),
+            kind,
+            enabled,
+            checked: false,
+        }
+    }
+
+    #[test]
+    fn context_menu_execute() {
+        let backend = RecordingMenuBackend::default();
+        let executed = backend.executed.clone();
+        let added = backend.added.clone();
+        let mut menu = HostContextMenu::new(
+            vec![
+                menu_item("MIDI Learn", ContextMenuItemKind::Action, true),
+                menu_item("", ContextMenuItemKind::Separator, true),
+                menu_item("Automation", ContextMenuItemKind::SubmenuStart, true),
+                menu_item("Show", ContextMenuItemKind::Action, false),
+                menu_item("", ContextMenuItemKind::SubmenuEnd, true),
+            ],
+            Box::new(backend),
+        );
+
+        let num_plugin_calls = Arc::new(Mutex::new(0));
+        menu.add_item("Reset", {
+            let num_plugin_calls = num_plugin_calls.clone();
+            move || *num_plugin_calls.lock() += 1
+        });
+        assert_eq!(menu.items().len(), 6);
+        assert_eq!(menu.items()[5].name, "Reset");
+        assert_eq!(*added.lock(), ["Reset"]);
+
+        // Only enabled actions do anything, and the plugin's own items never reach the host
+        for index in 0..menu.items().len() {
+            menu.execute(index);
+        }
+        assert_eq!(*executed.lock(), [0]);
+        assert_eq!(*num_plugin_calls.lock(), 1);
+    }
 }
@@ -0,0 +1,167 @@
+//! A [`GuiContext`] that is not connected to any host. Used to run and test editors without a
+//! plugin wrapper.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::gui::GuiContext;
+use super::PluginApi;
+use crate::params::changes::{
+    ParamChange, ParamChangeSource, ParamChangeSubscription, ParamChangeTracker,
+};
+use crate::params::internals::ParamPtr;
+use crate::params::Params;
+use crate::plugin::Plugin;
+use crate::wrapper::state::{self, PluginState};
+use crate::wrapper::util::ParamValueValidator;
+
+/// A call made to a [`HeadlessGuiContext`], in the order they were made.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuiContextCall {
+    BeginSetParameter(ParamPtr),
+    SetParameterNormalized(ParamPtr, f32),
+    EndSetParameter(ParamPtr),
+    BeginBatch,
+    EndBatch,
+    MarkStateDirty,
+}
+
+/// A [`GuiContext`] backed directly by a plugin's [`Params`] object, without a host or a plugin
+/// wrapper. Parameter changes made through this context are applied to the parameters immediately
+/// and they are also recorded, so tests can check which gestures an editor produced. Reports
+/// itself as [`PluginApi::Standalone`].
+pub struct HeadlessGuiContext<P: Plugin> {
+    params: Arc<dyn Params>,
+    /// A mapping from parameter string IDs to parameter pointers, used for saving and restoring
+    /// state.
+    param_map: HashMap<String, ParamPtr>,
+    param_changes: Arc<ParamChangeTracker>,
+    param_value_validator: ParamValueValidator,
+    calls: Mutex<Vec<GuiContextCall>>,
+
+    _phantom: PhantomData<fn() -> P>,
+}
+
+impl<P: Plugin> HeadlessGuiContext<P> {
+    /// Create a context for a plugin's parameters, as returned by [`Plugin::params()`].
+    pub fn new(params: Arc<dyn Params>) -> Self {
+        let param_map: HashMap<String, ParamPtr> = params
+            .param_map()
+            .into_iter()
+            .map(|(id, ptr, _)| (id, ptr))
+            .collect();
+        let param_changes = Arc::new(ParamChangeTracker::new(param_map.values().copied()));
+
+        Self {
+            params,
+            param_map,
+            param_changes,
+            param_value_validator: ParamValueValidator::default(),
+            calls: Mutex::new(Vec::new()),
+
+            _phantom: PhantomData,
+        }
+    }
+
+    /// All calls made to this context so far.
+    pub fn calls(&self) -> Vec<GuiContextCall> {
+        self.calls.lock().clone()
+    }
+
+    /// All calls made to this context since the last time this function was called.
+    pub fn take_calls(&self) -> Vec<GuiContextCall> {
+        std::mem::take(&mut self.calls.lock())
+    }
+
+    fn is_known_param(&self, param: ParamPtr) -> bool {
+        let known = self.param_map.values().any(|ptr| *ptr == param);
+        nih_debug_assert!(known, "Unknown parameter: {:?}", param);
+
+        known
+    }
+}
+
+impl<P: Plugin> GuiContext for HeadlessGuiContext<P> {
+    fn plugin_api(&self) -> PluginApi {
+        PluginApi::Standalone
+    }
+
+    fn request_resize(&self) -> bool {
+        // There's no window to resize
+        true
+    }
+
+    unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        if self.is_known_param(param) {
+            self.calls
+                .lock()
+                .push(GuiContextCall::BeginSetParameter(param));
+        }
+    }
+
+    unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        if self.is_known_param(param) {
+            param.set_normalized_value(normalized);
+            self.param_changes
+                .record(param, ParamChangeSource::GuiGesture, normalized);
+            self.calls
+                .lock()
+                .push(GuiContextCall::SetParameterNormalized(param, normalized));
+        }
+    }
+
+    unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        if self.is_known_param(param) {
+            self.calls
+                .lock()
+                .push(GuiContextCall::EndSetParameter(param));
+        }
+    }
+
+    fn raw_begin_batch(&self) {
+        self.calls.lock().push(GuiContextCall::BeginBatch);
+    }
+
+    fn raw_end_batch(&self) {
+        self.calls.lock().push(GuiContextCall::EndBatch);
+    }
+
+    fn get_state(&self) -> PluginState {
+        unsafe {
+            state::serialize_object::<P>(
+                self.params.clone(),
+                self.param_map.iter().map(|(id, ptr)| (id, *ptr)),
+            )
+        }
+    }
+
+    fn set_state(&self, mut state: PluginState) {
+        let success = unsafe {
+            state::deserialize_object::<P>(
+                &mut state,
+                self.params.clone(),
+                |param_id| self.param_map.get(param_id).copied(),
+                None,
+                false,
+                &self.param_value_validator,
+            )
+        };
+        if success {
+            self.param_changes.record_all(ParamChangeSource::Internal);
+        }
+    }
+
+    fn last_param_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        self.param_changes.last_change(param)
+    }
+
+    fn subscribe_param_changes(&self) -> ParamChangeSubscription {
+        ParamChangeSubscription::new(self.param_changes.clone())
+    }
+
+    fn mark_state_dirty(&self) {
+        self.calls.lock().push(GuiContextCall::MarkStateDirty);
+    }
+}
@@ -27,6 +27,14 @@ pub trait InitContext<P: Plugin> {
     /// this may cause audio playback to be restarted.
     fn set_latency_samples(&self, samples: u32);
 
+    /// The highest sample rate this plugin instance can expect to run at. This is
+    /// [`Plugin::MAX_SUPPORTED_SAMPLE_RATE`], or the current sample rate if the host is running at
+    /// a higher sample rate than that. The standalone target cannot change its sample rate, so
+    /// there this is always the current sample rate. Plugins that set
+    /// [`Plugin::PREALLOCATES_FOR_MAX_SAMPLE_RATE`] should size their buffers for this sample
+    /// rate.
+    fn max_expected_sample_rate(&self) -> f32;
+
     /// Set the current voice **capacity** for this plugin (so not the number of currently active
     /// voices). This may only be called if
     /// [`ClapPlugin::CLAP_POLY_MODULATION_CONFIG`][crate::prelude::ClapPlugin::CLAP_POLY_MODULATION_CONFIG]
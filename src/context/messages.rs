@@ -0,0 +1,223 @@
+//! Typed message queues between a plugin's editor and its process function, for editor controls
+//! that don't map to parameters. See [`editor_message_channel()`].
+
+use crossbeam::queue::ArrayQueue;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// The number of messages that can be queued up in either direction. Messages sent while the queue
+/// is full are dropped.
+pub const EDITOR_MESSAGE_QUEUE_CAPACITY: usize = 512;
+
+/// The process function's end of an [`editor_message_channel()`]. Store this in the plugin.
+pub struct ProcessMessages<T> {
+    channel: Arc<EditorMessageChannel<T>>,
+}
+
+/// The editor's end of an [`editor_message_channel()`]. Move this into the editor from
+/// [`Plugin::editor()`][crate::prelude::Plugin::editor()]. This can be cloned freely, and all
+/// clones share the same queues.
+pub struct EditorMessages<T> {
+    channel: Arc<EditorMessageChannel<T>>,
+}
+
+/// The message queues between a plugin's editor and its process function, shared between the
+/// [`ProcessMessages`] and [`EditorMessages`] ends.
+struct EditorMessageChannel<T> {
+    /// Messages sent by the editor, drained by the process function.
+    to_dsp: MessageQueue<T>,
+    /// Messages sent by the process function, drained by the editor.
+    to_editor: MessageQueue<T>,
+}
+
+/// A bounded lock-free queue that drops new messages when it's full. The slots for all messages
+/// are allocated up front, so sending and receiving messages never allocates.
+struct MessageQueue<T> {
+    queue: ArrayQueue<T>,
+    /// The number of messages that were dropped because the queue was full.
+    num_dropped: AtomicUsize,
+}
+
+/// Pops messages from an editor message queue in the order they were sent. Created using
+/// [`ProcessMessages::drain_editor_messages()`] and [`EditorMessages::drain_dsp_messages()`].
+/// Messages that are sent while iterating are also returned.
+pub struct DrainMessages<'a, T> {
+    queue: &'a MessageQueue<T>,
+}
+
+/// Create a message channel between a plugin's process function and its editor, for editor
+/// controls that are not parameters, like a button that clears a delay line or a tap tempo button.
+/// `T` is usually an enum, and the same type is used in both directions. Plugins that need this
+/// create the channel in their `Default` implementation, keep the [`ProcessMessages`] end, and move
+/// the [`EditorMessages`] end into their editor.
+///
+/// Both directions use bounded queues with [`EDITOR_MESSAGE_QUEUE_CAPACITY`] preallocated slots,
+/// so sending and receiving messages never allocates. Like background tasks, these messages should
+/// not contain heap allocated data if they're dropped on the audio thread.
+pub fn editor_message_channel<T: Send>() -> (ProcessMessages<T>, EditorMessages<T>) {
+    let channel = Arc::new(EditorMessageChannel {
+        to_dsp: MessageQueue::new(EDITOR_MESSAGE_QUEUE_CAPACITY),
+        to_editor: MessageQueue::new(EDITOR_MESSAGE_QUEUE_CAPACITY),
+    });
+
+    (
+        ProcessMessages {
+            channel: channel.clone(),
+        },
+        EditorMessages { channel },
+    )
+}
+
+impl<T> ProcessMessages<T> {
+    /// Receive the messages the editor sent with [`EditorMessages::send_to_dsp()`], in the order
+    /// they were sent. This does not allocate. Messages that are not drained stay queued until the
+    /// next process call, so call this at the start of every block.
+    pub fn drain_editor_messages(&self) -> DrainMessages<'_, T> {
+        self.channel.to_dsp.drain()
+    }
+
+    /// Send a message to the editor, which receives it through
+    /// [`EditorMessages::drain_dsp_messages()`]. This does not allocate. If the editor is closed or
+    /// falls behind and the queue fills up, the message is dropped.
+    pub fn send_to_editor(&self, message: T) {
+        self.channel.to_editor.push(message);
+    }
+}
+
+impl<T> EditorMessages<T> {
+    /// Send a message to the plugin's process function, which receives it through
+    /// [`ProcessMessages::drain_editor_messages()`] at the start of the next block. If the queue is
+    /// full because the plugin is not processing audio, then the message is dropped.
+    pub fn send_to_dsp(&self, message: T) {
+        self.channel.to_dsp.push(message);
+    }
+
+    /// Receive the messages the process function sent with [`ProcessMessages::send_to_editor()`],
+    /// in the order they were sent. Call this every frame.
+    pub fn drain_dsp_messages(&self) -> DrainMessages<'_, T> {
+        self.channel.to_editor.drain()
+    }
+}
+
+// Can't derive this since Rust then requires `T` to also be `Clone`able
+impl<T> Clone for EditorMessages<T> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> MessageQueue<T> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queue: ArrayQueue::new(capacity),
+            num_dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a message to the queue. If the queue is full the message is dropped and `false` is
+    /// returned.
+    fn push(&self, message: T) -> bool {
+        match self.queue.push(message) {
+            Ok(()) => true,
+            Err(_) => {
+                let num_dropped = self.num_dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                nih_debug_assert_failure!(
+                    "The editor message queue is full, dropping the message ({} messages dropped \
+                     so far)",
+                    num_dropped
+                );
+
+                false
+            }
+        }
+    }
+
+    /// Pop all queued messages in the order they were sent.
+    fn drain(&self) -> DrainMessages<'_, T> {
+        DrainMessages { queue: self }
+    }
+
+    /// The number of messages that were dropped because the queue was full.
+    #[cfg(test)]
+    fn num_dropped(&self) -> usize {
+        self.num_dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Iterator for DrainMessages<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.queue.queue.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preserves_order() {
+        let queue = MessageQueue::new(8);
+        for message in 0..5 {
+            assert!(queue.push(message));
+        }
+
+        let mut drain = queue.drain();
+        assert_eq!(drain.next(), Some(0));
+        assert_eq!(drain.next(), Some(1));
+
+        // Messages sent while draining the queue end up after the existing messages
+        assert!(queue.push(5));
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [2, 3, 4, 5]);
+        assert_eq!(queue.drain().next(), None);
+    }
+
+    #[test]
+    fn drops_messages_when_full() {
+        let queue = MessageQueue::new(4);
+        for message in 0..4 {
+            assert!(queue.push(message));
+        }
+        assert!(!queue.push(4));
+        assert!(!queue.push(5));
+        assert_eq!(queue.num_dropped(), 2);
+
+        // The oldest messages are kept, and the queue can be used again after draining it
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert!(queue.push(6));
+        assert_eq!(queue.drain().collect::<Vec<_>>(), [6]);
+        assert_eq!(queue.num_dropped(), 2);
+    }
+
+    #[test]
+    fn non_copy_messages() {
+        let (process_messages, editor_messages) = editor_message_channel::<String>();
+        editor_messages.send_to_dsp(String::from("clear"));
+        process_messages.send_to_editor(String::from("cleared"));
+
+        assert_eq!(
+            process_messages.drain_editor_messages().collect::<Vec<_>>(),
+            ["clear"]
+        );
+        assert_eq!(
+            editor_messages.drain_dsp_messages().collect::<Vec<_>>(),
+            ["cleared"]
+        );
+    }
+
+    #[test]
+    fn shared_between_editor_clones() {
+        let (process_messages, editor_messages) = editor_message_channel();
+        let editor_messages_clone = editor_messages.clone();
+        editor_messages.send_to_dsp(1);
+        editor_messages_clone.send_to_dsp(2);
+
+        assert_eq!(
+            process_messages.drain_editor_messages().collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+}
@@ -2,7 +2,9 @@
 
 use super::PluginApi;
 use crate::midi::NoteEvent;
-use crate::plugin::Plugin;
+use crate::params::internals::ParamPtr;
+use crate::params::Param;
+use crate::plugin::{BypassMode, Plugin};
 
 /// Contains both context data and callbacks the plugin can use during processing. Most notably this
 /// is how a plugin sends and receives note events, gets transport information, and accesses
@@ -41,6 +43,33 @@ pub trait ProcessContext<P: Plugin> {
     /// Get information about the current transport position and status.
     fn transport(&self) -> &Transport;
 
+    /// A seed for random number generators that stays the same between renders while the host is
+    /// rendering offline ([`ProcessMode::Offline`][crate::prelude::ProcessMode::Offline]), so
+    /// rendering the same project twice produces identical audio. The seed is derived from a
+    /// random ID that is stored in the plugin's state, so it is stable for a plugin instance
+    /// across save and load cycles, and it differs between instances. This returns `None` when
+    /// processing in realtime, in which case plugins should keep using their own entropy.
+    ///
+    /// Reseed your random number generators at points that are the same for every render, like
+    /// when the plugin is reset or when playback starts at the beginning of the project. See
+    /// [`Xoshiro128::from_context()`][crate::util::Xoshiro128::from_context()].
+    fn deterministic_seed(&self) -> Option<u64>;
+
+    /// Whether the plugin's bypass parameter is currently enabled. If the plugin does not have its
+    /// own bypass parameter then NIH-plug adds one and implements the bypass in the wrapper, so
+    /// this will always be `false` in that case. The wrapper's bypass crossfades to the plugin's
+    /// latency compensated input during realtime processing, and it switches instantly when
+    /// rendering offline. Plugins that want to implement their own bypass should add their own
+    /// parameter using [`BoolParam::make_bypass()`][crate::prelude::BoolParam::make_bypass()], and
+    /// should follow [`bypass_mode()`][Self::bypass_mode()].
+    fn bypassed(&self) -> bool;
+
+    /// How the plugin should apply its bypass parameter in the current processing mode. Plugins
+    /// with their own bypass parameter should crossfade to their input in
+    /// [`BypassMode::Soft`], and switch to it instantly in [`BypassMode::Hard`]. In both cases the
+    /// input should be delayed by the plugin's latency.
+    fn bypass_mode(&self) -> BypassMode;
+
     /// Returns the next note event, if there is one. Use [`NoteEvent::timing()`] to get the event's
     /// timing within the buffer. Only available when
     /// [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT] is set.
@@ -80,6 +109,14 @@ pub trait ProcessContext<P: Plugin> {
     /// otherwise.
     fn send_event(&mut self, event: NoteEvent);
 
+    /// Ask for the editor to be redrawn on its next frame, for instance after writing new data for a
+    /// meter or a waveform display. This only sets an atomic flag, so it doesn't allocate, lock, or
+    /// make any system calls and it can be called for every block. Requests are merged until the
+    /// editor gets redrawn, and how often that happens depends on the editor's
+    /// [`RepaintPolicy`][crate::prelude::RepaintPolicy]. This does nothing when the editor is not
+    /// open.
+    fn request_editor_repaint(&self);
+
     /// Update the current latency of the plugin. If the plugin is currently processing audio, then
     /// this may cause audio playback to be restarted.
     fn set_latency_samples(&self, samples: u32);
@@ -92,15 +129,31 @@ pub trait ProcessContext<P: Plugin> {
     /// monophonic modulation when dropping the capacity down to 1.
     fn set_current_voice_capacity(&self, capacity: u32);
 
-    // TODO: Add this, this works similar to [GuiContext::set_parameter] but it adds the parameter
-    //       change to a queue (or directly to the VST3 plugin's parameter output queues) instead of
-    //       using main thread host automation (and all the locks involved there).
-    // fn set_parameter<P: Param>(&self, param: &P, value: P::Plain);
+    /// Set the value of a read-only output parameter, like a meter or a clip indicator. See
+    /// [`ParamFlags::READ_ONLY`][crate::prelude::ParamFlags::READ_ONLY]. The parameter's value is
+    /// updated immediately, and the new value is sent to the host at the end of the current
+    /// process call so it also shows up in the host's generic UI. Setting a parameter that is not
+    /// marked as read-only does nothing.
+    fn set_output_parameter<T: Param>(&mut self, param: &T, value: T::Plain)
+    where
+        Self: Sized,
+    {
+        let normalized = param.preview_normalized(value);
+        unsafe { self.raw_set_output_parameter(param.as_ptr(), normalized) };
+    }
+
+    /// Set a read-only output parameter's value from a normalized value. Use
+    /// [`set_output_parameter()`][Self::set_output_parameter()] instead.
+    ///
+    /// # Safety
+    ///
+    /// `param` needs to be one of the plugin's own parameters.
+    unsafe fn raw_set_output_parameter(&mut self, param: ParamPtr, normalized: f32);
 }
 
 /// Information about the plugin's transport. Depending on the plugin API and the host not all
 /// fields may be available.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Transport {
     /// Whether the transport is currently running.
     pub playing: bool,
@@ -120,8 +173,22 @@ pub struct Transport {
     /// The time signature's denominator.
     pub time_sig_denominator: Option<i32>,
 
-    // XXX: VST3 also has a continuous time in samples that ignores loops, but we can't reconstruct
-    //      something similar in CLAP so it may be best to just ignore that so you can't rely on it
+    /// A continuous sample counter maintained by the wrapper. This starts at 0 when the host starts
+    /// processing audio or resets the plugin, and it advances by the length of every processed block regardless of
+    /// whether the transport is running. Unlike the song position this never loops, jumps, or runs
+    /// backwards, so it can be used as a stable time base for anything that should keep running
+    /// while the host relocates the playhead. This is always available.
+    pub continuous_pos_samples: i64,
+    /// Whether the song position moved by something other than the length of the previous block.
+    /// This happens when the host wraps around a loop, when the user relocates the playhead, and
+    /// when the host jumps back to start a pre-roll. Tempo-synced effects can use this to re-anchor
+    /// their beat grid to the new position instead of trying to interpolate towards it. This is
+    /// always `false` for the first block after activation, and when the song position is not
+    /// known or was not requested through
+    /// [`TransportRequirements::PROJECT_TIME`]. The song position does not advance while the
+    /// transport is stopped, so relocating a stopped transport also sets this flag.
+    pub position_jumped: bool,
+
     /// The position in the song in samples. Can be used to calculate the time in seconds if needed.
     pub(crate) pos_samples: Option<i64>,
     /// The position in the song in seconds. Can be used to calculate the time in samples if needed.
@@ -152,6 +219,30 @@ pub struct Transport {
     pub(crate) loop_range_beats: Option<(f64, f64)>,
 }
 
+bitflags::bitflags! {
+    /// The transport information a plugin needs, set through
+    /// [`Plugin::REQUIRED_TRANSPORT`][crate::prelude::Plugin::REQUIRED_TRANSPORT]. VST3 hosts are
+    /// told to only provide this information, and any [`Transport`] fields that were not requested
+    /// are always left empty regardless of whether the host provided them. This makes sure plugins
+    /// don't accidentally rely on information that only some hosts provide.
+    #[repr(transparent)]
+    #[derive(Default)]
+    pub struct TransportRequirements: u32 {
+        /// The transport's play, record, and pre-roll state.
+        const TRANSPORT_STATE = 1 << 0;
+        /// The project's tempo.
+        const TEMPO = 1 << 1;
+        /// The time signature's numerator and denominator.
+        const TIME_SIGNATURE = 1 << 2;
+        /// The position in the song in samples, seconds, and quarter notes.
+        const PROJECT_TIME = 1 << 3;
+        /// The position and number of the current bar.
+        const BAR_POSITION = 1 << 4;
+        /// The loop range, if the loop is active.
+        const LOOP_RANGE = 1 << 5;
+    }
+}
+
 impl Transport {
     /// Initialize the transport struct without any information.
     pub(crate) fn new(sample_rate: f32) -> Self {
@@ -165,6 +256,9 @@ impl Transport {
             time_sig_numerator: None,
             time_sig_denominator: None,
 
+            continuous_pos_samples: 0,
+            position_jumped: false,
+
             pos_samples: None,
             pos_seconds: None,
             pos_beats: None,
@@ -177,6 +271,38 @@ impl Transport {
         }
     }
 
+    /// Clear all information the plugin did not ask for. Called by the wrappers after filling in the
+    /// transport information provided by the host.
+    pub(crate) fn retain(&mut self, requirements: TransportRequirements) {
+        if !requirements.contains(TransportRequirements::TRANSPORT_STATE) {
+            self.playing = false;
+            self.recording = false;
+            self.preroll_active = None;
+        }
+        if !requirements.contains(TransportRequirements::TEMPO) {
+            self.tempo = None;
+        }
+        if !requirements.contains(TransportRequirements::TIME_SIGNATURE) {
+            self.time_sig_numerator = None;
+            self.time_sig_denominator = None;
+        }
+        if !requirements.contains(TransportRequirements::PROJECT_TIME) {
+            self.position_jumped = false;
+            self.pos_samples = None;
+            self.pos_seconds = None;
+            self.pos_beats = None;
+        }
+        if !requirements.contains(TransportRequirements::BAR_POSITION) {
+            self.bar_start_pos_beats = None;
+            self.bar_number = None;
+        }
+        if !requirements.contains(TransportRequirements::LOOP_RANGE) {
+            self.loop_range_samples = None;
+            self.loop_range_seconds = None;
+            self.loop_range_beats = None;
+        }
+    }
+
     /// The position in the song in samples. Will be calculated from other information if needed.
     pub fn pos_samples(&self) -> Option<i64> {
         match (
@@ -0,0 +1,199 @@
+//! Repaint requests for a plugin's editor. See
+//! [`GuiContext::request_repaint()`][super::gui::GuiContext::request_repaint()] and
+//! [`ProcessContext::request_editor_repaint()`][super::process::ProcessContext::request_editor_repaint()].
+
+use crossbeam::atomic::AtomicCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// How often a plugin's editor is redrawn. The editor can change this at any time using
+/// [`GuiContext::set_repaint_policy()`][super::gui::GuiContext::set_repaint_policy()].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RepaintPolicy {
+    /// Whether the editor should be redrawn on every frame. When this is `false`, the editor is
+    /// only redrawn after a repaint has been requested by the editor, by the process function, or
+    /// by the wrapper when the host changes a parameter. GUI libraries still redraw in response to
+    /// input events either way.
+    pub continuous: bool,
+    /// The maximum number of times per second the editor is redrawn, or `None` to redraw as often
+    /// as the GUI library's frame timer allows. Repaints requested in between are merged into the
+    /// next allowed repaint.
+    pub max_rate: Option<f32>,
+}
+
+/// The repaint requests for a plugin instance's editor. The wrappers share this between their
+/// process contexts and the [`GuiContext`][super::gui::GuiContext] passed to the editor.
+pub(crate) struct RepaintRequests {
+    /// Set when a repaint has been requested, and cleared when [`poll()`][Self::poll()] allows the
+    /// editor to be redrawn. This is the only field touched by the audio thread.
+    requested: AtomicBool,
+    policy: AtomicCell<RepaintPolicy>,
+    /// The earliest time the editor may be redrawn again when the policy has a maximum rate.
+    next_repaint: AtomicCell<Option<Instant>>,
+}
+
+impl Default for RepaintPolicy {
+    fn default() -> Self {
+        Self {
+            continuous: true,
+            max_rate: None,
+        }
+    }
+}
+
+impl RepaintPolicy {
+    /// Only redraw the editor after a repaint has been requested, at most `max_rate` times per
+    /// second.
+    pub fn on_request(max_rate: f32) -> Self {
+        Self {
+            continuous: false,
+            max_rate: Some(max_rate),
+        }
+    }
+}
+
+impl Default for RepaintRequests {
+    fn default() -> Self {
+        Self {
+            // The editor should always be drawn at least once
+            requested: AtomicBool::new(true),
+            policy: AtomicCell::new(RepaintPolicy::default()),
+            next_repaint: AtomicCell::new(None),
+        }
+    }
+}
+
+impl RepaintRequests {
+    /// Request a repaint. This only sets an atomic flag, so it can safely be called from the
+    /// audio thread.
+    #[inline]
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Release);
+    }
+
+    /// Change the editor's repaint policy. Maximum rates that are not positive finite numbers are
+    /// ignored.
+    pub fn set_policy(&self, mut policy: RepaintPolicy) {
+        if let Some(max_rate) = policy.max_rate {
+            if !(max_rate.is_finite() && max_rate > 0.0) {
+                nih_debug_assert_failure!("Invalid maximum repaint rate: {}", max_rate);
+                policy.max_rate = None;
+            }
+        }
+
+        self.policy.store(policy);
+        self.next_repaint.store(None);
+    }
+
+    /// Called from the editor's frame callback to decide whether it should redraw during the
+    /// frame starting at `now`. When this returns `true`, the pending repaint request has been
+    /// consumed.
+    pub fn poll(&self, now: Instant) -> bool {
+        let policy = self.policy.load();
+        if !policy.continuous && !self.requested.load(Ordering::Acquire) {
+            return false;
+        }
+
+        if let Some(max_rate) = policy.max_rate {
+            let next_repaint = self.next_repaint.load();
+            if matches!(next_repaint, Some(next_repaint) if now < next_repaint) {
+                return false;
+            }
+
+            // Scheduling the next repaint relative to the last deadline instead of to `now` keeps
+            // the average rate at `max_rate` when the frame timer jitters a bit
+            let interval = Duration::from_secs_f32(max_rate.recip());
+            let next_repaint = match next_repaint {
+                Some(next_repaint) if next_repaint + interval > now => next_repaint + interval,
+                _ => now + interval,
+            };
+            self.next_repaint.store(Some(next_repaint));
+        }
+
+        self.requested.store(false, Ordering::Release);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(milliseconds: u64) -> Duration {
+        Duration::from_millis(milliseconds)
+    }
+
+    #[test]
+    fn continuous_by_default() {
+        let requests = RepaintRequests::default();
+        let start = Instant::now();
+        for frame in 0..4 {
+            assert!(requests.poll(start + ms(frame)));
+        }
+    }
+
+    #[test]
+    fn on_request() {
+        let requests = RepaintRequests::default();
+        requests.set_policy(RepaintPolicy {
+            continuous: false,
+            max_rate: None,
+        });
+        let start = Instant::now();
+
+        // The first frame is always drawn
+        assert!(requests.poll(start));
+        assert!(!requests.poll(start + ms(1)));
+
+        // Multiple requests before the next frame result in a single repaint
+        requests.request();
+        requests.request();
+        assert!(requests.poll(start + ms(2)));
+        assert!(!requests.poll(start + ms(3)));
+    }
+
+    #[test]
+    fn max_rate() {
+        let requests = RepaintRequests::default();
+        requests.set_policy(RepaintPolicy::on_request(50.0));
+        let start = Instant::now();
+
+        assert!(requests.poll(start));
+        requests.request();
+        assert!(!requests.poll(start + ms(5)));
+        assert!(!requests.poll(start + ms(15)));
+        // The request is kept until the editor is allowed to repaint again
+        assert!(requests.poll(start + ms(21)));
+        assert!(!requests.poll(start + ms(25)));
+
+        // The next deadline is based on the previous one, so this is only 19 ms later
+        requests.request();
+        assert!(requests.poll(start + ms(40)));
+
+        // After being idle for a while the editor can repaint immediately
+        requests.request();
+        assert!(requests.poll(start + ms(200)));
+    }
+
+    #[test]
+    fn continuous_max_rate() {
+        let requests = RepaintRequests::default();
+        requests.set_policy(RepaintPolicy {
+            continuous: true,
+            max_rate: Some(100.0),
+        });
+        let start = Instant::now();
+
+        let num_repaints = (0..100)
+            .filter(|frame| requests.poll(start + ms(frame * 2)))
+            .count();
+        assert_eq!(num_repaints, 20);
+    }
+
+    #[test]
+    fn invalid_max_rate() {
+        let requests = RepaintRequests::default();
+        requests.set_policy(RepaintPolicy::on_request(0.0));
+        assert_eq!(requests.policy.load().max_rate, None);
+    }
+}
@@ -113,6 +113,55 @@ macro_rules! nih_debug_assert_failure {
 #[doc(inline)]
 pub use nih_debug_assert_failure;
 
+/// A debug assertion that checks whether the current thread is running one of the plugin's audio
+/// thread functions through one of NIH-plug's wrappers. See
+/// [`util::is_audio_thread()`][crate::util::is_audio_thread()]. Like the other debug assertions
+/// this is compiled out in release builds.
+#[macro_export]
+macro_rules! nih_debug_assert_audio_thread {
+    () => {
+        if cfg!(debug_assertions) && !$crate::util::is_audio_thread() {
+            $crate::util::thread_role_assertion_failed(
+                "This function may only be called from the audio thread",
+            );
+        }
+    };
+}
+#[doc(inline)]
+pub use nih_debug_assert_audio_thread;
+
+/// The inverse of [`nih_debug_assert_audio_thread!()`]. Used for functions that may block, or that
+/// would cause problems when called from within the process function.
+#[macro_export]
+macro_rules! nih_debug_assert_not_audio_thread {
+    () => {
+        if cfg!(debug_assertions) && $crate::util::is_audio_thread() {
+            $crate::util::thread_role_assertion_failed(
+                "This function may not be called from the audio thread",
+            );
+        }
+    };
+}
+#[doc(inline)]
+pub use nih_debug_assert_not_audio_thread;
+
+/// A debug assertion that checks whether the current thread is running a task one of NIH-plug's
+/// wrappers scheduled on the GUI thread. See
+/// [`util::is_gui_thread()`][crate::util::is_gui_thread()]. The plugin's editor may run on its own
+/// thread on some platforms, so this is only used for the wrappers' own GUI thread callbacks.
+#[macro_export]
+macro_rules! nih_debug_assert_gui_thread {
+    () => {
+        if cfg!(debug_assertions) && !$crate::util::is_gui_thread() {
+            $crate::util::thread_role_assertion_failed(
+                "This function may only be called from the GUI thread",
+            );
+        }
+    };
+}
+#[doc(inline)]
+pub use nih_debug_assert_gui_thread;
+
 /// A `debug_assert_eq!()` analogue that prints the error with line number information instead of
 /// panicking.
 #[macro_export]
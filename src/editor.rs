@@ -62,11 +62,90 @@ pub trait Editor: Send {
     /// not allocate.
     fn param_values_changed(&self);
 
+    /// Called once when the plugin panicked. The plugin won't process any more audio after this
+    /// until the host reloads it, so the editor can use this to tell the user what happened. This
+    /// is called from the GUI thread, and it's currently only called by the VST3 wrapper.
+    fn plugin_panicked(&self) {}
+
+    /// The sizes the host may resize the editor to, or `None` if the editor cannot be resized by
+    /// the host. When this returns `Some`, the wrapper will snap the host's proposed sizes to these
+    /// constraints and then call [`set_size()`][Self::set_size()] with the result. Editors can
+    /// still resize themselves through
+    /// [`GuiContext::request_resize()`][crate::prelude::GuiContext::request_resize()] either way.
+    fn size_constraints(&self) -> Option<SizeConstraints> {
+        None
+    }
+
+    /// Called when the host resizes the editor's window. The size is in _logical pixels_, and it
+    /// has already been snapped to the editor's [`size_constraints()`][Self::size_constraints()].
+    /// After this returns `true`, [`size()`][Self::size()] should return the new size. This is
+    /// only called if `size_constraints()` returns `Some`.
+    fn set_size(&self, width: u32, height: u32) -> bool {
+        let _ = (width, height);
+        false
+    }
+
+    /// The editor as an [`Any`] object, or `None` if the editor doesn't support this. GUI adapter
+    /// crates can use this to recover their own editor type from a `Box<dyn Editor>`. This is used
+    /// by `nih_plug_egui` to render editors offscreen in tests.
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+
     // TODO: Reconsider adding a tick function here for the Linux `IRunLoop`. To keep this platform
     //       and API agnostic, add a way to ask the GuiContext if the wrapper already provides a
     //       tick function. If it does not, then the Editor implementation must handle this by
     //       itself. This would also need an associated `PREFERRED_FRAME_RATE` constant.
-    // TODO: Host->Plugin resizing
+}
+
+/// Constraints for host driven editor resizing, returned from [`Editor::size_constraints()`]. All
+/// sizes are in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SizeConstraints {
+    /// The editor's minimum `(width, height)`.
+    pub min_size: (u32, u32),
+    /// The editor's maximum `(width, height)`.
+    pub max_size: (u32, u32),
+    /// If set, the editor's width divided by its height must always equal this ratio.
+    pub aspect_ratio: Option<f32>,
+}
+
+impl SizeConstraints {
+    /// Snap a proposed size to the closest size allowed by these constraints. When the aspect
+    /// ratio is fixed, the result is the largest size with that ratio that fits within the
+    /// proposed size. If the constraints contradict each other, then the minimum and maximum sizes
+    /// take precedence over the aspect ratio.
+    pub fn constrain(&self, width: u32, height: u32) -> (u32, u32) {
+        let (min_width, min_height) = self.min_size;
+        let (max_width, max_height) = self.max_size;
+        let clamp = |width: u32, height: u32| {
+            (
+                width.clamp(min_width, max_width.max(min_width)),
+                height.clamp(min_height, max_height.max(min_height)),
+            )
+        };
+
+        let (mut width, mut height) = clamp(width, height);
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            if width as f32 / height as f32 > aspect_ratio {
+                width = (height as f32 * aspect_ratio).round() as u32;
+            } else {
+                height = (width as f32 / aspect_ratio).round() as u32;
+            }
+
+            // Shrinking to fit the aspect ratio may have pushed one side below its minimum
+            if width < min_width {
+                width = min_width;
+                height = (width as f32 / aspect_ratio).round() as u32;
+            }
+            if height < min_height {
+                height = min_height;
+                width = (height as f32 * aspect_ratio).round() as u32;
+            }
+        }
+
+        clamp(width, height)
+    }
 }
 
 /// A raw window handle for platform and GUI framework agnostic editors.
@@ -79,3 +158,64 @@ unsafe impl HasRawWindowHandle for ParentWindowHandle {
         self.handle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONSTRAINTS: SizeConstraints = SizeConstraints {
+        min_size: (200, 100),
+        max_size: (2000, 1000),
+        aspect_ratio: None,
+    };
+
+    #[test]
+    fn constrain_within_bounds() {
+        assert_eq!(CONSTRAINTS.constrain(640, 480), (640, 480));
+        assert_eq!(CONSTRAINTS.constrain(200, 100), (200, 100));
+        assert_eq!(CONSTRAINTS.constrain(2000, 1000), (2000, 1000));
+    }
+
+    #[test]
+    fn constrain_clamps() {
+        assert_eq!(CONSTRAINTS.constrain(10, 10), (200, 100));
+        assert_eq!(CONSTRAINTS.constrain(5000, 5000), (2000, 1000));
+        assert_eq!(CONSTRAINTS.constrain(10, 5000), (200, 1000));
+    }
+
+    #[test]
+    fn constrain_aspect_ratio() {
+        let constraints = SizeConstraints {
+            aspect_ratio: Some(2.0),
+            ..CONSTRAINTS
+        };
+
+        assert_eq!(constraints.constrain(800, 400), (800, 400));
+        // The result should fit within the proposed size
+        assert_eq!(constraints.constrain(1000, 400), (800, 400));
+        assert_eq!(constraints.constrain(800, 1000), (800, 400));
+        assert_eq!(constraints.constrain(5000, 5000), (2000, 1000));
+        // Growing back to the minimum size should keep the aspect ratio
+        assert_eq!(constraints.constrain(1000, 10), (200, 100));
+        assert_eq!(constraints.constrain(10, 1000), (200, 100));
+        assert_eq!(constraints.constrain(301, 1000), (301, 151));
+    }
+
+    #[test]
+    fn constrain_conflicting_aspect_ratio() {
+        let constraints = SizeConstraints {
+            min_size: (100, 300),
+            max_size: (1000, 1000),
+            aspect_ratio: Some(1.0),
+        };
+
+        // Fitting the aspect ratio would go below the minimum height, so the width grows instead
+        assert_eq!(constraints.constrain(200, 500), (300, 300));
+        // And if that width is not allowed, then the maximum width wins over the aspect ratio
+        let constraints = SizeConstraints {
+            max_size: (200, 1000),
+            ..constraints
+        };
+        assert_eq!(constraints.constrain(200, 500), (200, 300));
+    }
+}
@@ -2,6 +2,9 @@
 
 use std::sync::Arc;
 
+use crate::plugin::BackgroundTaskPriority;
+use crate::wrapper::thread_role;
+
 mod background_thread;
 
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
@@ -12,7 +15,9 @@ mod linux;
 #[cfg(target_os = "windows")]
 mod windows;
 
-pub(crate) use self::background_thread::BackgroundThread;
+#[cfg(test)]
+pub(crate) use self::background_thread::live_worker_threads;
+pub(crate) use self::background_thread::{join_stopped_worker_threads, BackgroundThread};
 
 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
 pub(crate) use self::linux::LinuxEventLoop as OsEventLoop;
@@ -37,8 +42,8 @@ pub(crate) const TASK_QUEUE_CAPACITY: usize = 512;
 /// an implementation to show up as compiler errors even when using a different platform. And since
 /// the tasks and executor will be sent to a thread, they need to have static lifetimes.
 ///
-/// TODO: At some point rethink the design to make it possible to have a singleton message queue for
-///       all instances of a plugin.
+/// Background tasks from all instances are run on a single shared thread pool, see
+/// [`BackgroundThread`].
 pub(crate) trait EventLoop<T, E>
 where
     T: Send + 'static,
@@ -56,13 +61,12 @@ where
     #[must_use]
     fn schedule_gui(&self, task: T) -> bool;
 
-    /// Post a task to the background task queue so it can be run in a dedicated background thread
-    /// without blocking the plugin's GUI. This function needs to be callable at any time without
-    /// blocking.
+    /// Post a task to the background task queue so it can be run on a background thread without
+    /// blocking the plugin's GUI. This function needs to be callable at any time without blocking.
     ///
-    /// If the task queue is full, then this will return false.
+    /// If the task queue for the priority class is full, then this will return false.
     #[must_use]
-    fn schedule_background(&self, task: T) -> bool;
+    fn schedule_background(&self, task: T, priority: BackgroundTaskPriority) -> bool;
 
     /// Whether the calling thread is the event loop's main thread. This is usually the thread the
     /// event loop instance was initialized on.
@@ -74,4 +78,11 @@ pub(crate) trait MainThreadExecutor<T>: Send + Sync {
     /// Execute a task on the current thread. This is either called from the GUI thread or from
     /// another background thread, depending on how the task was scheduled in the [`EventContext`].
     fn execute(&self, task: T, is_gui_thread: bool);
+
+    /// Execute a task on the GUI thread. The current thread is marked as the GUI thread for
+    /// [`util::is_gui_thread()`][crate::util::is_gui_thread()] while the task is running.
+    fn execute_gui(&self, task: T) {
+        let _gui_thread_guard = thread_role::enter_gui_thread();
+        self.execute(task, true);
+    }
 }
@@ -1,32 +1,119 @@
-//! Used by the other [`EventLoop`][super::EventLoop] implementations to spawn threads for running
-//! tasks in the background without blocking the GUI thread.
+//! Used by the other [`EventLoop`][super::EventLoop] implementations to run tasks in the background
+//! without blocking the GUI thread.
 //!
-//! This is essentially a slimmed down version of the `LinuxEventLoop`.
+//! All plugin instances in the process share a single pool of worker threads. The pool is created
+//! when the first instance needs it, and its threads are shut down when the last instance using it
+//! is dropped. Every instance has its own task queues, and the pool's workers take turns running
+//! tasks from those queues so a single instance can't hog all threads.
 
 use crossbeam::channel;
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 use std::thread::{self, JoinHandle};
 
 use super::MainThreadExecutor;
+use crate::plugin::BackgroundTaskPriority;
 
-/// See the module's documentation. This is a slimmed down version of the `LinuxEventLoop` that can
-/// be used with other OS and plugin format specific event loop implementations.
+/// The number of [`BackgroundTaskPriority::Interactive`] tasks that can be queued per instance.
+/// These should be rare, so this is smaller than the bulk task queue.
+const INTERACTIVE_QUEUE_CAPACITY: usize = 128;
+/// The maximum number of worker threads in the pool.
+const MAX_WORKER_THREADS: usize = 4;
+
+/// The pool shared by all instances in this process. This only holds a weak reference so the pool
+/// gets shut down when the last instance is dropped.
+#[cfg(not(test))]
+static POOL: Mutex<Weak<BackgroundThreadPool>> = parking_lot::const_mutex(Weak::new());
+
+// Tests run in parallel, so every test thread gets its own pool. Otherwise the tests could not
+// check whether the pool shuts down after their last instance has been dropped.
+#[cfg(test)]
+thread_local! {
+    static POOL: Mutex<Weak<BackgroundThreadPool>> = parking_lot::const_mutex(Weak::new());
+}
+
+/// The threads joining the worker threads of pools that have been shut down. See
+/// [`join_stopped_worker_threads()`].
+static REAPER_THREADS: Mutex<Vec<JoinHandle<()>>> = parking_lot::const_mutex(Vec::new());
+
+/// A single plugin instance's handle to the shared [`BackgroundThreadPool`]. Tasks scheduled
+/// through this handle are run one at a time per priority class, in the order they were scheduled.
+/// Dropping the handle cancels the instance's queued tasks. A task that is already running keeps
+/// the executor alive until it has finished.
 pub(crate) struct BackgroundThread<T> {
-    /// A thread that act as our worker thread. When [`schedule_gui()`][Self::schedule_gui()] is
-    /// called, this thread will be woken up to execute the task on the executor. This is wrapped in
-    /// an `Option` so the thread can be taken out of it and joined when this struct gets dropped.
-    worker_thread: Option<JoinHandle<()>>,
-    /// A channel for waking up the worker thread and having it perform one of the tasks from
-    /// [`Message`].
-    tasks_sender: channel::Sender<Message<T>>,
+    /// The sending halves of the instance's interactive and bulk task queues.
+    interactive_sender: channel::Sender<T>,
+    bulk_sender: channel::Sender<T>,
+    /// The instance's task queues as registered with the pool. The pool only holds a weak
+    /// reference, so this is what keeps the instance registered.
+    instance: Arc<dyn PoolInstance>,
+    /// Keeps the pool alive for as long as this instance exists.
+    pool: Arc<BackgroundThreadPool>,
+}
+
+/// A pool of worker threads shared between all plugin instances.
+struct BackgroundThreadPool {
+    shared: Arc<PoolShared>,
+    /// Used to wake up the worker threads when an instance schedules a new task. Dropping these
+    /// shuts down the workers, so this is wrapped in an `Option` so the senders can be dropped
+    /// before the threads are joined.
+    wakeup_senders: Option<WakeupSenders>,
+    worker_threads: Vec<JoinHandle<()>>,
+    /// The number of this pool's worker threads that are still running. The workers decrement this
+    /// right before they exit.
+    num_live_workers: Arc<AtomicUsize>,
+}
+
+/// See [`BackgroundThreadPool::wakeup_senders`]. Bulk tasks use a separate channel because not
+/// every worker thread can run them. If an interactive-only worker received the wakeup for a bulk
+/// task, that task would not run until the next wakeup.
+struct WakeupSenders {
+    interactive: channel::Sender<()>,
+    bulk: channel::Sender<()>,
+}
+
+/// The part of the pool the worker threads have access to.
+struct PoolShared {
+    instances: Mutex<PoolInstances>,
+    interactive_wakeup_receiver: channel::Receiver<()>,
+    bulk_wakeup_receiver: channel::Receiver<()>,
+}
+
+/// The instances registered with a pool.
+struct PoolInstances {
+    /// Dead instances are pruned when looking for the next task to run.
+    instances: Vec<Weak<dyn PoolInstance>>,
+    /// The index of the instance that should be checked for new tasks first. This makes the
+    /// workers take turns servicing the instances.
+    next_instance_idx: usize,
+}
+
+/// An instance's task queues, type erased so instances of different plugins can share a pool.
+trait PoolInstance: Send + Sync {
+    /// Claim the queue for the specified priority if it has any pending tasks and if none of its
+    /// tasks are currently running. Returns `true` if the queue was claimed, after which
+    /// [`run_claimed()`][Self::run_claimed()] must be called with the same priority.
+    fn try_claim(&self, priority: BackgroundTaskPriority) -> bool;
+
+    /// Run the next task from a previously claimed queue, and release the claim afterwards.
+    fn run_claimed(&self, priority: BackgroundTaskPriority);
+
+    /// Prevent any more tasks from being run. Called when the instance's handle is dropped.
+    fn cancel(&self);
 }
 
-/// A message for communicating with the worker thread.
-enum Message<T> {
-    /// A new task for the event loop to execute.
-    Task(T),
-    /// Shut down the worker thread.
-    Shutdown,
+/// The receiving halves of an instance's task queues, along with the executor that runs the tasks.
+struct InstanceQueues<T, E> {
+    interactive_receiver: channel::Receiver<T>,
+    bulk_receiver: channel::Receiver<T>,
+    /// Whether a worker thread is currently running a task from the corresponding queue.
+    interactive_claimed: AtomicBool,
+    bulk_claimed: AtomicBool,
+    /// Set when the instance's handle is dropped. A worker may still hold a reference to the
+    /// instance at that point, and it should not pick up any new tasks.
+    cancelled: AtomicBool,
+    executor: Weak<E>,
 }
 
 impl<T> BackgroundThread<T>
@@ -37,63 +124,491 @@ where
     where
         E: MainThreadExecutor<T> + 'static,
     {
-        let (tasks_sender, tasks_receiver) = channel::bounded(super::TASK_QUEUE_CAPACITY);
+        #[cfg(not(test))]
+        let pool = BackgroundThreadPool::get_or_spawn(&POOL);
+        #[cfg(test)]
+        let pool = POOL.with(BackgroundThreadPool::get_or_spawn);
+
+        Self::new_in_pool(pool, &executor)
+    }
+
+    /// Register a new instance with `pool`.
+    fn new_in_pool<E>(pool: Arc<BackgroundThreadPool>, executor: &Arc<E>) -> Self
+    where
+        E: MainThreadExecutor<T> + 'static,
+    {
+        let (interactive_sender, interactive_receiver) =
+            channel::bounded(INTERACTIVE_QUEUE_CAPACITY);
+        let (bulk_sender, bulk_receiver) = channel::bounded(super::TASK_QUEUE_CAPACITY);
+        let instance: Arc<dyn PoolInstance> = Arc::new(InstanceQueues {
+            interactive_receiver,
+            bulk_receiver,
+            interactive_claimed: AtomicBool::new(false),
+            bulk_claimed: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            executor: Arc::downgrade(executor),
+        });
+        pool.shared
+            .instances
+            .lock()
+            .instances
+            .push(Arc::downgrade(&instance));
 
         Self {
-            // With our drop implementation we guarantee that this thread never outlives this struct
-            worker_thread: Some(
-                thread::Builder::new()
-                    .name(String::from("bg-worker"))
-                    .spawn(move || worker_thread(tasks_receiver, Arc::downgrade(&executor)))
-                    .expect("Could not spawn background worker thread"),
-            ),
-            tasks_sender,
+            interactive_sender,
+            bulk_sender,
+            instance,
+            pool,
         }
     }
 
-    pub fn schedule(&self, task: T) -> bool {
-        self.tasks_sender.try_send(Message::Task(task)).is_ok()
+    /// Schedule a task to be run on one of the pool's worker threads. This does not block or
+    /// allocate. Returns `false` if the queue for the task's priority is full.
+    pub fn schedule(&self, task: T, priority: BackgroundTaskPriority) -> bool {
+        let wakeup_senders = self.pool.wakeup_senders.as_ref().unwrap();
+        let (sender, wakeup_sender) = match priority {
+            BackgroundTaskPriority::Interactive => {
+                (&self.interactive_sender, &wakeup_senders.interactive)
+            }
+            BackgroundTaskPriority::Bulk => (&self.bulk_sender, &wakeup_senders.bulk),
+        };
+        if sender.try_send(task).is_err() {
+            return false;
+        }
+
+        // If this fails then there are already enough pending wakeups to get to this task
+        let _ = wakeup_sender.try_send(());
+
+        true
     }
 }
 
 impl<T> Drop for BackgroundThread<T> {
     fn drop(&mut self) {
-        self.tasks_sender
-            .send(Message::Shutdown)
-            .expect("Failed while sending worker thread shutdown request");
-        if let Some(join_handle) = self.worker_thread.take() {
-            join_handle.join().expect("Worker thread panicked");
+        // The queued tasks are dropped together with the instance. There's no need to unregister
+        // it, the pool prunes dead instances on its own.
+        self.instance.cancel();
+    }
+}
+
+impl BackgroundThreadPool {
+    /// Get the pool stored in `slot`, or spawn a new pool if it doesn't exist (anymore).
+    fn get_or_spawn(slot: &Mutex<Weak<Self>>) -> Arc<Self> {
+        let mut slot = slot.lock();
+        if let Some(pool) = slot.upgrade() {
+            return pool;
+        }
+
+        let pool = Arc::new(Self::spawn());
+        *slot = Arc::downgrade(&pool);
+
+        pool
+    }
+
+    fn spawn() -> Self {
+        let num_threads = thread::available_parallelism()
+            .map_or(2, |n| n.get())
+            .clamp(2, MAX_WORKER_THREADS);
+        let (interactive_wakeup_sender, interactive_wakeup_receiver) =
+            channel::bounded(INTERACTIVE_QUEUE_CAPACITY);
+        let (bulk_wakeup_sender, bulk_wakeup_receiver) =
+            channel::bounded(super::TASK_QUEUE_CAPACITY);
+        let shared = Arc::new(PoolShared {
+            instances: Mutex::new(PoolInstances {
+                instances: Vec::new(),
+                next_instance_idx: 0,
+            }),
+            interactive_wakeup_receiver,
+            bulk_wakeup_receiver,
+        });
+
+        // The first thread only runs interactive tasks so those never have to wait for a long
+        // running bulk task to finish
+        let num_live_workers = Arc::new(AtomicUsize::new(num_threads));
+        let worker_threads = (0..num_threads)
+            .map(|thread_idx| {
+                let shared = shared.clone();
+                let num_live_workers = num_live_workers.clone();
+                let interactive_only = thread_idx == 0;
+
+                thread::Builder::new()
+                    .name(format!("bg-worker-{thread_idx}"))
+                    .spawn(move || {
+                        worker_thread(&shared, interactive_only);
+                        num_live_workers.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .expect("Could not spawn background worker thread")
+            })
+            .collect();
+
+        Self {
+            shared,
+            wakeup_senders: Some(WakeupSenders {
+                interactive: interactive_wakeup_sender,
+                bulk: bulk_wakeup_sender,
+            }),
+            worker_threads,
+            num_live_workers,
         }
     }
 }
 
-/// The worker thread used in [`EventLoop`] that executes incoming tasks on the event loop's
-/// executor.
-fn worker_thread<T, E>(tasks_receiver: channel::Receiver<Message<T>>, executor: Weak<E>)
+impl Drop for BackgroundThreadPool {
+    fn drop(&mut self) {
+        // Closing the channels makes the workers shut down after they've finished their current
+        // task
+        self.wakeup_senders = None;
+
+        // If the last instance was dropped from within one of its own background tasks, then this
+        // is running on one of the worker threads and that thread can't join itself
+        let current_thread_id = thread::current().id();
+        let worker_threads: Vec<_> = self
+            .worker_threads
+            .drain(..)
+            .filter(|join_handle| join_handle.thread().id() != current_thread_id)
+            .collect();
+
+        // Hosts usually drop plugin instances on their GUI thread, and a worker may still be busy
+        // with a long running bulk task. Joining the workers here would block the host's GUI until
+        // that task has finished, so that happens on a separate thread instead. That thread is in
+        // turn joined when the plugin library gets unloaded.
+        let reaper_thread = thread::Builder::new()
+            .name(String::from("bg-reaper"))
+            .spawn(move || {
+                for join_handle in worker_threads {
+                    if join_handle.join().is_err() {
+                        nih_error!("A background worker thread panicked");
+                    }
+                }
+            });
+        match reaper_thread {
+            Ok(reaper_thread) => {
+                let mut reaper_threads = REAPER_THREADS.lock();
+                reaper_threads.retain(|reaper_thread| !reaper_thread.is_finished());
+                reaper_threads.push(reaper_thread);
+            }
+            // The workers still shut down on their own, they just won't be joined
+            Err(err) => nih_error!("Could not spawn the background reaper thread: {err}"),
+        }
+    }
+}
+
+/// Wait for the worker threads of every pool that has been shut down to exit. These threads must
+/// not outlive the plugin library, so this is called right before the library gets unloaded.
+pub(crate) fn join_stopped_worker_threads() {
+    // If this is called from multiple threads at the same time, then one of those calls may have
+    // taken the other's reaper threads. The calls are serialized so neither of them returns before
+    // those have been joined. Pools only ever lock `REAPER_THREADS`, so this can't deadlock with a
+    // pool being dropped from a worker thread.
+    static JOIN_LOCK: Mutex<()> = parking_lot::const_mutex(());
+    let _join_guard = JOIN_LOCK.lock();
+
+    let reaper_threads = std::mem::take(&mut *REAPER_THREADS.lock());
+    for reaper_thread in reaper_threads {
+        if reaper_thread.join().is_err() {
+            nih_error!("The background reaper thread panicked");
+        }
+    }
+}
+
+/// The number of worker threads that are still running for the calling thread's pool, if it has
+/// one. The returned counter keeps being updated after the pool has been shut down.
+#[cfg(test)]
+pub(crate) fn live_worker_threads() -> Option<Arc<AtomicUsize>> {
+    POOL.with(|slot| Some(slot.lock().upgrade()?.num_live_workers.clone()))
+}
+
+impl PoolShared {
+    /// Run the next task from the first instance after the previously serviced instance that has a
+    /// runnable task. Interactive tasks are always run before bulk tasks. Returns `false` if there
+    /// were no tasks to run.
+    fn run_next_task(&self, interactive_only: bool) -> bool {
+        let priorities: &[BackgroundTaskPriority] = if interactive_only {
+            &[BackgroundTaskPriority::Interactive]
+        } else {
+            &[
+                BackgroundTaskPriority::Interactive,
+                BackgroundTaskPriority::Bulk,
+            ]
+        };
+
+        let claimed = {
+            let mut instances = self.instances.lock();
+            let PoolInstances {
+                instances,
+                next_instance_idx,
+            } = &mut *instances;
+            instances.retain(|instance| instance.strong_count() > 0);
+
+            let num_instances = instances.len();
+            priorities.iter().find_map(|&priority| {
+                (0..num_instances).find_map(|offset| {
+                    let instance_idx = (*next_instance_idx + offset) % num_instances;
+                    let instance = instances[instance_idx].upgrade()?;
+                    if instance.try_claim(priority) {
+                        *next_instance_idx = (instance_idx + 1) % num_instances;
+                        Some((instance, priority))
+                    } else {
+                        None
+                    }
+                })
+            })
+        };
+
+        match claimed {
+            Some((instance, priority)) => {
+                instance.run_claimed(priority);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Block until a task may be available. Returns `false` if the pool has been dropped.
+    fn wait_for_wakeup(&self, interactive_only: bool) -> bool {
+        if interactive_only {
+            return self.interactive_wakeup_receiver.recv().is_ok();
+        }
+
+        channel::select! {
+            recv(self.interactive_wakeup_receiver) -> message => message.is_ok(),
+            recv(self.bulk_wakeup_receiver) -> message => message.is_ok(),
+        }
+    }
+}
+
+impl<T, E> InstanceQueues<T, E> {
+    fn queue(&self, priority: BackgroundTaskPriority) -> (&channel::Receiver<T>, &AtomicBool) {
+        match priority {
+            BackgroundTaskPriority::Interactive => {
+                (&self.interactive_receiver, &self.interactive_claimed)
+            }
+            BackgroundTaskPriority::Bulk => (&self.bulk_receiver, &self.bulk_claimed),
+        }
+    }
+}
+
+impl<T, E> PoolInstance for InstanceQueues<T, E>
 where
     T: Send,
     E: MainThreadExecutor<T>,
 {
+    fn try_claim(&self, priority: BackgroundTaskPriority) -> bool {
+        let (receiver, claimed) = self.queue(priority);
+        if self.cancelled.load(Ordering::Acquire)
+            || receiver.is_empty()
+            || claimed.swap(true, Ordering::AcqRel)
+        {
+            return false;
+        }
+
+        // The queue may have been emptied by another worker between the check and the claim
+        if receiver.is_empty() {
+            claimed.store(false, Ordering::Release);
+            return false;
+        }
+
+        true
+    }
+
+    fn run_claimed(&self, priority: BackgroundTaskPriority) {
+        let (receiver, claimed) = self.queue(priority);
+        if let Ok(task) = receiver.try_recv() {
+            match self.executor.upgrade() {
+                Some(executor) if !self.cancelled.load(Ordering::Acquire) => {
+                    executor.execute(task, true)
+                }
+                Some(_) => (),
+                None => nih_trace!("Received a new task but the executor is no longer alive"),
+            }
+        }
+
+        claimed.store(false, Ordering::Release);
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// The worker thread used in [`BackgroundThreadPool`] that executes tasks from all instances' task
+/// queues. Runs until the pool's wakeup channels are closed.
+fn worker_thread(shared: &PoolShared, interactive_only: bool) {
     loop {
-        match tasks_receiver.recv() {
-            Ok(Message::Task(task)) => match executor.upgrade() {
-                Some(e) => e.execute(task, true),
-                None => {
-                    nih_trace!(
-                        "Received a new task but the executor is no longer alive, shutting down \
-                         worker"
-                    );
-                    return;
+        // After running a task there may be more tasks that couldn't be claimed while it was
+        // running, so the worker only goes back to sleep once there's nothing left to do
+        if shared.run_next_task(interactive_only) {
+            continue;
+        }
+
+        if !shared.wait_for_wakeup(interactive_only) {
+            nih_trace!("The background thread pool has been dropped, shutting down worker");
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Barrier;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Counts executed tasks. Tasks are the number of milliseconds to sleep for.
+    #[derive(Default)]
+    struct SleepingExecutor {
+        num_executed: AtomicUsize,
+    }
+
+    impl MainThreadExecutor<u64> for SleepingExecutor {
+        fn execute(&self, task: u64, _is_gui_thread: bool) {
+            thread::sleep(Duration::from_millis(task));
+            self.num_executed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "Timed out");
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn pool_shuts_down_with_last_instance() {
+        static TEST_POOL: Mutex<Weak<BackgroundThreadPool>> = parking_lot::const_mutex(Weak::new());
+
+        // This simulates a host repeatedly loading and unloading a couple of plugin instances
+        for _ in 0..8 {
+            let pool = BackgroundThreadPool::get_or_spawn(&TEST_POOL);
+            assert!(Arc::ptr_eq(
+                &pool,
+                &BackgroundThreadPool::get_or_spawn(&TEST_POOL)
+            ));
+
+            let shared = Arc::downgrade(&pool.shared);
+            let num_live_workers = pool.num_live_workers.clone();
+            let executor = Arc::new(SleepingExecutor::default());
+            let instances: Vec<_> = (0..4)
+                .map(|_| BackgroundThread::new_in_pool(pool.clone(), &executor))
+                .collect();
+            drop(pool);
+
+            for instance in &instances {
+                assert!(instance.schedule(0, BackgroundTaskPriority::Interactive));
+                assert!(instance.schedule(0, BackgroundTaskPriority::Bulk));
+            }
+            wait_until(|| executor.num_executed.load(Ordering::SeqCst) == 8);
+
+            // Dropping the last instance doesn't wait for the workers, but every worker thread holds
+            // a reference to the shared state so this can only be freed once all of them have been
+            // joined
+            drop(instances);
+            assert!(TEST_POOL.lock().upgrade().is_none());
+            join_stopped_worker_threads();
+            assert_eq!(num_live_workers.load(Ordering::SeqCst), 0);
+            assert!(shared.upgrade().is_none());
+        }
+    }
+
+    #[test]
+    fn dropping_last_instance_does_not_wait_for_tasks() {
+        struct BlockingExecutor {
+            started_sender: channel::Sender<()>,
+            finish_receiver: channel::Receiver<()>,
+        }
+
+        impl MainThreadExecutor<()> for BlockingExecutor {
+            fn execute(&self, _task: (), _is_gui_thread: bool) {
+                self.started_sender.send(()).unwrap();
+                let _ = self.finish_receiver.recv_timeout(Duration::from_secs(10));
+            }
+        }
+
+        let pool = Arc::new(BackgroundThreadPool::spawn());
+        let num_live_workers = pool.num_live_workers.clone();
+        let (started_sender, started_receiver) = channel::bounded(1);
+        let (finish_sender, finish_receiver) = channel::bounded(1);
+        let executor = Arc::new(BlockingExecutor {
+            started_sender,
+            finish_receiver,
+        });
+        let instance = BackgroundThread::new_in_pool(pool, &executor);
+
+        // This simulates a host unloading the last plugin instance on its GUI thread while one of
+        // the plugin's long running tasks is still running
+        assert!(instance.schedule((), BackgroundTaskPriority::Bulk));
+        started_receiver
+            .recv_timeout(Duration::from_secs(10))
+            .expect("Timed out");
+        let drop_start = Instant::now();
+        drop(instance);
+        assert!(drop_start.elapsed() < Duration::from_secs(5));
+        assert!(num_live_workers.load(Ordering::SeqCst) > 0);
+
+        finish_sender.send(()).unwrap();
+        join_stopped_worker_threads();
+        assert_eq!(num_live_workers.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn interactive_tasks_are_not_blocked_by_bulk_tasks() {
+        let pool = Arc::new(BackgroundThreadPool::spawn());
+        let executor = Arc::new(SleepingExecutor::default());
+        let flooding_instance = BackgroundThread::new_in_pool(pool.clone(), &executor);
+        let other_executor = Arc::new(SleepingExecutor::default());
+        let other_instance = BackgroundThread::new_in_pool(pool.clone(), &other_executor);
+
+        // One instance floods the pool with slow bulk tasks, the other instance's tasks should
+        // still get through long before those have finished
+        for _ in 0..64 {
+            assert!(flooding_instance.schedule(50, BackgroundTaskPriority::Bulk));
+        }
+        assert!(other_instance.schedule(0, BackgroundTaskPriority::Interactive));
+        assert!(other_instance.schedule(0, BackgroundTaskPriority::Bulk));
+        wait_until(|| other_executor.num_executed.load(Ordering::SeqCst) == 2);
+        assert!(executor.num_executed.load(Ordering::SeqCst) < 8);
+
+        // Dropping the flooding instance cancels its queued tasks
+        drop(flooding_instance);
+        let num_executed = executor.num_executed.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(200));
+        assert!(executor.num_executed.load(Ordering::SeqCst) <= num_executed + 1);
+    }
+
+    #[test]
+    fn tasks_run_in_order_per_instance() {
+        struct OrderExecutor {
+            executed: Mutex<Vec<u64>>,
+            barrier: Barrier,
+        }
+
+        impl MainThreadExecutor<u64> for OrderExecutor {
+            fn execute(&self, task: u64, _is_gui_thread: bool) {
+                if task == 0 {
+                    self.barrier.wait();
                 }
-            },
-            Ok(Message::Shutdown) => return,
-            Err(err) => {
-                nih_trace!(
-                    "Worker thread got disconnected unexpectedly, shutting down: {}",
-                    err
-                );
-                return;
+                self.executed.lock().push(task);
             }
         }
+
+        let pool = Arc::new(BackgroundThreadPool::spawn());
+        let executor = Arc::new(OrderExecutor {
+            executed: Mutex::new(Vec::new()),
+            barrier: Barrier::new(2),
+        });
+        let instance = BackgroundThread::new_in_pool(pool.clone(), &executor);
+
+        // The first task blocks until all other tasks have been queued, so they would run out of
+        // order if tasks from the same queue could run in parallel
+        for task in 0..32 {
+            assert!(instance.schedule(task, BackgroundTaskPriority::Bulk));
+        }
+        executor.barrier.wait();
+        wait_until(|| executor.executed.lock().len() == 32);
+        assert_eq!(*executor.executed.lock(), (0..32).collect::<Vec<_>>());
     }
 }
@@ -6,7 +6,8 @@ use crossbeam::channel;
 use std::sync::{Arc, Weak};
 use std::thread::{self, JoinHandle, ThreadId};
 
-use super::{EventLoop, MainThreadExecutor};
+use super::{BackgroundThread, EventLoop, MainThreadExecutor};
+use crate::plugin::BackgroundTaskPriority;
 use crate::util::permit_alloc;
 
 /// See [`EventLoop`][super::EventLoop].
@@ -32,6 +33,11 @@ pub(crate) struct LinuxEventLoop<T, E> {
     /// A channel for waking up the worker thread and having it perform one of the tasks from
     /// [`Message`].
     tasks_sender: channel::Sender<Message<T>>,
+
+    /// A handle to the background thread pool shared by all instances. Background tasks go here so
+    /// long running tasks don't hold up the tasks scheduled with
+    /// [`schedule_gui()`][Self::schedule_gui()].
+    background_thread: BackgroundThread<T>,
 }
 
 /// A message for communicating with the worker thread.
@@ -49,6 +55,7 @@ where
 {
     fn new_and_spawn(executor: Arc<E>) -> Self {
         let (tasks_sender, tasks_receiver) = channel::bounded(super::TASK_QUEUE_CAPACITY);
+        let background_thread = BackgroundThread::new_and_spawn(executor.clone());
 
         Self {
             executor: executor.clone(),
@@ -61,22 +68,21 @@ where
                     .expect("Could not spawn worker thread"),
             ),
             tasks_sender,
+            background_thread,
         }
     }
 
     fn schedule_gui(&self, task: T) -> bool {
         if self.is_main_thread() {
-            self.executor.execute(task, true);
+            self.executor.execute_gui(task);
             true
         } else {
             self.tasks_sender.try_send(Message::Task(task)).is_ok()
         }
     }
 
-    fn schedule_background(&self, task: T) -> bool {
-        // This event loop implementation already uses a thread that's completely decoupled from the
-        // operating system's or the host's main thread, so we don't need _another_ thread here
-        self.tasks_sender.try_send(Message::Task(task)).is_ok()
+    fn schedule_background(&self, task: T, priority: BackgroundTaskPriority) -> bool {
+        self.background_thread.schedule(task, priority)
     }
 
     fn is_main_thread(&self) -> bool {
@@ -107,7 +113,7 @@ where
     loop {
         match tasks_receiver.recv() {
             Ok(Message::Task(task)) => match executor.upgrade() {
-                Some(e) => e.execute(task, true),
+                Some(e) => e.execute_gui(task),
                 None => {
                     nih_trace!(
                         "Received a new task but the executor is no longer alive, shutting down \
@@ -18,6 +18,7 @@ use windows::Win32::UI::WindowsAndMessaging::{
 };
 
 use super::{BackgroundThread, EventLoop, MainThreadExecutor};
+use crate::plugin::BackgroundTaskPriority;
 use crate::util::permit_alloc;
 
 /// The custom message ID for our notify event. If the hidden event loop window receives this, then
@@ -99,7 +100,7 @@ where
                 };
 
                 while let Ok(task) = tasks_receiver.try_recv() {
-                    executor.execute(task, true);
+                    executor.execute_gui(task);
                 }
             })
         };
@@ -137,7 +138,7 @@ where
 
     fn schedule_gui(&self, task: T) -> bool {
         if self.is_main_thread() {
-            self.executor.execute(task, true);
+            self.executor.execute_gui(task);
             true
         } else {
             let success = self.tasks_sender.try_send(task).is_ok();
@@ -153,8 +154,8 @@ where
         }
     }
 
-    fn schedule_background(&self, task: T) -> bool {
-        self.background_thread.schedule(task)
+    fn schedule_background(&self, task: T, priority: BackgroundTaskPriority) -> bool {
+        self.background_thread.schedule(task, priority)
     }
 
     fn is_main_thread(&self) -> bool {
@@ -69,10 +69,10 @@ pub fn s2v_compression_ratio() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync>
 
 /// Turn an `f32` value from voltage gain to decibels using the semantics described in
 /// [`util::gain_to_db()]. You should use either `" dB"` or `" dBFS"` for the parameter's unit.
-/// `0.0` will be formatted as `-inf`.
+/// Gain values at or below [`util::MINUS_INFINITY_GAIN`] will be formatted as `-inf`.
 pub fn v2s_f32_gain_to_db(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + Sync> {
     Arc::new(move |value| {
-        if value < util::MINUS_INFINITY_GAIN {
+        if value <= util::MINUS_INFINITY_GAIN {
             String::from("-inf")
         } else {
             // Never print -0.0 since that just looks weird and confusing
@@ -85,11 +85,23 @@ pub fn v2s_f32_gain_to_db(digits: usize) -> Arc<dyn Fn(f32) -> String + Send + S
 }
 
 /// Parse a decibel value to a linear voltage gain ratio. Handles the `dB` or `dBFS` units for you.
-/// Used in conjunction with [`v2s_f32_gain_to_db()`]. `-inf dB` will be parsed to 0.0.
+/// Used in conjunction with [`v2s_f32_gain_to_db()`]. `-inf dB` will be parsed to 0.0, and so will
+/// `-∞ dB` written with either an ASCII hyphen, a minus sign, or a non-breaking hyphen.
 pub fn s2v_f32_gain_to_db() -> Arc<dyn Fn(&str) -> Option<f32> + Send + Sync> {
     Arc::new(|string| {
-        let string = string.trim_end_matches(&[' ', 'd', 'D', 'b', 'B', 'f', 'F', 's', 'S']);
-        if string.eq_ignore_ascii_case("-inf") {
+        // The unit needs to be stripped as a whole, since `-inf` also ends with an `f`
+        let string = string.trim();
+        let string = strip_suffix_ignore_ascii_case(string, "dbfs")
+            .or_else(|| strip_suffix_ignore_ascii_case(string, "db"))
+            .unwrap_or(string)
+            .trim_end();
+
+        let is_minus_infinity = string
+            .strip_prefix(&['-', '\u{2212}', '\u{2011}'])
+            .map_or(false, |rest| {
+                rest.eq_ignore_ascii_case("inf") || rest == "\u{221e}"
+            });
+        if is_minus_infinity {
             Some(0.0)
         } else {
             string.parse().ok().map(util::db_to_gain)
@@ -305,3 +317,13 @@ pub fn s2v_bool_bypass() -> Arc<dyn Fn(&str) -> Option<bool> + Send + Sync> {
         }
     })
 }
+
+/// Strip `suffix` from the end of `string`, ignoring ASCII case.
+fn strip_suffix_ignore_ascii_case<'a>(string: &'a str, suffix: &str) -> Option<&'a str> {
+    let split_idx = string.len().checked_sub(suffix.len())?;
+    if string.is_char_boundary(split_idx) && string[split_idx..].eq_ignore_ascii_case(suffix) {
+        Some(&string[..split_idx])
+    } else {
+        None
+    }
+}
@@ -4,6 +4,9 @@ use midi_consts::channel_event as midi;
 
 pub use midi_consts::channel_event::control_change;
 
+pub mod routing;
+pub mod voice_allocator;
+
 /// Determines which note events a plugin receives.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MidiConfig {
@@ -21,6 +24,29 @@ pub enum MidiConfig {
     MidiCCs,
 }
 
+bitflags::bitflags! {
+    /// The polyphonic note expressions a plugin supports, set through
+    /// [`Plugin::SUPPORTED_NOTE_EXPRESSIONS`][crate::prelude::Plugin::SUPPORTED_NOTE_EXPRESSIONS].
+    /// VST3 hosts only offer expression lanes for these expressions, and expression events for
+    /// other expressions are not passed on to the plugin.
+    #[repr(transparent)]
+    #[derive(Default)]
+    pub struct NoteExpressions: u32 {
+        /// [`NoteEvent::PolyVolume`] events.
+        const VOLUME = 1 << 0;
+        /// [`NoteEvent::PolyPan`] events.
+        const PAN = 1 << 1;
+        /// [`NoteEvent::PolyTuning`] events.
+        const TUNING = 1 << 2;
+        /// [`NoteEvent::PolyVibrato`] events.
+        const VIBRATO = 1 << 3;
+        /// [`NoteEvent::PolyExpression`] events.
+        const EXPRESSION = 1 << 4;
+        /// [`NoteEvent::PolyBrightness`] events.
+        const BRIGHTNESS = 1 << 5;
+    }
+}
+
 /// Event for (incoming) notes. The set of supported note events depends on the value of
 /// [`Plugin::MIDI_INPUT`][crate::prelude::Plugin::MIDI_INPUT]. Also check out the
 /// [`util`][crate::util] module for convenient conversion functions.
@@ -346,6 +372,68 @@ impl NoteEvent {
         }
     }
 
+    /// Returns the event's MIDI channel, if it has any.
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            NoteEvent::NoteOn { channel, .. } => Some(*channel),
+            NoteEvent::NoteOff { channel, .. } => Some(*channel),
+            NoteEvent::Choke { channel, .. } => Some(*channel),
+            NoteEvent::VoiceTerminated { channel, .. } => Some(*channel),
+            NoteEvent::PolyModulation { .. } => None,
+            NoteEvent::MonoAutomation { .. } => None,
+            NoteEvent::PolyPressure { channel, .. } => Some(*channel),
+            NoteEvent::PolyVolume { channel, .. } => Some(*channel),
+            NoteEvent::PolyPan { channel, .. } => Some(*channel),
+            NoteEvent::PolyTuning { channel, .. } => Some(*channel),
+            NoteEvent::PolyVibrato { channel, .. } => Some(*channel),
+            NoteEvent::PolyExpression { channel, .. } => Some(*channel),
+            NoteEvent::PolyBrightness { channel, .. } => Some(*channel),
+            NoteEvent::MidiChannelPressure { channel, .. } => Some(*channel),
+            NoteEvent::MidiPitchBend { channel, .. } => Some(*channel),
+            NoteEvent::MidiCC { channel, .. } => Some(*channel),
+            NoteEvent::MidiProgramChange { channel, .. } => Some(*channel),
+        }
+    }
+
+    /// For [`NoteEvent::MidiPitchBend`] events, returns the pitch bend in semitones for a pitch
+    /// bend range of `bend_range` semitones in either direction. The plugin's configured range is
+    /// available as
+    /// [`MidiInputConfig::pitch_bend_range`][routing::MidiInputConfig::pitch_bend_range].
+    pub fn pitch_bend_semitones(&self, bend_range: f32) -> Option<f32> {
+        match self {
+            NoteEvent::MidiPitchBend { value, .. } => {
+                Some(routing::bend_to_semitones(*value, bend_range))
+            }
+            _ => None,
+        }
+    }
+
+    /// For events that refer to a single note, returns the note's effective pitch as a fractional
+    /// MIDI note number after applying `pitch_bend` semitones of channel-wide pitch bend. Tuning
+    /// expression events also include their tuning offset. `pitch_bend` can be computed with
+    /// [`pitch_bend_semitones()`][Self::pitch_bend_semitones()].
+    pub fn note_pitch(&self, pitch_bend: f32) -> Option<f32> {
+        match self {
+            NoteEvent::PolyTuning { note, tuning, .. } => Some(*note as f32 + tuning + pitch_bend),
+            NoteEvent::NoteOn { note, .. }
+            | NoteEvent::NoteOff { note, .. }
+            | NoteEvent::Choke { note, .. }
+            | NoteEvent::VoiceTerminated { note, .. }
+            | NoteEvent::PolyPressure { note, .. }
+            | NoteEvent::PolyVolume { note, .. }
+            | NoteEvent::PolyPan { note, .. }
+            | NoteEvent::PolyVibrato { note, .. }
+            | NoteEvent::PolyExpression { note, .. }
+            | NoteEvent::PolyBrightness { note, .. } => Some(*note as f32 + pitch_bend),
+            NoteEvent::PolyModulation { .. }
+            | NoteEvent::MonoAutomation { .. }
+            | NoteEvent::MidiChannelPressure { .. }
+            | NoteEvent::MidiPitchBend { .. }
+            | NoteEvent::MidiCC { .. }
+            | NoteEvent::MidiProgramChange { .. } => None,
+        }
+    }
+
     /// Parse MIDI into a [`NoteEvent`]. Will return `Err(event_type)` if the parsing failed.
     pub fn from_midi(timing: u32, midi_data: [u8; 3]) -> Result<Self, u8> {
         // TODO: Maybe add special handling for 14-bit CCs and RPN messages at some
@@ -504,24 +592,46 @@ impl NoteEvent {
     /// Subtract a sample offset from this event's timing, needed to compensate for the block
     /// splitting in the VST3 wrapper implementation because all events have to be read upfront.
     pub(crate) fn subtract_timing(&mut self, samples: u32) {
+        *self.timing_mut() -= samples;
+    }
+
+    /// Add a sample offset to this event's timing. Used to move the output events from a split
+    /// block back to their position in the host's block.
+    pub(crate) fn add_timing(&mut self, samples: u32) {
+        *self.timing_mut() += samples;
+    }
+
+    /// Multiply this event's timing by the wrapper's oversampling factor so it lines up with the
+    /// oversampled block the plugin processes.
+    pub(crate) fn multiply_timing(&mut self, factor: u32) {
+        *self.timing_mut() *= factor;
+    }
+
+    /// Divide this event's timing by the wrapper's oversampling factor. The inverse of
+    /// [`multiply_timing()`][Self::multiply_timing()] for events sent by an oversampled plugin.
+    pub(crate) fn divide_timing(&mut self, factor: u32) {
+        *self.timing_mut() /= factor;
+    }
+
+    fn timing_mut(&mut self) -> &mut u32 {
         match self {
-            NoteEvent::NoteOn { timing, .. } => *timing -= samples,
-            NoteEvent::NoteOff { timing, .. } => *timing -= samples,
-            NoteEvent::Choke { timing, .. } => *timing -= samples,
-            NoteEvent::VoiceTerminated { timing, .. } => *timing -= samples,
-            NoteEvent::PolyModulation { timing, .. } => *timing -= samples,
-            NoteEvent::MonoAutomation { timing, .. } => *timing -= samples,
-            NoteEvent::PolyPressure { timing, .. } => *timing -= samples,
-            NoteEvent::PolyVolume { timing, .. } => *timing -= samples,
-            NoteEvent::PolyPan { timing, .. } => *timing -= samples,
-            NoteEvent::PolyTuning { timing, .. } => *timing -= samples,
-            NoteEvent::PolyVibrato { timing, .. } => *timing -= samples,
-            NoteEvent::PolyExpression { timing, .. } => *timing -= samples,
-            NoteEvent::PolyBrightness { timing, .. } => *timing -= samples,
-            NoteEvent::MidiChannelPressure { timing, .. } => *timing -= samples,
-            NoteEvent::MidiPitchBend { timing, .. } => *timing -= samples,
-            NoteEvent::MidiCC { timing, .. } => *timing -= samples,
-            NoteEvent::MidiProgramChange { timing, .. } => *timing -= samples,
+            NoteEvent::NoteOn { timing, .. } => timing,
+            NoteEvent::NoteOff { timing, .. } => timing,
+            NoteEvent::Choke { timing, .. } => timing,
+            NoteEvent::VoiceTerminated { timing, .. } => timing,
+            NoteEvent::PolyModulation { timing, .. } => timing,
+            NoteEvent::MonoAutomation { timing, .. } => timing,
+            NoteEvent::PolyPressure { timing, .. } => timing,
+            NoteEvent::PolyVolume { timing, .. } => timing,
+            NoteEvent::PolyPan { timing, .. } => timing,
+            NoteEvent::PolyTuning { timing, .. } => timing,
+            NoteEvent::PolyVibrato { timing, .. } => timing,
+            NoteEvent::PolyExpression { timing, .. } => timing,
+            NoteEvent::PolyBrightness { timing, .. } => timing,
+            NoteEvent::MidiChannelPressure { timing, .. } => timing,
+            NoteEvent::MidiPitchBend { timing, .. } => timing,
+            NoteEvent::MidiCC { timing, .. } => timing,
+            NoteEvent::MidiProgramChange { timing, .. } => timing,
         }
     }
 }
@@ -637,4 +747,35 @@ mod tests {
             event
         );
     }
+
+    #[test]
+    fn test_pitch_helpers() {
+        let bend = NoteEvent::MidiPitchBend {
+            timing: TIMING,
+            channel: 1,
+            value: 0.75,
+        };
+        let pitch_bend = bend.pitch_bend_semitones(2.0).unwrap();
+        assert_eq!(pitch_bend, 1.0);
+        assert_eq!(bend.note_pitch(pitch_bend), None);
+
+        let note_on = NoteEvent::NoteOn {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 60,
+            velocity: 1.0,
+        };
+        assert_eq!(note_on.pitch_bend_semitones(2.0), None);
+        assert_eq!(note_on.note_pitch(pitch_bend), Some(61.0));
+
+        let tuning = NoteEvent::PolyTuning {
+            timing: TIMING,
+            voice_id: None,
+            channel: 1,
+            note: 60,
+            tuning: -0.5,
+        };
+        assert_eq!(tuning.note_pitch(pitch_bend), Some(60.5));
+    }
 }
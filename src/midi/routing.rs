@@ -0,0 +1,499 @@
+//! Host independent MIDI input configuration. The wrappers use a [`MidiInputRouter`] to drop events
+//! on MIDI channels the plugin doesn't respond to, and to turn the channel-wide messages on MPE
+//! member channels into polyphonic expression events for the notes playing on those channels.
+
+use super::NoteEvent;
+
+/// The number of MIDI channels.
+const NUM_CHANNELS: usize = 16;
+/// The maximum number of notes that are tracked on a single MPE member channel. MPE controllers
+/// normally only play a single note per member channel, but notes can overlap when the controller
+/// runs out of channels.
+const MAX_NOTES_PER_CHANNEL: usize = 16;
+/// The CC used for the third dimension of control in MPE, often called timbre or slide. This is
+/// translated to [`NoteEvent::PolyBrightness`] events.
+const MPE_TIMBRE_CC: u8 = 74;
+
+/// Configures how a plugin's note input is filtered and translated before it reaches
+/// [`ProcessContext::next_event()`][crate::prelude::ProcessContext::next_event()]. Set through
+/// [`Plugin::MIDI_INPUT_CONFIG`][crate::prelude::Plugin::MIDI_INPUT_CONFIG].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiInputConfig {
+    /// A bit mask containing the MIDI channels the plugin responds to, where the least significant
+    /// bit corresponds to the first channel. Events on any other channel are dropped.
+    pub channel_mask: u16,
+    /// The MPE lower zone, if any. This zone uses the first channel as its master channel, and the
+    /// channels directly after it as its member channels.
+    pub mpe_lower_zone: Option<MpeZone>,
+    /// The MPE upper zone, if any. This zone uses the last channel as its master channel, and the
+    /// channels directly before it as its member channels. If the two zones overlap, then the
+    /// lower zone takes precedence.
+    pub mpe_upper_zone: Option<MpeZone>,
+    /// The pitch bend range in semitones for regular channels and for the MPE master channels. The
+    /// wrappers don't use this themselves, but it can be passed to
+    /// [`NoteEvent::pitch_bend_semitones()`].
+    pub pitch_bend_range: f32,
+}
+
+/// An MPE zone. See [`MidiInputConfig`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpeZone {
+    /// The number of member channels in this zone, in `1..16`.
+    pub num_member_channels: u8,
+    /// The pitch bend range in semitones for the zone's member channels. This is used to convert
+    /// pitch bend on a member channel to [`NoteEvent::PolyTuning`] events.
+    pub member_pitch_bend_range: f32,
+}
+
+/// Filters and translates note events according to a [`MidiInputConfig`]. On MPE member channels,
+/// pitch bend is converted to [`NoteEvent::PolyTuning`], channel pressure to
+/// [`NoteEvent::PolyPressure`], and CC 74 to [`NoteEvent::PolyBrightness`] events for every note
+/// playing on that channel. Messages on the zones' master channels are passed through as is since
+/// they affect every note in the zone. The router doesn't allocate, so it can be used on the audio
+/// thread.
+#[derive(Debug, Clone)]
+pub struct MidiInputRouter {
+    config: MidiInputConfig,
+    /// The state for every MIDI channel. Only used for MPE member channels.
+    channels: [MemberChannel; NUM_CHANNELS],
+}
+
+/// The state for an MPE member channel.
+#[derive(Debug, Clone, Copy)]
+struct MemberChannel {
+    /// The notes currently playing on this channel, as `(note, voice_id)` pairs.
+    notes: [Option<(u8, Option<i32>)>; MAX_NOTES_PER_CHANNEL],
+    /// The channel's last pitch bend value, in `[0, 1]`. Notes started while the channel is bent
+    /// start at that tuning.
+    pitch_bend: f32,
+}
+
+impl Default for MidiInputConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl MidiInputConfig {
+    /// Respond to all channels without any MPE handling, with a pitch bend range of two semitones.
+    pub const DEFAULT: Self = Self {
+        channel_mask: u16::MAX,
+        mpe_lower_zone: None,
+        mpe_upper_zone: None,
+        pitch_bend_range: 2.0,
+    };
+
+    /// Only respond to a single MIDI channel, in `0..16`.
+    pub const fn single_channel(channel: u8) -> Self {
+        Self {
+            channel_mask: 1 << channel,
+            ..Self::DEFAULT
+        }
+    }
+
+    /// Use `zone` as the MPE lower zone.
+    pub const fn with_mpe_lower_zone(mut self, zone: MpeZone) -> Self {
+        self.mpe_lower_zone = Some(zone);
+        self
+    }
+
+    /// Use `zone` as the MPE upper zone.
+    pub const fn with_mpe_upper_zone(mut self, zone: MpeZone) -> Self {
+        self.mpe_upper_zone = Some(zone);
+        self
+    }
+
+    /// Set the pitch bend range in semitones for regular channels and MPE master channels.
+    pub const fn with_pitch_bend_range(mut self, semitones: f32) -> Self {
+        self.pitch_bend_range = semitones;
+        self
+    }
+
+    /// Whether the plugin responds to events on `channel`.
+    pub const fn accepts_channel(&self, channel: u8) -> bool {
+        (channel as usize) < NUM_CHANNELS && self.channel_mask & (1 << channel) != 0
+    }
+
+    /// Get the zone `channel` is a member channel of, if any.
+    pub fn member_zone(&self, channel: u8) -> Option<&MpeZone> {
+        let lower_zone = self.mpe_lower_zone.as_ref().filter(|zone| {
+            (1..=zone.num_member_channels.min(NUM_CHANNELS as u8 - 1)).contains(&channel)
+        });
+        if lower_zone.is_some() {
+            return lower_zone;
+        }
+
+        self.mpe_upper_zone.as_ref().filter(|zone| {
+            let last_member_channel = NUM_CHANNELS as u8 - 2;
+            let first_member_channel =
+                last_member_channel.saturating_sub(zone.num_member_channels.saturating_sub(1));
+            (first_member_channel..=last_member_channel).contains(&channel)
+        })
+    }
+}
+
+impl MpeZone {
+    /// A zone with `num_member_channels` member channels, in `1..16`, and MPE's default member
+    /// channel pitch bend range of 48 semitones.
+    pub const fn new(num_member_channels: u8) -> Self {
+        Self {
+            num_member_channels,
+            member_pitch_bend_range: 48.0,
+        }
+    }
+
+    /// Change the member channels' pitch bend range.
+    pub const fn with_member_pitch_bend_range(mut self, semitones: f32) -> Self {
+        self.member_pitch_bend_range = semitones;
+        self
+    }
+}
+
+impl Default for MemberChannel {
+    fn default() -> Self {
+        Self {
+            notes: [None; MAX_NOTES_PER_CHANNEL],
+            pitch_bend: 0.5,
+        }
+    }
+}
+
+impl MidiInputRouter {
+    /// Create a router for the specified configuration.
+    pub fn new(config: MidiInputConfig) -> Self {
+        Self {
+            config,
+            channels: [MemberChannel::default(); NUM_CHANNELS],
+        }
+    }
+
+    /// The configuration this router was created with.
+    pub fn config(&self) -> &MidiInputConfig {
+        &self.config
+    }
+
+    /// Forget about all playing notes and reset the member channels' pitch bend. This should be
+    /// called when the plugin gets reset.
+    pub fn reset(&mut self) {
+        self.channels = [MemberChannel::default(); NUM_CHANNELS];
+    }
+
+    /// Filter and translate `event`, and pass the resulting events to `output`. This may output
+    /// zero, one, or multiple events. All output events have the same timing as `event`.
+    pub fn route(&mut self, event: NoteEvent, mut output: impl FnMut(NoteEvent)) {
+        let channel = match event.channel() {
+            Some(channel) => channel,
+            None => {
+                output(event);
+                return;
+            }
+        };
+        if !self.config.accepts_channel(channel) {
+            return;
+        }
+        let member_pitch_bend_range = match self.config.member_zone(channel) {
+            Some(zone) => zone.member_pitch_bend_range,
+            None => {
+                output(event);
+                return;
+            }
+        };
+
+        let state = &mut self.channels[channel as usize];
+        match event {
+            NoteEvent::NoteOn {
+                timing,
+                voice_id,
+                channel,
+                note,
+                ..
+            } => {
+                // If the note somehow got retriggered without a note off, then it will only be
+                // tracked once
+                state.remove_note(note);
+                nih_debug_assert!(
+                    state.notes.iter().any(Option::is_none),
+                    "Too many overlapping notes on MPE member channel {}",
+                    channel
+                );
+                if let Some(slot) = state.notes.iter_mut().find(|slot| slot.is_none()) {
+                    *slot = Some((note, voice_id));
+                }
+
+                output(event);
+                if state.pitch_bend != 0.5 {
+                    output(NoteEvent::PolyTuning {
+                        timing,
+                        voice_id,
+                        channel,
+                        note,
+                        tuning: bend_to_semitones(state.pitch_bend, member_pitch_bend_range),
+                    });
+                }
+            }
+            NoteEvent::NoteOff { note, .. } | NoteEvent::Choke { note, .. } => {
+                state.remove_note(note);
+                output(event);
+            }
+            NoteEvent::MidiPitchBend { timing, value, .. } => {
+                state.pitch_bend = value;
+                let tuning = bend_to_semitones(value, member_pitch_bend_range);
+                for (note, voice_id) in state.notes.iter().flatten().copied() {
+                    output(NoteEvent::PolyTuning {
+                        timing,
+                        voice_id,
+                        channel,
+                        note,
+                        tuning,
+                    });
+                }
+            }
+            NoteEvent::MidiChannelPressure {
+                timing, pressure, ..
+            } => {
+                for (note, voice_id) in state.notes.iter().flatten().copied() {
+                    output(NoteEvent::PolyPressure {
+                        timing,
+                        voice_id,
+                        channel,
+                        note,
+                        pressure,
+                    });
+                }
+            }
+            NoteEvent::MidiCC {
+                timing,
+                cc: MPE_TIMBRE_CC,
+                value,
+                ..
+            } => {
+                for (note, voice_id) in state.notes.iter().flatten().copied() {
+                    output(NoteEvent::PolyBrightness {
+                        timing,
+                        voice_id,
+                        channel,
+                        note,
+                        brightness: value,
+                    });
+                }
+            }
+            event => output(event),
+        }
+    }
+}
+
+impl MemberChannel {
+    fn remove_note(&mut self, note: u8) {
+        for slot in &mut self.notes {
+            if matches!(slot, Some((slot_note, _)) if *slot_note == note) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+/// Convert a normalized pitch bend value in `[0, 1]` to semitones.
+pub(super) fn bend_to_semitones(value: f32, bend_range: f32) -> f32 {
+    (value * 2.0 - 1.0) * bend_range
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: u32 = 5;
+
+    /// Route all events and collect the output.
+    fn route(router: &mut MidiInputRouter, events: &[NoteEvent]) -> Vec<NoteEvent> {
+        let mut output = Vec::new();
+        for event in events {
+            router.route(*event, |event| output.push(event));
+        }
+
+        output
+    }
+
+    fn note_on(channel: u8, note: u8) -> NoteEvent {
+        NoteEvent::NoteOn {
+            timing: TIMING,
+            voice_id: None,
+            channel,
+            note,
+            velocity: 1.0,
+        }
+    }
+
+    fn note_off(channel: u8, note: u8) -> NoteEvent {
+        NoteEvent::NoteOff {
+            timing: TIMING,
+            voice_id: None,
+            channel,
+            note,
+            velocity: 0.0,
+        }
+    }
+
+    fn pitch_bend(channel: u8, value: f32) -> NoteEvent {
+        NoteEvent::MidiPitchBend {
+            timing: TIMING,
+            channel,
+            value,
+        }
+    }
+
+    fn tuning(channel: u8, note: u8, tuning: f32) -> NoteEvent {
+        NoteEvent::PolyTuning {
+            timing: TIMING,
+            voice_id: None,
+            channel,
+            note,
+            tuning,
+        }
+    }
+
+    #[test]
+    fn channel_filter() {
+        let mut router = MidiInputRouter::new(MidiInputConfig::single_channel(2));
+        let automation = NoteEvent::MonoAutomation {
+            timing: TIMING,
+            poly_modulation_id: 0,
+            normalized_value: 0.5,
+        };
+
+        assert_eq!(
+            route(
+                &mut router,
+                &[
+                    note_on(0, 60),
+                    note_on(2, 61),
+                    pitch_bend(3, 0.0),
+                    automation
+                ]
+            ),
+            [note_on(2, 61), automation]
+        );
+    }
+
+    #[test]
+    fn no_mpe() {
+        let mut router = MidiInputRouter::new(MidiInputConfig::DEFAULT);
+        let events = [note_on(1, 60), pitch_bend(1, 1.0), note_off(1, 60)];
+
+        assert_eq!(route(&mut router, &events), events);
+    }
+
+    #[test]
+    fn mpe_note_lifecycle() {
+        let mut router =
+            MidiInputRouter::new(MidiInputConfig::DEFAULT.with_mpe_lower_zone(MpeZone::new(15)));
+
+        // Bend sent before the note starts applies to the new note
+        assert_eq!(route(&mut router, &[pitch_bend(1, 0.75)]), []);
+        assert_eq!(
+            route(&mut router, &[note_on(1, 60)]),
+            [note_on(1, 60), tuning(1, 60, 24.0)]
+        );
+        assert_eq!(
+            route(
+                &mut router,
+                &[
+                    pitch_bend(1, 0.5),
+                    NoteEvent::MidiChannelPressure {
+                        timing: TIMING,
+                        channel: 1,
+                        pressure: 0.25,
+                    },
+                    NoteEvent::MidiCC {
+                        timing: TIMING,
+                        channel: 1,
+                        cc: MPE_TIMBRE_CC,
+                        value: 0.75,
+                    },
+                    note_off(1, 60),
+                ]
+            ),
+            [
+                tuning(1, 60, 0.0),
+                NoteEvent::PolyPressure {
+                    timing: TIMING,
+                    voice_id: None,
+                    channel: 1,
+                    note: 60,
+                    pressure: 0.25,
+                },
+                NoteEvent::PolyBrightness {
+                    timing: TIMING,
+                    voice_id: None,
+                    channel: 1,
+                    note: 60,
+                    brightness: 0.75,
+                },
+                note_off(1, 60),
+            ]
+        );
+
+        // Without any playing notes, channel-wide messages have nothing to apply to
+        assert_eq!(route(&mut router, &[pitch_bend(1, 0.25)]), []);
+    }
+
+    #[test]
+    fn mpe_overlapping_notes() {
+        let mut router =
+            MidiInputRouter::new(MidiInputConfig::DEFAULT.with_mpe_lower_zone(MpeZone::new(3)));
+
+        assert_eq!(
+            route(
+                &mut router,
+                &[note_on(2, 60), note_on(2, 64), pitch_bend(2, 1.0)]
+            ),
+            [
+                note_on(2, 60),
+                note_on(2, 64),
+                tuning(2, 60, 48.0),
+                tuning(2, 64, 48.0),
+            ]
+        );
+        assert_eq!(
+            route(&mut router, &[note_off(2, 60), pitch_bend(2, 0.0)]),
+            [note_off(2, 60), tuning(2, 64, -48.0)]
+        );
+
+        router.reset();
+        assert_eq!(route(&mut router, &[pitch_bend(2, 1.0)]), []);
+    }
+
+    #[test]
+    fn mpe_zone_master_messages() {
+        let mut router = MidiInputRouter::new(
+            MidiInputConfig::DEFAULT
+                .with_mpe_lower_zone(MpeZone::new(3))
+                .with_mpe_upper_zone(MpeZone::new(4)),
+        );
+        assert!(router.config().member_zone(0).is_none());
+        assert!(router.config().member_zone(3).is_some());
+        assert!(router.config().member_zone(4).is_none());
+        assert!(router.config().member_zone(10).is_none());
+        assert!(router.config().member_zone(11).is_some());
+        assert!(router.config().member_zone(14).is_some());
+        assert!(router.config().member_zone(15).is_none());
+
+        // The master channels and the channels outside of the zones are passed through as is
+        let events = [
+            note_on(1, 60),
+            pitch_bend(0, 1.0),
+            pitch_bend(15, 0.0),
+            note_on(7, 62),
+            pitch_bend(7, 1.0),
+        ];
+        assert_eq!(
+            route(&mut router, &events),
+            [
+                note_on(1, 60),
+                pitch_bend(0, 1.0),
+                pitch_bend(15, 0.0),
+                note_on(7, 62),
+                pitch_bend(7, 1.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,726 @@
+//! A voice allocator for polyphonic and monophonic instruments. This keeps track of which note is
+//! playing on which voice so a plugin only needs to store its own voice data in an array with the
+//! same capacity as the allocator.
+
+use super::NoteEvent;
+
+/// The number of MIDI channels sustain pedal states are tracked for.
+const NUM_CHANNELS: usize = 16;
+/// The maximum number of held keys that are remembered in monophonic mode. Enough for every key on
+/// a keyboard.
+const MAX_HELD_NOTES: usize = 128;
+
+/// Keeps track of which notes are playing on which voices. All memory is allocated up front when
+/// creating the allocator, so it can be used from the audio thread. The plugin should store its
+/// per-voice data in an array with [`capacity()`][Self::capacity()] elements, and use the voice
+/// indices returned from the allocator to index into that array.
+///
+/// Whenever a voice gets stolen or terminated, the plugin is responsible for sending a
+/// [`NoteEvent::VoiceTerminated`] event for that voice if it uses polyphonic modulation.
+#[derive(Debug, Clone)]
+pub struct VoiceAllocator {
+    /// The voices, `None` if the voice is not in use.
+    voices: Vec<Option<Voice>>,
+    /// How to choose a voice to reuse when all voices are in use. Only used for polyphonic
+    /// allocators.
+    stealing: VoiceStealing,
+    /// Whether this allocator is monophonic, and if so, whether new notes should glide into each
+    /// other.
+    mode: AllocatorMode,
+    /// Whether the sustain pedal is currently held down on a channel.
+    sustain_pedal: [bool; NUM_CHANNELS],
+    /// Incremented every time a voice is started. Used to find the oldest voice.
+    next_age: u64,
+    /// The keys that are currently held down in the order they were pressed, used in monophonic
+    /// mode to return to the previous note when a key gets released.
+    held_notes: Vec<HeldNote>,
+}
+
+/// Determines which voice gets reused for a new note when all voices are in use. Voices that are
+/// already releasing are always stolen before voices for keys that are still being held down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceStealing {
+    /// Reuse the oldest voice.
+    Oldest,
+    /// Reuse the voice with the lowest amplitude, as set with
+    /// [`VoiceAllocator::set_amplitude()`]. Voices start out with their velocity as their
+    /// amplitude.
+    Quietest,
+    /// If the same note is already playing on the same channel, then that voice is retriggered.
+    /// Otherwise this works the same as [`Oldest`][Self::Oldest].
+    SameNoteRetrigger,
+}
+
+/// The state of a voice, see [`Voice::state`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VoiceState {
+    /// The key for this voice is still held down.
+    Held,
+    /// The key has been released, but the sustain pedal is keeping the voice alive.
+    Sustained,
+    /// The voice has been released and should now fade out. Call
+    /// [`VoiceAllocator::voice_terminated()`] when it has finished playing.
+    Releasing,
+}
+
+/// Information about a voice that's currently in use.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Voice {
+    /// The voice's unique identifier, if the host provided one.
+    pub voice_id: Option<i32>,
+    /// The note's channel, in `0..16`.
+    pub channel: u8,
+    /// The note's MIDI key number, in `0..128`.
+    pub note: u8,
+    /// The note's velocity, in `[0, 1]`.
+    pub velocity: f32,
+    /// The voice's current amplitude, used for [`VoiceStealing::Quietest`].
+    pub amplitude: f32,
+    /// Whether the voice is still held, sustained, or releasing.
+    pub state: VoiceState,
+    /// Used to determine the voice's age. Higher values are newer.
+    age: u64,
+}
+
+/// The voice a new note has been assigned to. Returned from [`VoiceAllocator::note_on()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoiceSlot {
+    /// The index of the voice the note should be played on.
+    pub index: usize,
+    /// The voice that previously used this slot, if it was still in use. This voice should be
+    /// stopped immediately, and a [`NoteEvent::VoiceTerminated`] event should be sent for it when
+    /// using polyphonic modulation.
+    pub stolen: Option<Voice>,
+    /// Only used in monophonic mode with legato enabled. If this is set, then the voice should
+    /// glide to the new note without retriggering its envelopes.
+    pub legato: bool,
+}
+
+/// What to do after a note off event. Returned from [`VoiceAllocator::note_off()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteOffResult {
+    /// The note off event doesn't require any action, either because the note isn't playing
+    /// anymore or because the sustain pedal is held down.
+    Ignored,
+    /// The voice at this index should start releasing.
+    Release(usize),
+    /// Only used in monophonic mode. The released key was the one that was playing, but other
+    /// keys are still being held down. The voice should switch to the most recently pressed key
+    /// that's still held, as described by this slot. The `stolen` field contains the voice for the
+    /// released key.
+    Resume(VoiceSlot),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AllocatorMode {
+    Polyphonic,
+    Monophonic { legato: bool },
+}
+
+/// A key that's held down in monophonic mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HeldNote {
+    voice_id: Option<i32>,
+    channel: u8,
+    note: u8,
+    velocity: f32,
+}
+
+impl VoiceAllocator {
+    /// Create a polyphonic voice allocator with room for `capacity` voices.
+    pub fn new(capacity: usize, stealing: VoiceStealing) -> Self {
+        nih_debug_assert!(capacity > 0, "A voice allocator needs at least one voice");
+
+        Self {
+            voices: vec![None; capacity.max(1)],
+            stealing,
+            mode: AllocatorMode::Polyphonic,
+            sustain_pedal: [false; NUM_CHANNELS],
+            next_age: 0,
+            held_notes: Vec::new(),
+        }
+    }
+
+    /// Create a monophonic voice allocator with last note priority. When a key is released while
+    /// other keys are still held down, the voice returns to the most recently pressed key that's
+    /// still held. If `legato` is set, then new notes played while a key is still held down glide
+    /// into each other instead of retriggering the voice.
+    pub fn new_monophonic(legato: bool) -> Self {
+        Self {
+            voices: vec![None; 1],
+            stealing: VoiceStealing::Oldest,
+            mode: AllocatorMode::Monophonic { legato },
+            sustain_pedal: [false; NUM_CHANNELS],
+            next_age: 0,
+            held_notes: Vec::with_capacity(MAX_HELD_NOTES),
+        }
+    }
+
+    /// The number of voices this allocator can hand out.
+    pub fn capacity(&self) -> usize {
+        self.voices.len()
+    }
+
+    /// Get the voice at an index, if it's in use.
+    pub fn voice(&self, index: usize) -> Option<&Voice> {
+        self.voices.get(index).and_then(Option::as_ref)
+    }
+
+    /// Iterate over all voices that are currently in use, along with their indices.
+    pub fn active_voices(&self) -> impl Iterator<Item = (usize, &Voice)> + '_ {
+        self.voices
+            .iter()
+            .enumerate()
+            .filter_map(|(index, voice)| voice.as_ref().map(|voice| (index, voice)))
+    }
+
+    /// Assign a voice to a new note. The voice's previous occupant, if any, is returned as part of
+    /// the [`VoiceSlot`].
+    pub fn note_on(
+        &mut self,
+        note: u8,
+        channel: u8,
+        velocity: f32,
+        voice_id: Option<i32>,
+    ) -> VoiceSlot {
+        let voice = self.new_voice(voice_id, channel, note, velocity);
+        match self.mode {
+            AllocatorMode::Polyphonic => {
+                let retrigger_index = if self.stealing == VoiceStealing::SameNoteRetrigger {
+                    self.voices.iter().position(|voice| {
+                        matches!(voice, Some(voice) if voice.channel == channel && voice.note == note)
+                    })
+                } else {
+                    None
+                };
+                let index = retrigger_index
+                    .or_else(|| self.voices.iter().position(Option::is_none))
+                    .unwrap_or_else(|| self.steal_index());
+
+                VoiceSlot {
+                    index,
+                    stolen: self.voices[index].replace(voice),
+                    legato: false,
+                }
+            }
+            AllocatorMode::Monophonic { legato } => {
+                self.remove_held_note(voice_id, channel, note);
+                if self.held_notes.len() == MAX_HELD_NOTES {
+                    self.held_notes.remove(0);
+                }
+                self.held_notes.push(HeldNote {
+                    voice_id,
+                    channel,
+                    note,
+                    velocity,
+                });
+
+                let stolen = self.voices[0].replace(voice);
+                VoiceSlot {
+                    index: 0,
+                    stolen,
+                    legato: legato
+                        && matches!(
+                            stolen,
+                            Some(Voice {
+                                state: VoiceState::Held,
+                                ..
+                            })
+                        ),
+                }
+            }
+        }
+    }
+
+    /// Handle a note off event. If the host provided a voice ID then that is used to find the
+    /// voice, otherwise the note and channel are used. See [`NoteOffResult`] for what the plugin
+    /// should do afterwards.
+    pub fn note_off(&mut self, note: u8, channel: u8, voice_id: Option<i32>) -> NoteOffResult {
+        if let AllocatorMode::Monophonic { legato } = self.mode {
+            self.remove_held_note(voice_id, channel, note);
+
+            let current_voice = match self.voices[0] {
+                Some(voice)
+                    if voice.state == VoiceState::Held
+                        && voice.matches(voice_id, channel, note) =>
+                {
+                    voice
+                }
+                _ => return NoteOffResult::Ignored,
+            };
+            if let Some(held_note) = self.held_notes.last().copied() {
+                let voice = self.new_voice(
+                    held_note.voice_id,
+                    held_note.channel,
+                    held_note.note,
+                    held_note.velocity,
+                );
+                self.voices[0] = Some(voice);
+
+                return NoteOffResult::Resume(VoiceSlot {
+                    index: 0,
+                    stolen: Some(current_voice),
+                    legato,
+                });
+            }
+        }
+
+        match self.find_voice(voice_id, channel, note) {
+            Some(index) => self.release_voice(index),
+            None => NoteOffResult::Ignored,
+        }
+    }
+
+    /// Immediately stop a voice in response to a [`NoteEvent::Choke`] event. Returns the index and
+    /// the voice that was stopped, if the note was playing.
+    pub fn choke(
+        &mut self,
+        note: u8,
+        channel: u8,
+        voice_id: Option<i32>,
+    ) -> Option<(usize, Voice)> {
+        if let AllocatorMode::Monophonic { .. } = self.mode {
+            self.remove_held_note(voice_id, channel, note);
+        }
+
+        let index = self.find_voice(voice_id, channel, note)?;
+        self.voices[index].take().map(|voice| (index, voice))
+    }
+
+    /// Update the sustain pedal's state for a channel. This is MIDI CC 64, where values of 0.5 and
+    /// above mean the pedal is held down. When the pedal is released, `release` is called with the
+    /// index of every voice that was being kept alive by the pedal. Those voices should now start
+    /// releasing.
+    pub fn set_sustain_pedal(&mut self, channel: u8, held: bool, mut release: impl FnMut(usize)) {
+        let channel_idx = channel as usize % NUM_CHANNELS;
+        self.sustain_pedal[channel_idx] = held;
+        if held {
+            return;
+        }
+
+        for (index, voice) in self.voices.iter_mut().enumerate() {
+            if let Some(voice) = voice {
+                if voice.channel == channel && voice.state == VoiceState::Sustained {
+                    voice.state = VoiceState::Releasing;
+                    release(index);
+                }
+            }
+        }
+    }
+
+    /// Update a voice's amplitude for [`VoiceStealing::Quietest`].
+    pub fn set_amplitude(&mut self, index: usize, amplitude: f32) {
+        if let Some(Some(voice)) = self.voices.get_mut(index) {
+            voice.amplitude = amplitude;
+        }
+    }
+
+    /// Mark a voice as finished, for instance because its release envelope has ended. The voice
+    /// can be reused afterwards. Returns the voice that was terminated, if it was in use.
+    pub fn voice_terminated(&mut self, index: usize) -> Option<Voice> {
+        self.voices.get_mut(index).and_then(Option::take)
+    }
+
+    /// Find the voice a polyphonic note expression or polyphonic modulation event belongs to.
+    /// Returns `None` for other events or if the voice isn't playing anymore.
+    pub fn voice_for_event(&self, event: &NoteEvent) -> Option<usize> {
+        match *event {
+            NoteEvent::PolyPressure {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyVolume {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyPan {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyTuning {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyVibrato {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyExpression {
+                voice_id,
+                channel,
+                note,
+                ..
+            }
+            | NoteEvent::PolyBrightness {
+                voice_id,
+                channel,
+                note,
+                ..
+            } => self.find_voice(voice_id, channel, note),
+            NoteEvent::PolyModulation { voice_id, .. } => self
+                .voices
+                .iter()
+                .position(|voice| matches!(voice, Some(voice) if voice.voice_id == Some(voice_id))),
+            _ => None,
+        }
+    }
+
+    /// Stop all voices and forget about any held keys and sustain pedals.
+    pub fn reset(&mut self) {
+        self.voices.fill(None);
+        self.sustain_pedal = [false; NUM_CHANNELS];
+        self.held_notes.clear();
+    }
+
+    fn new_voice(&mut self, voice_id: Option<i32>, channel: u8, note: u8, velocity: f32) -> Voice {
+        let age = self.next_age;
+        self.next_age += 1;
+
+        Voice {
+            voice_id,
+            channel,
+            note,
+            velocity,
+            amplitude: velocity,
+            state: VoiceState::Held,
+            age,
+        }
+    }
+
+    /// Find a voice by its voice ID, falling back to matching the note and channel if the host did
+    /// not provide a voice ID or if no voice with that ID exists. Voices that are still held are
+    /// preferred when matching by note.
+    fn find_voice(&self, voice_id: Option<i32>, channel: u8, note: u8) -> Option<usize> {
+        if let Some(voice_id) = voice_id {
+            let index = self
+                .voices
+                .iter()
+                .position(|voice| matches!(voice, Some(voice) if voice.voice_id == Some(voice_id)));
+            if index.is_some() {
+                return index;
+            }
+        }
+
+        let matches_note = |voice: &Option<Voice>| matches!(voice, Some(voice) if voice.channel == channel && voice.note == note);
+        self.voices
+            .iter()
+            .position(|voice| {
+                matches_note(voice)
+                    && matches!(
+                        voice,
+                        Some(Voice {
+                            state: VoiceState::Held,
+                            ..
+                        })
+                    )
+            })
+            .or_else(|| self.voices.iter().position(matches_note))
+    }
+
+    /// Release a held voice, or mark it as sustained if the sustain pedal is held down.
+    fn release_voice(&mut self, index: usize) -> NoteOffResult {
+        let voice = match &mut self.voices[index] {
+            Some(voice) if voice.state == VoiceState::Held => voice,
+            _ => return NoteOffResult::Ignored,
+        };
+
+        if self.sustain_pedal[voice.channel as usize % NUM_CHANNELS] {
+            voice.state = VoiceState::Sustained;
+            NoteOffResult::Ignored
+        } else {
+            voice.state = VoiceState::Releasing;
+            NoteOffResult::Release(index)
+        }
+    }
+
+    /// Find the voice to steal when all voices are in use. Releasing voices are stolen first.
+    fn steal_index(&self) -> usize {
+        let any_releasing = self
+            .voices
+            .iter()
+            .flatten()
+            .any(|voice| voice.state == VoiceState::Releasing);
+        let candidates = self
+            .voices
+            .iter()
+            .enumerate()
+            .filter_map(|(index, voice)| voice.as_ref().map(|voice| (index, voice)))
+            .filter(|(_, voice)| !any_releasing || voice.state == VoiceState::Releasing);
+
+        let stolen = match self.stealing {
+            VoiceStealing::Quietest => candidates.min_by(|(_, a), (_, b)| {
+                a.amplitude.total_cmp(&b.amplitude).then(a.age.cmp(&b.age))
+            }),
+            VoiceStealing::Oldest | VoiceStealing::SameNoteRetrigger => {
+                candidates.min_by_key(|(_, voice)| voice.age)
+            }
+        };
+
+        stolen.map(|(index, _)| index).unwrap_or(0)
+    }
+
+    fn remove_held_note(&mut self, voice_id: Option<i32>, channel: u8, note: u8) {
+        self.held_notes
+            .retain(|held_note| match (voice_id, held_note.voice_id) {
+                (Some(voice_id), Some(held_voice_id)) => voice_id != held_voice_id,
+                _ => !(held_note.channel == channel && held_note.note == note),
+            });
+    }
+}
+
+impl Voice {
+    /// Whether this voice belongs to a note. Voice IDs are compared if both sides have one,
+    /// otherwise the note and channel are compared.
+    fn matches(&self, voice_id: Option<i32>, channel: u8, note: u8) -> bool {
+        match (voice_id, self.voice_id) {
+            (Some(voice_id), Some(own_voice_id)) => voice_id == own_voice_id,
+            _ => self.channel == channel && self.note == note,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release_all_sustained(allocator: &mut VoiceAllocator, channel: u8) -> Vec<usize> {
+        let mut released = Vec::new();
+        allocator.set_sustain_pedal(channel, false, |index| released.push(index));
+        released
+    }
+
+    #[test]
+    fn allocate_and_release() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        let a = allocator.note_on(60, 0, 1.0, None);
+        let b = allocator.note_on(64, 0, 1.0, None);
+        assert_eq!((a.index, a.stolen), (0, None));
+        assert_eq!((b.index, b.stolen), (1, None));
+
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Release(0));
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Ignored);
+        assert_eq!(allocator.voice(0).unwrap().state, VoiceState::Releasing);
+
+        assert_eq!(allocator.voice_terminated(0).unwrap().note, 60);
+        assert_eq!(allocator.active_voices().count(), 1);
+        assert_eq!(allocator.note_on(67, 0, 1.0, None).index, 0);
+    }
+
+    #[test]
+    fn note_off_by_voice_id() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        // Two overlapping voices for the same key can only be told apart by their IDs
+        allocator.note_on(60, 0, 1.0, Some(10));
+        allocator.note_on(60, 0, 1.0, Some(11));
+
+        assert_eq!(
+            allocator.note_off(60, 0, Some(11)),
+            NoteOffResult::Release(1)
+        );
+        assert_eq!(allocator.voice(0).unwrap().state, VoiceState::Held);
+        // Hosts like Bitwig don't send voice IDs with note offs
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Release(0));
+    }
+
+    #[test]
+    fn steal_oldest() {
+        let mut allocator = VoiceAllocator::new(2, VoiceStealing::Oldest);
+        allocator.note_on(60, 0, 1.0, None);
+        allocator.note_on(62, 0, 1.0, None);
+        let slot = allocator.note_on(64, 0, 1.0, None);
+        assert_eq!(slot.index, 0);
+        assert_eq!(slot.stolen.unwrap().note, 60);
+
+        // Releasing voices are stolen first, even if they're newer
+        assert_eq!(allocator.note_off(64, 0, None), NoteOffResult::Release(0));
+        let slot = allocator.note_on(65, 0, 1.0, None);
+        assert_eq!(slot.index, 0);
+        assert_eq!(slot.stolen.unwrap().note, 64);
+    }
+
+    #[test]
+    fn steal_quietest() {
+        let mut allocator = VoiceAllocator::new(3, VoiceStealing::Quietest);
+        allocator.note_on(60, 0, 1.0, None);
+        allocator.note_on(62, 0, 0.2, None);
+        allocator.note_on(64, 0, 0.8, None);
+        allocator.set_amplitude(2, 0.1);
+
+        let slot = allocator.note_on(65, 0, 1.0, None);
+        assert_eq!(slot.index, 2);
+        assert_eq!(slot.stolen.unwrap().note, 64);
+    }
+
+    #[test]
+    fn same_note_retrigger() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::SameNoteRetrigger);
+        allocator.note_on(60, 0, 1.0, None);
+        allocator.note_on(62, 0, 1.0, None);
+
+        let slot = allocator.note_on(62, 0, 0.5, None);
+        assert_eq!(slot.index, 1);
+        assert_eq!(slot.stolen.unwrap().velocity, 1.0);
+        // The same note on another channel is a different note
+        assert_eq!(allocator.note_on(62, 1, 1.0, None).index, 2);
+    }
+
+    #[test]
+    fn sustain_pedal() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        allocator.note_on(60, 0, 1.0, None);
+        allocator.note_on(60, 1, 1.0, None);
+        allocator.set_sustain_pedal(0, true, |_| panic!("Nothing should be released"));
+
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Ignored);
+        assert_eq!(allocator.voice(0).unwrap().state, VoiceState::Sustained);
+        // The pedal only affects its own channel
+        assert_eq!(allocator.note_off(60, 1, None), NoteOffResult::Release(1));
+
+        assert_eq!(release_all_sustained(&mut allocator, 0), [0]);
+        assert_eq!(allocator.voice(0).unwrap().state, VoiceState::Releasing);
+        assert_eq!(
+            release_all_sustained(&mut allocator, 0),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn sustained_note_retriggered() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        allocator.set_sustain_pedal(0, true, |_| ());
+        allocator.note_on(60, 0, 1.0, None);
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Ignored);
+
+        // Playing the same key again while it's being sustained starts a new voice, and releasing
+        // that key should affect the new voice and not the sustained one
+        assert_eq!(allocator.note_on(60, 0, 1.0, None).index, 1);
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Ignored);
+        assert_eq!(allocator.voice(1).unwrap().state, VoiceState::Sustained);
+        assert_eq!(release_all_sustained(&mut allocator, 0), [0, 1]);
+    }
+
+    #[test]
+    fn route_note_expressions() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        allocator.note_on(60, 0, 1.0, Some(10));
+        allocator.note_on(64, 2, 1.0, None);
+
+        let pressure = NoteEvent::PolyPressure {
+            timing: 0,
+            voice_id: None,
+            channel: 2,
+            note: 64,
+            pressure: 0.5,
+        };
+        let tuning = NoteEvent::PolyTuning {
+            timing: 0,
+            voice_id: Some(10),
+            channel: 0,
+            note: 60,
+            tuning: 1.0,
+        };
+        let modulation = NoteEvent::PolyModulation {
+            timing: 0,
+            voice_id: 10,
+            poly_modulation_id: 0,
+            normalized_offset: 0.1,
+        };
+        let unknown_voice = NoteEvent::PolyModulation {
+            timing: 0,
+            voice_id: 11,
+            poly_modulation_id: 0,
+            normalized_offset: 0.1,
+        };
+        let pitch_bend = NoteEvent::MidiPitchBend {
+            timing: 0,
+            channel: 0,
+            value: 0.5,
+        };
+
+        assert_eq!(allocator.voice_for_event(&pressure), Some(1));
+        assert_eq!(allocator.voice_for_event(&tuning), Some(0));
+        assert_eq!(allocator.voice_for_event(&modulation), Some(0));
+        assert_eq!(allocator.voice_for_event(&unknown_voice), None);
+        assert_eq!(allocator.voice_for_event(&pitch_bend), None);
+    }
+
+    #[test]
+    fn choke() {
+        let mut allocator = VoiceAllocator::new(4, VoiceStealing::Oldest);
+        allocator.note_on(60, 0, 1.0, None);
+
+        assert_eq!(
+            allocator.choke(60, 0, None).map(|(index, _)| index),
+            Some(0)
+        );
+        assert_eq!(allocator.choke(60, 0, None), None);
+        assert_eq!(allocator.active_voices().count(), 0);
+    }
+
+    #[test]
+    fn monophonic_last_note_priority() {
+        let mut allocator = VoiceAllocator::new_monophonic(false);
+        assert_eq!(allocator.note_on(60, 0, 1.0, None).stolen, None);
+        let slot = allocator.note_on(64, 0, 0.5, None);
+        assert_eq!(slot.stolen.unwrap().note, 60);
+        assert!(!slot.legato);
+        allocator.note_on(67, 0, 1.0, None);
+
+        // Releasing a key that isn't playing doesn't do anything, but it should be forgotten
+        assert_eq!(allocator.note_off(64, 0, None), NoteOffResult::Ignored);
+        // Releasing the playing key returns to the last held key
+        match allocator.note_off(67, 0, None) {
+            NoteOffResult::Resume(slot) => {
+                assert_eq!(slot.stolen.unwrap().note, 67);
+                assert!(!slot.legato);
+            }
+            result => panic!("Unexpected result {result:?}"),
+        }
+        assert_eq!(allocator.voice(0).unwrap().note, 60);
+        assert_eq!(allocator.voice(0).unwrap().velocity, 1.0);
+
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Release(0));
+        assert_eq!(allocator.voice(0).unwrap().state, VoiceState::Releasing);
+    }
+
+    #[test]
+    fn monophonic_legato() {
+        let mut allocator = VoiceAllocator::new_monophonic(true);
+        assert!(!allocator.note_on(60, 0, 1.0, None).legato);
+        assert!(allocator.note_on(62, 0, 1.0, None).legato);
+        match allocator.note_off(62, 0, None) {
+            NoteOffResult::Resume(slot) => assert!(slot.legato),
+            result => panic!("Unexpected result {result:?}"),
+        }
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Release(0));
+
+        // A new note after all keys have been released, even while the voice is still releasing,
+        // should retrigger the voice
+        assert!(!allocator.note_on(64, 0, 1.0, None).legato);
+    }
+
+    #[test]
+    fn monophonic_sustain() {
+        let mut allocator = VoiceAllocator::new_monophonic(true);
+        allocator.set_sustain_pedal(0, true, |_| ());
+        allocator.note_on(60, 0, 1.0, None);
+        assert_eq!(allocator.note_off(60, 0, None), NoteOffResult::Ignored);
+
+        // The sustained note is no longer held, so this is not a legato transition
+        let slot = allocator.note_on(62, 0, 1.0, None);
+        assert!(!slot.legato);
+        assert_eq!(slot.stolen.unwrap().state, VoiceState::Sustained);
+        assert_eq!(allocator.note_off(62, 0, None), NoteOffResult::Ignored);
+        assert_eq!(release_all_sustained(&mut allocator, 0), [0]);
+    }
+}
@@ -19,6 +19,7 @@ pub mod enums;
 mod float;
 mod integer;
 
+pub mod changes;
 pub mod internals;
 pub mod persist;
 pub mod range;
@@ -45,11 +46,36 @@ bitflags::bitflags! {
         const NON_AUTOMATABLE = 1 << 1;
         /// Hides the parameter in the host's generic UI for this plugin. This also implies
         /// `NON_AUTOMATABLE`. Setting this does not prevent you from changing the parameter in the
-        /// plugin's editor GUI.
+        /// plugin's editor GUI. The VST3 wrapper doesn't enumerate hidden parameters to the host at
+        /// all, and NIH-plug's generic UI widgets skip them. Hidden parameters are still saved
+        /// and restored as part of the plugin's state.
         const HIDDEN = 1 << 2;
         /// Don't show this parameter when generating a generic UI for the plugin using one of
         /// NIH-plug's generic UI widgets.
         const HIDE_IN_GENERIC_UI = 1 << 3;
+        /// The parameter is an output of the plugin, like a meter or a clip indicator. The host can
+        /// display its value but it cannot change it. The plugin updates the parameter from its
+        /// process function using
+        /// [`ProcessContext::set_output_parameter()`][crate::prelude::ProcessContext::set_output_parameter()].
+        /// This also implies `NON_AUTOMATABLE`. Since the plugin keeps these values up to date
+        /// itself, read-only parameters are not saved as part of the plugin's state.
+        const READ_ONLY = 1 << 4;
+        /// Follow the host's automation ramps in plain value space instead of in normalized value
+        /// space. VST3 hosts describe automation as linear ramps between normalized values, so on
+        /// a heavily skewed range the plain value curve the plugin sees differs a lot from the line
+        /// the user drew. With this flag and
+        /// [`Plugin::SAMPLE_ACCURATE_AUTOMATION`][crate::prelude::Plugin::SAMPLE_ACCURATE_AUTOMATION]
+        /// enabled, the VST3 wrapper converts the ramp's end points to plain values and updates the
+        /// parameter at regular intervals along a straight line between them. CLAP hosts send
+        /// individual values instead of ramps, so this has no effect there.
+        const LINEAR_PLAIN_AUTOMATION = 1 << 5;
+        /// Keep the sample offsets of this parameter's automation when the VST3 wrapper merges
+        /// dense automation, see
+        /// [`Plugin::MAX_AUTOMATION_SPLITS`][crate::prelude::Plugin::MAX_AUTOMATION_SPLITS]. This
+        /// is useful for stepped parameters like mode switches where the exact moment of the
+        /// change matters more than the number of sub-blocks. These changes are only merged when
+        /// a block contains more of them than the maximum number of splits.
+        const EXACT_AUTOMATION = 1 << 6;
     }
 }
 
@@ -183,6 +209,50 @@ pub trait Param: Display + Debug + sealed::Sealed {
         self.preview_plain(self.unmodulated_normalized_value() + normalized_offset)
     }
 
+    /// Get the plain value at the start of the parameter's range, i.e. the value for a normalized
+    /// value of 0.0. For reversed ranges this is the largest value.
+    #[inline]
+    fn min_plain_value(&self) -> Self::Plain {
+        self.preview_plain(0.0)
+    }
+
+    /// Get the plain value at the end of the parameter's range, i.e. the value for a normalized
+    /// value of 1.0. For reversed ranges this is the smallest value.
+    #[inline]
+    fn max_plain_value(&self) -> Self::Plain {
+        self.preview_plain(1.0)
+    }
+
+    /// Fill `out` with the plain values at `out.len()` evenly spaced normalized values, with the
+    /// first and last elements corresponding to the start and the end of the parameter's range.
+    /// This uses the same mapping as [`preview_plain()`][Self::preview_plain()], including
+    /// skewing and step sizes, so it can be used to draw a parameter's response curve or a scale
+    /// for it in a GUI.
+    fn sample_plain_curve(&self, out: &mut [Self::Plain]) {
+        let num_points = out.len();
+        for (idx, plain) in out.iter_mut().enumerate() {
+            let normalized = if num_points > 1 {
+                idx as f32 / (num_points - 1) as f32
+            } else {
+                0.0
+            };
+
+            *plain = self.preview_plain(normalized);
+        }
+    }
+
+    /// The inverse of [`sample_plain_curve()`][Self::sample_plain_curve()]. Fill `out` with the
+    /// normalized values for the plain values in `plain`. Both slices should have the same length.
+    fn normalized_for_plain_slice(&self, plain: &[Self::Plain], out: &mut [f32])
+    where
+        Self::Plain: Clone,
+    {
+        nih_debug_assert_eq!(plain.len(), out.len());
+        for (plain, normalized) in plain.iter().zip(out.iter_mut()) {
+            *normalized = self.preview_normalized(plain.clone());
+        }
+    }
+
     /// Flags to control the parameter's behavior. See [`ParamFlags`].
     fn flags(&self) -> ParamFlags;
 
@@ -221,6 +291,12 @@ pub(crate) trait ParamMut: Param {
     /// restoring a plugin so everything is in sync. In that case the smoother should completely
     /// reset to the current value.
     fn update_smoother(&self, sample_rate: f32, reset: bool);
+
+    /// Point the smoother to the current value like
+    /// [`update_smoother()`][Self::update_smoother()], but reach that value in `duration_ms`
+    /// milliseconds regardless of the parameter's smoothing style's duration. Used for
+    /// [`Plugin::STATE_LOAD_SMOOTHING_MS`][crate::prelude::Plugin::STATE_LOAD_SMOOTHING_MS].
+    fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32);
 }
 
 /// Describes a struct containing parameters and other persistent fields.
@@ -233,6 +309,14 @@ pub(crate) trait ParamMut: Param {
 /// collisions) that will be used to identify the parameter internally so you can safely move it
 /// around and rename the field without breaking compatibility with old presets.
 ///
+/// ## `#[legacy_ids("old_id", "older_id")]`
+///
+/// Parameter IDs should never change, but if a parameter's ID does need to be changed then its old
+/// IDs can be listed in this attribute next to the parameter's `#[id = "new_id"]` attribute. Host
+/// automation and plugin state using one of the old IDs will then still be applied to the renamed
+/// parameter, while the host only ever sees the new ID. Legacy IDs may not be used by any other
+/// parameter.
+///
 /// ## `#[persist = "key"]`
 ///
 /// The struct can also contain other fields that should be persisted along with the rest of the
@@ -288,6 +372,14 @@ pub unsafe trait Params: 'static + Send + Sync {
     /// fine to be able to support custom reusable Params implementations.
     fn param_map(&self) -> Vec<(String, ParamPtr, String)>;
 
+    /// Get a list of `(legacy_id, current_id)` pairs for parameters whose IDs have changed. The
+    /// wrappers map the legacy IDs to the parameter with the current ID when receiving automation
+    /// from the host or when loading state. The derive macro generates these pairs from the
+    /// `#[legacy_ids(...)]` attributes, taking nested ID prefixes and suffixes into account.
+    fn legacy_param_ids(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
     /// Serialize all fields marked with `#[persist = "stable_name"]` into a hash map containing
     /// JSON-representations of those fields so they can be written to the plugin's state and
     /// recalled later. This uses [`persist::serialize_field()`] under the hood.
@@ -310,6 +402,10 @@ unsafe impl<P: Params> Params for Arc<P> {
         self.as_ref().param_map()
     }
 
+    fn legacy_param_ids(&self) -> Vec<(String, String)> {
+        self.as_ref().legacy_param_ids()
+    }
+
     fn serialize_fields(&self) -> BTreeMap<String, String> {
         self.as_ref().serialize_fields()
     }
@@ -214,6 +214,8 @@ impl ParamMut for BoolParam {
     fn update_smoother(&self, _sample_rate: f32, _init: bool) {
         // Can't really smooth a binary parameter now can you
     }
+
+    fn update_smoother_with_duration(&self, _sample_rate: f32, _duration_ms: f32) {}
 }
 
 impl BoolParam {
@@ -316,10 +318,26 @@ impl BoolParam {
         self
     }
 
+    /// Mark the parameter as a read-only output parameter. The host can display the parameter's
+    /// value, but only the plugin can change it by calling
+    /// [`ProcessContext::set_output_parameter()`][crate::prelude::ProcessContext::set_output_parameter()]
+    /// from its process function. This also implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Don't show this parameter when generating a generic UI for the plugin using one of
     /// NIH-plug's generic UI widgets.
     pub fn hide_in_generic_ui(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDE_IN_GENERIC_UI);
         self
     }
+
+    /// Keep the sample offsets of this parameter's automation when dense automation gets merged.
+    /// See [`ParamFlags::EXACT_AUTOMATION`] for more information.
+    pub fn with_exact_automation(mut self) -> Self {
+        self.flags.insert(ParamFlags::EXACT_AUTOMATION);
+        self
+    }
 }
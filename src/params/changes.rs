@@ -0,0 +1,380 @@
+//! Keeping track of where parameter changes came from. The wrappers record the source of every
+//! parameter change they make, and the plugin's editor can use this to find out which parameter
+//! was changed last and by whom. This can be used to implement things like a MIDI learn function or
+//! a 'last touched parameter' display.
+
+use atomic_float::AtomicF32;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::internals::ParamPtr;
+
+/// Where a parameter change came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ParamChangeSource {
+    /// The host changed the parameter, either through automation playback or because the user
+    /// changed the parameter through the host's own controls.
+    HostAutomation,
+    /// The parameter was changed because the host loaded a preset or a previously saved state.
+    HostStateLoad,
+    /// The parameter was changed from the plugin's editor.
+    GuiGesture,
+    /// The parameter was changed by the plugin or the wrapper itself, for instance because the
+    /// plugin restored a state through [`GuiContext::set_state()`][crate::prelude::GuiContext::set_state()].
+    Internal,
+}
+
+impl ParamChangeSource {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ParamChangeSource::HostAutomation,
+            1 => ParamChangeSource::HostStateLoad,
+            2 => ParamChangeSource::GuiGesture,
+            _ => ParamChangeSource::Internal,
+        }
+    }
+}
+
+/// The most recent change made to a parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParamChange {
+    /// The parameter that was changed.
+    pub param: ParamPtr,
+    /// Where the change came from.
+    pub source: ParamChangeSource,
+    /// The parameter's new normalized value.
+    pub normalized_value: f32,
+    /// When the change was made.
+    pub time: Instant,
+}
+
+/// Keeps track of the most recent change for every parameter. This is shared between the wrapper
+/// and the plugin's editor. Recording a change only involves a couple of atomic stores, so it can
+/// safely be done from the audio thread.
+pub(crate) struct ParamChangeTracker {
+    /// The timestamps are stored relative to this instant.
+    epoch: Instant,
+    /// The sequence number for the next change. Used to order the changes and to find out which
+    /// changes a [`ParamChangeSubscription`] has not yet seen.
+    next_sequence: AtomicU64,
+    params: Vec<TrackedParam>,
+    /// Indices into `params`. Built once, so looking up parameters does not allocate.
+    index_by_ptr: HashMap<ParamPtr, usize>,
+    /// The index into `params` of the most recent change that wasn't caused by loading state.
+    /// Loading state records a change for every parameter, so this can't be derived from the
+    /// parameters' last changes.
+    last_touched_idx: AtomicUsize,
+    /// The most recent change that wasn't caused by loading state.
+    last_touched: TrackedChange,
+}
+
+struct TrackedParam {
+    ptr: ParamPtr,
+    /// The parameter's last change.
+    change: TrackedChange,
+}
+
+/// A change stored as a couple of atomics so it can be recorded from the audio thread.
+struct TrackedChange {
+    /// The change's sequence number, or zero if nothing has been recorded yet. This is stored last
+    /// so the other fields are up to date when this is read.
+    sequence: AtomicU64,
+    source: AtomicU8,
+    normalized_value: AtomicF32,
+    /// The time since `epoch` in nanoseconds.
+    time_nanos: AtomicU64,
+}
+
+/// A subscription to parameter changes, created using
+/// [`GuiContext::subscribe_param_changes()`][crate::prelude::GuiContext::subscribe_param_changes()].
+/// The editor can periodically [`poll()`][Self::poll()] this to find out which parameters have
+/// changed since the last poll, and where those changes came from.
+pub struct ParamChangeSubscription {
+    tracker: Arc<ParamChangeTracker>,
+    /// The sequence number for every parameter's last change that was delivered through `poll()`.
+    last_seen_sequences: Vec<u64>,
+}
+
+impl ParamChangeTracker {
+    /// Create a tracker for a plugin's parameters.
+    pub fn new(params: impl IntoIterator<Item = ParamPtr>) -> Self {
+        let params: Vec<TrackedParam> = params
+            .into_iter()
+            .map(|ptr| TrackedParam {
+                ptr,
+                change: TrackedChange::default(),
+            })
+            .collect();
+        let index_by_ptr = params
+            .iter()
+            .enumerate()
+            .map(|(idx, param)| (param.ptr, idx))
+            .collect();
+
+        Self {
+            epoch: Instant::now(),
+            next_sequence: AtomicU64::new(1),
+            params,
+            index_by_ptr,
+            last_touched_idx: AtomicUsize::new(0),
+            last_touched: TrackedChange::default(),
+        }
+    }
+
+    /// Record a change to a parameter. Unknown parameters are ignored.
+    pub fn record(&self, param: ParamPtr, source: ParamChangeSource, normalized_value: f32) {
+        if let Some(&idx) = self.index_by_ptr.get(&param) {
+            let sequence = self.next_sequence.fetch_add(1, Ordering::Relaxed);
+            let time_nanos = self.epoch.elapsed().as_nanos() as u64;
+            self.params[idx]
+                .change
+                .store(sequence, source, normalized_value, time_nanos);
+
+            if source != ParamChangeSource::HostStateLoad {
+                self.last_touched_idx.store(idx, Ordering::Relaxed);
+                self.last_touched
+                    .store(sequence, source, normalized_value, time_nanos);
+            }
+        }
+    }
+
+    /// Record a change the host made to a parameter. Changes made from the plugin's GUI are sent to
+    /// the host, and the host will often send them back to the plugin as automation. If the last
+    /// change to this parameter was a GUI gesture with the same value, then this is that echoed
+    /// change and the parameter keeps its GUI gesture source.
+    pub fn record_host_automation(&self, param: ParamPtr, normalized_value: f32) {
+        if let Some(&idx) = self.index_by_ptr.get(&param) {
+            let change = &self.params[idx].change;
+            if change.source.load(Ordering::Relaxed) == ParamChangeSource::GuiGesture as u8
+                && change.normalized_value.load(Ordering::Relaxed) == normalized_value
+            {
+                return;
+            }
+
+            self.record(param, ParamChangeSource::HostAutomation, normalized_value);
+        }
+    }
+
+    /// Record a change to every parameter using their current values. Used after loading state.
+    pub fn record_all(&self, source: ParamChangeSource) {
+        for tracked in &self.params {
+            self.record(tracked.ptr, source, unsafe {
+                tracked.ptr.unmodulated_normalized_value()
+            });
+        }
+    }
+
+    /// Get the last change made to a parameter, if it has been changed since the plugin was
+    /// created.
+    pub fn last_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        let idx = *self.index_by_ptr.get(&param)?;
+        let (_, change) = self.load(idx)?;
+
+        Some(change)
+    }
+
+    /// Get the most recent parameter change that wasn't caused by loading state.
+    pub fn last_touched(&self) -> Option<ParamChange> {
+        // The index is stored before the change's sequence number, so it needs to be loaded after
+        // the sequence number to belong to the same change
+        if self.last_touched.sequence.load(Ordering::Acquire) == 0 {
+            return None;
+        }
+
+        let idx = self.last_touched_idx.load(Ordering::Relaxed);
+        let (_, change) = self.last_touched.load(self.params[idx].ptr, self.epoch)?;
+
+        Some(change)
+    }
+
+    /// Load a parameter's last change along with its sequence number.
+    fn load(&self, idx: usize) -> Option<(u64, ParamChange)> {
+        let tracked = &self.params[idx];
+        tracked.change.load(tracked.ptr, self.epoch)
+    }
+}
+
+impl Default for TrackedChange {
+    fn default() -> Self {
+        Self {
+            sequence: AtomicU64::new(0),
+            source: AtomicU8::new(ParamChangeSource::Internal as u8),
+            normalized_value: AtomicF32::new(0.0),
+            time_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl TrackedChange {
+    fn store(
+        &self,
+        sequence: u64,
+        source: ParamChangeSource,
+        normalized_value: f32,
+        time_nanos: u64,
+    ) {
+        self.source.store(source as u8, Ordering::Relaxed);
+        self.normalized_value
+            .store(normalized_value, Ordering::Relaxed);
+        self.time_nanos.store(time_nanos, Ordering::Relaxed);
+        self.sequence.store(sequence, Ordering::Release);
+    }
+
+    /// Load the change along with its sequence number, or `None` if nothing has been recorded yet.
+    fn load(&self, param: ParamPtr, epoch: Instant) -> Option<(u64, ParamChange)> {
+        let sequence = self.sequence.load(Ordering::Acquire);
+        if sequence == 0 {
+            return None;
+        }
+
+        Some((
+            sequence,
+            ParamChange {
+                param,
+                source: ParamChangeSource::from_u8(self.source.load(Ordering::Relaxed)),
+                normalized_value: self.normalized_value.load(Ordering::Relaxed),
+                time: epoch + Duration::from_nanos(self.time_nanos.load(Ordering::Relaxed)),
+            },
+        ))
+    }
+}
+
+impl ParamChangeSubscription {
+    /// Subscribe to the changes recorded by `tracker`. Only changes made after this point are
+    /// delivered through [`poll()`][Self::poll()].
+    pub(crate) fn new(tracker: Arc<ParamChangeTracker>) -> Self {
+        let last_seen_sequences = tracker
+            .params
+            .iter()
+            .map(|tracked| tracked.change.sequence.load(Ordering::Acquire))
+            .collect();
+
+        Self {
+            tracker,
+            last_seen_sequences,
+        }
+    }
+
+    /// Call `callback` for every parameter that has changed since the last time this function was
+    /// called, in the order those changes happened. If a parameter changed multiple times, then only
+    /// the most recent change is delivered.
+    pub fn poll(&mut self, mut callback: impl FnMut(ParamChange)) {
+        let mut changes: Vec<(u64, ParamChange)> = Vec::new();
+        for (idx, last_seen_sequence) in self.last_seen_sequences.iter_mut().enumerate() {
+            if let Some((sequence, change)) = self.tracker.load(idx) {
+                if sequence != *last_seen_sequence {
+                    *last_seen_sequence = sequence;
+                    changes.push((sequence, change));
+                }
+            }
+        }
+
+        changes.sort_by_key(|(sequence, _)| *sequence);
+        for (_, change) in changes {
+            callback(change);
+        }
+    }
+
+    /// Get the last change made to a parameter. See
+    /// [`GuiContext::last_param_change()`][crate::prelude::GuiContext::last_param_change()].
+    pub fn last_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        self.tracker.last_change(param)
+    }
+
+    /// Get the most recent parameter change that wasn't caused by loading state. This includes
+    /// changes made before the subscription was created.
+    pub fn last_touched(&self) -> Option<ParamChange> {
+        self.tracker.last_touched()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::range::FloatRange;
+    use crate::params::{FloatParam, Param};
+
+    fn params() -> [FloatParam; 2] {
+        let range = FloatRange::Linear { min: 0.0, max: 1.0 };
+        [
+            FloatParam::new("A", 0.0, range),
+            FloatParam::new("B", 0.0, range),
+        ]
+    }
+
+    #[test]
+    fn last_change_and_last_touched() {
+        let params = params();
+        let tracker = ParamChangeTracker::new(params.iter().map(|p| p.as_ptr()));
+        assert_eq!(tracker.last_change(params[0].as_ptr()), None);
+        assert_eq!(tracker.last_touched(), None);
+
+        tracker.record(params[0].as_ptr(), ParamChangeSource::GuiGesture, 0.5);
+        tracker.record(params[1].as_ptr(), ParamChangeSource::HostAutomation, 0.25);
+        let change = tracker.last_change(params[0].as_ptr()).unwrap();
+        assert_eq!(change.source, ParamChangeSource::GuiGesture);
+        assert_eq!(change.normalized_value, 0.5);
+        assert_eq!(tracker.last_touched().unwrap().param, params[1].as_ptr());
+
+        // Loading state should not count as touching a parameter
+        tracker.record_all(ParamChangeSource::HostStateLoad);
+        assert_eq!(
+            tracker.last_change(params[0].as_ptr()).unwrap().source,
+            ParamChangeSource::HostStateLoad
+        );
+        let last_touched = tracker.last_touched().unwrap();
+        assert_eq!(last_touched.param, params[1].as_ptr());
+        assert_eq!(last_touched.source, ParamChangeSource::HostAutomation);
+        assert_eq!(last_touched.normalized_value, 0.25);
+    }
+
+    #[test]
+    fn echoed_gui_changes_keep_their_source() {
+        let params = params();
+        let param = params[0].as_ptr();
+        let tracker = ParamChangeTracker::new(params.iter().map(|p| p.as_ptr()));
+
+        tracker.record(param, ParamChangeSource::GuiGesture, 0.5);
+        tracker.record_host_automation(param, 0.5);
+        assert_eq!(
+            tracker.last_change(param).unwrap().source,
+            ParamChangeSource::GuiGesture
+        );
+
+        tracker.record_host_automation(param, 0.75);
+        assert_eq!(
+            tracker.last_change(param).unwrap().source,
+            ParamChangeSource::HostAutomation
+        );
+    }
+
+    #[test]
+    fn subscription_delivers_new_changes_in_order() {
+        let params = params();
+        let tracker = Arc::new(ParamChangeTracker::new(params.iter().map(|p| p.as_ptr())));
+        tracker.record(params[0].as_ptr(), ParamChangeSource::GuiGesture, 0.1);
+
+        let mut subscription = ParamChangeSubscription::new(tracker.clone());
+        tracker.record(params[1].as_ptr(), ParamChangeSource::HostAutomation, 0.2);
+        tracker.record(params[0].as_ptr(), ParamChangeSource::GuiGesture, 0.3);
+
+        let mut changes = Vec::new();
+        subscription
+            .poll(|change| changes.push((change.param, change.source, change.normalized_value)));
+        assert_eq!(
+            changes,
+            [
+                (params[1].as_ptr(), ParamChangeSource::HostAutomation, 0.2),
+                (params[0].as_ptr(), ParamChangeSource::GuiGesture, 0.3),
+            ]
+        );
+
+        changes.clear();
+        subscription
+            .poll(|change| changes.push((change.param, change.source, change.normalized_value)));
+        assert!(changes.is_empty());
+    }
+}
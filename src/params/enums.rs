@@ -309,6 +309,11 @@ impl<T: Enum + PartialEq> ParamMut for EnumParam<T> {
     fn update_smoother(&self, sample_rate: f32, reset: bool) {
         self.inner.update_smoother(sample_rate, reset)
     }
+
+    fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32) {
+        self.inner
+            .update_smoother_with_duration(sample_rate, duration_ms)
+    }
 }
 
 impl ParamMut for EnumParamInner {
@@ -327,20 +332,40 @@ impl ParamMut for EnumParamInner {
     fn update_smoother(&self, sample_rate: f32, reset: bool) {
         self.inner.update_smoother(sample_rate, reset)
     }
+
+    fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32) {
+        self.inner
+            .update_smoother_with_duration(sample_rate, duration_ms)
+    }
 }
 
 impl<T: Enum + PartialEq + 'static> EnumParam<T> {
     /// Build a new [Self]. Use the other associated functions to modify the behavior of the
     /// parameter.
     pub fn new(name: impl Into<String>, default: T) -> Self {
-        let variants = T::variants();
-        let ids = T::ids();
+        Self::new_with_num_variants(name, default, T::variants().len())
+    }
+
+    /// The same as [`new()`][Self::new()], but only the first `num_variants` variants of `T` can be
+    /// selected. The wrappers use this for their own parameters when only some variants apply to
+    /// the plugin. `default` needs to be one of those variants.
+    pub(crate) fn new_with_num_variants(
+        name: impl Into<String>,
+        default: T,
+        num_variants: usize,
+    ) -> Self {
+        nih_debug_assert!(num_variants > 0 && num_variants <= T::variants().len());
+        let default_index = T::to_index(default);
+        nih_debug_assert!(default_index < num_variants);
+        let num_variants = num_variants.min(T::variants().len());
+        let variants = &T::variants()[..num_variants];
+        let ids = T::ids().map(|ids| &ids[..num_variants]);
 
         Self {
             inner: EnumParamInner {
                 inner: IntParam::new(
                     name,
-                    T::to_index(default) as i32,
+                    default_index as i32,
                     IntRange::Linear {
                         min: 0,
                         max: variants.len() as i32 - 1,
@@ -403,12 +428,28 @@ impl<T: Enum + PartialEq + 'static> EnumParam<T> {
         self
     }
 
+    /// Mark the parameter as a read-only output parameter. The host can display the parameter's
+    /// value, but only the plugin can change it by calling
+    /// [`ProcessContext::set_output_parameter()`][crate::prelude::ProcessContext::set_output_parameter()]
+    /// from its process function. This also implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.inner.inner = self.inner.inner.read_only();
+        self
+    }
+
     /// Don't show this parameter when generating a generic UI for the plugin using one of
     /// NIH-plug's generic UI widgets.
     pub fn hide_in_generic_ui(mut self) -> Self {
         self.inner.inner = self.inner.inner.hide_in_generic_ui();
         self
     }
+
+    /// Keep the sample offsets of this parameter's automation when dense automation gets merged.
+    /// See [`ParamFlags::EXACT_AUTOMATION`] for more information.
+    pub fn with_exact_automation(mut self) -> Self {
+        self.inner.inner = self.inner.inner.with_exact_automation();
+        self
+    }
 }
 
 impl EnumParamInner {
@@ -9,6 +9,8 @@ use super::internals::ParamPtr;
 use super::range::FloatRange;
 use super::smoothing::{Smoother, SmoothingStyle};
 use super::{Param, ParamFlags, ParamMut};
+use crate::formatters;
+use crate::util;
 
 /// A floating point parameter that's stored unnormalized. The range is used for the normalization
 /// process.
@@ -51,6 +53,11 @@ pub struct FloatParam {
     /// input. If this is set and if [`value_to_string`][Self::value_to_string] is not set, then
     /// this is also used when formatting the parameter. This must be a positive, nonzero number.
     step_size: Option<f32>,
+    /// The number of decimal places to use when formatting the parameter's value. If this is set
+    /// and if [`value_to_string`][Self::value_to_string] is not set, then this takes precedence
+    /// over the number of decimals implied by [`step_size`][Self::step_size]. If neither is set,
+    /// then the number of decimals is derived from the parameter's range.
+    display_precision: Option<usize>,
     /// The parameter's human readable display name.
     name: String,
     /// The parameter value's unit, added after [`value_to_string`][Self::value_to_string] if that
@@ -75,14 +82,9 @@ pub struct FloatParam {
 
 impl Display for FloatParam {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match (&self.value_to_string, &self.step_size) {
-            (Some(func), _) => write!(f, "{}{}", func(self.value()), self.unit),
-            (None, Some(step_size)) => {
-                let num_digits = decimals_from_step_size(*step_size);
-                write!(f, "{:.num_digits$}{}", self.value(), self.unit)
-            }
-            _ => write!(f, "{}{}", self.value(), self.unit),
-        }
+        // This goes through the same function the wrappers use so the values displayed by the host
+        // and by the plugin's GUI always match up
+        write!(f, "{}", self.format_plain_value(self.value(), true))
     }
 }
 
@@ -153,21 +155,7 @@ impl Param for FloatParam {
     }
 
     fn normalized_value_to_string(&self, normalized: f32, include_unit: bool) -> String {
-        let value = self.preview_plain(normalized);
-        match (&self.value_to_string, &self.step_size, include_unit) {
-            (Some(f), _, true) => format!("{}{}", f(value), self.unit),
-            (Some(f), _, false) => f(value),
-            (None, Some(step_size), true) => {
-                let num_digits = decimals_from_step_size(*step_size);
-                format!("{:.num_digits$}{}", value, self.unit)
-            }
-            (None, Some(step_size), false) => {
-                let num_digits = decimals_from_step_size(*step_size);
-                format!("{:.num_digits$}", value)
-            }
-            (None, None, true) => format!("{}{}", value, self.unit),
-            (None, None, false) => format!("{}", value),
-        }
+        self.format_plain_value(self.preview_plain(normalized), include_unit)
     }
 
     fn string_to_normalized_value(&self, string: &str) -> Option<f32> {
@@ -175,7 +163,9 @@ impl Param for FloatParam {
             Some(f) => f(string.trim()),
             // In the CLAP wrapper the unit will be included, so make sure to handle that
             None => string.trim().trim_end_matches(self.unit).parse().ok(),
-        }?;
+        }
+        // Rust happily parses strings like `NaN` and `inf`
+        .filter(|value: &f32| value.is_finite())?;
 
         Some(self.preview_normalized(value))
     }
@@ -253,6 +243,14 @@ impl ParamMut for FloatParam {
                 .set_target(sample_rate, self.modulated_plain_value());
         }
     }
+
+    fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32) {
+        self.smoothed.set_target_with_duration(
+            sample_rate,
+            self.modulated_plain_value(),
+            duration_ms,
+        );
+    }
 }
 
 impl FloatParam {
@@ -273,6 +271,7 @@ impl FloatParam {
 
             range,
             step_size: None,
+            display_precision: None,
             name: name.into(),
             unit: "",
             poly_modulation_id: None,
@@ -281,6 +280,41 @@ impl FloatParam {
         }
     }
 
+    /// Build a new [`FloatParam`] for a gain value that's displayed in decibels. The parameter's
+    /// plain value is a linear gain ratio, so [`value()`][Self::value()] and the
+    /// [`smoothed`][Self::smoothed] values can directly be multiplied with the audio. The range
+    /// is skewed so it appears linear when displayed in decibels, like a typical fader. The
+    /// parameter uses logarithmic smoothing so fades sound even, and the values are formatted and
+    /// parsed as decibels with the `" dB"` unit.
+    ///
+    /// A `min_db` at or below [`util::MINUS_INFINITY_DB`] makes the parameter's minimum value
+    /// silent. That minimum is displayed as `-inf dB`, and strings like `-inf` and `-∞` can be used
+    /// to enter it. Since logarithmic smoothing cannot reach zero, the actual minimum gain is
+    /// [`util::MINUS_INFINITY_GAIN`] in that case. The other builder methods can be used to
+    /// override any of these settings.
+    pub fn new_gain_db(name: impl Into<String>, default_db: f32, min_db: f32, max_db: f32) -> Self {
+        nih_debug_assert!(min_db < max_db);
+
+        let min_db = min_db.max(util::MINUS_INFINITY_DB);
+        let min_gain = util::db_to_gain(min_db).max(util::MINUS_INFINITY_GAIN);
+        let max_gain = util::db_to_gain(max_db);
+        let default = util::db_to_gain(default_db).clamp(min_gain, max_gain);
+
+        Self::new(
+            name,
+            default,
+            FloatRange::Skewed {
+                min: min_gain,
+                max: max_gain,
+                factor: FloatRange::gain_skew_factor(min_db, max_db),
+            },
+        )
+        .with_smoother(SmoothingStyle::Logarithmic(50.0))
+        .with_unit(" dB")
+        .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+        .with_string_to_value(formatters::s2v_f32_gain_to_db())
+    }
+
     /// The field's current plain value, after monophonic modulation has been applied. Equivalent to
     /// calling `param.plain_value()`.
     #[inline]
@@ -288,6 +322,31 @@ impl FloatParam {
         self.modulated_plain_value()
     }
 
+    /// Create a voice-local copy of this parameter's smoother that starts at `voice_current`
+    /// instead of at the smoother's current value. The copy smooths towards the parameter's current
+    /// target value, and it reaches that target at the same time as [`smoothed`][Self::smoothed].
+    /// This is useful for polyphonic synthesizers where every voice smooths a parameter from its
+    /// own position.
+    ///
+    /// This does not allocate, so it can be called at the start of every block to keep a large
+    /// number of voices in sync with a single parameter. The parameter's own smoother then needs to
+    /// be advanced by the same number of samples as the voices so the step counts stay aligned:
+    ///
+    /// ```ignore
+    /// for voice in voices.iter_mut() {
+    ///     // This picks up any new target value, and continues from the voice's last value
+    ///     voice.cutoff = params.cutoff.smoothed_at(voice.cutoff.previous_value());
+    ///     voice.cutoff.next_block(&mut voice.cutoff_values, block_len);
+    /// }
+    /// params.cutoff.smoothed.next_step(block_len as u32);
+    /// ```
+    ///
+    /// When a voice is stolen, [`Smoother::snapshot()`] and [`Smoother::restore()`] can be used to
+    /// hand its smoother's state over to the new voice.
+    pub fn smoothed_at(&self, voice_current: f32) -> Smoother<f32> {
+        self.smoothed.clone_with_current(voice_current)
+    }
+
     /// Enable polyphonic modulation for this parameter. The ID is used to uniquely identify this
     /// parameter in [`NoteEvent::PolyModulation`][crate::prelude::NoteEvent::PolyModulation]
     /// events, and must thus be unique between _all_ polyphonically modulatable parameters. See the
@@ -354,6 +413,16 @@ impl FloatParam {
         self
     }
 
+    /// Always display the parameter's value with this many decimal places. If this is not set, then
+    /// the number of decimals is derived from the step size instead, if that is set, or from the
+    /// size of the parameter's range otherwise. This does not affect the values that can be entered
+    /// for the parameter. Has no effect if a [`value_to_string`][Self::with_value_to_string()]
+    /// function is also set.
+    pub fn with_display_precision(mut self, decimals: usize) -> Self {
+        self.display_precision = Some(decimals);
+        self
+    }
+
     /// Use a custom conversion function to convert the plain, unnormalized value to a
     /// string.
     pub fn with_value_to_string(
@@ -394,12 +463,72 @@ impl FloatParam {
         self
     }
 
+    /// Mark the parameter as a read-only output parameter. The host can display the parameter's
+    /// value, but only the plugin can change it by calling
+    /// [`ProcessContext::set_output_parameter()`][crate::prelude::ProcessContext::set_output_parameter()]
+    /// from its process function. This also implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Don't show this parameter when generating a generic UI for the plugin using one of
     /// NIH-plug's generic UI widgets.
     pub fn hide_in_generic_ui(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDE_IN_GENERIC_UI);
         self
     }
+
+    /// Interpolate the host's automation ramps linearly in plain values instead of in normalized
+    /// values. Useful for parameters with skewed ranges. See
+    /// [`ParamFlags::LINEAR_PLAIN_AUTOMATION`] for more information.
+    pub fn with_linear_plain_automation(mut self) -> Self {
+        self.flags.insert(ParamFlags::LINEAR_PLAIN_AUTOMATION);
+        self
+    }
+
+    /// Keep the sample offsets of this parameter's automation when dense automation gets merged.
+    /// See [`ParamFlags::EXACT_AUTOMATION`] for more information.
+    pub fn with_exact_automation(mut self) -> Self {
+        self.flags.insert(ParamFlags::EXACT_AUTOMATION);
+        self
+    }
+
+    /// Format a plain value for display. Both the [`Display`] implementation used by the GUI
+    /// widgets and [`Param::normalized_value_to_string()`] used by the wrappers go through this
+    /// function so the two can never diverge.
+    fn format_plain_value(&self, value: f32, include_unit: bool) -> String {
+        let unit = if include_unit { self.unit } else { "" };
+        if let Some(f) = &self.value_to_string {
+            return format!("{}{}", f(value), unit);
+        }
+
+        let num_digits = self
+            .display_precision
+            .or_else(|| self.step_size.map(decimals_from_step_size))
+            .unwrap_or_else(|| decimals_from_range(&self.range));
+        format!("{:.num_digits$}{}", value, unit)
+    }
+}
+
+/// Calculate how many decimals to round to when displaying a value in a range without a step size
+/// or an explicit display precision. This shows roughly three significant digits for the span
+/// between the range's minimum and maximum values, so a `0..1` range uses two decimals and a
+/// `20..20_000` range uses none.
+fn decimals_from_range(range: &FloatRange) -> usize {
+    const DEFAULT_DECIMALS: usize = 2;
+
+    let span = match range {
+        FloatRange::Linear { min, max }
+        | FloatRange::Skewed { min, max, .. }
+        | FloatRange::SymmetricalSkewed { min, max, .. } => (max - min).abs(),
+        FloatRange::Reversed(range) => return decimals_from_range(range),
+    };
+    if !span.is_finite() || span == 0.0 {
+        return DEFAULT_DECIMALS;
+    }
+
+    (2 - span.log10().floor() as i32).clamp(0, f32::DIGITS as i32) as usize
 }
 
 /// Calculate how many decimals to round to when displaying a floating point value with a specific
@@ -419,3 +548,302 @@ fn decimals_from_step_size(step_size: f32) -> usize {
 
     num_digits as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The GUI widgets use the [`Display`] implementation while the wrappers use
+    /// [`Param::normalized_value_to_string()`]. These should always produce the same strings.
+    fn assert_consistent_formatting(param: FloatParam) {
+        for i in 0..=100 {
+            let normalized = i as f32 / 100.0;
+            param.set_normalized_value(normalized);
+
+            assert_eq!(
+                param.to_string(),
+                param.normalized_value_to_string(param.modulated_normalized_value(), true)
+            );
+        }
+    }
+
+    #[test]
+    fn consistent_formatting_step_size() {
+        assert_consistent_formatting(
+            FloatParam::new(
+                "Foo",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_step_size(0.01)
+            .with_unit(" dB"),
+        );
+    }
+
+    #[test]
+    fn consistent_formatting_display_precision() {
+        assert_consistent_formatting(
+            FloatParam::new(
+                "Foo",
+                1.0,
+                FloatRange::Skewed {
+                    min: 0.1,
+                    max: 10.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_display_precision(3),
+        );
+    }
+
+    #[test]
+    fn display_precision_overrides_step_size() {
+        let param = FloatParam::new("Foo", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_step_size(0.1)
+            .with_display_precision(3)
+            .with_unit(" s");
+
+        assert_eq!(param.to_string(), "0.500 s");
+        assert_eq!(param.normalized_value_to_string(0.5, false), "0.500");
+    }
+
+    #[test]
+    fn consistent_formatting_default_precision() {
+        assert_consistent_formatting(FloatParam::new(
+            "Foo",
+            1000.0,
+            FloatRange::Skewed {
+                min: 20.0,
+                max: 20_000.0,
+                factor: FloatRange::skew_factor(-2.0),
+            },
+        ));
+        assert_consistent_formatting(
+            FloatParam::new("Foo", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }).with_unit(" s"),
+        );
+    }
+
+    #[test]
+    fn default_precision_from_range() {
+        static WRAPPED_RANGE: FloatRange = FloatRange::Linear { min: 0.0, max: 1.0 };
+
+        assert_eq!(
+            decimals_from_range(&FloatRange::Linear { min: 0.0, max: 1.0 }),
+            2
+        );
+        assert_eq!(
+            decimals_from_range(&FloatRange::Linear {
+                min: -1.0,
+                max: 1.0
+            }),
+            2
+        );
+        assert_eq!(
+            decimals_from_range(&FloatRange::Linear {
+                min: 0.0,
+                max: 10.0
+            }),
+            1
+        );
+        assert_eq!(
+            decimals_from_range(&FloatRange::Linear {
+                min: 0.0,
+                max: 0.05
+            }),
+            4
+        );
+        assert_eq!(
+            decimals_from_range(&FloatRange::Skewed {
+                min: 20.0,
+                max: 20_000.0,
+                factor: FloatRange::skew_factor(-2.0),
+            }),
+            0
+        );
+        assert_eq!(
+            decimals_from_range(&FloatRange::Reversed(&WRAPPED_RANGE)),
+            2
+        );
+        // Degenerate ranges fall back to two decimals
+        assert_eq!(
+            decimals_from_range(&FloatRange::Linear { min: 1.0, max: 1.0 }),
+            2
+        );
+
+        let param =
+            FloatParam::new("Foo", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }).with_unit(" s");
+        assert_eq!(param.to_string(), "0.50 s");
+        assert_eq!(param.normalized_value_to_string(0.25, false), "0.25");
+
+        let param = FloatParam::new(
+            "Cutoff",
+            1000.0,
+            FloatRange::Linear {
+                min: 20.0,
+                max: 20_000.0,
+            },
+        )
+        .with_unit(" Hz");
+        assert_eq!(param.to_string(), "1000 Hz");
+    }
+
+    #[test]
+    fn invalid_strings() {
+        let param =
+            FloatParam::new("Foo", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }).with_unit(" s");
+
+        for string in ["NaN", "inf", "-inf s", "1e39"] {
+            assert_eq!(param.string_to_normalized_value(string), None, "{string}");
+        }
+        assert_eq!(param.string_to_normalized_value("-1"), Some(0.0));
+        assert_eq!(param.string_to_normalized_value("1e30 s"), Some(1.0));
+    }
+
+    #[test]
+    fn gain_db_edge_values() {
+        let param = FloatParam::new_gain_db("Gain", 0.0, f32::NEG_INFINITY, 12.0);
+        assert_eq!(param.min_plain_value(), util::MINUS_INFINITY_GAIN);
+        assert_eq!(param.value(), 1.0);
+        assert_eq!(param.to_string(), "0.00 dB");
+        assert_eq!(param.normalized_value_to_string(0.0, true), "-inf dB");
+        assert_eq!(param.normalized_value_to_string(1.0, true), "12.00 dB");
+        for string in ["-inf", "-inf dB", "-∞", "−∞ dB", "‑∞"] {
+            assert_eq!(
+                param.string_to_normalized_value(string),
+                Some(0.0),
+                "{string}"
+            );
+        }
+        let normalized = param.string_to_normalized_value("12 dB").unwrap();
+        assert!((normalized - 1.0).abs() < 1e-6);
+        let normalized = param.string_to_normalized_value("0").unwrap();
+        assert_eq!(
+            param.normalized_value_to_string(normalized, true),
+            "0.00 dB"
+        );
+
+        let param = FloatParam::new_gain_db("Gain", -60.0, -30.0, 30.0);
+        assert_eq!(param.to_string(), "-30.00 dB");
+        assert_eq!(param.normalized_value_to_string(0.0, true), "-30.00 dB");
+        let normalized = param.string_to_normalized_value("-inf").unwrap();
+        assert_eq!(normalized, 0.0);
+    }
+
+    #[test]
+    fn parse_more_precision_than_displayed() {
+        let param = FloatParam::new("Foo", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_display_precision(1);
+
+        assert_eq!(param.string_to_normalized_value("0.1234"), Some(0.1234));
+    }
+
+    /// Sampled curves should be monotonic in the direction of the range, and they should match the
+    /// parameter's normal mapping.
+    fn assert_monotonic_curve(param: FloatParam, increasing: bool) {
+        let mut curve = [0.0; 257];
+        param.sample_plain_curve(&mut curve);
+
+        assert_eq!(curve[0], param.min_plain_value());
+        assert_eq!(curve[curve.len() - 1], param.max_plain_value());
+        for window in curve.windows(2) {
+            if increasing {
+                assert!(window[0] <= window[1], "{window:?} is not increasing");
+            } else {
+                assert!(window[0] >= window[1], "{window:?} is not decreasing");
+            }
+        }
+
+        let mut normalized = [0.0; 257];
+        param.normalized_for_plain_slice(&curve, &mut normalized);
+        for window in normalized.windows(2) {
+            assert!(window[0] <= window[1], "{window:?} is not increasing");
+        }
+        assert!(normalized[0].abs() < 1e-6);
+        assert!((normalized[normalized.len() - 1] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sample_plain_curve_linear() {
+        assert_monotonic_curve(
+            FloatParam::new(
+                "Foo",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            ),
+            true,
+        );
+    }
+
+    #[test]
+    fn sample_plain_curve_skewed() {
+        for factor in [-2.0, 2.0] {
+            assert_monotonic_curve(
+                FloatParam::new(
+                    "Foo",
+                    1000.0,
+                    FloatRange::Skewed {
+                        min: 20.0,
+                        max: 20_000.0,
+                        factor: FloatRange::skew_factor(factor),
+                    },
+                ),
+                true,
+            );
+        }
+    }
+
+    #[test]
+    fn sample_plain_curve_symmetrical_skewed() {
+        for factor in [-2.0, 2.0] {
+            assert_monotonic_curve(
+                FloatParam::new(
+                    "Foo",
+                    0.0,
+                    FloatRange::SymmetricalSkewed {
+                        min: -10.0,
+                        max: 20.0,
+                        factor: FloatRange::skew_factor(factor),
+                        center: 5.0,
+                    },
+                ),
+                true,
+            );
+        }
+    }
+
+    #[test]
+    fn sample_plain_curve_reversed() {
+        static RANGE: FloatRange = FloatRange::Skewed {
+            min: 20.0,
+            max: 20_000.0,
+            factor: 0.35,
+        };
+
+        assert_monotonic_curve(
+            FloatParam::new("Foo", 1000.0, FloatRange::Reversed(&RANGE)),
+            false,
+        );
+    }
+
+    #[test]
+    fn sample_plain_curve_step_size() {
+        let param = FloatParam::new("Foo", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+            .with_step_size(0.25);
+        let mut curve = [0.0; 5];
+        param.sample_plain_curve(&mut curve);
+        assert_eq!(curve, [0.0, 0.25, 0.5, 0.75, 1.0]);
+
+        let mut curve = [0.0; 9];
+        param.sample_plain_curve(&mut curve);
+        for window in curve.windows(2) {
+            assert!(window[0] <= window[1], "{window:?} is not increasing");
+        }
+        assert!(curve.iter().all(|value| (value * 4.0).fract() == 0.0));
+    }
+}
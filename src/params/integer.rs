@@ -233,6 +233,14 @@ impl ParamMut for IntParam {
                 .set_target(sample_rate, self.modulated_plain_value());
         }
     }
+
+    fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32) {
+        self.smoothed.set_target_with_duration(
+            sample_rate,
+            self.modulated_plain_value(),
+            duration_ms,
+        );
+    }
 }
 
 impl IntParam {
@@ -363,10 +371,61 @@ impl IntParam {
         self
     }
 
+    /// Mark the parameter as a read-only output parameter. The host can display the parameter's
+    /// value, but only the plugin can change it by calling
+    /// [`ProcessContext::set_output_parameter()`][crate::prelude::ProcessContext::set_output_parameter()]
+    /// from its process function. This also implies `NON_AUTOMATABLE`.
+    pub fn read_only(mut self) -> Self {
+        self.flags.insert(ParamFlags::READ_ONLY);
+        self
+    }
+
     /// Don't show this parameter when generating a generic UI for the plugin using one of
     /// NIH-plug's generic UI widgets.
     pub fn hide_in_generic_ui(mut self) -> Self {
         self.flags.insert(ParamFlags::HIDE_IN_GENERIC_UI);
         self
     }
+
+    /// Keep the sample offsets of this parameter's automation when dense automation gets merged.
+    /// See [`ParamFlags::EXACT_AUTOMATION`] for more information.
+    pub fn with_exact_automation(mut self) -> Self {
+        self.flags.insert(ParamFlags::EXACT_AUTOMATION);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_plain_curve() {
+        let param = IntParam::new("Foo", 0, IntRange::Linear { min: -2, max: 2 });
+        let mut curve = [0; 33];
+        param.sample_plain_curve(&mut curve);
+
+        assert_eq!(curve[0], param.min_plain_value());
+        assert_eq!(curve[curve.len() - 1], param.max_plain_value());
+        assert!(curve.windows(2).all(|window| window[0] <= window[1]));
+        for value in -2..=2 {
+            assert!(curve.contains(&value));
+        }
+    }
+
+    #[test]
+    fn sample_plain_curve_reversed() {
+        static RANGE: IntRange = IntRange::Linear { min: -2, max: 2 };
+        let param = IntParam::new("Foo", 0, IntRange::Reversed(&RANGE));
+        let mut curve = [0; 33];
+        param.sample_plain_curve(&mut curve);
+
+        assert_eq!(param.min_plain_value(), 2);
+        assert_eq!(param.max_plain_value(), -2);
+        assert!(curve.windows(2).all(|window| window[0] >= window[1]));
+
+        let mut normalized = [0.0; 33];
+        param.normalized_for_plain_slice(&curve, &mut normalized);
+        assert!(normalized.windows(2).all(|window| window[0] <= window[1]));
+    }
 }
@@ -77,6 +77,7 @@ impl ParamPtr {
     param_ptr_forward!(pub(crate) unsafe fn set_normalized_value(&self, normalized: f32));
     param_ptr_forward!(pub(crate) unsafe fn modulate_value(&self, modulation_offset: f32));
     param_ptr_forward!(pub(crate) unsafe fn update_smoother(&self, sample_rate: f32, reset: bool));
+    param_ptr_forward!(pub(crate) unsafe fn update_smoother_with_duration(&self, sample_rate: f32, duration_ms: f32));
 
     // These functions involve casts since the plugin formats only do floating point types, so we
     // can't generate them with the macro:
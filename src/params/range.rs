@@ -15,7 +15,8 @@ pub enum FloatRange {
     Skewed { min: f32, max: f32, factor: f32 },
     /// The same as [`FloatRange::Skewed`], but with the skewing happening from a central point.
     /// This central point is rescaled to be at 50% of the parameter's range for convenience of use.
-    /// Git blame this comment to find a version that doesn't do this.
+    /// Git blame this comment to find a version that doesn't do this. The center should lie within
+    /// `[min, max]`, and like with [`FloatRange::Skewed`] the factor should be positive.
     SymmetricalSkewed {
         min: f32,
         max: f32,
@@ -78,7 +79,9 @@ impl FloatRange {
                 // how I managed to implement this correctly on the first try.
                 let unscaled_proportion = (plain.clamp(*min, *max) - min) / (max - min);
                 let center_proportion = (center - min) / (max - min);
-                if unscaled_proportion > center_proportion {
+                // If the center is at the start of the range then the lower half would end up
+                // dividing by zero, so in that case everything falls in the upper half
+                if unscaled_proportion > center_proportion || center_proportion <= 0.0 {
                     // The part above the center gets normalized to a [0, 1] range, skewed, and then
                     // unnormalized and scaled back to the original [center_proportion, 1] range
                     let scaled_proportion = (unscaled_proportion - center_proportion)
@@ -381,6 +384,104 @@ mod tests {
         }
     }
 
+    mod round_trip {
+        use super::*;
+
+        const STEPS: usize = 1000;
+
+        /// Assert that `normalize(unnormalize(x)) == x` within 1e-6 over the entire normalized
+        /// range. Skewing with a factor below 1 squashes the plain values near the start of the
+        /// range together, and since those plain values are stored as `f32`s information gets lost
+        /// there. Those ranges are thus checked with [`assert_plain_round_trip()`] instead.
+        fn assert_normalized_round_trip(range: &FloatRange) {
+            for i in 0..=STEPS {
+                let normalized = i as f32 / STEPS as f32;
+                let round_tripped = range.normalize(range.unnormalize(normalized));
+                assert!(
+                    (round_tripped - normalized).abs() <= 1e-6,
+                    "{range:?}: normalized {normalized} round tripped to {round_tripped}"
+                );
+            }
+        }
+
+        /// Assert that `unnormalize(normalize(x)) == x` within 1e-6 (relative to the range's size)
+        /// over the entire plain range. This is the inverse of [`assert_normalized_round_trip()`],
+        /// and it is only exact for skew factors up to 1 for the same reasons.
+        fn assert_plain_round_trip(range: &FloatRange, min: f32, max: f32) {
+            for i in 0..=STEPS {
+                let plain = min + ((max - min) * (i as f32 / STEPS as f32));
+                let round_tripped = range.unnormalize(range.normalize(plain));
+                assert!(
+                    ((round_tripped - plain) / (max - min)).abs() <= 1e-6,
+                    "{range:?}: plain {plain} round tripped to {round_tripped}"
+                );
+            }
+        }
+
+        #[test]
+        fn linear() {
+            let range = make_linear_float_range();
+            assert_normalized_round_trip(&range);
+            assert_plain_round_trip(&range, 10.0, 20.0);
+        }
+
+        #[test]
+        fn skewed() {
+            for skew in [-2.0, -1.0, -0.5, 0.0] {
+                let range = make_skewed_float_range(FloatRange::skew_factor(skew));
+                assert_plain_round_trip(&range, 10.0, 20.0);
+            }
+            for skew in [0.0, 0.5, 1.0, 2.0] {
+                let range = make_skewed_float_range(FloatRange::skew_factor(skew));
+                assert_normalized_round_trip(&range);
+            }
+        }
+
+        #[test]
+        fn symmetrical_skewed() {
+            for skew in [-2.0, -1.0, -0.5, 0.0] {
+                let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(skew));
+                assert_plain_round_trip(&range, 10.0, 20.0);
+            }
+            for skew in [0.0, 0.5, 1.0, 2.0] {
+                let range = make_symmetrical_skewed_float_range(FloatRange::skew_factor(skew));
+                assert_normalized_round_trip(&range);
+            }
+        }
+
+        #[test]
+        fn symmetrical_skewed_center_at_bounds() {
+            for center in [10.0, 20.0] {
+                let range = FloatRange::SymmetricalSkewed {
+                    min: 10.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                    center,
+                };
+                assert_eq!(range.normalize(center), 0.5);
+                assert_eq!(range.unnormalize(0.5), center);
+
+                // Half of the normalized range maps to a single plain value here, so only the
+                // plain values can be round tripped
+                assert_plain_round_trip(&range, 10.0, 20.0);
+            }
+        }
+
+        #[test]
+        fn reversed() {
+            const WRAPPED_LINEAR_RANGE: FloatRange = make_linear_float_range();
+            const WRAPPED_SKEWED_RANGE: FloatRange = make_skewed_float_range(0.25);
+            const WRAPPED_SYMMETRICAL_SKEWED_RANGE: FloatRange =
+                make_symmetrical_skewed_float_range(4.0);
+
+            let range = FloatRange::Reversed(&WRAPPED_LINEAR_RANGE);
+            assert_normalized_round_trip(&range);
+            assert_plain_round_trip(&range, 10.0, 20.0);
+            assert_plain_round_trip(&FloatRange::Reversed(&WRAPPED_SKEWED_RANGE), 10.0, 20.0);
+            assert_normalized_round_trip(&FloatRange::Reversed(&WRAPPED_SYMMETRICAL_SKEWED_RANGE));
+        }
+    }
+
     mod reversed_linear {
         use super::*;
 
@@ -3,6 +3,11 @@
 use atomic_float::AtomicF32;
 use std::sync::atomic::{AtomicI32, Ordering};
 
+/// The number of time constants after which [`SmoothingStyle::ExponentialTimeConstant`] snaps to the
+/// target value. At that point the smoother has covered all but `e^-10`, or about 0.005%, of the
+/// distance to the target value.
+pub const TIME_CONSTANTS_UNTIL_SNAP: f32 = 10.0;
+
 /// Controls if and how parameters gets smoothed.
 #[derive(Debug, Clone, Copy)]
 pub enum SmoothingStyle {
@@ -27,6 +32,16 @@ pub enum SmoothingStyle {
     /// This results in a smoother transition, with the caveat being that there will be a tiny jump
     /// at the end. Unlike the `Logarithmic` option, this does support crossing the zero value.
     Exponential(f32),
+    /// A one-pole exponential smoother defined by its time constant in milliseconds, the same
+    /// curve as the `Exponential` option. Every sample the current value moves `1 - e^(-1 / (time
+    /// constant * sample rate))` of the remaining distance towards the target. This means that the
+    /// smoother covers 63.2% of the distance in one time constant and 95% of it in three time
+    /// constants, regardless of the sample rate.
+    ///
+    /// Since an exponential curve never reaches its target, the smoother snaps to the target value
+    /// after [`TIME_CONSTANTS_UNTIL_SNAP`] time constants. [`Smoother::is_smoothing()`] returns
+    /// `false` from then on.
+    ExponentialTimeConstant(f32),
 }
 
 /// A smoother, providing a smoothed value for each sample.
@@ -63,6 +78,18 @@ pub struct SmootherIter<'a, T: Smoothable> {
     smoother: &'a Smoother<T>,
 }
 
+/// A copy of a [`Smoother`]'s entire state, obtained through [`Smoother::snapshot()`]. This can be
+/// restored later using [`Smoother::restore()`], for instance to let a stolen voice in a
+/// synthesizer continue smoothing from where it left off for legato playing. The smoothing style
+/// is not part of the snapshot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmootherState<T: Smoothable> {
+    steps_left: i32,
+    step_size: f32,
+    current: f32,
+    target: T,
+}
+
 impl SmoothingStyle {
     /// Utility function to modify the duration to compensate for an oversampling factor. When using
     /// 4x oversampling, the duration needs to be four times as long to compensate for the four
@@ -73,6 +100,9 @@ impl SmoothingStyle {
             SmoothingStyle::Linear(time) => SmoothingStyle::Linear(time * factor),
             SmoothingStyle::Logarithmic(time) => SmoothingStyle::Logarithmic(time * factor),
             SmoothingStyle::Exponential(time) => SmoothingStyle::Exponential(time * factor),
+            SmoothingStyle::ExponentialTimeConstant(time) => {
+                SmoothingStyle::ExponentialTimeConstant(time * factor)
+            }
         }
     }
 
@@ -90,6 +120,10 @@ impl SmoothingStyle {
                 nih_debug_assert!(*time >= 0.0);
                 (sample_rate * time / 1000.0).round() as u32
             }
+            SmoothingStyle::ExponentialTimeConstant(time_constant) => {
+                nih_debug_assert!(*time_constant >= 0.0);
+                (sample_rate * time_constant * TIME_CONSTANTS_UNTIL_SNAP / 1000.0).round() as u32
+            }
         }
     }
 
@@ -113,6 +147,11 @@ impl SmoothingStyle {
             // reaches 99.99% of the target value after `num_steps`. The smoother will snap to the
             // target value after that point.
             SmoothingStyle::Exponential(_) => 0.0001f64.powf((num_steps as f64).recip()) as f32,
+            // The same coefficient, but `num_steps` spans `TIME_CONSTANTS_UNTIL_SNAP` time
+            // constants. That makes this `e^(-1 / time_constant_samples)`.
+            SmoothingStyle::ExponentialTimeConstant(_) => {
+                (-TIME_CONSTANTS_UNTIL_SNAP as f64 / num_steps as f64).exp() as f32
+            }
         }
     }
 
@@ -128,7 +167,9 @@ impl SmoothingStyle {
             SmoothingStyle::None => target,
             SmoothingStyle::Linear(_) => current + step_size,
             SmoothingStyle::Logarithmic(_) => current * step_size,
-            SmoothingStyle::Exponential(_) => (current * step_size) + (target * (1.0 - step_size)),
+            SmoothingStyle::Exponential(_) | SmoothingStyle::ExponentialTimeConstant(_) => {
+                (current * step_size) + (target * (1.0 - step_size))
+            }
         }
     }
 
@@ -146,7 +187,7 @@ impl SmoothingStyle {
             SmoothingStyle::None => target,
             SmoothingStyle::Linear(_) => current + (step_size * steps as f32),
             SmoothingStyle::Logarithmic(_) => current * (step_size.powi(steps as i32)),
-            SmoothingStyle::Exponential(_) => {
+            SmoothingStyle::Exponential(_) | SmoothingStyle::ExponentialTimeConstant(_) => {
                 // This is the same as calculating `current = (current * step_size) +
                 // (target * (1 - step_size))` in a loop since the target value won't change
                 let coefficient = step_size.powi(steps as i32);
@@ -250,11 +291,75 @@ impl<T: Smoothable> Smoother<T> {
         self.steps_left.store(0, Ordering::Relaxed);
     }
 
+    /// Change the smoother's current value without touching the target value. If the smoother is
+    /// still smoothing, then it will continue from the new current value and it will still reach
+    /// the target in [`steps_left()`][Self::steps_left()] steps. If it is not, then
+    /// [`next()`][Self::next()] keeps yielding the target value until the next call to
+    /// [`set_target()`][Self::set_target()], which then starts smoothing from `current`.
+    pub fn reset_to(&self, current: T) {
+        let current = current.to_f32();
+        self.current.store(current, Ordering::Relaxed);
+
+        let steps_left = self.steps_left.load(Ordering::Relaxed);
+        if steps_left > 0 {
+            let target_f32 = T::atomic_load(&self.target).to_f32();
+            self.step_size.store(
+                self.style.step_size(current, target_f32, steps_left as u32),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Create a copy of this smoother that uses the same style and target value, but that starts
+    /// from a different current value. The copy reaches the target value at the same time as this
+    /// smoother. See [`reset_to()`][Self::reset_to()] for more information.
+    pub fn clone_with_current(&self, current: T) -> Self {
+        let smoother = self.clone();
+        smoother.reset_to(current);
+
+        smoother
+    }
+
+    /// Get a copy of the smoother's current state. This can be restored at a later point using
+    /// [`restore()`][Self::restore()].
+    pub fn snapshot(&self) -> SmootherState<T> {
+        SmootherState {
+            steps_left: self.steps_left.load(Ordering::Relaxed),
+            step_size: self.step_size.load(Ordering::Relaxed),
+            current: self.current.load(Ordering::Relaxed),
+            target: T::atomic_load(&self.target),
+        }
+    }
+
+    /// Restore a state obtained through [`snapshot()`][Self::snapshot()]. The smoother then yields
+    /// exactly the same values it would have yielded at the time the snapshot was taken, even if
+    /// the target has been changed since then.
+    pub fn restore(&self, state: SmootherState<T>) {
+        self.steps_left.store(state.steps_left, Ordering::Relaxed);
+        self.step_size.store(state.step_size, Ordering::Relaxed);
+        self.current.store(state.current, Ordering::Relaxed);
+        T::atomic_store(&self.target, state.target);
+    }
+
     /// Set the target value.
     pub fn set_target(&self, sample_rate: f32, target: T) {
+        self.set_target_with_steps(target, self.style.num_steps(sample_rate));
+    }
+
+    /// The same as [`set_target()`][Self::set_target()], but the target will be reached in
+    /// `duration_ms` milliseconds instead of the smoothing style's duration. The smoothing style's
+    /// curve is still used. Used when restoring state, see
+    /// [`Plugin::STATE_LOAD_SMOOTHING_MS`][crate::prelude::Plugin::STATE_LOAD_SMOOTHING_MS].
+    pub(crate) fn set_target_with_duration(&self, sample_rate: f32, target: T, duration_ms: f32) {
+        nih_debug_assert!(sample_rate > 0.0 && duration_ms >= 0.0);
+        let num_steps = (sample_rate * duration_ms / 1000.0).round() as u32;
+        self.set_target_with_steps(target, num_steps.max(1));
+    }
+
+    fn set_target_with_steps(&self, target: T, num_steps: u32) {
         T::atomic_store(&self.target, target);
 
-        let steps_left = self.style.num_steps(sample_rate) as i32;
+        let steps_left = num_steps as i32;
         self.steps_left.store(steps_left, Ordering::Relaxed);
 
         let current = self.current.load(Ordering::Relaxed);
@@ -475,6 +580,23 @@ impl<T: Smoothable> Smoother<T> {
     }
 }
 
+impl<T: Smoothable> SmootherState<T> {
+    /// The smoother's current value at the time the snapshot was taken.
+    pub fn current(&self) -> T {
+        T::from_f32(self.current)
+    }
+
+    /// The value the smoother was smoothing towards at the time the snapshot was taken.
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    /// The number of smoothing steps that were left at the time the snapshot was taken.
+    pub fn steps_left(&self) -> i32 {
+        self.steps_left.max(0)
+    }
+}
+
 impl Smoothable for f32 {
     type Atomic = AtomicF32;
 
@@ -605,6 +727,21 @@ mod tests {
         assert_eq!(smoother.next(), 20.0);
     }
 
+    #[test]
+    fn linear_f32_smoothing_with_duration() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+
+        // This should take 20 steps instead of the 10 steps from the smoothing style
+        smoother.set_target_with_duration(100.0, 20.0, 200.0);
+        assert_eq!(smoother.next(), 10.5);
+        for _ in 0..(20 - 3) {
+            smoother.next();
+        }
+        assert_ne!(smoother.next(), 20.0);
+        assert_eq!(smoother.next(), 20.0);
+    }
+
     #[test]
     fn linear_i32_smoothing() {
         let smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Linear(100.0));
@@ -703,5 +840,231 @@ mod tests {
         assert_eq!(smoother.next(), 20);
     }
 
-    // TODO: Tests for the exponential smoothing
+    #[test]
+    fn linear_f32_reset_to() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        for _ in 0..4 {
+            smoother.next();
+        }
+
+        // The target should still be reached in the remaining six steps, but now starting from 8.0
+        smoother.reset_to(8.0);
+        assert_eq!(smoother.steps_left(), 6);
+        assert_eq!(smoother.next(), 10.0);
+        for _ in 0..(6 - 3) {
+            smoother.next();
+        }
+        assert_ne!(smoother.next(), 20.0);
+        assert_eq!(smoother.next(), 20.0);
+    }
+
+    #[test]
+    fn linear_i32_reset_to() {
+        let smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10);
+        smoother.set_target(100.0, 20);
+        for _ in 0..4 {
+            smoother.next();
+        }
+
+        smoother.reset_to(8);
+        assert_eq!(smoother.steps_left(), 6);
+        assert_eq!(smoother.next(), 10);
+        for _ in 0..(6 - 3) {
+            smoother.next();
+        }
+        assert_ne!(smoother.next(), 20);
+        assert_eq!(smoother.next(), 20);
+    }
+
+    /// Without any smoothing left, the new current value is only used once a new target is set.
+    #[test]
+    fn reset_to_after_smoothing() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        smoother.reset_to(0.0);
+        assert_eq!(smoother.next(), 10.0);
+
+        smoother.set_target(100.0, 10.0);
+        assert_eq!(smoother.next(), 1.0);
+    }
+
+    #[test]
+    fn clone_with_current() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        smoother.next_step(2);
+
+        // Both smoothers should reach the target at the same time, and the original smoother
+        // should not be affected
+        let clone = smoother.clone_with_current(40.0);
+        assert_eq!(clone.steps_left(), smoother.steps_left());
+        assert!(smoother.previous_value() < 20.0);
+        assert_eq!(clone.previous_value(), 40.0);
+        for _ in 0..(8 - 1) {
+            assert!(smoother.next() < 20.0);
+            assert!(clone.next() > 20.0);
+        }
+        assert_eq!(smoother.next(), 20.0);
+        assert_eq!(clone.next(), 20.0);
+    }
+
+    /// Restoring a snapshot after retargeting should continue exactly where the smoother left off.
+    #[test]
+    fn linear_f32_snapshot_restore() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Linear(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        smoother.next_step(3);
+
+        let state = smoother.snapshot();
+        let reference = smoother.clone();
+        assert_eq!(state.current(), 13.0);
+        assert_eq!(state.target(), 20.0);
+        assert_eq!(state.steps_left(), 7);
+
+        smoother.set_target(100.0, 50.0);
+        smoother.next_step(5);
+        smoother.restore(state);
+        assert_eq!(smoother.snapshot(), state);
+        for _ in 0..10 {
+            assert_eq!(smoother.next(), reference.next());
+        }
+        assert_eq!(smoother.next(), 20.0);
+    }
+
+    #[test]
+    fn logarithmic_i32_snapshot_restore() {
+        let smoother: Smoother<i32> = Smoother::new(SmoothingStyle::Logarithmic(100.0));
+        smoother.reset(10);
+        smoother.set_target(100.0, 20);
+        smoother.next_step(3);
+
+        let state = smoother.snapshot();
+        let reference = smoother.clone();
+
+        smoother.set_target(100.0, 50);
+        smoother.next_step(5);
+        smoother.restore(state);
+        for _ in 0..10 {
+            assert_eq!(smoother.next(), reference.next());
+        }
+        assert_eq!(smoother.next(), 20);
+    }
+
+    /// The block based API should produce the same values after restoring a snapshot.
+    #[test]
+    fn snapshot_restore_block() {
+        let smoother: Smoother<f32> = Smoother::new(SmoothingStyle::Exponential(100.0));
+        smoother.reset(10.0);
+        smoother.set_target(100.0, 20.0);
+        let state = smoother.snapshot();
+
+        let mut expected = [0.0; 16];
+        smoother.next_block_exact(&mut expected);
+
+        smoother.set_target(100.0, 0.0);
+        smoother.restore(state);
+        let mut actual = [0.0; 16];
+        smoother.next_block_exact(&mut actual);
+        assert_eq!(actual, expected);
+        assert_eq!(actual[9], 20.0);
+        assert_eq!(actual[15], 20.0);
+    }
+
+    /// The time constant should mean the same thing at every sample rate: 63.2% of the distance is
+    /// covered after one time constant, and 95% after three time constants.
+    #[test]
+    fn exponential_time_constant_convergence() {
+        for sample_rate in [44_100.0, 192_000.0] {
+            let smoother: Smoother<f32> =
+                Smoother::new(SmoothingStyle::ExponentialTimeConstant(10.0));
+            smoother.reset(0.0);
+            smoother.set_target(sample_rate, 1.0);
+
+            let time_constant_samples = sample_rate * 10.0 / 1000.0;
+            let mut reached_63_percent = None;
+            let mut reached_95_percent = None;
+            let mut num_steps = 0;
+            while smoother.is_smoothing() {
+                let value = smoother.next();
+                num_steps += 1;
+
+                if value >= 1.0 - (-1.0f32).exp() && reached_63_percent.is_none() {
+                    reached_63_percent = Some(num_steps);
+                }
+                if value >= 0.95 && reached_95_percent.is_none() {
+                    reached_95_percent = Some(num_steps);
+                }
+
+                assert!(num_steps <= 100_000, "The smoother never stopped smoothing");
+            }
+
+            approx::assert_abs_diff_eq!(
+                reached_63_percent.unwrap() as f32,
+                time_constant_samples,
+                epsilon = 1.0
+            );
+            approx::assert_abs_diff_eq!(
+                reached_95_percent.unwrap() as f32,
+                time_constant_samples * 20.0f32.ln(),
+                epsilon = 1.0
+            );
+
+            // The smoother snaps to the target value after `TIME_CONSTANTS_UNTIL_SNAP` time
+            // constants
+            assert_eq!(
+                num_steps as f32,
+                (time_constant_samples * TIME_CONSTANTS_UNTIL_SNAP).round()
+            );
+            assert_eq!(smoother.previous_value(), 1.0);
+            assert_eq!(smoother.next(), 1.0);
+            assert!(!smoother.is_smoothing());
+        }
+    }
+
+    #[test]
+    fn exponential_time_constant_f32_next_equivalence() {
+        let style = SmoothingStyle::ExponentialTimeConstant(100.0);
+
+        let mut current = 0.4;
+        let target = 0.8;
+        let steps = 15;
+        let step_size = style.step_size(current, target, steps);
+
+        let expected_result = style.next_step(current, target, step_size, steps);
+        for _ in 0..steps {
+            current = style.next(current, target, step_size);
+        }
+
+        approx::assert_relative_eq!(current, expected_result, epsilon = 1e-5);
+    }
+
+    /// The block based API should yield the same values as `next()`, and it should also stop
+    /// smoothing. This also works for integers.
+    #[test]
+    fn exponential_time_constant_i32_block() {
+        let smoother: Smoother<i32> = Smoother::new(SmoothingStyle::ExponentialTimeConstant(5.0));
+        smoother.reset(0);
+        smoother.set_target(44_100.0, 1000);
+        let reference = smoother.clone();
+
+        let mut block = [0; 512];
+        let mut num_blocks = 0;
+        while smoother.is_smoothing() {
+            smoother.next_block_exact(&mut block);
+            for value in block {
+                assert_eq!(value, reference.next());
+            }
+
+            num_blocks += 1;
+            assert!(num_blocks <= 100, "The smoother never stopped smoothing");
+        }
+
+        assert_eq!(block[511], 1000);
+        assert!(!reference.is_smoothing());
+    }
 }
@@ -4,13 +4,15 @@ use std::sync::Arc;
 
 use crate::buffer::Buffer;
 use crate::context::init::InitContext;
-use crate::context::process::ProcessContext;
+use crate::context::process::{ProcessContext, TransportRequirements};
 use crate::editor::Editor;
-use crate::midi::MidiConfig;
+use crate::midi::routing::MidiInputConfig;
+use crate::midi::{MidiConfig, NoteExpressions};
 use crate::params::Params;
 use crate::prelude::AsyncExecutor;
 use crate::wrapper::clap::features::ClapFeature;
 use crate::wrapper::state::PluginState;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
 
 /// A function that can execute a plugin's [`BackgroundTask`][Plugin::BackgroundTask]s. A plugin can
 /// dispatch these tasks from the `initialize()` function, the `process()` function, or the GUI, so
@@ -24,6 +26,18 @@ pub type TaskExecutor<P> = Box<dyn Fn(<P as Plugin>::BackgroundTask) + Send>;
 /// See the trait's documentation for more information on how to do that, or check out the examples.
 /// Most of the other functionality is optional and comes with default trait method implementations.
 ///
+/// The plugin is constructed through its `Default` implementation. Hosts create plugin instances on
+/// the main thread when scanning for plugins, often without ever activating them, so this should be
+/// cheap. Large buffers and other expensive resources should be allocated in
+/// [`initialize()`][Self::initialize()] instead, for instance using
+/// [`util::LateInit`][crate::util::LateInit].
+///
+/// The wrappers guarantee the following call order, regardless of the order the host calls into
+/// the plugin in: `Default`, then [`initialize()`][Self::initialize()] (which may be called again
+/// later), then [`reset()`][Self::reset()], then any number of [`process()`][Self::process()]
+/// calls, and finally [`deactivate()`][Self::deactivate()]. `process()` is never called before
+/// `initialize()` or after `deactivate()`. Out of order host calls are rejected by the wrapper.
+///
 /// Some notable not yet implemented features include:
 ///
 /// - MIDI SysEx and MIDI2 for CLAP, note expressions, polyphonic modulation and MIDI1 are already
@@ -50,7 +64,11 @@ pub trait Plugin: Default + Send + 'static {
     /// passed to [`initialize()`][Self::initialize()]. Some hosts like, like Bitwig and Ardour, use
     /// the defaults instead of setting up the busses properly.
     ///
-    /// Setting this to zero causes the plugin to have no main input bus.
+    /// Setting this to zero causes the plugin to have no main input bus. This is what generators and
+    /// instruments should do. Hosts then won't see an input bus at all, and the standalone won't
+    /// open an audio input device. For VST3 plugins you'll likely also want to include
+    /// [`Vst3SubCategory::Instrument`] or [`Vst3SubCategory::Generator`] in
+    /// [`Vst3Plugin::VST3_SUBCATEGORIES`].
     const DEFAULT_INPUT_CHANNELS: u32 = 2;
     /// The default number of output channels. All of the same caveats mentioned for
     /// `DEFAULT_INPUT_CHANNELS` apply here.
@@ -66,6 +84,12 @@ pub trait Plugin: Default + Send + 'static {
     /// channels. Negotiating the actual configuration works the same was as with
     /// `DEFAULT_INPUT_CHANNELS`.
     const DEFAULT_AUX_OUTPUTS: Option<AuxiliaryIOConfig> = None;
+    /// What the wrapper should do when the host wants to use a main channel layout the plugin does
+    /// not accept, like when a stereo-only plugin is inserted on a mono track. By default such
+    /// layouts are rejected. With [`ChannelAdaptation::DuplicateDownmix`] the wrapper will instead
+    /// run the plugin with a mono or stereo layout it does accept and convert between the two
+    /// layouts before and after processing. This is currently only done by the VST3 wrapper.
+    const CHANNEL_ADAPTATION: ChannelAdaptation = ChannelAdaptation::Reject;
 
     /// Optional names for the main and auxiliary input and output ports. Will be generated if not
     /// set. This is mostly useful to give descriptive names to the outputs for multi-output
@@ -85,17 +109,109 @@ pub trait Plugin: Default + Send + 'static {
     /// the plugin will consume all note and MIDI CC input. If you don't want that, then you will
     /// need to forward those events yourself.
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
+    /// Which MIDI channels the plugin responds to, whether it uses MPE, and its preferred pitch bend
+    /// range. Events on other channels are dropped before they reach
+    /// [`ProcessContext::next_event()`], and on MPE member channels pitch bend, channel pressure,
+    /// and CC 74 are converted to polyphonic expression events for the notes on those channels.
+    /// This is handled by the VST3 and standalone wrappers. See [`MidiInputConfig`].
+    const MIDI_INPUT_CONFIG: MidiInputConfig = MidiInputConfig::DEFAULT;
+    /// The polyphonic note expressions the plugin responds to. The VST3 wrapper only declares these
+    /// expressions to the host, so hosts only show expression lanes for them, and VST3 note
+    /// expression events for other expressions are dropped before they reach
+    /// [`ProcessContext::next_event()`]. This only has an effect when
+    /// [`MIDI_INPUT`][Self::MIDI_INPUT] is set to [`MidiConfig::Basic`] or higher.
+    const SUPPORTED_NOTE_EXPRESSIONS: NoteExpressions = NoteExpressions::all();
     /// If enabled, the audio processing cycle may be split up into multiple smaller chunks if
     /// parameter values change occur in the middle of the buffer. Depending on the host these
     /// blocks may be as small as a single sample. Bitwig Studio sends at most one parameter change
     /// every 64 samples.
     const SAMPLE_ACCURATE_AUTOMATION: bool = false;
+    /// If enabled, the audio processing cycle is also split up at every incoming note event, so
+    /// [`process()`][Self::process()] only ever receives events at the very start of the block. A
+    /// simple synth can then handle all of its events at the start of the process function and
+    /// still start its notes at the exact sample. This composes with
+    /// [`SAMPLE_ACCURATE_AUTOMATION`][Self::SAMPLE_ACCURATE_AUTOMATION], in which case the blocks
+    /// are split at both parameter changes and note events.
+    const SAMPLE_ACCURATE_EVENTS: bool = false;
+    /// The maximum number of times a block may be split up because of parameter changes when
+    /// [`SAMPLE_ACCURATE_AUTOMATION`][Self::SAMPLE_ACCURATE_AUTOMATION] is enabled. Some hosts
+    /// send hundreds of automation points per block for dense automation, which would otherwise
+    /// result in hundreds of tiny blocks. When a block contains more split points than this, or
+    /// when the changes are closer together than
+    /// [`MIN_AUTOMATION_SPLIT_LENGTH`][Self::MIN_AUTOMATION_SPLIT_LENGTH], the changes are merged
+    /// so every sub-block uses the values the parameters had at the sub-block's last sample. The
+    /// values at the end of the block are always exact. Changes for parameters with
+    /// [`ParamFlags::EXACT_AUTOMATION`][crate::prelude::ParamFlags::EXACT_AUTOMATION] keep their
+    /// sample offsets whenever possible. This is only done by the VST3 wrapper.
+    const MAX_AUTOMATION_SPLITS: u32 = 32;
+    /// The shortest sub-block in samples that merged parameter changes may result in. See
+    /// [`MAX_AUTOMATION_SPLITS`][Self::MAX_AUTOMATION_SPLITS].
+    const MIN_AUTOMATION_SPLIT_LENGTH: u32 = 16;
 
     /// If this is set to true, then the plugin will report itself as having a hard realtime
     /// processing requirement when the host asks for it. Supported hosts will never ask the plugin
     /// to do offline processing.
     const HARD_REALTIME_ONLY: bool = false;
 
+    /// The highest sample rate the plugin expects to run at. Plugins can query this through
+    /// [`InitContext::max_expected_sample_rate()`] to allocate buffers like delay lines for the
+    /// worst case up front, so they don't need to reallocate when the host changes the sample rate
+    /// later. The wrappers never use a value lower than the current sample rate, and the standalone
+    /// target uses the sample rate it actually runs at.
+    const MAX_SUPPORTED_SAMPLE_RATE: f32 = 192_000.0;
+    /// If this is set to true, then the plugin promises that it allocated everything it needs for
+    /// [`InitContext::max_expected_sample_rate()`] in [`initialize()`][Self::initialize()], and
+    /// that it keeps those allocations around when [`deactivate()`][Self::deactivate()] is called.
+    /// When the host then reactivates the plugin at a different sample rate with the same bus and
+    /// buffer configurations, the wrapper calls
+    /// [`sample_rate_changed()`][Self::sample_rate_changed()] instead of reinitializing the plugin,
+    /// and it skips the [`reset()`][Self::reset()] that normally follows an initialization. This
+    /// lets the plugin keep its state across the sample rate change. This is done by the VST3 and
+    /// CLAP wrappers.
+    const PREALLOCATES_FOR_MAX_SAMPLE_RATE: bool = false;
+
+    /// When the host or the plugin's editor restores the plugin's state while the plugin is
+    /// processing audio, the restored parameter values are normally snapped to immediately. If this
+    /// is set to a non-zero value, then the parameters' smoothers will instead ramp to the restored
+    /// values over this many milliseconds, using the parameters' own smoothing styles. Parameters
+    /// without smoothing and the unsmoothed `value` fields still change immediately, as do any
+    /// persisted fields. State loaded while the plugin is not processing audio is always applied
+    /// immediately.
+    const STATE_LOAD_SMOOTHING_MS: f32 = 0.0;
+
+    /// If this is set to a non-zero value, then the wrapper fades in the plugin's main output over
+    /// this many milliseconds whenever the plugin starts processing audio. This avoids clicks when
+    /// the plugin is inserted or enabled during playback. The fade is applied after the plugin has
+    /// processed the audio, so the plugin's state still warms up as usual during the fade. It is not
+    /// applied while the wrapper's own bypass parameter passes the audio through unchanged.
+    ///
+    /// There is no matching fade-out. Hosts deactivate plugins without giving them another chance
+    /// to process audio, so only the fade-in side can be handled reliably by the wrapper.
+    const ACTIVATION_FADE_MS: f32 = 0.0;
+
+    /// The oversampling factors the wrapper may run the entire plugin at, for instance
+    /// `&[1, 2, 4]`. This needs to contain consecutive powers of two starting at 1. If it contains
+    /// more than one factor, then the wrapper adds a non-automatable oversampling parameter with
+    /// these factors to the plugin's parameters. The wrapper then upsamples the plugin's inputs,
+    /// calls [`process()`][Self::process()] with the upsampled buffers, and downsamples the
+    /// outputs again. The plugin sees the oversampled sample rate and block sizes everywhere,
+    /// including in its [`BufferConfig`], the [`Transport`][crate::prelude::Transport], note event
+    /// timings, and its parameter smoothers. Latencies set through
+    /// [`set_latency_samples()`][crate::prelude::InitContext::set_latency_samples()] are also in
+    /// oversampled samples, and the wrapper adds the oversampling filters' latency to them.
+    ///
+    /// Changing the factor reinitializes the plugin the same way a sample rate change would, so
+    /// everything is only allocated in [`initialize()`][Self::initialize()]. At 1x the wrapper
+    /// passes the buffers straight through. This is done by the VST3 and CLAP wrappers.
+    const ALLOW_WRAPPER_OVERSAMPLING: &'static [u8] = &[1];
+
+    /// The transport information this plugin needs. VST3 hosts will only be asked to provide this
+    /// information, and any other fields in [`ProcessContext::transport()`] will always be empty.
+    /// By default this contains the play state, the tempo, and the position in the song.
+    const REQUIRED_TRANSPORT: TransportRequirements = TransportRequirements::TRANSPORT_STATE
+        .union(TransportRequirements::TEMPO)
+        .union(TransportRequirements::PROJECT_TIME);
+
     /// A type encoding the different background tasks this plugin wants to run, or `()` if it
     /// doesn't have any background tasks. This is usually set to an enum type. The task type should
     /// not contain any heap allocated data like [`Vec`]s and [`Box`]es. Tasks can be send using the
@@ -111,6 +227,16 @@ pub trait Plugin: Default + Send + 'static {
         Box::new(|_| ())
     }
 
+    /// The priority class for a background task scheduled with one of the contexts'
+    /// `execute_background()` functions. Background tasks from all plugin instances in the process
+    /// share a small pool of worker threads. Interactive tasks are always picked up first, while
+    /// long running bulk tasks like scanning files should use [`BackgroundTaskPriority::Bulk`] so
+    /// they don't hold up other instances' interactive tasks. Tasks with the same priority run one
+    /// at a time in the order they were scheduled.
+    fn background_task_priority(_task: &Self::BackgroundTask) -> BackgroundTaskPriority {
+        BackgroundTaskPriority::Interactive
+    }
+
     /// The plugin's parameters. The host will update the parameter values before calling
     /// `process()`. These parameters are identified by strings that should never change when the
     /// plugin receives an update.
@@ -138,6 +264,16 @@ pub trait Plugin: Default + Send + 'static {
     /// This is an advanced feature that the vast majority of plugins won't need to implement.
     fn filter_state(state: &mut PluginState) {}
 
+    /// Called after a [`PluginState`] has been loaded, once the parameters and the persistent
+    /// fields have been restored. This is always called right before the plugin gets initialized
+    /// through [`initialize()`][Self::initialize()]. If the plugin is already initialized when the
+    /// state is loaded, then it is reinitialized immediately. If it's not, for instance because the
+    /// host restored a project before activating the plugin, then this call is deferred until the
+    /// plugin gets initialized for the first time. This makes the behavior the same regardless of
+    /// the order the host loads state and activates the plugin in. Loading more than one state
+    /// before the plugin gets initialized results in a single call.
+    fn state_restored(&mut self) {}
+
     //
     // The following functions follow the lifetime of the plugin.
     //
@@ -156,7 +292,8 @@ pub trait Plugin: Default + Send + 'static {
     /// whatsoever) the plugin needs to introduce latency, then you can do so here using the process
     /// context. Depending on how the host restores plugin state, this function may also be called
     /// twice in rapid succession. If the plugin fails to initialize for whatever reason, then this
-    /// should return `false`.
+    /// should return `false`. Implement [`try_initialize()`][Self::try_initialize()] instead if you
+    /// want to tell the user why the plugin could not be initialized.
     ///
     /// Before this point, the plugin should not have done any expensive initialization. Please
     /// don't be that plugin that takes twenty seconds to scan.
@@ -172,11 +309,42 @@ pub trait Plugin: Default + Send + 'static {
         true
     }
 
+    /// The same as [`initialize()`][Self::initialize()], but with an [`InitError`] describing why
+    /// the plugin could not be initialized. The wrappers always call this function instead of
+    /// `initialize()`, and the error's message is logged and shown to the user where possible.
+    /// Plugins that can fail to initialize should implement this function instead of
+    /// `initialize()`. The default implementation calls `initialize()` and returns a generic error
+    /// if that returns `false`.
+    fn try_initialize(
+        &mut self,
+        bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> Result<(), InitError> {
+        if self.initialize(bus_config, buffer_config, context) {
+            Ok(())
+        } else {
+            Err(InitError::new(
+                InitErrorKind::Other,
+                "The plugin failed to initialize",
+            ))
+        }
+    }
+
     /// Clear internal state such as filters and envelopes. This is always called after
     /// [`initialize()`][Self::initialize()], and it may also be called at any other time from the
     /// audio thread. You should thus not do any allocations in this function.
     fn reset(&mut self) {}
 
+    /// Called instead of [`initialize()`][Self::initialize()] when the host reactivates the plugin
+    /// at a different sample rate if
+    /// [`PREALLOCATES_FOR_MAX_SAMPLE_RATE`][Self::PREALLOCATES_FOR_MAX_SAMPLE_RATE] is set. The
+    /// new sample rate is never higher than the maximum expected sample rate from the last
+    /// initialization. This is where the plugin should update its coefficients and rescale things
+    /// like delay line read positions without clearing any state. Everything else about the
+    /// [`BufferConfig`] stays the same.
+    fn sample_rate_changed(&mut self, new_sample_rate: f32) {}
+
     /// Process audio. The host's input buffers have already been copied to the output buffers if
     /// they are not processing audio in place (most hosts do however). All channels are also
     /// guaranteed to contain the same number of samples. Lastly, denormals have already been taken
@@ -236,22 +404,53 @@ pub trait ClapPlugin: Plugin {
 }
 
 /// Provides auxiliary metadata needed for a VST3 plugin.
+#[allow(unused_variables)]
 pub trait Vst3Plugin: Plugin {
     /// The unique class ID that identifies this particular plugin. You can use the
     /// `*b"fooofooofooofooo"` syntax for this.
     ///
     /// This will be shuffled into a different byte order on Windows for project-compatibility.
     const VST3_CLASS_ID: [u8; 16];
+    /// One or more subcategories, starting with [`Vst3SubCategory::Fx`],
+    /// [`Vst3SubCategory::Instrument`], or [`Vst3SubCategory::Spatial`]. The subcategories are
+    /// joined with pipe characters (`|`), and subcategories that don't fit in VST3's 127 character
+    /// limit are dropped from the end.
+    ///
+    /// Plugins whose first subcategory is [`Vst3SubCategory::Instrument`] are reported to the host
+    /// as instruments, which don't support VST3's simple IO mode.
+    ///
+    /// This defaults to the deprecated [`VST3_CATEGORIES`][Self::VST3_CATEGORIES] string so
+    /// plugins that still set that string keep working.
+    #[allow(deprecated)]
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Custom(Self::VST3_CATEGORIES)];
     /// One or more categories, separated by pipe characters (`|`), up to 127 characters. Anything
-    /// longer than that will be truncated. See the VST3 SDK for examples of common categories:
-    /// <https://github.com/steinbergmedia/vst3_pluginterfaces/blob/2ad397ade5b51007860bedb3b01b8afd2c5f6fba/vst/ivstaudioprocessor.h#L49-L90>
-    //
-    // TODO: Create a category enum similar to ClapFeature
-    const VST3_CATEGORIES: &'static str;
+    /// longer than that will be truncated. This is only used when
+    /// [`VST3_SUBCATEGORIES`][Self::VST3_SUBCATEGORIES] is not set. In debug builds the categories
+    /// are checked against the list of subcategories from the VST3 SDK when the plugin is loaded.
+    #[deprecated(note = "Use `VST3_SUBCATEGORIES` instead, typos in this string go unnoticed")]
+    const VST3_CATEGORIES: &'static str = "";
+    /// Whether the host may run this plugin in a different process or on a different computer
+    /// than the one it's being used on, as done by for instance Steinberg's VST System Link. This
+    /// sets VST3's `kDistributable` class flag.
+    const VST3_DISTRIBUTABLE: bool = false;
 
     /// [`VST3_CLASS_ID`][Self::VST3_CLASS_ID`] in the correct order for the current platform so
     /// projects and presets can be shared between platforms. This should not be overridden.
     const PLATFORM_VST3_CLASS_ID: [u8; 16] = swap_vst3_uid_byte_order(Self::VST3_CLASS_ID);
+
+    /// VST3 plugins don't receive MIDI CC messages directly. Instead, the host asks the plugin
+    /// which parameter a MIDI CC should control. Return a parameter ID from this function to have
+    /// MIDI CC `cc` on MIDI channel `channel` (starting at 0) control that parameter. `cc` can be
+    /// any of the 128 MIDI CCs, 128 for channel pressure, or 129 for pitch bend. CCs that are not
+    /// mapped to a parameter are sent to the plugin as [`NoteEvent`][crate::prelude::NoteEvent]s
+    /// if [`MIDI_INPUT`][Plugin::MIDI_INPUT] is set to [`MidiConfig::MidiCCs`] or higher.
+    ///
+    /// Parameter IDs that don't exist are ignored. This is not used for the CLAP and standalone
+    /// targets, where the plugin can map the MIDI CC events to parameters itself.
+    fn vst3_midi_cc_mapping(channel: u8, cc: u8) -> Option<&'static str> {
+        None
+    }
 }
 
 #[cfg(not(target_os = "windows"))]
@@ -290,6 +489,29 @@ pub struct BusConfig {
     pub aux_output_busses: AuxiliaryIOConfig,
 }
 
+/// How the wrapper handles main channel layouts the plugin doesn't accept. See
+/// [`Plugin::CHANNEL_ADAPTATION`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelAdaptation {
+    /// Reject the layout. The host will then have to try another layout.
+    Reject,
+    /// Accept mono and stereo layouts the plugin doesn't support by running the plugin with a mono
+    /// or stereo layout it does support. Mono signals are duplicated to both channels, and stereo
+    /// signals are downmixed to mono using a -3 dB pan law. If the host has more output channels
+    /// than the plugin produces, then the remaining channels are filled with silence.
+    DuplicateDownmix,
+}
+
+/// The priority class of a background task. See [`Plugin::background_task_priority()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundTaskPriority {
+    /// Short tasks the user is waiting on, like loading a preset or rebuilding lookup tables after
+    /// a parameter change.
+    Interactive,
+    /// Long running tasks that can wait, like scanning directories or prefetching files from disk.
+    Bulk,
+}
+
 /// Configuration for auxiliary inputs or outputs on [`BusConfig`].
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct AuxiliaryIOConfig {
@@ -321,7 +543,13 @@ pub struct PortNames {
 pub struct BufferConfig {
     /// The current sample rate.
     pub sample_rate: f32,
-    /// The minimum buffer size the host will use. This may not be set.
+    /// The minimum buffer size the host will use. This is `None` if the plugin API or the host
+    /// doesn't provide this information, which is always the case for VST3. The standalone wrapper
+    /// always processes fixed size blocks, so there this is always equal to `max_buffer_size`. If
+    /// the minimum and maximum buffer sizes are equal then every block will have exactly that size,
+    /// which may allow the plugin to skip buffering its input. Keep in mind that blocks may still
+    /// be split up into smaller blocks for sample accurate automation and events if
+    /// [`Plugin::SAMPLE_ACCURATE_AUTOMATION`] or [`Plugin::SAMPLE_ACCURATE_EVENTS`] are enabled.
     pub min_buffer_size: Option<u32>,
     /// The maximum buffer size the host will use. The plugin should be able to accept variable
     /// sized buffers up to this size, or between the minimum and the maximum buffer size if both
@@ -331,6 +559,15 @@ pub struct BufferConfig {
     pub process_mode: ProcessMode,
 }
 
+impl BufferConfig {
+    /// The precision of the samples in the audio buffers passed to the plugin. NIH-plug currently
+    /// only supports single precision processing, so this always returns
+    /// [`ProcessPrecision::Single`].
+    pub fn process_precision(&self) -> ProcessPrecision {
+        ProcessPrecision::Single
+    }
+}
+
 /// Contains auxiliary (sidechain) input and output buffers for a process call.
 pub struct AuxiliaryBuffers<'a> {
     /// All auxiliary (sidechain) inputs defined for this plugin. The data in these buffers can
@@ -358,6 +595,78 @@ pub enum ProcessStatus {
     KeepAlive,
 }
 
+/// The reason why a plugin failed to initialize, returned from
+/// [`Plugin::try_initialize()`]. The wrappers log the error's message, and the standalone
+/// wrapper also prints it before exiting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitError {
+    kind: InitErrorKind,
+    message: String,
+}
+
+/// The category of an [`InitError`]. This lets the wrappers and the user tell common failures
+/// apart without having to parse the error's message.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum InitErrorKind {
+    /// The plugin cannot run at the requested sample rate. `supported` lists the sample rates the
+    /// plugin does support, if there is a fixed set of them.
+    UnsupportedSampleRate { supported: Vec<f32> },
+    /// The plugin could not allocate or create a resource it needs to run, like a buffer or a
+    /// resampler.
+    AllocationFailed,
+    /// Any other reason.
+    Other,
+}
+
+impl InitError {
+    /// Create a new error with a message describing what went wrong.
+    pub fn new(kind: InitErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// An [`InitErrorKind::UnsupportedSampleRate`] error for `sample_rate` with a message listing
+    /// the supported sample rates.
+    pub fn unsupported_sample_rate(sample_rate: f32, supported: Vec<f32>) -> Self {
+        let message = if supported.is_empty() {
+            format!("The plugin does not support a sample rate of {sample_rate} Hz")
+        } else {
+            let supported_str = supported
+                .iter()
+                .map(|rate| format!("{rate} Hz"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "The plugin does not support a sample rate of {sample_rate} Hz, supported sample \
+                 rates are: {supported_str}"
+            )
+        };
+
+        Self::new(InitErrorKind::UnsupportedSampleRate { supported }, message)
+    }
+
+    /// The category of this error.
+    pub fn kind(&self) -> &InitErrorKind {
+        &self.kind
+    }
+
+    /// The human readable message describing this error.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for InitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for InitError {}
+
 /// The plugin's current processing mode. Exposed through [`BufferConfig::process_mode`]. The host
 /// will reinitialize the plugin whenever this changes.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -374,6 +683,40 @@ pub enum ProcessMode {
     Offline,
 }
 
+impl ProcessMode {
+    /// The way a bypass should be applied in this processing mode. See [`BypassMode`].
+    pub fn bypass_mode(self) -> BypassMode {
+        match self {
+            ProcessMode::Realtime | ProcessMode::Buffered => BypassMode::Soft,
+            ProcessMode::Offline => BypassMode::Hard,
+        }
+    }
+}
+
+/// How toggling the bypass should be applied. Exposed through
+/// [`ProcessContext::bypass_mode()`][crate::prelude::ProcessContext::bypass_mode()]. In both modes
+/// the bypassed signal should be the plugin's input delayed by the plugin's latency, so it stays
+/// aligned with the processed signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BypassMode {
+    /// Crossfade between the processed and the bypassed signal to avoid clicks. Used when
+    /// processing audio in realtime, and during buffered processing.
+    Soft,
+    /// Switch between the processed and the bypassed signal instantly without any fading. Used
+    /// when rendering offline, so a bypassed bounce nulls against the unprocessed input.
+    Hard,
+}
+
+/// The precision of the audio buffers passed to the plugin. Exposed through
+/// [`BufferConfig::process_precision()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessPrecision {
+    /// The plugin processes 32-bit floating point samples.
+    Single,
+    /// The plugin processes 64-bit floating point samples.
+    Double,
+}
+
 /// Configuration for the plugin's polyphonic modulation options, if it supports .
 pub struct PolyModulationConfig {
     /// The maximum number of voices this plugin will ever use. Call the context's
@@ -385,3 +728,116 @@ pub struct PolyModulationConfig {
     /// enabling this, you should always prioritize using voice IDs to map note events to voices.
     pub supports_overlapping_voices: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::PluginApi;
+    use crate::params::internals::ParamPtr;
+
+    /// A plugin that still uses the old `initialize()` function and always fails to initialize.
+    #[derive(Default)]
+    struct LegacyFailingPlugin;
+
+    struct NoParams;
+
+    unsafe impl Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    impl Plugin for LegacyFailingPlugin {
+        const NAME: &'static str = "Legacy Failing Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(NoParams)
+        }
+
+        fn initialize(
+            &mut self,
+            _bus_config: &BusConfig,
+            _buffer_config: &BufferConfig,
+            _context: &mut impl InitContext<Self>,
+        ) -> bool {
+            false
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    struct TestInitContext;
+
+    impl<P: Plugin> InitContext<P> for TestInitContext {
+        fn plugin_api(&self) -> PluginApi {
+            PluginApi::Standalone
+        }
+
+        fn execute(&self, _task: P::BackgroundTask) {}
+
+        fn set_latency_samples(&self, _samples: u32) {}
+
+        fn max_expected_sample_rate(&self) -> f32 {
+            P::MAX_SUPPORTED_SAMPLE_RATE
+        }
+
+        fn set_current_voice_capacity(&self, _capacity: u32) {}
+    }
+
+    #[test]
+    fn legacy_initialize_failure() {
+        let bus_config = BusConfig {
+            num_input_channels: 2,
+            num_output_channels: 2,
+            aux_input_busses: AuxiliaryIOConfig::default(),
+            aux_output_busses: AuxiliaryIOConfig::default(),
+        };
+        let buffer_config = BufferConfig {
+            sample_rate: 44_100.0,
+            min_buffer_size: None,
+            max_buffer_size: 512,
+            process_mode: ProcessMode::Realtime,
+        };
+
+        let error = LegacyFailingPlugin
+            .try_initialize(&bus_config, &buffer_config, &mut TestInitContext)
+            .unwrap_err();
+        assert_eq!(error.kind(), &InitErrorKind::Other);
+        assert_eq!(error.to_string(), "The plugin failed to initialize");
+    }
+
+    #[test]
+    fn unsupported_sample_rate_message() {
+        let error = InitError::unsupported_sample_rate(22050.0, vec![44100.0, 48000.0]);
+        assert_eq!(
+            error.kind(),
+            &InitErrorKind::UnsupportedSampleRate {
+                supported: vec![44100.0, 48000.0]
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "The plugin does not support a sample rate of 22050 Hz, supported sample rates are: \
+             44100 Hz, 48000 Hz"
+        );
+
+        let error = InitError::unsupported_sample_rate(22050.0, Vec::new());
+        assert_eq!(
+            error.message(),
+            "The plugin does not support a sample rate of 22050 Hz"
+        );
+    }
+}
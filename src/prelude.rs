@@ -11,21 +11,31 @@ pub use crate::formatters;
 pub use crate::util;
 
 pub use crate::buffer::Buffer;
-pub use crate::context::gui::{AsyncExecutor, GuiContext, ParamSetter};
+pub use crate::context::gui::{
+    AsyncExecutor, ChannelColor, ChannelInfo, ContextMenuItem, ContextMenuItemKind, GuiContext,
+    HostContextMenu, MusicalQuantize, ParamDragGuard, ParamSetter,
+};
 pub use crate::context::init::InitContext;
-pub use crate::context::process::ProcessContext;
+pub use crate::context::messages::{editor_message_channel, EditorMessages, ProcessMessages};
+pub use crate::context::process::{ProcessContext, TransportRequirements};
+pub use crate::context::repaint::RepaintPolicy;
 // This also includes the derive macro
-pub use crate::editor::{Editor, ParentWindowHandle};
-pub use crate::midi::{control_change, MidiConfig, NoteEvent};
+pub use crate::editor::{Editor, ParentWindowHandle, SizeConstraints};
+pub use crate::midi::routing::{MidiInputConfig, MpeZone};
+pub use crate::midi::{control_change, MidiConfig, NoteEvent, NoteExpressions};
+pub use crate::params::changes::{ParamChange, ParamChangeSource, ParamChangeSubscription};
 pub use crate::params::enums::{Enum, EnumParam};
 pub use crate::params::internals::ParamPtr;
 pub use crate::params::range::{FloatRange, IntRange};
-pub use crate::params::smoothing::{Smoothable, Smoother, SmoothingStyle};
+pub use crate::params::smoothing::{Smoothable, Smoother, SmootherState, SmoothingStyle};
 pub use crate::params::Params;
 pub use crate::params::{BoolParam, FloatParam, IntParam, Param, ParamFlags};
 pub use crate::plugin::{
-    AuxiliaryBuffers, AuxiliaryIOConfig, BufferConfig, BusConfig, ClapPlugin, Plugin,
-    PolyModulationConfig, PortNames, ProcessMode, ProcessStatus, TaskExecutor, Vst3Plugin,
+    AuxiliaryBuffers, AuxiliaryIOConfig, BackgroundTaskPriority, BufferConfig, BusConfig,
+    BypassMode, ChannelAdaptation, ClapPlugin, InitError, InitErrorKind, Plugin,
+    PolyModulationConfig, PortNames, ProcessMode, ProcessPrecision, ProcessStatus, TaskExecutor,
+    Vst3Plugin,
 };
 pub use crate::wrapper::clap::features::ClapFeature;
 pub use crate::wrapper::state::PluginState;
+pub use crate::wrapper::vst3_subcategories::Vst3SubCategory;
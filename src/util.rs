@@ -1,9 +1,42 @@
 //! General conversion functions and utilities.
 
+mod dc_blocker;
+mod late_init;
+mod latency;
+mod mid_side;
+mod modulation;
+mod oversampling;
+mod random;
+mod section_bypass;
+mod spectral_processor;
+mod spectrum_analyzer;
 mod stft;
+mod waveform_buffer;
 pub mod window;
 
+pub use dc_blocker::DcBlocker;
+pub use late_init::LateInit;
+pub use latency::{LatencyRegistry, LatencyStage};
+pub use mid_side::MidSide;
+pub use modulation::{
+    EnvelopeFollower, LfoShape, ModulationMatrix, ModulationRouteParams, ModulationSource,
+    TempoSyncedLfo,
+};
+pub(crate) use oversampling::oversampling_latency;
+pub use oversampling::{Oversampler, MAX_OVERSAMPLING_STAGES};
+pub use random::{PinkNoise, Xoshiro128};
+pub use section_bypass::{SectionBypass, SectionBypassParams};
+pub use spectral_processor::{SpectralProcessor, SpectralProcessorConfig};
+pub use spectrum_analyzer::{
+    SpectrumAnalyzer, SpectrumAnalyzerConfig, SpectrumAveraging, SpectrumFrame, SpectrumOutput,
+    SpectrumWindow,
+};
 pub use stft::StftHelper;
+pub use waveform_buffer::{WaveformBuffer, WaveformReader};
+
+#[doc(hidden)]
+pub use crate::wrapper::thread_role::thread_role_assertion_failed;
+pub use crate::wrapper::thread_role::{is_audio_thread, is_gui_thread};
 
 pub const MINUS_INFINITY_DB: f32 = -100.0;
 pub const MINUS_INFINITY_GAIN: f32 = 1e-5; // 10f32.powf(MINUS_INFINITY_DB / 20)
@@ -12,20 +45,23 @@ pub const NOTES: [&str; 12] = [
 ];
 
 /// Temporarily allow allocations within `func` if NIH-plug was configured with the
-/// `assert_process_allocs` feature.
+/// `assert_process_allocs` or `audio_thread_alloc_guard` features.
 #[cfg(all(debug_assertions, feature = "assert_process_allocs"))]
 pub fn permit_alloc<T, F: FnOnce() -> T>(func: F) -> T {
+    let _permit_alloc_guard = crate::wrapper::thread_role::permit_alloc();
     assert_no_alloc::permit_alloc(func)
 }
 
 /// Temporarily allow allocations within `func` if NIH-plug was configured with the
-/// `assert_process_allocs` feature.
+/// `assert_process_allocs` or `audio_thread_alloc_guard` features.
 #[cfg(not(all(debug_assertions, feature = "assert_process_allocs")))]
 pub fn permit_alloc<T, F: FnOnce() -> T>(func: F) -> T {
+    let _permit_alloc_guard = crate::wrapper::thread_role::permit_alloc();
     func()
 }
 
-/// Convert decibels to a voltage gain ratio, treating anything below -100 dB as minus infinity.
+/// Convert decibels to a voltage gain ratio, treating anything at or below -100 dB as minus
+/// infinity. This returns 0.0 for minus infinity and for NaN values.
 #[inline]
 pub fn db_to_gain(dbs: f32) -> f32 {
     if dbs > MINUS_INFINITY_DB {
@@ -35,8 +71,8 @@ pub fn db_to_gain(dbs: f32) -> f32 {
     }
 }
 
-/// Convert a voltage gain ratio to decibels. Gain ratios that aren't positive will be treated as
-/// [`MINUS_INFINITY_DB`].
+/// Convert a voltage gain ratio to decibels. Gain ratios at or below [`MINUS_INFINITY_GAIN`],
+/// including zero, negative ratios, and NaN values, will be treated as [`MINUS_INFINITY_DB`].
 #[inline]
 pub fn gain_to_db(gain: f32) -> f32 {
     if gain > MINUS_INFINITY_GAIN {
@@ -88,6 +124,12 @@ mod tests {
         assert_eq!(db_to_gain(-100.0), 0.0);
     }
 
+    #[test]
+    fn test_db_to_gain_minus_infinity_nan() {
+        assert_eq!(db_to_gain(f32::NEG_INFINITY), 0.0);
+        assert_eq!(db_to_gain(f32::NAN), 0.0);
+    }
+
     #[test]
     fn test_gain_to_db_positive() {
         assert_eq!(gain_to_db(4.0), 12.041201);
@@ -107,4 +149,9 @@ mod tests {
     fn test_gain_to_db_minus_infinity_negative() {
         assert_eq!(gain_to_db(-2.0), MINUS_INFINITY_DB);
     }
+
+    #[test]
+    fn test_gain_to_db_minus_infinity_nan() {
+        assert_eq!(gain_to_db(f32::NAN), MINUS_INFINITY_DB);
+    }
 }
@@ -0,0 +1,172 @@
+//! A simple DC blocking filter.
+
+use std::f32::consts::PI;
+
+/// The DC blocker's cutoff frequency. This is low enough to keep the attenuation at 40 Hz below
+/// 0.1 dB, while still removing DC offsets within a couple hundred milliseconds.
+const CUTOFF_HZ: f32 = 5.0;
+
+/// A one-pole, one-zero high-pass filter that removes DC offsets from a signal while leaving the
+/// audible band alone. The response is down by less than 0.1 dB at 40 Hz. This is useful in the
+/// feedback paths of delays and other recursive effects, where nonlinearities can otherwise cause
+/// a DC offset to build up over time.
+///
+/// The filter keeps separate state for every channel. All memory is allocated up front in
+/// [`DcBlocker::new()`] so this can be used from the audio thread.
+#[derive(Debug, Clone)]
+pub struct DcBlocker {
+    /// The filter's pole, computed from the sample rate in [`DcBlocker::set_sample_rate()`].
+    coefficient: f32,
+    /// The previous input and output sample for every channel.
+    channels: Vec<ChannelState>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ChannelState {
+    x1: f32,
+    y1: f32,
+}
+
+impl DcBlocker {
+    /// Create a DC blocker for `num_channels` channels of audio at the specified sample rate.
+    pub fn new(num_channels: usize, sample_rate: f32) -> Self {
+        let mut dc_blocker = Self {
+            coefficient: 0.0,
+            channels: vec![ChannelState::default(); num_channels],
+        };
+        dc_blocker.set_sample_rate(sample_rate);
+
+        dc_blocker
+    }
+
+    /// The number of channels this DC blocker was created with.
+    pub fn num_channels(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Recompute the filter's coefficient for a new sample rate. This should be called from the
+    /// plugin's `initialize()` function. This does not reset the filter's state.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        nih_debug_assert!(sample_rate > 0.0);
+
+        self.coefficient = (-2.0 * PI * CUTOFF_HZ / sample_rate).exp();
+    }
+
+    /// Clear the filter's state for all channels. This should be called from the plugin's
+    /// `reset()` function.
+    pub fn reset(&mut self) {
+        self.channels.fill(ChannelState::default());
+    }
+
+    /// Filter a single sample for channel `channel`.
+    #[inline]
+    pub fn process_sample(&mut self, channel: usize, sample: f32) -> f32 {
+        let state = &mut self.channels[channel];
+        let output = sample - state.x1 + self.coefficient * state.y1;
+        state.x1 = sample;
+        state.y1 = output;
+
+        output
+    }
+
+    /// Filter a block of samples for channel `channel` in place.
+    pub fn process_block(&mut self, channel: usize, block: &mut [f32]) {
+        let coefficient = self.coefficient;
+        let state = &mut self.channels[channel];
+        for sample in block {
+            let output = *sample - state.x1 + coefficient * state.y1;
+            state.x1 = *sample;
+            state.y1 = output;
+
+            *sample = output;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44_100.0;
+
+    #[test]
+    fn removes_dc() {
+        let mut dc_blocker = DcBlocker::new(1, SAMPLE_RATE);
+        let mut block = vec![1.0; SAMPLE_RATE as usize];
+        dc_blocker.process_block(0, &mut block);
+
+        assert!(block[0] > 0.99);
+        assert!(block.last().unwrap().abs() < 1e-4);
+    }
+
+    #[test]
+    fn transparent_at_40_hz() {
+        let mut dc_blocker = DcBlocker::new(1, SAMPLE_RATE);
+        let mut block: Vec<f32> = (0..SAMPLE_RATE as usize * 2)
+            .map(|i| (2.0 * PI * 40.0 * i as f32 / SAMPLE_RATE).sin())
+            .collect();
+        dc_blocker.process_block(0, &mut block);
+
+        // Only look at the second half to skip the filter's transient
+        let peak = block[SAMPLE_RATE as usize..]
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let gain_db = 20.0 * peak.log10();
+        assert!(gain_db > -0.1, "{gain_db} dB at 40 Hz");
+    }
+
+    #[test]
+    fn channels_are_independent() {
+        let mut dc_blocker = DcBlocker::new(2, SAMPLE_RATE);
+        for _ in 0..100 {
+            dc_blocker.process_sample(0, 1.0);
+        }
+
+        assert_eq!(dc_blocker.process_sample(1, 0.0), 0.0);
+        let mut single_channel = DcBlocker::new(1, SAMPLE_RATE);
+        for _ in 0..100 {
+            single_channel.process_sample(0, 1.0);
+        }
+        assert_eq!(
+            dc_blocker.process_sample(0, 1.0),
+            single_channel.process_sample(0, 1.0)
+        );
+    }
+
+    /// A DC offset fed into a feedback loop with a DC blocker in it should decay instead of
+    /// accumulating.
+    #[test]
+    fn feedback_loop_dc_decays() {
+        const DELAY_SAMPLES: usize = 1000;
+        const FEEDBACK: f32 = 0.95;
+
+        let run_loop = |dc_blocker: &mut Option<DcBlocker>| {
+            let mut delay_line = vec![0.0f32; DELAY_SAMPLES];
+            let mut sum = 0.0f64;
+            let num_samples = SAMPLE_RATE as usize * 10;
+            for i in 0..num_samples {
+                // An impulse train with a DC offset
+                let input = 0.1 + if i % 441 == 0 { 1.0 } else { 0.0 };
+
+                let delayed = delay_line[i % DELAY_SAMPLES];
+                let mut output = (input + delayed * FEEDBACK).tanh();
+                if let Some(dc_blocker) = dc_blocker {
+                    output = dc_blocker.process_sample(0, output);
+                }
+                delay_line[i % DELAY_SAMPLES] = output;
+
+                // Only measure the last second
+                if i >= num_samples - SAMPLE_RATE as usize {
+                    sum += output as f64;
+                }
+            }
+
+            sum / SAMPLE_RATE as f64
+        };
+
+        let dc_without_blocker = run_loop(&mut None);
+        let dc_with_blocker = run_loop(&mut Some(DcBlocker::new(1, SAMPLE_RATE)));
+        assert!(dc_without_blocker > 0.5, "{dc_without_blocker}");
+        assert!(dc_with_blocker.abs() < 0.001, "{dc_with_blocker}");
+    }
+}
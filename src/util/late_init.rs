@@ -0,0 +1,124 @@
+//! A container for values that can only be created after the plugin has been constructed.
+
+use std::ops::{Deref, DerefMut};
+
+/// A value that is initialized after the plugin has been constructed, usually in
+/// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()]. Hosts create plugin instances on
+/// the main thread to scan them, so a plugin's `Default` implementation should not allocate large
+/// buffers or do other expensive work. Anything that depends on the sample rate or the bus
+/// configuration can be stored in a `LateInit` instead, and then be accessed through `Deref` and
+/// `DerefMut` once it has been initialized.
+///
+/// Dereferencing a `LateInit` before it has been initialized panics. Use [`get()`][Self::get()] or
+/// [`get_mut()`][Self::get_mut()] if the value may not exist yet.
+#[derive(Debug, Clone)]
+pub struct LateInit<T> {
+    value: Option<T>,
+}
+
+impl<T> Default for LateInit<T> {
+    fn default() -> Self {
+        Self::uninit()
+    }
+}
+
+impl<T> Deref for LateInit<T> {
+    type Target = T;
+
+    #[track_caller]
+    fn deref(&self) -> &Self::Target {
+        match &self.value {
+            Some(value) => value,
+            None => panic!("A LateInit value was accessed before it was initialized"),
+        }
+    }
+}
+
+impl<T> DerefMut for LateInit<T> {
+    #[track_caller]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match &mut self.value {
+            Some(value) => value,
+            None => panic!("A LateInit value was accessed before it was initialized"),
+        }
+    }
+}
+
+impl<T> LateInit<T> {
+    /// Create an empty `LateInit`. This does not allocate.
+    pub const fn uninit() -> Self {
+        Self { value: None }
+    }
+
+    /// Initialize the value, replacing and dropping the old value if it had already been
+    /// initialized. Returns a reference to the new value.
+    pub fn init(&mut self, value: T) -> &mut T {
+        self.value.insert(value)
+    }
+
+    /// Initialize the value using `f` if it has not yet been initialized. The existing value is
+    /// kept otherwise. This can be used in `initialize()` to only allocate once, even if the
+    /// plugin gets initialized multiple times.
+    pub fn get_or_init_with(&mut self, f: impl FnOnce() -> T) -> &mut T {
+        self.value.get_or_insert_with(f)
+    }
+
+    /// Whether the value has been initialized.
+    pub fn is_initialized(&self) -> bool {
+        self.value.is_some()
+    }
+
+    /// Get a reference to the value, or `None` if it has not yet been initialized.
+    pub fn get(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Get a mutable reference to the value, or `None` if it has not yet been initialized.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.as_mut()
+    }
+
+    /// Take the value out of the `LateInit`, leaving it uninitialized again.
+    pub fn take(&mut self) -> Option<T> {
+        self.value.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_and_access() {
+        let mut buffer: LateInit<Vec<f32>> = LateInit::default();
+        assert!(!buffer.is_initialized());
+        assert_eq!(buffer.get(), None);
+
+        buffer.init(vec![0.0; 4]);
+        assert!(buffer.is_initialized());
+        assert_eq!(buffer.len(), 4);
+
+        buffer[1] = 1.0;
+        assert_eq!(buffer.get().unwrap(), &[0.0, 1.0, 0.0, 0.0]);
+
+        assert_eq!(buffer.take(), Some(vec![0.0, 1.0, 0.0, 0.0]));
+        assert!(!buffer.is_initialized());
+    }
+
+    #[test]
+    fn get_or_init_keeps_value() {
+        let mut value: LateInit<i32> = LateInit::uninit();
+        assert_eq!(*value.get_or_init_with(|| 1), 1);
+        assert_eq!(*value.get_or_init_with(|| 2), 1);
+
+        value.init(3);
+        assert_eq!(*value, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn access_before_init() {
+        let value: LateInit<f32> = LateInit::uninit();
+        let _ = *value;
+    }
+}
@@ -0,0 +1,185 @@
+//! Keeping track of the latency of multiple processing stages.
+
+/// Computes a plugin's total latency from the latencies of its internal processing stages, and
+/// reports it to the host only when it changes. This is useful for plugins with several stages
+/// that add latency depending on the plugin's settings, like oversampling, lookahead, or
+/// resampling, where keeping a single hand-computed latency up to date is error-prone.
+///
+/// The stages are registered in the plugin's `initialize()` function using
+/// [`clear()`][Self::clear()] and [`register()`][Self::register()]. This is the only part that
+/// allocates, so the set of stages may not change while processing audio. After that the stages'
+/// latencies can be changed from anywhere with [`set_latency()`][Self::set_latency()], after which
+/// a single call to [`report()`][Self::report()] sends the new total to the host through
+/// `set_latency_samples()` if it differs from the last reported value.
+///
+/// The total is the sum of all stages' latencies, since the stages are assumed to be processed in
+/// series. For parallel paths, for instance a dry path alongside an oversampled wet path, the
+/// shorter paths need to be delayed to match the longest path anyway. Register those paths as a
+/// single stage and set its latency to the maximum of the paths' latencies, which is also the
+/// delay needed to compensate the other paths.
+#[derive(Debug, Default, Clone)]
+pub struct LatencyRegistry {
+    stages: Vec<Stage>,
+    /// The total latency that was last passed to the host, if any.
+    reported: Option<u32>,
+}
+
+/// A handle to a stage registered with a [`LatencyRegistry`], returned from
+/// [`LatencyRegistry::register()`]. These handles are invalidated by
+/// [`LatencyRegistry::clear()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyStage(usize);
+
+#[derive(Debug, Clone)]
+struct Stage {
+    /// Only used for debugging.
+    name: &'static str,
+    latency: u32,
+}
+
+impl LatencyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove all stages so they can be registered again. The last reported latency is kept, so
+    /// the next call to [`report()`][Self::report()] only reports the latency if the new stages
+    /// add up to a different total. This should be called at the start of the plugin's
+    /// `initialize()` function. This may not be called from the audio thread.
+    pub fn clear(&mut self) {
+        nih_debug_assert_not_audio_thread!();
+
+        self.stages.clear();
+    }
+
+    /// Register a processing stage with an initial latency in samples. The name is only used for
+    /// debugging. This allocates, and it may thus only be called from the plugin's `initialize()`
+    /// function and not from the audio thread.
+    pub fn register(&mut self, name: &'static str, latency: u32) -> LatencyStage {
+        nih_debug_assert_not_audio_thread!();
+        nih_debug_assert!(
+            self.stages.iter().all(|stage| stage.name != name),
+            "A latency stage called '{}' has already been registered",
+            name
+        );
+
+        self.stages.push(Stage { name, latency });
+        LatencyStage(self.stages.len() - 1)
+    }
+
+    /// Change a stage's latency in samples. This does not allocate and it can be called from the
+    /// audio thread. Call [`report()`][Self::report()] afterwards to send the new total to the
+    /// host.
+    pub fn set_latency(&mut self, stage: LatencyStage, latency: u32) {
+        match self.stages.get_mut(stage.0) {
+            Some(stage) => stage.latency = latency,
+            None => nih_debug_assert_failure!(
+                "Unknown latency stage {:?}, the stages have changed since it was registered",
+                stage
+            ),
+        }
+    }
+
+    /// A stage's current latency in samples.
+    pub fn latency(&self, stage: LatencyStage) -> u32 {
+        self.stages
+            .get(stage.0)
+            .map(|stage| stage.latency)
+            .unwrap_or(0)
+    }
+
+    /// The sum of all stages' latencies in samples.
+    pub fn total(&self) -> u32 {
+        self.stages.iter().map(|stage| stage.latency).sum()
+    }
+
+    /// Pass the total latency to `set_latency_samples` if it is different from the last reported
+    /// total, or if the latency has not been reported yet. This is usually called with
+    /// `|samples| context.set_latency_samples(samples)` at the end of the plugin's `initialize()`
+    /// function and after changing a stage's latency in the process function. Returns `true` if
+    /// the latency was reported.
+    pub fn report(&mut self, set_latency_samples: impl FnOnce(u32)) -> bool {
+        let total = self.total();
+        if self.reported == Some(total) {
+            return false;
+        }
+
+        set_latency_samples(total);
+        self.reported = Some(total);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Oversampler;
+
+    #[test]
+    fn reports_changes_once() {
+        let mut registry = LatencyRegistry::new();
+        let resampler = registry.register("resampler", 12);
+        let lookahead = registry.register("lookahead", 0);
+        assert_eq!(registry.total(), 12);
+
+        let mut reported = Vec::new();
+        assert!(registry.report(|samples| reported.push(samples)));
+        assert!(!registry.report(|samples| reported.push(samples)));
+
+        // Changing a stage back and forth before reporting should not report anything
+        registry.set_latency(lookahead, 64);
+        registry.set_latency(lookahead, 0);
+        assert!(!registry.report(|samples| reported.push(samples)));
+
+        registry.set_latency(lookahead, 64);
+        registry.set_latency(resampler, 10);
+        assert!(registry.report(|samples| reported.push(samples)));
+        assert_eq!(registry.latency(lookahead), 64);
+        assert_eq!(reported, [12, 74]);
+    }
+
+    #[test]
+    fn clear_keeps_reported_latency() {
+        let mut registry = LatencyRegistry::new();
+        registry.register("lookahead", 32);
+        let mut reported = Vec::new();
+        registry.report(|samples| reported.push(samples));
+
+        // Reinitializing the plugin with the same total latency should not report it again
+        registry.clear();
+        registry.register("lookahead", 16);
+        registry.register("dry_compensation", 16);
+        assert!(!registry.report(|samples| reported.push(samples)));
+
+        registry.clear();
+        assert!(registry.report(|samples| reported.push(samples)));
+        assert_eq!(reported, [32, 0]);
+    }
+
+    /// Toggle between different oversampling amounts and lookahead lengths like a plugin would in
+    /// its process function, and check that the reported latency always matches the stages.
+    #[test]
+    fn oversampling_and_lookahead() {
+        const LOOKAHEAD_LENGTHS: [u32; 3] = [0, 32, 128];
+
+        let oversampler = Oversampler::new(64, 3);
+        let mut registry = LatencyRegistry::new();
+        let oversampling = registry.register("oversampling", oversampler.latency(0));
+        let lookahead = registry.register("lookahead", LOOKAHEAD_LENGTHS[0]);
+
+        let mut reported = None;
+        registry.report(|samples| reported = Some(samples));
+        for (num_stages, quality) in [(1, 0), (1, 1), (3, 1), (3, 2), (0, 2), (0, 0), (2, 1)] {
+            let expected = oversampler.latency(num_stages) + LOOKAHEAD_LENGTHS[quality];
+            let previous = reported;
+
+            registry.set_latency(oversampling, oversampler.latency(num_stages));
+            registry.set_latency(lookahead, LOOKAHEAD_LENGTHS[quality]);
+            let did_report = registry.report(|samples| reported = Some(samples));
+
+            assert_eq!(reported, Some(expected));
+            assert_eq!(did_report, previous != Some(expected));
+        }
+    }
+}
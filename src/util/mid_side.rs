@@ -0,0 +1,131 @@
+//! Mid/side encoding and stereo width.
+
+/// A stereo sample encoded as mid and side signals. This uses the `M = (L + R) / 2` and
+/// `S = (L - R) / 2` convention, so decoding is simply `L = M + S` and `R = M - S`, and the mid
+/// signal of a mono signal is the signal itself.
+///
+/// This is mostly useful for stereo width controls through [`MidSide::apply_width()`]. Everything
+/// here works on single samples and never allocates, so it can be used from the audio thread.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MidSide {
+    pub mid: f32,
+    pub side: f32,
+}
+
+impl MidSide {
+    /// Encode a stereo sample.
+    #[inline]
+    pub fn encode(left: f32, right: f32) -> Self {
+        Self {
+            mid: (left + right) * 0.5,
+            side: (left - right) * 0.5,
+        }
+    }
+
+    /// Decode this sample back to a `(left, right)` pair.
+    #[inline]
+    pub fn decode(self) -> (f32, f32) {
+        (self.mid + self.side, self.mid - self.side)
+    }
+
+    /// Scale the side signal by `width`. See [`MidSide::apply_width()`].
+    #[inline]
+    pub fn with_width(self, width: f32) -> Self {
+        Self {
+            mid: self.mid,
+            side: self.side * width,
+        }
+    }
+
+    /// Change the stereo width of a stereo sample in place. A width of 0 collapses the signal to
+    /// mono, 1 leaves it unchanged, and 2 doubles the side signal. At a width of 0 both channels
+    /// become exactly the average of the two input channels, so the level of sources that were
+    /// panned to the center does not change and the two channels can be summed without any
+    /// cancellation. Widths above 1 increase the peak level and can cause clipping, so this should
+    /// be applied before a plugin's output gain.
+    ///
+    /// A stereo width control only makes sense for stereo signals. Mono instances should skip
+    /// this entirely.
+    #[inline]
+    pub fn apply_width(left: &mut f32, right: &mut f32, width: f32) {
+        // Encoding and decoding is not completely lossless with floating point numbers
+        if width == 1.0 {
+            return;
+        }
+
+        (*left, *right) = Self::encode(*left, *right).with_width(width).decode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::Xoshiro128;
+
+    /// Apply `width` to one second of uncorrelated noise and return the correlation coefficient
+    /// between the two output channels.
+    fn noise_correlation(width: f32) -> f32 {
+        let mut rng = Xoshiro128::from_seed(1);
+        let (mut sum_lr, mut sum_ll, mut sum_rr) = (0.0f64, 0.0f64, 0.0f64);
+        for _ in 0..44_100 {
+            let mut left = rng.next_gaussian();
+            let mut right = rng.next_gaussian();
+            MidSide::apply_width(&mut left, &mut right, width);
+
+            sum_lr += left as f64 * right as f64;
+            sum_ll += left as f64 * left as f64;
+            sum_rr += right as f64 * right as f64;
+        }
+
+        (sum_lr / (sum_ll * sum_rr).sqrt()) as f32
+    }
+
+    #[test]
+    fn round_trip() {
+        let (left, right) = MidSide::encode(0.25, -0.75).decode();
+        assert_eq!((left, right), (0.25, -0.75));
+        assert_eq!(
+            MidSide::encode(0.5, 0.5),
+            MidSide {
+                mid: 0.5,
+                side: 0.0
+            }
+        );
+    }
+
+    /// For uncorrelated channels with equal power the output correlation is
+    /// `(1 - width^2) / (1 + width^2)`.
+    #[test]
+    fn width_changes_correlation() {
+        for (width, expected) in [(0.0, 1.0), (0.5, 0.6), (1.0, 0.0), (2.0, -0.6)] {
+            let correlation = noise_correlation(width);
+            assert!(
+                (correlation - expected).abs() < 0.02,
+                "correlation {correlation} at width {width}"
+            );
+        }
+    }
+
+    #[test]
+    fn unity_width_is_transparent() {
+        let (mut left, mut right) = (0.1, 0.7);
+        MidSide::apply_width(&mut left, &mut right, 1.0);
+        assert_eq!((left, right), (0.1, 0.7));
+    }
+
+    /// At zero width both channels should be the exact average of the input, so summing them to
+    /// mono gives the same result as summing the original channels.
+    #[test]
+    fn zero_width_is_mono_compatible() {
+        let mut rng = Xoshiro128::from_seed(2);
+        for _ in 0..1000 {
+            let (left, right) = (rng.next_f32(), rng.next_f32());
+            let (mut new_left, mut new_right) = (left, right);
+            MidSide::apply_width(&mut new_left, &mut new_right, 0.0);
+
+            assert_eq!(new_left, (left + right) * 0.5);
+            assert_eq!(new_left, new_right);
+            assert!(((new_left + new_right) - (left + right)).abs() <= f32::EPSILON * 4.0);
+        }
+    }
+}
@@ -0,0 +1,618 @@
+//! Internal modulation sources and a modulation matrix that routes them to the plugin's parameters.
+//! This works the same in every host, regardless of whether the host supports parameter
+//! modulation itself.
+
+use std::f64::consts::TAU;
+use std::iter;
+use std::sync::Arc;
+
+use crate::buffer::Buffer;
+use crate::context::process::Transport;
+use crate::params::internals::ParamPtr;
+use crate::params::range::{FloatRange, IntRange};
+use crate::params::{FloatParam, IntParam, Param, Params};
+
+/// The tempo [`TempoSyncedLfo`] runs at when the host does not report a tempo.
+const DEFAULT_TEMPO: f64 = 120.0;
+
+/// A source of modulation for a [`ModulationMatrix`]. Sources are evaluated once per block.
+pub trait ModulationSource: Send {
+    /// Compute the source's value for the current block. This should be in `[-1, 1]`, and values
+    /// outside of that range are clamped. `buffer` contains the block's input audio. This is
+    /// called from the audio thread, so it should not allocate.
+    fn next_block(&mut self, buffer: &Buffer, transport: &Transport) -> f32;
+
+    /// Reset the source's state. Call this from the plugin's
+    /// [`reset()`][crate::prelude::Plugin::reset()] function.
+    fn reset(&mut self);
+}
+
+/// The waveform of a [`TempoSyncedLfo`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LfoShape {
+    Sine,
+    Triangle,
+    /// A rising sawtooth.
+    Saw,
+    Square,
+}
+
+/// An LFO whose rate is specified in quarter notes. While the host's transport is playing and the
+/// song position is known, the LFO's phase follows the song position so it stays in sync with the
+/// project. Otherwise the LFO keeps running at the host's tempo, or at 120 BPM if the host does
+/// not report a tempo. The value is computed once for the start of every block.
+#[derive(Debug, Clone)]
+pub struct TempoSyncedLfo {
+    shape: LfoShape,
+    /// The length of a single cycle in quarter notes.
+    cycle_beats: f64,
+    /// The LFO's phase at the start of the next block, in `[0, 1)`.
+    phase: f64,
+}
+
+/// Follows the peak level of the input audio with separate attack and release times. The output is
+/// the envelope's linear gain, clamped to `[0, 1]`.
+#[derive(Debug, Clone)]
+pub struct EnvelopeFollower {
+    attack_ms: f32,
+    release_ms: f32,
+    /// The current envelope as a linear gain.
+    envelope: f32,
+}
+
+/// The parameters for a single route in a [`ModulationMatrix`]. Add a fixed number of these to the
+/// plugin's [`Params`] object, for instance using
+/// `#[nested(array, group = "Modulation")] routes: [ModulationRouteParams; 4]`, so the routes are
+/// automatable and saved with the plugin's state. A route is inactive while its source or its
+/// target is set to "None".
+pub struct ModulationRouteParams {
+    /// The index of the route's source plus one, or zero if the route does not have a source.
+    pub source: IntParam,
+    /// The index of the route's target plus one, or zero if the route does not have a target.
+    pub target: IntParam,
+    /// How much of the source's value gets added to the target's normalized value.
+    pub depth: FloatParam,
+}
+
+/// Routes [`ModulationSource`]s to the plugin's parameters. The routes are configured through
+/// [`ModulationRouteParams`]. Every block, [`process()`][Self::process()] computes the sources'
+/// values and adds them to the targets' normalized values as a modulation offset. This is
+/// non-destructive, so the host and the editor still see the parameters' unmodulated values, and
+/// the parameters' smoothers are used to get from one block's value to the next.
+///
+/// This uses the same modulation offset as CLAP's monophonic parameter modulation, so routing a
+/// parameter that the host also modulates replaces the host's modulation.
+pub struct ModulationMatrix {
+    /// Keeps the parameters `targets` point to alive.
+    _params: Arc<dyn Params>,
+    targets: Vec<ParamPtr>,
+
+    /// The sources' values for the current block.
+    source_values: Vec<f32>,
+    /// The summed modulation for every target for the current block.
+    target_offsets: Vec<f32>,
+    /// The modulation offsets that were last applied to the targets. Targets are only updated when
+    /// their offsets change.
+    applied_offsets: Vec<f32>,
+}
+
+impl LfoShape {
+    /// The waveform's value at `phase`, in `[0, 1)`. All shapes start at zero and rise first,
+    /// except for the square wave.
+    fn value(&self, phase: f64) -> f32 {
+        match self {
+            LfoShape::Sine => (phase * TAU).sin() as f32,
+            LfoShape::Triangle => (1.0 - 4.0 * ((phase + 0.25).fract() - 0.5).abs()) as f32,
+            LfoShape::Saw => (2.0 * (phase + 0.5).fract() - 1.0) as f32,
+            LfoShape::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+        }
+    }
+}
+
+impl TempoSyncedLfo {
+    /// Create an LFO that completes a cycle every `cycle_beats` quarter notes.
+    pub fn new(shape: LfoShape, cycle_beats: f64) -> Self {
+        Self {
+            shape,
+            cycle_beats,
+            phase: 0.0,
+        }
+    }
+
+    /// Change the LFO's waveform.
+    pub fn set_shape(&mut self, shape: LfoShape) {
+        self.shape = shape;
+    }
+
+    /// Change the length of a single cycle, in quarter notes. This can be called every block, for
+    /// instance to control the rate with a parameter.
+    pub fn set_cycle_beats(&mut self, cycle_beats: f64) {
+        self.cycle_beats = cycle_beats;
+    }
+}
+
+impl ModulationSource for TempoSyncedLfo {
+    fn next_block(&mut self, buffer: &Buffer, transport: &Transport) -> f32 {
+        if transport.playing {
+            if let Some(pos_beats) = transport.pos_beats() {
+                self.phase = (pos_beats / self.cycle_beats).rem_euclid(1.0);
+            }
+        }
+        let value = self.shape.value(self.phase);
+
+        let tempo = transport.tempo.unwrap_or(DEFAULT_TEMPO);
+        let block_beats = buffer.len() as f64 / transport.sample_rate as f64 * (tempo / 60.0);
+        self.phase = (self.phase + block_beats / self.cycle_beats).rem_euclid(1.0);
+
+        value
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+}
+
+impl EnvelopeFollower {
+    /// Create an envelope follower with the specified attack and release times in milliseconds.
+    /// These are the times it takes for the envelope to move 63% of the way towards a new level.
+    pub fn new(attack_ms: f32, release_ms: f32) -> Self {
+        Self {
+            attack_ms,
+            release_ms,
+            envelope: 0.0,
+        }
+    }
+
+    /// Change the attack and release times. See [`new()`][Self::new()].
+    pub fn set_times(&mut self, attack_ms: f32, release_ms: f32) {
+        self.attack_ms = attack_ms;
+        self.release_ms = release_ms;
+    }
+}
+
+impl ModulationSource for EnvelopeFollower {
+    fn next_block(&mut self, buffer: &Buffer, transport: &Transport) -> f32 {
+        let attack_coefficient = one_pole_coefficient(self.attack_ms, transport.sample_rate);
+        let release_coefficient = one_pole_coefficient(self.release_ms, transport.sample_rate);
+
+        let channels = buffer.as_slice_immutable();
+        for sample_idx in 0..buffer.len() {
+            let peak = channels
+                .iter()
+                .map(|channel| channel[sample_idx].abs())
+                .fold(0.0f32, f32::max);
+            let coefficient = if peak > self.envelope {
+                attack_coefficient
+            } else {
+                release_coefficient
+            };
+
+            self.envelope = peak + (coefficient * (self.envelope - peak));
+        }
+
+        self.envelope.min(1.0)
+    }
+
+    fn reset(&mut self) {
+        self.envelope = 0.0;
+    }
+}
+
+/// The coefficient for a one-pole filter with a time constant of `time_ms` milliseconds.
+fn one_pole_coefficient(time_ms: f32, sample_rate: f32) -> f32 {
+    if time_ms > 0.0 {
+        (-1000.0 / (time_ms * sample_rate)).exp()
+    } else {
+        0.0
+    }
+}
+
+impl ModulationRouteParams {
+    /// Create the parameters for a route. `source_names` and `target_names` are the names of the
+    /// matrix's sources and targets, in the same order as the sources passed to
+    /// [`ModulationMatrix::process()`] and the targets passed to [`ModulationMatrix::new()`].
+    /// These are used to display the route's source and target. The route starts out inactive.
+    pub fn new(source_names: &[&str], target_names: &[&str]) -> Self {
+        Self {
+            source: choice_param("Mod Source", source_names),
+            target: choice_param("Mod Target", target_names),
+            depth: FloatParam::new(
+                "Mod Depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_unit("%")
+            .with_value_to_string(crate::formatters::v2s_f32_percentage(0))
+            .with_string_to_value(crate::formatters::s2v_f32_percentage()),
+        }
+    }
+
+    /// The route's source index, target index, and depth, or `None` if the route is inactive.
+    fn route(&self) -> Option<(usize, usize, f32)> {
+        let source = self.source.value();
+        let target = self.target.value();
+        if source > 0 && target > 0 {
+            Some((source as usize - 1, target as usize - 1, self.depth.value()))
+        } else {
+            None
+        }
+    }
+}
+
+/// An integer parameter for selecting one of `choices`, with an additional "None" option at index
+/// zero.
+fn choice_param(name: &str, choices: &[&str]) -> IntParam {
+    let names: Arc<Vec<String>> = Arc::new(
+        iter::once("None")
+            .chain(choices.iter().copied())
+            .map(String::from)
+            .collect(),
+    );
+
+    IntParam::new(
+        name,
+        0,
+        IntRange::Linear {
+            min: 0,
+            max: choices.len() as i32,
+        },
+    )
+    .with_value_to_string({
+        let names = names.clone();
+        Arc::new(move |idx| names.get(idx as usize).cloned().unwrap_or_default())
+    })
+    .with_string_to_value(Arc::new(move |string| {
+        names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(string.trim()))
+            .map(|idx| idx as i32)
+    }))
+}
+
+unsafe impl Params for ModulationRouteParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![
+            (String::from("mod_src"), self.source.as_ptr(), String::new()),
+            (String::from("mod_dst"), self.target.as_ptr(), String::new()),
+            (
+                String::from("mod_depth"),
+                self.depth.as_ptr(),
+                String::new(),
+            ),
+        ]
+    }
+}
+
+impl ModulationMatrix {
+    /// Create a modulation matrix for `num_sources` sources and the parameters with the IDs in
+    /// `target_ids`. `params` is the plugin's parameters object. The targets are numbered in the
+    /// order they're listed in here.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `params` does not contain a parameter for one of the IDs.
+    pub fn new(params: Arc<dyn Params>, num_sources: usize, target_ids: &[&str]) -> Self {
+        let param_map = params.param_map();
+        let targets: Vec<ParamPtr> = target_ids
+            .iter()
+            .map(|target_id| {
+                param_map
+                    .iter()
+                    .find(|(param_id, _, _)| param_id == target_id)
+                    .map(|(_, param_ptr, _)| *param_ptr)
+                    .unwrap_or_else(|| panic!("Unknown parameter ID '{target_id}'"))
+            })
+            .collect();
+
+        Self {
+            _params: params,
+            source_values: vec![0.0; num_sources],
+            target_offsets: vec![0.0; targets.len()],
+            applied_offsets: vec![0.0; targets.len()],
+            targets,
+        }
+    }
+
+    /// Compute the sources' values for the current block and apply the modulation described by
+    /// `routes` to the targets. Call this at the start of the plugin's
+    /// [`process()`][crate::prelude::Plugin::process()] function, before reading any of the
+    /// targets' (smoothed) values. `sources` should contain the same number of sources as passed
+    /// to [`new()`][Self::new()], and `buffer` is passed to the sources. Routes referring to
+    /// sources or targets that don't exist are ignored.
+    ///
+    /// Once a target no longer has any active routes, its modulation offset is set back to zero
+    /// and the parameter behaves exactly like it would without the matrix.
+    pub fn process(
+        &mut self,
+        sources: &mut [&mut dyn ModulationSource],
+        routes: &[ModulationRouteParams],
+        buffer: &Buffer,
+        transport: &Transport,
+    ) {
+        nih_debug_assert_eq!(sources.len(), self.source_values.len());
+        for (value, source) in self.source_values.iter_mut().zip(sources.iter_mut()) {
+            *value = source.next_block(buffer, transport).clamp(-1.0, 1.0);
+        }
+
+        self.target_offsets.fill(0.0);
+        for (source_idx, target_idx, depth) in routes.iter().filter_map(|route| route.route()) {
+            if let (Some(source_value), Some(target_offset)) = (
+                self.source_values.get(source_idx),
+                self.target_offsets.get_mut(target_idx),
+            ) {
+                *target_offset += source_value * depth;
+            }
+        }
+
+        for ((target, offset), applied_offset) in self
+            .targets
+            .iter()
+            .zip(&self.target_offsets)
+            .zip(self.applied_offsets.iter_mut())
+        {
+            let offset = offset.clamp(-1.0, 1.0);
+            if offset != *applied_offset {
+                // SAFETY: `self.params` keeps the parameters alive, and this is only called from
+                //         the audio thread
+                unsafe {
+                    target.modulate_value(offset);
+                    target.update_smoother(transport.sample_rate, false);
+                }
+
+                *applied_offset = offset;
+            }
+        }
+    }
+
+    /// The modulation offsets applied to the targets during the last
+    /// [`process()`][Self::process()] call, in the same order as the targets passed to
+    /// [`new()`][Self::new()].
+    pub fn target_offsets(&self) -> &[f32] {
+        &self.applied_offsets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::smoothing::SmoothingStyle;
+    use crate::params::ParamMut;
+
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const BLOCK_SIZE: usize = 64;
+
+    struct TestParams {
+        speed: FloatParam,
+        routes: [ModulationRouteParams; 2],
+    }
+
+    impl TestParams {
+        fn new() -> Self {
+            Self {
+                speed: FloatParam::new("Speed", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                    .with_smoother(SmoothingStyle::Linear(5.0)),
+                routes: [(); 2].map(|_| ModulationRouteParams::new(&["LFO", "Env"], &["Speed"])),
+            }
+        }
+    }
+
+    unsafe impl Params for TestParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            let mut param_map = vec![(String::from("speed"), self.speed.as_ptr(), String::new())];
+            for (idx, route) in self.routes.iter().enumerate() {
+                param_map.extend(
+                    route
+                        .param_map()
+                        .into_iter()
+                        .map(|(id, ptr, group)| (format!("{id}_{}", idx + 1), ptr, group)),
+                );
+            }
+
+            param_map
+        }
+    }
+
+    fn transport() -> Transport {
+        let mut transport = Transport::new(SAMPLE_RATE);
+        transport.tempo = Some(120.0);
+
+        transport
+    }
+
+    /// Run `f` with a buffer containing `channels`.
+    fn with_buffer<R>(channels: &mut [Vec<f32>], f: impl FnOnce(&Buffer) -> R) -> R {
+        let mut slices: Vec<&mut [f32]> = channels.iter_mut().map(|c| c.as_mut_slice()).collect();
+        let mut buffer = Buffer::default();
+        unsafe { buffer.with_raw_vec(|raw_slices| raw_slices.append(&mut slices)) };
+
+        f(&buffer)
+    }
+
+    /// Process a block with the matrix and return the speed parameter's smoothed values.
+    fn process_block(
+        matrix: &mut ModulationMatrix,
+        params: &TestParams,
+        lfo: &mut TempoSyncedLfo,
+    ) -> Vec<f32> {
+        let mut channels = vec![vec![0.0; BLOCK_SIZE]];
+        with_buffer(&mut channels, |buffer| {
+            let mut envelope = EnvelopeFollower::new(1.0, 10.0);
+            let mut sources: [&mut dyn ModulationSource; 2] = [lfo, &mut envelope];
+            matrix.process(&mut sources, &params.routes, buffer, &transport());
+        });
+
+        (0..BLOCK_SIZE)
+            .map(|_| params.speed.smoothed.next())
+            .collect()
+    }
+
+    #[test]
+    fn lfo_shapes() {
+        for shape in [LfoShape::Sine, LfoShape::Triangle, LfoShape::Saw] {
+            assert!(shape.value(0.0).abs() < 1e-6, "{shape:?}");
+            assert!(shape.value(0.1) > 0.0, "{shape:?}");
+        }
+        assert!((LfoShape::Sine.value(0.25) - 1.0).abs() < 1e-6);
+        assert_eq!(LfoShape::Triangle.value(0.25), 1.0);
+        assert_eq!(LfoShape::Triangle.value(0.75), -1.0);
+        assert_eq!(LfoShape::Saw.value(0.5), -1.0);
+        assert_eq!(LfoShape::Square.value(0.25), 1.0);
+        assert_eq!(LfoShape::Square.value(0.75), -1.0);
+    }
+
+    #[test]
+    fn lfo_follows_tempo_and_song_position() {
+        // At 120 BPM a quarter note takes 24000 samples, so with a cycle length of one beat the
+        // LFO should advance by a quarter cycle every 6000 samples
+        let mut lfo = TempoSyncedLfo::new(LfoShape::Saw, 1.0);
+        let mut transport = transport();
+        let mut channels = vec![vec![0.0; 6000]];
+        let values: Vec<f32> = (0..4)
+            .map(|_| with_buffer(&mut channels, |buffer| lfo.next_block(buffer, &transport)))
+            .collect();
+        assert_eq!(values, [0.0, 0.5, -1.0, -0.5]);
+
+        // While playing, the phase is taken from the song position instead
+        transport.playing = true;
+        transport.pos_beats = Some(10.25);
+        let value = with_buffer(&mut channels, |buffer| lfo.next_block(buffer, &transport));
+        assert_eq!(value, 0.5);
+
+        lfo.reset();
+        transport.playing = false;
+        let value = with_buffer(&mut channels, |buffer| lfo.next_block(buffer, &transport));
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn envelope_follower() {
+        let mut follower = EnvelopeFollower::new(1.0, 50.0);
+        let transport = transport();
+
+        // A 10 ms burst at -6 dB should be followed closely
+        let mut channels = vec![vec![0.5; 480], vec![-0.25; 480]];
+        let value = with_buffer(&mut channels, |buffer| {
+            follower.next_block(buffer, &transport)
+        });
+        assert!((value - 0.5).abs() < 0.001, "{value}");
+
+        // And after 50 ms of silence it should have decayed to 1/e of that
+        let mut channels = vec![vec![0.0; 2400], vec![0.0; 2400]];
+        let value = with_buffer(&mut channels, |buffer| {
+            follower.next_block(buffer, &transport)
+        });
+        assert!((value - 0.5 / std::f32::consts::E).abs() < 0.001, "{value}");
+
+        follower.reset();
+        let value = with_buffer(&mut channels, |buffer| {
+            follower.next_block(buffer, &transport)
+        });
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn modulation_is_non_destructive() {
+        let params = Arc::new(TestParams::new());
+        params.speed.update_smoother(SAMPLE_RATE, true);
+        let mut matrix = ModulationMatrix::new(params.clone(), 2, &["speed"]);
+        let mut lfo = TempoSyncedLfo::new(LfoShape::Square, 1.0);
+
+        params.routes[0].source.set_plain_value(1);
+        params.routes[0].target.set_plain_value(1);
+        params.routes[0].depth.set_plain_value(0.25);
+        process_block(&mut matrix, &params, &mut lfo);
+
+        // The square wave starts at its maximum value
+        assert_eq!(matrix.target_offsets(), [0.25]);
+        assert_eq!(params.speed.unmodulated_plain_value(), 1.0);
+        assert_eq!(params.speed.modulated_plain_value(), 1.5);
+        assert_eq!(params.speed.value(), 1.5);
+
+        // Routes to the same target add up
+        params.routes[1].source.set_plain_value(1);
+        params.routes[1].target.set_plain_value(1);
+        params.routes[1].depth.set_plain_value(-0.5);
+        lfo.reset();
+        process_block(&mut matrix, &params, &mut lfo);
+        assert_eq!(matrix.target_offsets(), [-0.25]);
+        assert_eq!(params.speed.modulated_plain_value(), 0.5);
+
+        // Changing the base value keeps the modulation
+        params.speed.set_plain_value(1.5);
+        assert_eq!(params.speed.unmodulated_plain_value(), 1.5);
+        assert_eq!(params.speed.modulated_plain_value(), 1.0);
+    }
+
+    #[test]
+    fn removing_routes_restores_unmodulated_behavior() {
+        let params = Arc::new(TestParams::new());
+        params.speed.update_smoother(SAMPLE_RATE, true);
+        let reference_params = TestParams::new();
+        reference_params.speed.update_smoother(SAMPLE_RATE, true);
+
+        let mut matrix = ModulationMatrix::new(params.clone(), 2, &["speed"]);
+        let mut lfo = TempoSyncedLfo::new(LfoShape::Sine, 0.25);
+        params.routes[0].source.set_plain_value(1);
+        params.routes[0].target.set_plain_value(1);
+        params.routes[0].depth.set_plain_value(0.3);
+        for _ in 0..32 {
+            process_block(&mut matrix, &params, &mut lfo);
+        }
+        assert_ne!(matrix.target_offsets(), [0.0]);
+
+        // Removing the route sets the offset back to exactly zero, and once the smoother has
+        // caught up the parameter is indistinguishable from one that was never modulated
+        params.routes[0].target.set_plain_value(0);
+        process_block(&mut matrix, &params, &mut lfo);
+        assert_eq!(matrix.target_offsets(), [0.0]);
+        assert_eq!(
+            params.speed.modulated_normalized_value().to_bits(),
+            params.speed.unmodulated_normalized_value().to_bits()
+        );
+        assert_eq!(params.speed.value().to_bits(), 1.0f32.to_bits());
+
+        // The smoother takes 5 ms, or 240 samples, to get there
+        for _ in 0..4 {
+            process_block(&mut matrix, &params, &mut lfo);
+        }
+        for _ in 0..8 {
+            let modulated = process_block(&mut matrix, &params, &mut lfo);
+            let reference: Vec<f32> = (0..BLOCK_SIZE)
+                .map(|_| reference_params.speed.smoothed.next())
+                .collect();
+            assert_eq!(
+                modulated.iter().map(|v| v.to_bits()).collect::<Vec<_>>(),
+                reference.iter().map(|v| v.to_bits()).collect::<Vec<_>>()
+            );
+        }
+
+        // A zero depth also counts as an unmodulated parameter
+        params.routes[0].target.set_plain_value(1);
+        params.routes[0].depth.set_plain_value(0.0);
+        process_block(&mut matrix, &params, &mut lfo);
+        assert_eq!(matrix.target_offsets(), [0.0]);
+        assert_eq!(params.speed.value(), 1.0);
+    }
+
+    #[test]
+    fn route_params_display_names() {
+        let route = ModulationRouteParams::new(&["LFO", "Envelope"], &["Speed"]);
+        assert_eq!(route.source.to_string(), "None");
+        assert_eq!(
+            route.source.string_to_normalized_value("envelope"),
+            Some(1.0)
+        );
+
+        route.target.set_plain_value(1);
+        assert_eq!(route.target.to_string(), "Speed");
+    }
+}
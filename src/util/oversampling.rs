@@ -0,0 +1,342 @@
+//! Oversampling using a cascade of 2x halfband FIR filters.
+
+use std::f32;
+
+use super::window;
+
+/// The number of taps in the halfband filters. The filter's center tap needs to be at an even
+/// index, and the latency computation in [`Oversampler::latency()`] needs the filter's group delay
+/// to be divisible by all supported power of two oversampling factors.
+const HALFBAND_TAPS: usize = 65;
+/// The filter's group delay, in samples at the filter's own sample rate.
+const HALFBAND_DELAY: usize = (HALFBAND_TAPS - 1) / 2;
+
+/// The maximum supported oversampling amount, as a power of two. So up to 32x oversampling.
+pub const MAX_OVERSAMPLING_STAGES: usize = 5;
+
+/// Oversample a single channel of audio by a power of two factor by repeatedly upsampling by two
+/// with linear phase halfband filters, running a callback on the upsampled signal, and then
+/// downsampling again. All memory is allocated up front in [`Oversampler::new()`] so this can be
+/// used from the audio thread. Use one oversampler per channel.
+///
+/// The oversampling amount is expressed as the number of 2x stages, so 0 means no oversampling, 1
+/// means 2x oversampling, 2 means 4x oversampling, etc. Processing with zero stages calls the
+/// callback directly on the input without any filtering or added latency, and the amount can be
+/// changed between process calls. Call [`Oversampler::reset()`] after changing it to avoid
+/// clicks from stale filter states.
+pub struct Oversampler {
+    /// The 2x oversampling stages, in order of increasing sample rate.
+    stages: Vec<OversamplingStage>,
+    /// The maximum block size at the original sample rate.
+    max_block_size: usize,
+}
+
+/// A single 2x upsampling and downsampling stage.
+struct OversamplingStage {
+    upsampler: HalfbandFilter,
+    downsampler: HalfbandFilter,
+    /// The upsampled signal produced by this stage. Has room for twice as many samples as the
+    /// previous stage.
+    scratch_buffer: Vec<f32>,
+}
+
+/// A linear phase halfband lowpass filter with its cutoff at a quarter of its sample rate.
+struct HalfbandFilter {
+    /// The filter's coefficients. These are symmetrical, so they don't need to be reversed during
+    /// the convolution.
+    coefficients: [f32; HALFBAND_TAPS],
+    /// The filter's input history. Every sample is written twice, `HALFBAND_TAPS` samples apart,
+    /// so the last `HALFBAND_TAPS` samples can always be read as one contiguous slice.
+    history: [f32; HALFBAND_TAPS * 2],
+    /// The index in the first half of `history` the next sample should be written to.
+    pos: usize,
+}
+
+impl Oversampler {
+    /// Create an oversampler that can process blocks of up to `max_block_size` samples with up to
+    /// `num_stages` 2x oversampling stages. `num_stages` cannot exceed
+    /// [`MAX_OVERSAMPLING_STAGES`].
+    pub fn new(max_block_size: usize, num_stages: usize) -> Self {
+        nih_debug_assert!(num_stages <= MAX_OVERSAMPLING_STAGES);
+        let num_stages = num_stages.min(MAX_OVERSAMPLING_STAGES);
+
+        let coefficients = halfband_coefficients();
+        let stages = (1..=num_stages)
+            .map(|stage| OversamplingStage {
+                upsampler: HalfbandFilter::new(coefficients),
+                downsampler: HalfbandFilter::new(coefficients),
+                scratch_buffer: vec![0.0; max_block_size << stage],
+            })
+            .collect();
+
+        Self {
+            stages,
+            max_block_size,
+        }
+    }
+
+    /// The number of 2x stages this oversampler was created with.
+    pub fn num_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// The latency introduced by processing with `num_stages` 2x oversampling stages, in samples at
+    /// the original sample rate. This is zero when not oversampling.
+    pub fn latency(&self, num_stages: usize) -> u32 {
+        oversampling_latency(num_stages)
+    }
+
+    /// Clear the filters' histories.
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.upsampler.reset();
+            stage.downsampler.reset();
+        }
+    }
+
+    /// Upsample `block` using `num_stages` 2x stages, run `f` on the upsampled signal, and then
+    /// downsample the results back into `block`. `block` may not be longer than the maximum block
+    /// size passed to [`Oversampler::new()`], and `num_stages` may not exceed the number of stages
+    /// the oversampler was created with.
+    pub fn process(&mut self, block: &mut [f32], num_stages: usize, f: impl FnOnce(&mut [f32])) {
+        if num_stages == 0 {
+            nih_debug_assert!(block.len() <= self.max_block_size);
+            f(block);
+            return;
+        }
+
+        f(self.upsample(block, num_stages));
+        self.downsample(block, num_stages);
+    }
+
+    /// Upsample `block` using `num_stages` 2x stages, and return the upsampled signal. This and
+    /// [`downsample()`][Self::downsample()] are the two halves of [`process()`][Self::process()],
+    /// which is useful when all channels of a multichannel signal need to be upsampled before any
+    /// of them can be processed. `num_stages` needs to be at least one. The same limits as in
+    /// [`process()`][Self::process()] apply.
+    pub fn upsample(&mut self, block: &[f32], num_stages: usize) -> &mut [f32] {
+        nih_debug_assert!(block.len() <= self.max_block_size);
+        nih_debug_assert!(num_stages > 0 && num_stages <= self.stages.len());
+        let num_stages = num_stages.clamp(1, self.stages.len());
+
+        let stages = &mut self.stages[..num_stages];
+        for stage_idx in 0..num_stages {
+            let (previous_stages, next_stages) = stages.split_at_mut(stage_idx);
+            let stage = &mut next_stages[0];
+            let input: &[f32] = match previous_stages.last() {
+                Some(previous_stage) => &previous_stage.scratch_buffer[..block.len() << stage_idx],
+                None => block,
+            };
+
+            stage.upsample(input);
+        }
+
+        &mut stages[num_stages - 1].scratch_buffer[..block.len() << num_stages]
+    }
+
+    /// Downsample the signal returned by the last [`upsample()`][Self::upsample()] call, which may
+    /// have been modified in the meantime, back into `block`. `block` and `num_stages` need to be
+    /// the same as in that call.
+    pub fn downsample(&mut self, block: &mut [f32], num_stages: usize) {
+        nih_debug_assert!(block.len() <= self.max_block_size);
+        nih_debug_assert!(num_stages > 0 && num_stages <= self.stages.len());
+        let num_stages = num_stages.clamp(1, self.stages.len());
+
+        let stages = &mut self.stages[..num_stages];
+        for stage_idx in (0..num_stages).rev() {
+            let (previous_stages, next_stages) = stages.split_at_mut(stage_idx);
+            let stage = &mut next_stages[0];
+            let output: &mut [f32] = match previous_stages.last_mut() {
+                Some(previous_stage) => {
+                    &mut previous_stage.scratch_buffer[..block.len() << stage_idx]
+                }
+                None => block,
+            };
+
+            stage.downsample(output);
+        }
+    }
+}
+
+impl OversamplingStage {
+    /// Upsample `input` into the first `input.len() * 2` samples of the scratch buffer.
+    fn upsample(&mut self, input: &[f32]) {
+        for (input_sample, output_samples) in
+            input.iter().zip(self.scratch_buffer.chunks_exact_mut(2))
+        {
+            // Zero stuffing halves the signal's energy, so the gain needs to be doubled to
+            // compensate for that
+            output_samples[0] = self.upsampler.process(*input_sample * 2.0);
+            output_samples[1] = self.upsampler.process(0.0);
+        }
+    }
+
+    /// Downsample the first `output.len() * 2` samples of the scratch buffer into `output`.
+    fn downsample(&mut self, output: &mut [f32]) {
+        for (output_sample, input_samples) in
+            output.iter_mut().zip(self.scratch_buffer.chunks_exact(2))
+        {
+            // The even samples line up with the original samples, so those are the ones we keep.
+            // Otherwise the latency would be off by half a sample.
+            *output_sample = self.downsampler.process(input_samples[0]);
+            self.downsampler.process(input_samples[1]);
+        }
+    }
+}
+
+impl HalfbandFilter {
+    fn new(coefficients: [f32; HALFBAND_TAPS]) -> Self {
+        Self {
+            coefficients,
+            history: [0.0; HALFBAND_TAPS * 2],
+            pos: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.pos = 0;
+    }
+
+    #[inline]
+    fn process(&mut self, sample: f32) -> f32 {
+        self.history[self.pos] = sample;
+        self.history[self.pos + HALFBAND_TAPS] = sample;
+        self.pos = (self.pos + 1) % HALFBAND_TAPS;
+
+        // This now contains the last `HALFBAND_TAPS` samples from oldest to newest
+        let history = &self.history[self.pos..self.pos + HALFBAND_TAPS];
+        history
+            .iter()
+            .zip(self.coefficients.iter())
+            .map(|(sample, coefficient)| sample * coefficient)
+            .sum()
+    }
+}
+
+/// Compute the coefficients for a Blackman windowed sinc halfband lowpass filter with unity gain at
+/// DC.
+fn halfband_coefficients() -> [f32; HALFBAND_TAPS] {
+    let window = window::blackman(HALFBAND_TAPS);
+
+    let mut coefficients = [0.0; HALFBAND_TAPS];
+    for (i, (coefficient, window)) in coefficients.iter_mut().zip(window).enumerate() {
+        let x = (i as f32 - HALFBAND_DELAY as f32) * 0.5;
+        let sinc = if x == 0.0 {
+            1.0
+        } else {
+            (x * f32::consts::PI).sin() / (x * f32::consts::PI)
+        };
+
+        *coefficient = sinc * window;
+    }
+
+    let sum: f32 = coefficients.iter().sum();
+    for coefficient in &mut coefficients {
+        *coefficient /= sum;
+    }
+
+    coefficients
+}
+
+/// The latency introduced by `num_stages` 2x oversampling stages, in samples at the original sample
+/// rate. Used by the wrappers, which need to know this before any oversamplers have been created.
+pub(crate) fn oversampling_latency(num_stages: usize) -> u32 {
+    // Every stage's up and downsampling filters each add `HALFBAND_DELAY` samples of delay at that
+    // stage's sample rate
+    (1..=num_stages)
+        .map(|stage| ((HALFBAND_DELAY * 2) >> stage) as u32)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 64;
+
+    #[test]
+    fn no_oversampling_passthrough() {
+        let mut oversampler = Oversampler::new(BLOCK_SIZE, 2);
+        let mut block: Vec<f32> = (0..BLOCK_SIZE).map(|i| i as f32).collect();
+        let expected = block.clone();
+
+        let mut callback_len = 0;
+        oversampler.process(&mut block, 0, |upsampled| callback_len = upsampled.len());
+
+        assert_eq!(callback_len, BLOCK_SIZE);
+        assert_eq!(block, expected);
+        assert_eq!(oversampler.latency(0), 0);
+    }
+
+    #[test]
+    fn upsampled_block_size() {
+        let mut oversampler = Oversampler::new(BLOCK_SIZE, 3);
+        let mut block = [0.0; 10];
+
+        for num_stages in 1..=3 {
+            let mut callback_len = 0;
+            oversampler.process(&mut block, num_stages, |upsampled| {
+                callback_len = upsampled.len()
+            });
+
+            assert_eq!(callback_len, 10 << num_stages);
+        }
+    }
+
+    /// An impulse should come out of the oversampler with its peak exactly `latency()` samples
+    /// later.
+    #[test]
+    fn impulse_latency() {
+        for num_stages in 1..=MAX_OVERSAMPLING_STAGES {
+            let mut oversampler = Oversampler::new(BLOCK_SIZE, num_stages);
+            let latency = oversampler.latency(num_stages) as usize;
+
+            let mut output = Vec::new();
+            let mut block = [0.0; BLOCK_SIZE];
+            block[0] = 1.0;
+            while output.len() <= latency + BLOCK_SIZE {
+                oversampler.process(&mut block, num_stages, |_| ());
+                output.extend_from_slice(&block);
+                block.fill(0.0);
+            }
+
+            let (peak_idx, _) = output
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                .unwrap();
+            assert_eq!(peak_idx, latency, "{num_stages} stages");
+        }
+    }
+
+    /// A low frequency sine wave should pass through the filters unaltered, save for the delay.
+    #[test]
+    fn passband_gain() {
+        const FREQUENCY: f32 = 0.01; // In cycles per sample
+        let sine = |i: usize| (i as f32 * FREQUENCY * 2.0 * f32::consts::PI).sin();
+
+        for num_stages in 1..=3 {
+            let mut oversampler = Oversampler::new(BLOCK_SIZE, num_stages);
+            let latency = oversampler.latency(num_stages) as usize;
+
+            let mut output = Vec::new();
+            for block_idx in 0..32 {
+                let mut block: Vec<f32> = (0..BLOCK_SIZE)
+                    .map(|i| sine((block_idx * BLOCK_SIZE) + i))
+                    .collect();
+                oversampler.process(&mut block, num_stages, |_| ());
+                output.extend_from_slice(&block);
+            }
+
+            // Skip past the filters' initial transients
+            for (i, sample) in output.iter().enumerate().skip(latency + 256) {
+                let expected = sine(i - latency);
+                assert!(
+                    (sample - expected).abs() < 1e-3,
+                    "{num_stages} stages, sample {i}: {sample} != {expected}"
+                );
+            }
+        }
+    }
+}
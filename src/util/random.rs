@@ -0,0 +1,285 @@
+//! Small, allocation free random number generators and noise sources for use in DSP code.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::context::process::ProcessContext;
+use crate::plugin::Plugin;
+
+/// Used to give every [`Xoshiro128`] created with [`Xoshiro128::new()`] its own seed.
+static NEXT_SEED: AtomicU64 = AtomicU64::new(0);
+
+/// A fast pseudo random number generator based on the xoshiro128\*\* algorithm, suitable for use on
+/// the audio thread for things like noise, dithering, and randomized modulation. This does not
+/// allocate, lock, or make any system calls, and the generator's state is only 16 bytes so it can
+/// be copied freely. Use [`fork()`][Self::fork()] to give every voice its own stream of numbers.
+///
+/// **This is not a cryptographically secure random number generator.** Its output is entirely
+/// predictable from its seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xoshiro128 {
+    state: [u32; 4],
+}
+
+/// A filter that turns white noise into pink noise with a -3 dB per octave slope, using Paul
+/// Kellet's refined method. The coefficients are tuned for 44.1 kHz, where the slope is accurate to
+/// within ±0.05 dB above 9.2 Hz. At higher sample rates the slope holds up to around 20 kHz and
+/// flattens out above that. Feed it white noise from
+/// [`Xoshiro128::next_f32()`] to get pink noise with roughly the same peak amplitude.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PinkNoise {
+    b: [f32; 7],
+}
+
+impl Default for Xoshiro128 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Xoshiro128 {
+    /// Create a new generator with a unique seed. Seeds are taken from a global counter, so every
+    /// generator created this way produces a different stream of numbers, even when multiple
+    /// instances of a plugin are created at the same time. The seeds are still deterministic for a
+    /// given creation order.
+    pub fn new() -> Self {
+        Self::from_seed(NEXT_SEED.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Create a new generator from a fixed seed. Generators created with the same seed will always
+    /// produce the same numbers.
+    pub const fn from_seed(seed: u64) -> Self {
+        // The seed is expanded using SplitMix64 as recommended by the xoshiro authors. This also
+        // makes sure the state is never all zeroes.
+        let (seed, a) = splitmix64(seed);
+        let (_, b) = splitmix64(seed);
+
+        Self {
+            state: [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32],
+        }
+    }
+
+    /// Create a new generator for use in the process function. When the host is rendering offline
+    /// this is seeded with [`ProcessContext::deterministic_seed()`], so offline renders of the same
+    /// project produce the same numbers. Otherwise this is the same as [`new()`][Self::new()].
+    /// Generators created this way during the same offline render start out identical, so use
+    /// [`fork()`][Self::fork()] if you need more than one.
+    pub fn from_context<P: Plugin>(context: &impl ProcessContext<P>) -> Self {
+        match context.deterministic_seed() {
+            Some(seed) => Self::from_seed(seed),
+            None => Self::new(),
+        }
+    }
+
+    /// Create a new generator from this generator's output. The new generator's stream is
+    /// independent of this generator's future output, so this can be used to give every voice or
+    /// channel its own generator while keeping the whole thing deterministic.
+    pub fn fork(&mut self) -> Self {
+        Self::from_seed(((self.next_u32() as u64) << 32) | self.next_u32() as u64)
+    }
+
+    /// Generate a uniformly distributed random 32-bit integer.
+    #[inline]
+    pub fn next_u32(&mut self) -> u32 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+
+        let t = self.state[1] << 9;
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(11);
+
+        result
+    }
+
+    /// Generate a uniformly distributed random number in `[0, 1)`.
+    #[inline]
+    pub fn next_f32_unipolar(&mut self) -> f32 {
+        // The upper 24 bits fit exactly in an `f32`'s mantissa
+        (self.next_u32() >> 8) as f32 * (1.0 / (1 << 24) as f32)
+    }
+
+    /// Generate a uniformly distributed random number in `[-1, 1)`. This can be used directly as
+    /// white noise.
+    #[inline]
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1 << 23) as f32) - 1.0
+    }
+
+    /// Generate an approximately normally distributed random number with a mean of zero and a
+    /// standard deviation of one. This sums four uniform random numbers, so the output is always
+    /// within `[-3.47, 3.47)`. That makes it a good fit for noise and jitter where an
+    /// occasional extreme value would be undesirable anyways.
+    #[inline]
+    pub fn next_gaussian(&mut self) -> f32 {
+        // The sum of four uniform numbers in [-1, 1) has a variance of 4/3
+        const SCALE: f32 = 0.866_025_4; // (3.0f32 / 4.0).sqrt()
+
+        (self.next_f32() + self.next_f32() + self.next_f32() + self.next_f32()) * SCALE
+    }
+}
+
+impl PinkNoise {
+    /// Create a new pink noise filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reset the filter's state.
+    pub fn reset(&mut self) {
+        self.b = [0.0; 7];
+    }
+
+    /// Filter a single sample of white noise.
+    #[inline]
+    pub fn process(&mut self, white: f32) -> f32 {
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+
+        // The filter has a gain of about 20 dB at low frequencies
+        pink * 0.11
+    }
+
+    /// Generate the next pink noise sample using white noise from `rng`.
+    #[inline]
+    pub fn next_sample(&mut self, rng: &mut Xoshiro128) -> f32 {
+        self.process(rng.next_f32())
+    }
+}
+
+/// A single SplitMix64 step. Returns the new state and the output.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    (state, z ^ (z >> 31))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUM_SAMPLES: usize = 1 << 20;
+
+    /// The mean and variance of a sequence of numbers.
+    fn mean_variance(mut f: impl FnMut() -> f32) -> (f64, f64) {
+        let mut sum = 0.0f64;
+        let mut sum_squares = 0.0f64;
+        for _ in 0..NUM_SAMPLES {
+            let x = f() as f64;
+            sum += x;
+            sum_squares += x * x;
+        }
+
+        let mean = sum / NUM_SAMPLES as f64;
+        (mean, sum_squares / NUM_SAMPLES as f64 - mean * mean)
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut a = Xoshiro128::from_seed(1234);
+        let mut b = Xoshiro128::from_seed(1234);
+        for _ in 0..1024 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+
+        // Instances created with `new()` should decorrelate
+        let mut a = Xoshiro128::new();
+        let mut b = Xoshiro128::new();
+        assert_ne!(a, b);
+        let matches = (0..1024).filter(|_| a.next_u32() == b.next_u32()).count();
+        assert!(matches < 4);
+    }
+
+    #[test]
+    fn forks_are_independent() {
+        let mut rng = Xoshiro128::from_seed(1);
+        let mut fork = rng.fork();
+        let matches = (0..1024)
+            .filter(|_| rng.next_u32() == fork.next_u32())
+            .count();
+        assert!(matches < 4);
+    }
+
+    #[test]
+    fn uniform_distribution() {
+        let mut rng = Xoshiro128::from_seed(42);
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let (mean, variance) = mean_variance(|| {
+            let x = rng.next_f32();
+            min = min.min(x);
+            max = max.max(x);
+            x
+        });
+
+        assert!((-1.0..1.0).contains(&min) && (-1.0..1.0).contains(&max));
+        assert!(mean.abs() < 0.005, "mean: {mean}");
+        // The variance of a uniform distribution on [-1, 1) is 1/3
+        assert!((variance - 1.0 / 3.0).abs() < 0.005, "variance: {variance}");
+
+        let (mean, variance) = mean_variance(|| rng.next_f32_unipolar());
+        assert!((mean - 0.5).abs() < 0.005, "mean: {mean}");
+        assert!(
+            (variance - 1.0 / 12.0).abs() < 0.002,
+            "variance: {variance}"
+        );
+    }
+
+    #[test]
+    fn gaussian_distribution() {
+        let mut rng = Xoshiro128::from_seed(42);
+        let (mean, variance) = mean_variance(|| rng.next_gaussian());
+
+        assert!(mean.abs() < 0.005, "mean: {mean}");
+        assert!((variance - 1.0).abs() < 0.01, "variance: {variance}");
+    }
+
+    /// The pink noise filter's magnitude response should drop by 3 dB per octave.
+    #[test]
+    fn pink_noise_slope() {
+        const SAMPLE_RATE: f64 = 44_100.0;
+        const IMPULSE_RESPONSE_LEN: usize = 1 << 15;
+
+        let mut filter = PinkNoise::new();
+        let impulse_response: Vec<f64> = (0..IMPULSE_RESPONSE_LEN)
+            .map(|i| filter.process(if i == 0 { 1.0 } else { 0.0 }) as f64)
+            .collect();
+        let magnitude_db = |frequency: f64| {
+            let omega = 2.0 * std::f64::consts::PI * frequency / SAMPLE_RATE;
+            let (re, im) =
+                impulse_response
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (n, x)| {
+                        (
+                            re + x * (omega * n as f64).cos(),
+                            im - x * (omega * n as f64).sin(),
+                        )
+                    });
+
+            10.0 * (re * re + im * im).log10()
+        };
+
+        let mut frequency = 20.0;
+        while frequency < 10_000.0 {
+            let slope = magnitude_db(frequency * 2.0) - magnitude_db(frequency);
+            assert!(
+                (slope + 3.01).abs() < 0.25,
+                "Slope of {slope} dB per octave at {frequency} Hz"
+            );
+
+            frequency *= 2.0;
+        }
+    }
+}
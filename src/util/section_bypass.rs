@@ -0,0 +1,313 @@
+//! Click-free enable switches for sections of a plugin's processing.
+
+use crate::buffer::Buffer;
+use crate::params::internals::ParamPtr;
+use crate::params::{BoolParam, Param, Params};
+
+/// The parameter for a [`SectionBypass`]. Add this to the plugin's [`Params`] object, for instance
+/// using `#[nested(id_prefix = "eq", group = "EQ")] eq_section: SectionBypassParams`, so the
+/// switch is automatable and saved with the plugin's state.
+pub struct SectionBypassParams {
+    /// Whether the section is enabled. Sections are enabled by default.
+    pub enabled: BoolParam,
+}
+
+/// Runs part of a plugin's processing only while that section is enabled, and crossfades between
+/// the section's output and its input when the section gets toggled. This replaces
+/// `if self.params.section_enabled.value() { ... }` checks in the process function, which cause
+/// clicks when the section gets toggled while audio is playing.
+///
+/// Call [`initialize()`][Self::initialize()] from the plugin's `initialize()` function to allocate
+/// the buffer for the section's input, and [`reset()`][Self::reset()] from the plugin's `reset()`
+/// function. After that [`process_section()`][Self::process_section()] never allocates. Sections
+/// can be nested by calling another section's `process_section()` from within the closure. A
+/// section must not add any latency of its own, since the section's input is mixed in without any
+/// latency compensation.
+///
+/// While the section is fully disabled the closure is not called at all, so the section's DSP
+/// state is left as is until it gets enabled again. When NIH-plug's own bypass parameter is used,
+/// the plugin's process function is not called while the plugin is bypassed, and sections continue
+/// where they left off once the plugin is no longer bypassed.
+#[derive(Debug, Clone)]
+pub struct SectionBypass {
+    /// The length of the crossfade in milliseconds.
+    fade_time_ms: f32,
+    /// The length of the crossfade in samples, computed in [`initialize()`][Self::initialize()].
+    fade_length: u32,
+    /// A copy of the section's input for the current block for every channel, allocated for the
+    /// maximum block size. Only used while fading.
+    dry: Vec<Vec<f32>>,
+    /// How far the section has been faded out, from zero for only the section's output to
+    /// `fade_length` for only the section's input.
+    position: u32,
+    /// Whether the section was enabled for the current block. `position` moves towards this.
+    enabled: bool,
+}
+
+impl SectionBypassParams {
+    /// Create the parameter for a section called `section_name`. The parameter's name will be
+    /// `"{section_name} Enabled"`.
+    pub fn new(section_name: &str) -> Self {
+        Self {
+            enabled: BoolParam::new(format!("{section_name} Enabled"), true),
+        }
+    }
+}
+
+unsafe impl Params for SectionBypassParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(
+            String::from("enabled"),
+            self.enabled.as_ptr(),
+            String::new(),
+        )]
+    }
+}
+
+impl SectionBypass {
+    /// Create a section that crossfades over `fade_time_ms` milliseconds when it gets toggled. A
+    /// fade time of zero switches instantly. The section starts out enabled.
+    pub fn new(fade_time_ms: f32) -> Self {
+        nih_debug_assert!(fade_time_ms >= 0.0);
+
+        Self {
+            fade_time_ms: fade_time_ms.max(0.0),
+            fade_length: 0,
+            dry: Vec::new(),
+            position: 0,
+            enabled: true,
+        }
+    }
+
+    /// Allocate the buffers for `num_channels` channels and `max_block_size` samples, and compute
+    /// the crossfade's length for `sample_rate`. This should be called from the plugin's
+    /// `initialize()` function.
+    pub fn initialize(&mut self, num_channels: usize, max_block_size: usize, sample_rate: f32) {
+        self.dry.resize_with(num_channels, Vec::new);
+        for channel in &mut self.dry {
+            channel.clear();
+            channel.resize(max_block_size, 0.0);
+        }
+
+        self.fade_length = (self.fade_time_ms / 1000.0 * sample_rate).round() as u32;
+        self.position = self.target_position();
+    }
+
+    /// Jump to `enabled` without fading. This should be called from the plugin's `reset()`
+    /// function with the section's current state.
+    pub fn reset(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.position = self.target_position();
+    }
+
+    /// Whether the section is disabled and has been faded out completely. The plugin can use this
+    /// to reset the section's DSP state.
+    pub fn is_bypassed(&self) -> bool {
+        !self.enabled && self.position == self.fade_length
+    }
+
+    /// Process `buffer` with the section's DSP in `section` if `enabled` is set, usually the value
+    /// of [`SectionBypassParams::enabled`]. If the section was toggled, the output is crossfaded
+    /// between the section's output and `buffer`'s original contents. The crossfade can span
+    /// multiple blocks. `section` is only called while the section is enabled or fading.
+    ///
+    /// Blocks with more channels or samples than the section was
+    /// [initialized][Self::initialize()] for can't be crossfaded without allocating. In that case
+    /// the section switches instantly instead, and a debug assertion is triggered.
+    pub fn process_section(
+        &mut self,
+        buffer: &mut Buffer,
+        enabled: bool,
+        section: impl FnOnce(&mut Buffer),
+    ) {
+        self.enabled = enabled;
+        if enabled && self.position == 0 {
+            section(buffer);
+            return;
+        }
+        if self.is_bypassed() {
+            return;
+        }
+
+        let num_samples = buffer.len();
+        if buffer.channels() > self.dry.len()
+            || self.dry.iter().any(|channel| channel.len() < num_samples)
+        {
+            nih_debug_assert_failure!(
+                "SectionBypass::initialize() was not called with the correct channel count and \
+                 maximum block size, switching without a crossfade"
+            );
+
+            self.position = self.target_position();
+            if enabled {
+                section(buffer);
+            }
+
+            return;
+        }
+
+        for (channel, dry) in buffer.as_slice_immutable().iter().zip(self.dry.iter_mut()) {
+            dry[..num_samples].copy_from_slice(channel);
+        }
+
+        section(buffer);
+
+        for (sample_idx, channel_samples) in buffer.iter_samples().enumerate() {
+            let dry_gain = self.position as f32 / self.fade_length as f32;
+            for (sample, dry) in channel_samples.into_iter().zip(self.dry.iter()) {
+                *sample = (*sample * (1.0 - dry_gain)) + (dry[sample_idx] * dry_gain);
+            }
+
+            self.position = if self.enabled {
+                self.position.saturating_sub(1)
+            } else {
+                (self.position + 1).min(self.fade_length)
+            };
+        }
+    }
+
+    fn target_position(&self) -> u32 {
+        if self.enabled {
+            0
+        } else {
+            self.fade_length
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 8000.0;
+
+    /// Process `input` with `section` in blocks of `block_size` samples, toggling the section to
+    /// `enabled(block_idx)` at the start of every block. The section's DSP replaces the signal
+    /// with `wet`.
+    fn process(
+        bypass: &mut SectionBypass,
+        input: &[f32],
+        block_size: usize,
+        enabled: impl Fn(usize) -> bool,
+        wet: impl Fn(f32) -> f32,
+    ) -> Vec<f32> {
+        let mut output = input.to_vec();
+        for (block_idx, block) in output.chunks_mut(block_size).enumerate() {
+            let mut buffer = Buffer::default();
+            unsafe { buffer.with_raw_vec(|slices| slices.push(block)) };
+            bypass.process_section(&mut buffer, enabled(block_idx), |buffer| {
+                for channel in buffer.as_slice() {
+                    for sample in channel.iter_mut() {
+                        *sample = wet(*sample);
+                    }
+                }
+            });
+        }
+
+        output
+    }
+
+    #[test]
+    fn crossfade_spans_blocks() {
+        // 1 ms at 8 kHz is an 8 sample fade, which starts in the middle of the second block here
+        let mut bypass = SectionBypass::new(1.0);
+        bypass.initialize(1, 4, SAMPLE_RATE);
+        let output = process(&mut bypass, &[1.0; 15], 3, |block| block == 0, |_| 0.0);
+        assert_eq!(
+            output,
+            [0.0, 0.0, 0.0, 0.0, 0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875, 1.0, 1.0, 1.0, 1.0]
+        );
+        assert!(bypass.is_bypassed());
+
+        // Enabling the section again fades back out in the same way
+        let output = process(&mut bypass, &[1.0; 12], 4, |_| true, |_| 0.0);
+        assert_eq!(
+            output,
+            [1.0, 0.875, 0.75, 0.625, 0.5, 0.375, 0.25, 0.125, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn toggle_during_fade() {
+        // The fade reverses from where it was instead of jumping
+        let mut bypass = SectionBypass::new(1.0);
+        bypass.initialize(1, 2, SAMPLE_RATE);
+        let output = process(&mut bypass, &[1.0; 4], 2, |block| block < 1, |_| 0.0);
+        assert_eq!(output, [0.0, 0.0, 0.0, 0.125]);
+        let output = process(&mut bypass, &[1.0; 4], 2, |_| true, |_| 0.0);
+        assert_eq!(output, [0.25, 0.125, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn fully_bypassed_skips_section() {
+        let mut bypass = SectionBypass::new(1.0);
+        bypass.initialize(1, 4, SAMPLE_RATE);
+        bypass.reset(false);
+
+        let mut samples = [0.5; 4];
+        let mut buffer = Buffer::default();
+        unsafe { buffer.with_raw_vec(|slices| slices.push(&mut samples)) };
+        bypass.process_section(&mut buffer, false, |_| {
+            panic!("The section should be skipped")
+        });
+        assert_eq!(samples, [0.5; 4]);
+    }
+
+    /// A sine wave through a section that inverts it shouldn't jump by more than the sine's own
+    /// slope and a single fade step when toggled at arbitrary points.
+    #[test]
+    fn no_discontinuities() {
+        const FADE_MS: f32 = 5.0;
+        const FREQUENCY: f32 = 100.0;
+
+        let input: Vec<f32> = (0..4000)
+            .map(|i| (i as f32 / SAMPLE_RATE * FREQUENCY * std::f32::consts::TAU).sin())
+            .collect();
+        let max_slope = FREQUENCY * std::f32::consts::TAU / SAMPLE_RATE;
+        let fade_length = FADE_MS / 1000.0 * SAMPLE_RATE;
+        let max_step = 2.0 / fade_length + 2.0 * max_slope;
+
+        let mut bypass = SectionBypass::new(FADE_MS);
+        bypass.initialize(1, 7, SAMPLE_RATE);
+        let output = process(&mut bypass, &input, 7, |block| block % 5 < 2, |x| -x);
+        for (idx, window) in output.windows(2).enumerate() {
+            let step = (window[1] - window[0]).abs();
+            assert!(step <= max_step, "step of {step} at sample {idx}");
+        }
+    }
+
+    /// The outer section's crossfade should include the inner section's output, and toggling the
+    /// inner section during the outer section's fade should still be continuous.
+    #[test]
+    fn nested_sections() {
+        let mut outer = SectionBypass::new(1.0);
+        let mut inner = SectionBypass::new(1.0);
+        outer.initialize(1, 4, SAMPLE_RATE);
+        inner.initialize(1, 4, SAMPLE_RATE);
+
+        let mut output = vec![1.0; 24];
+        for (block_idx, block) in output.chunks_mut(4).enumerate() {
+            let mut buffer = Buffer::default();
+            unsafe { buffer.with_raw_vec(|slices| slices.push(block)) };
+            outer.process_section(&mut buffer, block_idx < 1, |buffer| {
+                for sample in buffer.as_slice()[0].iter_mut() {
+                    *sample *= 2.0;
+                }
+                inner.process_section(buffer, block_idx < 2, |buffer| {
+                    for sample in buffer.as_slice()[0].iter_mut() {
+                        *sample += 1.0;
+                    }
+                });
+            });
+        }
+
+        // Both sections enabled gives 3, the outer section fades from there to the input over 8
+        // samples while the inner section fades from 3 to 2 within the outer section
+        assert_eq!(output[..4], [3.0; 4]);
+        assert_eq!(output[16..], [1.0; 8]);
+        for window in output.windows(2) {
+            assert!(window[1] <= window[0], "{output:?}");
+            assert!(window[0] - window[1] <= 0.5, "{output:?}");
+        }
+    }
+}
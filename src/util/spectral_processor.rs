@@ -0,0 +1,415 @@
+//! A complete STFT overlap-add scaffold for spectral effects that need to look at the spectra of
+//! all channels at once.
+
+use realfft::num_complex::Complex32;
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex};
+use std::mem;
+use std::sync::Arc;
+
+use super::{window, SpectrumWindow};
+use crate::buffer::Buffer;
+
+/// The configuration for a [`SpectralProcessor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpectralProcessorConfig {
+    /// The size of the analysis window in samples. Must be a power of two.
+    pub window_size: usize,
+    /// How many windows are processed for every `window_size` samples. Must be at least 2 and a
+    /// divisor of `window_size`.
+    pub overlap: usize,
+    /// The analysis window applied before each FFT.
+    pub window: SpectrumWindow,
+    /// The number of zeroes appended to every window before taking the FFT. The FFT size is
+    /// `window_size + padding`. This can be used to prevent time domain aliasing when convolving
+    /// in the frequency domain. The tail of the inverse FFT gets added to the following windows.
+    pub padding: usize,
+}
+
+/// A short-time Fourier transform scaffold for spectral effects. Unlike
+/// [`StftHelper`][super::StftHelper], this does everything for you: it buffers the input, applies
+/// the window function, takes the forward FFT of every channel, hands the spectra of _all_
+/// channels to a single callback, takes the inverse FFTs, and overlap-adds the results with the
+/// correct gain compensation. If the callback leaves the spectra alone, the output is the input
+/// delayed by [`latency_samples()`][Self::latency_samples()] samples.
+///
+/// Only the analysis window is applied. The output is normalized by the sum of the overlapping
+/// analysis windows, so this reconstructs the input perfectly for any supported window and
+/// overlap, even for window functions that don't sum to a constant on their own.
+///
+/// All buffers are allocated in [`new()`][Self::new()]. Processing does not allocate.
+pub struct SpectralProcessor {
+    config: SpectralProcessorConfig,
+    /// `window_size / overlap`, the number of samples between the starts of two windows.
+    hop_size: usize,
+
+    r2c_plan: Arc<dyn RealToComplex<f32>>,
+    c2r_plan: Arc<dyn ComplexToReal<f32>>,
+    /// The analysis window function.
+    window_function: Vec<f32>,
+    /// The gain applied to the inverse FFT output, indexed by the sample's position within a hop.
+    /// This compensates for both the unnormalized inverse FFT and the sum of the overlapping
+    /// windows.
+    output_gain: Vec<f32>,
+
+    /// The last `window_size` input samples for every channel. New samples are written to the last
+    /// `hop_size` samples, and the buffer is shifted back by `hop_size` after every window.
+    input_buffers: Vec<Vec<f32>>,
+    /// The overlap-added output for every channel, `window_size + padding` samples long. Output is
+    /// read from the first `hop_size` samples, and the buffer is shifted back by `hop_size` after
+    /// every window.
+    output_buffers: Vec<Vec<f32>>,
+    /// The spectra for every channel, one `fft_size / 2 + 1` bin buffer per channel.
+    complex_fft_buffers: Vec<Vec<Complex32>>,
+    /// An always empty vector with capacity for `num_channels` slices. This is filled with slices
+    /// of `complex_fft_buffers` before calling the callback, so the callback can't change the
+    /// number of bins and the slices don't need to be allocated on the audio thread.
+    spectrum_slices: Vec<&'static mut [Complex32]>,
+    /// Scratch space for the real valued FFT input and output.
+    real_fft_buffer: Vec<f32>,
+
+    /// The position within the current hop, in `[0, hop_size)`.
+    hop_pos: usize,
+}
+
+impl SpectralProcessor {
+    /// Create a new spectral processor for `num_channels` channels. This allocates all buffers, so
+    /// it should be called from the plugin's `Default` implementation or from `initialize()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the window size is not a power of two, or if the overlap is smaller than two or
+    /// does not divide the window size.
+    pub fn new(num_channels: usize, config: SpectralProcessorConfig) -> Self {
+        assert!(config.window_size.is_power_of_two());
+        assert!(config.overlap >= 2 && config.window_size % config.overlap == 0);
+
+        let hop_size = config.window_size / config.overlap;
+        let fft_size = config.window_size + config.padding;
+
+        let mut planner = RealFftPlanner::new();
+        let r2c_plan = planner.plan_fft_forward(fft_size);
+        let c2r_plan = planner.plan_fft_inverse(fft_size);
+
+        let window_function = match config.window {
+            SpectrumWindow::Hann => window::hann(config.window_size),
+            SpectrumWindow::Blackman => window::blackman(config.window_size),
+        };
+
+        // Every output sample is the sum of `overlap` windowed copies of the input sample. Since
+        // the windows start every `hop_size` samples, that sum only depends on the sample's
+        // position within the hop.
+        let output_gain = (0..hop_size)
+            .map(|hop_offset| {
+                let window_sum: f32 = window_function
+                    .iter()
+                    .skip(hop_offset)
+                    .step_by(hop_size)
+                    .sum();
+
+                (window_sum * fft_size as f32).recip()
+            })
+            .collect();
+
+        Self {
+            config,
+            hop_size,
+
+            complex_fft_buffers: vec![r2c_plan.make_output_vec(); num_channels],
+            spectrum_slices: Vec::with_capacity(num_channels),
+            real_fft_buffer: r2c_plan.make_input_vec(),
+            r2c_plan,
+            c2r_plan,
+            window_function,
+            output_gain,
+
+            input_buffers: vec![vec![0.0; config.window_size]; num_channels],
+            output_buffers: vec![vec![0.0; fft_size]; num_channels],
+
+            hop_pos: 0,
+        }
+    }
+
+    /// The configuration this processor was created with.
+    pub fn config(&self) -> &SpectralProcessorConfig {
+        &self.config
+    }
+
+    /// The number of channels this processor was created for.
+    pub fn num_channels(&self) -> usize {
+        self.input_buffers.len()
+    }
+
+    /// The size of the FFT, including padding.
+    pub fn fft_size(&self) -> usize {
+        self.config.window_size + self.config.padding
+    }
+
+    /// The number of frequency bins in every spectrum passed to the callback, including the DC and
+    /// Nyquist bins.
+    pub fn num_bins(&self) -> usize {
+        self.fft_size() / 2 + 1
+    }
+
+    /// The amount of latency introduced by the processor, in samples. This is always equal to the
+    /// window size.
+    pub fn latency_samples(&self) -> u32 {
+        self.config.window_size as u32
+    }
+
+    /// Clear the internal buffers. Call this from the plugin's `reset()` function.
+    pub fn reset(&mut self) {
+        for buffer in self
+            .input_buffers
+            .iter_mut()
+            .chain(self.output_buffers.iter_mut())
+        {
+            buffer.fill(0.0);
+        }
+
+        self.hop_pos = 0;
+    }
+
+    /// Process the audio in `buffer` in place. Every `window_size / overlap` samples,
+    /// `process_cb` is called with the spectra of the most recent window for all channels, in
+    /// channel order. Every spectrum contains [`num_bins()`][Self::num_bins()] bins, and can be
+    /// modified in place. The imaginary parts of the DC and Nyquist bins are discarded afterwards
+    /// since those need to be real valued for the inverse FFT.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer's channel count does not match the processor's.
+    pub fn process<F>(&mut self, buffer: &mut Buffer, mut process_cb: F)
+    where
+        F: FnMut(&mut [&mut [Complex32]]),
+    {
+        let channels = buffer.as_slice();
+        assert_eq!(channels.len(), self.num_channels());
+
+        let num_samples = channels.first().map_or(0, |channel| channel.len());
+        let window_size = self.config.window_size;
+        let hop_size = self.hop_size;
+
+        let mut block_start = 0;
+        while block_start < num_samples {
+            // Process samples up to the next hop boundary in one go
+            let block_len = (hop_size - self.hop_pos).min(num_samples - block_start);
+            let block_end = block_start + block_len;
+            let input_start = window_size - hop_size + self.hop_pos;
+            for ((channel, input_buffer), output_buffer) in channels
+                .iter_mut()
+                .zip(self.input_buffers.iter_mut())
+                .zip(self.output_buffers.iter())
+            {
+                let channel = &mut channel[block_start..block_end];
+                input_buffer[input_start..input_start + block_len].copy_from_slice(channel);
+                channel.copy_from_slice(&output_buffer[self.hop_pos..self.hop_pos + block_len]);
+            }
+
+            self.hop_pos += block_len;
+            block_start = block_end;
+
+            if self.hop_pos == hop_size {
+                self.process_window(&mut process_cb);
+                self.hop_pos = 0;
+            }
+        }
+    }
+
+    /// Take the FFTs of the current windows, run the callback, and overlap-add the inverse FFTs to
+    /// the output buffers.
+    fn process_window<F>(&mut self, process_cb: &mut F)
+    where
+        F: FnMut(&mut [&mut [Complex32]]),
+    {
+        let window_size = self.config.window_size;
+        let hop_size = self.hop_size;
+
+        for (input_buffer, complex_fft_buffer) in self
+            .input_buffers
+            .iter_mut()
+            .zip(self.complex_fft_buffers.iter_mut())
+        {
+            for ((real_sample, input_sample), window_sample) in self
+                .real_fft_buffer
+                .iter_mut()
+                .zip(input_buffer.iter())
+                .zip(&self.window_function)
+            {
+                *real_sample = input_sample * window_sample;
+            }
+            self.real_fft_buffer[window_size..].fill(0.0);
+
+            // RustFFT doesn't actually need a scratch buffer here, so we'll pass an empty buffer
+            // instead
+            self.r2c_plan
+                .process_with_scratch(&mut self.real_fft_buffer, complex_fft_buffer, &mut [])
+                .unwrap();
+
+            input_buffer.copy_within(hop_size.., 0);
+        }
+
+        // SAFETY: The vector is empty, so only the lifetime of the (nonexistent) elements changes.
+        //         It is cleared again before the borrows of `complex_fft_buffers` end.
+        let mut spectra: Vec<&mut [Complex32]> =
+            unsafe { mem::transmute(mem::take(&mut self.spectrum_slices)) };
+        spectra.extend(
+            self.complex_fft_buffers
+                .iter_mut()
+                .map(|complex_fft_buffer| complex_fft_buffer.as_mut_slice()),
+        );
+        process_cb(&mut spectra);
+        spectra.clear();
+        self.spectrum_slices = unsafe { mem::transmute(spectra) };
+
+        for (output_buffer, complex_fft_buffer) in self
+            .output_buffers
+            .iter_mut()
+            .zip(self.complex_fft_buffers.iter_mut())
+        {
+            // The inverse FFT fails if these are not real valued
+            if let Some(dc_bin) = complex_fft_buffer.first_mut() {
+                dc_bin.im = 0.0;
+            }
+            if self.real_fft_buffer.len() % 2 == 0 {
+                if let Some(nyquist_bin) = complex_fft_buffer.last_mut() {
+                    nyquist_bin.im = 0.0;
+                }
+            }
+            // This can't fail since the buffer sizes are fixed and the DC and Nyquist bins are real
+            // valued now
+            let result = self.c2r_plan.process_with_scratch(
+                complex_fft_buffer,
+                &mut self.real_fft_buffer,
+                &mut [],
+            );
+            nih_debug_assert!(result.is_ok());
+
+            // The first `hop_size` samples have already been written to the output
+            output_buffer.copy_within(hop_size.., 0);
+            let output_len = output_buffer.len();
+            output_buffer[output_len - hop_size..].fill(0.0);
+
+            for (sample_idx, (output_sample, real_sample)) in output_buffer
+                .iter_mut()
+                .zip(&self.real_fft_buffer)
+                .enumerate()
+            {
+                *output_sample += real_sample * self.output_gain[sample_idx % hop_size];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW_SIZE: usize = 256;
+
+    fn config(window: SpectrumWindow, overlap: usize, padding: usize) -> SpectralProcessorConfig {
+        SpectralProcessorConfig {
+            window_size: WINDOW_SIZE,
+            overlap,
+            window,
+            padding,
+        }
+    }
+
+    /// A deterministic noise-like test signal.
+    fn test_signal(num_samples: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(2_654_435_761).max(1);
+        (0..num_samples)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state as f32 / u32::MAX as f32) * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    /// Run the channels through the processor in a couple of uneven blocks without modifying the
+    /// spectra.
+    fn process_passthrough(processor: &mut SpectralProcessor, channels: &mut [Vec<f32>]) {
+        let num_samples = channels[0].len();
+        let mut block_start = 0;
+        for block_len in [1, 100, 37, 300, 511].iter().cycle() {
+            if block_start >= num_samples {
+                break;
+            }
+
+            let block_end = (block_start + block_len).min(num_samples);
+            let mut slices: Vec<&mut [f32]> = channels
+                .iter_mut()
+                .map(|channel| &mut channel[block_start..block_end])
+                .collect();
+            let mut buffer = Buffer::default();
+            unsafe { buffer.with_raw_vec(|raw_slices| raw_slices.append(&mut slices)) };
+            let num_bins = processor.num_bins();
+            processor.process(&mut buffer, |spectra| {
+                assert_eq!(spectra.len(), 2);
+                assert!(spectra.iter().all(|spectrum| spectrum.len() == num_bins));
+            });
+
+            block_start = block_end;
+        }
+    }
+
+    #[test]
+    fn perfect_reconstruction() {
+        for window in [SpectrumWindow::Hann, SpectrumWindow::Blackman] {
+            for overlap in [2, 4, 8] {
+                for padding in [0, 64] {
+                    let mut processor = SpectralProcessor::new(2, config(window, overlap, padding));
+                    let latency = processor.latency_samples() as usize;
+
+                    let inputs = [test_signal(8192, 1), test_signal(8192, 2)];
+                    let mut outputs = inputs.clone();
+                    process_passthrough(&mut processor, &mut outputs);
+
+                    for (input, output) in inputs.iter().zip(&outputs) {
+                        let error_power: f32 = input
+                            .iter()
+                            .zip(&output[latency..])
+                            .map(|(input, output)| (output - input).powi(2))
+                            .sum();
+                        let signal_power: f32 = input[..input.len() - latency]
+                            .iter()
+                            .map(|sample| sample.powi(2))
+                            .sum();
+                        let error_db = 10.0 * (error_power / signal_power).log10();
+                        assert!(
+                            error_db < -120.0,
+                            "{window:?}, {overlap}x overlap, {padding} samples of padding: \
+                             {error_db} dB"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn latency_matches_cross_correlation() {
+        for overlap in [2, 4] {
+            let mut processor = SpectralProcessor::new(2, config(SpectrumWindow::Hann, overlap, 0));
+
+            let input = test_signal(4096, 3);
+            let mut outputs = vec![input.clone(), input.clone()];
+            process_passthrough(&mut processor, &mut outputs);
+
+            let (best_lag, _) = (0..WINDOW_SIZE * 2)
+                .map(|lag| {
+                    let correlation: f32 = input
+                        .iter()
+                        .zip(&outputs[0][lag..])
+                        .map(|(input, output)| input * output)
+                        .sum();
+                    (lag, correlation)
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .unwrap();
+            assert_eq!(best_lag, processor.latency_samples() as usize);
+        }
+    }
+}
@@ -0,0 +1,458 @@
+//! A spectrum analyzer that computes magnitude spectra on the audio thread and sends them to the
+//! editor.
+
+use realfft::num_complex::Complex32;
+use realfft::{RealFftPlanner, RealToComplex};
+use std::sync::Arc;
+use triple_buffer::TripleBuffer;
+
+use super::stft::StftInput;
+use super::{window, StftHelper, MINUS_INFINITY_DB};
+use crate::buffer::Buffer;
+
+/// The window function applied before each FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpectrumWindow {
+    /// A Hann window. A good default choice.
+    Hann,
+    /// A Blackman window. Has less spectral leakage than the Hann window at the cost of a wider
+    /// main lobe.
+    Blackman,
+}
+
+/// How consecutive magnitude frames are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpectrumAveraging {
+    /// Every frame contains only the most recent FFT.
+    None,
+    /// An exponential moving average. `decay_ms` is the time it takes for a bin to drop by 12 dB
+    /// after its input disappears, and a bin rises just as slowly.
+    Exponential { decay_ms: f32 },
+    /// Peak meter-like behavior. A bin rises immediately, holds its value for `hold_ms`
+    /// milliseconds, and then falls at `decay_db_per_second`.
+    PeakHold {
+        hold_ms: f32,
+        decay_db_per_second: f32,
+    },
+}
+
+/// The configuration for a [`SpectrumAnalyzer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumAnalyzerConfig {
+    /// The FFT size in samples. Must be a power of two.
+    pub fft_size: usize,
+    /// How many FFTs are computed for every `fft_size` samples. Must be a divisor of `fft_size`.
+    pub overlap: usize,
+    pub window: SpectrumWindow,
+    pub averaging: SpectrumAveraging,
+}
+
+/// A magnitude spectrum computed by a [`SpectrumAnalyzer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpectrumFrame {
+    /// The sample rate the spectrum was computed at, or zero if the analyzer has not been
+    /// initialized yet.
+    pub sample_rate: f32,
+    /// The FFT size used to compute the spectrum.
+    pub fft_size: usize,
+    /// The magnitude of every frequency bin in decibels, including the DC and Nyquist bins. A sine
+    /// wave with amplitude 1.0 that lies exactly on a bin shows up as 0 dB. Contains `fft_size / 2
+    /// + 1` values.
+    pub magnitudes_db: Vec<f32>,
+}
+
+/// Continuously computes magnitude spectra for the audio passed to
+/// [`process_buffer()`][Self::process_buffer()], and publishes them to the connected
+/// [`SpectrumOutput`] without blocking. The spectra of all channels are averaged together.
+///
+/// The FFTs are computed inline on the audio thread. In the worst case a single `process_buffer()`
+/// call performs `ceil(num_samples / (fft_size / overlap)) * num_channels` real FFTs of `fft_size`
+/// samples, each of which takes `O(fft_size * log(fft_size))` time. This does not allocate.
+pub struct SpectrumAnalyzer {
+    config: SpectrumAnalyzerConfig,
+    /// Buffers the input and calls back whenever a new window is available. Recreated in
+    /// [`initialize()`][Self::initialize()] when the number of channels changes.
+    stft: StftHelper,
+
+    plan: Arc<dyn RealToComplex<f32>>,
+    /// The window function, with the gain compensation needed to make a full scale sine wave show
+    /// up as 0 dB baked in.
+    compensated_window_function: Vec<f32>,
+    /// The output of the real->complex FFT.
+    complex_fft_buffer: Vec<Complex32>,
+    /// The per-bin linear magnitudes summed over all channels for the current window.
+    channel_magnitude_sum: Vec<f32>,
+
+    /// The averaged result in decibels. Copied to the output after every window.
+    magnitudes_db: Vec<f32>,
+    /// For [`SpectrumAveraging::PeakHold`], the number of windows every bin still holds its value.
+    hold_windows_remaining: Vec<u32>,
+    /// The sample rate set in [`initialize()`][Self::initialize()].
+    sample_rate: f32,
+    /// The averaging parameters, computed from the sample rate.
+    averaging: AveragingCoefficients,
+
+    output: triple_buffer::Input<SpectrumFrame>,
+}
+
+/// Receives the magnitude spectra computed by a [`SpectrumAnalyzer`]. Move this to the editor.
+pub struct SpectrumOutput {
+    output: triple_buffer::Output<SpectrumFrame>,
+}
+
+/// [`SpectrumAveraging`] converted to per-window coefficients.
+#[derive(Debug, Clone, Copy)]
+enum AveragingCoefficients {
+    None,
+    /// The old value is multiplied by this weight, and the new value by one minus this weight.
+    Exponential {
+        weight: f32,
+    },
+    PeakHold {
+        hold_windows: u32,
+        decay_db_per_window: f32,
+    },
+}
+
+impl SpectrumAnalyzer {
+    /// Create a new analyzer and output pair. This allocates, so it should be called from the
+    /// plugin's `Default` implementation, and the output can then be moved to the editor.
+    /// [`initialize()`][Self::initialize()] needs to be called before the analyzer produces any
+    /// results.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the FFT size is not a power of two or if the overlap does not divide the FFT size.
+    pub fn new(config: SpectrumAnalyzerConfig) -> (Self, SpectrumOutput) {
+        assert!(config.fft_size.is_power_of_two());
+        assert!(config.overlap > 0 && config.fft_size % config.overlap == 0);
+
+        let num_bins = config.fft_size / 2 + 1;
+        let (input, output) = TripleBuffer::new(&SpectrumFrame {
+            sample_rate: 0.0,
+            fft_size: config.fft_size,
+            magnitudes_db: vec![MINUS_INFINITY_DB; num_bins],
+        })
+        .split();
+
+        let mut window_function = match config.window {
+            SpectrumWindow::Hann => window::hann(config.fft_size),
+            SpectrumWindow::Blackman => window::blackman(config.fft_size),
+        };
+        // A sine wave with amplitude `a` that lies exactly on a bin results in a magnitude of `a / 2
+        // * sum(window)` in that bin. The DC and Nyquist bins are compensated separately.
+        let gain_compensation = 2.0 / window_function.iter().sum::<f32>();
+        for sample in &mut window_function {
+            *sample *= gain_compensation;
+        }
+
+        let analyzer = Self {
+            config,
+            // The actual number of channels is set in `initialize()`
+            stft: StftHelper::new(1, config.fft_size, 0),
+
+            plan: RealFftPlanner::new().plan_fft_forward(config.fft_size),
+            compensated_window_function: window_function,
+            complex_fft_buffer: vec![Complex32::default(); num_bins],
+            channel_magnitude_sum: vec![0.0; num_bins],
+
+            magnitudes_db: vec![MINUS_INFINITY_DB; num_bins],
+            hold_windows_remaining: vec![0; num_bins],
+            sample_rate: 0.0,
+            averaging: AveragingCoefficients::None,
+
+            output: input,
+        };
+
+        (analyzer, SpectrumOutput { output })
+    }
+
+    /// Set the number of channels and the sample rate, and reset the analyzer. Call this from the
+    /// plugin's `initialize()` function. This allocates if the number of channels changed.
+    pub fn initialize(&mut self, num_channels: usize, sample_rate: f32) {
+        if self.stft.num_channels() != num_channels {
+            self.stft = StftHelper::new(num_channels, self.config.fft_size, 0);
+        }
+        self.sample_rate = sample_rate;
+
+        let windows_per_second = sample_rate / (self.config.fft_size / self.config.overlap) as f32;
+        self.averaging = match self.config.averaging {
+            SpectrumAveraging::None => AveragingCoefficients::None,
+            SpectrumAveraging::Exponential { decay_ms } => {
+                let decay_windows = (decay_ms / 1000.0 * windows_per_second) as f64;
+                AveragingCoefficients::Exponential {
+                    weight: 0.25f64.powf(decay_windows.recip()) as f32,
+                }
+            }
+            SpectrumAveraging::PeakHold {
+                hold_ms,
+                decay_db_per_second,
+            } => AveragingCoefficients::PeakHold {
+                hold_windows: (hold_ms / 1000.0 * windows_per_second).round() as u32,
+                decay_db_per_window: decay_db_per_second / windows_per_second,
+            },
+        };
+
+        self.reset();
+    }
+
+    /// Clear the analyzer's buffered input and its averaged results. This does not allocate, so it
+    /// can be called from the plugin's `reset()` function.
+    pub fn reset(&mut self) {
+        self.stft.set_block_size(self.config.fft_size);
+        self.channel_magnitude_sum.fill(0.0);
+        self.magnitudes_db.fill(MINUS_INFINITY_DB);
+        self.hold_windows_remaining.fill(0);
+    }
+
+    /// The analyzer's configuration.
+    pub fn config(&self) -> &SpectrumAnalyzerConfig {
+        &self.config
+    }
+
+    /// Analyze a buffer. A new spectrum is published every `fft_size / overlap` samples. This does
+    /// not modify the buffer. The buffer needs to have the number of channels passed to
+    /// [`initialize()`][Self::initialize()].
+    pub fn process_buffer(&mut self, buffer: &Buffer) {
+        self.process(buffer);
+    }
+
+    fn process<B: StftInput>(&mut self, buffer: &B) {
+        if self.sample_rate <= 0.0 {
+            nih_debug_assert_failure!("SpectrumAnalyzer::initialize() has not been called");
+            return;
+        }
+
+        let num_channels = self.stft.num_channels();
+        self.stft.process_analyze_only(
+            buffer,
+            self.config.overlap,
+            |channel_idx, real_fft_scratch_buffer| {
+                window::multiply_with_window(
+                    real_fft_scratch_buffer,
+                    &self.compensated_window_function,
+                );
+                self.plan
+                    .process_with_scratch(
+                        real_fft_scratch_buffer,
+                        &mut self.complex_fft_buffer,
+                        // We don't actually need a scratch buffer
+                        &mut [],
+                    )
+                    .unwrap();
+
+                for (bin, magnitude_sum) in self
+                    .complex_fft_buffer
+                    .iter()
+                    .zip(&mut self.channel_magnitude_sum)
+                {
+                    *magnitude_sum += bin.norm();
+                }
+
+                // The spectrum is published after the last channel has been analyzed
+                if channel_idx == num_channels - 1 {
+                    Self::update_magnitudes(
+                        &mut self.channel_magnitude_sum,
+                        &mut self.magnitudes_db,
+                        &mut self.hold_windows_remaining,
+                        self.averaging,
+                        num_channels,
+                    );
+
+                    let frame = self.output.input_buffer();
+                    frame.sample_rate = self.sample_rate;
+                    frame.magnitudes_db.copy_from_slice(&self.magnitudes_db);
+                    self.output.publish();
+                }
+            },
+        );
+    }
+
+    /// Fold the magnitudes summed over all channels into the averaged decibel values, and reset the
+    /// sums for the next window.
+    fn update_magnitudes(
+        channel_magnitude_sum: &mut [f32],
+        magnitudes_db: &mut [f32],
+        hold_windows_remaining: &mut [u32],
+        averaging: AveragingCoefficients,
+        num_channels: usize,
+    ) {
+        let last_bin_idx = channel_magnitude_sum.len() - 1;
+        for (bin_idx, ((magnitude_sum, magnitude_db), hold_windows_remaining)) in
+            channel_magnitude_sum
+                .iter_mut()
+                .zip(magnitudes_db.iter_mut())
+                .zip(hold_windows_remaining.iter_mut())
+                .enumerate()
+        {
+            let mut magnitude = *magnitude_sum / num_channels as f32;
+            *magnitude_sum = 0.0;
+            // The DC and Nyquist bins don't have a negative frequency counterpart
+            if bin_idx == 0 || bin_idx == last_bin_idx {
+                magnitude *= 0.5;
+            }
+
+            let new_db = super::gain_to_db(magnitude);
+            *magnitude_db = match averaging {
+                AveragingCoefficients::None => new_db,
+                AveragingCoefficients::Exponential { weight } => {
+                    // This is done on the linear magnitudes so silence doesn't get stuck at the
+                    // minus infinity floor
+                    let old_magnitude = super::db_to_gain(*magnitude_db);
+                    super::gain_to_db(old_magnitude * weight + magnitude * (1.0 - weight))
+                }
+                AveragingCoefficients::PeakHold {
+                    hold_windows,
+                    decay_db_per_window,
+                } => {
+                    if new_db >= *magnitude_db {
+                        *hold_windows_remaining = hold_windows;
+                        new_db
+                    } else if *hold_windows_remaining > 0 {
+                        *hold_windows_remaining -= 1;
+                        *magnitude_db
+                    } else {
+                        (*magnitude_db - decay_db_per_window).max(new_db)
+                    }
+                }
+            };
+        }
+    }
+}
+
+impl SpectrumOutput {
+    /// Get the most recently published spectrum. Call this from the editor.
+    pub fn read(&mut self) -> &SpectrumFrame {
+        self.output.read()
+    }
+}
+
+impl SpectrumFrame {
+    /// The number of frequency bins in this spectrum.
+    pub fn num_bins(&self) -> usize {
+        self.magnitudes_db.len()
+    }
+
+    /// The center frequency of a bin in Hertz.
+    pub fn bin_frequency(&self, bin_idx: usize) -> f32 {
+        bin_idx as f32 * self.sample_rate / self.fft_size as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const FFT_SIZE: usize = 1024;
+    const SINE_BIN: usize = 64;
+
+    fn config(window: SpectrumWindow, averaging: SpectrumAveraging) -> SpectrumAnalyzerConfig {
+        SpectrumAnalyzerConfig {
+            fft_size: FFT_SIZE,
+            overlap: 4,
+            window,
+            averaging,
+        }
+    }
+
+    /// Run `num_samples` samples of a sine wave with the specified amplitude that lies exactly on
+    /// [`SINE_BIN`] through the analyzer, in a couple of uneven blocks.
+    fn process_sine(analyzer: &mut SpectrumAnalyzer, amplitude: f32, num_samples: usize) {
+        let frequency = SINE_BIN as f32 * SAMPLE_RATE / FFT_SIZE as f32;
+        let mut samples: Vec<f32> = (0..num_samples)
+            .map(|i| (i as f32 / SAMPLE_RATE * frequency * 2.0 * PI).sin() * amplitude)
+            .collect();
+
+        for block in samples.chunks_mut(300) {
+            let mut buffer = Buffer::default();
+            unsafe { buffer.with_raw_vec(|slices| slices.push(block)) };
+            analyzer.process_buffer(&buffer);
+        }
+    }
+
+    #[test]
+    fn sine_magnitude() {
+        for window in [SpectrumWindow::Hann, SpectrumWindow::Blackman] {
+            let (mut analyzer, mut output) =
+                SpectrumAnalyzer::new(config(window, SpectrumAveraging::None));
+            analyzer.initialize(1, SAMPLE_RATE);
+            process_sine(&mut analyzer, 0.5, FFT_SIZE * 4);
+
+            let frame = output.read();
+            assert_eq!(frame.sample_rate, SAMPLE_RATE);
+            assert_eq!(frame.num_bins(), FFT_SIZE / 2 + 1);
+            assert_eq!(
+                frame.bin_frequency(SINE_BIN),
+                SINE_BIN as f32 * SAMPLE_RATE / FFT_SIZE as f32
+            );
+
+            // An amplitude of 0.5 is about -6 dB
+            let expected_db = crate::util::gain_to_db(0.5);
+            let sine_db = frame.magnitudes_db[SINE_BIN];
+            assert!(
+                (sine_db - expected_db).abs() < 0.1,
+                "{window:?}: {sine_db} dB instead of {expected_db} dB"
+            );
+
+            // Leakage should be limited to the window's main lobe
+            for (bin_idx, magnitude_db) in frame.magnitudes_db.iter().enumerate() {
+                if bin_idx.abs_diff(SINE_BIN) > 3 {
+                    assert!(
+                        *magnitude_db < expected_db - 60.0,
+                        "{window:?}: bin {bin_idx} is at {magnitude_db} dB"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_before_initialize() {
+        let (mut analyzer, mut output) =
+            SpectrumAnalyzer::new(config(SpectrumWindow::Hann, SpectrumAveraging::None));
+        process_sine(&mut analyzer, 0.5, FFT_SIZE * 2);
+
+        let frame = output.read();
+        assert_eq!(frame.sample_rate, 0.0);
+        assert!(frame
+            .magnitudes_db
+            .iter()
+            .all(|db| *db == MINUS_INFINITY_DB));
+    }
+
+    #[test]
+    fn peak_hold() {
+        let (mut analyzer, mut output) = SpectrumAnalyzer::new(config(
+            SpectrumWindow::Hann,
+            SpectrumAveraging::PeakHold {
+                hold_ms: 1000.0,
+                decay_db_per_second: 10.0,
+            },
+        ));
+        analyzer.initialize(1, SAMPLE_RATE);
+        process_sine(&mut analyzer, 0.5, FFT_SIZE * 4);
+        let peak_db = output.read().magnitudes_db[SINE_BIN];
+
+        // The peak should still be held after the sine becomes quieter
+        process_sine(&mut analyzer, 0.1, FFT_SIZE * 4);
+        assert_eq!(output.read().magnitudes_db[SINE_BIN], peak_db);
+    }
+
+    #[test]
+    fn exponential_averaging() {
+        let (mut analyzer, mut output) = SpectrumAnalyzer::new(config(
+            SpectrumWindow::Hann,
+            SpectrumAveraging::Exponential { decay_ms: 1000.0 },
+        ));
+        analyzer.initialize(1, SAMPLE_RATE);
+        process_sine(&mut analyzer, 0.5, FFT_SIZE * 4);
+
+        // The average should rise towards the sine's level without reaching it yet
+        let sine_db = output.read().magnitudes_db[SINE_BIN];
+        assert!(sine_db > MINUS_INFINITY_DB);
+        assert!(sine_db < crate::util::gain_to_db(0.5) - 1.0);
+    }
+}
@@ -0,0 +1,302 @@
+//! A lock-free ring buffer containing the most recent audio, for drawing waveforms and
+//! oscilloscopes in the editor.
+
+use atomic_float::AtomicF32;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::buffer::Buffer;
+
+/// Stores the most recent `capacity` samples for a fixed number of channels in a ring buffer that
+/// can be read from the editor using the connected [`WaveformReader`]. Writing to the buffer never
+/// blocks or allocates, and the oldest samples are overwritten once the buffer is full. This is a
+/// single-producer single-consumer structure: the audio thread owns the `WaveformBuffer`, and the
+/// editor reads from the `WaveformReader`.
+///
+/// The reader does not synchronize with the writer beyond the write position, so a read that
+/// happens while the audio thread is writing may contain a mix of old and new samples. That's fine
+/// for drawing, and reads never fail because of it.
+pub struct WaveformBuffer {
+    shared: Arc<SharedWaveform>,
+    /// The audio thread's copy of the write position. The shared copy is updated after every push.
+    write_pos: u64,
+}
+
+/// Reads the audio written to a [`WaveformBuffer`]. Move this to the editor.
+pub struct WaveformReader {
+    shared: Arc<SharedWaveform>,
+}
+
+struct SharedWaveform {
+    /// The ring buffer for each channel. Sample `n` is stored at index `n % capacity`.
+    channels: Vec<Vec<AtomicF32>>,
+    capacity: usize,
+    /// The total number of samples written to the buffer, and thus the position the next sample
+    /// will be written to.
+    write_pos: AtomicU64,
+    /// The write position at the time [`WaveformBuffer::trigger()`] was last called, or
+    /// `u64::MAX` if it was never called.
+    trigger_pos: AtomicU64,
+    /// The sample rate set through [`WaveformBuffer::set_sample_rate()`].
+    sample_rate: AtomicF32,
+}
+
+impl WaveformBuffer {
+    /// Create a new buffer and reader pair for `num_channels` channels holding `capacity` samples
+    /// each. This allocates, so it should be called from the plugin's `Default` implementation,
+    /// and the reader can then be moved to the editor. The buffer starts out filled with silence.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(num_channels: usize, capacity: usize) -> (Self, WaveformReader) {
+        assert!(capacity > 0);
+
+        let shared = Arc::new(SharedWaveform {
+            channels: (0..num_channels)
+                .map(|_| (0..capacity).map(|_| AtomicF32::new(0.0)).collect())
+                .collect(),
+            capacity,
+            write_pos: AtomicU64::new(0),
+            trigger_pos: AtomicU64::new(u64::MAX),
+            sample_rate: AtomicF32::new(0.0),
+        });
+
+        (
+            Self {
+                shared: shared.clone(),
+                write_pos: 0,
+            },
+            WaveformReader { shared },
+        )
+    }
+
+    /// Set the sample rate the reader uses to convert between time and samples. Call this from
+    /// the plugin's `initialize()` function.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.shared
+            .sample_rate
+            .store(sample_rate, Ordering::Relaxed);
+    }
+
+    /// Append a buffer's samples. Channels beyond this buffer's number of channels are ignored,
+    /// and channels the buffer doesn't have are filled with silence.
+    pub fn push_buffer(&mut self, buffer: &Buffer) {
+        let samples = buffer.as_slice_immutable();
+        for (channel_idx, ring) in self.shared.channels.iter().enumerate() {
+            match samples.get(channel_idx) {
+                Some(channel_samples) => {
+                    for (sample_idx, sample) in channel_samples.iter().enumerate() {
+                        ring[self.ring_index(sample_idx as u64)].store(*sample, Ordering::Relaxed);
+                    }
+                }
+                None => {
+                    for sample_idx in 0..buffer.len() {
+                        ring[self.ring_index(sample_idx as u64)].store(0.0, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        self.advance(buffer.len());
+    }
+
+    /// Append a single sample for every channel. This can be used to record any signal, not just
+    /// the plugin's output. Missing samples are treated as silence, and any extra samples are
+    /// ignored.
+    pub fn push_frame(&mut self, frame: &[f32]) {
+        let idx = self.ring_index(0);
+        for (channel_idx, ring) in self.shared.channels.iter().enumerate() {
+            ring[idx].store(
+                frame.get(channel_idx).copied().unwrap_or(0.0),
+                Ordering::Relaxed,
+            );
+        }
+
+        self.advance(1);
+    }
+
+    /// Mark the current write position as a trigger point. The next sample written to the buffer
+    /// will be the first sample after the trigger. An oscilloscope can use this to align its view
+    /// to an external event, like a note being played or the start of a beat.
+    pub fn trigger(&mut self) {
+        self.shared
+            .trigger_pos
+            .store(self.write_pos, Ordering::Release);
+    }
+
+    /// Clear the buffer's contents and the trigger point. The write position keeps increasing, so
+    /// a reader can tell that new data was written.
+    pub fn reset(&mut self) {
+        for ring in &self.shared.channels {
+            for sample in ring {
+                sample.store(0.0, Ordering::Relaxed);
+            }
+        }
+        self.shared.trigger_pos.store(u64::MAX, Ordering::Release);
+    }
+
+    /// The index in the ring buffers for the sample `offset` samples after the write position.
+    fn ring_index(&self, offset: u64) -> usize {
+        ((self.write_pos + offset) % self.shared.capacity as u64) as usize
+    }
+
+    fn advance(&mut self, num_samples: usize) {
+        self.write_pos += num_samples as u64;
+        self.shared
+            .write_pos
+            .store(self.write_pos, Ordering::Release);
+    }
+}
+
+impl WaveformReader {
+    /// The number of channels in the buffer.
+    pub fn num_channels(&self) -> usize {
+        self.shared.channels.len()
+    }
+
+    /// The number of samples stored for every channel.
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// The sample rate set by the audio thread, or zero if it has not been set yet.
+    pub fn sample_rate(&self) -> f32 {
+        self.shared.sample_rate.load(Ordering::Relaxed)
+    }
+
+    /// The total number of samples written to the buffer so far. Only the last
+    /// [`capacity()`][Self::capacity()] samples before this position can be read.
+    pub fn write_position(&self) -> u64 {
+        self.shared.write_pos.load(Ordering::Acquire)
+    }
+
+    /// The number of times the ring buffer has wrapped around. This changes every `capacity`
+    /// samples, so the editor can compare this to the previous value to tell whether any data it
+    /// held on to has been overwritten.
+    pub fn generation(&self) -> u64 {
+        self.write_position() / self.shared.capacity as u64
+    }
+
+    /// The write position at the time [`WaveformBuffer::trigger()`] was last called, if it has been
+    /// called since the buffer was created or last reset.
+    pub fn trigger_position(&self) -> Option<u64> {
+        match self.shared.trigger_pos.load(Ordering::Acquire) {
+            u64::MAX => None,
+            pos => Some(pos),
+        }
+    }
+
+    /// Copy a channel's samples starting at the absolute position `start_pos` to `dest`. Positions
+    /// that have not been written yet or that have already been overwritten are filled with
+    /// silence. Returns `false` if any of the requested samples were not available.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel_idx` is out of bounds.
+    pub fn read(&self, channel_idx: usize, start_pos: u64, dest: &mut [f32]) -> bool {
+        let ring = &self.shared.channels[channel_idx];
+        let capacity = self.shared.capacity as u64;
+
+        let write_pos = self.write_position();
+        let oldest_pos = write_pos.saturating_sub(capacity);
+        let mut all_available = true;
+        for (pos, sample) in (start_pos..).zip(dest.iter_mut()) {
+            if pos >= oldest_pos && pos < write_pos {
+                *sample = ring[(pos % capacity) as usize].load(Ordering::Relaxed);
+            } else {
+                *sample = 0.0;
+                all_available = false;
+            }
+        }
+
+        all_available
+    }
+
+    /// Copy the most recent `dest.len()` samples of a channel to `dest`, oldest sample first.
+    /// Returns the absolute position of the first sample. See [`read()`][Self::read()].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `channel_idx` is out of bounds.
+    pub fn read_latest(&self, channel_idx: usize, dest: &mut [f32]) -> u64 {
+        let start_pos = self.write_position().saturating_sub(dest.len() as u64);
+        self.read(channel_idx, start_pos, dest);
+
+        start_pos
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_buffer<'a>(channels: &'a mut [Vec<f32>]) -> Buffer<'a> {
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                *output_slices = channels
+                    .iter_mut()
+                    .map(|channel| &mut *(channel.as_mut_slice() as *mut [f32]))
+                    .collect();
+            })
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn overwrite_oldest() {
+        let (mut waveform, reader) = WaveformBuffer::new(2, 8);
+
+        // Write 20 samples in blocks of 5, the second channel is negated
+        for block_idx in 0..4 {
+            let left: Vec<f32> = (0..5).map(|i| (block_idx * 5 + i) as f32).collect();
+            let right = left.iter().map(|sample| -sample).collect();
+            let mut channels = [left, right];
+            waveform.push_buffer(&make_buffer(&mut channels));
+        }
+        assert_eq!(reader.write_position(), 20);
+        assert_eq!(reader.generation(), 2);
+
+        let mut dest = [0.0; 4];
+        assert_eq!(reader.read_latest(0, &mut dest), 16);
+        assert_eq!(dest, [16.0, 17.0, 18.0, 19.0]);
+        assert_eq!(reader.read_latest(1, &mut dest), 16);
+        assert_eq!(dest, [-16.0, -17.0, -18.0, -19.0]);
+
+        // The samples before position 12 have been overwritten
+        let mut dest = [1.0; 10];
+        assert!(!reader.read(0, 10, &mut dest));
+        assert_eq!(
+            dest,
+            [0.0, 0.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0, 18.0, 19.0]
+        );
+        let mut dest = [0.0; 8];
+        assert!(reader.read(0, 12, &mut dest));
+    }
+
+    #[test]
+    fn frames_and_triggers() {
+        let (mut waveform, reader) = WaveformBuffer::new(2, 16);
+        assert_eq!(reader.trigger_position(), None);
+
+        waveform.push_frame(&[1.0, 2.0]);
+        waveform.trigger();
+        // Missing channels are silent
+        waveform.push_frame(&[3.0]);
+        assert_eq!(reader.trigger_position(), Some(1));
+
+        let mut dest = [0.0; 2];
+        reader.read_latest(0, &mut dest);
+        assert_eq!(dest, [1.0, 3.0]);
+        reader.read_latest(1, &mut dest);
+        assert_eq!(dest, [2.0, 0.0]);
+
+        waveform.reset();
+        assert_eq!(reader.trigger_position(), None);
+        assert_eq!(reader.write_position(), 2);
+        reader.read_latest(0, &mut dest);
+        assert_eq!(dest, [0.0, 0.0]);
+    }
+}
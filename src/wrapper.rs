@@ -1,14 +1,22 @@
 //! Wrappers for different plugin types. Each wrapper has an entry point macro that you can pass the
 //! name of a type that implements `Plugin` to. The macro will handle the rest.
 
+pub(crate) mod activation_fade;
+pub(crate) mod alloc_stats;
+pub(crate) mod bypass;
 pub mod clap;
+pub(crate) mod editor_lifecycle;
+pub(crate) mod musical_scheduler;
+pub(crate) mod oversampling;
 pub mod state;
+pub(crate) mod thread_role;
 pub(crate) mod util;
+pub mod vst3_subcategories;
 
 #[cfg(feature = "standalone")]
 pub mod standalone;
 #[cfg(feature = "vst3")]
 pub mod vst3;
 
-// This is used by the wrappers.
-pub use util::setup_logger;
+// These are used by the wrappers.
+pub use util::{setup_logger, teardown_library};
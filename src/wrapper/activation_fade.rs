@@ -0,0 +1,126 @@
+//! Fading in the plugin's output after it gets activated.
+
+use crate::buffer::Buffer;
+use crate::plugin::Plugin;
+
+/// Fades in the plugin's main output after it starts processing audio when
+/// [`Plugin::ACTIVATION_FADE_MS`] is non-zero. The wrappers call [`start()`][Self::start()] when
+/// the plugin starts processing, and either [`apply()`][Self::apply()] or
+/// [`skip()`][Self::skip()] for every (sub-)block after that.
+#[derive(Debug, Default)]
+pub struct ActivationFade {
+    /// The length of the fade in samples. Zero if there is no fade.
+    length: u32,
+    /// The number of samples into the fade the next block starts at.
+    position: u32,
+}
+
+impl ActivationFade {
+    /// Start a new fade of `P::ACTIVATION_FADE_MS` milliseconds.
+    pub fn start<P: Plugin>(&mut self, sample_rate: f32) {
+        self.length = (P::ACTIVATION_FADE_MS / 1000.0 * sample_rate)
+            .round()
+            .max(0.0) as u32;
+        self.position = 0;
+    }
+
+    /// Multiply the plugin's output by the fade's gain. The gain ramps up linearly from zero at the
+    /// start of the fade to one at the end of the fade.
+    pub fn apply(&mut self, buffer: &mut Buffer) {
+        let remaining = (self.length - self.position) as usize;
+        for (sample_idx, channel_samples) in buffer.iter_samples().take(remaining).enumerate() {
+            let gain = (self.position as usize + sample_idx) as f32 / self.length as f32;
+            for sample in channel_samples {
+                *sample *= gain;
+            }
+        }
+
+        self.skip(buffer.len());
+    }
+
+    /// Advance the fade without applying it, for blocks where the plugin did not process audio.
+    pub fn skip(&mut self, num_samples: usize) {
+        self.position = (self.position as usize + num_samples).min(self.length as usize) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::context::process::ProcessContext;
+    use crate::params::Params;
+    use crate::plugin::{AuxiliaryBuffers, ProcessStatus};
+
+    #[derive(Default)]
+    struct FadePlugin;
+
+    impl Plugin for FadePlugin {
+        const NAME: &'static str = "Fade Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const ACTIVATION_FADE_MS: f32 = 1.0;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            unimplemented!()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            unimplemented!()
+        }
+    }
+
+    /// Process `num_samples` samples of ones with a fade, and return the output.
+    fn fade_block(fade: &mut ActivationFade, num_samples: usize) -> Vec<f32> {
+        let mut samples = vec![1.0; num_samples];
+        {
+            let mut buffer = Buffer::default();
+            unsafe { buffer.with_raw_vec(|slices| slices.push(samples.as_mut_slice())) };
+            fade.apply(&mut buffer);
+        }
+
+        samples
+    }
+
+    #[test]
+    fn activation_fade() {
+        // 1 ms at 8 kHz is an 8 sample fade, split over multiple blocks here
+        let mut fade = ActivationFade::default();
+        fade.start::<FadePlugin>(8000.0);
+        let mut output = fade_block(&mut fade, 3);
+        output.extend(fade_block(&mut fade, 3));
+        output.extend(fade_block(&mut fade, 4));
+        assert_eq!(
+            output,
+            [0.0, 0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875, 1.0, 1.0]
+        );
+
+        // The fade starts over when the plugin starts processing again
+        fade.start::<FadePlugin>(8000.0);
+        assert_eq!(fade_block(&mut fade, 2), [0.0, 0.125]);
+
+        // Skipped samples still count towards the fade
+        fade.skip(4);
+        assert_eq!(fade_block(&mut fade, 3), [0.75, 0.875, 1.0]);
+    }
+
+    #[test]
+    fn no_activation_fade() {
+        let mut fade = ActivationFade::default();
+        assert_eq!(fade_block(&mut fade, 4), [1.0; 4]);
+
+        fade.start::<FadePlugin>(0.0);
+        assert_eq!(fade_block(&mut fade, 4), [1.0; 4]);
+    }
+}
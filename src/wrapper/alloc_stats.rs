@@ -0,0 +1,194 @@
+//! Per-instance allocation statistics for debug builds with the `alloc_stats` feature enabled. The
+//! wrappers mark the thread they're calling the plugin from with the phase of the plugin's
+//! lifecycle the call belongs to, and a counting global allocator attributes every allocation made
+//! during that call to the instance and the phase. The totals are logged when the plugin gets
+//! deactivated and when the instance is dropped.
+//!
+//! When the feature is disabled or in release builds [`AllocStats`] and [`PhaseGuard`] are empty
+//! types that don't do anything, and no allocator is installed.
+
+/// The phases of a plugin's lifecycle allocations are attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocPhase {
+    /// [`Plugin::initialize()`][crate::prelude::Plugin::initialize()] when the plugin gets
+    /// activated.
+    Initialize,
+    /// Loading state from the host, including reinitializing the plugin afterwards.
+    StateLoad,
+    /// Opening the plugin's editor.
+    EditorOpen,
+    /// Running the plugin's background tasks.
+    BackgroundTask,
+}
+
+#[cfg(all(debug_assertions, feature = "alloc_stats"))]
+pub use enabled::{AllocCounts, AllocStats, PhaseGuard};
+
+#[cfg(not(all(debug_assertions, feature = "alloc_stats")))]
+pub use disabled::{AllocStats, PhaseGuard};
+
+#[cfg(all(debug_assertions, feature = "alloc_stats"))]
+mod enabled {
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
+    use std::marker::PhantomData;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::AllocPhase;
+
+    const ALL_PHASES: [AllocPhase; 4] = [
+        AllocPhase::Initialize,
+        AllocPhase::StateLoad,
+        AllocPhase::EditorOpen,
+        AllocPhase::BackgroundTask,
+    ];
+
+    // This needs to wrap the allocation checking allocator if that's also enabled, since there can
+    // only be a single global allocator
+    #[cfg(feature = "assert_process_allocs")]
+    type InnerAllocator = assert_no_alloc::AllocDisabler;
+    #[cfg(not(feature = "assert_process_allocs"))]
+    type InnerAllocator = std::alloc::System;
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator(InnerAllocator {});
+
+    thread_local! {
+        /// The counters allocations made on this thread should be added to, if any. Set by
+        /// [`AllocStats::enter()`].
+        static CURRENT_COUNTERS: Cell<*const PhaseCounters> = const { Cell::new(std::ptr::null()) };
+    }
+
+    /// Adds the size of every allocation to the counters in [`CURRENT_COUNTERS`].
+    struct CountingAllocator(InnerAllocator);
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation(layout.size());
+            self.0.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            count_allocation(layout.size());
+            self.0.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation(new_size);
+            self.0.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.dealloc(ptr, layout)
+        }
+    }
+
+    /// Count an allocation of `size` bytes if the current thread is in one of the phases. This
+    /// thread local does not allocate and it does not have a destructor, so it is safe to use from
+    /// within the allocator. It may no longer be accessible while the thread is shutting down.
+    fn count_allocation(size: usize) {
+        // The audio thread allocation guard can't install its own allocator alongside this one
+        #[cfg(feature = "audio_thread_alloc_guard")]
+        crate::wrapper::thread_role::check_allocation();
+
+        let _ = CURRENT_COUNTERS.try_with(|counters| {
+            // SAFETY: The pointer is reset by `PhaseGuard`, which borrows the counters
+            if let Some(counters) = unsafe { counters.get().as_ref() } {
+                counters.count.fetch_add(1, Ordering::Relaxed);
+                counters.bytes.fetch_add(size, Ordering::Relaxed);
+            }
+        });
+    }
+
+    /// The number of allocations and the total number of bytes allocated during a single phase.
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct AllocCounts {
+        pub count: usize,
+        pub bytes: usize,
+    }
+
+    #[derive(Default)]
+    struct PhaseCounters {
+        count: AtomicUsize,
+        bytes: AtomicUsize,
+    }
+
+    /// The allocation counters for a single plugin instance.
+    #[derive(Default)]
+    pub struct AllocStats {
+        phases: [PhaseCounters; ALL_PHASES.len()],
+    }
+
+    /// Attributes allocations made on the current thread to a phase until it is dropped. Returned
+    /// by [`AllocStats::enter()`].
+    pub struct PhaseGuard<'a> {
+        previous: *const PhaseCounters,
+        /// The guard needs to be dropped on the thread it was created on.
+        _marker: PhantomData<(&'a AllocStats, *const ())>,
+    }
+
+    impl AllocStats {
+        /// Attribute all allocations made on this thread to `phase` until the returned guard is
+        /// dropped. Phases can be nested, in which case the innermost phase is used.
+        pub fn enter(&self, phase: AllocPhase) -> PhaseGuard<'_> {
+            let counters: *const PhaseCounters = &self.phases[phase as usize];
+            PhaseGuard {
+                previous: CURRENT_COUNTERS.with(|current| current.replace(counters)),
+                _marker: PhantomData,
+            }
+        }
+
+        /// The allocations made so far during `phase`.
+        pub fn counts(&self, phase: AllocPhase) -> AllocCounts {
+            let counters = &self.phases[phase as usize];
+            AllocCounts {
+                count: counters.count.load(Ordering::Relaxed),
+                bytes: counters.bytes.load(Ordering::Relaxed),
+            }
+        }
+
+        /// Log the allocations made during every phase so far. `name` is the plugin's name.
+        pub fn log_summary(&self, name: &str) {
+            let summary = ALL_PHASES
+                .iter()
+                .map(|&phase| {
+                    let counts = self.counts(phase);
+                    format!(
+                        "{phase:?}: {} allocations, {} bytes",
+                        counts.count, counts.bytes
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            nih_log!("Allocations for '{name}': {summary}");
+        }
+    }
+
+    impl Drop for PhaseGuard<'_> {
+        fn drop(&mut self) {
+            CURRENT_COUNTERS.with(|current| current.set(self.previous));
+        }
+    }
+}
+
+#[cfg(not(all(debug_assertions, feature = "alloc_stats")))]
+mod disabled {
+    use super::AllocPhase;
+
+    /// Does nothing unless the `alloc_stats` feature is enabled in a debug build.
+    #[derive(Default)]
+    pub struct AllocStats;
+
+    /// Does nothing unless the `alloc_stats` feature is enabled in a debug build.
+    pub struct PhaseGuard;
+
+    impl AllocStats {
+        #[inline(always)]
+        pub fn enter(&self, _phase: AllocPhase) -> PhaseGuard {
+            PhaseGuard
+        }
+
+        #[inline(always)]
+        pub fn log_summary(&self, _name: &str) {}
+    }
+}
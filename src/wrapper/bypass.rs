@@ -0,0 +1,155 @@
+//! The bypass parameter the wrappers add for plugins that don't have their own.
+
+use std::mem;
+
+use crate::buffer::Buffer;
+use crate::plugin::BypassMode;
+
+/// The length of the crossfade used when the wrapper's own bypass parameter is toggled while
+/// processing audio in realtime. See [`BypassProcessor`].
+pub const BYPASS_CROSSFADE_MS: f32 = 10.0;
+
+/// Implements the bypass parameter the wrappers add for plugins that don't have their own. The
+/// bypassed signal is the plugin's main input delayed by the plugin's latency, so it stays aligned
+/// with the processed signal. In [`BypassMode::Soft`] toggling the bypass crossfades between the
+/// two signals over [`BYPASS_CROSSFADE_MS`] milliseconds, and in [`BypassMode::Hard`] it switches
+/// instantly. The wrappers call [`setup()`][Self::setup()] when the plugin is activated,
+/// [`reset()`][Self::reset()] when it starts processing audio, and
+/// [`begin_block()`][Self::begin_block()] and [`end_block()`][Self::end_block()] around every
+/// (sub-)block. An instance that was never set up doesn't do anything while not bypassed.
+#[derive(Debug, Default)]
+pub struct BypassProcessor {
+    /// Ring buffers containing the last `latency` input samples for every channel. The latency is
+    /// only updated when the plugin is reactivated, which hosts do after the plugin reports a new
+    /// latency.
+    delay_lines: Vec<Vec<f32>>,
+    /// The index of the oldest sample in the delay lines.
+    delay_pos: usize,
+    /// The latency compensated input for the current block for every channel, allocated for the
+    /// maximum block size.
+    dry: Vec<Vec<f32>>,
+    /// The length of a soft bypass crossfade in samples.
+    crossfade_length: u32,
+    /// How far the bypass has been faded in, from zero for only the plugin's output to
+    /// `crossfade_length` for only the bypassed signal.
+    position: u32,
+    /// Whether the bypass was enabled for the current block. `position` moves towards this.
+    bypassed: bool,
+}
+
+impl BypassProcessor {
+    /// Allocate the delay lines for `latency` samples and the dry signal buffers for
+    /// `max_block_size` samples.
+    pub fn setup(
+        &mut self,
+        num_channels: usize,
+        max_block_size: usize,
+        latency: usize,
+        sample_rate: f32,
+    ) {
+        self.delay_lines.resize_with(num_channels, Vec::new);
+        for delay_line in &mut self.delay_lines {
+            delay_line.clear();
+            delay_line.resize(latency, 0.0);
+        }
+        self.dry.resize_with(num_channels, Vec::new);
+        for channel in &mut self.dry {
+            channel.clear();
+            channel.resize(max_block_size, 0.0);
+        }
+
+        self.delay_pos = 0;
+        self.crossfade_length = (BYPASS_CROSSFADE_MS / 1000.0 * sample_rate).round() as u32;
+    }
+
+    /// Clear the delay lines and jump to the current bypass state without fading.
+    pub fn reset(&mut self, bypassed: bool) {
+        for delay_line in &mut self.delay_lines {
+            delay_line.fill(0.0);
+        }
+
+        self.delay_pos = 0;
+        self.bypassed = bypassed;
+        self.position = if bypassed { self.crossfade_length } else { 0 };
+    }
+
+    /// Store the latency compensated input for this block before the plugin overwrites `buffer`.
+    /// Returns `false` if the bypass has been fully faded in, in which case the plugin should not
+    /// process this block.
+    pub fn begin_block(&mut self, buffer: &Buffer, bypassed: bool, mode: BypassMode) -> bool {
+        self.bypassed = bypassed;
+        if mode == BypassMode::Hard {
+            self.position = if bypassed { self.crossfade_length } else { 0 };
+        }
+        if !self.is_active() {
+            return !self.is_fully_bypassed();
+        }
+
+        let block_len = buffer.len();
+        let mut new_delay_pos = self.delay_pos;
+        for ((input, dry), delay_line) in buffer
+            .as_slice_immutable()
+            .iter()
+            .zip(self.dry.iter_mut())
+            .zip(self.delay_lines.iter_mut())
+        {
+            nih_debug_assert!(block_len <= dry.len());
+            let len = block_len.min(dry.len());
+            let dry = &mut dry[..len];
+            if delay_line.is_empty() {
+                dry.copy_from_slice(&input[..dry.len()]);
+                continue;
+            }
+
+            let mut delay_pos = self.delay_pos;
+            for (dry_sample, input_sample) in dry.iter_mut().zip(input.iter()) {
+                *dry_sample = mem::replace(&mut delay_line[delay_pos], *input_sample);
+                delay_pos = (delay_pos + 1) % delay_line.len();
+            }
+            new_delay_pos = delay_pos;
+        }
+        self.delay_pos = new_delay_pos;
+
+        !self.is_fully_bypassed()
+    }
+
+    /// Mix the latency compensated input stored in [`begin_block()`][Self::begin_block()] into the
+    /// plugin's output, or replace the output with it if the plugin did not process this block.
+    pub fn end_block(&mut self, buffer: &mut Buffer) {
+        if !self.bypassed && self.position == 0 {
+            return;
+        }
+
+        if self.is_fully_bypassed() {
+            for (channel, dry) in buffer.as_slice().iter_mut().zip(self.dry.iter()) {
+                let block_len = channel.len().min(dry.len());
+                channel[..block_len].copy_from_slice(&dry[..block_len]);
+            }
+
+            return;
+        }
+
+        for (sample_idx, channel_samples) in buffer.iter_samples().enumerate() {
+            let dry_gain = self.position as f32 / self.crossfade_length as f32;
+            for (sample, dry) in channel_samples.into_iter().zip(self.dry.iter()) {
+                *sample = (*sample * (1.0 - dry_gain)) + (dry[sample_idx] * dry_gain);
+            }
+
+            self.position = if self.bypassed {
+                (self.position + 1).min(self.crossfade_length)
+            } else {
+                self.position.saturating_sub(1)
+            };
+        }
+    }
+
+    /// Whether the dry signal needs to be stored and mixed in. This is always the case when the
+    /// plugin has latency since the delay lines need to stay up to date.
+    fn is_active(&self) -> bool {
+        self.bypassed || self.position > 0 || self.delay_lines.iter().any(|line| !line.is_empty())
+    }
+
+    fn is_fully_bypassed(&self) -> bool {
+        self.bypassed && self.position == self.crossfade_length
+    }
+}
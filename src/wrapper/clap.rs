@@ -35,7 +35,9 @@ macro_rules! nih_export_clap {
                 true
             }
 
-            pub extern "C" fn deinit() {}
+            pub extern "C" fn deinit() {
+                ::nih_plug::wrapper::teardown_library();
+            }
 
             pub extern "C" fn get_factory(
                 factory_id: *const ::std::os::raw::c_char,
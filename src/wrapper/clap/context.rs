@@ -2,16 +2,21 @@ use atomic_refcell::AtomicRefMut;
 use std::cell::Cell;
 use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Instant;
 
-use super::wrapper::{OutputParamEvent, Task, Wrapper};
+use super::wrapper::{ClapParamUpdate, OutputParamEvent, Task, Wrapper};
 use crate::context::gui::GuiContext;
 use crate::context::init::InitContext;
 use crate::context::process::{ProcessContext, Transport};
+use crate::context::repaint::RepaintPolicy;
 use crate::context::PluginApi;
 use crate::event_loop::EventLoop;
 use crate::midi::NoteEvent;
+use crate::params::changes::{ParamChange, ParamChangeSource, ParamChangeSubscription};
 use crate::params::internals::ParamPtr;
-use crate::plugin::ClapPlugin;
+use crate::params::ParamFlags;
+use crate::plugin::{BypassMode, ClapPlugin, ProcessMode};
+use crate::wrapper::util;
 
 /// An [`InitContext`] implementation for the wrapper.
 ///
@@ -71,6 +76,17 @@ impl<P: ClapPlugin> InitContext<P> for WrapperInitContext<'_, P> {
         self.pending_requests.latency_changed.set(Some(samples));
     }
 
+    fn max_expected_sample_rate(&self) -> f32 {
+        let sample_rate = self
+            .wrapper
+            .current_buffer_config
+            .load()
+            .map_or(0.0, |c| c.sample_rate);
+
+        // The oversampling factor can only change when the plugin gets reinitialized
+        util::max_expected_sample_rate::<P>(sample_rate) * self.wrapper.oversampling.factor() as f32
+    }
+
     fn set_current_voice_capacity(&self, capacity: u32) {
         self.wrapper.set_current_voice_capacity(capacity)
     }
@@ -82,11 +98,18 @@ impl<P: ClapPlugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
     }
 
     fn execute_background(&self, task: P::BackgroundTask) {
-        let task_posted = self.wrapper.schedule_background(Task::PluginTask(task));
+        nih_debug_assert_audio_thread!();
+
+        let priority = P::background_task_priority(&task);
+        let task_posted = self
+            .wrapper
+            .schedule_background(Task::PluginTask(task), priority);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
 
     fn execute_gui(&self, task: P::BackgroundTask) {
+        nih_debug_assert_audio_thread!();
+
         let task_posted = self.wrapper.schedule_gui(Task::PluginTask(task));
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
@@ -96,14 +119,40 @@ impl<P: ClapPlugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
         &self.transport
     }
 
+    fn deterministic_seed(&self) -> Option<u64> {
+        match self.wrapper.current_process_mode.load() {
+            ProcessMode::Offline => Some(self.wrapper.instance_id.load().seed()),
+            ProcessMode::Realtime | ProcessMode::Buffered => None,
+        }
+    }
+
+    fn bypassed(&self) -> bool {
+        self.wrapper.bypass_param_ptr.map_or(
+            false,
+            |ptr| unsafe { ptr.modulated_normalized_value() } >= 0.5,
+        )
+    }
+
+    fn bypass_mode(&self) -> BypassMode {
+        self.wrapper.current_process_mode.load().bypass_mode()
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
-        self.input_events_guard.pop_front()
+        let mut event = self.input_events_guard.pop_front()?;
+        self.wrapper.oversampling.upsample_event(&mut event);
+
+        Some(event)
     }
 
-    fn send_event(&mut self, event: NoteEvent) {
+    fn send_event(&mut self, mut event: NoteEvent) {
+        self.wrapper.oversampling.downsample_event(&mut event);
         self.output_events_guard.push_back(event);
     }
 
+    fn request_editor_repaint(&self) {
+        self.wrapper.editor_repaint.request();
+    }
+
     fn set_latency_samples(&self, samples: u32) {
         self.wrapper.set_latency_samples(samples)
     }
@@ -111,6 +160,44 @@ impl<P: ClapPlugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
     fn set_current_voice_capacity(&self, capacity: u32) {
         self.wrapper.set_current_voice_capacity(capacity)
     }
+
+    unsafe fn raw_set_output_parameter(&mut self, param: ParamPtr, normalized: f32) {
+        if !param.flags().contains(ParamFlags::READ_ONLY) {
+            nih_debug_assert_failure!(
+                "Only read-only parameters can be set from the process function: {:?}",
+                param
+            );
+            return;
+        }
+
+        match self.wrapper.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                // The value is updated right away so the plugin and its editor can read it back,
+                // and the event is written to the host's output events at the end of the process
+                // call
+                let clap_plain_value = normalized as f64 * param.step_count().unwrap_or(1) as f64;
+                self.wrapper.update_plain_value_by_hash(
+                    *hash,
+                    ClapParamUpdate::PlainValueSet(clap_plain_value),
+                    Some(self.transport.sample_rate),
+                    ParamChangeSource::Internal,
+                );
+
+                let success = self
+                    .wrapper
+                    .queue_parameter_event(OutputParamEvent::SetValue {
+                        param_hash: *hash,
+                        clap_plain_value,
+                    });
+                nih_debug_assert!(
+                    success,
+                    "Parameter output event queue was full, the new value will not be sent to \
+                     the host"
+                );
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
 }
 
 impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
@@ -122,6 +209,18 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
         self.wrapper.request_resize()
     }
 
+    fn request_repaint(&self) {
+        self.wrapper.editor_repaint.request();
+    }
+
+    fn set_repaint_policy(&self, policy: RepaintPolicy) {
+        self.wrapper.editor_repaint.set_policy(policy);
+    }
+
+    fn poll_repaint(&self) -> bool {
+        self.wrapper.editor_repaint.poll(Instant::now())
+    }
+
     // All of these functions are supposed to be called from the main thread, so we'll put some
     // trust in the caller and assume that this is indeed the case
     unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
@@ -189,6 +288,21 @@ impl<P: ClapPlugin> GuiContext for WrapperGuiContext<P> {
     }
 
     fn set_state(&self, state: crate::wrapper::state::PluginState) {
-        self.wrapper.set_state_object(state)
+        self.wrapper.set_state_object(state);
+
+        // The state may contain persistent fields, which the host doesn't know about
+        self.wrapper.mark_state_dirty();
+    }
+
+    fn last_param_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        self.wrapper.param_changes.last_change(param)
+    }
+
+    fn subscribe_param_changes(&self) -> ParamChangeSubscription {
+        ParamChangeSubscription::new(self.wrapper.param_changes.clone())
+    }
+
+    fn mark_state_dirty(&self) {
+        self.wrapper.mark_state_dirty();
     }
 }
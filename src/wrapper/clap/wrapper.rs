@@ -44,7 +44,7 @@ use clap_sys::ext::render::{
     clap_plugin_render, clap_plugin_render_mode, CLAP_EXT_RENDER, CLAP_RENDER_OFFLINE,
     CLAP_RENDER_REALTIME,
 };
-use clap_sys::ext::state::{clap_plugin_state, CLAP_EXT_STATE};
+use clap_sys::ext::state::{clap_host_state, clap_plugin_state, CLAP_EXT_STATE};
 use clap_sys::ext::tail::{clap_plugin_tail, CLAP_EXT_TAIL};
 use clap_sys::ext::thread_check::{clap_host_thread_check, CLAP_EXT_THREAD_CHECK};
 use clap_sys::fixedpoint::{CLAP_BEATTIME_FACTOR, CLAP_SECTIME_FACTOR};
@@ -79,19 +79,30 @@ use super::util::ClapPtr;
 use crate::buffer::Buffer;
 use crate::context::gui::AsyncExecutor;
 use crate::context::process::Transport;
+use crate::context::repaint::RepaintRequests;
 use crate::editor::{Editor, ParentWindowHandle};
 use crate::event_loop::{BackgroundThread, EventLoop, MainThreadExecutor, TASK_QUEUE_CAPACITY};
 use crate::midi::{MidiConfig, NoteEvent};
+use crate::params::changes::{ParamChangeSource, ParamChangeTracker};
 use crate::params::internals::ParamPtr;
-use crate::params::{ParamFlags, Params};
+use crate::params::{BoolParam, ParamFlags, Params};
 use crate::plugin::{
-    AuxiliaryBuffers, BufferConfig, BusConfig, ClapPlugin, Plugin, ProcessMode, ProcessStatus,
-    TaskExecutor,
+    AuxiliaryBuffers, BackgroundTaskPriority, BufferConfig, BusConfig, ClapPlugin, Plugin,
+    ProcessMode, ProcessStatus, TaskExecutor,
 };
 use crate::util::permit_alloc;
+use crate::wrapper::activation_fade::ActivationFade;
+use crate::wrapper::alloc_stats::{AllocPhase, AllocStats};
+use crate::wrapper::bypass::BypassProcessor;
 use crate::wrapper::clap::util::{read_stream, write_stream};
-use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::{hash_param_id, process_wrapper, strlcpy};
+use crate::wrapper::oversampling::WrapperOversampling;
+use crate::wrapper::state::{self, InstanceId, PluginState};
+use crate::wrapper::util::{
+    aux_input_name, aux_output_name, can_change_sample_rate_in_place, find_bypass_param,
+    hash_param_id, main_input_name, main_output_name, param_id_for_hash, param_map_with_bypass,
+    process_wrapper, register_legacy_param_ids, strlcpy, ParamValueValidator,
+    StateRestoredNotifier, TransportTracker,
+};
 
 /// How many output parameter changes we can store in our output parameter change queue. Storing
 /// more than this many parameters at a time will cause changes to get lost.
@@ -128,9 +139,21 @@ pub struct Wrapper<P: ClapPlugin> {
     current_bus_config: AtomicCell<BusConfig>,
     /// The current buffer configuration, containing the sample rate and the maximum block size.
     /// Will be set in `clap_plugin::activate()`.
-    current_buffer_config: AtomicCell<Option<BufferConfig>>,
+    pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
+    /// The bus and buffer configurations the plugin was last initialized with in
+    /// `clap_plugin::activate()`. Used to decide whether a sample rate change can be handled with
+    /// [`Plugin::sample_rate_changed()`][crate::prelude::Plugin::sample_rate_changed()].
+    initialized_config: AtomicCell<Option<(BusConfig, BufferConfig)>>,
+    /// Set when the plugin was reactivated through
+    /// [`Plugin::sample_rate_changed()`][crate::prelude::Plugin::sample_rate_changed()]. The next
+    /// `clap_plugin::start_processing()` call then does not reset the plugin.
+    skip_next_reset: AtomicBool,
     /// The current audio processing mode. Set through the render extension. Defaults to realtime.
     pub current_process_mode: AtomicCell<ProcessMode>,
+    /// A random ID for this instance that's stored in the plugin's state. Used to derive the seed
+    /// for
+    /// [`ProcessContext::deterministic_seed()`][crate::prelude::ProcessContext::deterministic_seed()].
+    pub instance_id: AtomicCell<InstanceId>,
     /// The incoming events for the plugin, if `P::MIDI_INPUT` is set to `MidiConfig::Basic` or
     /// higher.
     ///
@@ -140,11 +163,26 @@ pub struct Wrapper<P: ClapPlugin> {
     /// Stores any events the plugin has output during the current processing cycle, analogous to
     /// `input_events`.
     output_events: AtomicRefCell<VecDeque<NoteEvent>>,
+    /// Repaint requests for the editor from the process function and the editor itself, and the
+    /// editor's [`RepaintPolicy`][crate::prelude::RepaintPolicy].
+    pub editor_repaint: RepaintRequests,
+    /// Computes the continuous sample counter and the position jump flag for [`Transport`]. This is
+    /// reset when the host starts processing audio or resets the plugin.
+    transport_tracker: AtomicRefCell<TransportTracker>,
+    /// Fades in the plugin's output after the host starts processing audio if
+    /// [`Plugin::ACTIVATION_FADE_MS`] is set.
+    activation_fade: AtomicRefCell<ActivationFade>,
+    /// Implements [`wrapper_bypass_param`][Self::wrapper_bypass_param]. This is only set up when
+    /// the plugin does not have its own bypass parameter.
+    bypass_processor: AtomicRefCell<BypassProcessor>,
     /// The last process status returned by the plugin. This is used for tail handling.
     last_process_status: AtomicCell<ProcessStatus>,
     /// The current latency in samples, as set by the plugin through the [`ProcessContext`]. uses
     /// the latency extension
     pub current_latency: AtomicU32,
+    /// Whether the host has been told that the plugin's state has changed since it last saved or
+    /// loaded that state. See [`Self::mark_state_dirty()`].
+    state_dirty: AtomicBool,
     /// Contains slices for the plugin's outputs. You can't directly create a nested slice from
     /// a pointer to pointers, so this needs to be preallocated in the setup call and kept around
     /// between process calls. This buffer owns the vector, because otherwise it would need to store
@@ -221,11 +259,38 @@ pub struct Wrapper<P: ClapPlugin> {
     /// Mappings from string parameter identifiers to parameter hashes. Useful for debug logging
     /// and when storing and restoring plugin state.
     param_id_to_hash: HashMap<String, u32>,
+    /// Maps legacy string parameter IDs to the hash of the parameter they were renamed to. The
+    /// legacy IDs' hashes are also included in [`param_by_hash`][Self::param_by_hash], but they're
+    /// not part of the other maps so they're never shown to the host or written to the state.
+    legacy_param_id_to_hash: HashMap<String, u32>,
     /// The inverse mapping from [`param_by_hash`][Self::param_by_hash]. This is needed to be able
     /// to have an ergonomic parameter setting API that uses references to the parameters instead of
     /// having to add a setter function to the parameter (or even worse, have it be completely
     /// untyped).
     pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+    /// The source of the most recent change to every parameter, shared with the editor through the
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub param_changes: Arc<ParamChangeTracker>,
+    /// Clamps the values set through
+    /// [`update_plain_value_by_hash()`][Self::update_plain_value_by_hash()] and rejects NaN and
+    /// infinite values.
+    pub param_value_validator: ParamValueValidator,
+    /// Counts the allocations made during the different phases of the plugin's lifecycle when the
+    /// `alloc_stats` feature is enabled. This does nothing otherwise.
+    pub alloc_stats: AllocStats,
+    /// Calls [`Plugin::state_restored()`][crate::prelude::Plugin::state_restored()] before the
+    /// plugin is initialized after loading state.
+    pub state_restored: StateRestoredNotifier,
+    /// The bypass parameter added by the wrapper if the plugin does not have its own bypass
+    /// parameter. This is included in the parameter maps above. The plugin is not processed while
+    /// this parameter is enabled.
+    wrapper_bypass_param: Option<Box<BoolParam>>,
+    /// The plugin's bypass parameter, or [`wrapper_bypass_param`][Self::wrapper_bypass_param] if
+    /// the plugin does not have one.
+    pub bypass_param_ptr: Option<ParamPtr>,
+    /// Oversamples the plugin if it allows this through [`Plugin::ALLOW_WRAPPER_OVERSAMPLING`].
+    /// This owns the oversampling parameter, which is included in the parameter maps above.
+    pub oversampling: WrapperOversampling,
     /// For all polyphonically modulatable parameters, mappings from the parameter hash's hash to
     /// the parameter's poly modulation ID. These IDs are then passed to the plugin, so it can
     /// quickly refer to parameter by matching on constant IDs.
@@ -243,6 +308,7 @@ pub struct Wrapper<P: ClapPlugin> {
     clap_plugin_render: clap_plugin_render,
 
     clap_plugin_state: clap_plugin_state,
+    host_state: AtomicRefCell<Option<ClapPtr<clap_host_state>>>,
 
     clap_plugin_tail: clap_plugin_tail,
 
@@ -282,6 +348,11 @@ pub enum Task<P: Plugin> {
     VoiceInfoChanged,
     /// Tell the host that it should rescan the current parameter values.
     RescanParamValues,
+    /// Tell the host that the plugin's state has changed.
+    MarkStateDirty,
+    /// Ask the host to deactivate and reactivate the plugin so a new oversampling factor can be
+    /// applied.
+    RequestRestart,
 }
 
 /// The types of CLAP parameter updates for events.
@@ -324,7 +395,7 @@ impl<P: ClapPlugin> EventLoop<Task<P>, Wrapper<P>> for Wrapper<P> {
 
     fn schedule_gui(&self, task: Task<P>) -> bool {
         if self.is_main_thread() {
-            self.execute(task, true);
+            self.execute_gui(task);
             true
         } else {
             let success = self.tasks.push(task).is_ok();
@@ -338,12 +409,12 @@ impl<P: ClapPlugin> EventLoop<Task<P>, Wrapper<P>> for Wrapper<P> {
         }
     }
 
-    fn schedule_background(&self, task: Task<P>) -> bool {
+    fn schedule_background(&self, task: Task<P>, priority: BackgroundTaskPriority) -> bool {
         self.background_thread
             .borrow()
             .as_ref()
             .unwrap()
-            .schedule(task)
+            .schedule(task, priority)
     }
 
     fn is_main_thread(&self) -> bool {
@@ -361,13 +432,16 @@ impl<P: ClapPlugin> EventLoop<Task<P>, Wrapper<P>> for Wrapper<P> {
 }
 
 impl<P: ClapPlugin> MainThreadExecutor<Task<P>> for Wrapper<P> {
-    fn execute(&self, task: Task<P>, is_gui_thread: bool) {
+    fn execute(&self, task: Task<P>, _is_gui_thread: bool) {
         // This function is always called from the main thread, from [Self::on_main_thread].
         match task {
-            Task::PluginTask(task) => (self.task_executor.lock())(task),
+            Task::PluginTask(task) => {
+                let _alloc_phase = self.alloc_stats.enter(AllocPhase::BackgroundTask);
+                (self.task_executor.lock())(task)
+            }
             Task::LatencyChanged => match &*self.host_latency.borrow() {
                 Some(host_latency) => {
-                    nih_debug_assert!(is_gui_thread);
+                    nih_debug_assert_gui_thread!();
 
                     // XXX: The CLAP docs mention that you should request a restart if this happens
                     //      while the plugin is activated (which is not entirely the same thing as
@@ -383,18 +457,29 @@ impl<P: ClapPlugin> MainThreadExecutor<Task<P>> for Wrapper<P> {
             },
             Task::VoiceInfoChanged => match &*self.host_voice_info.borrow() {
                 Some(host_voice_info) => {
-                    nih_debug_assert!(is_gui_thread);
+                    nih_debug_assert_gui_thread!();
                     unsafe_clap_call! { host_voice_info=>changed(&*self.host_callback) };
                 }
                 None => nih_debug_assert_failure!("Host does not support the voice-info extension"),
             },
             Task::RescanParamValues => match &*self.host_params.borrow() {
                 Some(host_params) => {
-                    nih_debug_assert!(is_gui_thread);
+                    nih_debug_assert_gui_thread!();
                     unsafe_clap_call! { host_params=>rescan(&*self.host_callback, CLAP_PARAM_RESCAN_VALUES) };
                 }
                 None => nih_debug_assert_failure!("The host does not support parameters? What?"),
             },
+            Task::MarkStateDirty => {
+                // Hosts that don't implement the state extension don't track unsaved changes
+                if let Some(host_state) = &*self.host_state.borrow() {
+                    nih_debug_assert_gui_thread!();
+                    unsafe_clap_call! { host_state=>mark_dirty(&*self.host_callback) };
+                }
+            }
+            Task::RequestRestart => {
+                nih_debug_assert_gui_thread!();
+                unsafe_clap_call! { &*self.host_callback=>request_restart(&*self.host_callback) };
+            }
         };
     }
 }
@@ -421,8 +506,10 @@ impl<P: ClapPlugin> Wrapper<P> {
         // we'll calculate from the string ID specified by the plugin. These parameters should also
         // remain in the same order as the one returned by the plugin.
         let params = plugin.params();
-        let param_id_hashes_ptrs_groups: Vec<_> = params
-            .param_map()
+        let (mut param_map, wrapper_bypass_param) = param_map_with_bypass(params.as_ref());
+        let bypass_param_ptr = find_bypass_param(&param_map);
+        let oversampling = WrapperOversampling::new::<P>(&mut param_map);
+        let param_id_hashes_ptrs_groups: Vec<_> = param_map
             .into_iter()
             .map(|(id, ptr, group)| {
                 let hash = hash_param_id(&id);
@@ -433,7 +520,7 @@ impl<P: ClapPlugin> Wrapper<P> {
             .iter()
             .map(|(_, hash, _, _)| *hash)
             .collect();
-        let param_by_hash = param_id_hashes_ptrs_groups
+        let mut param_by_hash = param_id_hashes_ptrs_groups
             .iter()
             .map(|(_, hash, ptr, _)| (*hash, *ptr))
             .collect();
@@ -445,10 +532,13 @@ impl<P: ClapPlugin> Wrapper<P> {
             .iter()
             .map(|(id, hash, _, _)| (id.clone(), *hash))
             .collect();
-        let param_ptr_to_hash = param_id_hashes_ptrs_groups
+        let legacy_param_id_to_hash =
+            register_legacy_param_ids(params.as_ref(), &mut param_by_hash, &param_id_to_hash);
+        let param_ptr_to_hash: HashMap<ParamPtr, u32> = param_id_hashes_ptrs_groups
             .iter()
             .map(|(_, hash, ptr, _)| (*ptr, *hash))
             .collect();
+        let param_changes = Arc::new(ParamChangeTracker::new(param_ptr_to_hash.keys().copied()));
         let poly_mod_ids_by_hash: HashMap<u32, u32> = param_id_hashes_ptrs_groups
             .iter()
             .filter_map(|(_, hash, ptr, _)| unsafe {
@@ -457,13 +547,12 @@ impl<P: ClapPlugin> Wrapper<P> {
             .collect();
 
         if cfg!(debug_assertions) {
-            let param_map = params.param_map();
             let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
                 .iter()
                 .map(|(id, _, _, _)| id.clone())
                 .collect();
             nih_debug_assert_eq!(
-                param_map.len(),
+                param_id_hashes_ptrs_groups.len(),
                 param_ids.len(),
                 "The plugin has duplicate parameter IDs, weird things may happen. Consider using \
                  6 character parameter IDs to avoid collisions."
@@ -557,11 +646,19 @@ impl<P: ClapPlugin> Wrapper<P> {
                 aux_output_busses: P::DEFAULT_AUX_OUTPUTS.unwrap_or_default(),
             }),
             current_buffer_config: AtomicCell::new(None),
+            initialized_config: AtomicCell::new(None),
+            skip_next_reset: AtomicBool::new(false),
             current_process_mode: AtomicCell::new(ProcessMode::Realtime),
+            instance_id: AtomicCell::new(InstanceId::new()),
             input_events: AtomicRefCell::new(VecDeque::with_capacity(512)),
             output_events: AtomicRefCell::new(VecDeque::with_capacity(512)),
+            editor_repaint: RepaintRequests::default(),
+            transport_tracker: AtomicRefCell::new(TransportTracker::default()),
+            activation_fade: AtomicRefCell::new(ActivationFade::default()),
+            bypass_processor: AtomicRefCell::new(BypassProcessor::default()),
             last_process_status: AtomicCell::new(ProcessStatus::Normal),
             current_latency: AtomicU32::new(0),
+            state_dirty: AtomicBool::new(false),
             output_buffer: AtomicRefCell::new(Buffer::default()),
             aux_input_storage: AtomicRefCell::new(Vec::new()),
             aux_input_buffers: AtomicRefCell::new(Vec::new()),
@@ -646,7 +743,15 @@ impl<P: ClapPlugin> Wrapper<P> {
             param_by_hash,
             param_group_by_hash,
             param_id_to_hash,
+            legacy_param_id_to_hash,
             param_ptr_to_hash,
+            param_changes,
+            param_value_validator: ParamValueValidator::default(),
+            alloc_stats: AllocStats::default(),
+            state_restored: StateRestoredNotifier::default(),
+            wrapper_bypass_param,
+            bypass_param_ptr,
+            oversampling,
             poly_mod_ids_by_hash,
             output_parameter_events: ArrayQueue::new(OUTPUT_EVENT_QUEUE_CAPACITY),
 
@@ -661,6 +766,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                 save: Some(Self::ext_state_save),
                 load: Some(Self::ext_state_load),
             },
+            host_state: AtomicRefCell::new(None),
 
             clap_plugin_tail: clap_plugin_tail {
                 get: Some(Self::ext_tail_get),
@@ -706,7 +812,9 @@ impl<P: ClapPlugin> Wrapper<P> {
                     let wrapper = wrapper.clone();
 
                     move |task| {
-                        let task_posted = wrapper.schedule_background(Task::PluginTask(task));
+                        let priority = P::background_task_priority(&task);
+                        let task_posted =
+                            wrapper.schedule_background(Task::PluginTask(task), priority);
                         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
                     }
                 }),
@@ -743,7 +851,28 @@ impl<P: ClapPlugin> Wrapper<P> {
         }
     }
 
-    fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+    /// Whether the bypass parameter added by the wrapper is enabled. Always `false` if the plugin
+    /// has its own bypass parameter.
+    fn wrapper_bypassed(&self) -> bool {
+        self.wrapper_bypass_param
+            .as_ref()
+            .map_or(false, |param| param.value())
+    }
+
+    /// The buffer config the plugin was initialized with. This differs from
+    /// [`current_buffer_config`][Self::current_buffer_config] when the wrapper oversamples the
+    /// plugin.
+    pub fn plugin_buffer_config(&self) -> Option<BufferConfig> {
+        self.current_buffer_config
+            .load()
+            .map(|buffer_config| self.oversampling.buffer_config(&buffer_config))
+    }
+
+    fn make_process_context(&self, mut transport: Transport) -> WrapperProcessContext<'_, P> {
+        // Plugins should never see transport information they did not ask for
+        transport.retain(P::REQUIRED_TRANSPORT);
+        self.oversampling.oversample_transport(&mut transport);
+
         WrapperProcessContext {
             wrapper: self,
             input_events_guard: self.input_events.borrow_mut(),
@@ -779,6 +908,9 @@ impl<P: ClapPlugin> Wrapper<P> {
     /// the editor instance is currently locked then nothing will happen, and the request can safely
     /// be ignored.
     pub fn notify_param_values_changed(&self) {
+        // Editors that only redraw on request should still show the new values
+        self.editor_repaint.request();
+
         if let Some(editor) = self.editor.borrow().as_ref() {
             match editor.try_lock() {
                 Some(editor) => editor.param_values_changed(),
@@ -815,7 +947,8 @@ impl<P: ClapPlugin> Wrapper<P> {
     }
 
     /// Convenience function for setting a value for a parameter as triggered by a VST3 parameter
-    /// update. The same rate is for updating parameter smoothing.
+    /// update. The same rate is for updating parameter smoothing. Value changes are recorded in
+    /// [`param_changes`][Self::param_changes] with the specified source, modulation is not.
     ///
     /// After calling this function, you should call
     /// [`notify_param_values_changed()`][Self::notify_param_values_changed()] to allow the editor
@@ -831,13 +964,39 @@ impl<P: ClapPlugin> Wrapper<P> {
         hash: u32,
         update_type: ClapParamUpdate,
         sample_rate: Option<f32>,
+        source: ParamChangeSource,
     ) -> bool {
         match self.param_by_hash.get(&hash) {
+            // Read-only parameters are outputs of the plugin, so the host cannot change them
+            Some(param_ptr)
+                if source == ParamChangeSource::HostAutomation
+                    && unsafe { param_ptr.flags() }.contains(ParamFlags::READ_ONLY) =>
+            {
+                false
+            }
             Some(param_ptr) => {
                 match update_type {
                     ClapParamUpdate::PlainValueSet(clap_plain_value) => {
                         let normalized_value = clap_plain_value as f32
                             / unsafe { param_ptr.step_count() }.unwrap_or(1) as f32;
+                        let normalized_value = match self
+                            .param_value_validator
+                            .validate_normalized(normalized_value, || {
+                                param_id_for_hash(&self.param_id_to_hash, hash)
+                            }) {
+                            Some(normalized_value) => normalized_value,
+                            None => return false,
+                        };
+
+                        match source {
+                            ParamChangeSource::HostAutomation => self
+                                .param_changes
+                                .record_host_automation(*param_ptr, normalized_value),
+                            source => {
+                                self.param_changes
+                                    .record(*param_ptr, source, normalized_value)
+                            }
+                        }
 
                         // Also update the parameter's smoothing if applicable
                         unsafe { param_ptr.set_normalized_value(normalized_value) };
@@ -850,6 +1009,14 @@ impl<P: ClapPlugin> Wrapper<P> {
                     ClapParamUpdate::PlainValueMod(clap_plain_delta) => {
                         let normalized_delta = clap_plain_delta as f32
                             / unsafe { param_ptr.step_count() }.unwrap_or(1) as f32;
+                        let normalized_delta = match self
+                            .param_value_validator
+                            .validate_finite(normalized_delta, || {
+                                param_id_for_hash(&self.param_id_to_hash, hash)
+                            }) {
+                            Some(normalized_delta) => normalized_delta,
+                            None => return false,
+                        };
 
                         // Also update the parameter's smoothing if applicable
                         unsafe { param_ptr.modulate_value(normalized_delta) };
@@ -956,7 +1123,7 @@ impl<P: ClapPlugin> Wrapper<P> {
     pub unsafe fn handle_out_events(&self, out: &clap_output_events, current_sample_idx: usize) {
         // We'll always write these events to the first sample, so even when we add note output we
         // shouldn't have to think about interleaving events here
-        let sample_rate = self.current_buffer_config.load().map(|c| c.sample_rate);
+        let sample_rate = self.plugin_buffer_config().map(|c| c.sample_rate);
         let mut parameter_values_changed = false;
         while let Some(change) = self.output_parameter_events.pop() {
             let push_successful = match change {
@@ -982,6 +1149,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         param_hash,
                         ClapParamUpdate::PlainValueSet(clap_plain_value),
                         sample_rate,
+                        ParamChangeSource::GuiGesture,
                     );
                     parameter_values_changed = true;
 
@@ -1352,7 +1520,8 @@ impl<P: ClapPlugin> Wrapper<P> {
                 self.update_plain_value_by_hash(
                     event.param_id,
                     ClapParamUpdate::PlainValueSet(event.value),
-                    self.current_buffer_config.load().map(|c| c.sample_rate),
+                    self.plugin_buffer_config().map(|c| c.sample_rate),
+                    ParamChangeSource::HostAutomation,
                 );
 
                 // If the parameter supports polyphonic modulation, then the plugin needs to be
@@ -1409,7 +1578,8 @@ impl<P: ClapPlugin> Wrapper<P> {
                 self.update_plain_value_by_hash(
                     event.param_id,
                     ClapParamUpdate::PlainValueMod(event.amount),
-                    self.current_buffer_config.load().map(|c| c.sample_rate),
+                    self.plugin_buffer_config().map(|c| c.sample_rate),
+                    ParamChangeSource::HostAutomation,
                 );
 
                 true
@@ -1631,6 +1801,8 @@ impl<P: ClapPlugin> Wrapper<P> {
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
     pub fn set_state_object(&self, mut state: PluginState) {
+        nih_debug_assert_not_audio_thread!();
+
         // Use a loop and timeouts to handle the super rare edge case when this function gets called
         // between a process call and the host disabling the plugin
         loop {
@@ -1663,22 +1835,39 @@ impl<P: ClapPlugin> Wrapper<P> {
             } else {
                 // Otherwise we'll set the state right here and now, since this function should be
                 // called from a GUI thread
+                let _alloc_phase = self.alloc_stats.enter(AllocPhase::StateLoad);
                 unsafe {
                     state::deserialize_object::<P>(
                         &mut state,
                         self.params.clone(),
-                        state::make_params_getter(&self.param_by_hash, &self.param_id_to_hash),
-                        self.current_buffer_config.load().as_ref(),
+                        state::make_params_getter(
+                            &self.param_by_hash,
+                            &self.param_id_to_hash,
+                            &self.legacy_param_id_to_hash,
+                        ),
+                        self.plugin_buffer_config().as_ref(),
+                        false,
+                        &self.param_value_validator,
                     );
                 }
 
+                self.state_restored.mark();
+                self.param_changes.record_all(ParamChangeSource::Internal);
                 self.notify_param_values_changed();
                 let bus_config = self.current_bus_config.load();
-                if let Some(buffer_config) = self.current_buffer_config.load() {
+                if let Some(buffer_config) = self.plugin_buffer_config() {
                     // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
                     let mut init_context = self.make_init_context();
                     let mut plugin = self.plugin.lock();
-                    plugin.initialize(&bus_config, &buffer_config, &mut init_context);
+                    self.state_restored.notify(&mut *plugin);
+                    if let Err(err) =
+                        plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+                    {
+                        nih_error!(
+                            "Failed to reinitialize the plugin after loading state: {}",
+                            err
+                        );
+                    }
                     process_wrapper(|| plugin.reset());
                 }
 
@@ -1692,6 +1881,12 @@ impl<P: ClapPlugin> Wrapper<P> {
     }
 
     pub fn set_latency_samples(&self, samples: u32) {
+        // The plugin's latency is in oversampled samples if the wrapper oversamples the plugin
+        self.update_latency(self.oversampling.set_plugin_latency(samples));
+    }
+
+    /// Set the latency reported to the host, in samples at the host's sample rate.
+    fn update_latency(&self, samples: u32) {
         // Only make a callback if it's actually needed
         // XXX: For CLAP we could move this handling to the Plugin struct, but it may be worthwhile
         //      to keep doing it this way to stay consistent with VST3.
@@ -1702,6 +1897,15 @@ impl<P: ClapPlugin> Wrapper<P> {
         }
     }
 
+    /// Tell the host that the plugin's state has changed, unless it has already been told so since it
+    /// last saved or loaded the plugin's state.
+    pub fn mark_state_dirty(&self) {
+        if !self.state_dirty.swap(true, Ordering::SeqCst) {
+            let task_posted = self.schedule_gui(Task::MarkStateDirty);
+            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+        }
+    }
+
     pub fn set_current_voice_capacity(&self, capacity: u32) {
         match P::CLAP_POLY_MODULATION_CONFIG {
             Some(config) => {
@@ -1737,6 +1941,8 @@ impl<P: ClapPlugin> Wrapper<P> {
             query_host_extension::<clap_host_latency>(&wrapper.host_callback, CLAP_EXT_LATENCY);
         *wrapper.host_params.borrow_mut() =
             query_host_extension::<clap_host_params>(&wrapper.host_callback, CLAP_EXT_PARAMS);
+        *wrapper.host_state.borrow_mut() =
+            query_host_extension::<clap_host_state>(&wrapper.host_callback, CLAP_EXT_STATE);
         *wrapper.host_voice_info.borrow_mut() = query_host_extension::<clap_host_voice_info>(
             &wrapper.host_callback,
             CLAP_EXT_VOICE_INFO,
@@ -1751,7 +1957,9 @@ impl<P: ClapPlugin> Wrapper<P> {
 
     unsafe extern "C" fn destroy(plugin: *const clap_plugin) {
         assert!(!plugin.is_null() && !(*plugin).plugin_data.is_null());
-        drop(Arc::from_raw((*plugin).plugin_data as *mut Self));
+        let wrapper = Arc::from_raw((*plugin).plugin_data as *mut Self);
+        wrapper.alloc_stats.log_summary(P::NAME);
+        drop(wrapper);
     }
 
     unsafe extern "C" fn activate(
@@ -1771,77 +1979,119 @@ impl<P: ClapPlugin> Wrapper<P> {
             process_mode: wrapper.current_process_mode.load(),
         };
 
+        // A new oversampling factor is only applied here. The plugin sees the oversampled sample
+        // rate and block sizes, and the oversampling filters' latency needs to be reported to the
+        // host even if the plugin doesn't set a new latency during initialization.
+        wrapper.oversampling.update_factor();
+        wrapper.update_latency(wrapper.oversampling.host_latency());
+        let host_buffer_config = buffer_config;
+        let buffer_config = wrapper.oversampling.buffer_config(&host_buffer_config);
+
         // Before initializing the plugin, make sure all smoothers are set the the default values
-        for param in wrapper.param_by_hash.values() {
+        for param in wrapper.param_ptr_to_hash.keys() {
             param.update_smoother(buffer_config.sample_rate, true);
         }
 
+        // Also store this for later, so we can reinitialize the plugin after restoring state. This
+        // is done before initializing the plugin so the init context knows the new sample rate.
+        wrapper
+            .current_buffer_config
+            .store(Some(host_buffer_config));
+
         // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
         let mut init_context = wrapper.make_init_context();
         let mut plugin = wrapper.plugin.lock();
-        if plugin.initialize(&bus_config, &buffer_config, &mut init_context) {
-            // NOTE: `Plugin::reset()` is called in `clap_plugin::start_processing()` instead of in
-            //       this function
-
-            // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
-            // f32` to a `&mut [&mut f32]` in the process call
+        if can_change_sample_rate_in_place::<P>(
+            wrapper.initialized_config.load(),
+            &bus_config,
+            &buffer_config,
+        ) {
+            plugin.sample_rate_changed(buffer_config.sample_rate);
+            wrapper.skip_next_reset.store(true, Ordering::SeqCst);
+        } else {
+            let _alloc_phase = wrapper.alloc_stats.enter(AllocPhase::Initialize);
+            wrapper.state_restored.notify(&mut *plugin);
+            if let Err(err) = plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+            {
+                wrapper.initialized_config.store(None);
+                nih_error!("Failed to initialize the plugin: {}", err);
+                return false;
+            }
             wrapper
-                .output_buffer
-                .borrow_mut()
-                .with_raw_vec(|output_slices| {
-                    output_slices.resize_with(bus_config.num_output_channels as usize, || &mut [])
-                });
+                .initialized_config
+                .store(Some((bus_config, buffer_config)));
+        }
 
-            // Also allocate both the buffers and the slices pointing to those buffers for sidechain
-            // inputs. The slices will be assigned in the process function as this object may have
-            // been moved before then.
-            let mut aux_input_storage = wrapper.aux_input_storage.borrow_mut();
-            aux_input_storage
-                .resize_with(bus_config.aux_input_busses.num_busses as usize, Vec::new);
-            for bus_storage in aux_input_storage.iter_mut() {
-                bus_storage
-                    .resize_with(bus_config.aux_input_busses.num_channels as usize, Vec::new);
-                for channel_storage in bus_storage {
-                    channel_storage.resize(max_frames_count as usize, 0.0);
-                }
-            }
+        // NOTE: `Plugin::reset()` is called in `clap_plugin::start_processing()` instead of in
+        //       this function
 
-            let mut aux_input_buffers = wrapper.aux_input_buffers.borrow_mut();
-            aux_input_buffers.resize_with(
-                bus_config.aux_input_busses.num_busses as usize,
-                Buffer::default,
-            );
-            for buffer in aux_input_buffers.iter_mut() {
-                buffer.with_raw_vec(|channel_slices| {
-                    channel_slices
-                        .resize_with(bus_config.aux_input_busses.num_channels as usize, || {
-                            &mut []
-                        })
-                });
+        // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
+        // f32` to a `&mut [&mut f32]` in the process call
+        wrapper
+            .output_buffer
+            .borrow_mut()
+            .with_raw_vec(|output_slices| {
+                output_slices.resize_with(bus_config.num_output_channels as usize, || &mut [])
+            });
+
+        // Also allocate both the buffers and the slices pointing to those buffers for sidechain
+        // inputs. The slices will be assigned in the process function as this object may have
+        // been moved before then.
+        let mut aux_input_storage = wrapper.aux_input_storage.borrow_mut();
+        aux_input_storage.resize_with(bus_config.aux_input_busses.num_busses as usize, Vec::new);
+        for bus_storage in aux_input_storage.iter_mut() {
+            bus_storage.resize_with(bus_config.aux_input_busses.num_channels as usize, Vec::new);
+            for channel_storage in bus_storage {
+                channel_storage.resize(max_frames_count as usize, 0.0);
             }
+        }
 
-            // And the same thing for the output buffers
-            let mut aux_output_buffers = wrapper.aux_output_buffers.borrow_mut();
-            aux_output_buffers.resize_with(
-                bus_config.aux_output_busses.num_busses as usize,
-                Buffer::default,
-            );
-            for buffer in aux_output_buffers.iter_mut() {
-                buffer.with_raw_vec(|channel_slices| {
-                    channel_slices
-                        .resize_with(bus_config.aux_output_busses.num_channels as usize, || {
-                            &mut []
-                        })
-                });
-            }
+        let mut aux_input_buffers = wrapper.aux_input_buffers.borrow_mut();
+        aux_input_buffers.resize_with(
+            bus_config.aux_input_busses.num_busses as usize,
+            Buffer::default,
+        );
+        for buffer in aux_input_buffers.iter_mut() {
+            buffer.with_raw_vec(|channel_slices| {
+                channel_slices
+                    .resize_with(bus_config.aux_input_busses.num_channels as usize, || {
+                        &mut []
+                    })
+            });
+        }
 
-            // Also store this for later, so we can reinitialize the plugin after restoring state
-            wrapper.current_buffer_config.store(Some(buffer_config));
+        // And the same thing for the output buffers
+        let mut aux_output_buffers = wrapper.aux_output_buffers.borrow_mut();
+        aux_output_buffers.resize_with(
+            bus_config.aux_output_busses.num_busses as usize,
+            Buffer::default,
+        );
+        for buffer in aux_output_buffers.iter_mut() {
+            buffer.with_raw_vec(|channel_slices| {
+                channel_slices
+                    .resize_with(bus_config.aux_output_busses.num_channels as usize, || {
+                        &mut []
+                    })
+            });
+        }
 
-            true
-        } else {
-            false
+        // The latency set during initialization is only applied when the init context is dropped,
+        // and the bypass's delay lines need to be sized for that latency
+        drop(plugin);
+        drop(init_context);
+        if wrapper.wrapper_bypass_param.is_some() {
+            wrapper.bypass_processor.borrow_mut().setup(
+                bus_config.num_output_channels as usize,
+                max_frames_count as usize,
+                wrapper.current_latency.load(Ordering::SeqCst) as usize,
+                host_buffer_config.sample_rate,
+            );
         }
+        wrapper
+            .oversampling
+            .setup(&bus_config, max_frames_count as usize);
+
+        true
     }
 
     unsafe extern "C" fn deactivate(plugin: *const clap_plugin) {
@@ -1849,6 +2099,7 @@ impl<P: ClapPlugin> Wrapper<P> {
         let wrapper = &*((*plugin).plugin_data as *const Self);
 
         wrapper.plugin.lock().deactivate();
+        wrapper.alloc_stats.log_summary(P::NAME);
     }
 
     unsafe extern "C" fn start_processing(plugin: *const clap_plugin) -> bool {
@@ -1863,7 +2114,23 @@ impl<P: ClapPlugin> Wrapper<P> {
 
         // To be consistent with the VST3 wrapper, we'll also reset the buffers here in addition to
         // the dedicated `reset()` function.
-        process_wrapper(|| wrapper.plugin.lock().reset());
+        wrapper.transport_tracker.borrow_mut().reset();
+        if let Some(buffer_config) = wrapper.current_buffer_config.load() {
+            wrapper
+                .activation_fade
+                .borrow_mut()
+                .start::<P>(buffer_config.sample_rate);
+        }
+        wrapper
+            .bypass_processor
+            .borrow_mut()
+            .reset(wrapper.wrapper_bypassed());
+        wrapper.oversampling.reset();
+        // The plugin keeps its state when only the sample rate was changed, see
+        // `Plugin::PREALLOCATES_FOR_MAX_SAMPLE_RATE`
+        if !wrapper.skip_next_reset.swap(false, Ordering::SeqCst) {
+            process_wrapper(|| wrapper.plugin.lock().reset());
+        }
 
         true
     }
@@ -1879,6 +2146,12 @@ impl<P: ClapPlugin> Wrapper<P> {
         check_null_ptr!((), plugin, (*plugin).plugin_data);
         let wrapper = &*((*plugin).plugin_data as *const Self);
 
+        wrapper.transport_tracker.borrow_mut().reset();
+        wrapper
+            .bypass_processor
+            .borrow_mut()
+            .reset(wrapper.wrapper_bypassed());
+        wrapper.oversampling.reset();
         process_wrapper(|| wrapper.plugin.lock().reset());
     }
 
@@ -1938,31 +2211,38 @@ impl<P: ClapPlugin> Wrapper<P> {
                         event_start_idx,
                         |next_event| {
                             // Always split the buffer on transport information changes (tempo, time
-                            // signature, or position changes), and also split on parameter value
-                            // changes after the current sample if sample accurate automation is
-                            // enabled
-                            if P::SAMPLE_ACCURATE_AUTOMATION {
-                                match ((*next_event).space_id, (*next_event).type_) {
-                                    (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE)
-                                    | (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_TRANSPORT) => true,
-                                    (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_MOD) => {
-                                        let next_event =
-                                            &*(next_event as *const clap_event_param_mod);
-
-                                        // The buffer should not be split on polyphonic modulation
-                                        // as those events will be converted to note events
-                                        !(next_event.note_id != -1
-                                            && wrapper
-                                                .poly_mod_ids_by_hash
-                                                .contains_key(&next_event.param_id))
+                            // signature, or position changes), split on parameter value changes
+                            // after the current sample if sample accurate automation is enabled,
+                            // and split on note events if sample accurate events are enabled
+                            match ((*next_event).space_id, (*next_event).type_) {
+                                (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_TRANSPORT) => true,
+                                (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_VALUE) => {
+                                    P::SAMPLE_ACCURATE_AUTOMATION
+                                }
+                                (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_PARAM_MOD) => {
+                                    let next_event = &*(next_event as *const clap_event_param_mod);
+
+                                    // Polyphonic modulation is converted to note events, so that
+                                    // doesn't count as automation
+                                    if next_event.note_id != -1
+                                        && wrapper
+                                            .poly_mod_ids_by_hash
+                                            .contains_key(&next_event.param_id)
+                                    {
+                                        P::SAMPLE_ACCURATE_EVENTS
+                                    } else {
+                                        P::SAMPLE_ACCURATE_AUTOMATION
                                     }
-                                    _ => false,
                                 }
-                            } else {
-                                matches!(
-                                    ((*next_event).space_id, (*next_event).type_,),
-                                    (CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_TRANSPORT)
-                                )
+                                (
+                                    CLAP_CORE_EVENT_SPACE_ID,
+                                    CLAP_EVENT_NOTE_ON
+                                    | CLAP_EVENT_NOTE_OFF
+                                    | CLAP_EVENT_NOTE_CHOKE
+                                    | CLAP_EVENT_NOTE_EXPRESSION
+                                    | CLAP_EVENT_MIDI,
+                                ) => P::SAMPLE_ACCURATE_EVENTS,
+                                _ => false,
                             }
                         },
                     );
@@ -2193,7 +2473,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         // This is a bit messy, but we'll try to compensate for the block splitting.
                         // We can't use the functions on the transport information object for this
                         // because we don't have any sample information.
-                        if P::SAMPLE_ACCURATE_AUTOMATION
+                        if (P::SAMPLE_ACCURATE_AUTOMATION || P::SAMPLE_ACCURATE_EVENTS)
                             && block_start > 0
                             && (context.flags & CLAP_TRANSPORT_HAS_TEMPO != 0)
                         {
@@ -2210,7 +2490,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                         let seconds = context.song_pos_seconds as f64 / CLAP_SECTIME_FACTOR as f64;
 
                         // Same here
-                        if P::SAMPLE_ACCURATE_AUTOMATION
+                        if (P::SAMPLE_ACCURATE_AUTOMATION || P::SAMPLE_ACCURATE_EVENTS)
                             && block_start > 0
                             && (context.flags & CLAP_TRANSPORT_HAS_TEMPO != 0)
                         {
@@ -2221,7 +2501,9 @@ impl<P: ClapPlugin> Wrapper<P> {
                         }
                     }
                     // TODO: CLAP does not mention whether this is behind a flag or not
-                    if P::SAMPLE_ACCURATE_AUTOMATION && block_start > 0 {
+                    if (P::SAMPLE_ACCURATE_AUTOMATION || P::SAMPLE_ACCURATE_EVENTS)
+                        && block_start > 0
+                    {
                         transport.bar_start_pos_beats = match transport.bar_start_pos_beats() {
                             Some(updated) => Some(updated),
                             None => Some(context.bar_start as f64 / CLAP_BEATTIME_FACTOR as f64),
@@ -2255,8 +2537,22 @@ impl<P: ClapPlugin> Wrapper<P> {
                         ));
                     }
                 }
-
-                let result = if buffer_is_valid {
+                wrapper
+                    .transport_tracker
+                    .borrow_mut()
+                    .update(&mut transport, block_end - block_start);
+
+                // The wrapper's own bypass parameter crossfades to the latency compensated input
+                // in realtime, and switches to it instantly when rendering offline. The plugin
+                // isn't processed once the bypass is fully engaged.
+                let mut bypass_processor = wrapper.bypass_processor.borrow_mut();
+                let should_process = buffer_is_valid
+                    && bypass_processor.begin_block(
+                        &output_buffer,
+                        wrapper.wrapper_bypassed(),
+                        wrapper.current_process_mode.load().bypass_mode(),
+                    );
+                let result = if should_process {
                     let mut plugin = wrapper.plugin.lock();
                     // SAFETY: Shortening these borrows is safe as even if the plugin overwrites the
                     //         slices (which it cannot do without using unsafe code), then they
@@ -2266,12 +2562,29 @@ impl<P: ClapPlugin> Wrapper<P> {
                         outputs: &mut *(aux_output_buffers.as_mut_slice() as *mut [Buffer]),
                     };
                     let mut context = wrapper.make_process_context(transport);
-                    let result = plugin.process(&mut output_buffer, &mut aux, &mut context);
+                    let result = wrapper.oversampling.process(
+                        &mut output_buffer,
+                        &mut aux,
+                        |buffer, aux| plugin.process(buffer, aux, &mut context),
+                    );
                     wrapper.last_process_status.store(result);
+                    wrapper
+                        .activation_fade
+                        .borrow_mut()
+                        .apply(&mut output_buffer);
+
                     result
                 } else {
+                    wrapper
+                        .activation_fade
+                        .borrow_mut()
+                        .skip(block_end - block_start);
+
                     ProcessStatus::Normal
                 };
+                if buffer_is_valid {
+                    bypass_processor.end_block(&mut output_buffer);
+                }
 
                 let clap_result = match result {
                     ProcessStatus::Error(err) => {
@@ -2300,6 +2613,12 @@ impl<P: ClapPlugin> Wrapper<P> {
                 }
             };
 
+            // A new oversampling factor can only be applied by reactivating the plugin
+            if wrapper.oversampling.factor_changed() {
+                let task_posted = wrapper.schedule_gui(Task::RequestRestart);
+                nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+            }
+
             // After processing audio, we'll check if the editor has sent us updated plugin state.
             // We'll restore that here on the audio thread to prevent changing the values during the
             // process call and also to prevent inconsistent state when the host also wants to load
@@ -2311,22 +2630,42 @@ impl<P: ClapPlugin> Wrapper<P> {
                 state::deserialize_object::<P>(
                     &mut state,
                     wrapper.params.clone(),
-                    state::make_params_getter(&wrapper.param_by_hash, &wrapper.param_id_to_hash),
-                    wrapper.current_buffer_config.load().as_ref(),
+                    state::make_params_getter(
+                        &wrapper.param_by_hash,
+                        &wrapper.param_id_to_hash,
+                        &wrapper.legacy_param_id_to_hash,
+                    ),
+                    wrapper.plugin_buffer_config().as_ref(),
+                    true,
+                    &wrapper.param_value_validator,
                 );
 
+                wrapper.state_restored.mark();
+                wrapper
+                    .param_changes
+                    .record_all(ParamChangeSource::Internal);
                 wrapper.notify_param_values_changed();
 
                 // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
                 let mut init_context = wrapper.make_init_context();
                 let bus_config = wrapper.current_bus_config.load();
-                let buffer_config = wrapper.current_buffer_config.load().unwrap();
+                let buffer_config = wrapper.plugin_buffer_config().unwrap();
                 let mut plugin = wrapper.plugin.lock();
                 // FIXME: This is obviously not realtime-safe, but loading presets without doing
                 //         this could lead to inconsistencies. It's the plugin's responsibility to
                 //         not perform any realtime-unsafe work when the initialize function is
                 //         called a second time if it supports runtime preset loading.
-                permit_alloc(|| plugin.initialize(&bus_config, &buffer_config, &mut init_context));
+                permit_alloc(|| {
+                    wrapper.state_restored.notify(&mut *plugin);
+                    if let Err(err) =
+                        plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+                    {
+                        nih_error!(
+                            "Failed to reinitialize the plugin after loading state: {}",
+                            err
+                        );
+                    }
+                });
                 plugin.reset();
 
                 // We'll pass the state object back to the GUI thread so deallocation can happen
@@ -2388,7 +2727,7 @@ impl<P: ClapPlugin> Wrapper<P> {
         // [Self::schedule_gui] posts a task to the queue and asks the host to call this function
         // on the main thread, so once that's done we can just handle all requests here
         while let Some(task) = wrapper.tasks.pop() {
-            wrapper.execute(task, true);
+            wrapper.execute_gui(task);
         }
     }
 
@@ -2587,46 +2926,31 @@ impl<P: ClapPlugin> Wrapper<P> {
         let info = &mut *info;
         info.id = stable_id;
         match (is_input, is_main_port) {
-            (true, true) => strlcpy(&mut info.name, P::PORT_NAMES.main_input.unwrap_or("Input")),
-            (false, true) => strlcpy(
-                &mut info.name,
-                P::PORT_NAMES.main_output.unwrap_or("Output"),
-            ),
+            (true, true) => {
+                strlcpy(&mut info.name, main_input_name::<P>());
+            }
+            (false, true) => {
+                strlcpy(&mut info.name, main_output_name::<P>());
+            }
             (true, false) => {
                 let aux_input_idx = if has_main_input { index - 1 } else { index };
-                let custom_port_name = P::PORT_NAMES
-                    .aux_inputs
-                    .map(|names| names[aux_input_idx as usize]);
-                if current_bus_config.aux_input_busses.num_busses <= 1 {
-                    strlcpy(
-                        &mut info.name,
-                        custom_port_name.unwrap_or("Sidechain Input"),
-                    );
-                } else {
-                    strlcpy(
-                        &mut info.name,
-                        custom_port_name
-                            .unwrap_or(&format!("Sidechain Input {}", aux_input_idx + 1)),
-                    );
-                }
+                strlcpy(
+                    &mut info.name,
+                    &aux_input_name::<P>(
+                        aux_input_idx as usize,
+                        current_bus_config.aux_input_busses.num_busses as usize,
+                    ),
+                );
             }
             (false, false) => {
                 let aux_output_idx = if has_main_output { index - 1 } else { index };
-                let custom_port_name = P::PORT_NAMES
-                    .aux_outputs
-                    .map(|names| names[aux_output_idx as usize]);
-                if current_bus_config.aux_output_busses.num_busses <= 1 {
-                    strlcpy(
-                        &mut info.name,
-                        custom_port_name.unwrap_or("Auxiliary Output"),
-                    );
-                } else {
-                    strlcpy(
-                        &mut info.name,
-                        custom_port_name
-                            .unwrap_or(&format!("Auxiliary Output {}", aux_output_idx + 1)),
-                    );
-                }
+                strlcpy(
+                    &mut info.name,
+                    &aux_output_name::<P>(
+                        aux_output_idx as usize,
+                        current_bus_config.aux_output_busses.num_busses as usize,
+                    ),
+                );
             }
         };
         info.flags = if is_main_port {
@@ -2852,6 +3176,8 @@ impl<P: ClapPlugin> Wrapper<P> {
                 };
 
                 // This extension is only exposed when we have an editor
+                let _alloc_phase = wrapper.alloc_stats.enter(AllocPhase::EditorOpen);
+                wrapper.editor_repaint.request();
                 *editor_handle = Some(wrapper.editor.borrow().as_ref().unwrap().lock().spawn(
                     ParentWindowHandle { handle },
                     wrapper.clone().make_gui_context(),
@@ -2976,7 +3302,8 @@ impl<P: ClapPlugin> Wrapper<P> {
         let default_value = param_ptr.default_normalized_value();
         let step_count = param_ptr.step_count();
         let flags = param_ptr.flags();
-        let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE);
+        let read_only = flags.contains(ParamFlags::READ_ONLY);
+        let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE) && !read_only;
         let hidden = flags.contains(ParamFlags::HIDDEN);
         let is_bypass = flags.contains(ParamFlags::BYPASS);
 
@@ -2997,6 +3324,9 @@ impl<P: ClapPlugin> Wrapper<P> {
         if hidden {
             param_info.flags |= CLAP_PARAM_IS_HIDDEN | CLAP_PARAM_IS_READONLY;
         }
+        if read_only {
+            param_info.flags |= CLAP_PARAM_IS_READONLY;
+        }
         if is_bypass {
             param_info.flags |= CLAP_PARAM_IS_BYPASS
         }
@@ -3149,6 +3479,7 @@ impl<P: ClapPlugin> Wrapper<P> {
         let serialized = state::serialize_json::<P>(
             wrapper.params.clone(),
             state::make_params_iter(&wrapper.param_by_hash, &wrapper.param_id_to_hash),
+            wrapper.instance_id.load(),
         );
         match serialized {
             Ok(serialized) => {
@@ -3168,6 +3499,7 @@ impl<P: ClapPlugin> Wrapper<P> {
                     return false;
                 }
 
+                wrapper.state_dirty.store(false, Ordering::SeqCst);
                 nih_trace!("Saved state ({} bytes)", serialized.len());
 
                 true
@@ -3185,6 +3517,7 @@ impl<P: ClapPlugin> Wrapper<P> {
     ) -> bool {
         check_null_ptr!(false, plugin, (*plugin).plugin_data, stream);
         let wrapper = &*((*plugin).plugin_data as *const Self);
+        let _alloc_phase = wrapper.alloc_stats.enter(AllocPhase::StateLoad);
 
         // CLAP does not have a way to tell how much data there is left in a stream, so we've
         // prepended the size in front of our JSON state
@@ -3209,22 +3542,41 @@ impl<P: ClapPlugin> Wrapper<P> {
         let success = state::deserialize_json::<P>(
             &read_buffer,
             wrapper.params.clone(),
-            state::make_params_getter(&wrapper.param_by_hash, &wrapper.param_id_to_hash),
-            wrapper.current_buffer_config.load().as_ref(),
+            state::make_params_getter(
+                &wrapper.param_by_hash,
+                &wrapper.param_id_to_hash,
+                &wrapper.legacy_param_id_to_hash,
+            ),
+            wrapper.plugin_buffer_config().as_ref(),
+            wrapper.is_processing.load(Ordering::SeqCst),
+            &wrapper.instance_id,
+            &wrapper.param_value_validator,
         );
         if !success {
             return false;
         }
+        wrapper.state_dirty.store(false, Ordering::SeqCst);
+        wrapper.state_restored.mark();
 
         // Reinitialize the plugin after loading state so it can respond to the new parameter values
+        wrapper
+            .param_changes
+            .record_all(ParamChangeSource::HostStateLoad);
         wrapper.notify_param_values_changed();
 
         let bus_config = wrapper.current_bus_config.load();
-        if let Some(buffer_config) = wrapper.current_buffer_config.load() {
+        if let Some(buffer_config) = wrapper.plugin_buffer_config() {
             // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
             let mut init_context = wrapper.make_init_context();
             let mut plugin = wrapper.plugin.lock();
-            plugin.initialize(&bus_config, &buffer_config, &mut init_context);
+            wrapper.state_restored.notify(&mut *plugin);
+            if let Err(err) = plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+            {
+                nih_error!(
+                    "Failed to reinitialize the plugin after loading state: {}",
+                    err
+                );
+            }
             // TODO: This also goes for the VST3 version, but should we call reset here? Won't the
             //       host always restart playback? Check this with a couple of hosts and remove the
             //       duplicate reset if it's not needed.
@@ -0,0 +1,105 @@
+//! Opening and closing a plugin's editor.
+
+use parking_lot::Mutex;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Tracks whether a plugin's editor is open, and owns the handle returned by
+/// [`Editor::spawn()`][crate::prelude::Editor::spawn()] while it is. Dropping that handle tears
+/// down the editor's window and everything it created, so closing the editor is the same as
+/// dropping the handle. Used by both the VST3 and the standalone wrappers so reopening an editor
+/// goes through the same states everywhere.
+#[derive(Default)]
+pub struct EditorLifecycle {
+    /// The handle returned by the editor's `spawn()` function while the editor is open.
+    handle: Mutex<Option<Box<dyn Any + Send>>>,
+    /// Whether [`handle`][Self::handle] is set. This can be checked from any thread without
+    /// locking.
+    is_open: AtomicBool,
+}
+
+impl EditorLifecycle {
+    /// Open the editor by calling `spawn`, and store the handle it returns. Returns `false` without
+    /// calling `spawn` if the editor is already open.
+    pub fn open(&self, spawn: impl FnOnce() -> Box<dyn Any + Send>) -> bool {
+        let mut handle = self.handle.lock();
+        if handle.is_some() {
+            return false;
+        }
+
+        *handle = Some(spawn());
+        self.is_open.store(true, Ordering::SeqCst);
+
+        true
+    }
+
+    /// Close the editor by dropping its handle. Returns `false` if the editor was not open.
+    pub fn close(&self) -> bool {
+        // The handle is dropped after releasing the lock since the editor's teardown may end up
+        // querying this object through the GUI context
+        let handle = {
+            let mut handle = self.handle.lock();
+            self.is_open.store(false, Ordering::SeqCst);
+            handle.take()
+        };
+
+        handle.is_some()
+    }
+
+    /// Whether the editor is currently open.
+    pub fn is_open(&self) -> bool {
+        self.is_open.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A handle that counts how many instances of it are alive.
+    struct CountedHandle(Arc<AtomicU32>);
+
+    impl CountedHandle {
+        fn boxed(live_handles: &Arc<AtomicU32>) -> Box<dyn Any + Send> {
+            live_handles.fetch_add(1, Ordering::SeqCst);
+            Box::new(Self(live_handles.clone()))
+        }
+    }
+
+    impl Drop for CountedHandle {
+        fn drop(&mut self) {
+            self.0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn editor_lifecycle_reopen_cycles() {
+        let lifecycle = EditorLifecycle::default();
+        let live_handles = Arc::new(AtomicU32::new(0));
+        assert!(!lifecycle.is_open());
+        assert!(!lifecycle.close());
+
+        for _ in 0..100 {
+            assert!(lifecycle.open(|| CountedHandle::boxed(&live_handles)));
+            assert!(lifecycle.is_open());
+            assert_eq!(live_handles.load(Ordering::SeqCst), 1);
+
+            // Opening an open editor should not spawn a second one
+            assert!(!lifecycle.open(|| unreachable!()));
+            assert_eq!(live_handles.load(Ordering::SeqCst), 1);
+
+            assert!(lifecycle.close());
+            assert!(!lifecycle.is_open());
+            assert_eq!(live_handles.load(Ordering::SeqCst), 0);
+            assert!(!lifecycle.close());
+        }
+
+        // The handle is also torn down if the editor is still open when the wrapper goes away
+        assert!(lifecycle.open(|| CountedHandle::boxed(&live_handles)));
+        drop(lifecycle);
+        assert_eq!(live_handles.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,349 @@
+//! Applying parameter changes the editor scheduled for the next beat or bar boundary. See
+//! [`GuiContext::raw_set_parameter_at_musical()`][crate::prelude::GuiContext::raw_set_parameter_at_musical()].
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::context::gui::MusicalQuantize;
+use crate::context::process::Transport;
+use crate::params::internals::ParamPtr;
+use crate::params::ParamFlags;
+
+/// How far a position may be past a beat or bar boundary in quarter notes while still counting as
+/// being on that boundary. This absorbs rounding errors from hosts and from block splitting.
+const MUSICAL_BOUNDARY_EPSILON: f64 = 1e-6;
+
+/// The value stored in [`MusicalScheduler`] for parameters without a pending change. A packed
+/// change only uses the lower two bits of its upper half for the quantization, so this can never
+/// be confused with an actual change.
+const NO_PENDING_MUSICAL_CHANGE: u64 = u64::MAX;
+
+/// The number of samples from the start of the block described by `transport` until the first
+/// sample at or after the next beat or bar boundary. This is zero if the block starts exactly on a
+/// boundary. Returns `None` if the transport is not playing, or if the song position or tempo is
+/// not known, in which case scheduled changes should be applied right away.
+pub fn samples_until_musical_boundary(
+    transport: &Transport,
+    quantize: MusicalQuantize,
+) -> Option<u32> {
+    let (pos_beats, tempo) = match (transport.pos_beats(), transport.tempo) {
+        (Some(pos_beats), Some(tempo)) if transport.playing && tempo > 0.0 => (pos_beats, tempo),
+        _ => return None,
+    };
+
+    let numerator = transport.time_sig_numerator.filter(|n| *n > 0).unwrap_or(4);
+    let denominator = transport
+        .time_sig_denominator
+        .filter(|d| *d > 0)
+        .unwrap_or(4);
+    let beat_length = 4.0 / denominator as f64;
+    let grid_length = match quantize {
+        MusicalQuantize::Off => return Some(0),
+        MusicalQuantize::Beat => beat_length,
+        MusicalQuantize::Bar => beat_length * numerator as f64,
+    };
+
+    // Beats are counted from the start of the bar so they line up with the bar lines in odd time
+    // signatures
+    let grid_start = transport.bar_start_pos_beats().unwrap_or(0.0);
+    let next_boundary = grid_start
+        + ((pos_beats - grid_start) / grid_length - MUSICAL_BOUNDARY_EPSILON).ceil() * grid_length;
+    let samples = (next_boundary - pos_beats) / tempo * 60.0 * transport.sample_rate as f64;
+
+    Some((samples - MUSICAL_BOUNDARY_EPSILON).ceil().max(0.0) as u32)
+}
+
+/// Parameter changes the editor scheduled for the next beat or bar boundary. See
+/// [`GuiContext::raw_set_parameter_at_musical()`][crate::prelude::GuiContext::raw_set_parameter_at_musical()].
+/// Like [`ModulationWriter`][super::util::ModulationWriter], the parameters are stored when the wrapper is created so scheduling
+/// changes from the GUI thread and applying them from the audio thread never allocates or locks.
+pub struct MusicalScheduler {
+    /// The pending change for every parameter, packed into a single atomic so the audio thread can
+    /// compare and swap it without locking. The lower 32 bits contain the normalized value's bit
+    /// pattern and the upper 32 bits contain the quantization. This is
+    /// [`NO_PENDING_MUSICAL_CHANGE`] if there is no pending change.
+    pending: HashMap<u32, AtomicU64>,
+    /// The number of pending changes, so the audio thread can skip everything when there are none.
+    num_pending: AtomicUsize,
+}
+
+impl MusicalScheduler {
+    /// Create a scheduler for the parameters in `param_ptr_to_hash`. Read-only parameters can't be
+    /// set from the editor, so they can't be scheduled either.
+    pub fn new(param_ptr_to_hash: &HashMap<ParamPtr, u32>) -> Self {
+        let pending = param_ptr_to_hash
+            .iter()
+            .filter(|(param, _)| !unsafe { param.flags() }.contains(ParamFlags::READ_ONLY))
+            .map(|(_, hash)| (*hash, AtomicU64::new(NO_PENDING_MUSICAL_CHANGE)))
+            .collect();
+
+        Self {
+            pending,
+            num_pending: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pack a change into the format stored in [`pending`][Self::pending].
+    fn pack_change(normalized: f32, quantize: MusicalQuantize) -> u64 {
+        let quantize = match quantize {
+            MusicalQuantize::Off => 0u64,
+            MusicalQuantize::Beat => 1,
+            MusicalQuantize::Bar => 2,
+        };
+
+        (quantize << 32) | normalized.to_bits() as u64
+    }
+
+    /// Unpack a change stored in [`pending`][Self::pending]. Returns `None` if `change` is
+    /// [`NO_PENDING_MUSICAL_CHANGE`].
+    fn unpack_change(change: u64) -> Option<(f32, MusicalQuantize)> {
+        let quantize = match change >> 32 {
+            0 => MusicalQuantize::Off,
+            1 => MusicalQuantize::Beat,
+            2 => MusicalQuantize::Bar,
+            _ => return None,
+        };
+
+        Some((f32::from_bits(change as u32), quantize))
+    }
+
+    /// Schedule a change to the parameter with the given hash, replacing any change that was
+    /// already pending for it. Returns `false` if the parameter can't be scheduled.
+    pub fn schedule(&self, hash: u32, normalized: f32, quantize: MusicalQuantize) -> bool {
+        let pending = match self.pending.get(&hash) {
+            Some(pending) => pending,
+            None => return false,
+        };
+
+        if pending.swap(Self::pack_change(normalized, quantize), Ordering::SeqCst)
+            == NO_PENDING_MUSICAL_CHANGE
+        {
+            self.num_pending.fetch_add(1, Ordering::SeqCst);
+        }
+
+        true
+    }
+
+    /// Cancel the pending change for the parameter with the given hash. Returns whether there was a
+    /// pending change.
+    pub fn cancel(&self, hash: u32) -> bool {
+        match self
+            .pending
+            .get(&hash)
+            .map(|pending| pending.swap(NO_PENDING_MUSICAL_CHANGE, Ordering::SeqCst))
+        {
+            Some(change) if change != NO_PENDING_MUSICAL_CHANGE => {
+                self.num_pending.fetch_sub(1, Ordering::SeqCst);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The normalized value of the pending change for the parameter with the given hash, if any.
+    pub fn pending(&self, hash: u32) -> Option<f32> {
+        self.pending
+            .get(&hash)
+            .and_then(|pending| Self::unpack_change(pending.load(Ordering::SeqCst)))
+            .map(|(normalized, _)| normalized)
+    }
+
+    /// Call `apply` with the hash, the sample offset within the block, and the normalized value of
+    /// every pending change whose beat or bar boundary falls within the next `num_samples` samples
+    /// of `transport`, and remove those changes. Changes are due at the start of the block if the
+    /// transport does not provide enough information to compute the boundary. Called from the
+    /// audio thread at the start of every process call.
+    pub fn take_due(
+        &self,
+        transport: &Transport,
+        num_samples: u32,
+        mut apply: impl FnMut(u32, u32, f32),
+    ) {
+        if self.num_pending.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        for (hash, pending) in &self.pending {
+            let change = pending.load(Ordering::SeqCst);
+            let (normalized, quantize) = match Self::unpack_change(change) {
+                Some(unpacked) => unpacked,
+                None => continue,
+            };
+            let timing = match samples_until_musical_boundary(transport, quantize) {
+                Some(timing) if timing < num_samples => timing,
+                Some(_) => continue,
+                None => 0,
+            };
+
+            // The editor may have replaced or cancelled the change in the meantime
+            if pending
+                .compare_exchange(
+                    change,
+                    NO_PENDING_MUSICAL_CHANGE,
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                )
+                .is_ok()
+            {
+                self.num_pending.fetch_sub(1, Ordering::SeqCst);
+                apply(*hash, timing, normalized);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::range::FloatRange;
+    use crate::params::{FloatParam, Param};
+
+    /// A playing transport at 120 BPM and 44.1 kHz, where a quarter note is 22050 samples long.
+    fn musical_transport(pos_beats: f64, time_sig: (i32, i32)) -> Transport {
+        let mut transport = Transport::new(44_100.0);
+        transport.playing = true;
+        transport.tempo = Some(120.0);
+        transport.time_sig_numerator = Some(time_sig.0);
+        transport.time_sig_denominator = Some(time_sig.1);
+        transport.pos_beats = Some(pos_beats);
+
+        transport
+    }
+
+    #[test]
+    fn musical_boundaries() {
+        let transport = musical_transport(0.9, (4, 4));
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            Some(2205)
+        );
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Bar),
+            Some(68_355)
+        );
+
+        // A block that starts on the boundary fires on its first sample
+        let transport = musical_transport(4.0, (4, 4));
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            Some(0)
+        );
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Bar),
+            Some(0)
+        );
+
+        // In 6/8 a beat is an eighth note and a bar is three quarter notes long
+        let transport = musical_transport(3.2, (6, 8));
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            Some(6615)
+        );
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Bar),
+            Some(61_740)
+        );
+
+        // A boundary between two samples is rounded up to the first sample after it
+        let transport = musical_transport(1.0 - 10.5 / 22_050.0, (4, 4));
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            Some(11)
+        );
+    }
+
+    #[test]
+    fn musical_boundaries_without_transport() {
+        let mut transport = musical_transport(0.9, (4, 4));
+        transport.playing = false;
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            None
+        );
+
+        let mut transport = musical_transport(0.9, (4, 4));
+        transport.tempo = None;
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Beat),
+            None
+        );
+
+        // Without a time signature the bar length defaults to 4/4
+        let mut transport = musical_transport(0.9, (4, 4));
+        transport.time_sig_numerator = None;
+        transport.time_sig_denominator = None;
+        assert_eq!(
+            samples_until_musical_boundary(&transport, MusicalQuantize::Bar),
+            Some(68_355)
+        );
+    }
+
+    /// Schedule changes and advance the transport in blocks that don't line up with the beats. The
+    /// changes should fire exactly once, in the block that contains the boundary.
+    #[test]
+    fn musical_scheduler_across_blocks() {
+        const BLOCK_SIZE: u32 = 512;
+
+        let param = FloatParam::new("Param", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 });
+        let scheduler = MusicalScheduler::new(&HashMap::from([(param.as_ptr(), 1234)]));
+        assert!(!scheduler.schedule(5678, 0.5, MusicalQuantize::Beat));
+
+        // The change is replaced before it fires, and the beat boundary at sample 2205 falls in
+        // the fifth block
+        assert!(scheduler.schedule(1234, 0.25, MusicalQuantize::Beat));
+        assert!(scheduler.schedule(1234, 0.75, MusicalQuantize::Beat));
+        assert_eq!(scheduler.pending(1234), Some(0.75));
+
+        let mut applied = Vec::new();
+        for block_idx in 0..8 {
+            let pos_beats = 0.9 + (block_idx * BLOCK_SIZE) as f64 / 22_050.0;
+            scheduler.take_due(
+                &musical_transport(pos_beats, (4, 4)),
+                BLOCK_SIZE,
+                |hash, timing, normalized| applied.push((block_idx, hash, timing, normalized)),
+            );
+        }
+        assert_eq!(applied, [(4, 1234, 2205 - (4 * BLOCK_SIZE), 0.75)]);
+        assert_eq!(scheduler.pending(1234), None);
+
+        // A cancelled change never fires
+        assert!(scheduler.schedule(1234, 0.5, MusicalQuantize::Bar));
+        assert!(scheduler.cancel(1234));
+        assert!(!scheduler.cancel(1234));
+        scheduler.take_due(&musical_transport(4.0, (4, 4)), BLOCK_SIZE, |_, _, _| {
+            panic!("The change was cancelled")
+        });
+
+        // Without a playing transport the change fires at the start of the next block
+        let mut applied = Vec::new();
+        scheduler.schedule(1234, 0.5, MusicalQuantize::Bar);
+        scheduler.take_due(&Transport::new(44_100.0), BLOCK_SIZE, |hash, timing, _| {
+            applied.push((hash, timing))
+        });
+        assert_eq!(applied, [(1234, 0)]);
+    }
+
+    #[test]
+    fn musical_scheduler_packed_changes() {
+        for quantize in [
+            MusicalQuantize::Off,
+            MusicalQuantize::Beat,
+            MusicalQuantize::Bar,
+        ] {
+            for normalized in [0.0, 0.5, 1.0, f32::from_bits(u32::MAX)] {
+                let packed = MusicalScheduler::pack_change(normalized, quantize);
+                assert_ne!(packed, NO_PENDING_MUSICAL_CHANGE);
+
+                let (unpacked_normalized, unpacked_quantize) =
+                    MusicalScheduler::unpack_change(packed).unwrap();
+                assert_eq!(unpacked_normalized.to_bits(), normalized.to_bits());
+                assert_eq!(unpacked_quantize, quantize);
+            }
+        }
+
+        assert_eq!(
+            MusicalScheduler::unpack_change(NO_PENDING_MUSICAL_CHANGE),
+            None
+        );
+    }
+}
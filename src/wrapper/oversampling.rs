@@ -0,0 +1,406 @@
+//! The oversampling the wrappers can apply to the entire plugin. See
+//! [`Plugin::ALLOW_WRAPPER_OVERSAMPLING`].
+
+use atomic_refcell::AtomicRefCell;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::buffer::Buffer;
+use crate::context::process::Transport;
+use crate::midi::NoteEvent;
+use crate::params::enums::{Enum, EnumParam};
+use crate::params::internals::ParamPtr;
+use crate::params::Param;
+use crate::plugin::{AuxiliaryBuffers, BufferConfig, BusConfig, Plugin, ProcessStatus};
+use crate::util::{oversampling_latency, Oversampler, MAX_OVERSAMPLING_STAGES};
+
+/// The ID of the oversampling parameter the wrappers add for plugins that allow wrapper
+/// oversampling. Plugins should not use this ID for their own parameters.
+pub const OVERSAMPLING_PARAM_ID: &str = "nih_plug_oversampling";
+
+/// The factors the wrapper's oversampling parameter can be set to. The parameter only contains the
+/// factors up to the highest factor in [`Plugin::ALLOW_WRAPPER_OVERSAMPLING`]. The variant's index
+/// is the number of 2x oversampling stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum)]
+pub enum OversamplingFactor {
+    #[id = "1x"]
+    #[name = "1x"]
+    X1,
+    #[id = "2x"]
+    #[name = "2x"]
+    X2,
+    #[id = "4x"]
+    #[name = "4x"]
+    X4,
+    #[id = "8x"]
+    #[name = "8x"]
+    X8,
+    #[id = "16x"]
+    #[name = "16x"]
+    X16,
+    #[id = "32x"]
+    #[name = "32x"]
+    X32,
+}
+
+/// Implements [`Plugin::ALLOW_WRAPPER_OVERSAMPLING`] for the VST3 and CLAP wrappers. The wrappers
+/// call [`update_factor()`][Self::update_factor()] and [`setup()`][Self::setup()] when the plugin
+/// is activated, [`reset()`][Self::reset()] when it starts processing audio, and wrap the
+/// plugin's process call in [`process()`][Self::process()]. The plugin sees the buffer config
+/// from [`buffer_config()`][Self::buffer_config()].
+///
+/// The oversampling factor only changes when the plugin gets reactivated. When the parameter gets
+/// changed, [`factor_changed()`][Self::factor_changed()] tells the wrapper to ask the host for a
+/// restart.
+pub struct WrapperOversampling {
+    /// The oversampling parameter. This is `None` if the plugin doesn't allow more than one
+    /// factor. Boxed so the parameter map's pointer to it stays valid.
+    param: Option<Box<EnumParam<OversamplingFactor>>>,
+    /// The number of 2x stages the plugin is currently initialized with. Zero means that the
+    /// plugin is not oversampled.
+    active_stages: AtomicUsize,
+    /// Set when [`factor_changed()`][Self::factor_changed()] has returned `true`, so the restart
+    /// is only requested once. Cleared when the plugin gets reactivated.
+    restart_requested: AtomicBool,
+    /// The latency last set by the plugin, in oversampled samples. Needed to recompute the
+    /// latency reported to the host when the factor changes.
+    plugin_latency: AtomicU32,
+    /// The oversamplers and buffers. Only allocated while the plugin is oversampled.
+    processor: AtomicRefCell<OversamplingProcessor>,
+}
+
+/// The oversamplers and the buffers passed to the plugin while it is oversampled.
+#[derive(Default)]
+struct OversamplingProcessor {
+    /// One oversampler per main output channel. The main input is copied to the main output
+    /// before the plugin gets called, so the inputs don't need their own oversamplers.
+    main: Vec<Oversampler>,
+    /// One oversampler per channel for every auxiliary input bus.
+    aux_inputs: Vec<Vec<Oversampler>>,
+    /// One oversampler per channel for every auxiliary output bus.
+    aux_outputs: Vec<Vec<Oversampler>>,
+    /// Points to the main oversamplers' upsampled signals during
+    /// [`WrapperOversampling::process()`].
+    main_buffer: Buffer<'static>,
+    /// Point to the auxiliary input oversamplers' upsampled signals during
+    /// [`WrapperOversampling::process()`].
+    aux_input_buffers: Vec<Buffer<'static>>,
+    /// Point to the auxiliary output oversamplers' upsampled signals during
+    /// [`WrapperOversampling::process()`].
+    aux_output_buffers: Vec<Buffer<'static>>,
+}
+
+impl WrapperOversampling {
+    /// Add the oversampling parameter to the end of `param_map` if `P` allows more than one
+    /// oversampling factor.
+    pub fn new<P: Plugin>(param_map: &mut Vec<(String, ParamPtr, String)>) -> Self {
+        let max_stages = max_oversampling_stages(P::ALLOW_WRAPPER_OVERSAMPLING);
+        let param = if max_stages > 0 {
+            nih_debug_assert!(
+                !param_map
+                    .iter()
+                    .any(|(id, _, _)| id == OVERSAMPLING_PARAM_ID),
+                "The parameter ID '{}' is reserved for NIH-plug's own oversampling parameter",
+                OVERSAMPLING_PARAM_ID
+            );
+
+            let param = Box::new(
+                EnumParam::new_with_num_variants(
+                    "Oversampling",
+                    OversamplingFactor::X1,
+                    max_stages + 1,
+                )
+                .non_automatable(),
+            );
+            param_map.push((
+                String::from(OVERSAMPLING_PARAM_ID),
+                param.as_ptr(),
+                String::new(),
+            ));
+
+            Some(param)
+        } else {
+            None
+        };
+
+        Self {
+            param,
+            active_stages: AtomicUsize::new(0),
+            restart_requested: AtomicBool::new(false),
+            plugin_latency: AtomicU32::new(0),
+            processor: AtomicRefCell::new(OversamplingProcessor::default()),
+        }
+    }
+
+    /// The factor the plugin is currently oversampled by.
+    #[inline]
+    pub fn factor(&self) -> u32 {
+        1 << self.active_stages.load(Ordering::Relaxed)
+    }
+
+    /// Apply the oversampling parameter's current value. This should be called before the plugin
+    /// gets (re)initialized, since it changes the plugin's [buffer
+    /// config][Self::buffer_config()].
+    pub fn update_factor(&self) {
+        let num_stages = self
+            .param
+            .as_ref()
+            .map_or(0, |param| param.value().to_index());
+        self.active_stages.store(num_stages, Ordering::SeqCst);
+        self.restart_requested.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once after the oversampling parameter was changed to a value that differs
+    /// from the active factor. The wrapper should then ask the host to restart the plugin so
+    /// [`update_factor()`][Self::update_factor()] can apply the new factor. This is cheap enough
+    /// to be called for every block.
+    #[inline]
+    pub fn factor_changed(&self) -> bool {
+        match &self.param {
+            Some(param) => {
+                param.value().to_index() != self.active_stages.load(Ordering::Relaxed)
+                    && !self.restart_requested.swap(true, Ordering::SeqCst)
+            }
+            None => false,
+        }
+    }
+
+    /// The buffer config the plugin should see for the host's buffer config.
+    pub fn buffer_config(&self, host_buffer_config: &BufferConfig) -> BufferConfig {
+        let factor = self.factor();
+
+        BufferConfig {
+            sample_rate: host_buffer_config.sample_rate * factor as f32,
+            min_buffer_size: host_buffer_config.min_buffer_size.map(|size| size * factor),
+            max_buffer_size: host_buffer_config.max_buffer_size * factor,
+            ..*host_buffer_config
+        }
+    }
+
+    /// Convert the plugin's latency in oversampled samples to the latency that should be reported
+    /// to the host. The plugin's latency is stored so it can be converted again with
+    /// [`host_latency()`][Self::host_latency()] after the factor changes.
+    pub fn set_plugin_latency(&self, samples: u32) -> u32 {
+        self.plugin_latency.store(samples, Ordering::SeqCst);
+        self.host_latency()
+    }
+
+    /// The plugin's latency plus the oversampling filters' latency, in samples at the host's
+    /// sample rate.
+    pub fn host_latency(&self) -> u32 {
+        let num_stages = self.active_stages.load(Ordering::SeqCst);
+        let plugin_latency = self.plugin_latency.load(Ordering::SeqCst);
+
+        // Rounding up keeps the plugin's output from arriving earlier than the host expects
+        let factor = 1 << num_stages;
+        ((plugin_latency + factor - 1) / factor) + oversampling_latency(num_stages)
+    }
+
+    /// Allocate the oversamplers for the active factor. Everything is deallocated again when the
+    /// plugin is not oversampled.
+    pub fn setup(&self, bus_config: &BusConfig, max_block_size: usize) {
+        let num_stages = self.active_stages.load(Ordering::SeqCst);
+        let mut processor = self.processor.borrow_mut();
+        if num_stages == 0 {
+            *processor = OversamplingProcessor::default();
+            return;
+        }
+
+        let new_oversamplers = |num_channels: u32| -> Vec<Oversampler> {
+            (0..num_channels)
+                .map(|_| Oversampler::new(max_block_size, num_stages))
+                .collect()
+        };
+        processor.main = new_oversamplers(bus_config.num_output_channels);
+        processor.aux_inputs = (0..bus_config.aux_input_busses.num_busses)
+            .map(|_| new_oversamplers(bus_config.aux_input_busses.num_channels))
+            .collect();
+        processor.aux_outputs = (0..bus_config.aux_output_busses.num_busses)
+            .map(|_| new_oversamplers(bus_config.aux_output_busses.num_channels))
+            .collect();
+
+        let processor = &mut *processor;
+        resize_buffer(&mut processor.main_buffer, processor.main.len());
+        processor
+            .aux_input_buffers
+            .resize_with(processor.aux_inputs.len(), Buffer::default);
+        for (buffer, oversamplers) in processor
+            .aux_input_buffers
+            .iter_mut()
+            .zip(&processor.aux_inputs)
+        {
+            resize_buffer(buffer, oversamplers.len());
+        }
+        processor
+            .aux_output_buffers
+            .resize_with(processor.aux_outputs.len(), Buffer::default);
+        for (buffer, oversamplers) in processor
+            .aux_output_buffers
+            .iter_mut()
+            .zip(&processor.aux_outputs)
+        {
+            resize_buffer(buffer, oversamplers.len());
+        }
+    }
+
+    /// Clear the oversampling filters.
+    pub fn reset(&self) {
+        let mut processor = self.processor.borrow_mut();
+        let processor = &mut *processor;
+        for oversampler in processor
+            .main
+            .iter_mut()
+            .chain(processor.aux_inputs.iter_mut().flatten())
+            .chain(processor.aux_outputs.iter_mut().flatten())
+        {
+            oversampler.reset();
+        }
+    }
+
+    /// Call `process` with upsampled versions of `buffer` and `aux`, and downsample the outputs
+    /// back into `buffer` and `aux.outputs` afterwards. `process` is called with the original
+    /// buffers when the plugin is not oversampled.
+    pub fn process(
+        &self,
+        buffer: &mut Buffer,
+        aux: &mut AuxiliaryBuffers,
+        process: impl FnOnce(&mut Buffer, &mut AuxiliaryBuffers) -> ProcessStatus,
+    ) -> ProcessStatus {
+        let num_stages = self.active_stages.load(Ordering::Relaxed);
+        if num_stages == 0 {
+            return process(buffer, aux);
+        }
+
+        let mut processor = self.processor.borrow_mut();
+        let processor = &mut *processor;
+
+        // SAFETY: The buffers only point to the oversamplers' scratch buffers while this function
+        //         runs. Those are not reallocated until `setup()` is called again, and
+        //         `processor` stays borrowed in the meantime.
+        unsafe {
+            upsample_buffer(
+                &mut processor.main,
+                buffer,
+                &mut processor.main_buffer,
+                num_stages,
+            );
+            for ((oversamplers, input), oversampled) in processor
+                .aux_inputs
+                .iter_mut()
+                .zip(aux.inputs.iter())
+                .zip(processor.aux_input_buffers.iter_mut())
+            {
+                upsample_buffer(oversamplers, input, oversampled, num_stages);
+            }
+            // The plugin may read from its auxiliary outputs before writing to them, so these
+            // contain the upsampled host buffers just like the main output
+            for ((oversamplers, output), oversampled) in processor
+                .aux_outputs
+                .iter_mut()
+                .zip(aux.outputs.iter())
+                .zip(processor.aux_output_buffers.iter_mut())
+            {
+                upsample_buffer(oversamplers, output, oversampled, num_stages);
+            }
+        }
+
+        let mut oversampled_aux = AuxiliaryBuffers {
+            inputs: unsafe { &mut *(processor.aux_input_buffers.as_mut_slice() as *mut [Buffer]) },
+            outputs: unsafe {
+                &mut *(processor.aux_output_buffers.as_mut_slice() as *mut [Buffer])
+            },
+        };
+        let status = process(&mut processor.main_buffer, &mut oversampled_aux);
+
+        downsample_buffer(&mut processor.main, buffer, num_stages);
+        for (oversamplers, output) in processor.aux_outputs.iter_mut().zip(aux.outputs.iter_mut()) {
+            downsample_buffer(oversamplers, output, num_stages);
+        }
+
+        status
+    }
+
+    /// Convert the host's transport information to the oversampled sample rate.
+    pub fn oversample_transport(&self, transport: &mut Transport) {
+        let factor = self.factor();
+        if factor == 1 {
+            return;
+        }
+
+        transport.sample_rate *= factor as f32;
+        transport.continuous_pos_samples *= factor as i64;
+        transport.pos_samples = transport.pos_samples.map(|pos| pos * factor as i64);
+        transport.loop_range_samples = transport
+            .loop_range_samples
+            .map(|(start, end)| (start * factor as i64, end * factor as i64));
+    }
+
+    /// Convert an incoming event's timing to the oversampled sample rate.
+    #[inline]
+    pub fn upsample_event(&self, event: &mut NoteEvent) {
+        event.multiply_timing(self.factor());
+    }
+
+    /// Convert the timing of an event sent by the plugin back to the host's sample rate.
+    #[inline]
+    pub fn downsample_event(&self, event: &mut NoteEvent) {
+        event.divide_timing(self.factor());
+    }
+}
+
+/// The number of 2x stages needed for the highest factor in `factors`. Zero if the plugin does not
+/// allow oversampling.
+fn max_oversampling_stages(factors: &[u8]) -> usize {
+    nih_debug_assert!(
+        factors
+            .iter()
+            .enumerate()
+            .all(|(idx, factor)| *factor as usize == 1 << idx),
+        "ALLOW_WRAPPER_OVERSAMPLING needs to contain consecutive powers of two starting at 1, \
+         like '&[1, 2, 4]'"
+    );
+    nih_debug_assert!(factors.len() <= MAX_OVERSAMPLING_STAGES + 1);
+
+    let max_factor = factors.iter().copied().max().unwrap_or(1).max(1);
+    let max_stages = (u8::BITS - 1 - max_factor.leading_zeros()) as usize;
+    max_stages.min(MAX_OVERSAMPLING_STAGES)
+}
+
+/// Set the number of channels in a buffer that will point to the oversamplers' scratch buffers.
+fn resize_buffer(buffer: &mut Buffer<'static>, num_channels: usize) {
+    unsafe {
+        buffer.with_raw_vec(|output_slices| {
+            output_slices.resize_with(num_channels, || &mut []);
+        })
+    };
+}
+
+/// Upsample every channel in `buffer`, and point `oversampled`'s channels to the upsampled
+/// signals.
+///
+/// # Safety
+///
+/// `oversampled` may only be used while `oversamplers` is borrowed and not reallocated.
+unsafe fn upsample_buffer(
+    oversamplers: &mut [Oversampler],
+    buffer: &Buffer,
+    oversampled: &mut Buffer<'static>,
+    num_stages: usize,
+) {
+    oversampled.with_raw_vec(|oversampled_slices| {
+        for ((oversampled_slice, oversampler), channel) in oversampled_slices
+            .iter_mut()
+            .zip(oversamplers.iter_mut())
+            .zip(buffer.as_slice_immutable())
+        {
+            let upsampled = oversampler.upsample(channel, num_stages);
+            *oversampled_slice = &mut *(upsampled as *mut [f32]);
+        }
+    });
+}
+
+/// Downsample the oversamplers' upsampled signals back into `buffer`.
+fn downsample_buffer(oversamplers: &mut [Oversampler], buffer: &mut Buffer, num_stages: usize) {
+    for (oversampler, channel) in oversamplers.iter_mut().zip(buffer.as_slice()) {
+        oversampler.downsample(channel, num_stages);
+    }
+}
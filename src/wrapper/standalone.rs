@@ -5,13 +5,20 @@ use clap::{FromArgMatches, IntoApp};
 
 use self::backend::Backend;
 use self::config::WrapperConfig;
+use self::session::SessionFile;
 use self::wrapper::{Wrapper, WrapperError};
-use super::util::setup_logger;
+use super::util::{
+    aux_input_name, aux_output_name, channel_name, main_input_name, main_output_name, setup_logger,
+};
 use crate::plugin::Plugin;
 
+mod automation;
 mod backend;
 mod config;
 mod context;
+mod recorder;
+mod resampler;
+mod session;
 mod wrapper;
 
 /// Open an NIH-plug plugin as a standalone application. If the plugin has an editor, this will open
@@ -38,15 +45,22 @@ mod wrapper;
 /// use plugin_name::PluginName;
 ///
 /// fn main() {
-///     nih_export_standalone::<PluginName>();
+///     if !nih_export_standalone::<PluginName>() {
+///         std::process::exit(1);
+///     }
 /// }
 /// ```
 ///
 /// By default this will connect to the 'default' audio and MIDI ports. Use the command line options
 /// to change this. `--help` lists all available options.
 ///
+/// The audio and MIDI settings, the editor's DPI scale, and the plugin's state are remembered
+/// between runs in a session file in the platform's config directory. Options passed on the
+/// command line override the stored settings, and `--fresh` ignores the session file.
+///
 /// If the wrapped plugin fails to initialize or throws an error during audio processing, then this
-/// function will return `false`.
+/// function will print the error and return `false`. The standalone binary should then exit with a
+/// nonzero exit code, as shown above.
 pub fn nih_export_standalone<P: Plugin>() -> bool {
     nih_export_standalone_with_args::<P, _>(std::env::args())
 }
@@ -61,19 +75,45 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
     // Instead of parsing this directly, we need to take a bit of a roundabout approach to get the
     // plugin's name and vendor in here since they'd otherwise be taken from NIH-plug's own
     // `Cargo.toml` file.
-    let config = WrapperConfig::from_arg_matches(
-        &WrapperConfig::command()
-            .name(P::NAME)
-            .author(P::VENDOR)
-            .get_matches_from(args),
-    )
-    .unwrap_or_else(|err| err.exit());
+    let matches = WrapperConfig::command()
+        .name(P::NAME)
+        .author(P::VENDOR)
+        .get_matches_from(args);
+    let mut config = WrapperConfig::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    if config.dump_io {
+        print_io_layout::<P>(&config);
+        return true;
+    }
+
+    // The stored session is applied before the backend is created so the stored device settings
+    // can be used. Options passed on the command line always take precedence.
+    let session = session::session_path(P::NAME).map(|path| {
+        let stored_session = if config.fresh {
+            None
+        } else {
+            session::load_or_warn(&path, P::NAME)
+        };
+        let initial_state = stored_session.map(|stored_session| {
+            nih_log!("Restoring the session from '{}'", path.display());
+            stored_session.apply_to_config(&mut config, &matches);
+            stored_session.state
+        });
+
+        SessionFile {
+            path,
+            initial_state,
+        }
+    });
+    if session.is_none() {
+        nih_log!("Could not find the config directory, the session will not be saved");
+    }
 
     match config.backend {
         config::BackendType::Auto => {
             let result = backend::Jack::new::<P>(config.clone()).map(|backend| {
                 nih_log!("Using the JACK backend");
-                run_wrapper::<P, _>(backend, config.clone())
+                run_wrapper::<P, _>(backend, config.clone(), session.clone())
             });
 
             #[cfg(target_os = "linux")]
@@ -81,7 +121,11 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
                 match backend::Cpal::new::<P>(config.clone(), cpal::HostId::Alsa) {
                     Ok(backend) => {
                         nih_log!("Using the ALSA backend");
-                        Ok(run_wrapper::<P, _>(backend, config.clone()))
+                        Ok(run_wrapper::<P, _>(
+                            backend,
+                            config.clone(),
+                            session.clone(),
+                        ))
                     }
                     Err(err) => {
                         nih_error!(
@@ -97,7 +141,11 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
                 match backend::Cpal::new::<P>(config.clone(), cpal::HostId::CoreAudio) {
                     Ok(backend) => {
                         nih_log!("Using the CoreAudio backend");
-                        Ok(run_wrapper::<P, _>(backend, config.clone()))
+                        Ok(run_wrapper::<P, _>(
+                            backend,
+                            config.clone(),
+                            session.clone(),
+                        ))
                     }
                     Err(err) => {
                         nih_error!(
@@ -113,7 +161,11 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
                 match backend::Cpal::new::<P>(config.clone(), cpal::HostId::Wasapi) {
                     Ok(backend) => {
                         nih_log!("Using the WASAPI backend");
-                        Ok(run_wrapper::<P, _>(backend, config.clone()))
+                        Ok(run_wrapper::<P, _>(
+                            backend,
+                            config.clone(),
+                            session.clone(),
+                        ))
                     }
                     Err(err) => {
                         nih_error!(
@@ -127,11 +179,11 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
 
             result.unwrap_or_else(|_| {
                 nih_error!("Falling back to the dummy audio backend, audio and MIDI will not work");
-                run_wrapper::<P, _>(backend::Dummy::new::<P>(config.clone()), config)
+                run_wrapper::<P, _>(backend::Dummy::new::<P>(config.clone()), config, session)
             })
         }
         config::BackendType::Jack => match backend::Jack::new::<P>(config.clone()) {
-            Ok(backend) => run_wrapper::<P, _>(backend, config),
+            Ok(backend) => run_wrapper::<P, _>(backend, config, session),
             Err(err) => {
                 nih_error!("Could not initialize the JACK backend: {:#}", err);
                 false
@@ -140,7 +192,7 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
         #[cfg(target_os = "linux")]
         config::BackendType::Alsa => {
             match backend::Cpal::new::<P>(config.clone(), cpal::HostId::Alsa) {
-                Ok(backend) => run_wrapper::<P, _>(backend, config),
+                Ok(backend) => run_wrapper::<P, _>(backend, config, session),
                 Err(err) => {
                     nih_error!("Could not initialize the ALSA backend: {:#}", err);
                     false
@@ -150,7 +202,7 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
         #[cfg(target_os = "macos")]
         config::BackendType::CoreAudio => {
             match backend::Cpal::new::<P>(config.clone(), cpal::HostId::CoreAudio) {
-                Ok(backend) => run_wrapper::<P, _>(backend, config),
+                Ok(backend) => run_wrapper::<P, _>(backend, config, session),
                 Err(err) => {
                     nih_error!("Could not initialize the CoreAudio backend: {:#}", err);
                     false
@@ -160,7 +212,7 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
         #[cfg(target_os = "windows")]
         config::BackendType::Wasapi => {
             match backend::Cpal::new::<P>(config.clone(), cpal::HostId::Wasapi) {
-                Ok(backend) => run_wrapper::<P, _>(backend, config),
+                Ok(backend) => run_wrapper::<P, _>(backend, config, session),
                 Err(err) => {
                     nih_error!("Could not initialize the WASAPI backend: {:#}", err);
                     false
@@ -168,13 +220,62 @@ pub fn nih_export_standalone_with_args<P: Plugin, Args: IntoIterator<Item = Stri
             }
         }
         config::BackendType::Dummy => {
-            run_wrapper::<P, _>(backend::Dummy::new::<P>(config.clone()), config)
+            run_wrapper::<P, _>(backend::Dummy::new::<P>(config.clone()), config, session)
         }
     }
 }
 
-fn run_wrapper<P: Plugin, B: Backend>(backend: B, config: WrapperConfig) -> bool {
-    let wrapper = match Wrapper::<P, _>::new(backend, config) {
+/// Print the plugin's audio and MIDI ports for the `--dump-io` option. The standalone target only
+/// connects the main input and output ports, but the auxiliary ports are also listed.
+fn print_io_layout<P: Plugin>(config: &WrapperConfig) {
+    let print_port = |port_name: &str, num_channels: u32, note: &str| {
+        let channel_names: Vec<String> = (0..num_channels as usize)
+            .map(|channel_idx| channel_name(port_name, channel_idx, num_channels as usize))
+            .collect();
+        println!(
+            "  {port_name} ({num_channels} channels{note}): {}",
+            channel_names.join(", ")
+        );
+    };
+
+    println!("Audio inputs:");
+    let num_input_channels = config.input_channels.unwrap_or(P::DEFAULT_INPUT_CHANNELS);
+    if num_input_channels > 0 {
+        print_port(main_input_name::<P>(), num_input_channels, "");
+    }
+    let aux_inputs = P::DEFAULT_AUX_INPUTS.unwrap_or_default();
+    for aux_input_idx in 0..aux_inputs.num_busses as usize {
+        print_port(
+            &aux_input_name::<P>(aux_input_idx, aux_inputs.num_busses as usize),
+            aux_inputs.num_channels,
+            ", not connected in the standalone",
+        );
+    }
+
+    println!("Audio outputs:");
+    let num_output_channels = config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS);
+    if num_output_channels > 0 {
+        print_port(main_output_name::<P>(), num_output_channels, "");
+    }
+    let aux_outputs = P::DEFAULT_AUX_OUTPUTS.unwrap_or_default();
+    for aux_output_idx in 0..aux_outputs.num_busses as usize {
+        print_port(
+            &aux_output_name::<P>(aux_output_idx, aux_outputs.num_busses as usize),
+            aux_outputs.num_channels,
+            ", not connected in the standalone",
+        );
+    }
+
+    println!("MIDI input: {:?}", P::MIDI_INPUT);
+    println!("MIDI output: {:?}", P::MIDI_OUTPUT);
+}
+
+fn run_wrapper<P: Plugin, B: Backend>(
+    backend: B,
+    config: WrapperConfig,
+    session: Option<SessionFile>,
+) -> bool {
+    let wrapper = match Wrapper::<P, _>::new(backend, config, session) {
         Ok(wrapper) => wrapper,
         Err(err) => {
             print_error(err);
@@ -203,8 +304,14 @@ fn print_error(error: WrapperError) {
                  {output_channels} channel output configuration",
             );
         }
-        WrapperError::InitializationFailed => {
-            nih_error!("The plugin failed to initialize");
+        WrapperError::InitializationFailed(err) => {
+            nih_error!("The plugin failed to initialize: {}", err);
+        }
+        WrapperError::InvalidAutomationFile => {
+            nih_error!("Could not load the automation file");
+        }
+        WrapperError::ResamplerInitializationFailed => {
+            nih_error!("Could not set up sample rate conversion for '--force-sample-rate'");
         }
     }
 }
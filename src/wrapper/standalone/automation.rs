@@ -0,0 +1,566 @@
+//! Records the parameter changes made in the standalone wrapper to a JSON file, and plays them back
+//! again at the same sample positions. While recording, the audio thread only pushes changes to a
+//! lock-free queue, and a dedicated writer thread converts them to parameter IDs and plain values
+//! and writes the file.
+
+use anyhow::{Context, Result};
+use crossbeam::channel;
+use crossbeam::queue::ArrayQueue;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::params::internals::ParamPtr;
+use crate::params::Params;
+
+/// The version of the automation file format. Files with a different version are rejected.
+pub const AUTOMATION_FORMAT_VERSION: u32 = 1;
+
+/// The number of parameter changes that can be queued between the audio thread and the writer
+/// thread. Any further changes made before the writer thread empties the queue are dropped.
+const QUEUE_CAPACITY: usize = 4096;
+/// How often the writer thread empties the queue and rewrites the file if there are new changes.
+/// This makes sure the file contains everything up to the last write when the process gets killed,
+/// for instance with Ctrl+C.
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The contents of an automation file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Automation {
+    /// The file format's version, see [`AUTOMATION_FORMAT_VERSION`].
+    pub version: u32,
+    /// The name of the plugin the automation was recorded with. Automation can only be played back
+    /// with the same plugin.
+    pub plugin: String,
+    /// The sample rate the automation was recorded at. The sample positions only line up when
+    /// playing the automation back at the same sample rate.
+    pub sample_rate: f32,
+    /// The recorded parameter changes, in chronological order.
+    pub events: Vec<AutomationEvent>,
+}
+
+/// A single recorded parameter change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutomationEvent {
+    /// The number of samples processed since the audio stream started when this change was
+    /// applied. Changes are applied in between process calls, so during playback this change will
+    /// be applied right before processing the first block that starts at or after this sample.
+    pub sample: u64,
+    /// Where the change came from.
+    pub source: AutomationSource,
+    /// The parameter's ID.
+    pub param_id: String,
+    /// The parameter's new plain, unnormalized value.
+    pub plain_value: f32,
+}
+
+/// Where a recorded parameter change came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationSource {
+    /// The change was made from the plugin's editor.
+    Gui,
+}
+
+/// Records parameter changes to an automation file. The audio thread pushes changes through an
+/// [`AutomationRecorderSink`] obtained from [`AutomationRecorder::new()`].
+pub struct AutomationRecorder {
+    shutdown_sender: channel::Sender<()>,
+    /// The writer thread. This is taken out of the mutex when shutting down.
+    writer_thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The audio thread's side of an [`AutomationRecorder`]. This never blocks or allocates.
+pub struct AutomationRecorderSink {
+    queue: Arc<ArrayQueue<QueuedChange>>,
+    /// The number of changes that could not be queued because the writer thread could not keep up.
+    dropped_changes: Arc<AtomicU64>,
+}
+
+/// A parameter change as sent by the audio thread.
+struct QueuedChange {
+    sample: u64,
+    source: AutomationSource,
+    param_ptr: ParamPtr,
+    normalized_value: f32,
+}
+
+/// Plays back an automation file. The file is parsed and the parameter IDs are resolved up front in
+/// [`AutomationPlayer::load()`], so [`AutomationPlayer::next_change()`] can be called from the
+/// audio thread.
+pub struct AutomationPlayer {
+    /// `(sample, param_ptr, normalized_value)` tuples, sorted by sample position.
+    changes: Vec<(u64, ParamPtr, f32)>,
+    /// The index in `changes` of the next change that should be applied.
+    next_change_idx: usize,
+}
+
+impl AutomationRecorder {
+    /// Create a recorder that writes the parameter changes for the plugin `plugin_name` to `path`,
+    /// and spawn its writer thread. The returned [`AutomationRecorderSink`] should be moved to the
+    /// audio thread. `params` is kept alive until the recorder shuts down, and `param_map` is used
+    /// to look up the IDs of the parameters passed to the sink.
+    pub fn new(
+        path: PathBuf,
+        plugin_name: &str,
+        sample_rate: f32,
+        params: Arc<dyn Params>,
+        param_map: &HashMap<String, ParamPtr>,
+    ) -> (Self, AutomationRecorderSink) {
+        let queue = Arc::new(ArrayQueue::new(QUEUE_CAPACITY));
+        let dropped_changes = Arc::new(AtomicU64::new(0));
+        let automation = Automation {
+            version: AUTOMATION_FORMAT_VERSION,
+            plugin: String::from(plugin_name),
+            sample_rate,
+            events: Vec::new(),
+        };
+        let param_ids: HashMap<ParamPtr, String> = param_map
+            .iter()
+            .map(|(param_id, param_ptr)| (*param_ptr, param_id.clone()))
+            .collect();
+
+        let (shutdown_sender, shutdown_receiver) = channel::bounded(1);
+        let writer_thread = {
+            let queue = queue.clone();
+            let dropped_changes = dropped_changes.clone();
+            thread::Builder::new()
+                .name(String::from("automation-writer"))
+                .spawn(move || {
+                    // The parameter pointers are only valid while `params` is alive
+                    let _params = params;
+
+                    run_writer_thread(
+                        &path,
+                        automation,
+                        &queue,
+                        &dropped_changes,
+                        &param_ids,
+                        shutdown_receiver,
+                    )
+                })
+                .expect("Could not spawn the automation writer thread")
+        };
+
+        (
+            Self {
+                shutdown_sender,
+                writer_thread: Mutex::new(Some(writer_thread)),
+            },
+            AutomationRecorderSink {
+                queue,
+                dropped_changes,
+            },
+        )
+    }
+
+    /// Write any remaining changes to the file and terminate the writer thread. This should be
+    /// called when the audio thread has stopped. Calling this more than once does nothing.
+    pub fn shutdown(&self) {
+        if let Some(writer_thread) = self.writer_thread.lock().take() {
+            let _ = self.shutdown_sender.send(());
+            writer_thread
+                .join()
+                .expect("The automation writer thread panicked");
+        }
+    }
+}
+
+impl Drop for AutomationRecorder {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl AutomationRecorderSink {
+    /// Record that the parameter `param_ptr` was set to `normalized_value` after processing
+    /// `sample` samples.
+    pub fn record(
+        &self,
+        sample: u64,
+        source: AutomationSource,
+        param_ptr: ParamPtr,
+        normalized_value: f32,
+    ) {
+        let change = QueuedChange {
+            sample,
+            source,
+            param_ptr,
+            normalized_value,
+        };
+        if self.queue.push(change).is_err() {
+            self.dropped_changes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl AutomationPlayer {
+    /// Load the automation file at `path` so it can be played back for the plugin `plugin_name` at
+    /// `sample_rate`. Returns an error if the file cannot be read, if it uses a different format
+    /// version, or if it was recorded with another plugin.
+    pub fn load(
+        path: &Path,
+        plugin_name: &str,
+        sample_rate: f32,
+        param_map: &HashMap<String, ParamPtr>,
+    ) -> Result<Self> {
+        let json =
+            std::fs::read(path).with_context(|| format!("Could not read '{}'", path.display()))?;
+        let automation: Automation = serde_json::from_slice(&json)
+            .with_context(|| format!("Could not parse '{}'", path.display()))?;
+
+        Self::new(&automation, plugin_name, sample_rate, param_map)
+    }
+
+    /// The same as [`load()`][Self::load()], but for automation that has already been parsed.
+    pub fn new(
+        automation: &Automation,
+        plugin_name: &str,
+        sample_rate: f32,
+        param_map: &HashMap<String, ParamPtr>,
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            automation.version == AUTOMATION_FORMAT_VERSION,
+            "Unsupported automation format version {}, expected version {}",
+            automation.version,
+            AUTOMATION_FORMAT_VERSION
+        );
+        anyhow::ensure!(
+            automation.plugin == plugin_name,
+            "The automation was recorded with '{}', not with '{}'",
+            automation.plugin,
+            plugin_name
+        );
+        if automation.sample_rate != sample_rate {
+            nih_log!(
+                "The automation was recorded at {} Hz but is being played back at {} Hz, the \
+                 changes will happen at different times",
+                automation.sample_rate,
+                sample_rate
+            );
+        }
+
+        let mut changes: Vec<_> = automation
+            .events
+            .iter()
+            .filter_map(|event| match param_map.get(&event.param_id) {
+                Some(param_ptr) => {
+                    let normalized_value =
+                        unsafe { param_ptr.preview_normalized(event.plain_value) };
+                    Some((event.sample, *param_ptr, normalized_value))
+                }
+                None => {
+                    nih_log!(
+                        "Skipping automation for unknown parameter '{}'",
+                        event.param_id
+                    );
+                    None
+                }
+            })
+            .collect();
+        // This is a stable sort, so changes at the same position keep their order
+        changes.sort_by_key(|(sample, _, _)| *sample);
+
+        Ok(Self {
+            changes,
+            next_change_idx: 0,
+        })
+    }
+
+    /// Get the next parameter change that should be applied before processing a block starting at
+    /// `sample`, as a `(param_ptr, normalized_value)` pair. Call this in a loop until it returns
+    /// `None` to apply all changes up to that point. This never blocks or allocates.
+    pub fn next_change(&mut self, sample: u64) -> Option<(ParamPtr, f32)> {
+        let (change_sample, param_ptr, normalized_value) =
+            *self.changes.get(self.next_change_idx)?;
+        if change_sample <= sample {
+            self.next_change_idx += 1;
+            Some((param_ptr, normalized_value))
+        } else {
+            None
+        }
+    }
+}
+
+/// The writer thread's main loop. This periodically moves changes from the queue into `automation`
+/// and writes it to `path` whenever there are new changes, until a message is received on
+/// `shutdown_receiver`.
+fn run_writer_thread(
+    path: &Path,
+    mut automation: Automation,
+    queue: &ArrayQueue<QueuedChange>,
+    dropped_changes: &AtomicU64,
+    param_ids: &HashMap<ParamPtr, String>,
+    shutdown_receiver: channel::Receiver<()>,
+) {
+    // The file is always written at least once, even if nothing got recorded
+    let mut needs_write = true;
+    let mut reported_dropped_changes = 0;
+    loop {
+        let should_shutdown = !matches!(
+            shutdown_receiver.recv_timeout(WRITE_INTERVAL),
+            Err(channel::RecvTimeoutError::Timeout)
+        );
+
+        while let Some(change) = queue.pop() {
+            let param_id = match param_ids.get(&change.param_ptr) {
+                Some(param_id) => param_id,
+                None => {
+                    nih_debug_assert_failure!("Unknown parameter in the automation queue");
+                    continue;
+                }
+            };
+
+            automation.events.push(AutomationEvent {
+                sample: change.sample,
+                source: change.source,
+                param_id: param_id.clone(),
+                plain_value: unsafe { change.param_ptr.preview_plain(change.normalized_value) },
+            });
+            needs_write = true;
+        }
+
+        let dropped_changes = dropped_changes.load(Ordering::Relaxed);
+        if dropped_changes > reported_dropped_changes {
+            nih_log!(
+                "Dropped {} parameter changes while recording automation",
+                dropped_changes - reported_dropped_changes
+            );
+            reported_dropped_changes = dropped_changes;
+        }
+
+        if needs_write {
+            if let Err(err) = write_automation(path, &automation) {
+                nih_error!("Could not write the automation file: {:#}", err);
+            }
+            needs_write = false;
+        }
+
+        if should_shutdown {
+            break;
+        }
+    }
+}
+
+/// Write `automation` to `path`. The file is first written to a temporary file and then moved into
+/// place, so the file is never left half written.
+fn write_automation(path: &Path, automation: &Automation) -> Result<()> {
+    let json =
+        serde_json::to_vec_pretty(automation).context("Could not format the automation as JSON")?;
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, json)
+        .with_context(|| format!("Could not write to '{}'", Path::new(&temp_path).display()))?;
+    std::fs::rename(&temp_path, path)
+        .with_context(|| format!("Could not move the file to '{}'", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::range::FloatRange;
+    use crate::params::smoothing::SmoothingStyle;
+    use crate::params::{FloatParam, Param};
+    use crate::util::Xoshiro128;
+
+    const BLOCK_SIZE: usize = 64;
+    const NUM_BLOCKS: usize = 256;
+    const SAMPLE_RATE: f32 = 48_000.0;
+
+    struct TestParams {
+        gain: FloatParam,
+    }
+
+    impl Default for TestParams {
+        fn default() -> Self {
+            Self {
+                gain: FloatParam::new("Gain", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                    .with_smoother(SmoothingStyle::Linear(5.0)),
+            }
+        }
+    }
+
+    unsafe impl Params for TestParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+        }
+    }
+
+    fn test_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nih_plug_automation_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        path
+    }
+
+    fn make_param_map(params: &TestParams) -> HashMap<String, ParamPtr> {
+        params
+            .param_map()
+            .into_iter()
+            .map(|(param_id, param_ptr, _)| (param_id, param_ptr))
+            .collect()
+    }
+
+    /// Set a parameter the same way the standalone wrapper does in between process calls.
+    fn apply_change(param_ptr: ParamPtr, normalized_value: f32) {
+        unsafe {
+            param_ptr.set_normalized_value(normalized_value);
+            param_ptr.update_smoother(SAMPLE_RATE, false);
+        }
+    }
+
+    /// A deterministic input signal.
+    fn input_block(block_idx: usize) -> Vec<f32> {
+        (0..BLOCK_SIZE)
+            .map(|sample_idx| ((block_idx * BLOCK_SIZE + sample_idx) as f32 * 0.01).sin())
+            .collect()
+    }
+
+    fn process_block(params: &TestParams, block: &mut [f32]) {
+        for sample in block {
+            *sample *= params.gain.smoothed.next();
+        }
+    }
+
+    /// Playing back recorded automation against the same input should produce the exact same
+    /// output as the recording session.
+    #[test]
+    fn round_trip() {
+        let path = test_path("round_trip");
+
+        let params = Arc::new(TestParams::default());
+        let param_map = make_param_map(&params);
+        let (recorder, sink) = AutomationRecorder::new(
+            path.clone(),
+            "Test Plugin",
+            SAMPLE_RATE,
+            params.clone(),
+            &param_map,
+        );
+
+        let mut rng = Xoshiro128::from_seed(1234);
+        let mut recorded_output = Vec::new();
+        let mut num_processed_samples = 0;
+        for block_idx in 0..NUM_BLOCKS {
+            let mut block = input_block(block_idx);
+            process_block(&params, &mut block);
+            recorded_output.extend(block);
+            num_processed_samples += BLOCK_SIZE as u64;
+
+            // Random knob moves, which the wrapper applies after the process call
+            while rng.next_f32_unipolar() < 0.3 {
+                let normalized_value = rng.next_f32_unipolar();
+                apply_change(params.gain.as_ptr(), normalized_value);
+                sink.record(
+                    num_processed_samples,
+                    AutomationSource::Gui,
+                    params.gain.as_ptr(),
+                    normalized_value,
+                );
+            }
+        }
+        recorder.shutdown();
+
+        let params = Arc::new(TestParams::default());
+        let mut player =
+            AutomationPlayer::load(&path, "Test Plugin", SAMPLE_RATE, &make_param_map(&params))
+                .unwrap();
+        assert!(!player.changes.is_empty());
+
+        let mut played_output = Vec::new();
+        let mut num_processed_samples = 0;
+        for block_idx in 0..NUM_BLOCKS {
+            while let Some((param_ptr, normalized_value)) =
+                player.next_change(num_processed_samples)
+            {
+                apply_change(param_ptr, normalized_value);
+            }
+
+            let mut block = input_block(block_idx);
+            process_block(&params, &mut block);
+            played_output.extend(block);
+            num_processed_samples += BLOCK_SIZE as u64;
+        }
+
+        assert_eq!(recorded_output, played_output);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn file_format() {
+        let path = test_path("file_format");
+
+        let params = Arc::new(TestParams::default());
+        let param_map = make_param_map(&params);
+        let (recorder, sink) = AutomationRecorder::new(
+            path.clone(),
+            "Test Plugin",
+            SAMPLE_RATE,
+            params.clone(),
+            &param_map,
+        );
+        sink.record(128, AutomationSource::Gui, params.gain.as_ptr(), 0.25);
+        recorder.shutdown();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": AUTOMATION_FORMAT_VERSION,
+                "plugin": "Test Plugin",
+                "sample_rate": SAMPLE_RATE,
+                "events": [{
+                    "sample": 128,
+                    "source": "gui",
+                    "param_id": "gain",
+                    "plain_value": 0.25,
+                }],
+            })
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let params = TestParams::default();
+        let param_map = make_param_map(&params);
+        let automation = Automation {
+            version: AUTOMATION_FORMAT_VERSION,
+            plugin: String::from("Test Plugin"),
+            sample_rate: SAMPLE_RATE,
+            events: vec![AutomationEvent {
+                sample: 0,
+                source: AutomationSource::Gui,
+                param_id: String::from("does_not_exist"),
+                plain_value: 0.5,
+            }],
+        };
+
+        // Unknown parameters are skipped
+        let mut player =
+            AutomationPlayer::new(&automation, "Test Plugin", SAMPLE_RATE, &param_map).unwrap();
+        assert!(player.next_change(u64::MAX).is_none());
+
+        assert!(
+            AutomationPlayer::new(&automation, "Other Plugin", SAMPLE_RATE, &param_map).is_err()
+        );
+        let automation = Automation {
+            version: AUTOMATION_FORMAT_VERSION + 1,
+            ..automation
+        };
+        assert!(
+            AutomationPlayer::new(&automation, "Test Plugin", SAMPLE_RATE, &param_map).is_err()
+        );
+    }
+}
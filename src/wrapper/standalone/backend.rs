@@ -12,6 +12,15 @@ pub use crate::buffer::Buffer;
 
 /// An audio+MIDI backend for the standalone wrapper.
 pub trait Backend: 'static + Send + Sync {
+    /// The sample rate the audio device runs at. This is known before the backend starts
+    /// processing audio.
+    fn sample_rate(&self) -> f32;
+
+    /// The number of samples in every block passed to the process callback. The backends always
+    /// call the callback with blocks of exactly this size. This is known before the backend starts
+    /// processing audio.
+    fn period_size(&self) -> u32;
+
     /// Start processing audio and MIDI on this thread. The process callback will be called whenever
     /// there's a new block of audio to be processed. The process callback receives the audio
     /// buffers for the wrapped plugin's outputs. Any inputs will have already been copied to this
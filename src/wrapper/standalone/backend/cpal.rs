@@ -27,6 +27,16 @@ pub struct Cpal {
 }
 
 impl Backend for Cpal {
+    fn sample_rate(&self) -> f32 {
+        self.output_config.sample_rate.0 as f32
+    }
+
+    fn period_size(&self) -> u32 {
+        // The output callback always processes blocks of this size, regardless of how much data
+        // CPAL asks for
+        self.config.period_size
+    }
+
     fn run(
         &mut self,
         cb: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool
@@ -132,17 +142,30 @@ impl Cpal {
     pub fn new<P: Plugin>(config: WrapperConfig, cpal_host_id: cpal::HostId) -> Result<Self> {
         let host = cpal::host_from_id(cpal_host_id).context("The Audio API is unavailable")?;
 
-        if config.input_device.is_none() && P::DEFAULT_INPUT_CHANNELS > 0 {
+        let bus_config = BusConfig {
+            num_input_channels: config.input_channels.unwrap_or(P::DEFAULT_INPUT_CHANNELS),
+            num_output_channels: config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS),
+            // TODO: Support these in the standalone
+            aux_input_busses: AuxiliaryIOConfig::default(),
+            aux_output_busses: AuxiliaryIOConfig::default(),
+        };
+
+        if config.input_device.is_none() && bus_config.num_input_channels > 0 {
             nih_log!(
                 "Audio inputs are not connected automatically to prevent feedback. Use the \
                  '--input-device' option to choose an input device."
             )
         }
+        if config.input_device.is_some() && bus_config.num_input_channels == 0 {
+            nih_log!("The plugin does not have any audio inputs, ignoring the input device.")
+        }
 
-        // No input device is connected unless requested by the user to avoid feedback loops
+        // No input device is connected unless requested by the user to avoid feedback loops.
+        // Generators and instruments without audio inputs never get an input device.
         let input_device = config
             .input_device
             .as_ref()
+            .filter(|_| bus_config.num_input_channels > 0)
             .map(|name| -> Result<Device> {
                 let device = host
                     .input_devices()
@@ -186,13 +209,6 @@ impl Cpal {
                 .context("No default audio output device available")?,
         };
 
-        let bus_config = BusConfig {
-            num_input_channels: config.input_channels.unwrap_or(P::DEFAULT_INPUT_CHANNELS),
-            num_output_channels: config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS),
-            // TODO: Support these in the standalone
-            aux_input_busses: AuxiliaryIOConfig::default(),
-            aux_output_busses: AuxiliaryIOConfig::default(),
-        };
         let requested_sample_rate = cpal::SampleRate(config.sample_rate as u32);
         let requested_buffer_size = cpal::BufferSize::Fixed(config.period_size);
 
@@ -16,6 +16,14 @@ pub struct Dummy {
 }
 
 impl Backend for Dummy {
+    fn sample_rate(&self) -> f32 {
+        self.config.sample_rate
+    }
+
+    fn period_size(&self) -> u32 {
+        self.config.period_size
+    }
+
     fn run(
         &mut self,
         mut cb: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool
@@ -86,3 +94,48 @@ impl Dummy {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    /// The plugin is told that every block has exactly `period_size()` samples, so the backend
+    /// should never deviate from that.
+    #[test]
+    fn fixed_period_size() {
+        const NUM_PERIODS: usize = 8;
+
+        let config =
+            WrapperConfig::parse_from(["test", "--sample-rate", "48000", "--period-size", "128"]);
+        let mut backend = Dummy {
+            bus_config: BusConfig {
+                num_input_channels: 0,
+                num_output_channels: 2,
+                aux_input_busses: AuxiliaryIOConfig::default(),
+                aux_output_busses: AuxiliaryIOConfig::default(),
+            },
+            config,
+        };
+        assert_eq!(backend.period_size(), 128);
+
+        let (block_sizes_sender, block_sizes_receiver) = std::sync::mpsc::channel();
+        let mut num_periods = 0;
+        backend.run(move |buffer, transport, _, _| {
+            block_sizes_sender
+                .send((buffer.len(), transport.pos_samples))
+                .unwrap();
+
+            num_periods += 1;
+            num_periods < NUM_PERIODS
+        });
+
+        let block_sizes: Vec<_> = block_sizes_receiver.try_iter().collect();
+        assert_eq!(block_sizes.len(), NUM_PERIODS);
+        for (period_idx, (block_size, pos_samples)) in block_sizes.into_iter().enumerate() {
+            assert_eq!(block_size, 128);
+            assert_eq!(pos_samples, Some(period_idx as i64 * 128));
+        }
+    }
+}
@@ -14,6 +14,11 @@ use crate::buffer::Buffer;
 use crate::context::process::Transport;
 use crate::midi::{MidiConfig, NoteEvent};
 use crate::plugin::Plugin;
+use crate::wrapper::util::{channel_name, main_input_name, main_output_name};
+
+/// JACK's `JACK_PORT_NAME_SIZE`. A port's full name, including the client name, the colon
+/// separating the two, and the null terminator, cannot be longer than this many bytes.
+const JACK_PORT_NAME_SIZE: usize = 256;
 
 /// Uses JACK audio and MIDI.
 pub struct Jack {
@@ -21,6 +26,10 @@ pub struct Jack {
     /// The JACK client, wrapped in an option since it needs to be transformed into an `AsyncClient`
     /// and then back into a regular `Client`.
     client: Option<Client>,
+    /// The JACK server's sample rate. This cannot be changed while the server is running.
+    sample_rate: f32,
+    /// The JACK server's buffer size. Changing this while the client is running is not supported.
+    buffer_size: u32,
 
     inputs: Arc<Vec<Port<AudioIn>>>,
     outputs: Arc<Mutex<Vec<Port<AudioOut>>>>,
@@ -29,6 +38,14 @@ pub struct Jack {
 }
 
 impl Backend for Jack {
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn period_size(&self) -> u32 {
+        self.buffer_size
+    }
+
     fn run(
         &mut self,
         mut cb: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool
@@ -36,7 +53,7 @@ impl Backend for Jack {
             + Send,
     ) {
         let client = self.client.take().unwrap();
-        let buffer_size = client.buffer_size();
+        let buffer_size = self.buffer_size;
 
         let mut buffer = Buffer::default();
         unsafe {
@@ -191,10 +208,19 @@ impl Jack {
             )
         }
 
+        // Ports use the plugin's port names if it has any, and the old `input_1` style names
+        // otherwise
         let mut inputs = Vec::new();
         let num_input_channels = config.input_channels.unwrap_or(P::DEFAULT_INPUT_CHANNELS);
-        for port_no in 1..num_input_channels + 1 {
-            inputs.push(client.register_port(&format!("input_{port_no}"), AudioIn)?);
+        for channel_idx in 0..num_input_channels as usize {
+            let port_name = match P::PORT_NAMES.main_input {
+                Some(_) => {
+                    channel_port_name(main_input_name::<P>(), channel_idx, num_input_channels)
+                }
+                None => format!("input_{}", channel_idx + 1),
+            };
+            let port_name = truncate_port_name(client.name(), &port_name);
+            inputs.push(client.register_port(port_name, AudioIn)?);
         }
 
         // We can't immediately connect the outputs. Or well we can with PipeWire, but JACK2 says
@@ -202,8 +228,15 @@ impl Jack {
         // above.
         let mut outputs = Vec::new();
         let num_output_channels = config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS);
-        for port_no in 1..num_output_channels + 1 {
-            outputs.push(client.register_port(&format!("output_{port_no}"), AudioOut)?);
+        for channel_idx in 0..num_output_channels as usize {
+            let port_name = match P::PORT_NAMES.main_output {
+                Some(_) => {
+                    channel_port_name(main_output_name::<P>(), channel_idx, num_output_channels)
+                }
+                None => format!("output_{}", channel_idx + 1),
+            };
+            let port_name = truncate_port_name(client.name(), &port_name);
+            outputs.push(client.register_port(port_name, AudioOut)?);
         }
 
         let midi_input = if P::MIDI_INPUT >= MidiConfig::Basic {
@@ -222,6 +255,8 @@ impl Jack {
 
         Ok(Self {
             config,
+            sample_rate: client.sample_rate() as f32,
+            buffer_size: client.buffer_size(),
             client: Some(client),
 
             inputs: Arc::new(inputs),
@@ -284,3 +319,71 @@ impl Jack {
         Ok(())
     }
 }
+
+/// The JACK port name for a channel of a named plugin port, e.g. `Sidechain In L`. JACK port names
+/// are usually lowercase, but they can contain any character except for colons.
+fn channel_port_name(port_name: &str, channel_idx: usize, num_channels: u32) -> String {
+    channel_name(
+        &port_name.replace(':', "_"),
+        channel_idx,
+        num_channels as usize,
+    )
+}
+
+/// Truncate a port name so the full `client_name:port_name` name fits in JACK's port name limit.
+/// This never splits up a UTF-8 code point.
+fn truncate_port_name<'a>(client_name: &str, port_name: &'a str) -> &'a str {
+    // The colon and the null terminator also count towards the limit
+    let max_len = JACK_PORT_NAME_SIZE.saturating_sub(client_name.len() + 2);
+    if port_name.len() <= max_len {
+        return port_name;
+    }
+
+    let mut truncated_len = max_len;
+    while !port_name.is_char_boundary(truncated_len) {
+        truncated_len -= 1;
+    }
+
+    &port_name[..truncated_len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_port_names() {
+        assert_eq!(channel_port_name("Sidechain In", 0, 1), "Sidechain In");
+        assert_eq!(channel_port_name("Wet Out", 1, 2), "Wet Out R");
+        assert_eq!(channel_port_name("Surround", 4, 6), "Surround 5");
+        assert_eq!(channel_port_name("A:B", 0, 1), "A_B");
+    }
+
+    #[test]
+    fn port_name_fits() {
+        assert_eq!(truncate_port_name("Plugin", "Output L"), "Output L");
+
+        let port_name = "x".repeat(JACK_PORT_NAME_SIZE - "Plugin".len() - 2);
+        assert_eq!(truncate_port_name("Plugin", &port_name), port_name);
+    }
+
+    #[test]
+    fn port_name_truncated() {
+        let port_name = "x".repeat(JACK_PORT_NAME_SIZE);
+        let truncated = truncate_port_name("Plugin", &port_name);
+        assert_eq!(
+            "Plugin".len() + 1 + truncated.len() + 1,
+            JACK_PORT_NAME_SIZE
+        );
+    }
+
+    #[test]
+    fn port_name_truncated_at_char_boundary() {
+        // Every `ł` takes up two bytes, so the limit ends up in the middle of a character
+        let client_name = "Plugin";
+        let port_name = "ł".repeat(JACK_PORT_NAME_SIZE);
+        let truncated = truncate_port_name(client_name, &port_name);
+        assert!(client_name.len() + 1 + truncated.len() + 1 <= JACK_PORT_NAME_SIZE);
+        assert_eq!(truncated.chars().count(), (JACK_PORT_NAME_SIZE - 8) / 2);
+    }
+}
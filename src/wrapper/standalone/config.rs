@@ -1,6 +1,12 @@
 use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 /// Configuration for a standalone plugin that would normally be provided by the DAW.
+///
+/// The audio, MIDI, and editor settings are remembered between runs in a session file, see
+/// [`Session`][super::session::Session]. Options passed on the command line always take precedence
+/// over the stored values.
 #[derive(Debug, Clone, Parser)]
 #[clap(about = None, long_about = None)]
 pub struct WrapperConfig {
@@ -41,6 +47,14 @@ pub struct WrapperConfig {
     /// This setting is ignored when using the JACK backend.
     #[clap(value_parser, short = 'p', long, default_value = "512")]
     pub period_size: u32,
+    /// Initialize the plugin at this sample rate instead of at the audio backend's sample rate, and
+    /// resample the audio going to and coming from the plugin.
+    ///
+    /// This can be used to run plugins that don't support the audio device's sample rate. The
+    /// plugin then always processes blocks of exactly '--period-size' samples. The added latency
+    /// is printed at startup.
+    #[clap(value_parser, long)]
+    pub force_sample_rate: Option<f32>,
 
     /// If set to a port name ('foo:bar_1'), then all all inputs will be connected to that port. If
     /// the option is set to a comma separated list of port names ('foo:bar_1,foo:bar_2') then the
@@ -63,6 +77,10 @@ pub struct WrapperConfig {
     #[clap(value_parser, long)]
     pub connect_jack_midi_output: Option<String>,
 
+    /// Print the plugin's audio and MIDI ports and their channel names, and then exit.
+    #[clap(value_parser, long)]
+    pub dump_io: bool,
+
     /// The editor's DPI scaling factor.
     ///
     /// This option is ignored on macOS.
@@ -81,10 +99,45 @@ pub struct WrapperConfig {
     /// The time signature's denominator.
     #[clap(value_parser, long, default_value = "4")]
     pub timesig_denom: u32,
+
+    /// If set, then the plugin's output can be recorded to time-stamped WAV files in this
+    /// directory.
+    ///
+    /// Press Enter in the terminal to start or stop recording. The plugin's editor may also offer
+    /// a way to control this.
+    #[clap(value_parser, long)]
+    pub record_dir: Option<PathBuf>,
+    /// Also record the plugin's dry input to a second file with a '_dry' suffix.
+    ///
+    /// This option is only used together with '--record-dir'.
+    #[clap(value_parser, long)]
+    pub record_dry: bool,
+
+    /// Record every parameter change made from the plugin's editor to this JSON file, along with
+    /// the sample position at which it happened.
+    ///
+    /// The file can be played back again with '--play-automation'.
+    #[clap(value_parser, long)]
+    pub record_automation: Option<PathBuf>,
+    /// Play back the parameter changes from an automation file recorded with
+    /// '--record-automation'.
+    ///
+    /// The changes are applied at the same sample positions they were recorded at, counted from
+    /// the moment the audio stream started.
+    #[clap(value_parser, long)]
+    pub play_automation: Option<PathBuf>,
+
+    /// Ignore the session file with the settings and the plugin state from the last run.
+    ///
+    /// The plugin starts with its default state. The session file is still overwritten when the
+    /// application exits.
+    #[clap(value_parser, long)]
+    pub fresh: bool,
 }
 
 /// Determines which audio and MIDI backend should be used.
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum BackendType {
     /// Automatically pick the backend depending on what's available.
     ///
@@ -1,16 +1,29 @@
+use atomic_refcell::AtomicRefCell;
 use crossbeam::channel;
 use std::sync::Arc;
+use std::time::Instant;
 
 use super::backend::Backend;
 use super::wrapper::{GuiTask, Wrapper};
-use crate::context::gui::GuiContext;
+use crate::context::gui::{ChannelColor, ChannelInfo, GuiContext};
 use crate::context::init::InitContext;
 use crate::context::process::{ProcessContext, Transport};
+use crate::context::repaint::RepaintPolicy;
 use crate::context::PluginApi;
 use crate::event_loop::EventLoop;
 use crate::midi::NoteEvent;
+use crate::params::changes::{ParamChange, ParamChangeSource, ParamChangeSubscription};
 use crate::params::internals::ParamPtr;
-use crate::plugin::Plugin;
+use crate::params::ParamFlags;
+use crate::plugin::{BypassMode, Plugin};
+
+/// The color reported through [`GuiContext::channel_info()`], since there is no host to pick one.
+const STANDALONE_CHANNEL_COLOR: ChannelColor = ChannelColor {
+    r: 0x80,
+    g: 0x80,
+    b: 0x80,
+    a: 0xff,
+};
 
 /// An [`InitContext`] implementation for the standalone wrapper.
 pub(crate) struct WrapperInitContext<'a, P: Plugin, B: Backend> {
@@ -40,6 +53,19 @@ pub(crate) struct WrapperGuiContext<P: Plugin, B: Backend> {
     /// This allows us to send tasks to the parent view that will be handled at the start of its
     /// next frame.
     pub(super) gui_task_sender: channel::Sender<GuiTask>,
+    /// The parameter changes made during the current batch, if there is one. See
+    /// [`GuiContext::raw_begin_batch()`].
+    pub(super) batch: AtomicRefCell<ParamBatch>,
+}
+
+/// The state for a batch of parameter changes made from the GUI.
+#[derive(Default)]
+pub(crate) struct ParamBatch {
+    /// The number of nested batches that are currently active. Changes are only sent when the
+    /// outermost batch ends.
+    depth: usize,
+    /// The `(param, normalized_value)` pairs set during this batch, in the order they were made.
+    changes: Vec<(ParamPtr, f32)>,
 }
 
 impl<P: Plugin, B: Backend> InitContext<P> for WrapperInitContext<'_, P, B> {
@@ -55,6 +81,11 @@ impl<P: Plugin, B: Backend> InitContext<P> for WrapperInitContext<'_, P, B> {
         nih_debug_assert_failure!("TODO: WrapperInitContext::set_latency_samples()");
     }
 
+    fn max_expected_sample_rate(&self) -> f32 {
+        // The sample rate cannot change while the standalone is running
+        self.wrapper.buffer_config.sample_rate
+    }
+
     fn set_current_voice_capacity(&self, _capacity: u32) {
         // This is only supported by CLAP
     }
@@ -66,11 +97,16 @@ impl<P: Plugin, B: Backend> ProcessContext<P> for WrapperProcessContext<'_, P, B
     }
 
     fn execute_background(&self, task: P::BackgroundTask) {
-        let task_posted = self.wrapper.event_loop.schedule_background(task);
+        nih_debug_assert_audio_thread!();
+
+        let priority = P::background_task_priority(&task);
+        let task_posted = self.wrapper.event_loop.schedule_background(task, priority);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
 
     fn execute_gui(&self, task: P::BackgroundTask) {
+        nih_debug_assert_audio_thread!();
+
         let task_posted = self.wrapper.event_loop.schedule_gui(task);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
@@ -80,6 +116,23 @@ impl<P: Plugin, B: Backend> ProcessContext<P> for WrapperProcessContext<'_, P, B
         &self.transport
     }
 
+    fn deterministic_seed(&self) -> Option<u64> {
+        // The standalone wrapper always processes audio in realtime
+        None
+    }
+
+    fn bypassed(&self) -> bool {
+        self.wrapper.bypass_param_ptr.map_or(
+            false,
+            |ptr| unsafe { ptr.modulated_normalized_value() } >= 0.5,
+        )
+    }
+
+    fn bypass_mode(&self) -> BypassMode {
+        // The standalone wrapper always processes audio in realtime
+        BypassMode::Soft
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
         // We'll pretend we're a queue, choo choo
         if self.input_events_idx < self.input_events.len() {
@@ -96,6 +149,10 @@ impl<P: Plugin, B: Backend> ProcessContext<P> for WrapperProcessContext<'_, P, B
         self.output_events.push(event);
     }
 
+    fn request_editor_repaint(&self) {
+        self.wrapper.editor_repaint.request();
+    }
+
     fn set_latency_samples(&self, _samples: u32) {
         nih_debug_assert_failure!("TODO: WrapperProcessContext::set_latency_samples()");
     }
@@ -103,6 +160,27 @@ impl<P: Plugin, B: Backend> ProcessContext<P> for WrapperProcessContext<'_, P, B
     fn set_current_voice_capacity(&self, _capacity: u32) {
         // This is only supported by CLAP
     }
+
+    unsafe fn raw_set_output_parameter(&mut self, param: ParamPtr, normalized: f32) {
+        if !param.flags().contains(ParamFlags::READ_ONLY) {
+            nih_debug_assert_failure!(
+                "Only read-only parameters can be set from the process function: {:?}",
+                param
+            );
+            return;
+        }
+        if !self.wrapper.known_parameters.contains(&param) {
+            nih_debug_assert_failure!("Unknown parameter: {:?}", param);
+            return;
+        }
+
+        // There's no host to send the new value to
+        param.set_normalized_value(normalized);
+        param.update_smoother(self.transport.sample_rate, false);
+        self.wrapper
+            .param_changes
+            .record(param, ParamChangeSource::Internal, normalized);
+    }
 }
 
 impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
@@ -111,8 +189,15 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
     }
 
     fn request_resize(&self) -> bool {
-        let (unscaled_width, unscaled_height) =
-            self.wrapper.editor.borrow().as_ref().unwrap().lock().size();
+        // The editor may resize itself in response to a parameter change while its window is
+        // closed, and there's nothing to resize at that point
+        if !self.wrapper.editor_lifecycle.is_open() {
+            return false;
+        }
+        let (unscaled_width, unscaled_height) = match self.wrapper.editor.borrow().as_ref() {
+            Some(editor) => editor.lock().size(),
+            None => return false,
+        };
 
         // This will cause the editor to be resized at the start of the next frame
         let push_successful = self
@@ -124,16 +209,53 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
         true
     }
 
+    fn request_repaint(&self) {
+        // The editor may live on its own thread, so this goes through the wrapper's event loop just
+        // like resize requests. A full queue means the event loop is not running, so the request
+        // can safely be dropped.
+        let _ = self.gui_task_sender.try_send(GuiTask::Repaint);
+    }
+
+    fn set_repaint_policy(&self, policy: RepaintPolicy) {
+        self.wrapper.editor_repaint.set_policy(policy);
+    }
+
+    fn poll_repaint(&self) -> bool {
+        self.wrapper.editor_repaint.poll(Instant::now())
+    }
+
     unsafe fn raw_begin_set_parameter(&self, _param: ParamPtr) {
         // Since there's no automation being recorded here, gestures don't mean anything
     }
 
     unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
-        self.wrapper.set_parameter(param, normalized);
+        let mut batch = self.batch.borrow_mut();
+        if batch.depth > 0 {
+            batch.changes.push((param, normalized));
+        } else {
+            self.wrapper.set_parameter(param, normalized);
+        }
     }
 
     unsafe fn raw_end_set_parameter(&self, _param: ParamPtr) {}
 
+    fn raw_begin_batch(&self) {
+        self.batch.borrow_mut().depth += 1;
+    }
+
+    fn raw_end_batch(&self) {
+        let mut batch = self.batch.borrow_mut();
+        if batch.depth == 0 {
+            nih_debug_assert_failure!("Ended a parameter batch without starting one");
+            return;
+        }
+
+        batch.depth -= 1;
+        if batch.depth == 0 {
+            self.wrapper.set_parameters_batched(batch.changes.drain(..));
+        }
+    }
+
     fn get_state(&self) -> crate::wrapper::state::PluginState {
         self.wrapper.get_state_object()
     }
@@ -141,4 +263,52 @@ impl<P: Plugin, B: Backend> GuiContext for WrapperGuiContext<P, B> {
     fn set_state(&self, state: crate::wrapper::state::PluginState) {
         self.wrapper.set_state_object(state)
     }
+
+    fn set_recording(&self, recording: bool) -> bool {
+        match &self.wrapper.recorder {
+            Some(recorder) if recording != recorder.is_recording() => {
+                if recording {
+                    if let Err(err) = recorder.start() {
+                        nih_error!("Could not start recording: {err:#}");
+                    }
+                } else {
+                    recorder.stop();
+                }
+
+                recorder.is_recording()
+            }
+            Some(recorder) => recorder.is_recording(),
+            None => false,
+        }
+    }
+
+    fn is_recording(&self) -> bool {
+        self.wrapper
+            .recorder
+            .as_ref()
+            .map_or(false, |recorder| recorder.is_recording())
+    }
+
+    fn last_param_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        self.wrapper.param_changes.last_change(param)
+    }
+
+    fn subscribe_param_changes(&self) -> ParamChangeSubscription {
+        ParamChangeSubscription::new(self.wrapper.param_changes.clone())
+    }
+
+    fn channel_info(&self) -> Option<ChannelInfo> {
+        // There is no host track, so the standalone binary acts as its own channel
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from(P::NAME));
+
+        Some(ChannelInfo {
+            name: Some(name),
+            color: Some(STANDALONE_CHANNEL_COLOR),
+            index: None,
+            change_count: 0,
+        })
+    }
 }
@@ -0,0 +1,667 @@
+//! Records the standalone wrapper's output to WAV files. The audio thread only ever writes samples
+//! to a wait-free ring buffer, and a dedicated disk thread takes those samples out of the ring
+//! buffer and writes them to disk.
+
+use anyhow::{Context, Result};
+use crossbeam::channel;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// How long the ring buffers between the audio thread and the disk thread are, in seconds of audio.
+/// If the disk thread cannot keep up for longer than this, then samples will be dropped.
+const RING_BUFFER_LENGTH_SECONDS: f32 = 2.0;
+/// How often the disk thread empties the ring buffers when there are no other commands.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the WAV files' headers get updated. This makes sure that the file is still valid and
+/// contains everything up to the last flush when the process gets killed, for instance with Ctrl+C.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+type WavWriter = hound::WavWriter<BufWriter<File>>;
+
+/// Records the standalone wrapper's output, and optionally its dry input, to time-stamped WAV
+/// files. The audio thread pushes samples through a [`RecorderSink`] obtained from
+/// [`Recorder::new()`]. Recording can be started and stopped from any thread.
+pub struct Recorder {
+    /// The directory new takes are written to.
+    record_dir: PathBuf,
+    /// Prefixed to the file name of every take.
+    name: String,
+    /// Whether the dry input should be recorded to a second file.
+    record_dry: bool,
+    num_channels: u16,
+    /// The sample rate used when the audio thread has not processed any audio yet.
+    fallback_sample_rate: f32,
+
+    /// State shared with the audio thread's [`RecorderSink`] and the disk thread.
+    shared: Arc<Shared>,
+    /// The path to the output file of the current take, if we're recording.
+    current_take: Mutex<Option<PathBuf>>,
+
+    command_sender: channel::Sender<Command>,
+    /// The disk thread. This is taken out of the mutex when shutting down.
+    disk_thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// The audio thread's side of a [`Recorder`]. This never blocks or allocates.
+pub struct RecorderSink {
+    shared: Arc<Shared>,
+    output_producer: rtrb::Producer<f32>,
+    dry_producer: Option<rtrb::Producer<f32>>,
+}
+
+struct Shared {
+    /// Set by the disk thread once it's ready to receive samples for a new take, and cleared again
+    /// when recording stops.
+    recording: AtomicBool,
+    /// The sample rate the audio thread is running at, stored as `f32` bits. Zero if not yet known.
+    sample_rate: AtomicU32,
+    /// The total number of samples that could not be written to the ring buffers because the disk
+    /// thread could not keep up.
+    dropped_samples: AtomicU64,
+}
+
+/// A message sent to the disk thread.
+enum Command {
+    /// Start writing samples from the ring buffers to these writers. Any current take is finalized
+    /// first.
+    Start {
+        output: WavWriter,
+        dry: Option<WavWriter>,
+    },
+    /// Finalize the current take, if there is one.
+    Stop,
+    /// Finalize the current take and terminate the disk thread.
+    Shutdown,
+}
+
+impl Recorder {
+    /// Create a recorder that writes takes for `num_channels` channels of audio to `record_dir`,
+    /// and spawn its disk thread. The returned [`RecorderSink`] should be moved to the audio
+    /// thread. `sample_rate` is used to size the ring buffers, and as the file's sample rate if
+    /// recording starts before the audio thread has processed any audio.
+    pub fn new(
+        record_dir: PathBuf,
+        name: &str,
+        record_dry: bool,
+        num_channels: u16,
+        sample_rate: f32,
+    ) -> (Self, RecorderSink) {
+        let capacity = (sample_rate * RING_BUFFER_LENGTH_SECONDS).ceil() as usize;
+
+        Self::with_capacity(
+            record_dir,
+            name,
+            record_dry,
+            num_channels,
+            sample_rate,
+            capacity,
+        )
+    }
+
+    /// The same as [`new()`][Self::new()], but with ring buffers that can hold `capacity_frames`
+    /// frames of audio.
+    fn with_capacity(
+        record_dir: PathBuf,
+        name: &str,
+        record_dry: bool,
+        num_channels: u16,
+        sample_rate: f32,
+        capacity_frames: usize,
+    ) -> (Self, RecorderSink) {
+        let shared = Arc::new(Shared {
+            recording: AtomicBool::new(false),
+            sample_rate: AtomicU32::new(0),
+            dropped_samples: AtomicU64::new(0),
+        });
+
+        let capacity = capacity_frames * num_channels as usize;
+        let (output_producer, output_consumer) = rtrb::RingBuffer::new(capacity);
+        let (dry_producer, dry_consumer) = if record_dry {
+            let (producer, consumer) = rtrb::RingBuffer::new(capacity);
+            (Some(producer), Some(consumer))
+        } else {
+            (None, None)
+        };
+
+        let (command_sender, command_receiver) = channel::unbounded();
+        let disk_thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name(String::from("recorder"))
+                .spawn(move || {
+                    run_disk_thread(shared, command_receiver, output_consumer, dry_consumer)
+                })
+                .expect("Could not spawn the recorder's disk thread")
+        };
+
+        let name = name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+
+        (
+            Self {
+                record_dir,
+                name,
+                record_dry,
+                num_channels,
+                fallback_sample_rate: sample_rate,
+
+                shared: shared.clone(),
+                current_take: Mutex::new(None),
+
+                command_sender,
+                disk_thread: Mutex::new(Some(disk_thread)),
+            },
+            RecorderSink {
+                shared,
+                output_producer,
+                dry_producer,
+            },
+        )
+    }
+
+    /// Whether a take is currently being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.current_take.lock().is_some()
+    }
+
+    /// The total number of samples that had to be dropped because the disk thread could not keep
+    /// up with the audio thread.
+    pub fn dropped_samples(&self) -> u64 {
+        self.shared.dropped_samples.load(Ordering::Relaxed)
+    }
+
+    /// Start recording a new take, finalizing the current take first if there is one. Returns the
+    /// path to the new take's output file.
+    pub fn start(&self) -> Result<PathBuf> {
+        let mut current_take = self.current_take.lock();
+
+        let sample_rate = match f32::from_bits(self.shared.sample_rate.load(Ordering::Relaxed)) {
+            sample_rate if sample_rate > 0.0 => sample_rate,
+            _ => self.fallback_sample_rate,
+        };
+        let spec = hound::WavSpec {
+            channels: self.num_channels,
+            sample_rate: sample_rate.round() as u32,
+            bits_per_sample: 32,
+            sample_format: hound::SampleFormat::Float,
+        };
+
+        std::fs::create_dir_all(&self.record_dir)
+            .with_context(|| format!("Could not create '{}'", self.record_dir.display()))?;
+        let (output_path, dry_path) = self.new_take_paths();
+        let output = create_writer(&output_path, spec)?;
+        let dry = match &dry_path {
+            Some(dry_path) => Some(create_writer(dry_path, spec)?),
+            None => None,
+        };
+
+        self.command_sender
+            .send(Command::Start { output, dry })
+            .context("The recorder's disk thread is no longer running")?;
+
+        match &dry_path {
+            Some(dry_path) => nih_log!(
+                "Recording to '{}' and '{}'",
+                output_path.display(),
+                dry_path.display()
+            ),
+            None => nih_log!("Recording to '{}'", output_path.display()),
+        }
+        *current_take = Some(output_path.clone());
+
+        Ok(output_path)
+    }
+
+    /// Stop recording and finalize the current take. Does nothing if we're not recording.
+    pub fn stop(&self) {
+        let mut current_take = self.current_take.lock();
+        if let Some(output_path) = current_take.take() {
+            // The audio thread should stop sending samples right away, the disk thread will write
+            // everything that's still in the ring buffer before finalizing the file
+            self.shared.recording.store(false, Ordering::SeqCst);
+            let _ = self.command_sender.send(Command::Stop);
+
+            nih_log!("Stopped recording to '{}'", output_path.display());
+        }
+    }
+
+    /// Start recording if we're not recording, or stop recording if we are. Returns whether we're
+    /// recording after this call.
+    pub fn toggle(&self) -> bool {
+        if self.is_recording() {
+            self.stop();
+            false
+        } else {
+            match self.start() {
+                Ok(_) => true,
+                Err(err) => {
+                    nih_error!("Could not start recording: {err:#}");
+                    false
+                }
+            }
+        }
+    }
+
+    /// Finalize the current take and wait for the disk thread to terminate. This is also done when
+    /// the recorder is dropped.
+    pub fn shutdown(&self) {
+        self.stop();
+
+        if let Some(disk_thread) = self.disk_thread.lock().take() {
+            let _ = self.command_sender.send(Command::Shutdown);
+            if disk_thread.join().is_err() {
+                nih_error!("The recorder's disk thread panicked");
+            }
+        }
+    }
+
+    /// Generate unique file names for a new take based on the current (UTC) time.
+    fn new_take_paths(&self) -> (PathBuf, Option<PathBuf>) {
+        let timestamp = format_timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        );
+
+        // Starting multiple takes within the same second should not overwrite the previous take
+        let mut take_name = format!("{}_{}", self.name, timestamp);
+        let mut suffix = 2;
+        while self.record_dir.join(format!("{take_name}.wav")).exists() {
+            take_name = format!("{}_{}_{}", self.name, timestamp, suffix);
+            suffix += 1;
+        }
+
+        (
+            self.record_dir.join(format!("{take_name}.wav")),
+            if self.record_dry {
+                Some(self.record_dir.join(format!("{take_name}_dry.wav")))
+            } else {
+                None
+            },
+        )
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+impl RecorderSink {
+    /// Record the dry input for the current block. This should be called with the buffer's
+    /// contents before the plugin processes it. Does nothing if the dry input is not being
+    /// recorded.
+    pub fn write_dry(&mut self, channels: &[&mut [f32]]) {
+        if let Some(dry_producer) = &mut self.dry_producer {
+            Self::write(&self.shared, dry_producer, channels);
+        }
+    }
+
+    /// Record the plugin's output for the current block. `sample_rate` is the audio stream's
+    /// current sample rate, which will be used for new takes.
+    pub fn write_output(&mut self, channels: &[&mut [f32]], sample_rate: f32) {
+        self.shared
+            .sample_rate
+            .store(sample_rate.to_bits(), Ordering::Relaxed);
+
+        Self::write(&self.shared, &mut self.output_producer, channels);
+    }
+
+    /// Write interleaved samples to a ring buffer. If the entire block doesn't fit, then the block
+    /// is dropped and counted as dropped samples. Partial blocks are never written so the channels
+    /// will always stay aligned.
+    fn write(shared: &Shared, producer: &mut rtrb::Producer<f32>, channels: &[&mut [f32]]) {
+        if !shared.recording.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let num_samples = channels.first().map_or(0, |channel| channel.len());
+        let num_values = num_samples * channels.len();
+        if producer.slots() < num_values {
+            shared
+                .dropped_samples
+                .fetch_add(num_values as u64, Ordering::Relaxed);
+            return;
+        }
+
+        for sample_idx in 0..num_samples {
+            for channel in channels {
+                // This cannot fail because of the check above, and there's only a single producer
+                let _ = producer.push(channel[sample_idx]);
+            }
+        }
+    }
+}
+
+/// The disk thread's main loop. Takes samples out of the ring buffers and writes them to the
+/// current take's files until a [`Command::Shutdown`] is received or the recorder is dropped.
+fn run_disk_thread(
+    shared: Arc<Shared>,
+    command_receiver: channel::Receiver<Command>,
+    mut output_consumer: rtrb::Consumer<f32>,
+    mut dry_consumer: Option<rtrb::Consumer<f32>>,
+) {
+    let mut output_writer: Option<WavWriter> = None;
+    let mut dry_writer: Option<WavWriter> = None;
+    let mut last_flush = Instant::now();
+    let mut logged_dropped_samples = 0;
+
+    loop {
+        let command = match command_receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(command) => Some(command),
+            Err(channel::RecvTimeoutError::Timeout) => None,
+            Err(channel::RecvTimeoutError::Disconnected) => Some(Command::Shutdown),
+        };
+
+        // Anything that's still in the ring buffers belongs to the current take
+        drain(&mut output_consumer, &mut output_writer);
+        if let Some(dry_consumer) = &mut dry_consumer {
+            drain(dry_consumer, &mut dry_writer);
+        }
+
+        let dropped_samples = shared.dropped_samples.load(Ordering::Relaxed);
+        if dropped_samples > logged_dropped_samples {
+            nih_log!(
+                "The recorder could not keep up, {} samples have been dropped",
+                dropped_samples - logged_dropped_samples
+            );
+            logged_dropped_samples = dropped_samples;
+        }
+
+        match command {
+            Some(Command::Start { output, dry }) => {
+                finalize(output_writer.take());
+                finalize(dry_writer.take());
+
+                // In the unlikely case that the audio thread was still writing a block when the
+                // last take was stopped, part of that block may still be in the ring buffer
+                discard(&mut output_consumer);
+                if let Some(dry_consumer) = &mut dry_consumer {
+                    discard(dry_consumer);
+                }
+
+                output_writer = Some(output);
+                dry_writer = dry;
+                last_flush = Instant::now();
+                shared.recording.store(true, Ordering::SeqCst);
+            }
+            Some(Command::Stop) => {
+                shared.recording.store(false, Ordering::SeqCst);
+                finalize(output_writer.take());
+                finalize(dry_writer.take());
+            }
+            Some(Command::Shutdown) => {
+                shared.recording.store(false, Ordering::SeqCst);
+                finalize(output_writer.take());
+                finalize(dry_writer.take());
+                break;
+            }
+            None if last_flush.elapsed() >= FLUSH_INTERVAL => {
+                for writer in output_writer.iter_mut().chain(dry_writer.iter_mut()) {
+                    if let Err(err) = writer.flush() {
+                        nih_error!("Could not write to the recording: {err}");
+                    }
+                }
+                last_flush = Instant::now();
+            }
+            None => (),
+        }
+    }
+}
+
+fn create_writer(path: &Path, spec: hound::WavSpec) -> Result<WavWriter> {
+    hound::WavWriter::create(path, spec)
+        .with_context(|| format!("Could not create '{}'", path.display()))
+}
+
+/// Write all samples from a ring buffer to a writer. The samples are discarded if there is no
+/// writer.
+fn drain(consumer: &mut rtrb::Consumer<f32>, writer: &mut Option<WavWriter>) {
+    match writer {
+        Some(writer) => {
+            while let Ok(sample) = consumer.pop() {
+                if let Err(err) = writer.write_sample(sample) {
+                    nih_error!("Could not write to the recording: {err}");
+                    discard(consumer);
+                    break;
+                }
+            }
+        }
+        None => discard(consumer),
+    }
+}
+
+/// Discard everything that's currently in a ring buffer.
+fn discard(consumer: &mut rtrb::Consumer<f32>) {
+    while consumer.pop().is_ok() {}
+}
+
+/// Update a writer's header and close the file.
+fn finalize(writer: Option<WavWriter>) {
+    if let Some(writer) = writer {
+        if let Err(err) = writer.finalize() {
+            nih_error!("Could not finalize the recording: {err}");
+        }
+    }
+}
+
+/// Format a UNIX timestamp as `YYYY-MM-DD_HH-MM-SS` in UTC, for use in file names.
+fn format_timestamp(unix_seconds: u64) -> String {
+    let days = (unix_seconds / 86_400) as i64;
+    let seconds_of_day = unix_seconds % 86_400;
+
+    // Converts the number of days since 1970-01-01 to a proleptic Gregorian calendar date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUM_CHANNELS: u16 = 2;
+    const BLOCK_SIZE: usize = 64;
+    const SAMPLE_RATE: f32 = 48_000.0;
+
+    /// A unique empty directory for a single test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("nih_plug_recorder_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        dir
+    }
+
+    /// Wait until the disk thread has picked up the new take.
+    fn wait_for_recording(recorder: &Recorder) {
+        let start = Instant::now();
+        while !recorder.shared.recording.load(Ordering::SeqCst) {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// A block where the left channel counts up from `block_idx * BLOCK_SIZE` and the right
+    /// channel contains the same values negated.
+    fn make_block(block_idx: usize) -> Vec<Vec<f32>> {
+        let left: Vec<f32> = (0..BLOCK_SIZE)
+            .map(|sample_idx| (block_idx * BLOCK_SIZE + sample_idx) as f32)
+            .collect();
+        let right = left.iter().map(|sample| -sample).collect();
+
+        vec![left, right]
+    }
+
+    fn write_block(sink: &mut RecorderSink, block_idx: usize, sample_rate: f32) {
+        let mut block = make_block(block_idx);
+        let channels: Vec<&mut [f32]> = block.iter_mut().map(|c| c.as_mut_slice()).collect();
+        sink.write_output(&channels, sample_rate);
+    }
+
+    fn read_samples(path: &Path) -> (hound::WavSpec, Vec<f32>) {
+        let mut reader = hound::WavReader::open(path).unwrap();
+        let samples = reader
+            .samples::<f32>()
+            .map(|sample| sample.unwrap())
+            .collect();
+
+        (reader.spec(), samples)
+    }
+
+    #[test]
+    fn records_output() {
+        let dir = test_dir("records_output");
+        let (recorder, mut sink) =
+            Recorder::new(dir.clone(), "Test Plugin", false, NUM_CHANNELS, SAMPLE_RATE);
+
+        // Nothing should be recorded before recording starts
+        write_block(&mut sink, 0, 44_100.0);
+
+        let path = recorder.start().unwrap();
+        assert!(recorder.is_recording());
+        assert!(path.starts_with(&dir));
+        assert!(path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("Test_Plugin_"));
+        wait_for_recording(&recorder);
+
+        for block_idx in 0..4 {
+            write_block(&mut sink, block_idx, 44_100.0);
+        }
+        recorder.stop();
+        assert!(!recorder.is_recording());
+        recorder.shutdown();
+
+        let (spec, samples) = read_samples(&path);
+        assert_eq!(spec.channels, NUM_CHANNELS);
+        // The sample rate is taken from the audio thread if it has processed any audio
+        assert_eq!(spec.sample_rate, 44_100);
+        assert_eq!(spec.sample_format, hound::SampleFormat::Float);
+        assert_eq!(samples.len(), 4 * BLOCK_SIZE * NUM_CHANNELS as usize);
+        for (frame_idx, frame) in samples.chunks_exact(NUM_CHANNELS as usize).enumerate() {
+            assert_eq!(frame, [frame_idx as f32, -(frame_idx as f32)]);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn records_dry_input() {
+        let dir = test_dir("records_dry_input");
+        let (recorder, mut sink) =
+            Recorder::new(dir.clone(), "dry", true, NUM_CHANNELS, SAMPLE_RATE);
+
+        let path = recorder.start().unwrap();
+        wait_for_recording(&recorder);
+
+        let mut block = make_block(0);
+        let mut channels: Vec<&mut [f32]> = block.iter_mut().map(|c| c.as_mut_slice()).collect();
+        sink.write_dry(&channels);
+        for channel in channels.iter_mut() {
+            channel.fill(0.5);
+        }
+        sink.write_output(&channels, SAMPLE_RATE);
+        recorder.shutdown();
+
+        let dry_path = path.with_file_name(format!(
+            "{}_dry.wav",
+            path.file_stem().unwrap().to_str().unwrap()
+        ));
+        let (_, output_samples) = read_samples(&path);
+        let (_, dry_samples) = read_samples(&dry_path);
+        assert!(output_samples.iter().all(|sample| *sample == 0.5));
+        assert_eq!(dry_samples.len(), BLOCK_SIZE * NUM_CHANNELS as usize);
+        assert_eq!(dry_samples[..4], [0.0, -0.0, 1.0, -1.0]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// A producer that writes blocks much faster than real time to a tiny ring buffer. Blocks that
+    /// don't fit should be dropped and counted without ever writing partial frames.
+    #[test]
+    fn fast_producer_drops_whole_blocks() {
+        const NUM_BLOCKS: usize = 2000;
+
+        let dir = test_dir("fast_producer");
+        let (recorder, mut sink) = Recorder::with_capacity(
+            dir.clone(),
+            "fast",
+            false,
+            NUM_CHANNELS,
+            SAMPLE_RATE,
+            BLOCK_SIZE * 4,
+        );
+
+        let path = recorder.start().unwrap();
+        wait_for_recording(&recorder);
+
+        for block_idx in 0..NUM_BLOCKS {
+            write_block(&mut sink, block_idx, SAMPLE_RATE);
+        }
+        recorder.shutdown();
+
+        let (_, samples) = read_samples(&path);
+        let dropped_samples = recorder.dropped_samples() as usize;
+        assert!(dropped_samples > 0, "The producer was not fast enough");
+        assert_eq!(dropped_samples % (BLOCK_SIZE * NUM_CHANNELS as usize), 0);
+        assert_eq!(
+            samples.len() + dropped_samples,
+            NUM_BLOCKS * BLOCK_SIZE * NUM_CHANNELS as usize
+        );
+
+        // Every recorded block should be intact, and the blocks should be in order
+        let mut last_frame_idx = None;
+        for block in samples.chunks_exact(BLOCK_SIZE * NUM_CHANNELS as usize) {
+            let first_frame_idx = block[0] as usize;
+            assert_eq!(first_frame_idx % BLOCK_SIZE, 0);
+            assert!(last_frame_idx.map_or(true, |last| first_frame_idx > last));
+            for (offset, frame) in block.chunks_exact(NUM_CHANNELS as usize).enumerate() {
+                let frame_idx = (first_frame_idx + offset) as f32;
+                assert_eq!(frame, [frame_idx, -frame_idx]);
+            }
+
+            last_frame_idx = Some(first_frame_idx + BLOCK_SIZE - 1);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01_00-00-00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29_00-00-00");
+        assert_eq!(format_timestamp(1_668_643_199), "2022-11-16_23-59-59");
+    }
+}
@@ -0,0 +1,698 @@
+//! Sample rate and block size conversion for the `--force-sample-rate` option. This sits between
+//! the audio backend's callback and the plugin so the plugin can run at a different sample rate
+//! than the audio device.
+
+use anyhow::{Context, Result};
+use rubato::{FftFixedInOut, Resampler};
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::context::process::Transport;
+use crate::midi::NoteEvent;
+
+/// The requested chunk size for the converters, in input frames. rubato rounds this up to a
+/// multiple of the smallest chunk size that works for the conversion ratio.
+const CONVERTER_CHUNK_SIZE: usize = 256;
+/// The number of chunks an impulse is sent through when measuring a converter's latency. This needs
+/// to be enough to cover the converter's entire impulse response.
+const LATENCY_PROBE_CHUNKS: usize = 16;
+/// The maximum number of MIDI events that can be queued up for the next plugin block.
+const EVENT_QUEUE_CAPACITY: usize = 2048;
+
+/// Converts a continuous multichannel stream of audio from one sample rate to another, in fixed
+/// size chunks. This keeps the resampling library used for the conversion an implementation detail.
+pub trait SampleRateConverter: Send {
+    /// The number of frames every call to [`process()`][Self::process()] consumes.
+    fn input_chunk_size(&self) -> usize;
+    /// The number of frames every call to [`process()`][Self::process()] produces.
+    fn output_chunk_size(&self) -> usize;
+    /// The delay introduced by the converter, in output frames.
+    fn latency(&self) -> usize;
+    /// Convert exactly [`input_chunk_size()`][Self::input_chunk_size()] frames for every channel in
+    /// `input`, and write exactly [`output_chunk_size()`][Self::output_chunk_size()] frames to
+    /// every channel in `output`. This must not allocate.
+    fn process(&mut self, input: &[Vec<f32>], output: &mut [Vec<f32>]);
+}
+
+/// Used when the audio device already runs at the plugin's sample rate. In that case only the
+/// block sizes need to be adapted.
+struct Passthrough {
+    chunk_size: usize,
+}
+
+/// A high quality FFT based sample rate converter for fixed conversion ratios.
+struct RubatoConverter {
+    resampler: FftFixedInOut<f32>,
+    /// rubato doesn't report its filters' delays, so this is measured once when the converter is
+    /// created.
+    latency: usize,
+}
+
+/// Sits between the audio backend's callback and the plugin's process function when the plugin
+/// runs at a different sample rate than the audio device. The device's audio is converted to the
+/// plugin's sample rate, the plugin processes blocks of exactly `block_size` samples, and its
+/// output is converted back to the device's sample rate. The FIFOs in between absorb the
+/// difference between the device's period size and the plugin's block size. Nothing allocates after
+/// the adapter has been created.
+///
+/// MIDI events are not sample accurate when resampling. Input events are sent to the plugin at the
+/// start of the next block it processes, and output events are sent to the device at the start of
+/// the current period.
+pub struct ResamplingAdapter {
+    device_sample_rate: f32,
+    plugin_sample_rate: f32,
+    /// The exact number of samples the plugin is called with. The device's buffers are also
+    /// processed in slices of at most this many samples to bound the FIFO sizes.
+    block_size: usize,
+    /// The adapter's total latency in device frames, including the silence `device_output` was
+    /// prefilled with.
+    latency: usize,
+
+    /// Device rate audio that has not yet been converted to the plugin's sample rate.
+    device_input: Fifo,
+    /// Converts from the device's sample rate to the plugin's sample rate.
+    input_stage: ConversionStage,
+    /// Plugin rate audio that still needs to be processed by the plugin.
+    plugin_input: Fifo,
+    /// Plugin rate audio that has been processed by the plugin but that has not yet been converted
+    /// back to the device's sample rate.
+    plugin_output: Fifo,
+    /// Converts from the plugin's sample rate back to the device's sample rate.
+    output_stage: ConversionStage,
+    /// Device rate audio that can be written to the device. This is prefilled with enough silence
+    /// to never run dry.
+    device_output: Fifo,
+
+    /// The storage for `plugin_buffer`.
+    plugin_channels: Vec<Vec<f32>>,
+    /// The buffer passed to the plugin. Its slices point to `plugin_channels`, and they are set
+    /// again at the start of every callback in case the channels have been moved.
+    plugin_buffer: Buffer<'static>,
+    /// MIDI events received from the device that will be sent to the plugin at the start of the
+    /// next block.
+    pending_input_events: Vec<NoteEvent>,
+    /// The plugin's output events for the current block.
+    block_output_events: Vec<NoteEvent>,
+}
+
+/// A converter together with the preallocated buffers it reads from and writes to.
+struct ConversionStage {
+    converter: Box<dyn SampleRateConverter>,
+    input: Vec<Vec<f32>>,
+    output: Vec<Vec<f32>>,
+}
+
+/// A fixed capacity multichannel FIFO. Reading shifts the remaining frames to the front, which is
+/// cheap for the small number of frames stored in these.
+struct Fifo {
+    channels: Vec<Vec<f32>>,
+    /// The number of frames currently stored in the FIFO.
+    len: usize,
+}
+
+impl SampleRateConverter for Passthrough {
+    fn input_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn output_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn latency(&self) -> usize {
+        0
+    }
+
+    fn process(&mut self, input: &[Vec<f32>], output: &mut [Vec<f32>]) {
+        for (input_channel, output_channel) in input.iter().zip(output.iter_mut()) {
+            output_channel.copy_from_slice(input_channel);
+        }
+    }
+}
+
+impl RubatoConverter {
+    fn new(input_sample_rate: u32, output_sample_rate: u32, num_channels: usize) -> Result<Self> {
+        let create_resampler = |num_channels| {
+            FftFixedInOut::<f32>::new(
+                input_sample_rate as usize,
+                output_sample_rate as usize,
+                CONVERTER_CHUNK_SIZE,
+                num_channels,
+            )
+            .with_context(|| {
+                format!(
+                    "Could not create a converter from {input_sample_rate} Hz to \
+                     {output_sample_rate} Hz"
+                )
+            })
+        };
+
+        // The latency is measured by sending an impulse through an identically configured mono
+        // converter and looking for the peak in its output. The filters are linear phase, so the
+        // peak is exactly where the impulse ends up.
+        let mut probe = create_resampler(1)?;
+        let mut probe_input = vec![vec![0.0f32; probe.input_frames_next()]];
+        let mut probe_output = vec![vec![0.0f32; probe.output_frames_next()]];
+        probe_input[0][0] = 1.0;
+        let mut latency = 0;
+        let mut peak_magnitude = 0.0;
+        for chunk_idx in 0..LATENCY_PROBE_CHUNKS {
+            probe
+                .process_into_buffer(&probe_input, &mut probe_output, None)
+                .context("Could not measure the converter's latency")?;
+            for (sample_idx, sample) in probe_output[0].iter().enumerate() {
+                if sample.abs() > peak_magnitude {
+                    latency = (chunk_idx * probe_output[0].len()) + sample_idx;
+                    peak_magnitude = sample.abs();
+                }
+            }
+
+            probe_input[0][0] = 0.0;
+        }
+
+        Ok(Self {
+            resampler: create_resampler(num_channels)?,
+            latency,
+        })
+    }
+}
+
+impl SampleRateConverter for RubatoConverter {
+    fn input_chunk_size(&self) -> usize {
+        self.resampler.input_frames_next()
+    }
+
+    fn output_chunk_size(&self) -> usize {
+        self.resampler.output_frames_next()
+    }
+
+    fn latency(&self) -> usize {
+        self.latency
+    }
+
+    fn process(&mut self, input: &[Vec<f32>], output: &mut [Vec<f32>]) {
+        if let Err(err) = self.resampler.process_into_buffer(input, output, None) {
+            nih_debug_assert_failure!("Error while resampling: {}", err);
+            for channel in output {
+                channel.fill(0.0);
+            }
+        }
+    }
+}
+
+impl ResamplingAdapter {
+    /// Create an adapter for a device running at `device_sample_rate` and a plugin that has been
+    /// initialized at `plugin_sample_rate`. The plugin's process function will always be called
+    /// with exactly `block_size` samples. Returns an error if the sample rates can't be converted
+    /// between.
+    pub fn new(
+        device_sample_rate: f32,
+        plugin_sample_rate: f32,
+        num_channels: usize,
+        block_size: usize,
+    ) -> Result<Self> {
+        nih_debug_assert!(block_size > 0);
+
+        let (input_converter, output_converter): (
+            Box<dyn SampleRateConverter>,
+            Box<dyn SampleRateConverter>,
+        ) = if device_sample_rate == plugin_sample_rate {
+            (
+                Box::new(Passthrough {
+                    chunk_size: block_size,
+                }),
+                Box::new(Passthrough {
+                    chunk_size: block_size,
+                }),
+            )
+        } else {
+            let device_rate = device_sample_rate.round() as u32;
+            let plugin_rate = plugin_sample_rate.round() as u32;
+            (
+                Box::new(RubatoConverter::new(
+                    device_rate,
+                    plugin_rate,
+                    num_channels,
+                )?),
+                Box::new(RubatoConverter::new(
+                    plugin_rate,
+                    device_rate,
+                    num_channels,
+                )?),
+            )
+        };
+
+        // Used to convert between the number of device frames and plugin frames, rounded up
+        let device_ratio = device_sample_rate as f64 / plugin_sample_rate as f64;
+        let to_device_frames =
+            |plugin_frames: usize| (plugin_frames as f64 * device_ratio).ceil() as usize;
+        let to_plugin_frames =
+            |device_frames: usize| (device_frames as f64 / device_ratio).ceil() as usize;
+
+        // At any point up to a chunk of device input, a block of plugin input, and a chunk of
+        // plugin output can be stuck in the FIFOs waiting for more data. Prefilling the output with
+        // this much silence ensures there's always enough output for the device.
+        let prefill = input_converter.input_chunk_size()
+            + to_device_frames(block_size + output_converter.input_chunk_size());
+        let latency = prefill
+            + (input_converter.latency() as f64 * device_ratio).round() as usize
+            + output_converter.latency();
+
+        let device_input_capacity = block_size + input_converter.input_chunk_size();
+        let plugin_input_capacity = block_size
+            + to_plugin_frames(device_input_capacity)
+            + input_converter.output_chunk_size();
+        let plugin_output_capacity = plugin_input_capacity + output_converter.input_chunk_size();
+        let device_output_capacity = prefill
+            + block_size
+            + to_device_frames(plugin_output_capacity)
+            + output_converter.output_chunk_size();
+
+        let mut device_output = Fifo::new(num_channels, device_output_capacity);
+        device_output.push_silence(prefill);
+
+        let mut plugin_buffer = Buffer::default();
+        unsafe {
+            plugin_buffer.with_raw_vec(|output_slices| {
+                // The pointers are set in `process()` because the adapter may be moved between
+                // now and then
+                output_slices.resize_with(num_channels, || &mut []);
+            })
+        }
+
+        Ok(Self {
+            device_sample_rate,
+            plugin_sample_rate,
+            block_size,
+            latency,
+
+            device_input: Fifo::new(num_channels, device_input_capacity),
+            input_stage: ConversionStage::new(input_converter, num_channels),
+            plugin_input: Fifo::new(num_channels, plugin_input_capacity),
+            plugin_output: Fifo::new(num_channels, plugin_output_capacity),
+            output_stage: ConversionStage::new(output_converter, num_channels),
+            device_output,
+
+            plugin_channels: vec![vec![0.0; block_size]; num_channels],
+            plugin_buffer,
+            pending_input_events: Vec::with_capacity(EVENT_QUEUE_CAPACITY),
+            block_output_events: Vec::with_capacity(EVENT_QUEUE_CAPACITY),
+        })
+    }
+
+    /// The latency added by the adapter, in device frames.
+    pub fn latency_samples(&self) -> u32 {
+        self.latency as u32
+    }
+
+    /// The latency added by the adapter, in seconds.
+    pub fn latency_seconds(&self) -> f32 {
+        self.latency as f32 / self.device_sample_rate
+    }
+
+    /// Process a buffer from the audio backend in place. `process_block` is called with the
+    /// plugin's buffers at the plugin's sample rate zero or more times. Returns `false` if any of
+    /// those calls returned `false`, just like the backend's callback.
+    pub fn process(
+        &mut self,
+        device_buffer: &mut Buffer,
+        device_transport: Transport,
+        input_events: &[NoteEvent],
+        output_events: &mut Vec<NoteEvent>,
+        mut process_block: impl FnMut(&mut Buffer, Transport, &[NoteEvent], &mut Vec<NoteEvent>) -> bool,
+    ) -> bool {
+        unsafe {
+            self.plugin_buffer.with_raw_vec(|output_slices| {
+                for (output_slice, channel) in output_slices
+                    .iter_mut()
+                    .zip(self.plugin_channels.iter_mut())
+                {
+                    // SAFETY: `plugin_channels` is not used directly after this, and it outlives
+                    //         the buffer
+                    *output_slice = &mut *(channel.as_mut_slice() as *mut [f32]);
+                }
+            })
+        }
+
+        for event in input_events {
+            if self.pending_input_events.len() < self.pending_input_events.capacity() {
+                let mut event = *event;
+                event.subtract_timing(event.timing());
+                self.pending_input_events.push(event);
+            } else {
+                nih_debug_assert_failure!("The MIDI event queue was full, dropping event");
+            }
+        }
+
+        // The plugin's position is the device's position at the start of the buffer converted to
+        // the plugin's sample rate, plus the number of samples the plugin has processed since then
+        let device_pos_samples = device_transport.pos_samples;
+        let mut num_processed_plugin_samples = 0;
+
+        let num_device_samples = device_buffer.len();
+        let mut slice_start = 0;
+        while slice_start < num_device_samples {
+            let slice = slice_start..(slice_start + self.block_size).min(num_device_samples);
+
+            self.device_input
+                .push(device_buffer.as_slice_immutable(), slice.clone());
+            self.input_stage
+                .process(&mut self.device_input, &mut self.plugin_input);
+
+            while self.plugin_input.len() >= self.block_size {
+                self.plugin_input
+                    .pop(self.plugin_buffer.as_slice(), 0..self.block_size);
+
+                let mut transport = Transport::new(self.plugin_sample_rate);
+                transport.playing = device_transport.playing;
+                transport.recording = device_transport.recording;
+                transport.preroll_active = device_transport.preroll_active;
+                transport.tempo = device_transport.tempo;
+                transport.time_sig_numerator = device_transport.time_sig_numerator;
+                transport.time_sig_denominator = device_transport.time_sig_denominator;
+                // The other positions can be derived from this one and the tempo
+                transport.pos_samples = device_pos_samples.map(|pos| {
+                    (pos as f64 * self.plugin_sample_rate as f64 / self.device_sample_rate as f64)
+                        .round() as i64
+                        + num_processed_plugin_samples
+                });
+
+                self.block_output_events.clear();
+                let keep_running = process_block(
+                    &mut self.plugin_buffer,
+                    transport,
+                    &self.pending_input_events,
+                    &mut self.block_output_events,
+                );
+                self.pending_input_events.clear();
+                num_processed_plugin_samples += self.block_size as i64;
+                if !keep_running {
+                    return false;
+                }
+
+                for event in self.block_output_events.drain(..) {
+                    if output_events.len() < output_events.capacity() {
+                        let mut event = event;
+                        event.subtract_timing(event.timing());
+                        output_events.push(event);
+                    } else {
+                        nih_debug_assert_failure!("The MIDI output queue was full, dropping event");
+                    }
+                }
+
+                self.plugin_output
+                    .push(self.plugin_buffer.as_slice_immutable(), 0..self.block_size);
+            }
+
+            self.output_stage
+                .process(&mut self.plugin_output, &mut self.device_output);
+
+            nih_debug_assert!(
+                self.device_output.len() >= slice.len(),
+                "The resampler's output ran dry"
+            );
+            self.device_output
+                .pop(device_buffer.as_slice(), slice.clone());
+
+            slice_start = slice.end;
+        }
+
+        true
+    }
+}
+
+impl ConversionStage {
+    pub fn new(converter: Box<dyn SampleRateConverter>, num_channels: usize) -> Self {
+        Self {
+            input: vec![vec![0.0; converter.input_chunk_size()]; num_channels],
+            output: vec![vec![0.0; converter.output_chunk_size()]; num_channels],
+            converter,
+        }
+    }
+
+    /// Convert as many chunks from `from` as possible, and write the results to `to`.
+    pub fn process(&mut self, from: &mut Fifo, to: &mut Fifo) {
+        let chunk_size = self.converter.input_chunk_size();
+        while from.len() >= chunk_size {
+            from.pop(&mut self.input, 0..chunk_size);
+            self.converter.process(&self.input, &mut self.output);
+            to.push(&self.output, 0..self.converter.output_chunk_size());
+        }
+    }
+}
+
+impl Fifo {
+    pub fn new(num_channels: usize, capacity: usize) -> Self {
+        Self {
+            channels: vec![vec![0.0; capacity]; num_channels],
+            len: 0,
+        }
+    }
+
+    /// The number of frames stored in the FIFO.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Append the frames in `range` from every channel in `input`. Frames that don't fit are
+    /// dropped.
+    pub fn push<S: AsRef<[f32]>>(&mut self, input: &[S], range: Range<usize>) {
+        let num_frames = self.reserve(range.len());
+        for (channel, input_channel) in self.channels.iter_mut().zip(input) {
+            channel[self.len..self.len + num_frames]
+                .copy_from_slice(&input_channel.as_ref()[range.start..range.start + num_frames]);
+        }
+
+        self.len += num_frames;
+    }
+
+    /// Append `num_frames` frames of silence.
+    pub fn push_silence(&mut self, num_frames: usize) {
+        let num_frames = self.reserve(num_frames);
+        for channel in &mut self.channels {
+            channel[self.len..self.len + num_frames].fill(0.0);
+        }
+
+        self.len += num_frames;
+    }
+
+    /// Remove `range.len()` frames from the front of the FIFO and write them to `range` in every
+    /// channel in `output`. If the FIFO contains fewer frames, then the rest is filled with
+    /// silence.
+    pub fn pop<S: AsMut<[f32]>>(&mut self, output: &mut [S], range: Range<usize>) {
+        let num_frames = range.len().min(self.len);
+        for (channel, output_channel) in self.channels.iter_mut().zip(output) {
+            let output_channel = &mut output_channel.as_mut()[range.clone()];
+            output_channel[..num_frames].copy_from_slice(&channel[..num_frames]);
+            output_channel[num_frames..].fill(0.0);
+
+            channel.copy_within(num_frames..self.len, 0);
+        }
+
+        self.len -= num_frames;
+    }
+
+    /// Returns how many of `num_frames` frames fit in the FIFO.
+    fn reserve(&self, num_frames: usize) -> usize {
+        let capacity = self
+            .channels
+            .first()
+            .map(|channel| channel.len())
+            .unwrap_or(0);
+        let num_frames_fit = num_frames.min(capacity - self.len);
+        nih_debug_assert_eq!(
+            num_frames_fit,
+            num_frames,
+            "The resampler's FIFO is full, dropping samples"
+        );
+
+        num_frames_fit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE_SAMPLE_RATE: f32 = 48_000.0;
+    const PLUGIN_SAMPLE_RATE: f32 = 44_100.0;
+    const NUM_CHANNELS: usize = 2;
+    const BLOCK_SIZE: usize = 512;
+    /// Deliberately doesn't divide the block size.
+    const DEVICE_PERIOD_SIZE: usize = 300;
+    const TONE_FREQUENCY: f32 = 1_000.0;
+
+    /// Send `input` through an adapter in periods of `DEVICE_PERIOD_SIZE` samples. The plugin's
+    /// block sizes are checked, and its input is passed to `plugin_input_cb` before being sent
+    /// back unchanged. Returns the adapter's output for the first channel.
+    fn run_adapter(
+        adapter: &mut ResamplingAdapter,
+        input: &[f32],
+        mut plugin_input_cb: impl FnMut(&[f32]),
+    ) -> Vec<f32> {
+        let mut output = Vec::with_capacity(input.len());
+        let mut output_events = Vec::with_capacity(EVENT_QUEUE_CAPACITY);
+        for period in input.chunks(DEVICE_PERIOD_SIZE) {
+            let mut channels = vec![period.to_vec(); NUM_CHANNELS];
+            let mut buffer = Buffer::default();
+            unsafe {
+                buffer.with_raw_vec(|output_slices| {
+                    *output_slices = channels
+                        .iter_mut()
+                        .map(|channel| &mut *(channel.as_mut_slice() as *mut [f32]))
+                        .collect();
+                })
+            }
+
+            let keep_running = adapter.process(
+                &mut buffer,
+                Transport::new(DEVICE_SAMPLE_RATE),
+                &[],
+                &mut output_events,
+                |plugin_buffer, transport, _, _| {
+                    assert_eq!(plugin_buffer.len(), BLOCK_SIZE);
+                    assert_eq!(transport.sample_rate, PLUGIN_SAMPLE_RATE);
+                    plugin_input_cb(&plugin_buffer.as_slice_immutable()[0]);
+
+                    true
+                },
+            );
+            assert!(keep_running);
+
+            output.extend_from_slice(&channels[0]);
+        }
+
+        output
+    }
+
+    fn sine(frequency: f32, sample_rate: f32, num_samples: usize) -> Vec<f32> {
+        (0..num_samples)
+            .map(|sample_idx| {
+                (sample_idx as f32 / sample_rate * frequency * std::f32::consts::TAU).sin() * 0.5
+            })
+            .collect()
+    }
+
+    /// Estimate a sine wave's frequency from the distance between its first and last upwards zero
+    /// crossings, interpolated linearly between samples.
+    fn estimate_frequency(samples: &[f32], sample_rate: f32) -> f32 {
+        let crossings: Vec<f32> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+            .map(|(sample_idx, pair)| sample_idx as f32 + (pair[0] / (pair[0] - pair[1])))
+            .collect();
+        assert!(crossings.len() > 2);
+
+        (crossings.len() - 1) as f32 / (crossings.last().unwrap() - crossings[0]) * sample_rate
+    }
+
+    #[test]
+    fn tone_frequency() {
+        let mut adapter = ResamplingAdapter::new(
+            DEVICE_SAMPLE_RATE,
+            PLUGIN_SAMPLE_RATE,
+            NUM_CHANNELS,
+            BLOCK_SIZE,
+        )
+        .unwrap();
+
+        let num_samples = DEVICE_SAMPLE_RATE as usize;
+        let input = sine(TONE_FREQUENCY, DEVICE_SAMPLE_RATE, num_samples);
+        let mut plugin_input = Vec::new();
+        let output = run_adapter(&mut adapter, &input, |samples| {
+            plugin_input.extend_from_slice(samples)
+        });
+
+        // The plugin should have received the same tone at its own sample rate. The start is
+        // skipped to get rid of the filter's transients.
+        let skip = BLOCK_SIZE * 4;
+        let plugin_frequency = estimate_frequency(&plugin_input[skip..], PLUGIN_SAMPLE_RATE);
+        assert!(
+            (plugin_frequency - TONE_FREQUENCY).abs() < 0.1,
+            "{plugin_frequency} Hz"
+        );
+
+        let skip = adapter.latency_samples() as usize + BLOCK_SIZE;
+        let output_frequency = estimate_frequency(&output[skip..], DEVICE_SAMPLE_RATE);
+        assert!(
+            (output_frequency - TONE_FREQUENCY).abs() < 0.1,
+            "{output_frequency} Hz"
+        );
+    }
+
+    #[test]
+    fn latency() {
+        for (device_sample_rate, plugin_sample_rate) in [
+            (DEVICE_SAMPLE_RATE, PLUGIN_SAMPLE_RATE),
+            (PLUGIN_SAMPLE_RATE, DEVICE_SAMPLE_RATE),
+            (DEVICE_SAMPLE_RATE, DEVICE_SAMPLE_RATE),
+        ] {
+            let mut adapter = ResamplingAdapter::new(
+                device_sample_rate,
+                plugin_sample_rate,
+                NUM_CHANNELS,
+                BLOCK_SIZE,
+            )
+            .unwrap();
+
+            // The reported latency should be exactly where an impulse ends up, and it should be
+            // reasonably low
+            let latency = adapter.latency_samples() as usize;
+            assert!(
+                adapter.latency_seconds() < 0.1,
+                "{latency} samples at {device_sample_rate} Hz -> {plugin_sample_rate} Hz"
+            );
+
+            let mut input = vec![0.0; latency * 2 + DEVICE_PERIOD_SIZE];
+            input[0] = 1.0;
+            let output = run_adapter(&mut adapter, &input, |_| ());
+            let (peak_idx, _) = output
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))
+                .unwrap();
+            assert!(
+                (peak_idx as isize - latency as isize).abs() <= 2,
+                "Impulse at {peak_idx}, reported latency {latency} at {device_sample_rate} Hz \
+                 -> {plugin_sample_rate} Hz"
+            );
+        }
+    }
+
+    #[test]
+    fn stop_processing() {
+        let mut adapter = ResamplingAdapter::new(
+            DEVICE_SAMPLE_RATE,
+            PLUGIN_SAMPLE_RATE,
+            NUM_CHANNELS,
+            BLOCK_SIZE,
+        )
+        .unwrap();
+
+        let mut channels = vec![vec![0.0; BLOCK_SIZE * 4]; NUM_CHANNELS];
+        let mut buffer = Buffer::default();
+        unsafe {
+            buffer.with_raw_vec(|output_slices| {
+                *output_slices = channels
+                    .iter_mut()
+                    .map(|channel| &mut *(channel.as_mut_slice() as *mut [f32]))
+                    .collect();
+            })
+        }
+
+        let mut num_calls = 0;
+        let keep_running = adapter.process(
+            &mut buffer,
+            Transport::new(DEVICE_SAMPLE_RATE),
+            &[],
+            &mut Vec::new(),
+            |_, _, _, _| {
+                num_calls += 1;
+                false
+            },
+        );
+        assert!(!keep_running);
+        assert_eq!(num_calls, 1);
+    }
+}
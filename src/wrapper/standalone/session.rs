@@ -0,0 +1,340 @@
+//! Remembers the standalone wrapper's settings and the plugin's state between runs. The session is
+//! stored as a JSON file in the platform's config directory. It's loaded before the plugin is
+//! initialized, and it's written periodically while the application is running and again when it
+//! exits.
+
+use anyhow::{Context, Result};
+use clap::{ArgMatches, ValueSource};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::config::{BackendType, WrapperConfig};
+use crate::wrapper::state::PluginState;
+
+/// The version of the session file format. Session files with a different version are ignored.
+pub const SESSION_FORMAT_VERSION: u32 = 1;
+
+/// How often the session is saved while the application is running. This makes sure the plugin's
+/// state survives the application getting killed or crashing.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The contents of a session file.
+///
+/// The editor's size is not stored here. Editors persist their own size as part of the plugin's
+/// state, so restoring the state also restores the window's size. The window's position is not
+/// stored since baseview has no way to query or set it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    /// The file format's version, see [`SESSION_FORMAT_VERSION`].
+    pub version: u32,
+    /// The name of the plugin the session was saved with.
+    pub plugin: String,
+
+    /// The value of the `--backend` option.
+    pub backend: BackendType,
+    /// The value of the `--input-device` option.
+    pub input_device: Option<String>,
+    /// The value of the `--output-device` option.
+    pub output_device: Option<String>,
+    /// The value of the `--sample-rate` option.
+    pub sample_rate: f32,
+    /// The value of the `--period-size` option.
+    pub period_size: u32,
+    /// The value of the `--connect-jack-inputs` option.
+    pub connect_jack_inputs: Option<String>,
+    /// The value of the `--connect-jack-midi-input` option.
+    pub connect_jack_midi_input: Option<String>,
+    /// The value of the `--connect-jack-midi-output` option.
+    pub connect_jack_midi_output: Option<String>,
+    /// The value of the `--dpi-scale` option.
+    pub dpi_scale: f32,
+
+    /// The plugin's parameters and persistent fields.
+    pub state: PluginState,
+}
+
+/// The session file for the standalone wrapper, as passed to the wrapper.
+#[derive(Debug, Clone)]
+pub struct SessionFile {
+    /// Where the session will be saved.
+    pub path: PathBuf,
+    /// The plugin state from the session file that was loaded at startup. This is restored before
+    /// the plugin is initialized.
+    pub initial_state: Option<PluginState>,
+}
+
+impl Session {
+    /// Create a session for the plugin `plugin_name` from the wrapper's current configuration and
+    /// the plugin's current state.
+    pub fn new(plugin_name: &str, config: &WrapperConfig, state: PluginState) -> Self {
+        Self {
+            version: SESSION_FORMAT_VERSION,
+            plugin: String::from(plugin_name),
+
+            backend: config.backend.clone(),
+            input_device: config.input_device.clone(),
+            output_device: config.output_device.clone(),
+            sample_rate: config.sample_rate,
+            period_size: config.period_size,
+            connect_jack_inputs: config.connect_jack_inputs.clone(),
+            connect_jack_midi_input: config.connect_jack_midi_input.clone(),
+            connect_jack_midi_output: config.connect_jack_midi_output.clone(),
+            dpi_scale: config.dpi_scale,
+
+            state,
+        }
+    }
+
+    /// Load the session file at `path` for the plugin `plugin_name`. Returns `Ok(None)` if the
+    /// file does not exist yet. Returns an error if the file cannot be read or parsed, if it uses a
+    /// different format version, or if it was saved with another plugin.
+    pub fn load(path: &Path, plugin_name: &str) -> Result<Option<Self>> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Could not read '{}'", path.display()))
+            }
+        };
+
+        // The version is checked before parsing the rest of the file so sessions from other
+        // versions result in a useful error message
+        let json: serde_json::Value = serde_json::from_slice(&json)
+            .with_context(|| format!("Could not parse '{}'", path.display()))?;
+        let version = json.get("version").and_then(|version| version.as_u64());
+        anyhow::ensure!(
+            version == Some(SESSION_FORMAT_VERSION as u64),
+            "Unsupported session format version {:?}, expected version {}",
+            version,
+            SESSION_FORMAT_VERSION
+        );
+
+        let session: Session = serde_json::from_value(json)
+            .with_context(|| format!("Could not parse '{}'", path.display()))?;
+        anyhow::ensure!(
+            session.plugin == plugin_name,
+            "The session was saved with '{}', not with '{}'",
+            session.plugin,
+            plugin_name
+        );
+
+        Ok(Some(session))
+    }
+
+    /// Write the session to `path`, creating the parent directories if needed. The file is first
+    /// written to a temporary file and then moved into place, so the file is never left half
+    /// written.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Could not create '{}'", parent.display()))?;
+        }
+
+        let json =
+            serde_json::to_vec_pretty(self).context("Could not format the session as JSON")?;
+
+        let mut temp_path = path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, json)
+            .with_context(|| format!("Could not write to '{}'", Path::new(&temp_path).display()))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Could not move the file to '{}'", path.display()))
+    }
+
+    /// Overwrite the settings in `config` with the stored settings, except for the options that
+    /// were explicitly passed on the command line. `matches` are the matches `config` was parsed
+    /// from.
+    pub fn apply_to_config(&self, config: &mut WrapperConfig, matches: &ArgMatches) {
+        let use_stored = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if use_stored("backend") {
+            config.backend = self.backend.clone();
+        }
+        if use_stored("input_device") {
+            config.input_device = self.input_device.clone();
+        }
+        if use_stored("output_device") {
+            config.output_device = self.output_device.clone();
+        }
+        if use_stored("sample_rate") {
+            config.sample_rate = self.sample_rate;
+        }
+        if use_stored("period_size") {
+            config.period_size = self.period_size;
+        }
+        if use_stored("connect_jack_inputs") {
+            config.connect_jack_inputs = self.connect_jack_inputs.clone();
+        }
+        if use_stored("connect_jack_midi_input") {
+            config.connect_jack_midi_input = self.connect_jack_midi_input.clone();
+        }
+        if use_stored("connect_jack_midi_output") {
+            config.connect_jack_midi_output = self.connect_jack_midi_output.clone();
+        }
+        if use_stored("dpi_scale") {
+            config.dpi_scale = self.dpi_scale;
+        }
+    }
+}
+
+/// The path to the session file for the plugin `plugin_name`, or `None` if the platform's config
+/// directory could not be determined.
+pub fn session_path(plugin_name: &str) -> Option<PathBuf> {
+    // The plugin's name may contain characters that cannot be used in file names
+    let file_name: String = plugin_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    dirs::config_dir().map(|config_dir| {
+        config_dir
+            .join("nih-plug")
+            .join("standalone")
+            .join(format!("{file_name}.json"))
+    })
+}
+
+/// Load the session file at `path` like [`Session::load()`], but print a warning and return `None`
+/// instead of returning an error. A broken session file should never prevent the application from
+/// starting.
+pub fn load_or_warn(path: &Path, plugin_name: &str) -> Option<Session> {
+    match Session::load(path, plugin_name) {
+        Ok(session) => session,
+        Err(err) => {
+            nih_error!("Ignoring the saved session: {:#}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{FromArgMatches, IntoApp};
+    use std::collections::BTreeMap;
+
+    use crate::wrapper::state::ParamValue;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("nih_plug_session_{}_{}", name, std::process::id()))
+            .join("Test Plugin.json")
+    }
+
+    fn parse_args(args: &[&str]) -> (WrapperConfig, ArgMatches) {
+        let matches = WrapperConfig::command()
+            .try_get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+            .unwrap();
+        let config = WrapperConfig::from_arg_matches(&matches).unwrap();
+
+        (config, matches)
+    }
+
+    fn test_session() -> Session {
+        let (mut config, _) = parse_args(&[]);
+        config.backend = BackendType::Dummy;
+        config.output_device = Some(String::from("Speakers"));
+        config.sample_rate = 96_000.0;
+        config.period_size = 256;
+        config.connect_jack_midi_input = Some(String::from("system:midi_capture_1"));
+        config.dpi_scale = 2.0;
+
+        Session::new(
+            "Test Plugin",
+            &config,
+            PluginState {
+                version: String::from("1.0.0"),
+                params: BTreeMap::from([(String::from("gain"), ParamValue::F32(-6.0))]),
+                fields: BTreeMap::from([(String::from("editor-state"), String::from("[640,480]"))]),
+                instance_id: None,
+            },
+        )
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = test_path("round_trip");
+        let session = test_session();
+        session.save(&path).unwrap();
+
+        let loaded = Session::load(&path, "Test Plugin").unwrap().unwrap();
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&session).unwrap()
+        );
+        assert_eq!(loaded.backend, BackendType::Dummy);
+        assert_eq!(loaded.sample_rate, 96_000.0);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = test_path("missing_file");
+        assert!(Session::load(&path, "Test Plugin").unwrap().is_none());
+    }
+
+    #[test]
+    fn command_line_overrides_session() {
+        let session = test_session();
+
+        // Without any options, everything is taken from the session
+        let (mut config, matches) = parse_args(&[]);
+        session.apply_to_config(&mut config, &matches);
+        assert_eq!(config.backend, BackendType::Dummy);
+        assert_eq!(config.output_device.as_deref(), Some("Speakers"));
+        assert_eq!(config.sample_rate, 96_000.0);
+        assert_eq!(config.period_size, 256);
+        assert_eq!(config.dpi_scale, 2.0);
+
+        // Explicitly passed options are kept, even when they're set to the default value
+        let (mut config, matches) = parse_args(&[
+            "--backend",
+            "auto",
+            "--sample-rate",
+            "48000",
+            "--output-device",
+            "Headphones",
+        ]);
+        session.apply_to_config(&mut config, &matches);
+        assert_eq!(config.backend, BackendType::Auto);
+        assert_eq!(config.output_device.as_deref(), Some("Headphones"));
+        assert_eq!(config.sample_rate, 48_000.0);
+        assert_eq!(config.period_size, 256);
+        assert_eq!(
+            config.connect_jack_midi_input.as_deref(),
+            Some("system:midi_capture_1")
+        );
+    }
+
+    #[test]
+    fn ignores_broken_files() {
+        let path = test_path("ignores_broken_files");
+        let session = test_session();
+        session.save(&path).unwrap();
+
+        // A file that was cut off halfway through writing it
+        let json = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &json[..json.len() / 2]).unwrap();
+        assert!(Session::load(&path, "Test Plugin").is_err());
+        assert!(load_or_warn(&path, "Test Plugin").is_none());
+
+        let mut json: serde_json::Value = serde_json::to_value(&session).unwrap();
+        json["version"] = serde_json::json!(SESSION_FORMAT_VERSION + 1);
+        std::fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+        assert!(load_or_warn(&path, "Test Plugin").is_none());
+
+        session.save(&path).unwrap();
+        assert!(load_or_warn(&path, "Other Plugin").is_none());
+        assert!(load_or_warn(&path, "Test Plugin").is_some());
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -1,32 +1,49 @@
 use atomic_refcell::AtomicRefCell;
 use baseview::{EventStatus, Window, WindowHandler, WindowOpenOptions};
+use crossbeam::atomic::AtomicCell;
 use crossbeam::channel;
 use crossbeam::queue::ArrayQueue;
 use parking_lot::Mutex;
 use raw_window_handle::HasRawWindowHandle;
-use std::any::Any;
 use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 
+use super::automation::{
+    AutomationPlayer, AutomationRecorder, AutomationRecorderSink, AutomationSource,
+};
 use super::backend::Backend;
 use super::config::WrapperConfig;
 use super::context::{WrapperGuiContext, WrapperInitContext, WrapperProcessContext};
+use super::recorder::{Recorder, RecorderSink};
+use super::resampler::ResamplingAdapter;
+use super::session::{self, Session, SessionFile};
+use crate::buffer::Buffer;
 use crate::context::gui::AsyncExecutor;
 use crate::context::process::Transport;
+use crate::context::repaint::RepaintRequests;
 use crate::editor::{Editor, ParentWindowHandle};
 use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
+use crate::midi::routing::MidiInputRouter;
 use crate::midi::NoteEvent;
+use crate::params::changes::{ParamChangeSource, ParamChangeTracker};
 use crate::params::internals::ParamPtr;
 use crate::params::{ParamFlags, Params};
 use crate::plugin::{
-    AuxiliaryBuffers, AuxiliaryIOConfig, BufferConfig, BusConfig, Plugin, ProcessMode,
+    AuxiliaryBuffers, AuxiliaryIOConfig, BufferConfig, BusConfig, InitError, Plugin, ProcessMode,
     ProcessStatus, TaskExecutor,
 };
 use crate::util::permit_alloc;
+use crate::wrapper::editor_lifecycle::EditorLifecycle;
 use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::process_wrapper;
+use crate::wrapper::util::{
+    find_bypass_param, process_wrapper, transition_lifecycle_state, LifecycleState,
+    ParamValueValidator, TransportTracker,
+};
 
 /// How many parameter changes we can store in our unprocessed parameter change queue. Storing more
 /// than this many parameters at a time will cause changes to get lost.
@@ -47,13 +64,29 @@ pub struct Wrapper<P: Plugin, B: Backend> {
     /// The set of parameter pointers in `params`. This is technically not necessary, but for
     /// consistency with the plugin wrappers we'll check whether the `ParamPtr` for an incoming
     /// parameter change actually belongs to a registered parameter.
-    known_parameters: HashSet<ParamPtr>,
+    pub(crate) known_parameters: HashSet<ParamPtr>,
     /// A mapping from parameter string IDs to parameter pointers.
     param_map: HashMap<String, ParamPtr>,
+    /// The source of the most recent change to every parameter, shared with the editor through the
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub param_changes: Arc<ParamChangeTracker>,
+    /// Clamps the normalized values coming from the editor and the automation player, and rejects
+    /// NaN and infinite values.
+    pub param_value_validator: ParamValueValidator,
+    /// A mapping from the plugin's legacy parameter IDs to the parameters they were renamed to.
+    /// These are only used when restoring state.
+    legacy_param_map: HashMap<String, ParamPtr>,
+    /// The plugin's bypass parameter, if it has one. Unlike the plugin wrappers, the standalone
+    /// wrapper does not add its own bypass parameter since there's no host to control it.
+    pub bypass_param_ptr: Option<ParamPtr>,
     /// The plugin's editor, if it has one. This object does not do anything on its own, but we need
     /// to instantiate this in advance so we don't need to lock the entire [`Plugin`] object when
     /// creating an editor. Wrapped in an `AtomicRefCell` because it needs to be initialized late.
     pub editor: AtomicRefCell<Option<Arc<Mutex<Box<dyn Editor>>>>>,
+    /// Owns the editor's handle while the editor's window is open. The handle is dropped when the
+    /// window closes, which breaks the reference cycle between the editor's GUI context and this
+    /// wrapper.
+    pub editor_lifecycle: Arc<EditorLifecycle>,
 
     /// A realtime-safe task queue so the plugin can schedule tasks that need to be run later on the
     /// GUI thread. See the same field in the VST3 wrapper for more information on why this looks
@@ -63,18 +96,26 @@ pub struct Wrapper<P: Plugin, B: Backend> {
     /// that using a special `MainThreadExecutor` wrapper around `AsyncExecutor`.
     pub(crate) event_loop: OsEventLoop<P::BackgroundTask, TaskExecutorWrapper<P>>,
 
-    /// This is used to grab the DPI scaling config. Not used on macOS.
-    #[allow(unused)]
+    /// This is used to grab the DPI scaling config, and it's stored in the session file together
+    /// with the plugin's state.
     config: WrapperConfig,
+    /// Where the session is saved periodically and when the application exits. `None` if the
+    /// platform's config directory could not be determined.
+    session_path: Option<PathBuf>,
 
     /// The bus and buffer configurations are static for the standalone target.
     bus_config: BusConfig,
-    buffer_config: BufferConfig,
+    pub(crate) buffer_config: BufferConfig,
 
     /// Parameter changes that have been output by the GUI that have not yet been set in the plugin.
     /// This queue will be flushed at the end of every processing cycle, just like in the plugin
     /// versions.
     unprocessed_param_changes: ArrayQueue<(ParamPtr, f32)>,
+    /// Parameter changes that were made from the GUI as part of a single batch. The GUI thread
+    /// only adds a batch to this vector once the batch has ended, and the audio thread applies all
+    /// of these changes at once at the end of a processing cycle. The audio thread only tries to
+    /// lock this, so it never blocks.
+    pending_param_batch: Mutex<Vec<(ParamPtr, f32)>>,
     /// The plugin is able to restore state through a method on the `GuiContext`. To avoid changing
     /// parameters mid-processing and running into garbled data if the host also tries to load state
     /// at the same time the restoring happens at the end of each processing call. If this zero
@@ -86,24 +127,61 @@ pub struct Wrapper<P: Plugin, B: Backend> {
     updated_state_sender: channel::Sender<PluginState>,
     /// The receiver belonging to [`new_state_sender`][Self::new_state_sender].
     updated_state_receiver: channel::Receiver<PluginState>,
+    /// The last process status returned by the plugin. The audio backends keep processing audio
+    /// regardless of the plugin's tail, but this can be used for diagnostics.
+    last_process_status: AtomicCell<ProcessStatus>,
+    /// Where the plugin currently is in its lifecycle. The audio thread only calls the plugin's
+    /// process function while this is [`LifecycleState::Processing`].
+    lifecycle_state: AtomicCell<LifecycleState>,
+    /// Set when the plugin panicked while processing audio. The plugin's state may have been left
+    /// half-modified at that point, so the audio thread stops and the plugin is not touched again
+    /// after this. State updates from the editor are ignored, and the plugin is not deactivated
+    /// when the application exits.
+    is_poisoned: AtomicBool,
+
+    /// Records the plugin's output to disk when the `--record-dir` option is set.
+    pub recorder: Option<Recorder>,
+    /// The audio thread's side of `recorder`. This is moved to the audio thread when it starts.
+    recorder_sink: Mutex<Option<RecorderSink>>,
+    /// Records parameter changes to a file when the `--record-automation` option is set.
+    automation_recorder: Option<AutomationRecorder>,
+    /// The audio thread's side of `automation_recorder`.
+    automation_recorder_sink: Option<AutomationRecorderSink>,
+    /// Plays back the automation file from the `--play-automation` option. This is moved to the
+    /// audio thread when it starts.
+    automation_player: Mutex<Option<AutomationPlayer>>,
+    /// Converts between the audio device's sample rate and the plugin's sample rate when the
+    /// `--force-sample-rate` option is set. This is moved to the audio thread when it starts.
+    resampling_adapter: Mutex<Option<ResamplingAdapter>>,
+    /// Repaint requests for the editor from the process function and the editor itself, and the
+    /// editor's [`RepaintPolicy`][crate::prelude::RepaintPolicy].
+    pub editor_repaint: Arc<RepaintRequests>,
 }
 
 /// Errors that may arise while initializing the wrapped plugins.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum WrapperError {
     /// The plugin does not accept the IO configuration from the config.
     IncompatibleConfig {
         input_channels: u32,
         output_channels: u32,
     },
-    /// The plugin returned `false` during initialization.
-    InitializationFailed,
+    /// The plugin returned an error during initialization.
+    InitializationFailed(InitError),
+    /// The file passed to `--play-automation` could not be loaded.
+    InvalidAutomationFile,
+    /// The audio device's sample rate could not be converted to the one passed to
+    /// `--force-sample-rate`.
+    ResamplerInitializationFailed,
 }
 
 struct WrapperWindowHandler {
-    /// The editor handle for the plugin's open editor. The editor should clean itself up when it
-    /// gets dropped.
-    _editor_handle: Box<dyn Any>,
+    /// Owns the editor handle for the plugin's open editor. The editor is closed when the window
+    /// handler gets dropped.
+    editor_lifecycle: Arc<EditorLifecycle>,
+    /// The editor's repaint requests. [`GuiTask::Repaint`] is forwarded to this so the editor
+    /// picks it up on its next frame.
+    editor_repaint: Arc<RepaintRequests>,
 
     /// This is used to communicate with the wrapper from the audio thread and from within the
     /// baseview window handler on the GUI thread.
@@ -116,6 +194,11 @@ pub enum GuiTask {
     Resize(u32, u32),
     /// The close window. This will cause the application to terminate.
     Close,
+    /// Redraw the editor on its next frame. Sent by
+    /// [`GuiContext::request_repaint()`][crate::prelude::GuiContext::request_repaint()]. The
+    /// process function's repaint requests don't go through this channel since sending a task can
+    /// block and wake up other threads.
+    Repaint,
 }
 
 impl WindowHandler for WrapperWindowHandler {
@@ -129,6 +212,7 @@ impl WindowHandler for WrapperWindowHandler {
                     });
                 }
                 GuiTask::Close => window.close(),
+                GuiTask::Repaint => self.editor_repaint.request(),
             }
         }
     }
@@ -138,6 +222,13 @@ impl WindowHandler for WrapperWindowHandler {
     }
 }
 
+impl Drop for WrapperWindowHandler {
+    fn drop(&mut self) {
+        // baseview drops the handler when the window is closed
+        self.editor_lifecycle.close();
+    }
+}
+
 /// Adapter to make `TaskExecutor<P>` work as a `MainThreadExecutor`.
 pub struct TaskExecutorWrapper<P: Plugin> {
     pub task_executor: Mutex<TaskExecutor<P>>,
@@ -151,8 +242,13 @@ impl<P: Plugin> MainThreadExecutor<P::BackgroundTask> for TaskExecutorWrapper<P>
 
 impl<P: Plugin, B: Backend> Wrapper<P, B> {
     /// Instantiate a new instance of the standalone wrapper. Returns an error if the plugin does
-    /// not accept the IO configuration from the wrapper config.
-    pub fn new(backend: B, config: WrapperConfig) -> Result<Arc<Self>, WrapperError> {
+    /// not accept the IO configuration from the wrapper config. The state from the session file is
+    /// restored before the plugin is initialized.
+    pub fn new(
+        backend: B,
+        config: WrapperConfig,
+        session: Option<SessionFile>,
+    ) -> Result<Arc<Self>, WrapperError> {
         let plugin = P::default();
         let task_executor_wrapper = Arc::new(TaskExecutorWrapper {
             task_executor: Mutex::new(plugin.task_executor()),
@@ -190,6 +286,25 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             }
         }
 
+        let legacy_param_map = params
+            .legacy_param_ids()
+            .into_iter()
+            .filter_map(|(legacy_id, current_id)| {
+                let param_ptr = param_map
+                    .iter()
+                    .find(|(param_id, _, _)| *param_id == current_id)
+                    .map(|(_, param_ptr, _)| *param_ptr);
+                nih_debug_assert!(
+                    param_ptr.is_some(),
+                    "Legacy parameter ID '{}' refers to unknown parameter '{}'",
+                    legacy_id,
+                    current_id
+                );
+
+                Some((legacy_id, param_ptr?))
+            })
+            .collect();
+
         // TODO: Sidechain inputs and auxiliary outputs
         if P::DEFAULT_AUX_INPUTS.is_some() {
             nih_log!("Sidechain inputs are not yet supported in this standalone version");
@@ -198,41 +313,169 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             nih_log!("Auxiliary outputs are not yet supported in this standalone version");
         }
 
+        // With `--force-sample-rate` the plugin runs at a different sample rate than the audio
+        // device, and everything the plugin interacts with uses the plugin's sample rate
+        let num_output_channels = config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS);
+        let sample_rate = config
+            .force_sample_rate
+            .unwrap_or_else(|| backend.sample_rate());
+        let resampling_adapter = match config.force_sample_rate {
+            Some(plugin_sample_rate) => {
+                let device_sample_rate = backend.sample_rate();
+                match ResamplingAdapter::new(
+                    device_sample_rate,
+                    plugin_sample_rate,
+                    num_output_channels as usize,
+                    config.period_size as usize,
+                ) {
+                    Ok(resampling_adapter) => {
+                        nih_log!(
+                            "Converting between the audio device's {device_sample_rate} Hz and \
+                             the plugin's {plugin_sample_rate} Hz, this adds {} samples ({:.1} \
+                             ms) of latency",
+                            resampling_adapter.latency_samples(),
+                            resampling_adapter.latency_seconds() * 1000.0
+                        );
+                        Some(resampling_adapter)
+                    }
+                    Err(err) => {
+                        nih_error!("{:#}", err);
+                        return Err(WrapperError::ResamplerInitializationFailed);
+                    }
+                }
+            }
+            None => None,
+        };
+        // The resampling adapter uses the configured period size for the plugin's blocks, since
+        // the device's period size doesn't mean much at a different sample rate
+        let block_size = if resampling_adapter.is_some() {
+            config.period_size
+        } else {
+            backend.period_size()
+        };
+
+        let (recorder, recorder_sink) = match &config.record_dir {
+            Some(record_dir) => {
+                let (recorder, recorder_sink) = Recorder::new(
+                    record_dir.clone(),
+                    P::NAME,
+                    config.record_dry,
+                    num_output_channels as u16,
+                    sample_rate,
+                );
+
+                (Some(recorder), Some(recorder_sink))
+            }
+            None => (None, None),
+        };
+
+        let bypass_param_ptr = find_bypass_param(&param_map);
+        let param_map: HashMap<String, ParamPtr> = param_map
+            .into_iter()
+            .map(|(param_id, param_ptr, _)| (param_id, param_ptr))
+            .collect();
+        let (automation_recorder, automation_recorder_sink) = match &config.record_automation {
+            Some(path) => {
+                let (automation_recorder, automation_recorder_sink) = AutomationRecorder::new(
+                    path.clone(),
+                    P::NAME,
+                    sample_rate,
+                    params.clone(),
+                    &param_map,
+                );
+
+                (Some(automation_recorder), Some(automation_recorder_sink))
+            }
+            None => (None, None),
+        };
+        let automation_player = match &config.play_automation {
+            Some(path) => match AutomationPlayer::load(path, P::NAME, sample_rate, &param_map) {
+                Ok(automation_player) => Some(automation_player),
+                Err(err) => {
+                    nih_error!("{:#}", err);
+                    return Err(WrapperError::InvalidAutomationFile);
+                }
+            },
+            None => None,
+        };
+
+        // The plugin has not been initialized yet, so the state can be restored directly
+        let (session_path, initial_state) = match session {
+            Some(session) => (Some(session.path), session.initial_state),
+            None => (None, None),
+        };
+        let param_value_validator = ParamValueValidator::default();
+        let has_initial_state = initial_state.is_some();
+        if let Some(mut state) = initial_state {
+            unsafe {
+                state::deserialize_object::<P>(
+                    &mut state,
+                    params.clone(),
+                    |param_id| {
+                        param_map
+                            .get(param_id)
+                            .or_else(|| legacy_param_map.get(param_id))
+                            .copied()
+                    },
+                    None,
+                    false,
+                    &param_value_validator,
+                );
+            }
+        }
+
         let wrapper = Arc::new(Wrapper {
             backend: AtomicRefCell::new(backend),
 
             plugin: Mutex::new(plugin),
             task_executor_wrapper: task_executor_wrapper.clone(),
             params,
-            known_parameters: param_map.iter().map(|(_, ptr, _)| *ptr).collect(),
-            param_map: param_map
-                .into_iter()
-                .map(|(param_id, param_ptr, _)| (param_id, param_ptr))
-                .collect(),
+            known_parameters: param_map.values().copied().collect(),
+            bypass_param_ptr,
+            param_changes: Arc::new(ParamChangeTracker::new(param_map.values().copied())),
+            param_value_validator,
+            param_map,
+            legacy_param_map,
             // Initialized later as it needs a reference to the wrapper for the async executor
             editor: AtomicRefCell::new(None),
+            editor_lifecycle: Arc::default(),
 
             event_loop: OsEventLoop::new_and_spawn(task_executor_wrapper),
 
             bus_config: BusConfig {
                 num_input_channels: config.input_channels.unwrap_or(P::DEFAULT_INPUT_CHANNELS),
-                num_output_channels: config.output_channels.unwrap_or(P::DEFAULT_OUTPUT_CHANNELS),
+                num_output_channels,
                 // TODO: Expose additional sidechain IO in the JACK backend
                 aux_input_busses: AuxiliaryIOConfig::default(),
                 aux_output_busses: AuxiliaryIOConfig::default(),
             },
             buffer_config: BufferConfig {
-                sample_rate: config.sample_rate,
-                min_buffer_size: None,
-                max_buffer_size: config.period_size,
+                sample_rate,
+                // The backends and the resampling adapter always call the plugin with full blocks
+                min_buffer_size: Some(block_size),
+                max_buffer_size: block_size,
                 // TODO: Detect JACK freewheeling and report it here
                 process_mode: ProcessMode::Realtime,
             },
             config,
+            session_path,
 
             unprocessed_param_changes: ArrayQueue::new(EVENT_QUEUE_CAPACITY),
+            pending_param_batch: Mutex::new(Vec::with_capacity(EVENT_QUEUE_CAPACITY)),
             updated_state_sender,
             updated_state_receiver,
+
+            last_process_status: AtomicCell::new(ProcessStatus::Normal),
+            is_poisoned: AtomicBool::new(false),
+            lifecycle_state: AtomicCell::new(LifecycleState::Setup),
+
+            recorder,
+            recorder_sink: Mutex::new(recorder_sink),
+            automation_recorder,
+            automation_recorder_sink,
+            automation_player: Mutex::new(automation_player),
+            resampling_adapter: Mutex::new(resampling_adapter),
+            editor_repaint: Arc::default(),
         });
 
         // The editor needs to be initialized later so the Async executor can work.
@@ -244,7 +487,8 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                     let wrapper = wrapper.clone();
 
                     move |task| {
-                        let task_posted = wrapper.event_loop.schedule_background(task);
+                        let priority = P::background_task_priority(&task);
+                        let task_posted = wrapper.event_loop.schedule_background(task, priority);
                         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
                     }
                 }),
@@ -275,14 +519,18 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                 unsafe { param.update_smoother(wrapper.buffer_config.sample_rate, true) };
             }
 
-            if !plugin.initialize(
-                &wrapper.bus_config,
-                &wrapper.buffer_config,
-                &mut wrapper.make_init_context(),
-            ) {
-                return Err(WrapperError::InitializationFailed);
+            if has_initial_state {
+                plugin.state_restored();
             }
+            plugin
+                .try_initialize(
+                    &wrapper.bus_config,
+                    &wrapper.buffer_config,
+                    &mut wrapper.make_init_context(),
+                )
+                .map_err(WrapperError::InitializationFailed)?;
             process_wrapper(|| plugin.reset());
+            transition_lifecycle_state(&wrapper.lifecycle_state, LifecycleState::Active);
         }
 
         Ok(wrapper)
@@ -299,6 +547,7 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         // We'll spawn a separate thread to handle IO and to process audio. This audio thread should
         // terminate together with this function.
         let terminate_audio_thread = Arc::new(AtomicBool::new(false));
+        transition_lifecycle_state(&self.lifecycle_state, LifecycleState::Processing);
         let audio_thread = {
             let this = self.clone();
             let terminate_audio_thread = terminate_audio_thread.clone();
@@ -306,6 +555,35 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
             thread::spawn(move || this.run_audio_thread(terminate_audio_thread, gui_task_sender))
         };
 
+        // The session is saved periodically so the plugin's state survives the application
+        // getting killed. Dropping the sender stops the thread.
+        let (stop_autosave_sender, stop_autosave_receiver) = channel::bounded::<()>(0);
+        let autosave_thread = self.session_path.is_some().then(|| {
+            let this = self.clone();
+            thread::spawn(move || {
+                while let Err(channel::RecvTimeoutError::Timeout) =
+                    stop_autosave_receiver.recv_timeout(session::AUTOSAVE_INTERVAL)
+                {
+                    this.save_session();
+                }
+            })
+        });
+
+        // Recording can be toggled from the terminal. This thread blocks on stdin, so it is simply
+        // left running until the application exits.
+        if self.recorder.is_some() {
+            let this = self.clone();
+            thread::spawn(move || {
+                for _ in std::io::stdin().lock().lines() {
+                    if let Some(recorder) = &this.recorder {
+                        recorder.toggle();
+                    }
+                }
+            });
+
+            nih_log!("Press Enter to start or stop recording");
+        }
+
         match self.editor.borrow().clone() {
             Some(editor) => {
                 let context = self.clone().make_gui_context(gui_task_sender);
@@ -320,6 +598,8 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                 };
 
                 let (width, height) = editor.lock().size();
+                let editor_lifecycle = self.editor_lifecycle.clone();
+                let editor_repaint = self.editor_repaint.clone();
                 Window::open_blocking(
                     WindowOpenOptions {
                         title: String::from(P::NAME),
@@ -335,15 +615,18 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
                         //       baseview does not support this yet. Once this is added, we should
                         //       immediately close the parent window when this happens so the loop
                         //       can exit.
-                        let editor_handle = editor.lock().spawn(
-                            ParentWindowHandle {
-                                handle: window.raw_window_handle(),
-                            },
-                            context,
-                        );
+                        editor_lifecycle.open(|| {
+                            editor.lock().spawn(
+                                ParentWindowHandle {
+                                    handle: window.raw_window_handle(),
+                                },
+                                context,
+                            )
+                        });
 
                         WrapperWindowHandler {
-                            _editor_handle: editor_handle,
+                            editor_lifecycle,
+                            editor_repaint,
                             gui_task_receiver,
                         }
                     },
@@ -359,14 +642,45 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
 
         terminate_audio_thread.store(true, Ordering::SeqCst);
         audio_thread.join().unwrap();
+        transition_lifecycle_state(&self.lifecycle_state, LifecycleState::Active);
+
+        // The audio backends stop processing right away, even if the plugin still wanted to output
+        // its tail
+        if let ProcessStatus::Tail(samples) = self.last_process_status() {
+            nih_log!("Stopped processing with {samples} samples of the plugin's tail remaining");
+        }
+
+        // The last take needs to be finalized before the application exits
+        if let Some(recorder) = &self.recorder {
+            recorder.shutdown();
+        }
+        if let Some(automation_recorder) = &self.automation_recorder {
+            automation_recorder.shutdown();
+        }
+
+        drop(stop_autosave_sender);
+        if let Some(autosave_thread) = autosave_thread {
+            autosave_thread.join().unwrap();
+        }
+        self.save_session();
 
         // Some plugins may use this to clean up resources. Should not be needed for the standalone
-        // application, but it seems like a good idea to stay consistent.
-        self.plugin.lock().deactivate();
+        // application, but it seems like a good idea to stay consistent. A plugin that panicked
+        // should not be touched again.
+        if !self.is_poisoned.load(Ordering::SeqCst) {
+            self.plugin.lock().deactivate();
+        }
+        transition_lifecycle_state(&self.lifecycle_state, LifecycleState::Setup);
 
         Ok(())
     }
 
+    /// The status returned by the plugin's last `process()` call, or [`ProcessStatus::Normal`] if
+    /// the plugin hasn't processed any audio yet.
+    pub fn last_process_status(&self) -> ProcessStatus {
+        self.last_process_status.load()
+    }
+
     /// Set a parameter based on a `ParamPtr`. The value will be updated at the end of the next
     /// processing cycle, and this won't do anything if the parameter has not been registered by the
     /// plugin.
@@ -377,6 +691,10 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         if !self.known_parameters.contains(&param) {
             return false;
         }
+        let normalized = match self.validate_normalized_value(param, normalized) {
+            Some(normalized) => normalized,
+            None => return false,
+        };
 
         let push_successful = self
             .unprocessed_param_changes
@@ -387,6 +705,33 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         push_successful
     }
 
+    /// Set multiple parameters at once. Unlike [`set_parameter()`][Self::set_parameter()], all of
+    /// these changes are guaranteed to be applied at the end of the same processing cycle. Unknown
+    /// parameters are ignored.
+    pub fn set_parameters_batched(&self, changes: impl IntoIterator<Item = (ParamPtr, f32)>) {
+        self.pending_param_batch.lock().extend(
+            changes
+                .into_iter()
+                .filter(|(param, _)| self.known_parameters.contains(param))
+                .filter_map(|(param, normalized)| {
+                    self.validate_normalized_value(param, normalized)
+                        .map(|normalized| (param, normalized))
+                }),
+        );
+    }
+
+    /// Clamp a normalized value for `param` to `[0, 1]`, or return `None` if it is NaN or infinite.
+    fn validate_normalized_value(&self, param: ParamPtr, normalized: f32) -> Option<f32> {
+        self.param_value_validator
+            .validate_normalized(normalized, || {
+                self.param_map
+                    .iter()
+                    .find(|(_, ptr)| **ptr == param)
+                    .map(|(param_id, _)| param_id.clone())
+                    .unwrap_or_else(|| format!("{param:?}"))
+            })
+    }
+
     /// Get the plugin's state object, may be called by the plugin's GUI as part of its own preset
     /// management. The wrapper doesn't use these functions and serializes and deserializes directly
     /// the JSON in the relevant plugin API methods instead.
@@ -405,6 +750,14 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
     pub fn set_state_object(&self, state: PluginState) {
+        nih_debug_assert_not_audio_thread!();
+
+        // The audio thread has stopped if the plugin panicked, so nothing would receive the state
+        if self.is_poisoned.load(Ordering::SeqCst) {
+            nih_error!("The plugin panicked earlier, ignoring the state update");
+            return;
+        }
+
         match self.updated_state_sender.send(state) {
             Ok(_) => {
                 // As mentioned above, the state object will be passed back to this thread
@@ -421,6 +774,20 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         }
     }
 
+    /// Write the wrapper's configuration and the plugin's current state to the session file. This
+    /// takes the same state snapshot as [`get_state_object()`][Self::get_state_object()], so it
+    /// must not be called from the audio thread.
+    fn save_session(&self) {
+        nih_debug_assert_not_audio_thread!();
+
+        if let Some(session_path) = &self.session_path {
+            let session = Session::new(P::NAME, &self.config, self.get_state_object());
+            if let Err(err) = session.save(session_path) {
+                nih_error!("Could not save the session: {:#}", err);
+            }
+        }
+    }
+
     /// The audio thread. This should be called from another thread, and it will run until
     /// `should_terminate` is `true`.
     fn run_audio_thread(
@@ -428,112 +795,286 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         should_terminate: Arc<AtomicBool>,
         gui_task_sender: channel::Sender<GuiTask>,
     ) {
-        self.clone().backend.borrow_mut().run(
-            move |buffer, transport, input_events, output_events| {
-                // TODO: This process wrapper should actually be in the backends (since the backends
-                //       should also not allocate in their audio callbacks), but that's a bit more
-                //       error prone
-                process_wrapper(|| {
-                    if should_terminate.load(Ordering::SeqCst) {
-                        return false;
-                    }
-
-                    let sample_rate = self.buffer_config.sample_rate;
-                    let mut plugin = self.plugin.lock();
-                    if let ProcessStatus::Error(err) = plugin.process(
-                        buffer,
-                        // TODO: Provide extra inputs and outputs in the JACk backend
-                        &mut AuxiliaryBuffers {
-                            inputs: &mut [],
-                            outputs: &mut [],
-                        },
-                        &mut self.make_process_context(transport, input_events, output_events),
-                    ) {
-                        nih_error!("The plugin returned an error while processing:");
-                        nih_error!("{}", err);
-
-                        let push_successful = gui_task_sender.send(GuiTask::Close).is_ok();
-                        nih_debug_assert!(
-                            push_successful,
-                            "Could not queue window close, the editor will remain open"
-                        );
+        let mut recorder_sink = self.recorder_sink.lock().take();
+        let mut midi_input_router = MidiInputRouter::new(P::MIDI_INPUT_CONFIG);
+        // The routed events are stored here so routing the input events doesn't allocate on the
+        // audio thread
+        let mut routed_input_events = Vec::with_capacity(EVENT_QUEUE_CAPACITY);
+        // Used to process a part of the buffer when the buffer is split at note events for
+        // `P::SAMPLE_ACCURATE_EVENTS`. This holds no channel slices outside of those split blocks.
+        let mut split_buffer = Buffer::default();
+        unsafe {
+            split_buffer.with_raw_vec(|channel_slices| {
+                channel_slices.reserve_exact(self.bus_config.num_output_channels as usize)
+            })
+        };
+        let mut automation_player = self.automation_player.lock().take();
+        // Used as the position for recording and playing back automation
+        let mut num_processed_samples = 0u64;
+        let mut transport_tracker = TransportTracker::default();
+        let mut resampling_adapter = self.resampling_adapter.lock().take();
+        let wrapper = self.clone();
+
+        // This processes a single block at the plugin's sample rate. Depending on the
+        // `--force-sample-rate` option, this is either called directly from the backend's callback
+        // or through the resampling adapter.
+        let mut process_block =
+            move |buffer: &mut Buffer,
+                  mut transport: Transport,
+                  input_events: &[NoteEvent],
+                  output_events: &mut Vec<NoteEvent>| {
+                if should_terminate.load(Ordering::SeqCst) {
+                    return false;
+                }
 
-                        return false;
+                // The plugin must never be called outside of its active processing period. The
+                // backend is kept running with silence instead.
+                if self.lifecycle_state.load() != LifecycleState::Processing {
+                    for channel in buffer.as_slice() {
+                        channel.fill(0.0);
                     }
 
-                    // Any output note events are now in a vector that can be processed by the
-                    // audio/MIDI backend
+                    return true;
+                }
+
+                let sample_rate = self.buffer_config.sample_rate;
+                let stream_sample_rate = transport.sample_rate;
+                transport_tracker.update(&mut transport, buffer.len());
+                if let Some(recorder_sink) = &mut recorder_sink {
+                    recorder_sink.write_dry(buffer.as_slice_immutable());
+                }
 
-                    // We'll always write these events to the first sample, so even when we add note
-                    // output we shouldn't have to think about interleaving events here
-                    let mut parameter_values_changed = false;
+                // Automation changes are applied in between process calls, just like the
+                // changes made from the GUI
+                let mut parameter_values_changed = false;
+                if let Some(automation_player) = &mut automation_player {
                     while let Some((param_ptr, normalized_value)) =
-                        self.unprocessed_param_changes.pop()
+                        automation_player.next_change(num_processed_samples)
                     {
+                        // Read-only parameters are outputs of the plugin, so they cannot be
+                        // automated
+                        if unsafe { param_ptr.flags() }.contains(ParamFlags::READ_ONLY) {
+                            continue;
+                        }
+                        let normalized_value =
+                            match self.validate_normalized_value(param_ptr, normalized_value) {
+                                Some(normalized_value) => normalized_value,
+                                None => continue,
+                            };
+
                         unsafe { param_ptr.set_normalized_value(normalized_value) };
                         unsafe { param_ptr.update_smoother(sample_rate, false) };
+                        self.param_changes.record(
+                            param_ptr,
+                            ParamChangeSource::HostAutomation,
+                            normalized_value,
+                        );
                         parameter_values_changed = true;
                     }
+                }
 
-                    // Allow the editor to react to the new parameter values if the editor uses a
-                    // reactive data binding model
-                    if parameter_values_changed {
-                        self.notify_param_values_changed();
+                // Events on channels the plugin doesn't respond to are dropped here, and MPE
+                // member channel messages are translated to polyphonic expressions
+                routed_input_events.clear();
+                for event in input_events {
+                    midi_input_router.route(*event, |event| routed_input_events.push(event));
+                }
+
+                let mut plugin = self.plugin.lock();
+                // A panicking plugin is treated the same way as a plugin that returned an
+                // error, except that it's also poisoned so nothing calls into it anymore. The
+                // panic itself will already have been logged by our panic hook.
+                let status = panic::catch_unwind(AssertUnwindSafe(|| {
+                    if P::SAMPLE_ACCURATE_EVENTS {
+                        self.process_split_at_events(
+                            &mut plugin,
+                            buffer,
+                            &mut split_buffer,
+                            transport,
+                            &mut routed_input_events,
+                            output_events,
+                        )
+                    } else {
+                        plugin.process(
+                            buffer,
+                            // TODO: Provide extra inputs and outputs in the JACk backend
+                            &mut AuxiliaryBuffers {
+                                inputs: &mut [],
+                                outputs: &mut [],
+                            },
+                            &mut self.make_process_context(
+                                transport,
+                                &routed_input_events,
+                                output_events,
+                            ),
+                        )
+                    }
+                }))
+                .unwrap_or_else(|_| {
+                    self.is_poisoned.store(true, Ordering::SeqCst);
+                    ProcessStatus::Error("The plugin panicked")
+                });
+                self.last_process_status.store(status);
+                if let ProcessStatus::Error(err) = status {
+                    nih_error!("The plugin returned an error while processing:");
+                    nih_error!("{}", err);
+
+                    let push_successful = gui_task_sender.send(GuiTask::Close).is_ok();
+                    nih_debug_assert!(
+                        push_successful,
+                        "Could not queue window close, the editor will remain open"
+                    );
+
+                    return false;
+                }
+
+                if let Some(recorder_sink) = &mut recorder_sink {
+                    recorder_sink.write_output(buffer.as_slice_immutable(), stream_sample_rate);
+                }
+                num_processed_samples += buffer.len() as u64;
+
+                // Any output note events are now in a vector that can be processed by the
+                // audio/MIDI backend
+
+                // We'll always write these events to the first sample, so even when we add note
+                // output we shouldn't have to think about interleaving events here
+                while let Some((param_ptr, normalized_value)) = self.unprocessed_param_changes.pop()
+                {
+                    unsafe { param_ptr.set_normalized_value(normalized_value) };
+                    unsafe { param_ptr.update_smoother(sample_rate, false) };
+                    self.param_changes.record(
+                        param_ptr,
+                        ParamChangeSource::GuiGesture,
+                        normalized_value,
+                    );
+                    if let Some(automation_recorder_sink) = &self.automation_recorder_sink {
+                        automation_recorder_sink.record(
+                            num_processed_samples,
+                            AutomationSource::Gui,
+                            param_ptr,
+                            normalized_value,
+                        );
                     }
+                    parameter_values_changed = true;
+                }
 
-                    // After processing audio, we'll check if the editor has sent us updated plugin
-                    // state.  We'll restore that here on the audio thread to prevent changing the
-                    // values during the process call and also to prevent inconsistent state when
-                    // the host also wants to load plugin state.
-                    // FIXME: Zero capacity channels allocate on receiving, find a better
-                    //        alternative that doesn't do that
-                    let updated_state = permit_alloc(|| self.updated_state_receiver.try_recv());
-                    if let Ok(mut state) = updated_state {
-                        unsafe {
-                            state::deserialize_object::<P>(
-                                &mut state,
-                                self.params.clone(),
-                                |param_id| self.param_map.get(param_id).copied(),
-                                Some(&self.buffer_config),
+                // Batched changes are applied all at once. If the GUI thread is currently
+                // adding a batch, then this batch will be applied after the next cycle instead.
+                if let Some(mut pending_param_batch) = self.pending_param_batch.try_lock() {
+                    for (param_ptr, normalized_value) in pending_param_batch.drain(..) {
+                        unsafe { param_ptr.set_normalized_value(normalized_value) };
+                        unsafe { param_ptr.update_smoother(sample_rate, false) };
+                        self.param_changes.record(
+                            param_ptr,
+                            ParamChangeSource::GuiGesture,
+                            normalized_value,
+                        );
+                        if let Some(automation_recorder_sink) = &self.automation_recorder_sink {
+                            automation_recorder_sink.record(
+                                num_processed_samples,
+                                AutomationSource::Gui,
+                                param_ptr,
+                                normalized_value,
                             );
                         }
+                        parameter_values_changed = true;
+                    }
+                }
 
-                        self.notify_param_values_changed();
-
-                        // FIXME: This is obviously not realtime-safe, but loading presets without
-                        //         doing this could lead to inconsistencies. It's the plugin's
-                        //         responsibility to not perform any realtime-unsafe work when the
-                        //         initialize function is called a second time if it supports
-                        //         runtime preset loading.
-                        permit_alloc(|| {
-                            plugin.initialize(
-                                &self.bus_config,
-                                &self.buffer_config,
-                                &mut self.make_init_context(),
-                            )
-                        });
-                        plugin.reset();
+                // Allow the editor to react to the new parameter values if the editor uses a
+                // reactive data binding model
+                if parameter_values_changed {
+                    self.notify_param_values_changed();
+                }
+
+                // After processing audio, we'll check if the editor has sent us updated plugin
+                // state.  We'll restore that here on the audio thread to prevent changing the
+                // values during the process call and also to prevent inconsistent state when
+                // the host also wants to load plugin state.
+                // FIXME: Zero capacity channels allocate on receiving, find a better
+                //        alternative that doesn't do that
+                let updated_state = permit_alloc(|| self.updated_state_receiver.try_recv());
+                if let Ok(mut state) = updated_state {
+                    unsafe {
+                        state::deserialize_object::<P>(
+                            &mut state,
+                            self.params.clone(),
+                            |param_id| {
+                                self.param_map
+                                    .get(param_id)
+                                    .or_else(|| self.legacy_param_map.get(param_id))
+                                    .copied()
+                            },
+                            Some(&self.buffer_config),
+                            true,
+                            &self.param_value_validator,
+                        );
+                    }
 
-                        // We'll pass the state object back to the GUI thread so deallocation can
-                        // happen there without potentially blocking the audio thread
-                        if let Err(err) = self.updated_state_sender.send(state) {
-                            nih_debug_assert_failure!(
-                                "Failed to send state object back to GUI thread: {}",
+                    self.param_changes.record_all(ParamChangeSource::Internal);
+                    self.notify_param_values_changed();
+
+                    // FIXME: This is obviously not realtime-safe, but loading presets without
+                    //         doing this could lead to inconsistencies. It's the plugin's
+                    //         responsibility to not perform any realtime-unsafe work when the
+                    //         initialize function is called a second time if it supports
+                    //         runtime preset loading.
+                    permit_alloc(|| {
+                        plugin.state_restored();
+                        if let Err(err) = plugin.try_initialize(
+                            &self.bus_config,
+                            &self.buffer_config,
+                            &mut self.make_init_context(),
+                        ) {
+                            nih_error!(
+                                "Failed to reinitialize the plugin after loading state: {}",
                                 err
                             );
-                        };
-                    }
+                        }
+                    });
+                    plugin.reset();
+                    midi_input_router.reset();
+
+                    // We'll pass the state object back to the GUI thread so deallocation can
+                    // happen there without potentially blocking the audio thread
+                    if let Err(err) = self.updated_state_sender.send(state) {
+                        nih_debug_assert_failure!(
+                            "Failed to send state object back to GUI thread: {}",
+                            err
+                        );
+                    };
+                }
 
-                    true
+                true
+            };
+
+        wrapper
+            .backend
+            .borrow_mut()
+            .run(move |buffer, transport, input_events, output_events| {
+                // TODO: This process wrapper should actually be in the backends (since the backends
+                //       should also not allocate in their audio callbacks), but that's a bit more
+                //       error prone
+                process_wrapper(|| match &mut resampling_adapter {
+                    Some(resampling_adapter) => resampling_adapter.process(
+                        buffer,
+                        transport,
+                        input_events,
+                        output_events,
+                        &mut process_block,
+                    ),
+                    None => process_block(buffer, transport, input_events, output_events),
                 })
-            },
-        );
+            });
     }
 
     /// Tell the editor that the parameter values have changed, if the plugin has an editor. In the
     /// off-chance that the editor instance is currently locked then nothing will happen, and the
     /// request can safely be ignored.
     fn notify_param_values_changed(&self) {
+        // Editors that only redraw on request should still show the new values
+        self.editor_repaint.request();
+
         if let Some(editor) = self.editor.borrow().as_ref() {
             match editor.try_lock() {
                 Some(editor) => editor.param_values_changed(),
@@ -552,6 +1093,7 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         Arc::new(WrapperGuiContext {
             wrapper: self,
             gui_task_sender,
+            batch: Default::default(),
         })
     }
 
@@ -559,12 +1101,99 @@ impl<P: Plugin, B: Backend> Wrapper<P, B> {
         WrapperInitContext { wrapper: self }
     }
 
+    /// Process `buffer` in smaller blocks that start at the note events in `input_events`, for
+    /// [`Plugin::SAMPLE_ACCURATE_EVENTS`]. Each block only receives the events at its start, with
+    /// their timings rebased to the start of that block. Any events past the end of the buffer are
+    /// sent with the last block.
+    fn process_split_at_events(
+        &self,
+        plugin: &mut P,
+        buffer: &mut Buffer,
+        split_buffer: &mut Buffer,
+        transport: Transport,
+        input_events: &mut [NoteEvent],
+        output_events: &mut Vec<NoteEvent>,
+    ) -> ProcessStatus {
+        let num_samples = buffer.len();
+        let mut status = ProcessStatus::Normal;
+        let mut block_start = 0;
+        let mut event_idx = 0;
+        while block_start < num_samples {
+            let block_events_start_idx = event_idx;
+            while event_idx < input_events.len()
+                && input_events[event_idx].timing() as usize <= block_start
+            {
+                event_idx += 1;
+            }
+            let block_end = match input_events.get(event_idx) {
+                Some(event) if (event.timing() as usize) < num_samples => event.timing() as usize,
+                _ => {
+                    event_idx = input_events.len();
+                    num_samples
+                }
+            };
+
+            let block_events = &mut input_events[block_events_start_idx..event_idx];
+            for event in block_events.iter_mut() {
+                event.subtract_timing(block_start as u32);
+            }
+
+            // SAFETY: The split buffer's slices point into `buffer`, and they are removed again
+            //         before this function returns
+            unsafe {
+                split_buffer.with_raw_vec(|channel_slices| {
+                    channel_slices.clear();
+                    for channel in buffer.as_slice() {
+                        channel_slices.push(&mut *(&mut channel[block_start..block_end] as *mut _));
+                    }
+                })
+            };
+
+            let mut block_transport = transport.clone();
+            if block_start > 0 {
+                block_transport.continuous_pos_samples += block_start as i64;
+                block_transport.position_jumped = false;
+                if block_transport.playing {
+                    block_transport.pos_samples = block_transport
+                        .pos_samples
+                        .map(|pos_samples| pos_samples + block_start as i64);
+                }
+            }
+
+            let output_events_start_idx = output_events.len();
+            status = plugin.process(
+                split_buffer,
+                // TODO: Provide extra inputs and outputs in the JACk backend
+                &mut AuxiliaryBuffers {
+                    inputs: &mut [],
+                    outputs: &mut [],
+                },
+                &mut self.make_process_context(block_transport, block_events, output_events),
+            );
+            for event in &mut output_events[output_events_start_idx..] {
+                event.add_timing(block_start as u32);
+            }
+
+            if let ProcessStatus::Error(_) = status {
+                break;
+            }
+            block_start = block_end;
+        }
+
+        unsafe { split_buffer.with_raw_vec(|channel_slices| channel_slices.clear()) };
+
+        status
+    }
+
     fn make_process_context<'a>(
         &'a self,
-        transport: Transport,
+        mut transport: Transport,
         input_events: &'a [NoteEvent],
         output_events: &'a mut Vec<NoteEvent>,
     ) -> WrapperProcessContext<'a, P, B> {
+        // Plugins should never see transport information they did not ask for
+        transport.retain(P::REQUIRED_TRANSPORT);
+
         WrapperProcessContext {
             wrapper: self,
             input_events,
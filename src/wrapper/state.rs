@@ -2,13 +2,19 @@
 //! to plugins through the [`GuiContext`][crate::prelude::GuiContext].
 
 use anyhow::{Context, Result};
+use crossbeam::atomic::AtomicCell;
 use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
 use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
 use std::sync::Arc;
 
 use crate::params::internals::ParamPtr;
-use crate::params::{Param, ParamMut, Params};
+use crate::params::{Param, ParamFlags, ParamMut, Params};
 use crate::plugin::{BufferConfig, Plugin};
+use crate::wrapper::util::ParamValueValidator;
 
 // These state objects are also exposed directly to the plugin so it can do its own internal preset
 // management
@@ -51,6 +57,67 @@ pub struct PluginState {
     /// The individual fields are also serialized as JSON so they can safely be restored
     /// independently of the other fields.
     pub fields: BTreeMap<String, String>,
+    /// A random UUID that identifies the plugin instance that saved this state. This is only
+    /// included when the host saves the plugin's state, and it is restored when the host loads that
+    /// state again. It's used to derive
+    /// [`ProcessContext::deterministic_seed()`][crate::prelude::ProcessContext::deterministic_seed()].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<String>,
+}
+
+/// A random 128-bit identifier for a plugin instance, formatted as a version 4 UUID. Every wrapper
+/// generates one when the instance is created. It is stored in the state saved by the host so
+/// the instance keeps the same ID when a project is reopened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstanceId(u128);
+
+impl InstanceId {
+    /// Generate a new random instance ID.
+    pub(crate) fn new() -> Self {
+        // `RandomState` is seeded using the operating system's random number generator, which
+        // avoids needing another dependency just for this
+        let random_u64 = |salt: u64| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(salt);
+            hasher.finish()
+        };
+        let id = ((random_u64(0) as u128) << 64) | random_u64(1) as u128;
+
+        // The version and variant bits mark this as a random (version 4) UUID
+        Self((id & !(0xf << 76) & !(0b11 << 62)) | (0x4 << 76) | (0b10 << 62))
+    }
+
+    /// A 64-bit seed derived from this ID.
+    pub(crate) fn seed(self) -> u64 {
+        (self.0 >> 64) as u64 ^ self.0 as u64
+    }
+}
+
+impl Display for InstanceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            (self.0 >> 96) as u32,
+            (self.0 >> 80) as u16,
+            (self.0 >> 64) as u16,
+            (self.0 >> 48) as u16,
+            self.0 & 0xffff_ffff_ffff
+        )
+    }
+}
+
+impl FromStr for InstanceId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex: String = s.chars().filter(|c| *c != '-').collect();
+        if hex.len() != 32 {
+            return Err(());
+        }
+
+        u128::from_str_radix(&hex, 16).map(Self).map_err(|_| ())
+    }
 }
 
 /// Create a parameters iterator from the hashtables stored in the plugin wrappers. This avoids
@@ -66,14 +133,17 @@ pub(crate) fn make_params_iter<'a>(
 }
 
 /// Create a getter function that gets a parameter from the hashtables stored in the plugin by
-/// string ID.
+/// string ID. Legacy parameter IDs from `legacy_param_id_to_hash` resolve to the parameter they
+/// were renamed to.
 pub(crate) fn make_params_getter<'a>(
     param_by_hash: &'a HashMap<u32, ParamPtr>,
     param_id_to_hash: &'a HashMap<String, u32>,
+    legacy_param_id_to_hash: &'a HashMap<String, u32>,
 ) -> impl Fn(&str) -> Option<ParamPtr> + 'a {
     |param_id_str| {
         param_id_to_hash
             .get(param_id_str)
+            .or_else(|| legacy_param_id_to_hash.get(param_id_str))
             .and_then(|hash| param_by_hash.get(hash))
             .copied()
     }
@@ -87,11 +157,15 @@ pub(crate) unsafe fn serialize_object<'a, P: Plugin>(
     plugin_params: Arc<dyn Params>,
     params_iter: impl IntoIterator<Item = (&'a String, ParamPtr)>,
 ) -> PluginState {
+    nih_debug_assert_not_audio_thread!();
+
     // We'll serialize parameter values as a simple `string_param_id: display_value` map.
     // NOTE: If the plugin is being modulated (and the plugin is a CLAP plugin in Bitwig Studio),
     //       then this should save the values without any modulation applied to it
+    // Read-only parameters are outputs of the plugin, so their values are not part of its state
     let params: BTreeMap<_, _> = params_iter
         .into_iter()
+        .filter(|(_, param_ptr)| !param_ptr.flags().contains(ParamFlags::READ_ONLY))
         .map(|(param_id_str, param_ptr)| match param_ptr {
             ParamPtr::FloatParam(p) => (
                 param_id_str.clone(),
@@ -126,17 +200,20 @@ pub(crate) unsafe fn serialize_object<'a, P: Plugin>(
         version: String::from(P::VERSION),
         params,
         fields,
+        instance_id: None,
     }
 }
 
 /// Serialize a plugin's state to a vector containing JSON data. This can (and should) be shared
 /// across plugin formats. If the `zstd` feature is enabled, then the state will be compressed using
-/// Zstandard.
+/// Zstandard. Unlike [`serialize_object()`], this also stores the plugin's instance ID.
 pub(crate) unsafe fn serialize_json<'a, P: Plugin>(
     plugin_params: Arc<dyn Params>,
     params_iter: impl IntoIterator<Item = (&'a String, ParamPtr)>,
+    instance_id: InstanceId,
 ) -> Result<Vec<u8>> {
-    let plugin_state = serialize_object::<P>(plugin_params, params_iter);
+    let mut plugin_state = serialize_object::<P>(plugin_params, params_iter);
+    plugin_state.instance_id = Some(instance_id.to_string());
     let json = serde_json::to_vec(&plugin_state).context("Could not format as JSON")?;
 
     #[cfg(feature = "zstd")]
@@ -158,7 +235,12 @@ pub(crate) unsafe fn serialize_json<'a, P: Plugin>(
 /// include expensive user written code. See [`make_params_getter()`].
 ///
 /// Make sure to reinitialize plugin after deserializing the state so it can react to the new
-/// parameter values. The smoothers have already been reset by this function.
+/// parameter values. The smoothers have already been reset by this function. If `is_processing` is
+/// set and [`Plugin::STATE_LOAD_SMOOTHING_MS`] is non-zero, then the smoothers will instead ramp to
+/// the new values.
+///
+/// Out of range values are clamped to the parameter's range, and NaN and infinite values are
+/// rejected by `validator` so the parameter keeps its current value.
 ///
 /// The [`Plugin`] argument is used to call [`Plugin::filter_state()`] just before loading the
 /// state.
@@ -167,11 +249,14 @@ pub(crate) unsafe fn deserialize_object<P: Plugin>(
     plugin_params: Arc<dyn Params>,
     params_getter: impl Fn(&str) -> Option<ParamPtr>,
     current_buffer_config: Option<&BufferConfig>,
+    is_processing: bool,
+    validator: &ParamValueValidator,
 ) -> bool {
     // This lets the plugin perform migrations on old state if needed
     P::filter_state(state);
 
     let sample_rate = current_buffer_config.map(|c| c.sample_rate);
+    let smooth_params = is_processing && P::STATE_LOAD_SMOOTHING_MS > 0.0;
     for (param_id_str, param_value) in &state.params {
         let param_ptr = match params_getter(param_id_str.as_str()) {
             Some(ptr) => ptr,
@@ -181,15 +266,27 @@ pub(crate) unsafe fn deserialize_object<P: Plugin>(
             }
         };
 
+        // Older versions of NIH-plug also saved read-only parameters. The plugin sets these
+        // itself, so they're not restored.
+        if param_ptr.flags().contains(ParamFlags::READ_ONLY) {
+            continue;
+        }
+
         match (param_ptr, param_value) {
-            (ParamPtr::FloatParam(p), ParamValue::F32(v)) => (*p).set_plain_value(*v),
-            (ParamPtr::IntParam(p), ParamValue::I32(v)) => (*p).set_plain_value(*v),
+            (ParamPtr::FloatParam(p), ParamValue::F32(v)) => {
+                if let Some(v) = validator.validate_finite(*v, || param_id_str.clone()) {
+                    (*p).set_plain_value(clamp_plain_value(&*p, v))
+                }
+            }
+            (ParamPtr::IntParam(p), ParamValue::I32(v)) => {
+                (*p).set_plain_value(clamp_plain_value(&*p, *v))
+            }
             (ParamPtr::BoolParam(p), ParamValue::Bool(v)) => (*p).set_plain_value(*v),
             // Enums are either serialized based on the active variant's index (which may not be the
             // same as the discriminator), or a custom set stable string ID. The latter allows the
             // variants to be reordered.
             (ParamPtr::EnumParam(p), ParamValue::I32(variant_idx)) => {
-                (*p).set_plain_value(*variant_idx)
+                (*p).set_plain_value(clamp_plain_value(&*p, *variant_idx))
             }
             (ParamPtr::EnumParam(p), ParamValue::String(id)) => {
                 let deserialized_enum = (*p).set_from_id(id);
@@ -210,9 +307,14 @@ pub(crate) unsafe fn deserialize_object<P: Plugin>(
             }
         }
 
-        // Make sure everything starts out in sync
-        if let Some(sample_rate) = sample_rate {
-            param_ptr.update_smoother(sample_rate, true);
+        // Make sure everything starts out in sync, unless the plugin wants to avoid clicks when
+        // loading state during playback
+        match sample_rate {
+            Some(sample_rate) if smooth_params => {
+                param_ptr.update_smoother_with_duration(sample_rate, P::STATE_LOAD_SMOOTHING_MS)
+            }
+            Some(sample_rate) => param_ptr.update_smoother(sample_rate, true),
+            None => (),
         }
     }
 
@@ -223,12 +325,20 @@ pub(crate) unsafe fn deserialize_object<P: Plugin>(
     true
 }
 
+/// Clamp a plain value loaded from a state object to the parameter's range. The value is converted
+/// through the parameter's normalized range, so any stepping is applied as well.
+fn clamp_plain_value<P: Param>(param: &P, plain: P::Plain) -> P::Plain {
+    let normalized = param.preview_normalized(plain);
+    param.preview_plain(normalized.clamp(0.0, 1.0))
+}
+
 /// Deserialize a plugin's state from a vector containing (compressed) JSON data. This can (and
 /// should) be shared across plugin formats. Returns `false` and logs an error if the state could
 /// not be deserialized. If the `zstd` feature is enabled, then this can
 ///
 /// Make sure to reinitialize plugin after deserializing the state so it can react to the new
-/// parameter values. The smoothers have already been reset by this function.
+/// parameter values. See [`deserialize_object()`] for how the smoothers are updated. If the state
+/// contains an instance ID, then that ID is stored in `instance_id`.
 ///
 /// The [`Plugin`] argument is used to call [`Plugin::filter_state()`] just before loading the
 /// state.
@@ -237,6 +347,9 @@ pub(crate) unsafe fn deserialize_json<P: Plugin>(
     plugin_params: Arc<dyn Params>,
     params_getter: impl Fn(&str) -> Option<ParamPtr>,
     current_buffer_config: Option<&BufferConfig>,
+    is_processing: bool,
+    instance_id: &AtomicCell<InstanceId>,
+    validator: &ParamValueValidator,
 ) -> bool {
     #[cfg(feature = "zstd")]
     let mut state: PluginState = match zstd::decode_all(state) {
@@ -278,10 +391,21 @@ pub(crate) unsafe fn deserialize_json<P: Plugin>(
         }
     };
 
+    // States saved before instance IDs were added keep the ID generated for this instance
+    match state.instance_id.as_deref().map(InstanceId::from_str) {
+        Some(Ok(id)) => instance_id.store(id),
+        Some(Err(())) => {
+            nih_debug_assert_failure!("Invalid instance ID {:?}", state.instance_id)
+        }
+        None => (),
+    }
+
     deserialize_object::<P>(
         &mut state,
         plugin_params,
         params_getter,
         current_buffer_config,
+        is_processing,
+        validator,
     )
 }
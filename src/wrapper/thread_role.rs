@@ -0,0 +1,320 @@
+//! Tracks the role the current thread has from the wrappers' point of view. The wrappers mark the
+//! audio thread while they're calling the plugin's processing functions, and the GUI thread while
+//! they're running tasks that were scheduled on it. The `nih_debug_assert_audio_thread!()`,
+//! `nih_debug_assert_not_audio_thread!()`, and `nih_debug_assert_gui_thread!()` macros use this to
+//! check the threading contracts described in the [`context`][crate::context] module.
+//!
+//! When the `audio_thread_alloc_guard` feature is enabled in debug builds, this also installs a
+//! global allocator that counts allocations made while the audio thread marker is active. These
+//! are reported as a failed debug assertion when the thread leaves the audio thread again.
+
+use std::cell::Cell;
+use std::panic::Location;
+
+/// What the wrappers are currently using a thread for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThreadRole {
+    /// Nothing in particular. This is also used for the editor's own thread, which on some
+    /// platforms is not the same as the GUI thread tasks are run on.
+    Other,
+    /// The thread the wrapper runs GUI tasks on.
+    Gui,
+    /// The thread the wrapper is calling the plugin's processing functions from.
+    Audio,
+}
+
+thread_local! {
+    /// The current thread's role. Set using [`enter_audio_thread()`] and [`enter_gui_thread()`].
+    static THREAD_ROLE: Cell<ThreadRole> = const { Cell::new(ThreadRole::Other) };
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of times a thread role assertion failed on this thread. Used to test that the
+    /// assertions fire.
+    static ASSERTION_FAILURES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether the current thread is running one of the plugin's audio thread functions, like
+/// [`Plugin::process()`][crate::prelude::Plugin::process()], through one of NIH-plug's wrappers.
+/// This is mostly useful for debug assertions, see
+/// [`nih_debug_assert_not_audio_thread!()`][crate::debug::nih_debug_assert_not_audio_thread].
+pub fn is_audio_thread() -> bool {
+    THREAD_ROLE.with(|role| role.get()) == ThreadRole::Audio
+}
+
+/// Whether the current thread is running a task one of NIH-plug's wrappers scheduled on the GUI
+/// thread. See [`nih_debug_assert_gui_thread!()`][crate::debug::nih_debug_assert_gui_thread].
+pub fn is_gui_thread() -> bool {
+    THREAD_ROLE.with(|role| role.get()) == ThreadRole::Gui
+}
+
+/// Mark the current thread as an audio thread for [`is_audio_thread()`] until the returned guard
+/// gets dropped. Nesting these is allowed.
+pub(crate) fn enter_audio_thread() -> ThreadRoleGuard {
+    enter(ThreadRole::Audio)
+}
+
+/// Mark the current thread as the GUI thread for [`is_gui_thread()`] until the returned guard gets
+/// dropped. Nesting these is allowed.
+pub(crate) fn enter_gui_thread() -> ThreadRoleGuard {
+    enter(ThreadRole::Gui)
+}
+
+fn enter(role: ThreadRole) -> ThreadRoleGuard {
+    ThreadRoleGuard {
+        role,
+        previous_role: THREAD_ROLE.with(|current_role| current_role.replace(role)),
+    }
+}
+
+/// Resets the thread's role to its old value when dropped. Returned by [`enter_audio_thread()`]
+/// and [`enter_gui_thread()`].
+pub(crate) struct ThreadRoleGuard {
+    role: ThreadRole,
+    previous_role: ThreadRole,
+}
+
+impl Drop for ThreadRoleGuard {
+    fn drop(&mut self) {
+        THREAD_ROLE.with(|role| role.set(self.previous_role));
+
+        if self.role == ThreadRole::Audio {
+            alloc_guard::report_audio_thread_allocations();
+        }
+    }
+}
+
+/// Called by the thread role assertion macros when an assertion fails. This logs the failure like
+/// any other failing debug assertion, with the location of the macro invocation.
+#[doc(hidden)]
+#[track_caller]
+pub fn thread_role_assertion_failed(message: &str) {
+    #[cfg(test)]
+    ASSERTION_FAILURES.with(|failures| failures.set(failures.get() + 1));
+
+    nih_debug_assert_failure!("{} ({})", message, Location::caller());
+}
+
+/// Return the number of thread role assertions that failed on this thread since the last call.
+#[cfg(test)]
+pub(crate) fn take_assertion_failures() -> usize {
+    ASSERTION_FAILURES.with(|failures| failures.replace(0))
+}
+
+pub(crate) use alloc_guard::PermitAllocGuard;
+
+/// Allow allocations on the audio thread without tripping the allocation guard until the returned
+/// guard gets dropped. Used by [`util::permit_alloc()`][crate::util::permit_alloc()].
+pub(crate) fn permit_alloc() -> PermitAllocGuard {
+    alloc_guard::permit_alloc()
+}
+
+#[cfg(all(debug_assertions, feature = "audio_thread_alloc_guard"))]
+mod alloc_guard {
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
+
+    use super::{ThreadRole, THREAD_ROLE};
+
+    // The `alloc_stats` allocator calls `check_allocation()` itself since there can only be a
+    // single global allocator. Otherwise this wraps the allocation checking allocator if that's
+    // enabled.
+    #[cfg(all(feature = "assert_process_allocs", not(feature = "alloc_stats")))]
+    type InnerAllocator = assert_no_alloc::AllocDisabler;
+    #[cfg(all(not(feature = "assert_process_allocs"), not(feature = "alloc_stats")))]
+    type InnerAllocator = std::alloc::System;
+
+    #[cfg(not(feature = "alloc_stats"))]
+    #[global_allocator]
+    static ALLOCATOR: GuardedAllocator = GuardedAllocator(InnerAllocator {});
+
+    thread_local! {
+        /// The number of nested [`permit_alloc()`] calls on this thread.
+        static PERMIT_DEPTH: Cell<usize> = const { Cell::new(0) };
+        /// The number of allocations made on this thread while it was marked as the audio thread.
+        static AUDIO_THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Passes everything through to the inner allocator, calling [`check_allocation()`] for every
+    /// allocation.
+    #[cfg(not(feature = "alloc_stats"))]
+    struct GuardedAllocator(InnerAllocator);
+
+    #[cfg(not(feature = "alloc_stats"))]
+    unsafe impl GlobalAlloc for GuardedAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            check_allocation();
+            self.0.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            check_allocation();
+            self.0.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            check_allocation();
+            self.0.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.dealloc(ptr, layout)
+        }
+    }
+
+    /// Count the allocation if the current thread is marked as the audio thread and allocations
+    /// have not been permitted. These thread locals do not allocate and they don't have
+    /// destructors, so this is safe to call from within the allocator.
+    pub fn check_allocation() {
+        let is_audio_thread = THREAD_ROLE
+            .try_with(|role| role.get() == ThreadRole::Audio)
+            .unwrap_or(false);
+        let is_permitted = PERMIT_DEPTH
+            .try_with(|depth| depth.get() > 0)
+            .unwrap_or(true);
+        if is_audio_thread && !is_permitted {
+            let _ = AUDIO_THREAD_ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        }
+    }
+
+    /// Log the allocations made while the current thread was marked as the audio thread, if
+    /// there were any, and reset the counter.
+    pub fn report_audio_thread_allocations() {
+        let count = AUDIO_THREAD_ALLOCATIONS.with(|count| count.replace(0));
+        if count > 0 {
+            nih_debug_assert_failure!(
+                "{} allocation(s) were made on the audio thread, enable the \
+                 'assert_process_allocs' feature to find out where",
+                count
+            );
+        }
+    }
+
+    /// Return the number of allocations counted on this thread without resetting the counter.
+    #[cfg(test)]
+    pub fn audio_thread_allocations() -> usize {
+        AUDIO_THREAD_ALLOCATIONS.with(|count| count.get())
+    }
+
+    pub fn permit_alloc() -> PermitAllocGuard {
+        PERMIT_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        PermitAllocGuard { _private: () }
+    }
+
+    /// Decrements the permit depth again when dropped.
+    pub struct PermitAllocGuard {
+        _private: (),
+    }
+
+    impl Drop for PermitAllocGuard {
+        fn drop(&mut self) {
+            PERMIT_DEPTH.with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+}
+
+#[cfg(not(all(debug_assertions, feature = "audio_thread_alloc_guard")))]
+mod alloc_guard {
+    #[inline]
+    pub fn report_audio_thread_allocations() {}
+
+    #[inline]
+    pub fn permit_alloc() -> PermitAllocGuard {
+        PermitAllocGuard
+    }
+
+    pub struct PermitAllocGuard;
+}
+
+#[cfg(all(
+    debug_assertions,
+    feature = "audio_thread_alloc_guard",
+    feature = "alloc_stats"
+))]
+pub(crate) use alloc_guard::check_allocation;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_nest() {
+        assert!(!is_audio_thread());
+        assert!(!is_gui_thread());
+
+        {
+            let _gui_thread_guard = enter_gui_thread();
+            assert!(is_gui_thread());
+
+            {
+                let _audio_thread_guard = enter_audio_thread();
+                assert!(is_audio_thread());
+                assert!(!is_gui_thread());
+            }
+
+            assert!(is_gui_thread());
+            assert!(!is_audio_thread());
+
+            // The marker is thread local
+            assert!(!std::thread::spawn(is_gui_thread).join().unwrap());
+        }
+
+        assert!(!is_audio_thread());
+        assert!(!is_gui_thread());
+    }
+
+    #[test]
+    fn audio_thread_assertions() {
+        take_assertion_failures();
+
+        nih_debug_assert_audio_thread!();
+        assert_eq!(take_assertion_failures(), 1);
+        nih_debug_assert_not_audio_thread!();
+        assert_eq!(take_assertion_failures(), 0);
+
+        let _audio_thread_guard = enter_audio_thread();
+        nih_debug_assert_audio_thread!();
+        assert_eq!(take_assertion_failures(), 0);
+        nih_debug_assert_not_audio_thread!();
+        assert_eq!(take_assertion_failures(), 1);
+    }
+
+    #[test]
+    fn gui_thread_assertions() {
+        take_assertion_failures();
+
+        nih_debug_assert_gui_thread!();
+        assert_eq!(take_assertion_failures(), 1);
+
+        {
+            let _gui_thread_guard = enter_gui_thread();
+            nih_debug_assert_gui_thread!();
+            assert_eq!(take_assertion_failures(), 0);
+        }
+
+        // Calling GUI functions from the audio thread is also a violation when the audio thread
+        // is entered from a GUI task
+        let _gui_thread_guard = enter_gui_thread();
+        let _audio_thread_guard = enter_audio_thread();
+        nih_debug_assert_gui_thread!();
+        assert_eq!(take_assertion_failures(), 1);
+    }
+
+    #[cfg(all(debug_assertions, feature = "audio_thread_alloc_guard"))]
+    #[test]
+    fn alloc_guard_counts_audio_thread_allocations() {
+        use super::alloc_guard::audio_thread_allocations;
+
+        let _audio_thread_guard = enter_audio_thread();
+        let before = audio_thread_allocations();
+
+        let permitted = crate::util::permit_alloc(|| vec![0u8; 16]);
+        assert_eq!(audio_thread_allocations(), before);
+
+        let not_permitted = vec![0u8; 16];
+        assert_eq!(audio_thread_allocations(), before + 1);
+
+        drop((permitted, not_permitted));
+    }
+}
@@ -1,12 +1,23 @@
+use atomic_float::AtomicF32;
 use backtrace::Backtrace;
+use crossbeam::atomic::AtomicCell;
+use std::borrow::Cow;
 use std::cmp;
+use std::collections::HashMap;
 use std::fs::File;
 use std::marker::PhantomData;
 use std::os::raw::c_char;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 
+use crate::context::process::Transport;
+use crate::formatters;
+use crate::params::internals::ParamPtr;
+use crate::params::{BoolParam, Param, ParamFlags, Params};
+use crate::plugin::{BufferConfig, BusConfig, Plugin};
 use crate::util::permit_alloc;
 
+use super::thread_role;
+
 /// The environment variable for controlling the logging behavior.
 const NIH_LOG_ENV: &str = "NIH_LOG";
 
@@ -24,10 +35,120 @@ const AARCH64_FTZ_BIT: u64 = 1 << 24;
 ))]
 compile_error!("The 'assert_process_allocs' feature does not work correctly in combination with the 'x86_64-pc-windows-gnu' target, see https://github.com/Windfisch/rust-assert-no-alloc/issues/7");
 
-#[cfg(all(debug_assertions, feature = "assert_process_allocs"))]
+// The `alloc_stats` and `audio_thread_alloc_guard` features install their own allocators that wrap
+// this one
+#[cfg(all(
+    debug_assertions,
+    feature = "assert_process_allocs",
+    not(feature = "alloc_stats"),
+    not(feature = "audio_thread_alloc_guard")
+))]
 #[global_allocator]
 static A: assert_no_alloc::AllocDisabler = assert_no_alloc::AllocDisabler;
 
+/// The ID of the bypass parameter the wrappers add for plugins that don't have their own bypass
+/// parameter. Plugins should not use this ID for their own parameters.
+pub const BYPASS_PARAM_ID: &str = "nih_plug_bypass";
+
+/// Find the ID of the parameter with the given hash. This does a linear search, so it should only
+/// be used for logging.
+pub fn param_id_for_hash(param_id_to_hash: &HashMap<String, u32>, hash: u32) -> String {
+    param_id_to_hash
+        .iter()
+        .find(|(_, param_hash)| **param_hash == hash)
+        .map(|(param_id, _)| param_id.clone())
+        .unwrap_or_else(|| format!("{hash:#x}"))
+}
+
+/// Get the plugin's parameter map. If the plugin does not have a bypass parameter, then a bypass
+/// parameter owned by the wrapper is added to the end of the map. The returned parameter needs to be
+/// kept alive by the wrapper for as long as the parameter map is used. This parameter is
+/// implemented by the wrapper using a [`BypassProcessor`][super::bypass::BypassProcessor].
+pub fn param_map_with_bypass(
+    params: &dyn Params,
+) -> (Vec<(String, ParamPtr, String)>, Option<Box<BoolParam>>) {
+    let mut param_map = params.param_map();
+    if find_bypass_param(&param_map).is_some() {
+        return (param_map, None);
+    }
+
+    nih_debug_assert!(
+        !param_map.iter().any(|(id, _, _)| id == BYPASS_PARAM_ID),
+        "The parameter ID '{}' is reserved for NIH-plug's own bypass parameter",
+        BYPASS_PARAM_ID
+    );
+
+    let bypass_param = Box::new(
+        BoolParam::new("Bypass", false)
+            .make_bypass()
+            .with_value_to_string(formatters::v2s_bool_bypass())
+            .with_string_to_value(formatters::s2v_bool_bypass()),
+    );
+    param_map.push((
+        String::from(BYPASS_PARAM_ID),
+        bypass_param.as_ptr(),
+        String::new(),
+    ));
+
+    (param_map, Some(bypass_param))
+}
+
+/// Find the first parameter marked as a bypass parameter in a parameter map. Hosts will only use
+/// the first bypass parameter.
+pub fn find_bypass_param(param_map: &[(String, ParamPtr, String)]) -> Option<ParamPtr> {
+    param_map
+        .iter()
+        .map(|(_, ptr, _)| *ptr)
+        .find(|ptr| unsafe { ptr.flags() }.contains(ParamFlags::BYPASS))
+}
+
+/// Register the plugin's [legacy parameter IDs][Params::legacy_param_ids()]. The legacy IDs'
+/// hashes are added to `param_by_hash` so host automation using those hashes still reaches the
+/// renamed parameter. The returned map maps the legacy string IDs to the current parameter's hash,
+/// and it should be used when loading state. Legacy IDs that conflict with another parameter are
+/// skipped.
+pub fn register_legacy_param_ids(
+    params: &dyn Params,
+    param_by_hash: &mut HashMap<u32, ParamPtr>,
+    param_id_to_hash: &HashMap<String, u32>,
+) -> HashMap<String, u32> {
+    let mut legacy_param_id_to_hash = HashMap::new();
+    for (legacy_id, current_id) in params.legacy_param_ids() {
+        let current_hash = match param_id_to_hash.get(&current_id) {
+            Some(hash) => *hash,
+            None => {
+                nih_debug_assert_failure!(
+                    "Legacy parameter ID '{}' refers to unknown parameter '{}'",
+                    legacy_id,
+                    current_id
+                );
+                continue;
+            }
+        };
+
+        // The legacy ID may not be the current ID of another parameter, and its hash may not
+        // collide with another parameter's hash or with another legacy ID
+        let legacy_hash = hash_param_id(&legacy_id);
+        if param_id_to_hash.contains_key(&legacy_id)
+            || legacy_param_id_to_hash.contains_key(&legacy_id)
+            || param_by_hash.contains_key(&legacy_hash)
+        {
+            nih_debug_assert_failure!(
+                "Legacy parameter ID '{}' for '{}' conflicts with another parameter, ignoring it",
+                legacy_id,
+                current_id
+            );
+            continue;
+        }
+
+        let param_ptr = param_by_hash[&current_hash];
+        param_by_hash.insert(legacy_hash, param_ptr);
+        legacy_param_id_to_hash.insert(legacy_id, current_hash);
+    }
+
+    legacy_param_id_to_hash
+}
+
 /// A Rabin fingerprint based string hash for parameter ID strings.
 pub fn hash_param_id(id: &str) -> u32 {
     let mut hash: u32 = 0;
@@ -44,10 +165,18 @@ pub fn hash_param_id(id: &str) -> u32 {
 
 /// The equivalent of the `strlcpy()` C function. Copy `src` to `dest` as a null-terminated
 /// C-string. If `dest` does not have enough capacity, add a null terminator at the end to prevent
-/// buffer overflows.
-pub fn strlcpy(dest: &mut [c_char], src: &str) {
+/// buffer overflows. The string is copied as UTF-8, and truncation only ever happens at a character
+/// boundary so the result is always valid UTF-8. Both CLAP and VST3's 8-bit string fields are
+/// interpreted as UTF-8 by hosts. Returns `true` if `src` had to be truncated.
+pub fn strlcpy(dest: &mut [c_char], src: &str) -> bool {
     if dest.is_empty() {
-        return;
+        return !src.is_empty();
+    }
+
+    // Make sure there's always room for a null terminator, and don't cut a character in half
+    let mut copy_len = cmp::min(dest.len() - 1, src.len());
+    while !src.is_char_boundary(copy_len) {
+        copy_len -= 1;
     }
 
     let src_bytes: &[u8] = src.as_bytes();
@@ -55,10 +184,141 @@ pub fn strlcpy(dest: &mut [c_char], src: &str) {
     //       anything.
     let src_bytes_signed: &[c_char] = unsafe { &*(src_bytes as *const [u8] as *const [c_char]) };
 
-    // Make sure there's always room for a null terminator
-    let copy_len = cmp::min(dest.len() - 1, src.len());
     dest[..copy_len].copy_from_slice(&src_bytes_signed[..copy_len]);
     dest[copy_len] = 0;
+
+    copy_len < src.len()
+}
+
+/// The name for the plugin's main input port. See [`Plugin::PORT_NAMES`].
+pub fn main_input_name<P: Plugin>() -> &'static str {
+    P::PORT_NAMES.main_input.unwrap_or("Input")
+}
+
+/// The name for the plugin's main output port. See [`Plugin::PORT_NAMES`].
+pub fn main_output_name<P: Plugin>() -> &'static str {
+    P::PORT_NAMES.main_output.unwrap_or("Output")
+}
+
+/// The name for the plugin's `aux_input_idx`th auxiliary input port out of `num_aux_inputs` ports.
+/// See [`Plugin::PORT_NAMES`].
+pub fn aux_input_name<P: Plugin>(aux_input_idx: usize, num_aux_inputs: usize) -> Cow<'static, str> {
+    aux_port_name(
+        P::PORT_NAMES.aux_inputs,
+        "Sidechain Input",
+        aux_input_idx,
+        num_aux_inputs,
+    )
+}
+
+/// The name for the plugin's `aux_output_idx`th auxiliary output port out of `num_aux_outputs`
+/// ports. See [`Plugin::PORT_NAMES`].
+pub fn aux_output_name<P: Plugin>(
+    aux_output_idx: usize,
+    num_aux_outputs: usize,
+) -> Cow<'static, str> {
+    aux_port_name(
+        P::PORT_NAMES.aux_outputs,
+        "Auxiliary Output",
+        aux_output_idx,
+        num_aux_outputs,
+    )
+}
+
+/// Use the custom port name if there is one, and otherwise generate one. The generated names are
+/// only numbered if there's more than one port.
+fn aux_port_name(
+    custom_names: Option<&'static [&'static str]>,
+    default_name: &'static str,
+    port_idx: usize,
+    num_ports: usize,
+) -> Cow<'static, str> {
+    match custom_names.and_then(|names| names.get(port_idx)) {
+        Some(name) => Cow::Borrowed(name),
+        None if num_ports <= 1 => Cow::Borrowed(default_name),
+        None => Cow::Owned(format!("{default_name} {}", port_idx + 1)),
+    }
+}
+
+/// A suffix to distinguish a port's channels from each other when the host or backend needs a name
+/// for every channel. Mono ports don't get a suffix, stereo ports use `L` and `R`, and the channels
+/// for any other layout are numbered starting from 1.
+pub fn channel_suffix(channel_idx: usize, num_channels: usize) -> Option<Cow<'static, str>> {
+    match (num_channels, channel_idx) {
+        (1, _) => None,
+        (2, 0) => Some(Cow::Borrowed("L")),
+        (2, 1) => Some(Cow::Borrowed("R")),
+        (_, channel_idx) => Some(Cow::Owned((channel_idx + 1).to_string())),
+    }
+}
+
+/// The name for a single channel of a port, built from the port's name and the channel's
+/// [`channel_suffix()`].
+pub fn channel_name(port_name: &str, channel_idx: usize, num_channels: usize) -> String {
+    match channel_suffix(channel_idx, num_channels) {
+        Some(suffix) => format!("{port_name} {suffix}"),
+        None => port_name.to_owned(),
+    }
+}
+
+/// The value returned by [`InitContext::max_expected_sample_rate()`][crate::prelude::InitContext::max_expected_sample_rate()]
+/// for the plugin and hosts that can change the sample rate at runtime.
+pub fn max_expected_sample_rate<P: Plugin>(sample_rate: f32) -> f32 {
+    P::MAX_SUPPORTED_SAMPLE_RATE.max(sample_rate)
+}
+
+/// Whether a plugin that was last initialized with `initialized_config` can be reactivated with
+/// [`Plugin::sample_rate_changed()`] instead of being fully reinitialized. This is only the case
+/// if the plugin sets [`Plugin::PREALLOCATES_FOR_MAX_SAMPLE_RATE`], if only the sample rate
+/// changed, and if the new sample rate does not exceed the maximum expected sample rate from that
+/// initialization.
+pub fn can_change_sample_rate_in_place<P: Plugin>(
+    initialized_config: Option<(BusConfig, BufferConfig)>,
+    bus_config: &BusConfig,
+    buffer_config: &BufferConfig,
+) -> bool {
+    if !P::PREALLOCATES_FOR_MAX_SAMPLE_RATE {
+        return false;
+    }
+
+    match initialized_config {
+        Some((old_bus_config, old_buffer_config)) => {
+            old_bus_config == *bus_config
+                && old_buffer_config.sample_rate != buffer_config.sample_rate
+                && BufferConfig {
+                    sample_rate: buffer_config.sample_rate,
+                    ..old_buffer_config
+                } == *buffer_config
+                && buffer_config.sample_rate
+                    <= max_expected_sample_rate::<P>(old_buffer_config.sample_rate)
+        }
+        None => false,
+    }
+}
+
+/// Where a plugin instance is in its lifecycle. The wrappers use this to make sure the plugin's
+/// functions are only called in the order described in the [`Plugin`] trait's documentation, even
+/// when the host calls the wrapper's functions in an order it should not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifecycleState {
+    /// The instance has been created, but the wrapper does not yet know the sample rate and the
+    /// maximum block size.
+    Created,
+    /// The buffer configuration is known, but the plugin has not yet been initialized or it has
+    /// been deactivated again.
+    Setup,
+    /// The plugin has been initialized with the current configuration.
+    Active,
+    /// The plugin has been reset and it is processing audio.
+    Processing,
+}
+
+/// Move an instance to a new lifecycle state, logging the transition.
+pub fn transition_lifecycle_state(state: &AtomicCell<LifecycleState>, new_state: LifecycleState) {
+    let old_state = state.swap(new_state);
+    if old_state != new_state {
+        nih_trace!("Lifecycle state changed from {old_state:?} to {new_state:?}");
+    }
 }
 
 /// Set up the logger so that the `nih_*!()` logging and assertion macros log output to a
@@ -136,6 +396,13 @@ pub fn setup_logger() {
     log_panics();
 }
 
+/// Clean up the library's global state before it gets unloaded. This waits for the background
+/// worker threads of plugin instances that have already been dropped to exit, as those threads
+/// cannot outlive the library's code.
+pub fn teardown_library() {
+    crate::event_loop::join_stopped_worker_threads();
+}
+
 /// This is copied from same as the `log_panics` crate, but it's wrapped in `permit_alloc()`.
 /// Otherwise logging panics will trigger `assert_no_alloc` as this also allocates.
 fn log_panics() {
@@ -183,10 +450,12 @@ fn log_panics() {
 
 /// A wrapper around the entire process function, including the plugin wrapper parts. This sets up
 /// `assert_no_alloc` if needed, while also making sure that things like FTZ are set up correctly if
-/// the host has not already done so.
+/// the host has not already done so. The current thread is also marked as an audio thread for
+/// [`is_audio_thread()`][crate::util::is_audio_thread()] while `f` is running.
 pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
     // Make sure FTZ is always enabled, even if the host doesn't do it for us
     let _ftz_guard = ScopedFtz::enable();
+    let _audio_thread_guard = thread_role::enter_audio_thread();
 
     cfg_if::cfg_if! {
         if #[cfg(all(debug_assertions, feature = "assert_process_allocs"))] {
@@ -197,6 +466,238 @@ pub fn process_wrapper<T, F: FnOnce() -> T>(f: F) -> T {
     }
 }
 
+/// Keeps track of the transport information the wrappers compute themselves, namely
+/// [`Transport::continuous_pos_samples`] and [`Transport::position_jumped`]. The wrappers call
+/// [`update()`][Self::update()] for every (sub-)block they process, right after filling in the
+/// host's transport information.
+#[derive(Debug, Default)]
+pub struct TransportTracker {
+    /// The value for `continuous_pos_samples` in the next block.
+    next_continuous_pos_samples: i64,
+    /// The song position in samples we expect to see in the next block if the host did not jump
+    /// around. `None` if the previous block did not have a song position, or if this is the first
+    /// block after a reset.
+    expected_pos_samples: Option<i64>,
+}
+
+impl TransportTracker {
+    /// The song position may be off by this many samples when the host reports it in seconds or in
+    /// beats, since it then needs to be rounded to samples.
+    const JUMP_TOLERANCE_SAMPLES: i64 = 1;
+
+    /// Fill in the wrapper-maintained transport fields for a block of `block_len` samples, and
+    /// advance the tracker's state past that block. This should be called before
+    /// [`Transport::retain()`] so the song position can be used even when the plugin didn't ask for
+    /// it.
+    pub fn update(&mut self, transport: &mut Transport, block_len: usize) {
+        let pos_samples = transport.pos_samples();
+
+        transport.continuous_pos_samples = self.next_continuous_pos_samples;
+        transport.position_jumped = match (self.expected_pos_samples, pos_samples) {
+            (Some(expected_pos_samples), Some(pos_samples)) => {
+                (pos_samples - expected_pos_samples).abs() > Self::JUMP_TOLERANCE_SAMPLES
+            }
+            _ => false,
+        };
+
+        // The song position doesn't move while the transport is stopped
+        self.next_continuous_pos_samples += block_len as i64;
+        self.expected_pos_samples = pos_samples.map(|pos_samples| {
+            if transport.playing {
+                pos_samples + block_len as i64
+            } else {
+                pos_samples
+            }
+        });
+    }
+
+    /// Start counting from zero again. Called whenever the plugin is activated or reset.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Validates the parameter values that enter a wrapper from the host, the editor, automation, or a
+/// state object. Finite normalized values are clamped to `[0, 1]`. NaN and infinite values are
+/// rejected so the parameter keeps its previous value, and they are counted so the number of
+/// rejected values can be inspected later. The first rejected value is logged together with the
+/// parameter's ID.
+#[derive(Debug, Default)]
+pub struct ParamValueValidator {
+    /// The number of NaN and infinite values that have been rejected so far.
+    num_rejected: AtomicU32,
+}
+
+impl ParamValueValidator {
+    /// Clamp a normalized value to `[0, 1]`, or return `None` if it is NaN or infinite. `param_id`
+    /// is only called when the value gets rejected.
+    #[inline]
+    pub fn validate_normalized(
+        &self,
+        normalized: f32,
+        param_id: impl FnOnce() -> String,
+    ) -> Option<f32> {
+        self.validate_finite(normalized, param_id)
+            .map(|normalized| normalized.clamp(0.0, 1.0))
+    }
+
+    /// Return `None` if `value` is NaN or infinite, and `value` otherwise. This is used for plain
+    /// values and modulation offsets, which are not clamped to `[0, 1]`.
+    #[inline]
+    pub fn validate_finite(&self, value: f32, param_id: impl FnOnce() -> String) -> Option<f32> {
+        if value.is_finite() {
+            Some(value)
+        } else {
+            self.reject(value, param_id);
+            None
+        }
+    }
+
+    /// The number of NaN and infinite values that have been rejected so far.
+    pub fn num_rejected(&self) -> u32 {
+        self.num_rejected.load(Ordering::Relaxed)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn reject(&self, value: f32, param_id: impl FnOnce() -> String) {
+        if self.num_rejected.fetch_add(1, Ordering::Relaxed) == 0 {
+            nih_debug_assert_failure!(
+                "Ignoring invalid value {} for parameter \"{}\", further invalid values will only \
+                 be counted",
+                value,
+                param_id()
+            );
+        }
+    }
+}
+
+/// Defers [`Plugin::state_restored()`] until right before the plugin gets initialized. The wrappers
+/// call [`mark()`][Self::mark()] after loading state, and [`notify()`][Self::notify()] right before
+/// every call to [`Plugin::try_initialize()`]. State loaded while the plugin is already initialized
+/// is always followed by a reinitialization, and state loaded before that happens is picked up by
+/// the first initialization. Loading state multiple times before the plugin gets initialized only
+/// results in a single call.
+#[derive(Debug, Default)]
+pub struct StateRestoredNotifier {
+    pending: AtomicBool,
+}
+
+impl StateRestoredNotifier {
+    /// Mark that a state has been loaded.
+    pub fn mark(&self) {
+        self.pending.store(true, Ordering::SeqCst);
+    }
+
+    /// Call [`Plugin::state_restored()`] if a state was loaded since the last time this was called.
+    pub fn notify<P: Plugin>(&self, plugin: &mut P) {
+        if self.pending.swap(false, Ordering::SeqCst) {
+            plugin.state_restored();
+        }
+    }
+}
+
+/// Tracks which parameters have their modulated values written to the host as automation. See
+/// [`GuiContext::raw_set_modulation_write()`][crate::prelude::GuiContext::raw_set_modulation_write()].
+/// The parameters are stored when the wrapper is created, so arming parameters from the GUI thread
+/// and emitting values from the audio thread never allocates or locks.
+pub struct ModulationWriter {
+    params: HashMap<u32, ModulationWriteState>,
+    /// The number of armed parameters, so the audio thread can skip everything when there are
+    /// none.
+    num_armed: AtomicUsize,
+}
+
+struct ModulationWriteState {
+    param: ParamPtr,
+    armed: AtomicBool,
+    /// The last normalized value that was written to the host, or NaN if nothing has been written
+    /// since the parameter was armed.
+    last_value: AtomicF32,
+}
+
+impl ModulationWriter {
+    /// Create a writer for the parameters in `param_ptr_to_hash`. Read-only parameters already
+    /// send their values to the host, so they can't be armed.
+    pub fn new(param_ptr_to_hash: &HashMap<ParamPtr, u32>) -> Self {
+        let params = param_ptr_to_hash
+            .iter()
+            .filter(|(param, _)| !unsafe { param.flags() }.contains(ParamFlags::READ_ONLY))
+            .map(|(param, hash)| {
+                (
+                    *hash,
+                    ModulationWriteState {
+                        param: *param,
+                        armed: AtomicBool::new(false),
+                        last_value: AtomicF32::new(f32::NAN),
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            params,
+            num_armed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Whether the parameter with the given hash can be armed.
+    pub fn can_write(&self, hash: u32) -> bool {
+        self.params.contains_key(&hash)
+    }
+
+    /// Whether the parameter with the given hash is currently armed.
+    pub fn is_armed(&self, hash: u32) -> bool {
+        self.params
+            .get(&hash)
+            .map_or(false, |state| state.armed.load(Ordering::SeqCst))
+    }
+
+    /// Arm or disarm a parameter. Returns whether this changed the parameter's armed state. The
+    /// first value written after arming a parameter is always its current value.
+    pub fn set_armed(&self, hash: u32, armed: bool) -> bool {
+        let state = match self.params.get(&hash) {
+            Some(state) => state,
+            None => return false,
+        };
+
+        if armed {
+            state.last_value.store(f32::NAN, Ordering::SeqCst);
+        }
+        if state.armed.swap(armed, Ordering::SeqCst) == armed {
+            return false;
+        }
+
+        if armed {
+            self.num_armed.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.num_armed.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        true
+    }
+
+    /// Call `write` with the hash and the modulated normalized value of every armed parameter whose
+    /// value changed since it was last written. Called from the audio thread once per process call,
+    /// which limits the number of automation points sent to the host.
+    pub fn write_changed(&self, mut write: impl FnMut(u32, f32)) {
+        if self.num_armed.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+
+        for (hash, state) in &self.params {
+            if !state.armed.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            let value = unsafe { state.param.modulated_normalized_value() };
+            if state.last_value.swap(value, Ordering::SeqCst) != value {
+                write(*hash, value);
+            }
+        }
+    }
+}
+
 /// Enable the CPU's Flush To Zero flag while this object is in scope. If the flag was not already
 /// set, it will be restored to its old value when this gets dropped.
 struct ScopedFtz {
@@ -291,4 +792,120 @@ mod miri {
             Ok("Hello")
         );
     }
+
+    #[test]
+    fn strlcpy_multibyte_overflow() {
+        // 'ź' is two bytes long in UTF-8, so this would otherwise cut the character in half
+        let mut dest = [0; 11];
+        assert!(strlcpy(&mut dest, "Teodor Woźniak"));
+
+        assert_eq!(
+            unsafe { CStr::from_ptr(dest.as_ptr()) }.to_str(),
+            Ok("Teodor Wo")
+        );
+    }
+
+    #[test]
+    fn strlcpy_exact_fit() {
+        // This includes room for the null terminator
+        let mut dest = [0; 15];
+        assert!(!strlcpy(&mut dest, "Hello, world!"));
+        assert!(!strlcpy(&mut dest, "Hello, world!!"));
+        assert!(strlcpy(&mut dest, "Hello, world!!!"));
+
+        assert_eq!(
+            unsafe { CStr::from_ptr(dest.as_ptr()) }.to_str(),
+            Ok("Hello, world!!")
+        );
+    }
+
+    #[test]
+    fn strlcpy_empty_dest() {
+        let mut dest: [c_char; 0] = [];
+        assert!(!strlcpy(&mut dest, ""));
+        assert!(strlcpy(&mut dest, "Hello, world!"));
+    }
+
+    /// Every prefix of these strings, copied into every field size used in the VST3 and CLAP
+    /// structs, should result in a valid null-terminated UTF-8 prefix of the original string.
+    #[test]
+    fn strlcpy_always_valid() {
+        const STRINGS: &[&str] = &[
+            "Teodor Woźniak",
+            "Delay Time (µs)",
+            "Phase 180°",
+            "混响 リバーブ 리버브",
+            "Emoji 🎛️🎚️🔊 Bus",
+            // An 'e' followed by a combining acute accent, and a family emoji made out of several
+            // code points joined with zero width joiners
+            "Cafe\u{301} 👨‍👩‍👧‍👦",
+        ];
+        const FIELD_SIZES: &[usize] = &[32, 64, 128, 256];
+
+        for src in STRINGS {
+            let src = src.repeat(20);
+            for &field_size in FIELD_SIZES {
+                for dest_len in 1..=field_size {
+                    let mut dest = vec![0x55 as c_char; dest_len];
+                    let truncated = strlcpy(&mut dest, &src);
+
+                    let result = unsafe { CStr::from_ptr(dest.as_ptr()) }
+                        .to_str()
+                        .expect("Invalid UTF-8");
+                    assert!(src.starts_with(result));
+                    assert!(result.len() < dest_len);
+                    assert_eq!(truncated, result.len() < src.len());
+                    // Truncation should never throw away more than a single character
+                    if truncated {
+                        assert!(dest_len - 1 - result.len() < 4);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn aux_port_names() {
+        const NAMES: &[&str] = &["Sidechain A"];
+
+        assert_eq!(aux_port_name(None, "Aux", 0, 1), "Aux");
+        assert_eq!(aux_port_name(None, "Aux", 1, 2), "Aux 2");
+        assert_eq!(aux_port_name(Some(NAMES), "Aux", 0, 2), "Sidechain A");
+        // Missing custom names fall back to generated names
+        assert_eq!(aux_port_name(Some(NAMES), "Aux", 1, 2), "Aux 2");
+    }
+
+    #[test]
+    fn channel_names() {
+        assert_eq!(channel_name("Input", 0, 1), "Input");
+        assert_eq!(channel_name("Input", 0, 2), "Input L");
+        assert_eq!(channel_name("Input", 1, 2), "Input R");
+        assert_eq!(channel_name("Input", 0, 6), "Input 1");
+        assert_eq!(channel_name("Input", 5, 6), "Input 6");
+    }
+
+    #[test]
+    fn transport_tracker_seconds_rounding() {
+        let mut tracker = TransportTracker::default();
+        let mut transport = Transport::new(44_100.0);
+        transport.playing = true;
+
+        // A position in seconds that doesn't land exactly on a sample should not count as a jump
+        for block_idx in 0..4 {
+            transport.pos_seconds = Some((block_idx * 100) as f64 / 44_100.0 + 0.4 / 44_100.0);
+            tracker.update(&mut transport, 100);
+            assert!(!transport.position_jumped);
+            assert_eq!(transport.continuous_pos_samples, block_idx * 100);
+        }
+
+        transport.pos_seconds = Some(1.0);
+        tracker.update(&mut transport, 100);
+        assert!(transport.position_jumped);
+        assert_eq!(transport.continuous_pos_samples, 400);
+
+        tracker.reset();
+        tracker.update(&mut transport, 100);
+        assert!(!transport.position_jumped);
+        assert_eq!(transport.continuous_pos_samples, 0);
+    }
 }
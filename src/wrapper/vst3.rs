@@ -1,24 +1,40 @@
 #[macro_use]
 mod util;
 
+mod channel_adapter;
+mod channel_context;
+#[cfg(test)]
+mod conformance;
 mod context;
+mod context_menu;
 mod factory;
 mod inner;
 mod note_expressions;
+mod param_coalescing;
 mod param_units;
+#[cfg(test)]
+mod tests;
 mod view;
 mod wrapper;
 
 /// Re-export for the wrapper.
-pub use factory::Factory;
+pub use factory::{assert_unique_class_ids, Factory, PluginClass};
 
-/// Export a VST3 plugin from this library using the provided plugin type.
+/// Export one or more VST3 plugins from this library using the provided plugin types. Multiple
+/// plugins can be exposed from a single library by passing a comma separated list of types, e.g.
+/// `nih_export_vst3!(PluginA, PluginB)`. Every plugin needs to have a unique
+/// [`VST3_CLASS_ID`][crate::prelude::Vst3Plugin::VST3_CLASS_ID], this is checked at compile time.
+/// The factory's vendor information is taken from the first plugin.
 #[macro_export]
 macro_rules! nih_export_vst3 {
-    ($plugin_ty:ty) => {
+    ($($plugin_ty:ty),+ $(,)?) => {
         #[no_mangle]
         pub extern "system" fn GetPluginFactory() -> *mut ::std::ffi::c_void {
-            let factory = ::nih_plug::wrapper::vst3::Factory::<$plugin_ty>::new();
+            const CLASSES: &[::nih_plug::wrapper::vst3::PluginClass] =
+                &[$(::nih_plug::wrapper::vst3::PluginClass::new::<$plugin_ty>()),*];
+            const _: () = ::nih_plug::wrapper::vst3::assert_unique_class_ids(CLASSES);
+
+            let factory = ::nih_plug::wrapper::vst3::Factory::new(CLASSES);
 
             Box::into_raw(factory) as *mut ::std::ffi::c_void
         }
@@ -36,6 +52,7 @@ macro_rules! nih_export_vst3 {
         #[no_mangle]
         #[cfg(all(target_family = "unix", not(target_os = "macos")))]
         pub extern "C" fn ModuleExit() -> bool {
+            ::nih_plug::wrapper::teardown_library();
             true
         }
 
@@ -51,6 +68,7 @@ macro_rules! nih_export_vst3 {
         #[no_mangle]
         #[cfg(target_os = "macos")]
         pub extern "C" fn bundleExit() -> bool {
+            ::nih_plug::wrapper::teardown_library();
             true
         }
 
@@ -66,6 +84,7 @@ macro_rules! nih_export_vst3 {
         #[no_mangle]
         #[cfg(target_os = "windows")]
         pub extern "system" fn ExitDll() -> bool {
+            ::nih_plug::wrapper::teardown_library();
             true
         }
     };
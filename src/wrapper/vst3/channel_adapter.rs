@@ -0,0 +1,255 @@
+//! Conversions between the host's main channel layout and the one the plugin runs with for plugins
+//! that set [`Plugin::CHANNEL_ADAPTATION`] to [`ChannelAdaptation::DuplicateDownmix`].
+
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::ptr;
+
+use crate::plugin::{BusConfig, ChannelAdaptation, Plugin};
+
+/// Find the bus configuration the plugin should be run with when the host proposes `host_config`.
+/// This is `host_config` itself if the plugin accepts it. Otherwise, if the plugin allows channel
+/// adaptation, this tries the other combinations of mono and stereo main inputs and outputs.
+/// Returns `None` if the host's configuration should be rejected.
+pub fn plugin_bus_config<P: Plugin>(plugin: &P, host_config: &BusConfig) -> Option<BusConfig> {
+    if plugin.accepts_bus_config(host_config) {
+        return Some(*host_config);
+    }
+    if P::CHANNEL_ADAPTATION == ChannelAdaptation::Reject {
+        return None;
+    }
+
+    // The host's own channel count is always tried first so only one side needs to be adapted if
+    // possible
+    let alternatives = |num_channels: u32| match num_channels {
+        1 => [1, 2],
+        2 => [2, 1],
+        n => [n, n],
+    };
+    for num_input_channels in alternatives(host_config.num_input_channels) {
+        for num_output_channels in alternatives(host_config.num_output_channels) {
+            let candidate = BusConfig {
+                num_input_channels,
+                num_output_channels,
+                ..*host_config
+            };
+            if candidate != *host_config && plugin.accepts_bus_config(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// Converts the host's main input to the plugin's channel layout, and the plugin's main output back
+/// to the host's layout. The plugin processes the adapter's own buffers instead of the host's.
+pub struct ChannelAdapter {
+    plugin_input_channels: usize,
+    /// One buffer for each of the plugin's output channels. The plugin's input is copied to these
+    /// buffers, and the plugin then processes them in place just like it would do with the host's
+    /// buffers.
+    storage: Vec<Vec<f32>>,
+}
+
+impl ChannelAdapter {
+    /// Create an adapter for the main channel layouts from the two bus configurations. Returns
+    /// `None` if the layouts are the same and the host's buffers can be used directly.
+    pub fn new(
+        host_config: &BusConfig,
+        plugin_config: &BusConfig,
+        max_buffer_size: usize,
+    ) -> Option<Self> {
+        if host_config.num_input_channels == plugin_config.num_input_channels
+            && host_config.num_output_channels == plugin_config.num_output_channels
+        {
+            return None;
+        }
+
+        Some(Self {
+            plugin_input_channels: plugin_config.num_input_channels as usize,
+            storage: vec![vec![0.0; max_buffer_size]; plugin_config.num_output_channels as usize],
+        })
+    }
+
+    /// The buffers the plugin should process, one for each of the plugin's output channels.
+    pub fn storage_mut(&mut self) -> &mut [Vec<f32>] {
+        &mut self.storage
+    }
+
+    /// Copy `num_samples` samples starting at `start` from the host's main input to the adapter's
+    /// buffers, duplicating or downmixing channels as needed. Any of the plugin's output channels
+    /// that don't have a matching input channel are filled with silence.
+    ///
+    /// # Safety
+    ///
+    /// `host_inputs` needs to point to `num_host_channels` valid channel pointers, and those
+    /// channels need to contain at least `start + num_samples` samples. `start + num_samples` may
+    /// not exceed the adapter's maximum buffer size.
+    pub unsafe fn read_inputs(
+        &mut self,
+        host_inputs: *const *const f32,
+        num_host_channels: usize,
+        start: usize,
+        num_samples: usize,
+    ) {
+        let num_plugin_channels = self.plugin_input_channels.min(self.storage.len());
+        let storage = self.storage.as_mut_ptr();
+        adapt_channels(
+            num_host_channels,
+            num_plugin_channels,
+            num_samples,
+            |channel_idx| (*host_inputs.add(channel_idx)).add(start),
+            |channel_idx| (*storage.add(channel_idx)).as_mut_ptr().add(start),
+        );
+
+        for channel in &mut self.storage[num_plugin_channels..] {
+            channel[start..start + num_samples].fill(0.0);
+        }
+    }
+
+    /// Copy `num_samples` samples starting at `start` from the adapter's buffers to the host's
+    /// main output, duplicating or downmixing channels as needed. Any of the host's channels that
+    /// don't have a matching output channel from the plugin are filled with silence.
+    ///
+    /// # Safety
+    ///
+    /// `host_outputs` needs to point to `num_host_channels` valid channel pointers, and those
+    /// channels need to contain at least `start + num_samples` samples. `start + num_samples` may
+    /// not exceed the adapter's maximum buffer size.
+    pub unsafe fn write_outputs(
+        &self,
+        host_outputs: *const *mut f32,
+        num_host_channels: usize,
+        start: usize,
+        num_samples: usize,
+    ) {
+        adapt_channels(
+            self.storage.len(),
+            num_host_channels,
+            num_samples,
+            |channel_idx| self.storage[channel_idx].as_ptr().add(start),
+            |channel_idx| (*host_outputs.add(channel_idx)).add(start),
+        );
+    }
+}
+
+/// Copy `num_samples` samples from `num_inputs` input channels to `num_outputs` output channels. A
+/// mono input is duplicated to all outputs, a stereo input is downmixed using a -3 dB pan law for a
+/// mono output, and in all other cases the channels are copied one-to-one with any remaining output
+/// channels being filled with silence. The input and output channels may not overlap.
+unsafe fn adapt_channels(
+    num_inputs: usize,
+    num_outputs: usize,
+    num_samples: usize,
+    input: impl Fn(usize) -> *const f32,
+    output: impl Fn(usize) -> *mut f32,
+) {
+    match (num_inputs, num_outputs) {
+        (1, _) => {
+            for output_channel_idx in 0..num_outputs {
+                ptr::copy_nonoverlapping(input(0), output(output_channel_idx), num_samples);
+            }
+        }
+        (2, 1) => {
+            let left = input(0);
+            let right = input(1);
+            let output = output(0);
+            for sample_idx in 0..num_samples {
+                *output.add(sample_idx) =
+                    (*left.add(sample_idx) + *right.add(sample_idx)) * FRAC_1_SQRT_2;
+            }
+        }
+        _ => {
+            for output_channel_idx in 0..num_outputs {
+                if output_channel_idx < num_inputs {
+                    ptr::copy_nonoverlapping(
+                        input(output_channel_idx),
+                        output(output_channel_idx),
+                        num_samples,
+                    );
+                } else {
+                    ptr::write_bytes(output(output_channel_idx), 0, num_samples);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::AuxiliaryIOConfig;
+
+    fn bus_config(num_input_channels: u32, num_output_channels: u32) -> BusConfig {
+        BusConfig {
+            num_input_channels,
+            num_output_channels,
+            aux_input_busses: AuxiliaryIOConfig::default(),
+            aux_output_busses: AuxiliaryIOConfig::default(),
+        }
+    }
+
+    /// Run the adapter for a single block and return the host's output channels.
+    fn adapt(
+        host_config: &BusConfig,
+        plugin_config: &BusConfig,
+        inputs: &[Vec<f32>],
+    ) -> Vec<Vec<f32>> {
+        let num_samples = inputs[0].len();
+        let mut adapter = ChannelAdapter::new(host_config, plugin_config, num_samples).unwrap();
+        let input_ptrs: Vec<*const f32> = inputs.iter().map(|channel| channel.as_ptr()).collect();
+        // The outputs start out containing garbage to make sure the adapter overwrites them
+        let mut outputs =
+            vec![vec![f32::NAN; num_samples]; host_config.num_output_channels as usize];
+        let output_ptrs: Vec<*mut f32> = outputs
+            .iter_mut()
+            .map(|channel| channel.as_mut_ptr())
+            .collect();
+
+        unsafe {
+            adapter.read_inputs(input_ptrs.as_ptr(), input_ptrs.len(), 0, num_samples);
+            adapter.write_outputs(output_ptrs.as_ptr(), output_ptrs.len(), 0, num_samples);
+        }
+
+        outputs
+    }
+
+    #[test]
+    fn mono_to_stereo() {
+        let outputs = adapt(&bus_config(1, 2), &bus_config(2, 2), &[vec![0.5, -0.25]]);
+        assert_eq!(outputs, [vec![0.5, -0.25], vec![0.5, -0.25]]);
+    }
+
+    #[test]
+    fn stereo_to_mono() {
+        let outputs = adapt(
+            &bus_config(2, 1),
+            &bus_config(2, 2),
+            &[vec![1.0, 0.5], vec![1.0, -0.5]],
+        );
+        assert_eq!(outputs, [vec![2.0 * FRAC_1_SQRT_2, 0.0]]);
+    }
+
+    #[test]
+    fn stereo_through_mono() {
+        let outputs = adapt(
+            &bus_config(2, 2),
+            &bus_config(1, 1),
+            &[vec![1.0, 0.0], vec![0.0, 0.0]],
+        );
+        assert_eq!(
+            outputs,
+            [vec![FRAC_1_SQRT_2, 0.0], vec![FRAC_1_SQRT_2, 0.0]]
+        );
+    }
+
+    #[test]
+    fn missing_outputs_are_silent() {
+        let outputs = adapt(
+            &bus_config(2, 4),
+            &bus_config(2, 2),
+            &[vec![1.0], vec![2.0]],
+        );
+        assert_eq!(outputs, [vec![1.0], vec![2.0], vec![0.0], vec![0.0]]);
+    }
+}
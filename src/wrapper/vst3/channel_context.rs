@@ -0,0 +1,347 @@
+//! Track information sent by the host through `IInfoListener`. See
+//! [`GuiContext::channel_info()`][crate::prelude::GuiContext::channel_info()] for the plugin facing
+//! side of this.
+
+use std::os::raw::c_char;
+use vst3_sys::base::kResultOk;
+use vst3_sys::vst::{IAttributeList, TChar};
+
+use super::util::VstPtr;
+use crate::context::gui::{ChannelColor, ChannelInfo};
+
+// Channel context keys missing from vst3-sys
+const CHANNEL_NAME_KEY: &[u8] = b"channel name\0";
+const CHANNEL_NAME_LENGTH_KEY: &[u8] = b"channel name length\0";
+const CHANNEL_COLOR_KEY: &[u8] = b"channel color\0";
+const CHANNEL_INDEX_KEY: &[u8] = b"channel index\0";
+
+/// The buffer size used for the channel name when the host does not send the name's length.
+const DEFAULT_NAME_LENGTH: usize = 128;
+/// Names longer than this are truncated, in case the host sends a nonsensical length.
+const MAX_NAME_LENGTH: usize = 4096;
+
+/// Read the channel information from the attribute list passed to
+/// `IInfoListener::setChannelContextInfos()`. Keys that are missing from the list are left empty.
+///
+/// # Safety
+///
+/// `list` needs to be a valid attribute list for the duration of this call.
+pub unsafe fn read_channel_info(
+    list: &VstPtr<dyn IAttributeList>,
+    change_count: u32,
+) -> ChannelInfo {
+    ChannelInfo {
+        name: read_string(list, CHANNEL_NAME_KEY, CHANNEL_NAME_LENGTH_KEY),
+        // The color is an `0xAARRGGBB` value stored in an integer attribute
+        color: read_int(list, CHANNEL_COLOR_KEY).map(|argb| ChannelColor::from_argb(argb as u32)),
+        index: read_int(list, CHANNEL_INDEX_KEY),
+        change_count,
+    }
+}
+
+unsafe fn read_int(list: &VstPtr<dyn IAttributeList>, key: &[u8]) -> Option<i64> {
+    let mut value = 0i64;
+    if list.get_int(key.as_ptr() as *const c_char, &mut value) == kResultOk {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Read a UTF-16 string attribute. `length_key` is an integer attribute containing the string's
+/// length in characters, which is used to size the buffer if the host provides it. Invalid UTF-16
+/// is replaced with replacement characters.
+unsafe fn read_string(
+    list: &VstPtr<dyn IAttributeList>,
+    key: &[u8],
+    length_key: &[u8],
+) -> Option<String> {
+    let length = read_int(list, length_key)
+        .and_then(|length| usize::try_from(length).ok())
+        .unwrap_or(DEFAULT_NAME_LENGTH)
+        .min(MAX_NAME_LENGTH);
+
+    // The buffer's size is passed in bytes, and the string is null terminated
+    let mut buffer: Vec<TChar> = vec![0; length + 1];
+    let result = list.get_string(
+        key.as_ptr() as *const c_char,
+        buffer.as_mut_ptr(),
+        (buffer.len() * std::mem::size_of::<TChar>()) as u32,
+    );
+    if result != kResultOk {
+        return None;
+    }
+
+    // The string does not need to be null terminated if it fills the entire buffer
+    let string: Vec<u16> = buffer
+        .iter()
+        .map(|&c| c as u16)
+        .take_while(|&c| c != 0)
+        .collect();
+
+    Some(String::from_utf16_lossy(&string))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_void, CStr};
+    use std::mem;
+    use std::ptr;
+    use std::sync::Arc;
+    use vst3_sys::base::{kInvalidArgument, kResultFalse, tresult};
+    use vst3_sys::vst::IInfoListener;
+    use vst3_sys::VST3;
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::gui::GuiContext;
+    use crate::context::process::ProcessContext;
+    use crate::params::internals::ParamPtr;
+    use crate::params::Params;
+    use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+    use crate::wrapper::vst3::wrapper::Wrapper;
+    use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+    // Alias needed for the VST3 attribute macro
+    use vst3_sys as vst3_com;
+
+    /// Doesn't do anything. The channel information is handled entirely by the wrapper.
+    #[derive(Default)]
+    struct ChannelInfoPlugin;
+
+    struct NoParams;
+
+    unsafe impl Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    impl Plugin for ChannelInfoPlugin {
+        const NAME: &'static str = "Channel Info Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(NoParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for ChannelInfoPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugChanInfo0";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// The track information a host sends through `IInfoListener`, containing only the given
+    /// integer and UTF-16 string attributes. Keys include their null terminators.
+    #[VST3(implements(IAttributeList))]
+    struct TestAttributeList {
+        ints: Vec<(&'static [u8], i64)>,
+        strings: Vec<(&'static [u8], Vec<u16>)>,
+    }
+
+    impl TestAttributeList {
+        fn new(ints: &[(&'static [u8], i64)], strings: &[(&'static [u8], &[u16])]) -> Box<Self> {
+            Self::allocate(
+                ints.to_vec(),
+                strings
+                    .iter()
+                    .map(|&(key, string)| (key, string.to_vec()))
+                    .collect(),
+            )
+        }
+    }
+
+    impl IAttributeList for TestAttributeList {
+        unsafe fn set_int(&self, _id: *const c_char, _value: i64) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn get_int(&self, id: *const c_char, value: *mut i64) -> tresult {
+            let id = CStr::from_ptr(id).to_bytes_with_nul();
+            match self.ints.iter().find(|(key, _)| *key == id) {
+                Some((_, int)) => {
+                    *value = *int;
+                    kResultOk
+                }
+                None => kResultFalse,
+            }
+        }
+
+        unsafe fn set_float(&self, _id: *const c_char, _value: f64) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn get_float(&self, _id: *const c_char, _value: *mut f64) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn set_string(&self, _id: *const c_char, _string: *const TChar) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn get_string(&self, id: *const c_char, string: *mut TChar, size: u32) -> tresult {
+            let id = CStr::from_ptr(id).to_bytes_with_nul();
+            match self.strings.iter().find(|(key, _)| *key == id) {
+                Some((_, value)) => {
+                    // Like the SDK's host implementation this truncates the string to fit the
+                    // buffer, and always adds a null terminator
+                    let buffer_len = size as usize / mem::size_of::<TChar>();
+                    let num_chars = value.len().min(buffer_len.saturating_sub(1));
+                    for (idx, &c) in value[..num_chars].iter().enumerate() {
+                        *string.add(idx) = c as TChar;
+                    }
+                    if buffer_len > 0 {
+                        *string.add(num_chars) = 0;
+                    }
+
+                    kResultOk
+                }
+                None => kResultFalse,
+            }
+        }
+
+        unsafe fn set_binary(
+            &self,
+            _id: *const c_char,
+            _data: *const c_void,
+            _size: u32,
+        ) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn get_binary(
+            &self,
+            _id: *const c_char,
+            _data: *mut *const c_void,
+            _size: *mut u32,
+        ) -> tresult {
+            kResultFalse
+        }
+    }
+
+    fn utf16(string: &str) -> Vec<u16> {
+        string.encode_utf16().collect()
+    }
+
+    /// The track information sent through `IInfoListener` should be available through the GUI
+    /// context. Missing keys are left empty, and every update increments the change counter.
+    #[test]
+    fn channel_context_info() {
+        unsafe {
+            let wrapper = Wrapper::<ChannelInfoPlugin>::new();
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            assert_eq!(gui_context.channel_info(), None);
+
+            let list = TestAttributeList::new(
+                &[(CHANNEL_COLOR_KEY, 0x80336699), (CHANNEL_INDEX_KEY, 3)],
+                &[(CHANNEL_NAME_KEY, &utf16("Drums"))],
+            );
+            assert_eq!(
+                wrapper
+                    .set_channel_context_infos(mem::transmute(&*list as *const TestAttributeList)),
+                kResultOk
+            );
+            assert_eq!(
+                gui_context.channel_info(),
+                Some(ChannelInfo {
+                    name: Some(String::from("Drums")),
+                    color: Some(ChannelColor {
+                        r: 0x33,
+                        g: 0x66,
+                        b: 0x99,
+                        a: 0x80
+                    }),
+                    index: Some(3),
+                    change_count: 0,
+                })
+            );
+
+            // Renaming the track replaces the previous information, including the color the host
+            // did not send again
+            let list = TestAttributeList::new(&[], &[(CHANNEL_NAME_KEY, &utf16("Bass"))]);
+            assert_eq!(
+                wrapper
+                    .set_channel_context_infos(mem::transmute(&*list as *const TestAttributeList)),
+                kResultOk
+            );
+            assert_eq!(
+                gui_context.channel_info(),
+                Some(ChannelInfo {
+                    name: Some(String::from("Bass")),
+                    color: None,
+                    index: None,
+                    change_count: 1,
+                })
+            );
+
+            let list = TestAttributeList::new(&[], &[]);
+            assert_eq!(
+                wrapper
+                    .set_channel_context_infos(mem::transmute(&*list as *const TestAttributeList)),
+                kResultOk
+            );
+            let info = gui_context.channel_info().unwrap();
+            assert_eq!((info.name, info.color, info.index), (None, None, None));
+            assert_eq!(info.change_count, 2);
+
+            assert_eq!(
+                wrapper.set_channel_context_infos(mem::transmute(ptr::null::<c_void>())),
+                kInvalidArgument
+            );
+        }
+    }
+
+    /// Unpaired surrogates in the track name are replaced instead of discarding the name, and the
+    /// name length the host sends is used to size the buffer.
+    #[test]
+    fn channel_context_info_invalid_utf16() {
+        unsafe {
+            let wrapper = Wrapper::<ChannelInfoPlugin>::new();
+            let gui_context = wrapper.inner.clone().make_gui_context();
+
+            let mut name = utf16("Vox");
+            name.insert(1, 0xd800);
+            let list = TestAttributeList::new(&[], &[(CHANNEL_NAME_KEY, &name)]);
+            assert_eq!(
+                wrapper
+                    .set_channel_context_infos(mem::transmute(&*list as *const TestAttributeList)),
+                kResultOk
+            );
+            assert_eq!(
+                gui_context.channel_info().unwrap().name.as_deref(),
+                Some("V\u{fffd}ox")
+            );
+
+            let list = TestAttributeList::new(
+                &[(CHANNEL_NAME_LENGTH_KEY, 3)],
+                &[(CHANNEL_NAME_KEY, &utf16("Drums"))],
+            );
+            assert_eq!(
+                wrapper
+                    .set_channel_context_infos(mem::transmute(&*list as *const TestAttributeList)),
+                kResultOk
+            );
+            assert_eq!(
+                gui_context.channel_info().unwrap().name.as_deref(),
+                Some("Dru")
+            );
+        }
+    }
+}
@@ -0,0 +1,1555 @@
+//! Tests that drive the VST3 factory and wrapper purely through their COM vtables, following the
+//! call orders real hosts are known to use. Every host behavior is described by a [`Scenario`] in
+//! [`SCENARIOS`], so new quirks can be covered by adding another table entry. [`fuzz()`] throws
+//! randomized, but reproducible, automation and host behavior at the wrapper to catch the bugs
+//! that only show up under pathological conditions.
+
+use atomic_float::AtomicF32;
+use realfft::RealFftPlanner;
+use std::f32::consts::PI;
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use vst3_sys::base::{
+    kInvalidArgument, kNoInterface, kNotInitialized, kResultFalse, kResultOk, tresult,
+};
+use vst3_sys::base::{IPluginBase, IPluginFactory};
+use vst3_sys::interfaces::IUnknown;
+use vst3_sys::utils::SharedVstPtr;
+use vst3_sys::vst::{
+    IAudioProcessor, IComponent, IEditController, IParamValueQueue, IParameterChanges,
+    ProcessModes, SpeakerArrangement, SymbolicSampleSizes,
+};
+use vst3_sys::{ComInterface, VstPtr, VST3};
+
+use super::factory::{Factory, PluginClass};
+use crate::buffer::Buffer;
+use crate::context::init::InitContext;
+use crate::context::process::ProcessContext;
+use crate::params::enums::Enum;
+use crate::params::internals::ParamPtr;
+use crate::params::{FloatParam, Param, Params};
+use crate::plugin::{AuxiliaryBuffers, BufferConfig, BusConfig, Plugin, ProcessStatus, Vst3Plugin};
+use crate::util::{oversampling_latency, Xoshiro128};
+use crate::wrapper::oversampling::{OversamplingFactor, OVERSAMPLING_PARAM_ID};
+use crate::wrapper::util::hash_param_id;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+// Alias needed for the VST3 attribute macro
+use vst3_sys as vst3_com;
+
+const CLASSES: &[PluginClass] = &[
+    PluginClass::new::<ConformanceGain>(),
+    PluginClass::new::<ConformanceTone>(),
+    PluginClass::new::<ConformanceClipper>(),
+];
+
+const K_INPUT: i32 = vst3_sys::vst::BusDirections::kInput as i32;
+const K_OUTPUT: i32 = vst3_sys::vst::BusDirections::kOutput as i32;
+const K_AUDIO: i32 = vst3_sys::vst::MediaTypes::kAudio as i32;
+const K_SAMPLE_32: i32 = SymbolicSampleSizes::kSample32 as i32;
+const K_SAMPLE_64: i32 = SymbolicSampleSizes::kSample64 as i32;
+
+/// A copy of the gain example plugin. The example itself is a separate crate that can't be used
+/// from NIH-plug's own tests.
+#[derive(Default)]
+struct ConformanceGain {
+    params: Arc<ConformanceGainParams>,
+}
+
+struct ConformanceGainParams {
+    gain: FloatParam,
+}
+
+impl Default for ConformanceGainParams {
+    fn default() -> Self {
+        Self {
+            gain: FloatParam::new_gain_db("Gain", 0.0, -30.0, 30.0),
+        }
+    }
+}
+
+unsafe impl Params for ConformanceGainParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+    }
+}
+
+impl Plugin for ConformanceGain {
+    const NAME: &'static str = "Conformance Gain";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            let gain = self.params.gain.smoothed.next();
+            for sample in channel_samples {
+                *sample *= gain;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ConformanceGain {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugConfGain0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// A generator without any audio inputs that outputs the value of its gain parameter as a DC
+/// signal. This covers plugins like test tone generators and instruments.
+#[derive(Default)]
+struct ConformanceTone {
+    params: Arc<ConformanceGainParams>,
+}
+
+impl Plugin for ConformanceTone {
+    const NAME: &'static str = "Conformance Tone";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            let gain = self.params.gain.smoothed.next();
+            for sample in channel_samples {
+                *sample = gain;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ConformanceTone {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugConfTone0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Instrument, Vst3SubCategory::Generator];
+}
+
+/// Applies the gain parameter and then hard clips the signal at -6 dBFS. The wrapper is allowed to
+/// oversample this plugin, which should reduce the aliasing caused by the clipping. The sample rate
+/// the plugin was last initialized with is stored in [`CLIPPER_SAMPLE_RATE`].
+#[derive(Default)]
+struct ConformanceClipper {
+    params: Arc<ConformanceGainParams>,
+    sample_rate: f32,
+}
+
+static CLIPPER_SAMPLE_RATE: AtomicF32 = AtomicF32::new(0.0);
+
+impl Plugin for ConformanceClipper {
+    const NAME: &'static str = "Conformance Clipper";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const ALLOW_WRAPPER_OVERSAMPLING: &'static [u8] = &[1, 2, 4];
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.sample_rate = buffer_config.sample_rate;
+        CLIPPER_SAMPLE_RATE.store(buffer_config.sample_rate, Ordering::SeqCst);
+
+        true
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        // A panic makes the wrapper refuse to process the block
+        assert_eq!(context.transport().sample_rate, self.sample_rate);
+
+        for channel_samples in buffer.iter_samples() {
+            let gain = self.params.gain.smoothed.next();
+            for sample in channel_samples {
+                *sample = (*sample * gain).clamp(-0.5, 0.5);
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ConformanceClipper {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugConfClip0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Distortion];
+}
+
+/// A sequence of calls a host makes, and what it expects to get back.
+struct Scenario {
+    name: &'static str,
+    steps: &'static [Step],
+}
+
+/// A single step in a [`Scenario`]. The steps that correspond to a single call check that call's
+/// return value.
+#[derive(Debug)]
+enum Step {
+    /// `IAudioProcessor::setBusArrangements()`.
+    SetBusArrangements {
+        inputs: &'static [SpeakerArrangement],
+        outputs: &'static [SpeakerArrangement],
+        expected: tresult,
+    },
+    /// `IAudioProcessor::getBusArrangement()`, checking the arrangement if the call succeeds.
+    GetBusArrangement {
+        dir: i32,
+        index: i32,
+        expected: Result<SpeakerArrangement, tresult>,
+    },
+    /// `IComponent::getBusInfo()` for an audio bus, checking the channel count if the call
+    /// succeeds.
+    GetBusInfo {
+        dir: i32,
+        index: i32,
+        expected: Result<i32, tresult>,
+    },
+    /// `IComponent::activateBus()` for an audio bus.
+    ActivateBus {
+        dir: i32,
+        index: i32,
+        expected: tresult,
+    },
+    /// `IAudioProcessor::canProcessSampleSize()`.
+    CanProcessSampleSize { size: i32, expected: tresult },
+    /// `IAudioProcessor::setupProcessing()` with a realtime process mode.
+    SetupProcessing {
+        sample_size: i32,
+        sample_rate: f64,
+        max_block_size: i32,
+        expected: tresult,
+    },
+    /// `IComponent::setActive()`.
+    SetActive(bool),
+    /// `IAudioProcessor::setProcessing()`.
+    SetProcessing(bool),
+    /// `IEditController::setParamNormalized()` for the gain parameter. This should only be done
+    /// while the plugin is not processing audio so the new value is not smoothed.
+    SetGain(f64),
+    /// Process blocks of stereo audio with these sizes, and check that the output is the input
+    /// multiplied by the gain parameter's value. Generators don't get any inputs and should output
+    /// the gain parameter's value instead.
+    Process(&'static [usize]),
+    /// Process a block without any samples or buffers, like hosts do to flush parameter changes.
+    Flush,
+    /// `IAudioProcessor::setProcessing(true)` in a state where the plugin cannot process audio.
+    RejectedSetProcessing,
+    /// Process a block of this size in a state where the plugin cannot process audio. The wrapper
+    /// should refuse this and output silence.
+    RejectedProcess(usize),
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        // Ableton Live sets the bus arrangements before setting up processing
+        name: "set_bus_arrangements_first",
+        steps: &[
+            Step::SetBusArrangements {
+                inputs: &[vst3_sys::vst::kStereo],
+                outputs: &[vst3_sys::vst::kStereo],
+                expected: kResultOk,
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 48_000.0,
+                max_block_size: 512,
+                expected: kResultOk,
+            },
+            Step::ActivateBus {
+                dir: K_INPUT,
+                index: 0,
+                expected: kResultOk,
+            },
+            Step::ActivateBus {
+                dir: K_OUTPUT,
+                index: 0,
+                expected: kResultOk,
+            },
+            Step::SetGain(0.25),
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[512, 512, 256]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // Bitwig queries the bus layouts before anything else has been set up
+        name: "early_bus_queries",
+        steps: &[
+            Step::GetBusArrangement {
+                dir: K_OUTPUT,
+                index: 0,
+                expected: Ok(vst3_sys::vst::kStereo),
+            },
+            Step::GetBusArrangement {
+                dir: K_INPUT,
+                index: 0,
+                expected: Ok(vst3_sys::vst::kStereo),
+            },
+            Step::GetBusInfo {
+                dir: K_OUTPUT,
+                index: 0,
+                expected: Ok(2),
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 44_100.0,
+                max_block_size: 1024,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Flush,
+            Step::Process(&[1024]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // FL Studio sends blocks of varying and often tiny sizes
+        name: "tiny_blocks",
+        steps: &[
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 44_100.0,
+                max_block_size: 2048,
+                expected: kResultOk,
+            },
+            Step::SetGain(0.75),
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[1, 3, 7, 0, 64, 2, 2048]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // REAPER asks for double precision processing first
+        name: "double_precision_queries",
+        steps: &[
+            Step::CanProcessSampleSize {
+                size: K_SAMPLE_64,
+                expected: kResultFalse,
+            },
+            Step::CanProcessSampleSize {
+                size: K_SAMPLE_32,
+                expected: kResultOk,
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_64,
+                sample_rate: 48_000.0,
+                max_block_size: 512,
+                expected: kResultFalse,
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 48_000.0,
+                max_block_size: 512,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[512, 100]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // A host that proposes a mono layout the plugin does not support, and then falls back to
+        // asking for the plugin's own layout
+        name: "rejected_layout",
+        steps: &[
+            Step::SetBusArrangements {
+                inputs: &[vst3_sys::vst::kMono],
+                outputs: &[vst3_sys::vst::kStereo],
+                expected: kResultFalse,
+            },
+            Step::GetBusArrangement {
+                dir: K_INPUT,
+                index: 0,
+                expected: Ok(vst3_sys::vst::kStereo),
+            },
+            Step::GetBusInfo {
+                dir: K_INPUT,
+                index: 1,
+                expected: Err(kInvalidArgument),
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 96_000.0,
+                max_block_size: 128,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[128]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // Processing audio before the plugin has been set up and activated should be refused
+        name: "process_before_activation",
+        steps: &[
+            Step::RejectedProcess(64),
+            Step::RejectedSetProcessing,
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 44_100.0,
+                max_block_size: 64,
+                expected: kResultOk,
+            },
+            Step::RejectedProcess(64),
+            Step::RejectedSetProcessing,
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[64]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // Some hosts deactivate plugins that were never activated
+        name: "deactivate_before_activation",
+        steps: &[
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 48_000.0,
+                max_block_size: 256,
+                expected: kResultOk,
+            },
+            Step::SetActive(false),
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[256]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // The processing setup cannot change while the plugin is processing audio
+        name: "setup_while_processing",
+        steps: &[
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 48_000.0,
+                max_block_size: 256,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 96_000.0,
+                max_block_size: 1024,
+                expected: kResultFalse,
+            },
+            Step::Process(&[256]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // Processing audio after the plugin has been deactivated should be refused, even if the
+        // host never stopped processing
+        name: "process_after_deactivation",
+        steps: &[
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 44_100.0,
+                max_block_size: 128,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[128]),
+            Step::SetActive(false),
+            Step::RejectedProcess(128),
+            Step::SetProcessing(false),
+            Step::RejectedSetProcessing,
+            Step::RejectedProcess(128),
+        ],
+    },
+];
+
+/// Scenarios for [`ConformanceTone`], which does not have a main input bus.
+const GENERATOR_SCENARIOS: &[Scenario] = &[
+    Scenario {
+        // Hosts query all busses they know about, and some query busses that don't exist. The
+        // generator should not expose an empty input bus.
+        name: "generator_bus_queries",
+        steps: &[
+            Step::GetBusInfo {
+                dir: K_INPUT,
+                index: 0,
+                expected: Err(kInvalidArgument),
+            },
+            Step::GetBusArrangement {
+                dir: K_INPUT,
+                index: 0,
+                expected: Err(kInvalidArgument),
+            },
+            Step::ActivateBus {
+                dir: K_INPUT,
+                index: 0,
+                expected: kInvalidArgument,
+            },
+            Step::GetBusInfo {
+                dir: K_OUTPUT,
+                index: 0,
+                expected: Ok(2),
+            },
+            Step::GetBusArrangement {
+                dir: K_OUTPUT,
+                index: 0,
+                expected: Ok(vst3_sys::vst::kStereo),
+            },
+            Step::SetBusArrangements {
+                inputs: &[],
+                outputs: &[vst3_sys::vst::kStereo],
+                expected: kResultOk,
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 48_000.0,
+                max_block_size: 512,
+                expected: kResultOk,
+            },
+            Step::SetGain(0.25),
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[512, 7, 256]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+    Scenario {
+        // A host that proposes a sidechain input the generator does not have
+        name: "generator_rejected_input",
+        steps: &[
+            Step::SetBusArrangements {
+                inputs: &[vst3_sys::vst::kStereo],
+                outputs: &[vst3_sys::vst::kStereo],
+                expected: kResultFalse,
+            },
+            Step::GetBusInfo {
+                dir: K_INPUT,
+                index: 0,
+                expected: Err(kInvalidArgument),
+            },
+            Step::SetupProcessing {
+                sample_size: K_SAMPLE_32,
+                sample_rate: 44_100.0,
+                max_block_size: 128,
+                expected: kResultOk,
+            },
+            Step::SetActive(true),
+            Step::SetProcessing(true),
+            Step::Process(&[128]),
+            Step::SetProcessing(false),
+            Step::SetActive(false),
+        ],
+    },
+];
+
+/// Settings for [`fuzz()`].
+#[derive(Debug, Clone)]
+struct FuzzConfig {
+    /// The seed for the random number generator. Fuzzing with the same seed and the same plugin
+    /// always performs the same actions.
+    seed: u64,
+    /// How much audio to process, in seconds at the sample rate that's being used at that point.
+    duration_seconds: f64,
+    /// The sample rates the plugin gets reinitialized with. The first one is used initially.
+    sample_rates: &'static [f64],
+    /// The maximum block size passed to `IAudioProcessor::setupProcessing()`. The blocks that get
+    /// processed are never larger than this.
+    max_block_size: usize,
+    /// The largest absolute sample value the plugin may output.
+    max_output: f32,
+}
+
+/// A single thing the host does while fuzzing. A list of these is printed as the reproduction
+/// script when fuzzing fails.
+#[derive(Debug, Clone)]
+enum FuzzAction {
+    /// Process a block of audio at the specified song position in samples. `param_changes`
+    /// contains the `(sample_offset, normalized_value)` points for every parameter hash that
+    /// changes during this block, with the points sorted by their offset.
+    Process {
+        block_size: usize,
+        playing: bool,
+        position: i64,
+        param_changes: Vec<(u32, Vec<(i32, f64)>)>,
+    },
+    /// Stop and restart processing, which makes the wrapper reset the plugin.
+    Reset,
+    /// Deactivate the plugin, set up processing with a new sample rate, and activate it again.
+    Reinitialize { sample_rate: f64 },
+}
+
+/// The number of times [`fuzz()`] replays a failing run while looking for a smaller reproduction.
+const MAX_MINIMIZATION_RUNS: usize = 256;
+
+/// The parameter changes for a single process call while fuzzing. These are read only.
+#[VST3(implements(IParameterChanges))]
+struct FuzzParameterChanges {
+    queues: Vec<Box<FuzzParamValueQueue>>,
+}
+
+/// A single parameter's value changes in [`FuzzParameterChanges`].
+#[VST3(implements(IParamValueQueue))]
+struct FuzzParamValueQueue {
+    hash: u32,
+    /// `(sample_offset, normalized_value)` pairs.
+    points: Vec<(i32, f64)>,
+}
+
+/// The interfaces a host holds on to for a single plugin instance. These are all obtained and
+/// called through the objects' vtables.
+struct TestHost {
+    factory: VstPtr<dyn IPluginFactory>,
+    component: VstPtr<dyn IComponent>,
+    processor: VstPtr<dyn IAudioProcessor>,
+    controller: VstPtr<dyn IEditController>,
+    max_block_size: usize,
+}
+
+/// Take ownership of a reference to a COM object obtained from `GetPluginFactory()`,
+/// `createInstance()`, or `queryInterface()`.
+unsafe fn from_raw<I: ComInterface + ?Sized>(obj: *mut c_void) -> VstPtr<I> {
+    // `SharedVstPtr` is a transparent wrapper around the interface pointer, and upgrading it adds
+    // a reference that the new smart pointer owns
+    let shared: SharedVstPtr<I> = ptr::read(&obj as *const *mut c_void as *const SharedVstPtr<I>);
+    let owned = shared.upgrade().expect("Null interface pointer");
+    owned.release();
+
+    owned
+}
+
+impl TestHost {
+    /// Load the plugin `P` stored at `class_idx` in [`CLASSES`] the same way a host would after
+    /// loading the library.
+    unsafe fn new<P: Plugin>(class_idx: i32) -> Self {
+        // This is what `GetPluginFactory()` returns
+        let factory: VstPtr<dyn IPluginFactory> =
+            from_raw(Box::into_raw(Factory::new(CLASSES)) as *mut c_void);
+        assert_eq!(factory.count_classes(), CLASSES.len() as i32);
+
+        let mut class_info: vst3_sys::base::PClassInfo = mem::zeroed();
+        assert_eq!(
+            factory.get_class_info(class_idx, &mut class_info),
+            kResultOk
+        );
+        assert_eq!(
+            CStr::from_ptr(class_info.category.as_ptr()).to_str(),
+            Ok("Audio Module Class")
+        );
+        assert_eq!(
+            CStr::from_ptr(class_info.name.as_ptr()).to_str(),
+            Ok(P::NAME)
+        );
+
+        let mut obj = ptr::null_mut();
+        assert_eq!(
+            factory.create_instance(
+                &class_info.cid,
+                &<dyn IComponent as ComInterface>::IID,
+                &mut obj
+            ),
+            kResultOk
+        );
+        let component: VstPtr<dyn IComponent> = from_raw(obj);
+        assert_eq!(component.initialize(ptr::null_mut()), kResultOk);
+
+        // The plugin is a single component, so there is no separate edit controller
+        let mut controller_cid: vst3_sys::IID = mem::zeroed();
+        assert_eq!(
+            component.get_controller_class_id(&mut controller_cid),
+            kNoInterface
+        );
+        let processor = component
+            .cast::<dyn IAudioProcessor>()
+            .expect("The component does not implement IAudioProcessor");
+        let controller = component
+            .cast::<dyn IEditController>()
+            .expect("The component does not implement IEditController");
+
+        Self {
+            factory,
+            component,
+            processor,
+            controller,
+            max_block_size: 0,
+        }
+    }
+
+    unsafe fn run(&mut self, scenario: &Scenario) {
+        for (step_idx, step) in scenario.steps.iter().enumerate() {
+            let context = format!("step {step_idx} ({step:?}) of '{}'", scenario.name);
+            match *step {
+                Step::SetBusArrangements {
+                    inputs,
+                    outputs,
+                    expected,
+                } => {
+                    let mut inputs = inputs.to_vec();
+                    let mut outputs = outputs.to_vec();
+                    assert_eq!(
+                        self.processor.set_bus_arrangements(
+                            inputs.as_mut_ptr(),
+                            inputs.len() as i32,
+                            outputs.as_mut_ptr(),
+                            outputs.len() as i32,
+                        ),
+                        expected,
+                        "{context}"
+                    );
+                }
+                Step::GetBusArrangement {
+                    dir,
+                    index,
+                    expected,
+                } => {
+                    let mut arrangement: SpeakerArrangement = 0;
+                    let result = self
+                        .processor
+                        .get_bus_arrangement(dir, index, &mut arrangement);
+                    match expected {
+                        Ok(expected) => {
+                            assert_eq!(result, kResultOk, "{context}");
+                            assert_eq!(arrangement, expected, "{context}");
+                        }
+                        Err(expected) => assert_eq!(result, expected, "{context}"),
+                    }
+                }
+                Step::GetBusInfo {
+                    dir,
+                    index,
+                    expected,
+                } => {
+                    let mut info: vst3_sys::vst::BusInfo = mem::zeroed();
+                    let result = self.component.get_bus_info(K_AUDIO, dir, index, &mut info);
+                    match expected {
+                        Ok(channel_count) => {
+                            assert_eq!(result, kResultOk, "{context}");
+                            assert_eq!(info.media_type, K_AUDIO, "{context}");
+                            assert_eq!(info.direction, dir, "{context}");
+                            assert_eq!(info.channel_count, channel_count, "{context}");
+                            assert_eq!(
+                                info.bus_type,
+                                vst3_sys::vst::BusTypes::kMain as i32,
+                                "{context}"
+                            );
+                        }
+                        Err(expected) => assert_eq!(result, expected, "{context}"),
+                    }
+                }
+                Step::ActivateBus {
+                    dir,
+                    index,
+                    expected,
+                } => {
+                    assert_eq!(
+                        self.component.activate_bus(K_AUDIO, dir, index, 1),
+                        expected,
+                        "{context}"
+                    );
+                }
+                Step::CanProcessSampleSize { size, expected } => {
+                    assert_eq!(
+                        self.processor.can_process_sample_size(size),
+                        expected,
+                        "{context}"
+                    );
+                }
+                Step::SetupProcessing {
+                    sample_size,
+                    sample_rate,
+                    max_block_size,
+                    expected,
+                } => {
+                    let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+                    setup.process_mode = ProcessModes::kRealtime as i32;
+                    setup.symbolic_sample_size = sample_size;
+                    setup.max_samples_per_block = max_block_size;
+                    setup.sample_rate = sample_rate;
+                    assert_eq!(
+                        self.processor.setup_processing(&setup),
+                        expected,
+                        "{context}"
+                    );
+                    if expected == kResultOk {
+                        self.max_block_size = max_block_size as usize;
+                    }
+                }
+                Step::SetActive(state) => {
+                    assert_eq!(
+                        self.component.set_active(state as u8),
+                        kResultOk,
+                        "{context}"
+                    );
+                }
+                Step::SetProcessing(state) => {
+                    assert_eq!(
+                        self.processor.set_processing(state as u8),
+                        kResultOk,
+                        "{context}"
+                    );
+                }
+                Step::SetGain(normalized) => {
+                    assert_eq!(
+                        self.controller
+                            .set_param_normalized(hash_param_id("gain"), normalized),
+                        kResultOk,
+                        "{context}"
+                    );
+                }
+                Step::Process(block_sizes) => {
+                    for &block_size in block_sizes {
+                        assert!(block_size <= self.max_block_size, "{context}");
+                        self.process_block(block_size, kResultOk, &context);
+                    }
+                }
+                Step::RejectedSetProcessing => {
+                    assert_eq!(
+                        self.processor.set_processing(1),
+                        kNotInitialized,
+                        "{context}"
+                    );
+                }
+                Step::RejectedProcess(block_size) => {
+                    self.process_block(block_size, kNotInitialized, &context);
+                }
+                Step::Flush => {
+                    let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+                    data.process_mode = ProcessModes::kRealtime as i32;
+                    data.symbolic_sample_size = K_SAMPLE_32;
+                    assert_eq!(self.processor.process(&mut data), kResultOk, "{context}");
+                }
+            }
+        }
+    }
+
+    /// Process a block of stereo audio and check that the output matches the gain parameter. If
+    /// the plugin does not have a main input bus, then the host does not pass any input busses. If
+    /// the wrapper is expected to refuse processing the block, then the output should be silent
+    /// instead.
+    unsafe fn process_block(&self, block_size: usize, expected_result: tresult, context: &str) {
+        let gain_hash = hash_param_id("gain");
+        let gain = self
+            .controller
+            .normalized_param_to_plain(gain_hash, self.controller.get_param_normalized(gain_hash))
+            as f32;
+
+        // All of the event and context pointers are left null
+        let (result, inputs, outputs) = self.process_audio(block_size, ptr::null_mut(), None);
+        assert_eq!(result, expected_result, "{context}");
+
+        if expected_result != kResultOk {
+            for output in &outputs {
+                assert!(output.iter().all(|&sample| sample == 0.0), "{context}");
+            }
+
+            return;
+        }
+
+        let has_main_input = self.component.get_bus_count(K_AUDIO, K_INPUT) > 0;
+        for (input, output) in inputs.iter().zip(&outputs) {
+            for (input_sample, output_sample) in input.iter().zip(output) {
+                let expected = if has_main_input {
+                    input_sample * gain
+                } else {
+                    gain
+                };
+                assert!(
+                    (expected - output_sample).abs() <= 1e-5 * expected.abs().max(1.0),
+                    "{context}: expected {expected} but got {output_sample}"
+                );
+            }
+        }
+    }
+
+    /// Process a block of stereo audio with a sine wave as the input. The outputs are filled with
+    /// NaN values before processing, so samples the wrapper didn't write to stand out. Returns the
+    /// process call's result, the inputs, and the outputs.
+    unsafe fn process_audio(
+        &self,
+        block_size: usize,
+        process_context: *mut vst3_sys::vst::ProcessContext,
+        param_changes: Option<&FuzzParameterChanges>,
+    ) -> (tresult, Vec<Vec<f32>>, Vec<Vec<f32>>) {
+        let mut inputs: Vec<Vec<f32>> = (0..2)
+            .map(|channel_idx| {
+                (0..block_size)
+                    .map(|sample_idx| ((sample_idx + channel_idx) as f32 * 0.1).sin())
+                    .collect()
+            })
+            .collect();
+        let mut outputs = vec![vec![f32::NAN; block_size]; 2];
+
+        let mut input_ptrs: Vec<*mut f32> = inputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let mut output_ptrs: Vec<*mut f32> = outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let mut input_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+        input_bus.num_channels = 2;
+        input_bus.buffers = input_ptrs.as_mut_ptr() as _;
+        let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+        output_bus.num_channels = 2;
+        output_bus.buffers = output_ptrs.as_mut_ptr() as _;
+
+        let has_main_input = self.component.get_bus_count(K_AUDIO, K_INPUT) > 0;
+        let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+        data.process_mode = ProcessModes::kRealtime as i32;
+        data.symbolic_sample_size = K_SAMPLE_32;
+        data.num_samples = block_size as i32;
+        if has_main_input {
+            data.num_inputs = 1;
+            data.inputs = &mut input_bus;
+        }
+        data.num_outputs = 1;
+        data.outputs = &mut output_bus;
+        data.context = process_context;
+        if let Some(param_changes) = param_changes {
+            data.input_param_changes = mem::transmute(param_changes as *const FuzzParameterChanges);
+        }
+        let result = self.processor.process(&mut data);
+
+        (result, inputs, outputs)
+    }
+
+    /// Process a mono signal in blocks of up to `max_block_size` samples. Both of the plugin's
+    /// input channels receive `input`, and the first output channel is returned.
+    unsafe fn process_signal(&self, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::with_capacity(input.len());
+        for block in input.chunks(self.max_block_size) {
+            let mut inputs = vec![block.to_vec(); 2];
+            let mut outputs = vec![vec![f32::NAN; block.len()]; 2];
+
+            let mut input_ptrs: Vec<*mut f32> = inputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+            let mut output_ptrs: Vec<*mut f32> =
+                outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+            let mut input_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+            input_bus.num_channels = 2;
+            input_bus.buffers = input_ptrs.as_mut_ptr() as _;
+            let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+            output_bus.num_channels = 2;
+            output_bus.buffers = output_ptrs.as_mut_ptr() as _;
+
+            let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+            data.process_mode = ProcessModes::kRealtime as i32;
+            data.symbolic_sample_size = K_SAMPLE_32;
+            data.num_samples = block.len() as i32;
+            data.num_inputs = 1;
+            data.inputs = &mut input_bus;
+            data.num_outputs = 1;
+            data.outputs = &mut output_bus;
+            assert_eq!(self.processor.process(&mut data), kResultOk);
+
+            output.extend_from_slice(&outputs[0]);
+        }
+
+        output
+    }
+
+    /// Set up processing at `sample_rate` and start processing audio, like a host does before
+    /// playback.
+    unsafe fn start_processing(&mut self, sample_rate: f64, max_block_size: usize) {
+        let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+        setup.process_mode = ProcessModes::kRealtime as i32;
+        setup.symbolic_sample_size = K_SAMPLE_32;
+        setup.max_samples_per_block = max_block_size as i32;
+        setup.sample_rate = sample_rate;
+        assert_eq!(self.processor.setup_processing(&setup), kResultOk);
+        self.max_block_size = max_block_size;
+
+        assert_eq!(self.component.set_active(1), kResultOk);
+        assert_eq!(self.processor.set_processing(1), kResultOk);
+    }
+
+    /// Perform a single fuzzing action and check that the wrapper and the plugin still behave.
+    /// Panics if any of the invariants are violated.
+    unsafe fn run_fuzz_action(
+        &mut self,
+        action: &FuzzAction,
+        config: &FuzzConfig,
+        sample_rate: &mut f64,
+        latency: u32,
+    ) {
+        match action {
+            FuzzAction::Process {
+                block_size,
+                playing,
+                position,
+                param_changes,
+            } => {
+                assert!(*block_size <= self.max_block_size);
+
+                let mut process_context: vst3_sys::vst::ProcessContext = mem::zeroed();
+                process_context.state = if *playing { 1 << 1 } else { 0 }; // kPlaying
+                process_context.sample_rate = *sample_rate;
+                process_context.project_time_samples = *position;
+
+                let param_changes = FuzzParameterChanges::allocate(
+                    param_changes
+                        .iter()
+                        .map(|(hash, points)| FuzzParamValueQueue::allocate(*hash, points.clone()))
+                        .collect(),
+                );
+
+                let (result, _, outputs) =
+                    self.process_audio(*block_size, &mut process_context, Some(&param_changes));
+                assert_eq!(
+                    result, kResultOk,
+                    "The wrapper refused to process the block"
+                );
+                for output in &outputs {
+                    for &sample in output {
+                        assert!(
+                            sample.is_finite() && sample.abs() <= config.max_output,
+                            "The plugin output {sample}"
+                        );
+                    }
+                }
+            }
+            FuzzAction::Reset => {
+                assert_eq!(self.processor.set_processing(0), kResultOk);
+                assert_eq!(self.processor.set_processing(1), kResultOk);
+            }
+            FuzzAction::Reinitialize {
+                sample_rate: new_sample_rate,
+            } => {
+                assert_eq!(self.processor.set_processing(0), kResultOk);
+                assert_eq!(self.component.set_active(0), kResultOk);
+                self.start_processing(*new_sample_rate, config.max_block_size);
+                *sample_rate = *new_sample_rate;
+            }
+        }
+
+        // None of the test plugins change their latency
+        assert_eq!(self.processor.get_latency_samples(), latency);
+    }
+
+    /// The hashes of all of the parameters the plugin exposes to the host.
+    unsafe fn param_hashes(&self) -> Vec<u32> {
+        (0..self.controller.get_parameter_count())
+            .map(|param_idx| {
+                let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+                assert_eq!(
+                    self.controller.get_parameter_info(param_idx, &mut info),
+                    kResultOk
+                );
+
+                info.id
+            })
+            .collect()
+    }
+}
+
+impl Drop for TestHost {
+    fn drop(&mut self) {
+        unsafe { assert_eq!(self.component.terminate(), kResultOk) };
+    }
+}
+
+impl IParameterChanges for FuzzParameterChanges {
+    unsafe fn get_parameter_count(&self) -> i32 {
+        self.queues.len() as i32
+    }
+
+    unsafe fn get_parameter_data(&self, index: i32) -> SharedVstPtr<dyn IParamValueQueue> {
+        match self.queues.get(index as usize) {
+            Some(queue) => mem::transmute(&**queue as *const FuzzParamValueQueue),
+            None => mem::transmute(ptr::null::<c_void>()),
+        }
+    }
+
+    unsafe fn add_parameter_data(
+        &self,
+        _id: *const u32,
+        _index: *mut i32,
+    ) -> SharedVstPtr<dyn IParamValueQueue> {
+        mem::transmute(ptr::null::<c_void>())
+    }
+}
+
+impl IParamValueQueue for FuzzParamValueQueue {
+    unsafe fn get_parameter_id(&self) -> u32 {
+        self.hash
+    }
+
+    unsafe fn get_point_count(&self) -> i32 {
+        self.points.len() as i32
+    }
+
+    unsafe fn get_point(&self, index: i32, sample_offset: *mut i32, value: *mut f64) -> tresult {
+        match self.points.get(index as usize) {
+            Some(&(point_sample_offset, point_value)) => {
+                *sample_offset = point_sample_offset;
+                *value = point_value;
+                kResultOk
+            }
+            None => kInvalidArgument,
+        }
+    }
+
+    unsafe fn add_point(&self, _sample_offset: i32, _value: f64, _index: *mut i32) -> tresult {
+        kResultFalse
+    }
+}
+
+/// Throw randomized host behavior at the plugin `P` stored at `class_idx` in [`CLASSES`]: block
+/// sizes, parameter changes including bursts of hundreds of changes per block, transport jumps,
+/// resets, and reinitializations at different sample rates. After every action the plugin's output
+/// must be finite and bounded, and its latency must not have changed. Allocations on the audio
+/// thread are caught when the `assert_process_allocs` feature is enabled.
+///
+/// If anything fails, the failing run is shrunk down to a smaller list of actions that still fails,
+/// and then this panics with the seed and that list of actions.
+fn fuzz<P: Plugin>(class_idx: i32, config: &FuzzConfig) {
+    let param_hashes = unsafe { TestHost::new::<P>(class_idx).param_hashes() };
+    let mut actions = generate_fuzz_actions(config, &param_hashes);
+    if let Some(failed_action_idx) = run_fuzz_actions::<P>(class_idx, config, &actions) {
+        actions.truncate(failed_action_idx + 1);
+        let actions = minimize_fuzz_actions::<P>(class_idx, config, actions);
+
+        let script: String = actions
+            .iter()
+            .map(|action| format!("    {action:?},\n"))
+            .collect();
+        panic!(
+            "Fuzzing '{}' failed with seed {}. After starting at {} Hz, these actions reproduce the \
+             failure:\n{script}",
+            P::NAME,
+            config.seed,
+            config.sample_rates[0]
+        );
+    }
+}
+
+/// Generate the actions for a fuzzing run from the config's seed.
+fn generate_fuzz_actions(config: &FuzzConfig, param_hashes: &[u32]) -> Vec<FuzzAction> {
+    let mut rng = Xoshiro128::from_seed(config.seed);
+
+    let mut actions = Vec::new();
+    let mut sample_rate = config.sample_rates[0];
+    let mut elapsed_seconds = 0.0;
+    let mut playing = false;
+    let mut position = 0i64;
+    while elapsed_seconds < config.duration_seconds {
+        match random_below(&mut rng, 64) {
+            0 => actions.push(FuzzAction::Reset),
+            1 => {
+                sample_rate =
+                    config.sample_rates[random_below(&mut rng, config.sample_rates.len())];
+                actions.push(FuzzAction::Reinitialize { sample_rate });
+            }
+            2 => playing = !playing,
+            // Jumps cover pre-roll, positions past what fits in 32 bits, and the area just before
+            // `i32::MAX`
+            3 => position = rng.next_u32() as i64 * 4 - (1 << 20),
+            4 => {
+                position =
+                    i32::MAX as i64 - random_below(&mut rng, config.max_block_size * 4) as i64
+            }
+            _ => {
+                let block_size = if random_below(&mut rng, 8) == 0 {
+                    random_below(&mut rng, 9).min(config.max_block_size)
+                } else {
+                    1 + random_below(&mut rng, config.max_block_size)
+                };
+                let num_changes = if random_below(&mut rng, 16) == 0 {
+                    100 + random_below(&mut rng, 400)
+                } else {
+                    random_below(&mut rng, 4)
+                };
+
+                let mut changes: Vec<(u32, i32, f64)> = (0..num_changes)
+                    .map(|_| {
+                        let hash = param_hashes[random_below(&mut rng, param_hashes.len())];
+                        let sample_offset = random_below(&mut rng, block_size.max(1)) as i32;
+                        let value = match random_below(&mut rng, 8) {
+                            0 => 0.0,
+                            1 => 1.0,
+                            _ => rng.next_f32_unipolar() as f64,
+                        };
+
+                        (hash, sample_offset, value)
+                    })
+                    .collect();
+                changes.sort_by_key(|&(hash, sample_offset, _)| (hash, sample_offset));
+
+                let mut param_changes: Vec<(u32, Vec<(i32, f64)>)> = Vec::new();
+                for (hash, sample_offset, value) in changes {
+                    match param_changes.last_mut() {
+                        Some((last_hash, points)) if *last_hash == hash => {
+                            points.push((sample_offset, value))
+                        }
+                        _ => param_changes.push((hash, vec![(sample_offset, value)])),
+                    }
+                }
+
+                actions.push(FuzzAction::Process {
+                    block_size,
+                    playing,
+                    position,
+                    param_changes,
+                });
+
+                if playing {
+                    position += block_size as i64;
+                }
+                elapsed_seconds += block_size as f64 / sample_rate;
+            }
+        }
+    }
+
+    actions
+}
+
+/// Run `actions` on a new instance of the plugin `P`. Returns the index of the first action that
+/// failed, if any.
+fn run_fuzz_actions<P: Plugin>(
+    class_idx: i32,
+    config: &FuzzConfig,
+    actions: &[FuzzAction],
+) -> Option<usize> {
+    unsafe {
+        let mut host = TestHost::new::<P>(class_idx);
+        let mut sample_rate = config.sample_rates[0];
+        host.start_processing(sample_rate, config.max_block_size);
+        let latency = host.processor.get_latency_samples();
+
+        for (action_idx, action) in actions.iter().enumerate() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                host.run_fuzz_action(action, config, &mut sample_rate, latency)
+            }));
+            if result.is_err() {
+                // The instance may be in any state after this, so terminating it could fail as well
+                mem::forget(host);
+                return Some(action_idx);
+            }
+        }
+
+        None
+    }
+}
+
+/// Try removing increasingly smaller chunks of actions from a failing run, keeping every removal
+/// after which the run still fails.
+fn minimize_fuzz_actions<P: Plugin>(
+    class_idx: i32,
+    config: &FuzzConfig,
+    mut actions: Vec<FuzzAction>,
+) -> Vec<FuzzAction> {
+    let mut num_runs = 0;
+    let mut chunk_size = actions.len() / 2;
+    while chunk_size > 0 && num_runs < MAX_MINIMIZATION_RUNS {
+        let mut chunk_start = 0;
+        while chunk_start < actions.len() && num_runs < MAX_MINIMIZATION_RUNS {
+            let chunk_end = (chunk_start + chunk_size).min(actions.len());
+            let mut candidate = actions.clone();
+            candidate.drain(chunk_start..chunk_end);
+
+            num_runs += 1;
+            match run_fuzz_actions::<P>(class_idx, config, &candidate) {
+                Some(failed_action_idx) => {
+                    candidate.truncate(failed_action_idx + 1);
+                    actions = candidate;
+                }
+                None => chunk_start = chunk_end,
+            }
+        }
+
+        chunk_size /= 2;
+    }
+
+    actions
+}
+
+/// A uniformly distributed random integer in `[0, n)`.
+fn random_below(rng: &mut Xoshiro128, n: usize) -> usize {
+    ((rng.next_u32() as u64 * n as u64) >> 32) as usize
+}
+
+#[test]
+fn scenarios() {
+    for scenario in SCENARIOS {
+        unsafe { TestHost::new::<ConformanceGain>(0).run(scenario) };
+    }
+}
+
+#[test]
+fn generator_scenarios() {
+    for scenario in GENERATOR_SCENARIOS {
+        unsafe { TestHost::new::<ConformanceTone>(1).run(scenario) };
+    }
+}
+
+/// Invalid arguments should be rejected instead of causing crashes.
+#[test]
+fn invalid_arguments() {
+    unsafe {
+        let host = TestHost::new::<ConformanceGain>(0);
+
+        assert_eq!(
+            host.factory.get_class_info(1, &mut mem::zeroed()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.factory.get_class_info(0, ptr::null_mut()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.factory.create_instance(
+                ptr::null(),
+                &<dyn IComponent as ComInterface>::IID,
+                &mut ptr::null_mut()
+            ),
+            kInvalidArgument
+        );
+
+        assert_eq!(
+            host.component
+                .get_bus_info(K_AUDIO, K_OUTPUT, 0, ptr::null_mut()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.component
+                .get_bus_info(K_AUDIO, K_OUTPUT, 1, &mut mem::zeroed()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.component
+                .get_bus_info(K_AUDIO, 2, 0, &mut mem::zeroed()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.component.activate_bus(K_AUDIO, K_INPUT, 1, 1),
+            kInvalidArgument
+        );
+
+        assert_eq!(
+            host.processor
+                .get_bus_arrangement(K_OUTPUT, 0, ptr::null_mut()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.processor.get_bus_arrangement(K_OUTPUT, 1, &mut 0),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.processor
+                .set_bus_arrangements(ptr::null_mut(), 1, &mut vst3_sys::vst::kStereo, 1),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.processor.set_bus_arrangements(
+                &mut vst3_sys::vst::kStereo,
+                -1,
+                &mut vst3_sys::vst::kStereo,
+                1
+            ),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.processor.setup_processing(ptr::null()),
+            kInvalidArgument
+        );
+
+        let num_params = host.controller.get_parameter_count();
+        assert!(num_params >= 1);
+        assert_eq!(
+            host.controller.get_parameter_info(0, ptr::null_mut()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.controller
+                .get_parameter_info(num_params, &mut mem::zeroed()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.controller.get_parameter_info(-1, &mut mem::zeroed()),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.controller.get_param_string_by_value(
+                hash_param_id("does_not_exist"),
+                0.5,
+                [0; 128].as_mut_ptr()
+            ),
+            kInvalidArgument
+        );
+        assert_eq!(
+            host.controller
+                .get_param_string_by_value(hash_param_id("gain"), 0.5, ptr::null_mut()),
+            kInvalidArgument
+        );
+
+        // Unknown interfaces should not be handed out
+        let mut obj = ptr::null_mut();
+        assert_eq!(
+            host.component
+                .query_interface(&<dyn IPluginFactory as ComInterface>::IID, &mut obj),
+            kNoInterface
+        );
+        assert!(obj.is_null());
+    }
+}
+
+/// Clip a sine wave with the wrapper's oversampling parameter set to `oversampling`, and return
+/// the ratio between the power of the aliasing and the power of the harmonics.
+unsafe fn clipper_aliasing(oversampling: OversamplingFactor) -> f32 {
+    const SAMPLE_RATE: f32 = 48_000.0;
+    const BLOCK_SIZE: usize = 512;
+    const FFT_SIZE: usize = 8192;
+    // The sine wave lines up exactly with an FFT bin, so no window is needed. This is about 1.5
+    // kHz. Because this is odd, none of the aliased harmonics end up in another harmonic's bin.
+    const SINE_BIN: usize = 255;
+
+    let mut host = TestHost::new::<ConformanceClipper>(2);
+    let num_stages = oversampling.to_index();
+    let normalized =
+        num_stages as f64 / (ConformanceClipper::ALLOW_WRAPPER_OVERSAMPLING.len() - 1) as f64;
+    assert_eq!(
+        host.controller
+            .set_param_normalized(hash_param_id(OVERSAMPLING_PARAM_ID), normalized),
+        kResultOk
+    );
+    host.start_processing(SAMPLE_RATE as f64, BLOCK_SIZE);
+
+    // The plugin should run at the oversampled sample rate, and the host should be told about the
+    // oversampling filters' latency
+    assert_eq!(
+        CLIPPER_SAMPLE_RATE.load(Ordering::SeqCst),
+        SAMPLE_RATE * (1 << num_stages) as f32
+    );
+    assert_eq!(
+        host.processor.get_latency_samples(),
+        oversampling_latency(num_stages)
+    );
+
+    // The first `FFT_SIZE` samples are skipped to get past the filters' transients
+    let input: Vec<f32> = (0..FFT_SIZE * 2)
+        .map(|i| ((i * SINE_BIN) % FFT_SIZE) as f32 / FFT_SIZE as f32 * 2.0 * PI)
+        .map(f32::sin)
+        .collect();
+    let mut output = host.process_signal(&input).split_off(FFT_SIZE);
+
+    let fft = RealFftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+    let mut spectrum = fft.make_output_vec();
+    fft.process(&mut output, &mut spectrum).unwrap();
+
+    // Everything that's not a harmonic of the sine wave is aliasing. The oversampling filters'
+    // transition bands are excluded.
+    let mut harmonics_power = 0.0;
+    let mut aliasing_power = 0.0;
+    for (bin_idx, bin) in spectrum.iter().enumerate().take(FFT_SIZE * 2 / 5).skip(1) {
+        if bin_idx % SINE_BIN == 0 {
+            harmonics_power += bin.norm_sqr();
+        } else {
+            aliasing_power += bin.norm_sqr();
+        }
+    }
+
+    aliasing_power / harmonics_power
+}
+
+/// The clipper's aliasing should be reduced by at least 20 dB at 4x oversampling.
+#[test]
+fn wrapper_oversampling_reduces_aliasing() {
+    let aliasing_1x = unsafe { clipper_aliasing(OversamplingFactor::X1) };
+    let aliasing_2x = unsafe { clipper_aliasing(OversamplingFactor::X2) };
+    let aliasing_4x = unsafe { clipper_aliasing(OversamplingFactor::X4) };
+
+    assert!(
+        aliasing_2x < aliasing_1x,
+        "{aliasing_2x} at 2x, {aliasing_1x} at 1x"
+    );
+    assert!(
+        aliasing_4x * 100.0 < aliasing_1x,
+        "{aliasing_4x} at 4x, {aliasing_1x} at 1x"
+    );
+}
+
+#[test]
+fn fuzz_gain() {
+    for seed in 0..4 {
+        fuzz::<ConformanceGain>(
+            0,
+            &FuzzConfig {
+                seed,
+                duration_seconds: 5.0,
+                sample_rates: &[44_100.0, 48_000.0, 96_000.0],
+                max_block_size: 1024,
+                // The gain parameter goes up to +30 dB and the input is a sine wave
+                max_output: 32.0,
+            },
+        );
+    }
+}
+
+#[test]
+fn fuzz_generator() {
+    for seed in 0..4 {
+        fuzz::<ConformanceTone>(
+            1,
+            &FuzzConfig {
+                seed,
+                duration_seconds: 5.0,
+                sample_rates: &[22_050.0, 48_000.0, 192_000.0],
+                max_block_size: 4096,
+                max_output: 32.0,
+            },
+        );
+    }
+}
@@ -1,19 +1,25 @@
-use atomic_refcell::AtomicRefMut;
+use atomic_refcell::{AtomicRefCell, AtomicRefMut};
 use std::cell::Cell;
 use std::collections::VecDeque;
-use std::sync::atomic::Ordering;
+use std::mem;
 use std::sync::Arc;
-use vst3_sys::vst::IComponentHandler;
+use std::time::Instant;
+use vst3_sys::vst::{IComponentHandler, IComponentHandler2};
 
 use super::inner::{Task, WrapperInner};
-use crate::context::gui::GuiContext;
+use super::util::ObjectPtr;
+use crate::context::gui::{ChannelInfo, GuiContext, HostContextMenu, MusicalQuantize};
 use crate::context::init::InitContext;
 use crate::context::process::{ProcessContext, Transport};
+use crate::context::repaint::RepaintPolicy;
 use crate::context::PluginApi;
 use crate::midi::NoteEvent;
+use crate::params::changes::{ParamChange, ParamChangeSource, ParamChangeSubscription};
 use crate::params::internals::ParamPtr;
-use crate::plugin::Vst3Plugin;
+use crate::params::ParamFlags;
+use crate::plugin::{BypassMode, ProcessMode, Vst3Plugin};
 use crate::wrapper::state::PluginState;
+use crate::wrapper::util::{self, LifecycleState};
 
 /// An [`InitContext`] implementation for the wrapper.
 ///
@@ -52,6 +58,23 @@ pub(crate) struct WrapperProcessContext<'a, P: Vst3Plugin> {
 /// with the host for things like setting parameters.
 pub(crate) struct WrapperGuiContext<P: Vst3Plugin> {
     pub(super) inner: Arc<WrapperInner<P>>,
+    /// The gestures and parameter changes made during the current batch, if there is one. See
+    /// [`GuiContext::raw_begin_batch()`].
+    pub(super) batch: AtomicRefCell<ParamBatch>,
+}
+
+/// The state for a batch of parameter changes made from the GUI. Everything is sent to the host
+/// when the outermost batch ends.
+#[derive(Debug, Default)]
+pub(crate) struct ParamBatch {
+    /// The number of nested batches that are currently active.
+    depth: usize,
+    /// The hashes of the parameters whose gestures were started during this batch.
+    begin_gestures: Vec<u32>,
+    /// The `(hash, normalized_value)` pairs set during this batch, in the order they were made.
+    changes: Vec<(u32, f32)>,
+    /// The hashes of the parameters whose gestures were ended during this batch.
+    end_gestures: Vec<u32>,
 }
 
 impl<P: Vst3Plugin> Drop for WrapperInitContext<'_, P> {
@@ -76,6 +99,17 @@ impl<P: Vst3Plugin> InitContext<P> for WrapperInitContext<'_, P> {
         self.pending_requests.latency_changed.set(Some(samples));
     }
 
+    fn max_expected_sample_rate(&self) -> f32 {
+        let sample_rate = self
+            .inner
+            .current_buffer_config
+            .load()
+            .map_or(0.0, |c| c.sample_rate);
+
+        // The oversampling factor can only change when the plugin gets reinitialized
+        util::max_expected_sample_rate::<P>(sample_rate) * self.inner.oversampling.factor() as f32
+    }
+
     fn set_current_voice_capacity(&self, _capacity: u32) {
         // This is only supported by CLAP
     }
@@ -87,11 +121,18 @@ impl<P: Vst3Plugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
     }
 
     fn execute_background(&self, task: P::BackgroundTask) {
-        let task_posted = self.inner.schedule_background(Task::PluginTask(task));
+        nih_debug_assert_audio_thread!();
+
+        let priority = P::background_task_priority(&task);
+        let task_posted = self
+            .inner
+            .schedule_background(Task::PluginTask(task), priority);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
 
     fn execute_gui(&self, task: P::BackgroundTask) {
+        nih_debug_assert_audio_thread!();
+
         let task_posted = self.inner.schedule_gui(Task::PluginTask(task));
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
     }
@@ -101,14 +142,40 @@ impl<P: Vst3Plugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
         &self.transport
     }
 
+    fn deterministic_seed(&self) -> Option<u64> {
+        match self.inner.current_process_mode.load() {
+            ProcessMode::Offline => Some(self.inner.instance_id.load().seed()),
+            ProcessMode::Realtime | ProcessMode::Buffered => None,
+        }
+    }
+
+    fn bypassed(&self) -> bool {
+        self.inner.bypass_param_ptr.map_or(
+            false,
+            |ptr| unsafe { ptr.modulated_normalized_value() } >= 0.5,
+        )
+    }
+
+    fn bypass_mode(&self) -> BypassMode {
+        self.inner.current_process_mode.load().bypass_mode()
+    }
+
     fn next_event(&mut self) -> Option<NoteEvent> {
-        self.input_events_guard.pop_front()
+        let mut event = self.input_events_guard.pop_front()?;
+        self.inner.oversampling.upsample_event(&mut event);
+
+        Some(event)
     }
 
-    fn send_event(&mut self, event: NoteEvent) {
+    fn send_event(&mut self, mut event: NoteEvent) {
+        self.inner.oversampling.downsample_event(&mut event);
         self.output_events_guard.push_back(event);
     }
 
+    fn request_editor_repaint(&self) {
+        self.inner.editor_repaint.request();
+    }
+
     fn set_latency_samples(&self, samples: u32) {
         self.inner.set_latency_samples(samples)
     }
@@ -116,6 +183,48 @@ impl<P: Vst3Plugin> ProcessContext<P> for WrapperProcessContext<'_, P> {
     fn set_current_voice_capacity(&self, _capacity: u32) {
         // This is only supported by CLAP
     }
+
+    unsafe fn raw_set_output_parameter(&mut self, param: ParamPtr, normalized: f32) {
+        if !param.flags().contains(ParamFlags::READ_ONLY) {
+            nih_debug_assert_failure!(
+                "Only read-only parameters can be set from the process function: {:?}",
+                param
+            );
+            return;
+        }
+
+        match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => {
+                self.inner.set_normalized_value_by_hash(
+                    *hash,
+                    normalized,
+                    Some(self.transport.sample_rate),
+                    ParamChangeSource::Internal,
+                );
+
+                let success = self.inner.output_param_changes.push((*hash, normalized));
+                nih_debug_assert!(
+                    success.is_ok(),
+                    "The output parameter queue is full, the new value will not be sent to the \
+                     host"
+                );
+            }
+            None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+        }
+    }
+}
+
+impl<P: Vst3Plugin> WrapperGuiContext<P> {
+    /// Get the current batch if a batch of parameter changes is being made, or `None` if changes
+    /// should be sent to the host immediately.
+    fn active_batch(&self) -> Option<AtomicRefMut<'_, ParamBatch>> {
+        let batch = self.batch.borrow_mut();
+        if batch.depth > 0 {
+            Some(batch)
+        } else {
+            None
+        }
+    }
 }
 
 impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
@@ -124,6 +233,12 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
     }
 
     fn request_resize(&self) -> bool {
+        // The editor may resize itself in response to a parameter change the host made while the
+        // editor was closed
+        if !self.inner.is_editor_open() {
+            return false;
+        }
+
         let task_posted = self.inner.schedule_gui(Task::RequestResize);
         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
 
@@ -132,9 +247,30 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
         true
     }
 
+    fn request_repaint(&self) {
+        self.inner.editor_repaint.request();
+    }
+
+    fn set_repaint_policy(&self, policy: RepaintPolicy) {
+        self.inner.editor_repaint.set_policy(policy);
+    }
+
+    fn poll_repaint(&self) -> bool {
+        self.inner.editor_repaint.poll(Instant::now())
+    }
+
     // All of these functions are supposed to be called from the main thread, so we'll put some
     // trust in the caller and assume that this is indeed the case
     unsafe fn raw_begin_set_parameter(&self, param: ParamPtr) {
+        if let Some(mut batch) = self.active_batch() {
+            match self.inner.param_ptr_to_hash.get(&param) {
+                Some(hash) => batch.begin_gestures.push(*hash),
+                None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+            }
+
+            return;
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -147,6 +283,15 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_set_parameter_normalized(&self, param: ParamPtr, normalized: f32) {
+        if let Some(mut batch) = self.active_batch() {
+            match self.inner.param_ptr_to_hash.get(&param) {
+                Some(hash) => batch.changes.push((*hash, normalized)),
+                None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+            }
+
+            return;
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -157,16 +302,22 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
                     // FIXME: So this doesn't work for REAPER, because they just silently stop
                     //        processing audio when you bypass the plugin. Great. We can add a time
                     //        based heuristic to work around this in the meantime.
-                    if !self.inner.is_processing.load(Ordering::SeqCst) {
+                    if !self.inner.is_processing() {
                         self.inner.set_normalized_value_by_hash(
                             *hash,
                             normalized,
-                            self.inner
-                                .current_buffer_config
-                                .load()
-                                .map(|c| c.sample_rate),
+                            self.inner.plugin_buffer_config().map(|c| c.sample_rate),
+                            ParamChangeSource::GuiGesture,
                         );
                         self.inner.notify_param_values_changed();
+                    } else {
+                        // The change is recorded now so the host sending it back during processing
+                        // doesn't count as automation
+                        self.inner.param_changes.record(
+                            param,
+                            ParamChangeSource::GuiGesture,
+                            normalized,
+                        );
                     }
 
                     handler.perform_edit(*hash, normalized as f64);
@@ -178,6 +329,15 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
     }
 
     unsafe fn raw_end_set_parameter(&self, param: ParamPtr) {
+        if let Some(mut batch) = self.active_batch() {
+            match self.inner.param_ptr_to_hash.get(&param) {
+                Some(hash) => batch.end_gestures.push(*hash),
+                None => nih_debug_assert_failure!("Unknown parameter: {:?}", param),
+            }
+
+            return;
+        }
+
         match &*self.inner.component_handler.borrow() {
             Some(handler) => match self.inner.param_ptr_to_hash.get(&param) {
                 Some(hash) => {
@@ -189,11 +349,836 @@ impl<P: Vst3Plugin> GuiContext for WrapperGuiContext<P> {
         }
     }
 
+    fn raw_begin_batch(&self) {
+        self.batch.borrow_mut().depth += 1;
+    }
+
+    fn raw_end_batch(&self) {
+        // The batch is taken out of the cell first so the host can safely call back into the GUI
+        // context while we're sending the changes
+        let (begin_gestures, changes, end_gestures) = {
+            let mut batch = self.batch.borrow_mut();
+            if batch.depth == 0 {
+                nih_debug_assert_failure!("Ended a parameter batch without starting one");
+                return;
+            }
+
+            batch.depth -= 1;
+            if batch.depth > 0 {
+                return;
+            }
+
+            (
+                mem::take(&mut batch.begin_gestures),
+                mem::take(&mut batch.changes),
+                mem::take(&mut batch.end_gestures),
+            )
+        };
+
+        // The new values are applied to the plugin at the same block boundary. The host will also
+        // send these changes back to the plugin during processing, but at that point the values
+        // will already match.
+        self.inner.set_normalized_values_batched(&changes);
+
+        match &*self.inner.component_handler.borrow() {
+            Some(handler) => unsafe {
+                // Hosts that support `IComponentHandler2` treat everything between these two calls
+                // as a single edit, which means the entire batch ends up as a single undo point
+                let group_handler = self.inner.component_handler2.borrow();
+                if let Some(group_handler) = &*group_handler {
+                    group_handler.start_group_edit();
+                }
+
+                for hash in begin_gestures {
+                    handler.begin_edit(hash);
+                }
+                for (hash, normalized) in changes {
+                    handler.perform_edit(hash, normalized as f64);
+                }
+                for hash in end_gestures {
+                    handler.end_edit(hash);
+                }
+
+                if let Some(group_handler) = &*group_handler {
+                    group_handler.finish_group_edit();
+                }
+            },
+            None => nih_debug_assert_failure!("Component handler not yet set"),
+        }
+    }
+
     fn get_state(&self) -> PluginState {
         self.inner.get_state_object()
     }
 
     fn set_state(&self, state: PluginState) {
-        self.inner.set_state_object(state)
+        self.inner.set_state_object(state);
+
+        // The state may contain persistent fields, which the host doesn't know about
+        self.inner.mark_state_dirty();
+    }
+
+    fn last_param_change(&self, param: ParamPtr) -> Option<ParamChange> {
+        self.inner.param_changes.last_change(param)
+    }
+
+    fn subscribe_param_changes(&self) -> ParamChangeSubscription {
+        ParamChangeSubscription::new(self.inner.param_changes.clone())
+    }
+
+    fn mark_state_dirty(&self) {
+        self.inner.mark_state_dirty();
+    }
+
+    fn param_context_menu(&self, param: ParamPtr) -> Option<HostContextMenu> {
+        let param_hash = match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => *hash,
+            None => {
+                nih_debug_assert_failure!("Unknown parameter: {:?}", param);
+                return None;
+            }
+        };
+
+        // The host needs our `IPlugView` to create the menu. The lock can't be held while calling
+        // into the host since the host may close the editor in the meantime.
+        let plug_view = self
+            .inner
+            .plug_view
+            .read()
+            .as_ref()
+            .map(|plug_view| ObjectPtr::from(&**plug_view))?;
+
+        plug_view.param_context_menu(param_hash)
+    }
+
+    fn channel_info(&self) -> Option<ChannelInfo> {
+        self.inner.channel_info.lock().clone()
+    }
+
+    unsafe fn raw_set_modulation_write(&self, param: ParamPtr, armed: bool) -> bool {
+        let hash = match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => *hash,
+            None => {
+                nih_debug_assert_failure!("Unknown parameter: {:?}", param);
+                return false;
+            }
+        };
+        if !self.inner.modulation_writer.can_write(hash) {
+            nih_debug_assert_failure!("Read-only parameters can't be armed: {:?}", param);
+            return false;
+        }
+
+        let handler = self.inner.component_handler.borrow();
+        let handler = match &*handler {
+            Some(handler) => handler,
+            None => {
+                nih_debug_assert_failure!("Component handler not yet set");
+                return false;
+            }
+        };
+
+        // The gesture needs to start before the audio thread writes the first value, and it ends
+        // after the audio thread stops writing values
+        if armed {
+            if !self.inner.modulation_writer.is_armed(hash) {
+                handler.begin_edit(hash);
+                self.inner.modulation_writer.set_armed(hash, true);
+            }
+        } else if self.inner.modulation_writer.set_armed(hash, false) {
+            handler.end_edit(hash);
+        }
+
+        armed
+    }
+
+    unsafe fn raw_set_parameter_at_musical(
+        &self,
+        param: ParamPtr,
+        normalized: f32,
+        quantize: MusicalQuantize,
+    ) -> bool {
+        let hash = match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => *hash,
+            None => {
+                nih_debug_assert_failure!("Unknown parameter: {:?}", param);
+                return false;
+            }
+        };
+
+        // The change would never fire if the plugin isn't processing audio, so it's applied
+        // immediately instead
+        if quantize == MusicalQuantize::Off
+            || !normalized.is_finite()
+            || self.inner.current_buffer_config.load().is_none()
+            || !matches!(
+                self.inner.lifecycle_state.load(),
+                LifecycleState::Active | LifecycleState::Processing
+            )
+        {
+            return false;
+        }
+
+        self.inner
+            .musical_scheduler
+            .schedule(hash, normalized.clamp(0.0, 1.0), quantize)
+    }
+
+    unsafe fn raw_cancel_parameter_at_musical(&self, param: ParamPtr) -> bool {
+        match self.inner.param_ptr_to_hash.get(&param) {
+            Some(hash) => self.inner.musical_scheduler.cancel(*hash),
+            None => {
+                nih_debug_assert_failure!("Unknown parameter: {:?}", param);
+                false
+            }
+        }
+    }
+
+    fn pending_parameter_at_musical(&self, param: ParamPtr) -> Option<f32> {
+        self.inner
+            .param_ptr_to_hash
+            .get(&param)
+            .and_then(|hash| self.inner.musical_scheduler.pending(*hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::c_void;
+    use std::ptr;
+    use vst3_sys::base::kResultOk;
+    use vst3_sys::vst::{IEditController, ProcessModes};
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::gui::ParamSetter;
+    use crate::params::range::FloatRange;
+    use crate::params::{FloatParam, Param, Params};
+    use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus};
+    use crate::util::Xoshiro128;
+    use crate::wrapper::state;
+    use crate::wrapper::util::hash_param_id;
+    use crate::wrapper::vst3::tests::fixture::{
+        activate, activate_in_mode, deactivate, process_block, process_block_output_params,
+        save_state, Fixture, HandlerCall, TestComponentHandler, BLOCK_SIZE, SAMPLE_RATE,
+    };
+    use crate::wrapper::vst3::wrapper::Wrapper;
+    use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+    /// The number of parameters on [`CountingPlugin`].
+    const NUM_COUNTING_PARAMS: usize = 20;
+
+    /// Outputs the number of its parameters that are set to at least 0.5 as a DC signal, so it can
+    /// be checked whether multiple parameter changes arrived in the same block.
+    #[derive(Default)]
+    struct CountingPlugin {
+        params: Arc<CountingParams>,
+    }
+
+    struct CountingParams {
+        params: Vec<FloatParam>,
+    }
+
+    impl Default for CountingParams {
+        fn default() -> Self {
+            Self {
+                params: (0..NUM_COUNTING_PARAMS)
+                    .map(|_| {
+                        FloatParam::new("Param", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    unsafe impl Params for CountingParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            self.params
+                .iter()
+                .enumerate()
+                .map(|(idx, param)| (format!("param_{idx}"), param.as_ptr(), String::new()))
+                .collect()
+        }
+    }
+
+    impl Plugin for CountingPlugin {
+        const NAME: &'static str = "Counting Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            let num_enabled = self
+                .params
+                .params
+                .iter()
+                .filter(|param| param.value() >= 0.5)
+                .count() as f32;
+            for channel_samples in buffer.iter_samples() {
+                for sample in channel_samples {
+                    *sample = num_enabled;
+                }
+            }
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for CountingPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugCounting0";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// The modulation offsets [`ModWritePlugin`] applies to its parameter in consecutive process
+    /// calls. The offset stays the same for two blocks in a row in a couple places.
+    const MOD_WRITE_OFFSETS: [f32; 8] = [0.0, 0.1, 0.2, 0.2, 0.3, -0.1, -0.1, 0.0];
+
+    /// Modulates its parameter from its process function, like an internal LFO would, using the
+    /// offsets from [`MOD_WRITE_OFFSETS`].
+    #[derive(Default)]
+    struct ModWritePlugin {
+        params: Arc<ModWriteParams>,
+        num_blocks: usize,
+    }
+
+    struct ModWriteParams {
+        speed: FloatParam,
+    }
+
+    impl Default for ModWriteParams {
+        fn default() -> Self {
+            Self {
+                speed: FloatParam::new("Speed", 0.5, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            }
+        }
+    }
+
+    unsafe impl Params for ModWriteParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("speed"), self.speed.as_ptr(), String::new())]
+        }
+    }
+
+    impl Plugin for ModWritePlugin {
+        const NAME: &'static str = "Modulation Write Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            let offset = MOD_WRITE_OFFSETS[self.num_blocks % MOD_WRITE_OFFSETS.len()];
+            self.params.speed.modulate_value(offset);
+            self.num_blocks += 1;
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for ModWritePlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugModWrite0";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// Outputs its parameter's unsmoothed value as a DC signal, with sample accurate automation so
+    /// the output shows the exact sample a change landed on.
+    #[derive(Default)]
+    struct ValuePlugin {
+        params: Arc<ValueParams>,
+    }
+
+    struct ValueParams {
+        value: FloatParam,
+    }
+
+    impl Default for ValueParams {
+        fn default() -> Self {
+            Self {
+                value: FloatParam::new(
+                    "Value",
+                    0.25,
+                    FloatRange::Linear {
+                        min: 0.25,
+                        max: 4.0,
+                    },
+                ),
+            }
+        }
+    }
+
+    unsafe impl Params for ValueParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("value"), self.value.as_ptr(), String::new())]
+        }
+    }
+
+    impl Plugin for ValuePlugin {
+        const NAME: &'static str = "Value Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            let value = self.params.value.value();
+            for channel_samples in buffer.iter_samples() {
+                for sample in channel_samples {
+                    *sample = value;
+                }
+            }
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for ValuePlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugValueTest";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// Outputs white noise. The random number generator is recreated using
+    /// [`Xoshiro128::from_context()`] whenever the plugin is reset.
+    #[derive(Default)]
+    struct NoisePlugin {
+        rng: Option<Xoshiro128>,
+    }
+
+    struct NoParams;
+
+    unsafe impl Params for NoParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    impl Plugin for NoisePlugin {
+        const NAME: &'static str = "Noise Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(NoParams)
+        }
+
+        fn reset(&mut self) {
+            self.rng = None;
+        }
+
+        fn process(
+            &mut self,
+            buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            let rng = self
+                .rng
+                .get_or_insert_with(|| Xoshiro128::from_context(context));
+            for channel_samples in buffer.iter_samples() {
+                for sample in channel_samples {
+                    *sample = rng.next_f32();
+                }
+            }
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for NoisePlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugNoiseTest";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// A batch of parameter changes made from the GUI should be sent to the host as a single group
+    /// edit, and all of the changes should reach the audio thread in the same block.
+    #[test]
+    fn gui_param_batch() {
+        unsafe {
+            let handler = TestComponentHandler::new();
+            let wrapper = Wrapper::<CountingPlugin>::new();
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(
+                    &*handler as *const TestComponentHandler
+                )),
+                kResultOk
+            );
+            activate(&wrapper);
+
+            let params = wrapper.inner.plugin.lock().params.clone();
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            let setter = ParamSetter::new(&*gui_context);
+            let mut output = Vec::new();
+            setter.with_batch(|setter| {
+                for (idx, param) in params.params.iter().enumerate() {
+                    setter.set_parameter_with_gesture(param, 1.0);
+
+                    // Nested batches are flattened into the outer batch, and nothing should be
+                    // sent until that has ended
+                    if idx == NUM_COUNTING_PARAMS / 2 {
+                        setter.with_batch(|_| ());
+                        process_block(&wrapper, &mut output);
+                        assert!(output.iter().all(|sample| *sample == 0.0));
+                        assert!(handler.calls.lock().is_empty());
+                    }
+                }
+            });
+
+            output.clear();
+            process_block(&wrapper, &mut output);
+            assert!(output
+                .iter()
+                .all(|sample| *sample == NUM_COUNTING_PARAMS as f32));
+
+            let hashes: Vec<u32> = (0..NUM_COUNTING_PARAMS)
+                .map(|idx| hash_param_id(&format!("param_{idx}")))
+                .collect();
+            let mut expected_calls = vec![HandlerCall::StartGroupEdit];
+            expected_calls.extend(hashes.iter().map(|&hash| HandlerCall::BeginEdit(hash)));
+            expected_calls.extend(
+                hashes
+                    .iter()
+                    .map(|&hash| HandlerCall::PerformEdit(hash, 1.0)),
+            );
+            expected_calls.extend(hashes.iter().map(|&hash| HandlerCall::EndEdit(hash)));
+            expected_calls.push(HandlerCall::FinishGroupEdit);
+            assert_eq!(*handler.calls.lock(), expected_calls);
+
+            // The wrapper needs to release the handler before the handler gets dropped
+            drop(setter);
+            drop(gui_context);
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(ptr::null::<c_void>())),
+                kResultOk
+            );
+            deactivate(&wrapper);
+        }
+    }
+
+    /// Schedule a change to [`ValuePlugin`]'s parameter for the next beat or bar and process
+    /// `num_blocks` blocks at 120 BPM in 4/4, starting at `start_pos_beats`. Returns the plugin's
+    /// output and the `(block_idx, hash, normalized_value)` points sent back to the host.
+    unsafe fn process_musical_blocks(
+        wrapper: &Wrapper<ValuePlugin>,
+        start_pos_beats: f64,
+        quantize: MusicalQuantize,
+        num_blocks: usize,
+    ) -> (Vec<f32>, Vec<(usize, u32, f64)>) {
+        let gui_context = wrapper.inner.clone().make_gui_context();
+        let setter = ParamSetter::new(gui_context.as_ref());
+        let params = wrapper.inner.plugin.lock().params.clone();
+        assert!(setter.set_parameter_at_musical(&params.value, 4.0, quantize));
+        assert!((setter.pending_parameter_at_musical(&params.value).unwrap() - 4.0).abs() < 1e-4);
+
+        let mut output = Vec::new();
+        let mut output_points = Vec::new();
+        for block_idx in 0..num_blocks {
+            // kPlaying | kProjectTimeMusicValid | kTempoValid | kTimeSigValid
+            let mut context: vst3_sys::vst::ProcessContext = mem::zeroed();
+            context.state = (1 << 1) | (1 << 9) | (1 << 10) | (1 << 13);
+            context.sample_rate = SAMPLE_RATE as f64;
+            context.project_time_samples = (block_idx * BLOCK_SIZE) as i64;
+            context.project_time_music =
+                start_pos_beats + (block_idx * BLOCK_SIZE) as f64 / 22_050.0;
+            context.tempo = 120.0;
+            context.time_sig_num = 4;
+            context.time_sig_den = 4;
+
+            output_points.extend(
+                process_block_output_params(wrapper, &mut output, &mut context)
+                    .into_iter()
+                    .map(|(hash, _, value)| (block_idx, hash, value)),
+            );
+        }
+
+        (output, output_points)
+    }
+
+    /// Changes scheduled from the editor should land on the first sample of the next beat or bar,
+    /// regardless of where that sample falls within the host's blocks.
+    #[test]
+    fn musical_parameter_changes() {
+        unsafe {
+            let hash = hash_param_id("value");
+
+            // At 120 BPM a beat is 22050 samples long, so the next beat is 2205 samples away.
+            // That's in the middle of the 18th block.
+            let wrapper = Fixture::<ValuePlugin>::new().activate();
+            let (output, output_points) =
+                process_musical_blocks(&wrapper, 0.9, MusicalQuantize::Beat, 20);
+            assert!(output[..2205].iter().all(|&sample| sample == 0.25));
+            assert!(output[2205..]
+                .iter()
+                .all(|&sample| (sample - 4.0).abs() < 1e-4));
+            assert_eq!(output_points, [(17, hash, 1.0)]);
+            assert_eq!(
+                wrapper
+                    .inner
+                    .param_changes
+                    .last_change(wrapper.inner.param_by_hash[&hash])
+                    .map(|change| change.source),
+                Some(ParamChangeSource::GuiGesture)
+            );
+            deactivate(&wrapper);
+
+            // Here the next bar starts exactly at the start of the 11th block
+            let wrapper = Fixture::<ValuePlugin>::new().activate();
+            let (output, output_points) = process_musical_blocks(
+                &wrapper,
+                4.0 - (10 * BLOCK_SIZE) as f64 / 22_050.0,
+                MusicalQuantize::Bar,
+                12,
+            );
+            assert!(output[..10 * BLOCK_SIZE]
+                .iter()
+                .all(|&sample| sample == 0.25));
+            assert!(output[10 * BLOCK_SIZE..]
+                .iter()
+                .all(|&sample| (sample - 4.0).abs() < 1e-4));
+            assert_eq!(output_points, [(10, hash, 1.0)]);
+
+            // A cancelled change never fires, and it's no longer shown as pending
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            let setter = ParamSetter::new(gui_context.as_ref());
+            let params = wrapper.inner.plugin.lock().params.clone();
+            assert!(setter.set_parameter_at_musical(&params.value, 1.0, MusicalQuantize::Beat));
+            assert!(setter.cancel_parameter_at_musical(&params.value));
+            assert!(!setter.cancel_parameter_at_musical(&params.value));
+            assert_eq!(setter.pending_parameter_at_musical(&params.value), None);
+            let mut output = Vec::new();
+            for _ in 0..200 {
+                process_block(&*wrapper, &mut output);
+            }
+            assert!(output.iter().all(|&sample| (sample - 4.0).abs() < 1e-4));
+            deactivate(&wrapper);
+        }
+    }
+
+    /// Modulation should only be written to the host while the parameter is armed. The written
+    /// values should follow the parameter's modulated value, and the host should see exactly one
+    /// gesture around them.
+    #[test]
+    fn write_modulation_to_host() {
+        /// Process a block and return the output parameter changes sent to the host.
+        unsafe fn process_mod_write_block(
+            wrapper: &Wrapper<ModWritePlugin>,
+        ) -> Vec<(u32, i32, f64)> {
+            process_block_output_params(wrapper, &mut Vec::new(), ptr::null_mut())
+        }
+
+        unsafe {
+            let handler = TestComponentHandler::new();
+            let wrapper = Wrapper::<ModWritePlugin>::new();
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(
+                    &*handler as *const TestComponentHandler
+                )),
+                kResultOk
+            );
+            activate(&*wrapper);
+
+            let speed_hash = hash_param_id("speed");
+            let speed_param = wrapper.inner.param_by_hash[&speed_hash];
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            let setter = ParamSetter::new(gui_context.as_ref());
+            let params = wrapper.inner.plugin.lock().params.clone();
+
+            // Internal modulation is invisible to the host by default
+            for _ in 0..MOD_WRITE_OFFSETS.len() {
+                assert!(process_mod_write_block(&wrapper).is_empty());
+            }
+            assert!(handler.calls.lock().is_empty());
+
+            assert!(setter.set_modulation_write(&params.speed, true));
+            assert!(setter.set_modulation_write(&params.speed, true));
+            assert_eq!(*handler.calls.lock(), [HandlerCall::BeginEdit(speed_hash)]);
+
+            // Every block writes the value the plugin ended up with, but only when it changed
+            let mut written = Vec::new();
+            let mut expected = Vec::new();
+            let mut last_value = None;
+            for _ in 0..MOD_WRITE_OFFSETS.len() * 2 {
+                written.extend(process_mod_write_block(&wrapper));
+
+                let value = speed_param.modulated_normalized_value();
+                if last_value != Some(value) {
+                    expected.push((speed_hash, 0, value as f64));
+                    last_value = Some(value);
+                }
+            }
+            assert_eq!(written, expected);
+            assert!(written.len() < MOD_WRITE_OFFSETS.len() * 2);
+
+            assert!(!setter.set_modulation_write(&params.speed, false));
+            assert!(!setter.set_modulation_write(&params.speed, false));
+            for _ in 0..MOD_WRITE_OFFSETS.len() {
+                assert!(process_mod_write_block(&wrapper).is_empty());
+            }
+            assert_eq!(
+                *handler.calls.lock(),
+                [
+                    HandlerCall::BeginEdit(speed_hash),
+                    HandlerCall::EndEdit(speed_hash)
+                ]
+            );
+
+            // The wrapper needs to release the handler before the handler gets dropped
+            drop(gui_context);
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(ptr::null::<c_void>())),
+                kResultOk
+            );
+            deactivate(&wrapper);
+        }
+    }
+
+    /// Rendering offline twice should produce bit-identical output, also after the plugin's state
+    /// has been loaded into another instance. Realtime processing should not be repeatable.
+    #[test]
+    fn deterministic_offline_rendering() {
+        /// Activate the wrapper in `process_mode`, render a couple of blocks, and deactivate it
+        /// again.
+        unsafe fn render(wrapper: &Wrapper<NoisePlugin>, process_mode: ProcessModes) -> Vec<f32> {
+            activate_in_mode(wrapper, process_mode);
+            let mut output = Vec::new();
+            for _ in 0..4 {
+                process_block(wrapper, &mut output);
+            }
+            deactivate(wrapper);
+
+            output
+        }
+
+        unsafe {
+            let wrapper = Wrapper::<NoisePlugin>::new();
+            assert_ne!(
+                render(&wrapper, ProcessModes::kRealtime),
+                render(&wrapper, ProcessModes::kRealtime)
+            );
+
+            let offline_output = render(&wrapper, ProcessModes::kOffline);
+            assert!(offline_output.iter().any(|sample| *sample != 0.0));
+            assert_eq!(render(&wrapper, ProcessModes::kOffline), offline_output);
+
+            // A different instance gets a different seed until it loads the first instance's state
+            let restored_wrapper = Wrapper::<NoisePlugin>::new();
+            assert_ne!(
+                render(&restored_wrapper, ProcessModes::kOffline),
+                offline_output
+            );
+
+            let state = state::serialize_json::<NoisePlugin>(
+                wrapper.inner.params.clone(),
+                state::make_params_iter(
+                    &wrapper.inner.param_by_hash,
+                    &wrapper.inner.param_id_to_hash,
+                ),
+                wrapper.inner.instance_id.load(),
+            )
+            .unwrap();
+            assert!(state::deserialize_json::<NoisePlugin>(
+                &state,
+                restored_wrapper.inner.params.clone(),
+                state::make_params_getter(
+                    &restored_wrapper.inner.param_by_hash,
+                    &restored_wrapper.inner.param_id_to_hash,
+                    &restored_wrapper.inner.legacy_param_id_to_hash,
+                ),
+                None,
+                false,
+                &restored_wrapper.inner.instance_id,
+                &restored_wrapper.inner.param_value_validator,
+            ));
+            assert_eq!(
+                render(&restored_wrapper, ProcessModes::kOffline),
+                offline_output
+            );
+        }
+    }
+
+    /// Marking the plugin's state as dirty should only call `IComponentHandler2::setDirty()` for
+    /// the first change after the host last saved the plugin's state.
+    #[test]
+    fn mark_state_dirty() {
+        unsafe {
+            let handler = TestComponentHandler::new();
+            let wrapper = Wrapper::<CountingPlugin>::new();
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(
+                    &*handler as *const TestComponentHandler
+                )),
+                kResultOk
+            );
+
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            for _ in 0..3 {
+                gui_context.mark_state_dirty();
+            }
+            assert_eq!(*handler.calls.lock(), [HandlerCall::SetDirty(true)]);
+
+            assert!(!save_state(&wrapper).is_empty());
+
+            // Saving the state starts a new dirty episode
+            handler.calls.lock().clear();
+            gui_context.mark_state_dirty();
+            gui_context.mark_state_dirty();
+            assert_eq!(*handler.calls.lock(), [HandlerCall::SetDirty(true)]);
+
+            // The wrapper needs to release the handler before the handler gets dropped
+            drop(gui_context);
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(ptr::null::<c_void>())),
+                kResultOk
+            );
+        }
     }
 }
@@ -0,0 +1,554 @@
+//! Host provided context menus for parameters, created through `IComponentHandler3`. See
+//! [`HostContextMenu`] for the plugin facing side of this.
+
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::sync::Arc;
+use vst3_sys::base::{kResultOk, tresult};
+use vst3_sys::interfaces::IUnknown;
+use vst3_sys::utils::SharedVstPtr;
+use vst3_sys::vst::{IContextMenu, IContextMenuItem, IContextMenuTarget};
+use vst3_sys::{ComInterface, VST3};
+
+use super::util::{u16strlcpy, ObjectPtr, VstPtr};
+use crate::context::gui::{
+    ContextMenuBackend, ContextMenuItem, ContextMenuItemKind, HostContextMenu,
+};
+
+// Alias needed for the VST3 attribute macro
+use vst3_sys as vst3_com;
+
+// Context menu item flags missing from vst3-sys
+const VST3_MENU_ITEM_IS_SEPARATOR: i32 = 1 << 0;
+const VST3_MENU_ITEM_IS_DISABLED: i32 = 1 << 1;
+const VST3_MENU_ITEM_IS_CHECKED: i32 = 1 << 2;
+const VST3_MENU_ITEM_IS_GROUP_START: i32 = (1 << 3) | VST3_MENU_ITEM_IS_DISABLED;
+const VST3_MENU_ITEM_IS_GROUP_END: i32 = (1 << 4) | VST3_MENU_ITEM_IS_SEPARATOR;
+
+/// The tag used for the items the plugin adds to the host's menu. Every item gets its own target,
+/// so the tags don't need to be unique.
+const PLUGIN_ITEM_TAG: i32 = 0;
+
+/// The [`ContextMenuBackend`] for the VST3 wrapper. This owns a reference to the host's
+/// `IContextMenu` and to the targets of all of the menu's items. Those references are released
+/// when this object is dropped.
+//
+// NOTE: The fields are dropped in declaration order. The menu needs to be released first, since the
+//       host may still use the targets until the menu has been destroyed.
+struct Vst3ContextMenu {
+    menu: VstPtr<dyn IContextMenu>,
+    /// The tag and the target for each of the host's own items, with the same indices as the items
+    /// passed to [`HostContextMenu::new()`]. Separators and submenu markers don't have a target.
+    host_targets: Vec<(i32, Option<VstPtr<dyn IContextMenuTarget>>)>,
+    /// The targets for the items added by the plugin. The host holds its own references to these,
+    /// so they may outlive this object.
+    plugin_targets: Vec<ObjectPtr<ContextMenuTarget>>,
+    /// The editor's DPI scaling factor. The host expects popup positions in physical pixels.
+    scaling_factor: f32,
+}
+
+/// The `IContextMenuTarget` for an item the plugin added to the host's menu.
+#[VST3(implements(IContextMenuTarget))]
+struct ContextMenuTarget {
+    callback: Arc<dyn Fn() + Send + Sync>,
+}
+
+/// Wrap the menu returned by `IComponentHandler3::create_context_menu()`. The host has already
+/// incremented the menu's reference count for us, and that reference is released when the
+/// returned object is dropped. Returns `None` if `menu` is a null pointer, which happens when the
+/// host could not create a menu for the parameter.
+///
+/// # Safety
+///
+/// `menu` needs to be either a null pointer or a pointer to an `IContextMenu` object we own a
+/// reference to.
+pub unsafe fn wrap_host_context_menu(
+    menu: *mut c_void,
+    scaling_factor: f32,
+) -> Option<HostContextMenu> {
+    let menu: VstPtr<dyn IContextMenu> = VstPtr::from(vst3_sys::VstPtr::new(NonNull::new(
+        menu as *mut *mut <dyn IContextMenu as ComInterface>::VTable,
+    )?));
+
+    let num_items = menu.get_item_count().max(0);
+    let mut items = Vec::with_capacity(num_items as usize);
+    let mut host_targets = Vec::with_capacity(num_items as usize);
+    for index in 0..num_items {
+        let mut item: IContextMenuItem = mem::zeroed();
+        let mut target: *mut c_void = ptr::null_mut();
+        if menu.get_item(index, &mut item, &mut target) != kResultOk {
+            nih_debug_assert_failure!("Could not query the host's context menu item {}", index);
+            continue;
+        }
+
+        // The host keeps its own reference to the target, so we need to take our own reference
+        // for as long as we hold on to it
+        let target = NonNull::new(
+            target as *mut *mut <dyn IContextMenuTarget as ComInterface>::VTable,
+        )
+        .map(|target| {
+            let target = vst3_sys::VstPtr::<dyn IContextMenuTarget>::new(target);
+            target.add_ref();
+
+            VstPtr::from(target)
+        });
+
+        items.push(convert_item(&item));
+        host_targets.push((item.tag, target));
+    }
+
+    Some(HostContextMenu::new(
+        items,
+        Box::new(Vst3ContextMenu {
+            menu,
+            host_targets,
+            plugin_targets: Vec::new(),
+            scaling_factor,
+        }),
+    ))
+}
+
+/// Convert one of the host's menu items to NIH-plug's representation.
+fn convert_item(item: &IContextMenuItem) -> ContextMenuItem {
+    let flags = item.flags;
+    let kind = if flags & VST3_MENU_ITEM_IS_GROUP_START == VST3_MENU_ITEM_IS_GROUP_START {
+        ContextMenuItemKind::SubmenuStart
+    } else if flags & VST3_MENU_ITEM_IS_GROUP_END == VST3_MENU_ITEM_IS_GROUP_END {
+        ContextMenuItemKind::SubmenuEnd
+    } else if flags & VST3_MENU_ITEM_IS_SEPARATOR != 0 {
+        ContextMenuItemKind::Separator
+    } else {
+        ContextMenuItemKind::Action
+    };
+
+    // The name does not need to be null terminated if it fills the entire buffer
+    let name: Vec<u16> = item
+        .name
+        .iter()
+        .map(|&c| c as u16)
+        .take_while(|&c| c != 0)
+        .collect();
+
+    ContextMenuItem {
+        name: String::from_utf16_lossy(&name),
+        kind,
+        // The submenu flag includes the disabled flag
+        enabled: kind != ContextMenuItemKind::Action || flags & VST3_MENU_ITEM_IS_DISABLED == 0,
+        checked: flags & VST3_MENU_ITEM_IS_CHECKED != 0,
+    }
+}
+
+impl ContextMenuBackend for Vst3ContextMenu {
+    fn execute(&self, index: usize) {
+        match self.host_targets.get(index) {
+            Some((tag, Some(target))) => unsafe {
+                target.execute_menu_item(*tag);
+            },
+            Some((_, None)) => (),
+            None => nih_debug_assert_failure!("Unknown context menu item {}", index),
+        }
+    }
+
+    fn add_item(&mut self, item: &ContextMenuItem, callback: Arc<dyn Fn() + Send + Sync>) {
+        let target = ObjectPtr::from_box(ContextMenuTarget::allocate(callback));
+
+        let mut vst3_item: IContextMenuItem = unsafe { mem::zeroed() };
+        u16strlcpy(&mut vst3_item.name, &item.name);
+        vst3_item.tag = PLUGIN_ITEM_TAG;
+        vst3_item.flags = if item.enabled {
+            0
+        } else {
+            VST3_MENU_ITEM_IS_DISABLED
+        };
+
+        // The host takes its own reference to the target
+        let result = unsafe {
+            let target_ptr: SharedVstPtr<dyn IContextMenuTarget> =
+                mem::transmute(&*target as *const ContextMenuTarget);
+            self.menu.add_item(&vst3_item, target_ptr)
+        };
+        nih_debug_assert_eq!(result, kResultOk);
+
+        self.plugin_targets.push(target);
+    }
+
+    fn popup(&self, x: f32, y: f32) -> bool {
+        let x = (x * self.scaling_factor).round() as i32;
+        let y = (y * self.scaling_factor).round() as i32;
+
+        unsafe { self.menu.popup(x, y) == kResultOk }
+    }
+}
+
+impl IContextMenuTarget for ContextMenuTarget {
+    unsafe fn execute_menu_item(&self, _tag: i32) -> tresult {
+        (self.callback)();
+
+        kResultOk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use std::any::Any;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use vst3_sys::base::{kInvalidArgument, kResultFalse};
+    use vst3_sys::gui::IPlugView;
+    use vst3_sys::vst::{IComponentHandler, IComponentHandler3, IEditController};
+    use widestring::U16CStr;
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::gui::GuiContext;
+    use crate::context::process::ProcessContext;
+    use crate::editor::{Editor, ParentWindowHandle};
+    use crate::params::internals::ParamPtr;
+    use crate::params::range::FloatRange;
+    use crate::params::{FloatParam, Param, Params};
+    use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+    use crate::wrapper::util::hash_param_id;
+    use crate::wrapper::vst3::view::WrapperView;
+    use crate::wrapper::vst3::wrapper::Wrapper;
+    use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+    /// Has a single parameter the host can create a context menu for.
+    #[derive(Default)]
+    struct MenuPlugin {
+        params: Arc<MenuParams>,
+    }
+
+    struct MenuParams {
+        param: FloatParam,
+    }
+
+    impl Default for MenuParams {
+        fn default() -> Self {
+            Self {
+                param: FloatParam::new("Param", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            }
+        }
+    }
+
+    unsafe impl Params for MenuParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("param"), self.param.as_ptr(), String::new())]
+        }
+    }
+
+    impl Plugin for MenuPlugin {
+        const NAME: &'static str = "Context Menu Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for MenuPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugCtxMenu00";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// An editor that doesn't open any windows. The host only creates context menus for plugins
+    /// with a [`WrapperView`].
+    struct TestEditor;
+
+    impl Editor for TestEditor {
+        fn spawn(
+            &self,
+            _parent: ParentWindowHandle,
+            _context: Arc<dyn GuiContext>,
+        ) -> Box<dyn Any + Send> {
+            Box::new(())
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (640, 480)
+        }
+
+        fn set_scale_factor(&self, _factor: f32) -> bool {
+            false
+        }
+
+        fn param_values_changed(&self) {}
+    }
+
+    /// A component handler that hands out a [`TestContextMenu`] through `IComponentHandler3`.
+    #[VST3(implements(IComponentHandler, IComponentHandler3))]
+    struct TestContextMenuHandler {
+        menu: Box<TestContextMenu>,
+        /// The parameters the plugin requested a context menu for.
+        requested_params: Mutex<Vec<u32>>,
+    }
+
+    /// A host context menu with a fixed set of items, see [`TEST_CONTEXT_MENU_ITEMS`].
+    #[VST3(implements(IContextMenu))]
+    struct TestContextMenu {
+        /// The targets for the menu's own items.
+        targets: Vec<Box<TestContextMenuTarget>>,
+        /// The names of the items added by the plugin, and the menu's references to their targets.
+        added_items: Mutex<Vec<(String, VstPtr<dyn IContextMenuTarget>)>>,
+        popups: Mutex<Vec<(i32, i32)>>,
+    }
+
+    /// A context menu target that records the tags of the items it executed.
+    #[VST3(implements(IContextMenuTarget))]
+    struct TestContextMenuTarget {
+        executed: Mutex<Vec<i32>>,
+    }
+
+    /// The name, tag, flags, and the index of the target in [`TestContextMenu::targets`] for every
+    /// item in a [`TestContextMenu`]. The second target is shared by multiple items.
+    const TEST_CONTEXT_MENU_ITEMS: [(&str, i32, i32, Option<usize>); 6] = [
+        ("MIDI Learn", 10, 0, Some(0)),
+        ("", 0, VST3_MENU_ITEM_IS_SEPARATOR, None),
+        ("Automation", 0, VST3_MENU_ITEM_IS_GROUP_START, None),
+        ("Show Automation", 20, VST3_MENU_ITEM_IS_CHECKED, Some(1)),
+        ("", 0, VST3_MENU_ITEM_IS_GROUP_END, None),
+        ("Unavailable", 30, VST3_MENU_ITEM_IS_DISABLED, Some(1)),
+    ];
+
+    impl TestContextMenuHandler {
+        fn new() -> Box<Self> {
+            let targets = (0..2)
+                .map(|_| TestContextMenuTarget::allocate(Mutex::new(Vec::new())))
+                .collect();
+
+            Self::allocate(
+                TestContextMenu::allocate(targets, Mutex::new(Vec::new()), Mutex::new(Vec::new())),
+                Mutex::new(Vec::new()),
+            )
+        }
+    }
+
+    impl IComponentHandler for TestContextMenuHandler {
+        unsafe fn begin_edit(&self, _id: u32) -> tresult {
+            kResultOk
+        }
+
+        unsafe fn perform_edit(&self, _id: u32, _value_normalized: f64) -> tresult {
+            kResultOk
+        }
+
+        unsafe fn end_edit(&self, _id: u32) -> tresult {
+            kResultOk
+        }
+
+        unsafe fn restart_component(&self, _flags: i32) -> tresult {
+            kResultOk
+        }
+    }
+
+    impl IComponentHandler3 for TestContextMenuHandler {
+        unsafe fn create_context_menu(
+            &self,
+            _plug_view: SharedVstPtr<dyn IPlugView>,
+            param_id: *const u32,
+        ) -> *mut c_void {
+            self.requested_params.lock().push(*param_id);
+
+            // The caller owns the returned reference
+            self.menu.add_ref();
+            &*self.menu as *const TestContextMenu as *mut c_void
+        }
+    }
+
+    impl IContextMenu for TestContextMenu {
+        unsafe fn get_item_count(&self) -> i32 {
+            TEST_CONTEXT_MENU_ITEMS.len() as i32
+        }
+
+        unsafe fn get_item(
+            &self,
+            index: i32,
+            item: *mut IContextMenuItem,
+            target: *mut *mut c_void,
+        ) -> tresult {
+            let (name, tag, flags, target_idx) = match TEST_CONTEXT_MENU_ITEMS.get(index as usize) {
+                Some(test_item) => *test_item,
+                None => return kInvalidArgument,
+            };
+
+            u16strlcpy(&mut (*item).name, name);
+            (*item).tag = tag;
+            (*item).flags = flags;
+            // Like in the SDK, the menu keeps its own reference to the target
+            *target = match target_idx {
+                Some(target_idx) => {
+                    &*self.targets[target_idx] as *const TestContextMenuTarget as *mut c_void
+                }
+                None => ptr::null_mut(),
+            };
+
+            kResultOk
+        }
+
+        unsafe fn add_item(
+            &self,
+            item: *const IContextMenuItem,
+            target: SharedVstPtr<dyn IContextMenuTarget>,
+        ) -> tresult {
+            let name = U16CStr::from_ptr_str((*item).name.as_ptr() as *const u16)
+                .to_string()
+                .unwrap();
+            match target.upgrade() {
+                Some(target) => {
+                    self.added_items.lock().push((name, VstPtr::from(target)));
+                    kResultOk
+                }
+                None => kInvalidArgument,
+            }
+        }
+
+        unsafe fn remove_item(
+            &self,
+            _item: *const IContextMenuItem,
+            _target: SharedVstPtr<dyn IContextMenuTarget>,
+        ) -> tresult {
+            kResultFalse
+        }
+
+        unsafe fn popup(&self, x: i32, y: i32) -> tresult {
+            self.popups.lock().push((x, y));
+            kResultOk
+        }
+    }
+
+    impl IContextMenuTarget for TestContextMenuTarget {
+        unsafe fn execute_menu_item(&self, tag: i32) -> tresult {
+            self.executed.lock().push(tag);
+            kResultOk
+        }
+    }
+
+    /// The current reference count of a COM object.
+    unsafe fn ref_count<T: IUnknown>(object: &T) -> u32 {
+        object.add_ref();
+        object.release()
+    }
+
+    /// The host's context menus should be usable through the GUI context, and every reference the
+    /// wrapper takes to the menu and to its items' targets needs to be released again.
+    #[test]
+    fn host_context_menu() {
+        unsafe {
+            let handler = TestContextMenuHandler::new();
+            let wrapper = Wrapper::<MenuPlugin>::new();
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(
+                    &*handler as *const TestContextMenuHandler
+                )),
+                kResultOk
+            );
+
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            let param_hash = hash_param_id("param");
+            let param_ptr = wrapper.inner.param_by_hash[&param_hash];
+
+            // The host needs the editor's view to create the menu
+            assert!(gui_context.param_context_menu(param_ptr).is_none());
+            assert!(handler.requested_params.lock().is_empty());
+
+            let view = WrapperView::new(
+                wrapper.inner.clone(),
+                Arc::new(Mutex::new(Box::new(TestEditor) as Box<dyn Editor>)),
+            );
+            *wrapper.inner.plug_view.write() = Some(ObjectPtr::from(&*view));
+
+            let menu_refs = ref_count(&*handler.menu);
+            let target_refs: Vec<u32> = handler
+                .menu
+                .targets
+                .iter()
+                .map(|target| ref_count(&**target))
+                .collect();
+
+            let mut menu = gui_context.param_context_menu(param_ptr).unwrap();
+            assert_eq!(*handler.requested_params.lock(), [param_hash]);
+            assert_eq!(ref_count(&*handler.menu), menu_refs + 1);
+            assert_eq!(ref_count(&*handler.menu.targets[0]), target_refs[0] + 1);
+            assert_eq!(ref_count(&*handler.menu.targets[1]), target_refs[1] + 2);
+
+            let items: Vec<_> = menu
+                .items()
+                .iter()
+                .map(|item| (item.name.as_str(), item.kind, item.enabled, item.checked))
+                .collect();
+            assert_eq!(
+                items,
+                [
+                    ("MIDI Learn", ContextMenuItemKind::Action, true, false),
+                    ("", ContextMenuItemKind::Separator, true, false),
+                    ("Automation", ContextMenuItemKind::SubmenuStart, true, false),
+                    ("Show Automation", ContextMenuItemKind::Action, true, true),
+                    ("", ContextMenuItemKind::SubmenuEnd, true, false),
+                    ("Unavailable", ContextMenuItemKind::Action, false, false),
+                ]
+            );
+
+            for index in 0..menu.items().len() {
+                menu.execute(index);
+            }
+            assert_eq!(*handler.menu.targets[0].executed.lock(), [10]);
+            assert_eq!(*handler.menu.targets[1].executed.lock(), [20]);
+
+            // The plugin's own items can be run both from the plugin's and from the host's menu
+            let num_plugin_calls = Arc::new(AtomicUsize::new(0));
+            menu.add_item("Reset", {
+                let num_plugin_calls = num_plugin_calls.clone();
+                move || {
+                    num_plugin_calls.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+            menu.execute(6);
+            {
+                let added_items = handler.menu.added_items.lock();
+                assert_eq!(added_items.len(), 1);
+                assert_eq!(added_items[0].0, "Reset");
+                added_items[0].1.execute_menu_item(PLUGIN_ITEM_TAG);
+            }
+            assert_eq!(num_plugin_calls.load(Ordering::SeqCst), 2);
+
+            assert!(menu.popup(10.0, 20.0));
+            assert_eq!(*handler.menu.popups.lock(), [(10, 20)]);
+
+            drop(menu);
+            assert_eq!(ref_count(&*handler.menu), menu_refs);
+            assert_eq!(ref_count(&*handler.menu.targets[0]), target_refs[0]);
+            assert_eq!(ref_count(&*handler.menu.targets[1]), target_refs[1]);
+
+            // The host's menu still holds a reference to the plugin's target, and the target
+            // should be freed once the host releases it
+            assert_eq!(Arc::strong_count(&num_plugin_calls), 2);
+            handler.menu.added_items.lock().clear();
+            assert_eq!(Arc::strong_count(&num_plugin_calls), 1);
+
+            // The wrapper needs to release the view and the handler before they get dropped
+            *wrapper.inner.plug_view.write() = None;
+            drop(gui_context);
+            assert_eq!(
+                wrapper.set_component_handler(mem::transmute(ptr::null::<c_void>())),
+                kResultOk
+            );
+        }
+    }
+}
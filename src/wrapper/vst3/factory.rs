@@ -1,7 +1,7 @@
 use std::ffi::c_void;
-use std::marker::PhantomData;
 use std::mem;
-use vst3_sys::base::{kInvalidArgument, kNoInterface, kResultOk, tresult};
+use std::panic;
+use vst3_sys::base::{kInvalidArgument, kNoInterface, kResultFalse, kResultOk, tresult};
 use vst3_sys::base::{IPluginFactory, IPluginFactory2, IPluginFactory3};
 use vst3_sys::VST3;
 
@@ -12,32 +12,222 @@ use super::util::u16strlcpy;
 use super::wrapper::Wrapper;
 use crate::plugin::Vst3Plugin;
 use crate::wrapper::util::strlcpy;
+use crate::wrapper::vst3_subcategories::{
+    compose_subcategories, is_known_subcategory, known_subcategories_list, Vst3SubCategory,
+};
 
 /// The VST3 SDK version this is roughly based on. The bindings include some VST 3.7 things but not
 /// everything, so we'll play it safe.
 const VST3_SDK_VERSION: &str = "VST 3.6.14";
 
+// Class flags missing from vst3-sys
+const K_DISTRIBUTABLE: u32 = 1 << 0;
+const K_SIMPLE_MODE_SUPPORTED: u32 = 1 << 1;
+
+/// The information needed to enumerate and instantiate a single plugin class from a [`Factory`].
+/// This erases the plugin's type so a single factory can expose multiple plugins. Use
+/// [`PluginClass::new()`] to create one for a plugin.
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct PluginClass {
+    cid: [u8; 16],
+    name: &'static str,
+    vendor: &'static str,
+    url: &'static str,
+    email: &'static str,
+    version: &'static str,
+    subcategories: &'static [Vst3SubCategory],
+    /// The deprecated subcategory string. `subcategories` defaults to this string as a single
+    /// custom subcategory.
+    categories: &'static str,
+    distributable: bool,
+    hard_realtime_only: bool,
+    /// Creates a new `Wrapper<P>` and returns it as a raw pointer.
+    create_instance: fn() -> *mut c_void,
+}
+
+impl PluginClass {
+    #[allow(deprecated)]
+    pub const fn new<P: Vst3Plugin>() -> Self {
+        Self {
+            cid: P::PLATFORM_VST3_CLASS_ID,
+            name: P::NAME,
+            vendor: P::VENDOR,
+            url: P::URL,
+            email: P::EMAIL,
+            version: P::VERSION,
+            subcategories: P::VST3_SUBCATEGORIES,
+            categories: P::VST3_CATEGORIES,
+            distributable: P::VST3_DISTRIBUTABLE,
+            hard_realtime_only: P::HARD_REALTIME_ONLY,
+            create_instance: create_wrapper::<P>,
+        }
+    }
+
+    /// Whether the plugin didn't set `VST3_SUBCATEGORIES`, in which case it defaults to the
+    /// deprecated `VST3_CATEGORIES` string.
+    fn uses_categories_string(&self) -> bool {
+        match self.subcategories {
+            [Vst3SubCategory::Custom(categories)] => *categories == self.categories,
+            _ => false,
+        }
+    }
+
+    /// The plugin's subcategories, either from `VST3_SUBCATEGORIES` or from the deprecated
+    /// `VST3_CATEGORIES` string.
+    fn subcategory_tokens(&self) -> Vec<&'static str> {
+        if self.uses_categories_string() {
+            self.categories
+                .split('|')
+                .filter(|token| !token.is_empty())
+                .collect()
+        } else {
+            self.subcategories
+                .iter()
+                .map(Vst3SubCategory::as_str)
+                .collect()
+        }
+    }
+}
+
+/// The parts of a plugin class's information that are computed when the factory is created.
+struct ClassInfo {
+    /// The pipe separated subcategory string, including `OnlyRT` for hard realtime only plugins.
+    subcategories: String,
+    class_flags: u32,
+}
+
+impl ClassInfo {
+    /// Compose the class's subcategory string and determine its flags. The subcategories are
+    /// checked for typos and for fitting in the VST3 struct's field, and failures are logged as
+    /// debug assertion failures.
+    fn new(class: &PluginClass) -> Self {
+        let tokens = class.subcategory_tokens();
+        if class.uses_categories_string() {
+            nih_debug_assert!(
+                !class.categories.is_empty(),
+                "'{}' does not set any VST3 subcategories",
+                class.name
+            );
+            nih_debug_assert!(
+                class.categories.is_empty() || tokens.len() == class.categories.split('|').count(),
+                "'{}' has an empty VST3 subcategory in '{}'",
+                class.name,
+                class.categories
+            );
+            for token in tokens.iter().filter(|token| !is_known_subcategory(token)) {
+                nih_debug_assert_failure!(
+                    "'{}' has an unknown VST3 subcategory '{}'. Valid subcategories are: {}",
+                    class.name,
+                    token,
+                    known_subcategories_list()
+                );
+            }
+        } else {
+            for token in &tokens {
+                nih_debug_assert!(
+                    !token.is_empty() && !token.contains('|'),
+                    "'{}' has an invalid custom VST3 subcategory '{}'",
+                    class.name,
+                    token
+                );
+            }
+        }
+
+        let (subcategories, truncated) = compose_subcategories(&tokens, class.hard_realtime_only);
+        nih_debug_assert!(
+            !truncated,
+            "The VST3 subcategories for '{}' are too long, they have been truncated to '{}'",
+            class.name,
+            subcategories
+        );
+
+        // Instruments don't have a main input, so they cannot be used in VST3's simple IO mode
+        let is_instrument = tokens.first() == Some(&Vst3SubCategory::Instrument.as_str());
+        let mut class_flags = 0;
+        if !is_instrument {
+            class_flags |= K_SIMPLE_MODE_SUPPORTED;
+        }
+        if class.distributable {
+            class_flags |= K_DISTRIBUTABLE;
+        }
+
+        Self {
+            subcategories,
+            class_flags,
+        }
+    }
+}
+
+/// Panics if two of the plugin classes share the same class ID. This is a `const fn` so
+/// [`nih_export_vst3!()`][crate::nih_export_vst3!()] can check this at compile time.
+#[doc(hidden)]
+pub const fn assert_unique_class_ids(classes: &[PluginClass]) {
+    let mut i = 0;
+    while i < classes.len() {
+        let mut j = i + 1;
+        while j < classes.len() {
+            let (a, b) = (&classes[i].cid, &classes[j].cid);
+            let mut k = 0;
+            while k < a.len() && a[k] == b[k] {
+                k += 1;
+            }
+
+            if k == a.len() {
+                panic!("Two of the exported VST3 plugins have the same VST3_CLASS_ID");
+            }
+
+            j += 1;
+        }
+
+        i += 1;
+    }
+}
+
+fn create_wrapper<P: Vst3Plugin>() -> *mut c_void {
+    Box::into_raw(Wrapper::<P>::new()) as *mut c_void
+}
+
 #[doc(hidden)]
 #[VST3(implements(IPluginFactory, IPluginFactory2, IPluginFactory3))]
-pub struct Factory<P: Vst3Plugin> {
-    /// The type will be used for constructing plugin instances later.
-    _phantom: PhantomData<P>,
+pub struct Factory {
+    /// The plugins exposed by this factory, in the order they were passed to
+    /// [`nih_export_vst3!()`][crate::nih_export_vst3!()].
+    classes: &'static [PluginClass],
+    /// The computed information for each class in `classes`.
+    class_infos: Vec<ClassInfo>,
 }
 
-impl<P: Vst3Plugin> Factory<P> {
-    pub fn new() -> Box<Self> {
-        Self::allocate(PhantomData::default())
+impl Factory {
+    pub fn new(classes: &'static [PluginClass]) -> Box<Self> {
+        Self::allocate(classes, classes.iter().map(ClassInfo::new).collect())
+    }
+
+    /// Get the plugin class at `index` and its computed information, if it exists.
+    fn class(&self, index: i32) -> Option<(&PluginClass, &ClassInfo)> {
+        let index = usize::try_from(index).ok()?;
+
+        Some((self.classes.get(index)?, self.class_infos.get(index)?))
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
+impl IPluginFactory for Factory {
     unsafe fn get_factory_info(&self, info: *mut vst3_sys::base::PFactoryInfo) -> tresult {
         *info = mem::zeroed();
 
+        // The factory information is shared between all plugins, so we'll use the first plugin's
+        // vendor information
         let info = &mut *info;
-        strlcpy(&mut info.vendor, P::VENDOR);
-        strlcpy(&mut info.url, P::URL);
-        strlcpy(&mut info.email, P::EMAIL);
+        if let Some(class) = self.classes.first() {
+            // These are all UTF-8, and the truncation is logged as a debug assertion failure since
+            // the values are compile time constants the plugin author can shorten
+            let truncated = strlcpy(&mut info.vendor, class.vendor);
+            nih_debug_assert!(!truncated, "The plugin's vendor name is too long");
+            let truncated = strlcpy(&mut info.url, class.url);
+            nih_debug_assert!(!truncated, "The plugin's URL is too long");
+            let truncated = strlcpy(&mut info.email, class.email);
+            nih_debug_assert!(!truncated, "The plugin's email address is too long");
+        }
         info.flags = vst3_sys::base::FactoryFlags::kUnicode as i32;
 
         kResultOk
@@ -46,22 +236,25 @@ impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
     unsafe fn count_classes(&self) -> i32 {
         // We don't do shell plugins, and good of an idea having separated components and edit
         // controllers in theory is, few software can use it, and doing that would make our simple
-        // microframework a lot less simple
-        1
+        // microframework a lot less simple. So every plugin is exactly one class.
+        self.classes.len() as i32
     }
 
     unsafe fn get_class_info(&self, index: i32, info: *mut vst3_sys::base::PClassInfo) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+        let (class, _) = match self.class(index) {
+            Some(class) => class,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::PLATFORM_VST3_CLASS_ID;
+        info.cid.data = class.cid;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        strlcpy(&mut info.name, P::NAME);
+        let truncated = strlcpy(&mut info.name, class.name);
+        nih_debug_assert!(!truncated, "The plugin's name is too long");
 
         kResultOk
     }
@@ -74,77 +267,79 @@ impl<P: Vst3Plugin> IPluginFactory for Factory<P> {
     ) -> tresult {
         check_null_ptr!(cid, obj);
 
-        if (*cid).data != P::PLATFORM_VST3_CLASS_ID {
-            return kNoInterface;
-        }
+        match self.classes.iter().find(|class| class.cid == (*cid).data) {
+            // Creating the wrapper creates the plugin, its parameters, and its editor. There's no
+            // instance to poison yet if that panics, so the host just gets an error instead.
+            Some(class) => match panic::catch_unwind(class.create_instance) {
+                Ok(instance) => {
+                    *obj = instance;
 
-        *obj = Box::into_raw(Wrapper::<P>::new()) as *mut vst3_sys::c_void;
+                    kResultOk
+                }
+                Err(_) => {
+                    nih_error!("The plugin '{}' panicked while being created", class.name);
+                    *obj = std::ptr::null_mut();
 
-        kResultOk
+                    kResultFalse
+                }
+            },
+            None => kNoInterface,
+        }
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory2 for Factory<P> {
+impl IPluginFactory2 for Factory {
     unsafe fn get_class_info2(
         &self,
         index: i32,
         info: *mut vst3_sys::base::PClassInfo2,
     ) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+        let (class, class_info) = match self.class(index) {
+            Some(class) => class,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::PLATFORM_VST3_CLASS_ID;
+        info.cid.data = class.cid;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        strlcpy(&mut info.name, P::NAME);
-        info.class_flags = 1 << 1; // kSimpleModeSupported
-
-        // No idea if any hosts do something with this, but it's part of VST3's example categories
-        // list
-        if P::HARD_REALTIME_ONLY {
-            nih_debug_assert!(!P::VST3_CATEGORIES.ends_with('|'));
-            nih_debug_assert!(!P::VST3_CATEGORIES.contains("OnlyRT"));
-            strlcpy(
-                &mut info.subcategories,
-                &format!("{}|OnlyRT", P::VST3_CATEGORIES),
-            );
-        } else {
-            strlcpy(&mut info.subcategories, P::VST3_CATEGORIES);
-        };
-
-        strlcpy(&mut info.vendor, P::VENDOR);
-        strlcpy(&mut info.version, P::VERSION);
+        strlcpy(&mut info.name, class.name);
+        info.class_flags = class_info.class_flags;
+        strlcpy(&mut info.subcategories, &class_info.subcategories);
+        strlcpy(&mut info.vendor, class.vendor);
+        strlcpy(&mut info.version, class.version);
         strlcpy(&mut info.sdk_version, VST3_SDK_VERSION);
 
         kResultOk
     }
 }
 
-impl<P: Vst3Plugin> IPluginFactory3 for Factory<P> {
+impl IPluginFactory3 for Factory {
     unsafe fn get_class_info_unicode(
         &self,
         index: i32,
         info: *mut vst3_sys::base::PClassInfoW,
     ) -> tresult {
-        if index != 0 {
-            return kInvalidArgument;
-        }
+        check_null_ptr!(info);
+        let (class, class_info) = match self.class(index) {
+            Some(class) => class,
+            None => return kInvalidArgument,
+        };
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        info.cid.data = P::PLATFORM_VST3_CLASS_ID;
+        info.cid.data = class.cid;
         info.cardinality = vst3_sys::base::ClassCardinality::kManyInstances as i32;
         strlcpy(&mut info.category, "Audio Module Class");
-        u16strlcpy(&mut info.name, P::NAME);
-        info.class_flags = 1 << 1; // kSimpleModeSupported
-        strlcpy(&mut info.subcategories, P::VST3_CATEGORIES);
-        u16strlcpy(&mut info.vendor, P::VENDOR);
-        u16strlcpy(&mut info.version, P::VERSION);
+        u16strlcpy(&mut info.name, class.name);
+        info.class_flags = class_info.class_flags;
+        strlcpy(&mut info.subcategories, &class_info.subcategories);
+        u16strlcpy(&mut info.vendor, class.vendor);
+        u16strlcpy(&mut info.version, class.version);
         u16strlcpy(&mut info.sdk_version, VST3_SDK_VERSION);
 
         kResultOk
@@ -155,3 +350,522 @@ impl<P: Vst3Plugin> IPluginFactory3 for Factory<P> {
         kResultOk
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use vst3_sys::interfaces::IUnknown;
+    use vst3_sys::vst::{IAudioProcessor, IComponent, IEditController};
+    use vst3_sys::ComInterface;
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::process::ProcessContext;
+    use crate::params::internals::ParamPtr;
+    use crate::params::Params;
+    use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus};
+
+    #[derive(Default)]
+    struct EmptyParams;
+
+    unsafe impl Params for EmptyParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            Vec::new()
+        }
+    }
+
+    /// Defines a trivial plugin that does nothing. The last argument contains the plugin's
+    /// `Vst3Plugin` constants other than the class ID.
+    macro_rules! trivial_plugin {
+        ($name:ident, $display_name:expr, $class_id:expr, { $($vst3_consts:tt)* }) => {
+            #[derive(Default)]
+            struct $name;
+
+            impl Plugin for $name {
+                const NAME: &'static str = $display_name;
+                const VENDOR: &'static str = "NIH-plug";
+                const URL: &'static str = "";
+                const EMAIL: &'static str = "";
+                const VERSION: &'static str = "0.0.0";
+
+                type BackgroundTask = ();
+
+                fn params(&self) -> Arc<dyn Params> {
+                    Arc::new(EmptyParams)
+                }
+
+                fn process(
+                    &mut self,
+                    _buffer: &mut Buffer,
+                    _aux: &mut AuxiliaryBuffers,
+                    _context: &mut impl ProcessContext<Self>,
+                ) -> ProcessStatus {
+                    ProcessStatus::Normal
+                }
+            }
+
+            impl Vst3Plugin for $name {
+                const VST3_CLASS_ID: [u8; 16] = $class_id;
+                $($vst3_consts)*
+            }
+        };
+    }
+
+    trivial_plugin!(PluginA, "Plugin A", *b"NihPlugFactoryTA", {
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    });
+    trivial_plugin!(PluginB, "Plugin B", *b"NihPlugFactoryTB", {
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    });
+    trivial_plugin!(SynthPlugin, "Synth Plugin", *b"NihPlugFactoryTE", {
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[
+            Vst3SubCategory::Instrument,
+            Vst3SubCategory::Synth,
+            Vst3SubCategory::Custom("Wavetable"),
+        ];
+        const VST3_DISTRIBUTABLE: bool = true;
+    });
+    trivial_plugin!(LegacyPlugin, "Legacy Plugin", *b"NihPlugFactoryTF", {
+        const VST3_CATEGORIES: &'static str = "Fx|Pitch Shift";
+    });
+
+    const CLASSES: &[PluginClass] = &[PluginClass::new::<PluginA>(), PluginClass::new::<PluginB>()];
+    const _: () = assert_unique_class_ids(CLASSES);
+
+    /// The number of [`CountedPlugin`] instances that are currently alive.
+    static LIVE_COUNTED_PLUGINS: AtomicUsize = AtomicUsize::new(0);
+
+    /// A trivial plugin that keeps track of how many instances of it are alive, so the tests can
+    /// check that releasing the last reference to the wrapper also drops the plugin.
+    struct CountedPlugin;
+
+    impl Default for CountedPlugin {
+        fn default() -> Self {
+            LIVE_COUNTED_PLUGINS.fetch_add(1, Ordering::SeqCst);
+            Self
+        }
+    }
+
+    impl Drop for CountedPlugin {
+        fn drop(&mut self) {
+            LIVE_COUNTED_PLUGINS.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Plugin for CountedPlugin {
+        const NAME: &'static str = "Counted Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(EmptyParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for CountedPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugFactoryTD";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// Allocation tracking for the leak test. This is only enabled when NIH-plug doesn't install
+    /// its own allocation checking or counting allocator.
+    #[cfg(not(all(
+        debug_assertions,
+        any(
+            feature = "assert_process_allocs",
+            feature = "alloc_stats",
+            feature = "audio_thread_alloc_guard"
+        )
+    )))]
+    mod tracking {
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::cell::Cell;
+        use std::mem;
+        use std::sync::atomic::{AtomicIsize, Ordering};
+
+        #[global_allocator]
+        static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+        thread_local! {
+            /// Whether allocations made on this thread should be counted by [`TrackingAllocator`].
+            static TRACK_ALLOCATIONS: Cell<bool> = const { Cell::new(false) };
+        }
+
+        /// The number of bytes allocated by tracked allocations that have not yet been freed.
+        pub static TRACKED_BYTES: AtomicIsize = AtomicIsize::new(0);
+
+        /// An allocator that keeps count of the bytes allocated on a thread while
+        /// [`TRACK_ALLOCATIONS`] is set. Every allocation gets a small header stating whether it was
+        /// tracked, so memory that is freed on another thread (like the event loop's worker thread) is
+        /// still accounted for correctly and allocations made by other tests running in parallel are
+        /// ignored.
+        struct TrackingAllocator;
+
+        impl TrackingAllocator {
+            /// The layout of the allocation including the header, and the offset to the part of the
+            /// allocation handed out to the caller. The header is stored in the `usize` right before
+            /// that offset.
+            fn outer_layout(layout: Layout) -> (Layout, usize) {
+                let offset = layout.align().max(mem::size_of::<usize>());
+                let outer = Layout::from_size_align(
+                    layout.size() + offset,
+                    layout.align().max(mem::align_of::<usize>()),
+                )
+                .expect("Invalid layout");
+
+                (outer, offset)
+            }
+        }
+
+        unsafe impl GlobalAlloc for TrackingAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let (outer, offset) = Self::outer_layout(layout);
+                let ptr = System.alloc(outer);
+                if ptr.is_null() {
+                    return ptr;
+                }
+
+                // This thread local does not allocate and it does not have a destructor, so this is
+                // safe to use from within the allocator
+                let tracked = TRACK_ALLOCATIONS.with(|track| track.get());
+                if tracked {
+                    TRACKED_BYTES.fetch_add(layout.size() as isize, Ordering::SeqCst);
+                }
+
+                let ptr = ptr.add(offset);
+                (ptr as *mut usize).sub(1).write(tracked as usize);
+
+                ptr
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                let (outer, offset) = Self::outer_layout(layout);
+                if (ptr as *mut usize).sub(1).read() != 0 {
+                    TRACKED_BYTES.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+                }
+
+                System.dealloc(ptr.sub(offset), outer);
+            }
+        }
+
+        /// Count the bytes allocated on this thread while running `f`.
+        pub fn track_allocations<T>(f: impl FnOnce() -> T) -> T {
+            TRACK_ALLOCATIONS.with(|track| track.set(true));
+            let result = f();
+            TRACK_ALLOCATIONS.with(|track| track.set(false));
+
+            result
+        }
+    }
+
+    /// Create a factory and an instance of `P` through that factory, query the instance's
+    /// interfaces, and then release everything again through the COM reference counting functions.
+    unsafe fn instance_cycle<P: Vst3Plugin>() {
+        const CLASSES: &[PluginClass] = &[
+            PluginClass::new::<PluginA>(),
+            PluginClass::new::<CountedPlugin>(),
+        ];
+        let factory = Box::into_raw(Factory::new(CLASSES));
+
+        let cid = vst3_sys::IID {
+            data: P::PLATFORM_VST3_CLASS_ID,
+        };
+        let mut obj = std::ptr::null_mut();
+        assert_eq!((*factory).create_instance(&cid, &cid, &mut obj), kResultOk);
+        let wrapper = obj as *mut Wrapper<P>;
+
+        let iids = [
+            <dyn IComponent as ComInterface>::IID,
+            <dyn IAudioProcessor as ComInterface>::IID,
+            <dyn IEditController as ComInterface>::IID,
+        ];
+        for iid in &iids {
+            let mut interface = std::ptr::null_mut();
+            assert_eq!((*wrapper).query_interface(iid, &mut interface), kResultOk);
+            assert!(!interface.is_null());
+        }
+
+        for _ in &iids {
+            (*wrapper).release();
+        }
+        assert_eq!((*wrapper).release(), 0);
+        assert_eq!((*factory).release(), 0);
+    }
+
+    #[test]
+    fn release_drops_plugin() {
+        unsafe {
+            let wrapper = Box::into_raw(Wrapper::<CountedPlugin>::new());
+            assert_eq!(LIVE_COUNTED_PLUGINS.load(Ordering::SeqCst), 1);
+
+            let mut interface = std::ptr::null_mut();
+            assert_eq!(
+                (*wrapper).query_interface(&<dyn IComponent as ComInterface>::IID, &mut interface),
+                kResultOk
+            );
+            assert_eq!((*wrapper).add_ref(), 3);
+            assert_eq!((*wrapper).release(), 2);
+            assert_eq!((*wrapper).release(), 1);
+            assert_eq!(LIVE_COUNTED_PLUGINS.load(Ordering::SeqCst), 1);
+
+            assert_eq!((*wrapper).release(), 0);
+            assert_eq!(LIVE_COUNTED_PLUGINS.load(Ordering::SeqCst), 0);
+
+            instance_cycle::<CountedPlugin>();
+            assert_eq!(LIVE_COUNTED_PLUGINS.load(Ordering::SeqCst), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(not(all(
+        debug_assertions,
+        any(
+            feature = "assert_process_allocs",
+            feature = "alloc_stats",
+            feature = "audio_thread_alloc_guard"
+        )
+    )))]
+    fn no_leaks_across_instances() {
+        use tracking::{track_allocations, TRACKED_BYTES};
+
+        // The first cycle may initialize some lazily initialized global state
+        track_allocations(|| unsafe { instance_cycle::<PluginA>() });
+        let baseline = TRACKED_BYTES.load(Ordering::SeqCst);
+
+        track_allocations(|| {
+            for _ in 0..1000 {
+                unsafe { instance_cycle::<PluginA>() };
+            }
+        });
+        let growth = TRACKED_BYTES.load(Ordering::SeqCst) - baseline;
+
+        // A single leaked instance would be many kilobytes large, so any leak in the instance
+        // cycle would show up here
+        assert!(
+            growth < 1024,
+            "{growth} bytes were leaked over 1000 plugin instances"
+        );
+    }
+
+    #[test]
+    fn background_threads_stop_when_unloaded() {
+        let cid_a = vst3_sys::IID {
+            data: PluginA::PLATFORM_VST3_CLASS_ID,
+        };
+        let cid_b = vst3_sys::IID {
+            data: PluginB::PLATFORM_VST3_CLASS_ID,
+        };
+
+        // This simulates a host repeatedly loading the library, creating a couple of instances,
+        // and then unloading the library again
+        for _ in 0..8 {
+            assert!(crate::event_loop::live_worker_threads().is_none());
+
+            unsafe {
+                let factory = Box::into_raw(Factory::new(CLASSES));
+                let create_instance = |cid: &vst3_sys::IID| {
+                    let mut obj = std::ptr::null_mut();
+                    assert_eq!((*factory).create_instance(cid, cid, &mut obj), kResultOk);
+
+                    obj
+                };
+                let wrappers_a = [
+                    create_instance(&cid_a) as *mut Wrapper<PluginA>,
+                    create_instance(&cid_a) as *mut Wrapper<PluginA>,
+                ];
+                let wrapper_b = create_instance(&cid_b) as *mut Wrapper<PluginB>;
+
+                // All instances share a single pool of worker threads
+                let live_worker_threads = crate::event_loop::live_worker_threads()
+                    .expect("The instances did not spawn a background thread pool");
+                assert!(live_worker_threads.load(Ordering::SeqCst) > 0);
+
+                for wrapper in wrappers_a {
+                    assert_eq!((*wrapper).release(), 0);
+                }
+                assert_eq!((*wrapper_b).release(), 0);
+                assert_eq!((*factory).release(), 0);
+                crate::wrapper::teardown_library();
+
+                assert_eq!(live_worker_threads.load(Ordering::SeqCst), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn enumerates_all_classes() {
+        let factory = Factory::new(CLASSES);
+        unsafe {
+            assert_eq!(factory.count_classes(), 2);
+
+            let mut info: vst3_sys::base::PClassInfo2 = mem::zeroed();
+            assert_eq!(factory.get_class_info2(0, &mut info), kResultOk);
+            assert_eq!(info.cid.data, PluginA::PLATFORM_VST3_CLASS_ID);
+            assert_eq!(CStr::from_ptr(info.name.as_ptr()).to_str(), Ok("Plugin A"));
+            assert_eq!(factory.get_class_info2(1, &mut info), kResultOk);
+            assert_eq!(info.cid.data, PluginB::PLATFORM_VST3_CLASS_ID);
+            assert_eq!(CStr::from_ptr(info.name.as_ptr()).to_str(), Ok("Plugin B"));
+
+            assert_eq!(factory.get_class_info2(2, &mut info), kInvalidArgument);
+            assert_eq!(factory.get_class_info2(-1, &mut info), kInvalidArgument);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn subcategories_default_to_categories_string() {
+        assert_eq!(
+            LegacyPlugin::VST3_SUBCATEGORIES,
+            [Vst3SubCategory::Custom("Fx|Pitch Shift")]
+        );
+        assert_eq!(PluginA::VST3_CATEGORIES, "");
+        assert!(PluginClass::new::<LegacyPlugin>().uses_categories_string());
+        assert!(!PluginClass::new::<PluginA>().uses_categories_string());
+    }
+
+    #[test]
+    fn class_subcategories_and_flags() {
+        const CLASSES: &[PluginClass] = &[
+            PluginClass::new::<PluginA>(),
+            PluginClass::new::<SynthPlugin>(),
+            PluginClass::new::<LegacyPlugin>(),
+        ];
+        let factory = Factory::new(CLASSES);
+        unsafe {
+            let mut info: vst3_sys::base::PClassInfo2 = mem::zeroed();
+            assert_eq!(factory.get_class_info2(0, &mut info), kResultOk);
+            assert_eq!(
+                CStr::from_ptr(info.subcategories.as_ptr()).to_str(),
+                Ok("Fx")
+            );
+            assert_eq!(info.class_flags, K_SIMPLE_MODE_SUPPORTED);
+
+            // Instruments don't support the simple IO mode
+            assert_eq!(factory.get_class_info2(1, &mut info), kResultOk);
+            assert_eq!(
+                CStr::from_ptr(info.subcategories.as_ptr()).to_str(),
+                Ok("Instrument|Synth|Wavetable")
+            );
+            assert_eq!(info.class_flags, K_DISTRIBUTABLE);
+
+            // The deprecated string is used as is, and the unicode version of the struct contains
+            // the same information
+            let mut info: vst3_sys::base::PClassInfoW = mem::zeroed();
+            assert_eq!(factory.get_class_info_unicode(2, &mut info), kResultOk);
+            assert_eq!(
+                CStr::from_ptr(info.subcategories.as_ptr()).to_str(),
+                Ok("Fx|Pitch Shift")
+            );
+            assert_eq!(info.class_flags, K_SIMPLE_MODE_SUPPORTED);
+        }
+    }
+
+    #[test]
+    fn instantiates_requested_class() {
+        let factory = Factory::new(CLASSES);
+        unsafe {
+            let iid = vst3_sys::IID {
+                data: PluginB::PLATFORM_VST3_CLASS_ID,
+            };
+            let mut obj = std::ptr::null_mut();
+            assert_eq!(factory.create_instance(&iid, &iid, &mut obj), kResultOk);
+            assert!(!obj.is_null());
+            assert_eq!((*(obj as *mut Wrapper<PluginB>)).release(), 0);
+
+            let iid = vst3_sys::IID {
+                data: *b"NihPlugFactoryTC",
+            };
+            let mut obj = std::ptr::null_mut();
+            assert_eq!(factory.create_instance(&iid, &iid, &mut obj), kNoInterface);
+            assert!(obj.is_null());
+        }
+    }
+
+    /// A plugin that panics while being created.
+    struct PanickingPlugin;
+
+    impl Default for PanickingPlugin {
+        fn default() -> Self {
+            panic!("The plugin panicked while being created")
+        }
+    }
+
+    impl Plugin for PanickingPlugin {
+        const NAME: &'static str = "Panicking Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            Arc::new(EmptyParams)
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for PanickingPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugFactoryTP";
+    }
+
+    /// A panic while creating the plugin should not unwind into the host.
+    #[test]
+    fn create_instance_catches_panics() {
+        const PANICKING_CLASSES: &[PluginClass] = &[PluginClass::new::<PanickingPlugin>()];
+
+        let factory = Factory::new(PANICKING_CLASSES);
+        unsafe {
+            let iid = vst3_sys::IID {
+                data: PanickingPlugin::PLATFORM_VST3_CLASS_ID,
+            };
+            let mut obj = std::ptr::null_mut();
+            assert_eq!(factory.create_instance(&iid, &iid, &mut obj), kResultFalse);
+            assert!(obj.is_null());
+        }
+    }
+
+    #[test]
+    fn empty_factory() {
+        let factory = Factory::new(&[]);
+        unsafe {
+            assert_eq!(factory.count_classes(), 0);
+
+            let mut info: vst3_sys::base::PFactoryInfo = mem::zeroed();
+            assert_eq!(factory.get_factory_info(&mut info), kResultOk);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_class_ids() {
+        assert_unique_class_ids(&[
+            PluginClass::new::<PluginA>(),
+            PluginClass::new::<PluginB>(),
+            PluginClass::new::<PluginA>(),
+        ]);
+    }
+}
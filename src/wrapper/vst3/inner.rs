@@ -1,39 +1,88 @@
 use atomic_refcell::AtomicRefCell;
 use crossbeam::atomic::AtomicCell;
 use crossbeam::channel::{self, SendTimeoutError};
-use parking_lot::{Mutex, RwLock};
+use crossbeam::queue::ArrayQueue;
+use parking_lot::{Mutex, MutexGuard, RwLock};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use vst3_sys::base::{kInvalidArgument, kResultOk, tresult};
-use vst3_sys::vst::{IComponentHandler, RestartFlags};
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult};
+use vst3_sys::vst::{IComponentHandler, IComponentHandler2, IComponentHandler3, RestartFlags};
 
+use super::channel_adapter::ChannelAdapter;
 use super::context::{WrapperGuiContext, WrapperInitContext, WrapperProcessContext};
 use super::note_expressions::NoteExpressionController;
 use super::param_units::ParamUnits;
 use super::util::{ObjectPtr, VstPtr, VST3_MIDI_PARAMS_END, VST3_MIDI_PARAMS_START};
 use super::view::WrapperView;
 use crate::buffer::Buffer;
-use crate::context::gui::AsyncExecutor;
+use crate::context::gui::{AsyncExecutor, ChannelInfo};
 use crate::context::process::Transport;
+use crate::context::repaint::RepaintRequests;
 use crate::editor::Editor;
 use crate::event_loop::{EventLoop, MainThreadExecutor, OsEventLoop};
+use crate::midi::routing::MidiInputRouter;
 use crate::midi::{MidiConfig, NoteEvent};
+use crate::params::changes::{ParamChangeSource, ParamChangeTracker};
 use crate::params::internals::ParamPtr;
-use crate::params::{ParamFlags, Params};
+use crate::params::{BoolParam, ParamFlags, Params};
 use crate::plugin::{
-    BufferConfig, BusConfig, Plugin, ProcessMode, ProcessStatus, TaskExecutor, Vst3Plugin,
+    BackgroundTaskPriority, BufferConfig, BusConfig, Plugin, ProcessMode, ProcessStatus,
+    TaskExecutor, Vst3Plugin,
 };
-use crate::wrapper::state::{self, PluginState};
-use crate::wrapper::util::{hash_param_id, process_wrapper};
+use crate::util::permit_alloc;
+use crate::wrapper::activation_fade::ActivationFade;
+use crate::wrapper::alloc_stats::{AllocPhase, AllocStats};
+use crate::wrapper::bypass::BypassProcessor;
+use crate::wrapper::musical_scheduler::MusicalScheduler;
+use crate::wrapper::oversampling::WrapperOversampling;
+use crate::wrapper::state::{self, InstanceId, PluginState};
+use crate::wrapper::util::{
+    find_bypass_param, hash_param_id, param_id_for_hash, param_map_with_bypass, process_wrapper,
+    register_legacy_param_ids, transition_lifecycle_state, LifecycleState, ModulationWriter,
+    ParamValueValidator, StateRestoredNotifier, TransportTracker,
+};
+
+/// The number of parameter changes from `IEditController::setParamNormalized()` that can be queued
+/// while the plugin is processing audio. Any further changes made before the next process call are
+/// dropped.
+const CONTROLLER_PARAM_CHANGES_CAPACITY: usize = 4096;
+/// The number of read-only output parameter values the plugin can set during a single process call.
+/// Any further values are not sent to the host.
+const OUTPUT_PARAM_CHANGES_CAPACITY: usize = 1024;
+/// The number of note events that can be stored in `input_events` and `output_events` without
+/// reallocating.
+const NOTE_EVENTS_CAPACITY: usize = 1024;
+/// The number of events that can be stored in `process_events` without reallocating.
+const PROCESS_EVENTS_CAPACITY: usize = 4096;
 
 /// The actual wrapper bits. We need this as an `Arc<T>` so we can safely use our event loop API.
 /// Since we can't combine that with VST3's interior reference counting this just has to be moved to
 /// its own struct.
+///
+/// Hosts call into the wrapper from multiple threads at the same time, so everything in here that
+/// can be accessed outside of the audio thread is either atomic or behind a lock. The
+/// `AtomicRefCell`s used for the audio thread's scratch buffers are only borrowed from within
+/// `IAudioProcessor::process()` and from functions like `IComponent::setActive()` that the VST3
+/// specification does not allow to be called concurrently with `process()`.
 pub(crate) struct WrapperInner<P: Vst3Plugin> {
-    /// The wrapped plugin instance.
+    /// The wrapped plugin instance. The audio thread only ever acquires this with
+    /// [`try_lock_plugin_for_processing()`][Self::try_lock_plugin_for_processing()], so it never
+    /// waits for another thread. Other threads use a regular blocking `lock()`. Those threads must
+    /// never wait for the audio thread while holding this lock, so a host calling something like
+    /// `IAudioProcessor::setBusArrangements()` during processing can at worst cause a silent block
+    /// instead of a deadlock.
     pub plugin: Mutex<P>,
+    /// The number of times the audio thread could not use the plugin because another thread was
+    /// using it. This is usually a block that was replaced with silence, but it also counts state
+    /// updates from the editor that had to be postponed to a later block.
+    pub num_contended_process_blocks: AtomicU32,
+    /// The number of parameter changes that were merged into other changes because the host sent
+    /// more of them than the plugin allows the block to be split for. See
+    /// [`Plugin::MAX_AUTOMATION_SPLITS`].
+    pub num_coalesced_param_changes: AtomicU32,
     /// The plugin's background task executor closure.
     pub task_executor: Mutex<TaskExecutor<P>>,
     /// The plugin's parameters. These are fetched once during initialization. That way the
@@ -48,6 +97,15 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// The host's [`IComponentHandler`] instance, if passed through
     /// [`IEditController::set_component_handler`].
     pub component_handler: AtomicRefCell<Option<VstPtr<dyn IComponentHandler>>>,
+    /// The same handler's [`IComponentHandler2`] interface, if the host implements it. Used for
+    /// group edits and for marking the plugin's state as dirty.
+    pub component_handler2: AtomicRefCell<Option<VstPtr<dyn IComponentHandler2>>>,
+    /// The same handler's [`IComponentHandler3`] interface, if the host implements it. Used to
+    /// create the host's context menus for parameters.
+    pub component_handler3: AtomicRefCell<Option<VstPtr<dyn IComponentHandler3>>>,
+    /// Whether the host has been told that the plugin's state has changed since it last saved or
+    /// loaded that state. See [`Self::mark_state_dirty()`].
+    pub state_dirty: AtomicBool,
 
     /// Our own [`IPlugView`] instance. This is set while the editor is actually visible (which is
     /// different form the lifetime of [`WrapperView`][super::WrapperView] itself).
@@ -64,25 +122,57 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// TODO: Is there a better type for Send+Sync late initialization?
     pub event_loop: AtomicRefCell<Option<OsEventLoop<Task<P>, Self>>>,
 
-    /// Whether the plugin is currently processing audio. In other words, the last state
-    /// `IAudioProcessor::setActive()` has been called with.
-    pub is_processing: AtomicBool,
+    /// Where the plugin instance is in its lifecycle. `IAudioProcessor::setupProcessing()`,
+    /// `IComponent::setActive()`, and `IAudioProcessor::setProcessing()` move the instance between
+    /// these states, and they and `IAudioProcessor::process()` refuse to do anything in states
+    /// where they should not have been called.
+    pub lifecycle_state: AtomicCell<LifecycleState>,
+    /// Set when the plugin panicked during any call the wrapper made into it, see
+    /// [`catch_plugin_panic()`][Self::catch_plugin_panic()]. The plugin's state may have been left
+    /// half-modified at that point, so once this is set the plugin won't be initialized, reset, or
+    /// process any more audio, the wrapper outputs silence instead, and loading state fails.
+    /// Parameter queries and saving state keep working as usual so the host doesn't lock up.
+    pub is_poisoned: AtomicBool,
     /// The current bus configuration, modified through `IAudioProcessor::setBusArrangements()`.
     pub current_bus_config: AtomicCell<BusConfig>,
+    /// The bus configuration the plugin is run with. This is the same as `current_bus_config`
+    /// unless the main channel layout is being adapted because of
+    /// [`Plugin::CHANNEL_ADAPTATION`][crate::prelude::Plugin::CHANNEL_ADAPTATION].
+    pub plugin_bus_config: AtomicCell<BusConfig>,
     /// The current buffer configuration, containing the sample rate and the maximum block size.
     /// Will be set in `IAudioProcessor::setupProcessing()`.
     pub current_buffer_config: AtomicCell<Option<BufferConfig>>,
+    /// The bus and buffer configurations the plugin was last initialized with in
+    /// `IComponent::setActive()`. Used to decide whether a sample rate change can be handled with
+    /// [`Plugin::sample_rate_changed()`][crate::prelude::Plugin::sample_rate_changed()].
+    pub initialized_config: AtomicCell<Option<(BusConfig, BufferConfig)>>,
+    /// Set when the plugin was reactivated through
+    /// [`Plugin::sample_rate_changed()`][crate::prelude::Plugin::sample_rate_changed()]. The next
+    /// `IAudioProcessor::setProcessing(true)` call then does not reset the plugin.
+    pub skip_next_reset: AtomicBool,
     /// The current audio processing mode. Set in `IAudioProcessor::setup_processing()`.
     pub current_process_mode: AtomicCell<ProcessMode>,
+    /// A random ID for this instance that's stored in the plugin's state. Used to derive the seed
+    /// for
+    /// [`ProcessContext::deterministic_seed()`][crate::prelude::ProcessContext::deterministic_seed()].
+    pub instance_id: AtomicCell<InstanceId>,
     /// The last process status returned by the plugin. This is used for tail handling.
     pub last_process_status: AtomicCell<ProcessStatus>,
     /// The current latency in samples, as set by the plugin through the [`ProcessContext`].
     pub current_latency: AtomicU32,
+    /// Whether the host's last process call used main channel counts that differ from the
+    /// negotiated bus configuration. Used to only report the mismatch once instead of on every
+    /// process call.
+    pub host_channel_mismatch: AtomicBool,
     /// Contains slices for the plugin's outputs. You can't directly create a nested slice from
     /// a pointer to pointers, so this needs to be preallocated in the setup call and kept around
     /// between process calls. This buffer owns the vector, because otherwise it would need to store
     /// a mutable reference to the data contained in this mutex.
     pub output_buffer: AtomicRefCell<Buffer<'static>>,
+    /// Converts between the host's and the plugin's main channel layouts when they differ. Set up
+    /// in `IAudioProcessor::set_active()`. When this is set, `output_buffer` points to the
+    /// adapter's buffers instead of the host's.
+    pub channel_adapter: AtomicRefCell<Option<ChannelAdapter>>,
     /// Stores sample data for every sidechain input the plugin has. Indexed by
     /// `[sidechain_input][channel][sample]` We'll copy the data to these buffers since modifying
     /// the host's sidechain input buffers may not be safe, and the plugin may want to be able to
@@ -111,6 +201,10 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// the most recent VST3 note IDs we've seen, and then map those back to MIDI note IDs and
     /// channels as needed.
     pub note_expression_controller: AtomicRefCell<NoteExpressionController>,
+    /// Filters the incoming note events and translates MPE member channel messages according to
+    /// [`Plugin::MIDI_INPUT_CONFIG`][crate::prelude::Plugin::MIDI_INPUT_CONFIG] before they're
+    /// added to `input_events`.
+    pub midi_input_router: AtomicRefCell<MidiInputRouter>,
     /// Unprocessed parameter changes and note events sent by the host during a process call.
     /// Parameter changes are sent as separate queues for each parameter, and note events are in
     /// another queue on top of that. And if `P::MIDI_INPUT >= MidiConfig::MidiCCs`, then we can
@@ -121,6 +215,39 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// then do the block splitting based on that. Note events need to have their timing adjusted to
     /// match the block start, since they're all read upfront.
     pub process_events: AtomicRefCell<Vec<ProcessEvent>>,
+    /// The sample offsets a block is split at after merging dense parameter changes in
+    /// `process_events`. Has room for [`Plugin::MAX_AUTOMATION_SPLITS`] offsets, up to the
+    /// capacity of `process_events`.
+    pub automation_split_points: AtomicRefCell<Vec<u32>>,
+    /// Computes the continuous sample counter and the position jump flag for [`Transport`]. This is
+    /// reset when the host starts processing audio.
+    pub transport_tracker: AtomicRefCell<TransportTracker>,
+    /// Fades in the plugin's output after the host starts processing audio if
+    /// [`Plugin::ACTIVATION_FADE_MS`] is set.
+    pub activation_fade: AtomicRefCell<ActivationFade>,
+    /// Implements [`wrapper_bypass_param`][Self::wrapper_bypass_param]. This is only set up when
+    /// the plugin does not have its own bypass parameter.
+    pub bypass_processor: AtomicRefCell<BypassProcessor>,
+    /// Parameter changes the host sent through `IEditController::setParamNormalized()` while the
+    /// plugin was processing audio, as `(hash, normalized_value)` pairs. These are applied at the
+    /// start of the next process call, unless that block's parameter queues also contain a change
+    /// for the same parameter. The host's parameter queues take precedence in that case.
+    pub controller_param_changes: ArrayQueue<(u32, f32)>,
+    /// Parameter changes made from the GUI as part of a single batch while the plugin was
+    /// processing audio, as `(hash, normalized_value)` pairs. The GUI thread only adds a batch to
+    /// this vector once the batch has ended, and the audio thread applies all of these changes at
+    /// once at the start of the next process call. The audio thread only tries to lock this, so it
+    /// never blocks.
+    pub pending_param_batch: Mutex<Vec<(u32, f32)>>,
+    /// The track information the host last sent through `IInfoListener::setChannelContextInfos()`.
+    pub channel_info: Mutex<Option<ChannelInfo>>,
+    /// Values the plugin set for its read-only output parameters during the current process call,
+    /// as `(hash, normalized_value)` pairs. These are written to the host's output parameter
+    /// changes at the end of the process call.
+    pub output_param_changes: ArrayQueue<(u32, f32)>,
+    /// Repaint requests for the editor from the process function and the editor itself, and the
+    /// editor's [`RepaintPolicy`][crate::prelude::RepaintPolicy].
+    pub editor_repaint: RepaintRequests,
     /// The plugin is able to restore state through a method on the `GuiContext`. To avoid changing
     /// parameters mid-processing and running into garbled data if the host also tries to load state
     /// at the same time the restoring happens at the end of each processing call. If this zero
@@ -132,8 +259,17 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     pub updated_state_sender: channel::Sender<PluginState>,
     /// The receiver belonging to [`new_state_sender`][Self::new_state_sender].
     pub updated_state_receiver: channel::Receiver<PluginState>,
+    /// Whether the editor is currently trying to send a state object through
+    /// [`updated_state_sender`][Self::updated_state_sender]. The audio thread only tries to acquire
+    /// the plugin for restoring the state when this is set. If another thread is using the plugin,
+    /// then the state is left in the channel and the restore is retried at the end of the next
+    /// process call.
+    pub updated_state_pending: AtomicBool,
 
-    /// The keys from `param_map` in a stable order.
+    /// The keys from `param_map` in a stable order, excluding parameters marked as
+    /// [`ParamFlags::HIDDEN`]. These are the parameters enumerated to the host. Hidden parameters
+    /// are still part of [`param_by_hash`][Self::param_by_hash] so they can be set by ID and
+    /// restored from the plugin's state.
     pub param_hashes: Vec<u32>,
     /// A mapping from parameter ID hashes (obtained from the string parameter IDs) to pointers to
     /// parameters belonging to the plugin. These addresses will remain stable as long as the
@@ -143,11 +279,49 @@ pub(crate) struct WrapperInner<P: Vst3Plugin> {
     /// Mappings from string parameter identifiers to parameter hashes. Useful for debug logging
     /// and when storing and restoring plugin state.
     pub param_id_to_hash: HashMap<String, u32>,
+    /// Maps legacy string parameter IDs to the hash of the parameter they were renamed to. The
+    /// legacy IDs' hashes are also included in [`param_by_hash`][Self::param_by_hash], but they're
+    /// not part of the other maps so they're never shown to the host or written to the state.
+    pub legacy_param_id_to_hash: HashMap<String, u32>,
     /// The inverse mapping from [`param_by_hash`][Self::param_by_hash]. This is needed to be able
     /// to have an ergonomic parameter setting API that uses references to the parameters instead of
     /// having to add a setter function to the parameter (or even worse, have it be completely
     /// untyped).
     pub param_ptr_to_hash: HashMap<ParamPtr, u32>,
+    /// The parameters whose modulated values are written to the host as automation. See
+    /// [`GuiContext::raw_set_modulation_write()`][crate::prelude::GuiContext::raw_set_modulation_write()].
+    pub modulation_writer: ModulationWriter,
+    /// Parameter changes the editor scheduled for the next beat or bar boundary. See
+    /// [`GuiContext::raw_set_parameter_at_musical()`][crate::prelude::GuiContext::raw_set_parameter_at_musical()].
+    pub musical_scheduler: MusicalScheduler,
+    /// The source of the most recent change to every parameter, shared with the editor through the
+    /// [`GuiContext`][crate::prelude::GuiContext].
+    pub param_changes: Arc<ParamChangeTracker>,
+    /// Clamps the normalized values set through
+    /// [`set_normalized_value_by_hash()`][Self::set_normalized_value_by_hash()] and rejects NaN and
+    /// infinite values.
+    pub param_value_validator: ParamValueValidator,
+    /// Counts the allocations made during the different phases of the plugin's lifecycle when the
+    /// `alloc_stats` feature is enabled. This does nothing otherwise.
+    pub alloc_stats: AllocStats,
+    /// Calls [`Plugin::state_restored()`][crate::prelude::Plugin::state_restored()] before the
+    /// plugin is initialized after loading state.
+    pub state_restored: StateRestoredNotifier,
+    /// Serializes state loading. Some hosts load state for multiple instances from background
+    /// threads, and they may call `IComponent::setState()` again before the last call has finished.
+    /// This is only held while loading state outside of the audio thread. It must be locked before
+    /// [`plugin`][Self::plugin].
+    pub state_load_lock: Mutex<()>,
+    /// The bypass parameter added by the wrapper if the plugin does not have its own bypass
+    /// parameter. This is included in the parameter maps above. The plugin is not processed while
+    /// this parameter is enabled.
+    pub wrapper_bypass_param: Option<Box<BoolParam>>,
+    /// The plugin's bypass parameter, or [`wrapper_bypass_param`][Self::wrapper_bypass_param] if
+    /// the plugin does not have one.
+    pub bypass_param_ptr: Option<ParamPtr>,
+    /// Oversamples the plugin if it allows this through [`Plugin::ALLOW_WRAPPER_OVERSAMPLING`].
+    /// This owns the oversampling parameter, which is included in the parameter maps above.
+    pub oversampling: WrapperOversampling,
 }
 
 /// Tasks that can be sent from the plugin to be executed on the main thread in a non-blocking
@@ -163,16 +337,20 @@ pub enum Task<P: Plugin> {
     /// Request the editor to be resized according to its current size. Right now there is no way to
     /// handle "denied resize" requests yet.
     RequestResize,
+    /// Let the editor know that the plugin panicked, see
+    /// [`Editor::plugin_panicked()`][crate::prelude::Editor::plugin_panicked()].
+    NotifyPluginPanicked,
 }
 
 /// VST3 makes audio processing pretty complicated. In order to support both block splitting for
 /// sample accurate automation and MIDI CC handling through parameters we need to put all parameter
 /// changes and (translated) note events into a sorted array first.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ProcessEvent {
-    /// An incoming parameter change sent by the host. This will only be used when sample accurate
-    /// automation has been enabled, and the parameters are only updated when we process this
-    /// spooled event at the start of a block.
+    /// An incoming parameter change sent by the host, or a change the editor scheduled for the
+    /// next beat or bar. This will only be used when sample accurate automation has been enabled,
+    /// and the parameters are only updated when we process this spooled event at the start of a
+    /// block.
     ParameterChange {
         /// The event's sample offset within the buffer. Used for sorting.
         timing: u32,
@@ -180,6 +358,8 @@ pub enum ProcessEvent {
         hash: u32,
         /// The normalized values, as provided by the host.
         normalized_value: f32,
+        /// Where the change came from.
+        source: ParamChangeSource,
     },
     /// An incoming parameter change sent by the host. This will only be used when sample accurate
     /// automation has been enabled, and the parameters are only updated when we process this
@@ -210,8 +390,10 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         // we'll calculate from the string ID specified by the plugin. These parameters should also
         // remain in the same order as the one returned by the plugin.
         let params = plugin.params();
-        let param_id_hashes_ptrs_groups: Vec<_> = params
-            .param_map()
+        let (mut param_map, wrapper_bypass_param) = param_map_with_bypass(params.as_ref());
+        let bypass_param_ptr = find_bypass_param(&param_map);
+        let oversampling = WrapperOversampling::new::<P>(&mut param_map);
+        let param_id_hashes_ptrs_groups: Vec<_> = param_map
             .into_iter()
             .map(|(id, ptr, group)| {
                 let hash = hash_param_id(&id);
@@ -219,13 +401,12 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             })
             .collect();
         if cfg!(debug_assertions) {
-            let param_map = params.param_map();
             let param_ids: HashSet<_> = param_id_hashes_ptrs_groups
                 .iter()
                 .map(|(id, _, _, _)| id.clone())
                 .collect();
             nih_debug_assert_eq!(
-                param_map.len(),
+                param_id_hashes_ptrs_groups.len(),
                 param_ids.len(),
                 "The plugin has duplicate parameter IDs, weird things may happen. Consider using \
                  6 character parameter IDs to avoid collisions."
@@ -258,9 +439,10 @@ impl<P: Vst3Plugin> WrapperInner<P> {
 
         let param_hashes = param_id_hashes_ptrs_groups
             .iter()
+            .filter(|(_, _, ptr, _)| !unsafe { ptr.flags() }.contains(ParamFlags::HIDDEN))
             .map(|(_, hash, _, _)| *hash)
             .collect();
-        let param_by_hash = param_id_hashes_ptrs_groups
+        let mut param_by_hash = param_id_hashes_ptrs_groups
             .iter()
             .map(|(_, hash, ptr, _)| (*hash, *ptr))
             .collect();
@@ -274,55 +456,100 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             .iter()
             .map(|(id, hash, _, _)| (id.clone(), *hash))
             .collect();
+        let legacy_param_id_to_hash =
+            register_legacy_param_ids(params.as_ref(), &mut param_by_hash, &param_id_to_hash);
         let param_ptr_to_hash = param_id_hashes_ptrs_groups
             .into_iter()
             .map(|(_, hash, ptr, _)| (ptr, hash))
             .collect();
 
+        let param_changes = Arc::new(ParamChangeTracker::new(param_ptr_to_hash.keys().copied()));
+        let modulation_writer = ModulationWriter::new(&param_ptr_to_hash);
+        let musical_scheduler = MusicalScheduler::new(&param_ptr_to_hash);
+
+        let default_bus_config = BusConfig {
+            num_input_channels: P::DEFAULT_INPUT_CHANNELS,
+            num_output_channels: P::DEFAULT_OUTPUT_CHANNELS,
+            aux_input_busses: P::DEFAULT_AUX_INPUTS.unwrap_or_default(),
+            aux_output_busses: P::DEFAULT_AUX_OUTPUTS.unwrap_or_default(),
+        };
+
         let wrapper = Self {
             plugin: Mutex::new(plugin),
+            num_contended_process_blocks: AtomicU32::new(0),
+            num_coalesced_param_changes: AtomicU32::new(0),
             task_executor,
             params,
             // Initialized later as it needs a reference to the wrapper for the async executor
             editor: AtomicRefCell::new(None),
 
             component_handler: AtomicRefCell::new(None),
+            component_handler2: AtomicRefCell::new(None),
+            component_handler3: AtomicRefCell::new(None),
+            state_dirty: AtomicBool::new(false),
 
             plug_view: RwLock::new(None),
 
             event_loop: AtomicRefCell::new(None),
 
-            is_processing: AtomicBool::new(false),
+            lifecycle_state: AtomicCell::new(LifecycleState::Created),
+            is_poisoned: AtomicBool::new(false),
             // Some hosts, like the current version of Bitwig and Ardour at the time of writing,
             // will try using the plugin's default not yet initialized bus arrangement. Because of
             // that, we'll always initialize this configuration even before the host requests a
             // channel layout.
-            current_bus_config: AtomicCell::new(BusConfig {
-                num_input_channels: P::DEFAULT_INPUT_CHANNELS,
-                num_output_channels: P::DEFAULT_OUTPUT_CHANNELS,
-                aux_input_busses: P::DEFAULT_AUX_INPUTS.unwrap_or_default(),
-                aux_output_busses: P::DEFAULT_AUX_OUTPUTS.unwrap_or_default(),
-            }),
+            current_bus_config: AtomicCell::new(default_bus_config),
+            plugin_bus_config: AtomicCell::new(default_bus_config),
             current_buffer_config: AtomicCell::new(None),
+            initialized_config: AtomicCell::new(None),
+            skip_next_reset: AtomicBool::new(false),
             current_process_mode: AtomicCell::new(ProcessMode::Realtime),
+            instance_id: AtomicCell::new(InstanceId::new()),
             last_process_status: AtomicCell::new(ProcessStatus::Normal),
             current_latency: AtomicU32::new(0),
+            host_channel_mismatch: AtomicBool::new(false),
             output_buffer: AtomicRefCell::new(Buffer::default()),
+            channel_adapter: AtomicRefCell::new(None),
             aux_input_storage: AtomicRefCell::new(Vec::new()),
             aux_input_buffers: AtomicRefCell::new(Vec::new()),
             aux_output_buffers: AtomicRefCell::new(Vec::new()),
-            input_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
-            output_events: AtomicRefCell::new(VecDeque::with_capacity(1024)),
+            // The event buffers are only preallocated when the plugin gets activated. Hosts create
+            // plugin instances on the main thread when scanning plugins, so this should be as cheap
+            // as possible.
+            input_events: AtomicRefCell::new(VecDeque::new()),
+            output_events: AtomicRefCell::new(VecDeque::new()),
             note_expression_controller: AtomicRefCell::new(NoteExpressionController::default()),
-            process_events: AtomicRefCell::new(Vec::with_capacity(4096)),
+            midi_input_router: AtomicRefCell::new(MidiInputRouter::new(P::MIDI_INPUT_CONFIG)),
+            process_events: AtomicRefCell::new(Vec::new()),
+            automation_split_points: AtomicRefCell::new(Vec::new()),
+            transport_tracker: AtomicRefCell::new(TransportTracker::default()),
+            activation_fade: AtomicRefCell::new(ActivationFade::default()),
+            bypass_processor: AtomicRefCell::new(BypassProcessor::default()),
+            controller_param_changes: ArrayQueue::new(CONTROLLER_PARAM_CHANGES_CAPACITY),
+            pending_param_batch: Mutex::new(Vec::new()),
+            channel_info: Mutex::new(None),
+            output_param_changes: ArrayQueue::new(OUTPUT_PARAM_CHANGES_CAPACITY),
+            editor_repaint: RepaintRequests::default(),
             updated_state_sender,
             updated_state_receiver,
+            updated_state_pending: AtomicBool::new(false),
 
             param_hashes,
             param_by_hash,
             param_units,
             param_id_to_hash,
+            legacy_param_id_to_hash,
             param_ptr_to_hash,
+            modulation_writer,
+            musical_scheduler,
+            param_changes,
+            param_value_validator: ParamValueValidator::default(),
+            alloc_stats: AllocStats::default(),
+            state_restored: StateRestoredNotifier::default(),
+            state_load_lock: Mutex::new(()),
+            wrapper_bypass_param,
+            bypass_param_ptr,
+            oversampling,
         };
 
         // FIXME: Right now this is safe, but if we are going to have a singleton main thread queue
@@ -340,7 +567,9 @@ impl<P: Vst3Plugin> WrapperInner<P> {
                     let wrapper = wrapper.clone();
 
                     move |task| {
-                        let task_posted = wrapper.schedule_background(Task::PluginTask(task));
+                        let priority = P::background_task_priority(&task);
+                        let task_posted =
+                            wrapper.schedule_background(Task::PluginTask(task), priority);
                         nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
                     }
                 }),
@@ -359,7 +588,10 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     }
 
     pub fn make_gui_context(self: Arc<Self>) -> Arc<WrapperGuiContext<P>> {
-        Arc::new(WrapperGuiContext { inner: self })
+        Arc::new(WrapperGuiContext {
+            inner: self,
+            batch: Default::default(),
+        })
     }
 
     /// # Note
@@ -373,7 +605,90 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         }
     }
 
-    pub fn make_process_context(&self, transport: Transport) -> WrapperProcessContext<'_, P> {
+    /// Whether the bypass parameter added by the wrapper is enabled. Always `false` if the plugin
+    /// has its own bypass parameter.
+    pub fn wrapper_bypassed(&self) -> bool {
+        self.wrapper_bypass_param
+            .as_ref()
+            .map_or(false, |param| param.value())
+    }
+
+    /// Whether the plugin is currently processing audio. In other words, whether the host called
+    /// `IAudioProcessor::setProcessing(true)` after activating the plugin.
+    pub fn is_processing(&self) -> bool {
+        self.lifecycle_state.load() == LifecycleState::Processing
+    }
+
+    /// Acquire the plugin from the audio thread without blocking. If another thread is currently
+    /// using the plugin, then this returns `None`, the contention is counted in
+    /// [`num_contended_process_blocks`][Self::num_contended_process_blocks], and the first
+    /// occurrence is logged. The caller should output silence for the current block or postpone
+    /// what it needed the plugin for in that case.
+    pub fn try_lock_plugin_for_processing(&self) -> Option<MutexGuard<'_, P>> {
+        // NOTE: `parking_lot`'s mutexes sometimes allocate because of their use of thread locals
+        let plugin = permit_alloc(|| self.plugin.try_lock());
+        if plugin.is_none()
+            && self
+                .num_contended_process_blocks
+                .fetch_add(1, Ordering::Relaxed)
+                == 0
+        {
+            permit_alloc(|| {
+                nih_log!(
+                    "The host called into the plugin from another thread while it was processing \
+                     audio, the audio thread will skip the plugin until it is available again"
+                )
+            });
+        }
+
+        plugin
+    }
+
+    /// Run `f`, which calls into the plugin, while catching panics. Unwinding into the host is
+    /// undefined behavior, and a panic won't poison the plugin's mutex, so a panic instead sets
+    /// [`is_poisoned`][Self::is_poisoned] and the editor is told about it. `action` describes what
+    /// the plugin was doing for the error message. Returns `None` if the plugin panicked. The panic
+    /// itself will already have been logged by our panic hook.
+    pub fn catch_plugin_panic<T>(&self, action: &str, f: impl FnOnce() -> T) -> Option<T> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => Some(result),
+            Err(payload) => {
+                permit_alloc(|| drop(payload));
+                nih_error!(
+                    "The plugin panicked while {}, it will output silence until it is reloaded",
+                    action
+                );
+
+                if !self.is_poisoned.swap(true, Ordering::SeqCst) {
+                    let task_posted = self.schedule_gui(Task::NotifyPluginPanicked);
+                    nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+                }
+
+                None
+            }
+        }
+    }
+
+    /// Move the instance to a new lifecycle state. See
+    /// [`lifecycle_state`][Self::lifecycle_state].
+    pub fn set_lifecycle_state(&self, new_state: LifecycleState) {
+        transition_lifecycle_state(&self.lifecycle_state, new_state);
+    }
+
+    /// The buffer config the plugin was initialized with. This differs from
+    /// [`current_buffer_config`][Self::current_buffer_config] when the wrapper oversamples the
+    /// plugin.
+    pub fn plugin_buffer_config(&self) -> Option<BufferConfig> {
+        self.current_buffer_config
+            .load()
+            .map(|buffer_config| self.oversampling.buffer_config(&buffer_config))
+    }
+
+    pub fn make_process_context(&self, mut transport: Transport) -> WrapperProcessContext<'_, P> {
+        // Plugins should never see transport information they did not ask for
+        transport.retain(P::REQUIRED_TRANSPORT);
+        self.oversampling.oversample_transport(&mut transport);
+
         WrapperProcessContext {
             inner: self,
             input_events_guard: self.input_events.borrow_mut(),
@@ -387,10 +702,10 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     ///
     /// If the task queue is full, then this will return false.
     #[must_use]
-    pub fn schedule_background(&self, task: Task<P>) -> bool {
+    pub fn schedule_background(&self, task: Task<P>, priority: BackgroundTaskPriority) -> bool {
         let event_loop = self.event_loop.borrow();
         let event_loop = event_loop.as_ref().unwrap();
-        event_loop.schedule_background(task)
+        event_loop.schedule_background(task, priority)
     }
 
     /// Either posts the task to the task queue using [`EventLoop::schedule_gui()`] so it can be
@@ -403,7 +718,7 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         let event_loop = self.event_loop.borrow();
         let event_loop = event_loop.as_ref().unwrap();
         if event_loop.is_main_thread() {
-            self.execute(task, true);
+            self.execute_gui(task);
             true
         } else {
             // If the editor is open, and the host exposes the `IRunLoop` interface, then we'll run
@@ -421,15 +736,30 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         }
     }
 
+    /// Whether the plugin's editor is currently open in a window provided by the host.
+    pub fn is_editor_open(&self) -> bool {
+        self.plug_view
+            .read()
+            .as_ref()
+            .map_or(false, |plug_view| plug_view.is_editor_open())
+    }
+
     /// If there's an editor open, let it know that parameter values have changed. This should be
     /// called whenever there's been a call or multiple calls to
     /// [`set_normalized_value_by_hash()[Self::set_normalized_value_by_hash()`]. In the off-chance
     /// that the editor instance is currently locked then nothing will happen, and the request can
     /// safely be ignored.
     pub fn notify_param_values_changed(&self) {
+        // Editors that only redraw on request should still show the new values
+        self.editor_repaint.request();
+
         if let Some(editor) = self.editor.borrow().as_ref() {
             match editor.try_lock() {
-                Some(editor) => editor.param_values_changed(),
+                Some(editor) => {
+                    self.catch_plugin_panic("notifying the editor of parameter changes", || {
+                        editor.param_values_changed()
+                    });
+                }
                 None => nih_debug_assert_failure!(
                     "The editor was locked when sending a parameter value change notification, \
                      ignoring"
@@ -439,7 +769,8 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     }
 
     /// Convenience function for setting a value for a parameter as triggered by a VST3 parameter
-    /// update. The same rate is for updating parameter smoothing.
+    /// update. The same rate is for updating parameter smoothing. The change is recorded in
+    /// [`param_changes`][Self::param_changes] with the specified source.
     ///
     /// After calling this function, you should call
     /// [`notify_param_values_changed()`][Self::notify_param_values_changed()] to allow the editor
@@ -450,24 +781,131 @@ impl<P: Vst3Plugin> WrapperInner<P> {
         hash: u32,
         normalized_value: f32,
         sample_rate: Option<f32>,
+        source: ParamChangeSource,
     ) -> tresult {
         match self.param_by_hash.get(&hash) {
             Some(param_ptr) => {
-                // Also update the parameter's smoothing if applicable
-                match (param_ptr, sample_rate) {
-                    (_, Some(sample_rate)) => unsafe {
-                        param_ptr.set_normalized_value(normalized_value);
-                        param_ptr.update_smoother(sample_rate, false);
-                    },
-                    _ => unsafe { param_ptr.set_normalized_value(normalized_value) },
+                // Read-only parameters are outputs of the plugin, so the host cannot change them
+                if source == ParamChangeSource::HostAutomation
+                    && unsafe { param_ptr.flags() }.contains(ParamFlags::READ_ONLY)
+                {
+                    return kResultFalse;
                 }
 
-                kResultOk
+                // Hosts and bridges occasionally send garbage, which would otherwise end up in the
+                // parameter's smoother and in the plugin's audio
+                let normalized_value = match self
+                    .param_value_validator
+                    .validate_normalized(normalized_value, || {
+                        param_id_for_hash(&self.param_id_to_hash, hash)
+                    }) {
+                    Some(normalized_value) => normalized_value,
+                    None => return kResultFalse,
+                };
+
+                match source {
+                    ParamChangeSource::HostAutomation => self
+                        .param_changes
+                        .record_host_automation(*param_ptr, normalized_value),
+                    source => self
+                        .param_changes
+                        .record(*param_ptr, source, normalized_value),
+                }
+
+                // Also update the parameter's smoothing if applicable. Setting the value runs the
+                // parameter's callback, which is the plugin's own code.
+                let updated = self.catch_plugin_panic("running a parameter callback", || {
+                    match (param_ptr, sample_rate) {
+                        (_, Some(sample_rate)) => unsafe {
+                            param_ptr.set_normalized_value(normalized_value);
+                            param_ptr.update_smoother(sample_rate, false);
+                        },
+                        _ => unsafe { param_ptr.set_normalized_value(normalized_value) },
+                    }
+                });
+
+                match updated {
+                    Some(()) => kResultOk,
+                    None => kResultFalse,
+                }
             }
             _ => kInvalidArgument,
         }
     }
 
+    /// Set a parameter's value while the plugin is not processing audio, for instance when the host
+    /// calls `IEditController::setParamNormalized()`. Unlike
+    /// [`set_normalized_value_by_hash()`][Self::set_normalized_value_by_hash()] this resets the
+    /// parameter's smoother to the new value, so the next time processing starts it won't ramp from
+    /// a stale value.
+    pub fn set_normalized_value_by_hash_unsmoothed(
+        &self,
+        hash: u32,
+        normalized_value: f32,
+        source: ParamChangeSource,
+    ) -> tresult {
+        let result = self.set_normalized_value_by_hash(hash, normalized_value, None, source);
+        if let (Some(param_ptr), Some(buffer_config)) =
+            (self.param_by_hash.get(&hash), self.plugin_buffer_config())
+        {
+            unsafe { param_ptr.update_smoother(buffer_config.sample_rate, true) };
+        }
+
+        result
+    }
+
+    /// Make sure the event buffers used during processing have enough capacity so they don't need
+    /// to be reallocated on the audio thread. This is done when the plugin gets activated instead
+    /// of when the wrapper is created. Does not allocate if the buffers are already large enough.
+    pub fn reserve_event_buffers(&self) {
+        let mut input_events = self.input_events.borrow_mut();
+        input_events.reserve(NOTE_EVENTS_CAPACITY.saturating_sub(input_events.len()));
+        let mut output_events = self.output_events.borrow_mut();
+        output_events.reserve(NOTE_EVENTS_CAPACITY.saturating_sub(output_events.len()));
+        let mut process_events = self.process_events.borrow_mut();
+        process_events.reserve(PROCESS_EVENTS_CAPACITY.saturating_sub(process_events.len()));
+        let mut automation_split_points = self.automation_split_points.borrow_mut();
+        automation_split_points.reserve(
+            (P::MAX_AUTOMATION_SPLITS as usize)
+                .min(PROCESS_EVENTS_CAPACITY)
+                .saturating_sub(automation_split_points.len()),
+        );
+        let mut pending_param_batch = self.pending_param_batch.lock();
+        pending_param_batch
+            .reserve(CONTROLLER_PARAM_CHANGES_CAPACITY.saturating_sub(pending_param_batch.len()));
+    }
+
+    /// Apply a batch of parameter changes made from the GUI. If the plugin is currently processing
+    /// audio, then all of these changes are applied together at the start of the next process call.
+    /// Otherwise they are applied immediately.
+    pub fn set_normalized_values_batched(&self, changes: &[(u32, f32)]) {
+        if self.is_processing() {
+            // The changes are recorded now so the host echoing them back doesn't count as
+            // automation
+            for &(hash, normalized_value) in changes {
+                if let Some(param_ptr) = self.param_by_hash.get(&hash) {
+                    self.param_changes.record(
+                        *param_ptr,
+                        ParamChangeSource::GuiGesture,
+                        normalized_value,
+                    );
+                }
+            }
+            self.pending_param_batch.lock().extend_from_slice(changes);
+        } else {
+            let sample_rate = self.plugin_buffer_config().map(|c| c.sample_rate);
+            for &(hash, normalized_value) in changes {
+                self.set_normalized_value_by_hash(
+                    hash,
+                    normalized_value,
+                    sample_rate,
+                    ParamChangeSource::GuiGesture,
+                );
+            }
+            self.notify_param_values_changed();
+        }
+    }
+
     /// Get the plugin's state object, may be called by the plugin's GUI as part of its own preset
     /// management. The wrapper doesn't use these functions and serializes and deserializes directly
     /// the JSON in the relevant plugin API methods instead.
@@ -484,19 +922,31 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     /// prevent corrupting data and changing parameters during processing the actual state is only
     /// updated at the end of the audio processing cycle.
     pub fn set_state_object(&self, mut state: PluginState) {
+        nih_debug_assert_not_audio_thread!();
+
         // Use a loop and timeouts to handle the super rare edge case when this function gets called
         // between a process call and the host disabling the plugin
         loop {
-            if self.is_processing.load(Ordering::SeqCst) {
+            // See `is_poisoned`, the plugin instance needs to be reloaded by the host. The audio
+            // thread also stops picking up new state once this is set.
+            if self.is_poisoned.load(Ordering::SeqCst) {
+                nih_error!("The plugin panicked earlier, ignoring the state update");
+                return;
+            }
+
+            if self.is_processing() {
                 // If the plugin is currently processing audio, then we'll perform the restore
                 // operation at the end of the audio call. This involves sending the state to the
                 // audio thread, having the audio thread handle the state restore at the very end of
                 // the process function, and then sending the state back to this thread so it can be
                 // deallocated without blocking the audio thread.
-                match self
+                self.updated_state_pending.store(true, Ordering::SeqCst);
+                let result = self
                     .updated_state_sender
-                    .send_timeout(state, Duration::from_secs(1))
-                {
+                    .send_timeout(state, Duration::from_secs(1));
+                self.updated_state_pending.store(false, Ordering::SeqCst);
+
+                match result {
                     Ok(_) => {
                         // As mentioned above, the state object will be passed back to this thread
                         // so we can deallocate it without blocking.
@@ -516,23 +966,46 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             } else {
                 // Otherwise we'll set the state right here and now, since this function should be
                 // called from a GUI thread
-                unsafe {
+                let _state_load_lock = self.state_load_lock.lock();
+                let _alloc_phase = self.alloc_stats.enter(AllocPhase::StateLoad);
+                let restored = self.catch_plugin_panic("loading state", || unsafe {
                     state::deserialize_object::<P>(
                         &mut state,
                         self.params.clone(),
-                        state::make_params_getter(&self.param_by_hash, &self.param_id_to_hash),
-                        self.current_buffer_config.load().as_ref(),
-                    );
+                        state::make_params_getter(
+                            &self.param_by_hash,
+                            &self.param_id_to_hash,
+                            &self.legacy_param_id_to_hash,
+                        ),
+                        self.plugin_buffer_config().as_ref(),
+                        false,
+                        &self.param_value_validator,
+                    )
+                });
+                if restored.is_none() {
+                    return;
                 }
 
+                self.state_restored.mark();
+                self.param_changes.record_all(ParamChangeSource::Internal);
                 self.notify_param_values_changed();
-                let bus_config = self.current_bus_config.load();
-                if let Some(buffer_config) = self.current_buffer_config.load() {
+                let bus_config = self.plugin_bus_config.load();
+                if let Some(buffer_config) = self.plugin_buffer_config() {
                     // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
                     let mut init_context = self.make_init_context();
                     let mut plugin = self.plugin.lock();
-                    plugin.initialize(&bus_config, &buffer_config, &mut init_context);
-                    process_wrapper(|| plugin.reset());
+                    self.catch_plugin_panic("loading state", || {
+                        self.state_restored.notify(&mut *plugin);
+                        if let Err(err) =
+                            plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+                        {
+                            nih_error!(
+                                "Failed to reinitialize the plugin after loading state: {}",
+                                err
+                            );
+                        }
+                        process_wrapper(|| plugin.reset());
+                    });
                 }
 
                 break;
@@ -552,6 +1025,12 @@ impl<P: Vst3Plugin> WrapperInner<P> {
     }
 
     pub fn set_latency_samples(&self, samples: u32) {
+        // The plugin's latency is in oversampled samples if the wrapper oversamples the plugin
+        self.update_latency(self.oversampling.set_plugin_latency(samples));
+    }
+
+    /// Set the latency reported to the host, in samples at the host's sample rate.
+    pub fn update_latency(&self, samples: u32) {
         // Only trigger a restart if it's actually needed
         let old_latency = self.current_latency.swap(samples, Ordering::SeqCst);
         if old_latency != samples {
@@ -560,27 +1039,392 @@ impl<P: Vst3Plugin> WrapperInner<P> {
             nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
         }
     }
+
+    /// Tell the host that the plugin's state has changed, unless it has already been told so since it
+    /// last saved or loaded the plugin's state. Must be called from the GUI thread.
+    pub fn mark_state_dirty(&self) {
+        if self.state_dirty.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Hosts that don't implement `IComponentHandler2` don't track unsaved changes
+        if let Some(handler) = &*self.component_handler2.borrow() {
+            unsafe { handler.set_dirty(1) };
+        }
+    }
+
+    /// Keep track of whether the host's main channel counts match the negotiated bus
+    /// configuration. When a mismatch starts, this returns `true` and the host is asked to
+    /// renegotiate the IO configuration. Further process calls with the same mismatch return
+    /// `false` so the mismatch is only reported once.
+    pub fn set_host_channel_mismatch(&self, is_mismatched: bool) -> bool {
+        let was_mismatched = self
+            .host_channel_mismatch
+            .swap(is_mismatched, Ordering::SeqCst);
+        if is_mismatched && !was_mismatched {
+            let task_posted =
+                self.schedule_gui(Task::TriggerRestart(RestartFlags::kIoChanged as i32));
+            nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+
+            true
+        } else {
+            false
+        }
+    }
 }
 
 impl<P: Vst3Plugin> MainThreadExecutor<Task<P>> for WrapperInner<P> {
-    fn execute(&self, task: Task<P>, is_gui_thread: bool) {
+    fn execute(&self, task: Task<P>, _is_gui_thread: bool) {
         // This function is always called from the main thread
         match task {
-            Task::PluginTask(task) => (self.task_executor.lock())(task),
+            Task::PluginTask(task) => {
+                let _alloc_phase = self.alloc_stats.enter(AllocPhase::BackgroundTask);
+                self.catch_plugin_panic("running a background task", || {
+                    (self.task_executor.lock())(task)
+                });
+            }
             Task::TriggerRestart(flags) => match &*self.component_handler.borrow() {
                 Some(handler) => unsafe {
-                    nih_debug_assert!(is_gui_thread);
+                    nih_debug_assert_gui_thread!();
                     handler.restart_component(flags);
                 },
                 None => nih_debug_assert_failure!("Component handler not yet set"),
             },
             Task::RequestResize => match &*self.plug_view.read() {
                 Some(plug_view) => unsafe {
-                    nih_debug_assert!(is_gui_thread);
+                    nih_debug_assert_gui_thread!();
                     plug_view.request_resize();
                 },
                 None => nih_debug_assert_failure!("Can't resize a closed editor"),
             },
+            Task::NotifyPluginPanicked => {
+                if let Some(editor) = self.editor.borrow().as_ref() {
+                    match editor.try_lock() {
+                        Some(editor) => editor.plugin_panicked(),
+                        None => nih_debug_assert_failure!(
+                            "The editor was locked when sending a panic notification, ignoring"
+                        ),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::thread;
+    use vst3_sys::vst::IEditController;
+
+    use super::*;
+    use crate::context::process::ProcessContext;
+    use crate::params::range::FloatRange;
+    use crate::params::smoothing::SmoothingStyle;
+    use crate::params::{FloatParam, Param};
+    use crate::plugin::AuxiliaryBuffers;
+    use crate::wrapper::state::ParamValue;
+    use crate::wrapper::vst3::tests::fixture::{
+        activate, deactivate, process_block, process_block_with, Fixture, TestParameterChanges,
+        SAMPLE_RATE,
+    };
+    use crate::wrapper::vst3::wrapper::Wrapper;
+    use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+    /// Outputs its smoothed gain parameter's value as a DC signal, and smooths the gain when
+    /// loading state.
+    #[derive(Default)]
+    struct SmoothedDcPlugin {
+        params: Arc<GainParams>,
+    }
+
+    struct GainParams {
+        gain: FloatParam,
+    }
+
+    impl Default for GainParams {
+        fn default() -> Self {
+            Self {
+                gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                    .with_smoother(SmoothingStyle::Linear(1.0)),
+            }
+        }
+    }
+
+    unsafe impl Params for GainParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+        }
+    }
+
+    impl Plugin for SmoothedDcPlugin {
+        const NAME: &'static str = "Smoothed DC Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        const STATE_LOAD_SMOOTHING_MS: f32 = 20.0;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            for channel_samples in buffer.iter_samples() {
+                let gain = self.params.gain.smoothed.next();
+                for sample in channel_samples {
+                    *sample = gain;
+                }
+            }
+
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for SmoothedDcPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugSmoothDc0";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// Has a gain parameter that used to be called `amp`.
+    #[derive(Default)]
+    struct LegacyIdPlugin {
+        params: Arc<LegacyIdParams>,
+    }
+
+    #[derive(Default)]
+    struct LegacyIdParams {
+        gain: GainParams,
+    }
+
+    unsafe impl Params for LegacyIdParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            self.gain.param_map()
+        }
+
+        fn legacy_param_ids(&self) -> Vec<(String, String)> {
+            vec![
+                (String::from("amp"), String::from("gain")),
+                // This conflicts with the parameter's current ID and should be ignored
+                (String::from("gain"), String::from("gain")),
+            ]
+        }
+    }
+
+    impl Plugin for LegacyIdPlugin {
+        const NAME: &'static str = "Legacy ID Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for LegacyIdPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugLegacyIds";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// Loading state from the editor while the plugin is processing audio should ramp to the new
+    /// values over `STATE_LOAD_SMOOTHING_MS` instead of jumping there.
+    #[test]
+    fn state_load_is_smoothed() {
+        unsafe {
+            let wrapper = Fixture::<SmoothedDcPlugin>::new()
+                .param("gain", 0.0)
+                .activate();
+            let mut output = Vec::new();
+            process_block(&wrapper, &mut output);
+
+            // This blocks until the audio thread has picked up the new state
+            let inner = wrapper.inner.clone();
+            let state_loader = thread::spawn(move || {
+                inner.set_state_object(PluginState {
+                    version: String::from(SmoothedDcPlugin::VERSION),
+                    params: [(String::from("gain"), ParamValue::F32(1.0))].into(),
+                    fields: Default::default(),
+                    instance_id: None,
+                })
+            });
+            while !state_loader.is_finished() {
+                process_block(&wrapper, &mut output);
+                thread::sleep(Duration::from_millis(1));
+            }
+            state_loader.join().unwrap();
+
+            // The ramp takes 882 samples, so this is more than enough to also reach the target
+            for _ in 0..16 {
+                process_block(&wrapper, &mut output);
+            }
+            deactivate(&wrapper);
+
+            let ramp_steps =
+                (SAMPLE_RATE * SmoothedDcPlugin::STATE_LOAD_SMOOTHING_MS / 1000.0).round();
+            // There's some leeway for rounding errors accumulated during the ramp
+            let max_step_size = (1.0 / ramp_steps) + 1e-4;
+            for (i, window) in output.windows(2).enumerate() {
+                let step_size = (window[1] - window[0]).abs();
+                assert!(
+                    step_size <= max_step_size,
+                    "Discontinuity of {step_size} at sample {i}"
+                );
+            }
+            assert_eq!(output[0], 0.0);
+            assert_eq!(*output.last().unwrap(), 1.0);
+        }
+    }
+
+    /// The audio thread should never wait for another thread that is using the plugin. The block
+    /// is silent instead, and processing continues as usual afterwards.
+    #[test]
+    fn contended_plugin_outputs_silence() {
+        unsafe {
+            let wrapper = Fixture::<SmoothedDcPlugin>::new()
+                .param("gain", 0.5)
+                .activate();
+            let mut output = Vec::new();
+            process_block(&wrapper, &mut output);
+            assert!(output.iter().all(|sample| *sample == 0.5));
+
+            {
+                let _plugin = wrapper.inner.plugin.lock();
+                output.clear();
+                process_block(&wrapper, &mut output);
+                process_block(&wrapper, &mut output);
+                assert!(output.iter().all(|sample| *sample == 0.0));
+            }
+            assert_eq!(
+                wrapper
+                    .inner
+                    .num_contended_process_blocks
+                    .load(Ordering::SeqCst),
+                2
+            );
+
+            output.clear();
+            process_block(&wrapper, &mut output);
+            assert!(output.iter().all(|sample| *sample == 0.5));
+
+            deactivate(&wrapper);
+        }
+    }
+
+    /// Host automation and state using a parameter's old ID should still reach the parameter, but
+    /// only the current ID should ever be shown to the host.
+    #[test]
+    fn legacy_param_ids() {
+        unsafe {
+            let wrapper = Wrapper::<LegacyIdPlugin>::new();
+            let gain_hash = hash_param_id("gain");
+            let legacy_hash = hash_param_id("amp");
+            let gain_ptr = wrapper.inner.param_by_hash[&gain_hash];
+            assert_eq!(
+                wrapper.inner.param_by_hash.get(&legacy_hash),
+                Some(&gain_ptr)
+            );
+            assert!(!wrapper.inner.param_hashes.contains(&legacy_hash));
+            assert_eq!(wrapper.inner.legacy_param_id_to_hash.len(), 1);
+
+            assert_eq!(wrapper.set_param_normalized(legacy_hash, 0.25), kResultOk);
+            assert_eq!(gain_ptr.unmodulated_normalized_value(), 0.25);
+
+            wrapper.inner.set_state_object(PluginState {
+                version: String::from(LegacyIdPlugin::VERSION),
+                params: [(String::from("amp"), ParamValue::F32(0.75))].into(),
+                fields: Default::default(),
+                instance_id: None,
+            });
+            assert_eq!(gain_ptr.unmodulated_normalized_value(), 0.75);
+
+            // New state is only ever saved under the current ID
+            let state = wrapper.inner.get_state_object();
+            assert!(state.params.contains_key("gain"));
+            assert!(!state.params.contains_key("amp"));
+        }
+    }
+
+    /// Automation from the host and changes made from the GUI should be tracked as different
+    /// sources, and the GUI's changes being echoed back by the host should not count as
+    /// automation.
+    #[test]
+    fn param_change_sources() {
+        let gain_hash = hash_param_id("gain");
+        unsafe {
+            let wrapper = Fixture::<SmoothedDcPlugin>::new()
+                .param("gain", 0.0)
+                .activate();
+            let gain_ptr = wrapper.inner.param_by_hash[&gain_hash];
+            let mut output = Vec::new();
+
+            let param_changes = TestParameterChanges::new(&[(gain_hash, 0.5)]);
+            process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+            let change = wrapper.inner.param_changes.last_change(gain_ptr).unwrap();
+            assert_eq!(change.source, ParamChangeSource::HostAutomation);
+            assert_eq!(change.normalized_value, 0.5);
+
+            wrapper
+                .inner
+                .set_normalized_values_batched(&[(gain_hash, 0.25)]);
+            let param_changes = TestParameterChanges::new(&[(gain_hash, 0.25)]);
+            output.clear();
+            process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+            assert_eq!(*output.last().unwrap(), 0.25);
+            let change = wrapper.inner.param_changes.last_change(gain_ptr).unwrap();
+            assert_eq!(change.source, ParamChangeSource::GuiGesture);
+            assert_eq!(change.normalized_value, 0.25);
+
+            deactivate(&wrapper);
+        }
+    }
+
+    /// Creating a wrapper should not preallocate the event buffers. Hosts create instances on the
+    /// main thread while scanning plugins, so that only happens when the plugin gets activated.
+    #[test]
+    fn event_buffers_allocated_on_activation() {
+        unsafe {
+            let wrapper = Wrapper::<SmoothedDcPlugin>::new();
+            assert_eq!(wrapper.inner.input_events.borrow().capacity(), 0);
+            assert_eq!(wrapper.inner.output_events.borrow().capacity(), 0);
+            assert_eq!(wrapper.inner.process_events.borrow().capacity(), 0);
+            assert_eq!(wrapper.inner.pending_param_batch.lock().capacity(), 0);
+
+            activate(&wrapper);
+            assert!(wrapper.inner.input_events.borrow().capacity() >= NOTE_EVENTS_CAPACITY);
+            assert!(wrapper.inner.output_events.borrow().capacity() >= NOTE_EVENTS_CAPACITY);
+            assert!(wrapper.inner.process_events.borrow().capacity() >= PROCESS_EVENTS_CAPACITY);
+            assert!(
+                wrapper.inner.pending_param_batch.lock().capacity()
+                    >= CONTROLLER_PARAM_CHANGES_CAPACITY
+            );
+            deactivate(&wrapper);
         }
     }
 }
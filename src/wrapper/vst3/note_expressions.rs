@@ -3,7 +3,7 @@
 
 use vst3_sys::vst::{NoteExpressionValueEvent, NoteOnEvent};
 
-use crate::midi::NoteEvent;
+use crate::midi::{NoteEvent, NoteExpressions};
 
 type MidiNote = u8;
 type MidiChannel = u8;
@@ -24,38 +24,51 @@ pub const VIBRATO_EXPRESSION_ID: u32 = 3;
 pub const EXPRESSION_EXPRESSION_ID: u32 = 4;
 /// `kBrightnessTypeID`
 pub const BRIGHTNESS_EXPRESSION_ID: u32 = 5;
+/// `kTextTypeID`. Text expressions are sent as `kNoteExpressionTextEvent`s and are not supported.
+pub const TEXT_EXPRESSION_ID: u32 = 6;
+/// `kPhonemeTypeID`. Phoneme expressions are sent as `kNoteExpressionTextEvent`s and are not
+/// supported.
+pub const PHONEME_EXPRESSION_ID: u32 = 7;
 
 /// The note expressions we support. It's completely undocumented, but apparently VST3 plugins need
 /// to specifically define a custom note expression for the predefined note expressions for them to
-/// work.
+/// work. Only the ones enabled in the plugin's
+/// [`SUPPORTED_NOTE_EXPRESSIONS`][crate::prelude::Plugin::SUPPORTED_NOTE_EXPRESSIONS] are declared
+/// to the host, see [`supported_note_expressions()`].
 pub const KNOWN_NOTE_EXPRESSIONS: [NoteExpressionInfo; 6] = [
     NoteExpressionInfo {
         type_id: VOLUME_EXPRESSION_ID,
+        expression: NoteExpressions::VOLUME,
         title: "Volume",
         unit: "dB",
     },
     NoteExpressionInfo {
         type_id: PAN_EXPRESSION_ID,
+        expression: NoteExpressions::PAN,
         title: "Pan",
         unit: "",
     },
     NoteExpressionInfo {
         type_id: TUNING_EXPRESSION_ID,
+        expression: NoteExpressions::TUNING,
         title: "Tuning",
         unit: "semitones",
     },
     NoteExpressionInfo {
         type_id: VIBRATO_EXPRESSION_ID,
+        expression: NoteExpressions::VIBRATO,
         title: "Vibrato",
         unit: "",
     },
     NoteExpressionInfo {
         type_id: EXPRESSION_EXPRESSION_ID,
+        expression: NoteExpressions::EXPRESSION,
         title: "Expression",
         unit: "",
     },
     NoteExpressionInfo {
         type_id: BRIGHTNESS_EXPRESSION_ID,
+        expression: NoteExpressions::BRIGHTNESS,
         title: "Brightness",
         unit: "",
     },
@@ -83,12 +96,34 @@ pub struct NoteExpressionController {
 pub struct NoteExpressionInfo {
     /// The predefined VST3 note expression type ID for this note expression.
     pub type_id: u32,
+    /// The flag in [`NoteExpressions`] that enables this note expression.
+    pub expression: NoteExpressions,
     /// The title for the note expression. Also used for the short title because why not.
     pub title: &'static str,
     /// The unit for the note expression.
     pub unit: &'static str,
 }
 
+/// The subset of [`KNOWN_NOTE_EXPRESSIONS`] that is enabled in `supported`, in the same order. The
+/// `INoteExpressionController` indices refer to this iterator.
+pub fn supported_note_expressions(
+    supported: NoteExpressions,
+) -> impl Iterator<Item = &'static NoteExpressionInfo> {
+    let expressions: &'static [NoteExpressionInfo] = &KNOWN_NOTE_EXPRESSIONS;
+    expressions
+        .iter()
+        .filter(move |info| supported.contains(info.expression))
+}
+
+/// The [`NoteExpressions`] flag for a predefined VST3 note expression type ID, if we know about
+/// that note expression.
+pub fn note_expression_for_type_id(type_id: u32) -> Option<NoteExpressions> {
+    KNOWN_NOTE_EXPRESSIONS
+        .iter()
+        .find(|info| info.type_id == type_id)
+        .map(|info| info.expression)
+}
+
 impl NoteExpressionController {
     /// Register the note ID from a note on event so it can later be retrieved when handling a note
     /// expression value event.
@@ -145,19 +180,19 @@ impl NoteExpressionController {
                 note,
                 vibrato: event.value as f32,
             }),
-            EXPRESSION_EXPRESSION_ID => Some(NoteEvent::PolyBrightness {
+            EXPRESSION_EXPRESSION_ID => Some(NoteEvent::PolyExpression {
                 timing,
                 voice_id: Some(note_id),
                 channel,
                 note,
-                brightness: event.value as f32,
+                expression: event.value as f32,
             }),
-            BRIGHTNESS_EXPRESSION_ID => Some(NoteEvent::PolyExpression {
+            BRIGHTNESS_EXPRESSION_ID => Some(NoteEvent::PolyBrightness {
                 timing,
                 voice_id: Some(note_id),
                 channel,
                 note,
-                expression: event.value as f32,
+                brightness: event.value as f32,
             }),
             _ => None,
         }
@@ -205,3 +240,101 @@ impl NoteExpressionController {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(note_id: i32, channel: i16, pitch: i16) -> NoteOnEvent {
+        NoteOnEvent {
+            channel,
+            pitch,
+            tuning: 0.0,
+            velocity: 1.0,
+            length: 0,
+            note_id,
+        }
+    }
+
+    #[test]
+    fn note_id_association() {
+        let mut controller = NoteExpressionController::default();
+        controller.register_note(&note_on(10, 0, 60));
+        controller.register_note(&note_on(11, 3, 64));
+
+        let event = NoteExpressionValueEvent {
+            type_id: TUNING_EXPRESSION_ID,
+            note_id: 11,
+            value: 0.75,
+        };
+        assert_eq!(
+            controller.translate_event(5, &event),
+            Some(NoteEvent::PolyTuning {
+                timing: 5,
+                voice_id: Some(11),
+                channel: 3,
+                note: 64,
+                tuning: 60.0,
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_note_id() {
+        let mut controller = NoteExpressionController::default();
+        controller.register_note(&note_on(10, 0, 60));
+
+        let event = NoteExpressionValueEvent {
+            type_id: VOLUME_EXPRESSION_ID,
+            note_id: 42,
+            value: 0.25,
+        };
+        assert_eq!(controller.translate_event(0, &event), None);
+    }
+
+    #[test]
+    fn supported_subset() {
+        let supported = NoteExpressions::TUNING | NoteExpressions::BRIGHTNESS;
+        let type_ids: Vec<u32> = supported_note_expressions(supported)
+            .map(|info| info.type_id)
+            .collect();
+        assert_eq!(type_ids, [TUNING_EXPRESSION_ID, BRIGHTNESS_EXPRESSION_ID]);
+
+        assert_eq!(
+            supported_note_expressions(NoteExpressions::all()).count(),
+            6
+        );
+        assert_eq!(
+            supported_note_expressions(NoteExpressions::empty()).count(),
+            0
+        );
+        assert_eq!(
+            note_expression_for_type_id(PAN_EXPRESSION_ID),
+            Some(NoteExpressions::PAN)
+        );
+        assert_eq!(note_expression_for_type_id(TEXT_EXPRESSION_ID), None);
+    }
+
+    #[test]
+    fn translate_round_trip() {
+        let mut controller = NoteExpressionController::default();
+        controller.register_note(&note_on(10, 1, 60));
+
+        for info in KNOWN_NOTE_EXPRESSIONS {
+            let event = NoteExpressionValueEvent {
+                type_id: info.type_id,
+                note_id: 10,
+                value: 0.5,
+            };
+            let translated = controller
+                .translate_event(0, &event)
+                .unwrap_or_else(|| panic!("Could not translate the {} expression", info.title));
+            let round_tripped =
+                NoteExpressionController::translate_event_reverse(10, &translated).unwrap();
+
+            assert_eq!(round_tripped.type_id, info.type_id, "{}", info.title);
+            assert_eq!(round_tripped.note_id, 10);
+            assert_eq!(round_tripped.value, 0.5);
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Limiting the number of block splits caused by dense parameter automation. Some hosts send
+//! hundreds of points per parameter per block when the user draws dense automation or when a
+//! control surface floods the host with values. With sample accurate automation every one of
+//! those points would otherwise result in its own tiny sub-block.
+
+use super::inner::ProcessEvent;
+use crate::params::changes::ParamChangeSource;
+use crate::util::permit_alloc;
+
+/// Merge the parameter changes in `events` so the block is split at most `max_splits` times because
+/// of parameter changes, and so every sub-block caused by those splits is at least `min_length`
+/// samples long. `events` needs to be sorted by timing. Note events are left untouched.
+///
+/// The sample offsets the block is still split at are stored in `split_points`, which should have
+/// room for `max_splits` offsets so this doesn't allocate. Every parameter change is moved to the
+/// start of the sub-block it falls in, and only the last change for each parameter within a
+/// sub-block is kept. Every sub-block thus uses the values the parameters would have had at its
+/// last sample, and the values at the end of the block are always exact.
+///
+/// Changes for which `is_exact` returns `true` are preferred when picking the split points. Those
+/// are not subject to `min_length`, and they're only merged when there are more than
+/// `max_splits` of them. Returns the number of parameter changes that were merged into others.
+pub fn coalesce_param_changes(
+    events: &mut Vec<ProcessEvent>,
+    split_points: &mut Vec<u32>,
+    num_samples: u32,
+    max_splits: u32,
+    min_length: u32,
+    is_exact: impl Fn(u32, ParamChangeSource) -> bool,
+) -> usize {
+    let max_splits = max_splits as usize;
+    let candidates = SplitCandidates {
+        events: events.as_slice(),
+        is_exact,
+        num_samples,
+        min_length: min_length.max(1),
+    };
+
+    // Most blocks don't need to be coalesced at all, so that's checked first. Two exact split
+    // points may be closer together than `min_length`.
+    let mut num_splits = 0;
+    let mut too_short = false;
+    let mut previous = (0, true);
+    candidates.for_each(|timing, exact| {
+        num_splits += 1;
+        too_short |= timing - previous.0 < candidates.min_length && !(exact && previous.1);
+        previous = (timing, exact);
+    });
+    too_short |= !previous.1 && num_samples.saturating_sub(previous.0) < candidates.min_length;
+    if num_splits <= max_splits && !too_short {
+        return 0;
+    }
+
+    // The exact changes get to keep their split points if there are few enough of them. If there
+    // are too many, then they're spread out over the block instead.
+    split_points.clear();
+    let mut exact_spacing = 1;
+    if candidates.pick(split_points, exact_spacing, true, true) > max_splits {
+        exact_spacing = evenly_spaced(num_samples, max_splits);
+    }
+    candidates.pick(split_points, exact_spacing, true, false);
+
+    // The remaining budget is used for the other changes, which also need to stay at least
+    // `min_length` samples away from every other split point
+    let budget = max_splits - split_points.len();
+    if budget > 0 {
+        let mut spacing = candidates.min_length;
+        if candidates.pick(split_points, spacing, false, true) > budget {
+            spacing = evenly_spaced(num_samples, budget).max(candidates.min_length);
+        }
+        candidates.pick(split_points, spacing, false, false);
+        split_points.sort_unstable();
+    }
+
+    // Every change is moved to the start of its sub-block. Sorting again keeps the parameter
+    // changes in front of the note events at the same sample offset.
+    for event in events.iter_mut() {
+        if let ProcessEvent::ParameterChange { timing, .. } = event {
+            let idx = split_points.partition_point(|&split| split <= *timing);
+            *timing = if idx == 0 { 0 } else { split_points[idx - 1] };
+        }
+    }
+    // FIXME: The stable sort may allocate, see the sort in the process function
+    permit_alloc(|| {
+        events.sort_by_key(|event| match event {
+            ProcessEvent::ParameterChange { timing, .. } => (*timing, false),
+            ProcessEvent::NoteEvent { timing, .. } => (*timing, true),
+        })
+    });
+
+    // Only the last change for every parameter within a sub-block is kept
+    let num_events = events.len();
+    let mut write_idx = 0;
+    for read_idx in 0..num_events {
+        let superseded = match events[read_idx] {
+            ProcessEvent::ParameterChange { timing, hash, .. } => events[read_idx + 1..]
+                .iter()
+                .map_while(|event| match *event {
+                    ProcessEvent::ParameterChange {
+                        timing: later_timing,
+                        hash: later_hash,
+                        ..
+                    } if later_timing == timing => Some(later_hash),
+                    _ => None,
+                })
+                .any(|later_hash| later_hash == hash),
+            ProcessEvent::NoteEvent { .. } => false,
+        };
+
+        if !superseded {
+            events.swap(write_idx, read_idx);
+            write_idx += 1;
+        }
+    }
+    events.truncate(write_idx);
+
+    num_events - write_idx
+}
+
+/// The sample offsets parameter changes would split a block at.
+struct SplitCandidates<'a, F> {
+    events: &'a [ProcessEvent],
+    is_exact: F,
+    num_samples: u32,
+    min_length: u32,
+}
+
+impl<F: Fn(u32, ParamChangeSource) -> bool> SplitCandidates<'_, F> {
+    /// Call `f` with every distinct sample offset that would split the block because of a parameter
+    /// change, along with whether any of the changes at that offset should be kept exact.
+    fn for_each(&self, mut f: impl FnMut(u32, bool)) {
+        let mut current: Option<(u32, bool)> = None;
+        for event in self.events {
+            if let ProcessEvent::ParameterChange {
+                timing,
+                hash,
+                source,
+                ..
+            } = *event
+            {
+                if timing == 0 {
+                    continue;
+                }
+
+                let exact = (self.is_exact)(hash, source);
+                match &mut current {
+                    Some((current_timing, current_exact)) if *current_timing == timing => {
+                        *current_exact |= exact
+                    }
+                    _ => {
+                        if let Some((current_timing, current_exact)) = current {
+                            f(current_timing, current_exact);
+                        }
+                        current = Some((timing, exact));
+                    }
+                }
+            }
+        }
+
+        if let Some((current_timing, current_exact)) = current {
+            f(current_timing, current_exact);
+        }
+    }
+
+    /// Greedily pick split points from left to right and add them to `split_points`, or only count
+    /// them if `dry_run` is set. Returns the number of picked split points. Only the exact split
+    /// points are considered when `exact` is set, and only the other split points are considered
+    /// otherwise. Picked split points are at least `spacing` samples apart from the previously
+    /// picked split point and from the start of the block. The other split points also need to be
+    /// at least `min_length` samples away from the end of the block and from the split points that
+    /// were already in `split_points`.
+    fn pick(&self, split_points: &mut Vec<u32>, spacing: u32, exact: bool, dry_run: bool) -> usize {
+        let min_length = if exact { 1 } else { self.min_length };
+        let num_fixed = split_points.len();
+        let mut num_picked = 0;
+        let mut previous_pick = 0;
+        self.for_each(|timing, timing_exact| {
+            if timing_exact != exact
+                || timing < previous_pick + spacing
+                || timing + min_length > self.num_samples
+            {
+                return;
+            }
+
+            let fixed = &split_points[..num_fixed];
+            let fixed_idx = fixed.partition_point(|&split| split < timing);
+            let after_previous_fixed =
+                fixed_idx == 0 || timing - fixed[fixed_idx - 1] >= min_length;
+            let before_next_fixed = fixed
+                .get(fixed_idx)
+                .map_or(true, |&split| split - timing >= min_length);
+            if after_previous_fixed && before_next_fixed {
+                if !dry_run {
+                    split_points.push(timing);
+                }
+                num_picked += 1;
+                previous_pick = timing;
+            }
+        });
+
+        num_picked
+    }
+}
+
+/// The spacing between split points needed to fit at most `max_splits` of them in a block of
+/// `num_samples` samples.
+fn evenly_spaced(num_samples: u32, max_splits: usize) -> u32 {
+    let num_sub_blocks = max_splits as u32 + 1;
+    ((num_samples + num_sub_blocks - 1) / num_sub_blocks).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi::NoteEvent;
+
+    const NUM_SAMPLES: u32 = 512;
+    const MAX_SPLITS: u32 = 32;
+    const MIN_LENGTH: u32 = 16;
+    /// Changes to this parameter should be kept exact.
+    const EXACT_HASH: u32 = 3;
+
+    fn param(timing: u32, hash: u32, normalized_value: f32) -> ProcessEvent {
+        ProcessEvent::ParameterChange {
+            timing,
+            hash,
+            normalized_value,
+            source: ParamChangeSource::HostAutomation,
+        }
+    }
+
+    fn note(timing: u32) -> ProcessEvent {
+        ProcessEvent::NoteEvent {
+            timing,
+            event: NoteEvent::NoteOn {
+                timing,
+                voice_id: None,
+                channel: 0,
+                note: 60,
+                velocity: 1.0,
+            },
+        }
+    }
+
+    fn coalesce(events: &mut Vec<ProcessEvent>) -> usize {
+        let mut split_points = Vec::with_capacity(MAX_SPLITS as usize);
+        coalesce_param_changes(
+            events,
+            &mut split_points,
+            NUM_SAMPLES,
+            MAX_SPLITS,
+            MIN_LENGTH,
+            |hash, _| hash == EXACT_HASH,
+        )
+    }
+
+    /// The order the wrapper processes events in.
+    fn sort_key(event: &ProcessEvent) -> (u32, bool) {
+        match *event {
+            ProcessEvent::ParameterChange { timing, .. } => (timing, false),
+            ProcessEvent::NoteEvent { timing, .. } => (timing, true),
+        }
+    }
+
+    /// The value every parameter has at `sample` after applying the changes in `events` in order.
+    fn values_at(events: &[ProcessEvent], sample: u32) -> [Option<f32>; 4] {
+        let mut values = [None; 4];
+        for event in events {
+            if let ProcessEvent::ParameterChange {
+                timing,
+                hash,
+                normalized_value,
+                ..
+            } = *event
+            {
+                if timing <= sample {
+                    values[hash as usize] = Some(normalized_value);
+                }
+            }
+        }
+
+        values
+    }
+
+    /// The sub-blocks the wrapper would process for `events`.
+    fn sub_blocks(events: &[ProcessEvent]) -> Vec<(u32, u32)> {
+        let mut boundaries = vec![0];
+        for event in events {
+            if let ProcessEvent::ParameterChange { timing, .. } = *event {
+                if timing != *boundaries.last().unwrap() {
+                    boundaries.push(timing);
+                }
+            }
+        }
+        boundaries.push(NUM_SAMPLES);
+
+        boundaries
+            .windows(2)
+            .map(|window| (window[0], window[1]))
+            .collect()
+    }
+
+    #[test]
+    fn sparse_changes_are_untouched() {
+        let mut events = vec![param(0, 0, 0.1), param(16, 1, 0.2), param(496, 0, 0.3)];
+        let original = events.clone();
+        assert_eq!(coalesce(&mut events), 0);
+        assert_eq!(events, original);
+    }
+
+    /// 1000 points spread over three parameters, with every sub-block using the values the
+    /// parameters had at its last sample.
+    #[test]
+    fn dense_changes() {
+        let mut events: Vec<_> = (0..1000u32)
+            .map(|i| param(i * NUM_SAMPLES / 1000, i % 3, (i % 7) as f32 / 7.0))
+            .collect();
+        let original = events.clone();
+        let num_merged = coalesce(&mut events);
+        assert_eq!(num_merged, original.len() - events.len());
+        assert!(num_merged > 900);
+
+        let sub_blocks = sub_blocks(&events);
+        assert!(sub_blocks.len() <= MAX_SPLITS as usize + 1);
+        for &(start, end) in &sub_blocks {
+            assert!(end - start >= MIN_LENGTH, "{sub_blocks:?}");
+            assert_eq!(values_at(&events, start), values_at(&original, end - 1));
+        }
+        assert_eq!(
+            values_at(&events, NUM_SAMPLES),
+            values_at(&original, NUM_SAMPLES)
+        );
+    }
+
+    /// Changes to exact parameters keep their sample offsets, even when they're closer together
+    /// than the minimum sub-block length.
+    #[test]
+    fn exact_changes_are_kept() {
+        let mut events: Vec<_> = (0..500u32)
+            .map(|i| param(i, i % 2, (i % 5) as f32 / 5.0))
+            .collect();
+        for timing in [5, 10, 200, 501] {
+            events.push(param(timing, EXACT_HASH, timing as f32 / 1000.0));
+        }
+        events.sort_by_key(|event| sort_key(event).0);
+        let original = events.clone();
+        coalesce(&mut events);
+
+        let sub_blocks = sub_blocks(&events);
+        assert!(sub_blocks.len() <= MAX_SPLITS as usize + 1);
+        for timing in [5, 10, 200, 501] {
+            assert!(sub_blocks.iter().any(|&(start, _)| start == timing));
+            assert_eq!(
+                values_at(&events, timing)[EXACT_HASH as usize],
+                Some(timing as f32 / 1000.0)
+            );
+        }
+        for &(start, end) in &sub_blocks {
+            assert_eq!(values_at(&events, start), values_at(&original, end - 1));
+        }
+    }
+
+    /// Parameter changes that are moved to a note event's sample offset need to be processed
+    /// before that note event.
+    #[test]
+    fn note_events_stay_in_order() {
+        let mut events: Vec<_> = (0..100u32).map(|i| param(i * 5, 0, 0.5)).collect();
+        events.push(note(32));
+        events.push(note(511));
+        events.sort_by_key(|event| sort_key(event).0);
+        coalesce(&mut events);
+
+        assert_eq!(events.iter().filter(|event| **event == note(32)).count(), 1);
+        assert_eq!(events.last(), Some(&note(511)));
+        for window in events.windows(2) {
+            assert!(sort_key(&window[0]) <= sort_key(&window[1]), "{events:?}");
+        }
+    }
+}
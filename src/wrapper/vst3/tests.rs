@@ -0,0 +1,17 @@
+//! Tests for the VST3 wrapper as a whole, split up by area. The host side of these tests and the
+//! plugins shared between them live in [`fixture`]. The lower level wrapper structs are tested next
+//! to their code, and [`conformance`][super::conformance] drives the wrapper through the factory
+//! like a real host would.
+
+pub(super) mod fixture;
+
+#[cfg(all(debug_assertions, feature = "alloc_stats"))]
+mod alloc_stats;
+mod bypass;
+mod channels;
+mod events;
+mod lifecycle;
+mod panics;
+mod params;
+mod state;
+mod transport;
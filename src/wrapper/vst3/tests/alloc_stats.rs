@@ -0,0 +1,132 @@
+//! Tests for the `alloc_stats` feature. These need the counting allocator, so they're only
+//! compiled when the feature is enabled.
+
+use std::mem;
+use std::sync::Arc;
+use vst3_sys::base::kResultOk;
+
+use super::fixture::{activate, deactivate, load_state, save_state, NoParams, SAMPLE_RATE};
+use crate::buffer::Buffer;
+use crate::context::init::InitContext;
+use crate::context::process::ProcessContext;
+use crate::params::Params;
+use crate::plugin::{AuxiliaryBuffers, BufferConfig, BusConfig, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::alloc_stats::AllocPhase;
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// The length of [`BufferingPlugin`]'s buffer.
+const BUFFER_SECONDS: f32 = 1.0;
+
+/// Allocates a buffer for `BUFFER_SECONDS` of audio in `initialize()`.
+#[derive(Default)]
+struct BufferingPlugin {
+    buffer: Vec<f32>,
+}
+
+impl Plugin for BufferingPlugin {
+    const NAME: &'static str = "Buffering Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.buffer = vec![0.0; (buffer_config.sample_rate * BUFFER_SECONDS) as usize];
+        true
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for BufferingPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugBuffering";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Allocations should be attributed to the phase they were made in, and only to the
+/// instance that made them. The upper bound on the initialization phase catches
+/// regressions where the wrapper starts allocating more than the plugin itself during
+/// initialization.
+#[test]
+fn allocations_by_phase() {
+    let buffer_bytes = (SAMPLE_RATE * BUFFER_SECONDS) as usize * mem::size_of::<f32>();
+
+    unsafe {
+        let wrapper = Wrapper::<BufferingPlugin>::new();
+        let other_wrapper = Wrapper::<BufferingPlugin>::new();
+        for phase in [
+            AllocPhase::Initialize,
+            AllocPhase::StateLoad,
+            AllocPhase::EditorOpen,
+            AllocPhase::BackgroundTask,
+        ] {
+            assert_eq!(wrapper.inner.alloc_stats.counts(phase).count, 0);
+        }
+
+        activate(&*wrapper);
+        let initialize = wrapper.inner.alloc_stats.counts(AllocPhase::Initialize);
+        assert_eq!(initialize.count, 1);
+        assert!(initialize.bytes >= buffer_bytes);
+        assert!(
+            initialize.bytes <= buffer_bytes + 1024,
+            "{} bytes were allocated during initialization",
+            initialize.bytes
+        );
+        assert_eq!(
+            other_wrapper
+                .inner
+                .alloc_stats
+                .counts(AllocPhase::Initialize)
+                .count,
+            0
+        );
+
+        // Allocations outside of the wrapper's callbacks are not counted
+        let unrelated = vec![0u8; 4096];
+        drop(unrelated);
+        assert_eq!(
+            wrapper.inner.alloc_stats.counts(AllocPhase::Initialize),
+            initialize
+        );
+
+        // Reinitializing the plugin after loading state counts towards the state loading
+        let state = save_state(&*wrapper);
+        assert_eq!(load_state(&*wrapper, state), kResultOk);
+        assert!(
+            wrapper
+                .inner
+                .alloc_stats
+                .counts(AllocPhase::StateLoad)
+                .bytes
+                >= buffer_bytes
+        );
+        assert_eq!(
+            wrapper.inner.alloc_stats.counts(AllocPhase::Initialize),
+            initialize
+        );
+
+        deactivate(&wrapper);
+    }
+}
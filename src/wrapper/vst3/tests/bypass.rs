@@ -0,0 +1,238 @@
+//! Tests for the bypass parameter the wrapper adds for plugins that don't have their own.
+
+use std::mem;
+use std::sync::Arc;
+use vst3_sys::base::kResultOk;
+use vst3_sys::vst::{IAudioProcessor, ProcessModes};
+
+use super::fixture::{
+    deactivate, process_block, process_channels, DcPlugin, Fixture, HalfGainPlugin, NoParams,
+    BLOCK_SIZE, SAMPLE_RATE,
+};
+use crate::buffer::Buffer;
+use crate::context::init::InitContext;
+use crate::context::process::ProcessContext;
+use crate::params::changes::ParamChangeSource;
+use crate::params::Params;
+use crate::plugin::{AuxiliaryBuffers, BufferConfig, BusConfig, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::bypass::BYPASS_CROSSFADE_MS;
+use crate::wrapper::state::ParamValue;
+use crate::wrapper::util::{hash_param_id, BYPASS_PARAM_ID};
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// A mono plugin that halves its input and delays it by [`LatencyPlugin::LATENCY`] samples,
+/// and reports that delay as its latency.
+#[derive(Default)]
+struct LatencyPlugin {
+    delay_line: Vec<f32>,
+    delay_pos: usize,
+}
+
+impl LatencyPlugin {
+    const LATENCY: u32 = 100;
+}
+
+impl Plugin for LatencyPlugin {
+    const NAME: &'static str = "Latency Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 1;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        _buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        context.set_latency_samples(Self::LATENCY);
+        self.delay_line.resize(Self::LATENCY as usize, 0.0);
+        true
+    }
+
+    fn reset(&mut self) {
+        self.delay_line.fill(0.0);
+        self.delay_pos = 0;
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples {
+                *sample = mem::replace(&mut self.delay_line[self.delay_pos], *sample) * 0.5;
+                self.delay_pos = (self.delay_pos + 1) % self.delay_line.len();
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for LatencyPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugLatency00";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Plugins without their own bypass parameter get one from the wrapper, and the plugin is not
+/// processed once the bypass has been faded in.
+#[test]
+fn wrapper_bypass_param() {
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 0.5).activate();
+        let bypass_hash = hash_param_id(BYPASS_PARAM_ID);
+        assert!(wrapper.inner.param_by_hash.contains_key(&bypass_hash));
+        assert_eq!(
+            wrapper.inner.bypass_param_ptr,
+            wrapper.inner.param_by_hash.get(&bypass_hash).copied()
+        );
+
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+        assert!(output.iter().all(|sample| *sample == 0.5));
+
+        // The wrapper is processing, so this needs to bypass the controller's setter
+        assert_eq!(
+            wrapper.inner.set_normalized_value_by_hash(
+                bypass_hash,
+                1.0,
+                Some(SAMPLE_RATE),
+                ParamChangeSource::HostAutomation
+            ),
+            kResultOk
+        );
+        let crossfade_length = (SAMPLE_RATE * BYPASS_CROSSFADE_MS / 1000.0).round() as usize;
+        for _ in 0..=crossfade_length / BLOCK_SIZE {
+            process_block(&wrapper, &mut Vec::new());
+        }
+        output.clear();
+        process_block(&wrapper, &mut output);
+        assert!(
+            output.iter().all(|sample| *sample == -1.0),
+            "The plugin was processed while bypassed"
+        );
+
+        let state = wrapper.inner.get_state_object();
+        assert!(matches!(
+            state.params.get(BYPASS_PARAM_ID),
+            Some(ParamValue::Bool(true))
+        ));
+
+        deactivate(&wrapper);
+    }
+}
+
+/// Toggling the wrapper's bypass while processing in realtime should crossfade between the
+/// plugin's output and its input over `BYPASS_CROSSFADE_MS`, in both directions.
+#[test]
+fn realtime_bypass_crossfade() {
+    unsafe {
+        let wrapper = Fixture::<HalfGainPlugin<1>>::new().activate();
+
+        let crossfade_length = (SAMPLE_RATE * BYPASS_CROSSFADE_MS / 1000.0).round() as usize;
+        let num_blocks = crossfade_length / BLOCK_SIZE + 2;
+        let render = |bypassed: bool| {
+            assert_eq!(
+                wrapper.inner.set_normalized_value_by_hash(
+                    hash_param_id(BYPASS_PARAM_ID),
+                    if bypassed { 1.0 } else { 0.0 },
+                    Some(SAMPLE_RATE),
+                    ParamChangeSource::HostAutomation
+                ),
+                kResultOk
+            );
+
+            let mut output = Vec::new();
+            for _ in 0..num_blocks {
+                output.extend(process_channels(&wrapper, &[vec![1.0; BLOCK_SIZE]], 1).remove(0));
+            }
+
+            output
+        };
+
+        // The plugin halves its input, so the output should ramp linearly from 0.5 to 1.0
+        let output = render(true);
+        for (sample_idx, sample) in output.iter().enumerate() {
+            let dry_gain = (sample_idx as f32 / crossfade_length as f32).min(1.0);
+            let expected = 0.5 + (dry_gain * 0.5);
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "Expected {expected} at sample {sample_idx}, got {sample}"
+            );
+        }
+        assert!(output[crossfade_length - 1] < 1.0);
+        assert!(output[crossfade_length..]
+            .iter()
+            .all(|sample| *sample == 1.0));
+
+        // And back again
+        let output = render(false);
+        assert_eq!(output[0], 1.0);
+        assert!(output[crossfade_length - 1] > 0.5);
+        assert!(output[crossfade_length..]
+            .iter()
+            .all(|sample| *sample == 0.5));
+
+        deactivate(&wrapper);
+    }
+}
+
+/// A bypassed offline render should null against the input delayed by the plugin's latency,
+/// without any fading when the bypass is enabled halfway through the render.
+#[test]
+fn offline_bypass_nulls() {
+    unsafe {
+        let wrapper = Fixture::<LatencyPlugin>::new()
+            .process_mode(ProcessModes::kOffline)
+            .activate();
+        assert_eq!(wrapper.get_latency_samples(), LatencyPlugin::LATENCY);
+
+        let latency = LatencyPlugin::LATENCY as usize;
+        let bypass_start = BLOCK_SIZE * 2;
+        let input: Vec<f32> = (0..BLOCK_SIZE * 4)
+            .map(|sample_idx| (sample_idx as f32 * 0.1).sin())
+            .collect();
+        let mut output = Vec::new();
+        for (block_idx, block) in input.chunks(BLOCK_SIZE).enumerate() {
+            if block_idx * BLOCK_SIZE == bypass_start {
+                assert_eq!(
+                    wrapper.inner.set_normalized_value_by_hash(
+                        hash_param_id(BYPASS_PARAM_ID),
+                        1.0,
+                        Some(SAMPLE_RATE),
+                        ParamChangeSource::HostAutomation
+                    ),
+                    kResultOk
+                );
+            }
+
+            output.extend(process_channels(&wrapper, &[block.to_vec()], 1).remove(0));
+        }
+
+        assert_eq!(
+            output[bypass_start - 1],
+            input[bypass_start - 1 - latency] * 0.5
+        );
+        for sample_idx in bypass_start..output.len() {
+            assert_eq!(
+                output[sample_idx] - input[sample_idx - latency],
+                0.0,
+                "The bypassed output does not null at sample {sample_idx}"
+            );
+        }
+
+        deactivate(&wrapper);
+    }
+}
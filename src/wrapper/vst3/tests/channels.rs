@@ -0,0 +1,109 @@
+//! Tests for adapting the host's main channel layouts to the plugin's.
+
+use std::ffi::c_void;
+use std::mem;
+use std::ptr;
+use vst3_sys::base::kResultOk;
+use vst3_sys::vst::{IEditController, RestartFlags};
+
+use super::fixture::{
+    activate, deactivate, process_channels, Fixture, HalfGainPlugin, HandlerCall,
+    TestComponentHandler, BLOCK_SIZE,
+};
+
+/// A mono input should be duplicated to both of a stereo plugin's input channels.
+#[test]
+fn channel_adaptation_mono_to_stereo() {
+    unsafe {
+        let wrapper = Fixture::<HalfGainPlugin<2>>::new()
+            .main_channels(1, 2)
+            .activate();
+
+        let outputs = process_channels(&wrapper, &[vec![1.0, -0.5]], 2);
+        assert_eq!(outputs, [vec![0.5, -0.25], vec![0.5, -0.25]]);
+        deactivate(&wrapper);
+    }
+}
+
+/// A stereo plugin's output should be downmixed to mono with a -3 dB pan law.
+#[test]
+fn channel_adaptation_stereo_to_mono() {
+    unsafe {
+        let wrapper = Fixture::<HalfGainPlugin<2>>::new()
+            .main_channels(2, 1)
+            .activate();
+
+        let outputs = process_channels(&wrapper, &[vec![1.0, 1.0], vec![1.0, -1.0]], 1);
+        assert_eq!(
+            outputs,
+            [vec![(0.5 + 0.5) * std::f32::consts::FRAC_1_SQRT_2, 0.0]]
+        );
+        deactivate(&wrapper);
+    }
+}
+
+/// A mono plugin on a stereo track where only one of the channels contains a signal should
+/// output the downmixed signal on both channels.
+#[test]
+fn channel_adaptation_stereo_through_mono() {
+    unsafe {
+        let wrapper = Fixture::<HalfGainPlugin<1>>::new()
+            .main_channels(2, 2)
+            .activate();
+
+        let outputs = process_channels(&wrapper, &[vec![1.0, 0.0], vec![0.0, 0.0]], 2);
+        let expected = 0.5 * std::f32::consts::FRAC_1_SQRT_2;
+        assert_eq!(outputs, [vec![expected, 0.0], vec![expected, 0.0]]);
+        deactivate(&wrapper);
+    }
+}
+
+/// The host passing different main channel counts than the ones it negotiated should result in
+/// silence and a request to renegotiate the layout, without ever touching channels the host
+/// didn't pass.
+#[test]
+fn mismatched_host_channel_counts() {
+    unsafe {
+        let handler = TestComponentHandler::new();
+        let wrapper = Fixture::<HalfGainPlugin<2>>::new()
+            .main_channels(2, 2)
+            .build();
+        assert_eq!(
+            wrapper.set_component_handler(mem::transmute(&*handler as *const TestComponentHandler)),
+            kResultOk
+        );
+        activate(&wrapper);
+
+        let input = |num_channels: usize| vec![vec![1.0f32; BLOCK_SIZE]; num_channels];
+        let outputs = process_channels(&wrapper, &input(2), 2);
+        assert!(outputs.iter().flatten().all(|sample| *sample == 0.5));
+
+        // More channels, fewer channels, and no channels at all. The host's buffers should
+        // always be fully overwritten with silence.
+        for (num_input_channels, num_output_channels) in [(3, 3), (1, 1), (2, 3), (0, 0)] {
+            let outputs =
+                process_channels(&wrapper, &input(num_input_channels), num_output_channels);
+            assert_eq!(outputs.len(), num_output_channels);
+            assert!(
+                outputs.iter().flatten().all(|sample| *sample == 0.0),
+                "The outputs were not silenced for {num_input_channels} input and \
+                 {num_output_channels} output channels"
+            );
+        }
+
+        // The host should only be asked to renegotiate once per mismatch
+        let restart = HandlerCall::RestartComponent(RestartFlags::kIoChanged as i32);
+        assert_eq!(*handler.calls.lock(), [restart]);
+
+        let outputs = process_channels(&wrapper, &input(2), 2);
+        assert!(outputs.iter().flatten().all(|sample| *sample == 0.5));
+        process_channels(&wrapper, &input(1), 1);
+        assert_eq!(*handler.calls.lock(), [restart, restart]);
+
+        assert_eq!(
+            wrapper.set_component_handler(mem::transmute(ptr::null::<c_void>())),
+            kResultOk
+        );
+        deactivate(&wrapper);
+    }
+}
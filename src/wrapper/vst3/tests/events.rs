@@ -0,0 +1,415 @@
+//! Tests for the note events the host sends to the plugin.
+
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult};
+use vst3_sys::vst::{
+    Event, EventTypes, IAudioProcessor, IEventList, INoteExpressionController,
+    NoteExpressionTypeInfo, NoteExpressionValueEvent, NoteOffEvent, NoteOnEvent, ProcessModes,
+};
+use vst3_sys::VST3;
+
+use super::fixture::{deactivate, Fixture, NoParams, BLOCK_SIZE};
+use crate::buffer::Buffer;
+use crate::context::process::ProcessContext;
+use crate::midi::{MidiConfig, NoteEvent, NoteExpressions};
+use crate::params::Params;
+use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+use crate::util::permit_alloc;
+use crate::wrapper::vst3::note_expressions::{
+    BRIGHTNESS_EXPRESSION_ID, TEXT_EXPRESSION_ID, TUNING_EXPRESSION_ID, VOLUME_EXPRESSION_ID,
+};
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+// Alias needed for the VST3 attribute macro
+use vst3_sys as vst3_com;
+
+/// A synth that renders an exponentially decaying click for every note on event. All events
+/// are handled at the start of the block, so the clicks only start at the right sample when the
+/// wrapper splits the block at every note event.
+#[derive(Default)]
+struct ClickSynth {
+    envelope: f32,
+}
+
+impl Plugin for ClickSynth {
+    const NAME: &'static str = "Click Synth";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    const SAMPLE_ACCURATE_EVENTS: bool = true;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        while let Some(event) = context.next_event() {
+            if let NoteEvent::NoteOn { .. } = event {
+                self.envelope = 1.0;
+            }
+        }
+
+        for sample in buffer.as_slice()[0].iter_mut() {
+            *sample = self.envelope;
+            self.envelope *= 0.5;
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ClickSynth {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugClickSynt";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Instrument, Vst3SubCategory::Synth];
+}
+
+/// A synth that only supports the tuning and brightness note expressions, and records every event
+/// it receives.
+#[derive(Default)]
+struct ExpressionSynth {
+    events: Vec<NoteEvent>,
+}
+
+impl Plugin for ExpressionSynth {
+    const NAME: &'static str = "Expression Synth Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
+    const SUPPORTED_NOTE_EXPRESSIONS: NoteExpressions =
+        NoteExpressions::TUNING.union(NoteExpressions::BRIGHTNESS);
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        while let Some(event) = context.next_event() {
+            permit_alloc(|| self.events.push(event));
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ExpressionSynth {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugExprSynth";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
+        &[Vst3SubCategory::Instrument, Vst3SubCategory::Synth];
+}
+
+/// The note events for a single process call, as the host would send them.
+#[VST3(implements(IEventList))]
+struct TestEventList {
+    events: Vec<Event>,
+}
+
+impl TestEventList {
+    /// Create a list containing `events`, which are passed to the plugin in this order.
+    fn new(events: Vec<Event>) -> Box<Self> {
+        Self::allocate(events)
+    }
+
+    /// Create a list of note on events at the given sample offsets.
+    fn note_ons(offsets: &[i32]) -> Box<Self> {
+        Self::new(
+            offsets
+                .iter()
+                .map(|&sample_offset| note_on(sample_offset, 0, 60, -1))
+                .collect(),
+        )
+    }
+}
+
+/// A note on event at `sample_offset`. `note_id` is -1 if the host doesn't use note IDs.
+fn note_on(sample_offset: i32, channel: i16, pitch: i16, note_id: i32) -> Event {
+    unsafe {
+        let mut event: Event = mem::zeroed();
+        event.sample_offset = sample_offset;
+        event.type_ = EventTypes::kNoteOnEvent as u16;
+        event.event.note_on = NoteOnEvent {
+            channel,
+            pitch,
+            tuning: 0.0,
+            velocity: 1.0,
+            length: 0,
+            note_id,
+        };
+
+        event
+    }
+}
+
+/// A note off event at `sample_offset`.
+fn note_off(sample_offset: i32, channel: i16, pitch: i16, note_id: i32) -> Event {
+    unsafe {
+        let mut event: Event = mem::zeroed();
+        event.sample_offset = sample_offset;
+        event.type_ = EventTypes::kNoteOffEvent as u16;
+        event.event.note_off = NoteOffEvent {
+            channel,
+            pitch,
+            velocity: 0.0,
+            note_id,
+            tuning: 0.0,
+        };
+
+        event
+    }
+}
+
+/// A note expression value event for the note with ID `note_id`.
+fn expression_value(sample_offset: i32, type_id: u32, note_id: i32, value: f64) -> Event {
+    unsafe {
+        let mut event: Event = mem::zeroed();
+        event.sample_offset = sample_offset;
+        event.type_ = EventTypes::kNoteExpressionValueEvent as u16;
+        event.event.note_expression_value = NoteExpressionValueEvent {
+            type_id,
+            note_id,
+            value,
+        };
+
+        event
+    }
+}
+
+/// An empty note expression text event for the note with ID `note_id`.
+fn expression_text(sample_offset: i32, type_id: u32, note_id: i32) -> Event {
+    unsafe {
+        let mut event: Event = mem::zeroed();
+        event.sample_offset = sample_offset;
+        event.type_ = EventTypes::kNoteExpressionTextEvent as u16;
+        event.event.note_expression_text.type_id = type_id;
+        event.event.note_expression_text.note_id = note_id;
+
+        event
+    }
+}
+
+/// Process a single block with `events` as the input events, and return the rendered output.
+unsafe fn process_events<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    events: &TestEventList,
+) -> [f32; BLOCK_SIZE] {
+    let mut channel = [f32::NAN; BLOCK_SIZE];
+    let mut channel_ptrs = [channel.as_mut_ptr()];
+    let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+    output_bus.num_channels = 1;
+    output_bus.buffers = channel_ptrs.as_mut_ptr() as _;
+
+    let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+    data.process_mode = ProcessModes::kRealtime as i32;
+    data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+    data.num_samples = BLOCK_SIZE as i32;
+    data.num_outputs = 1;
+    data.outputs = &mut output_bus;
+    data.input_events = mem::transmute(events as *const TestEventList);
+    assert_eq!(wrapper.process(&mut data), kResultOk);
+
+    channel
+}
+
+impl IEventList for TestEventList {
+    unsafe fn get_event_count(&self) -> i32 {
+        self.events.len() as i32
+    }
+
+    unsafe fn get_event(&self, index: i32, e: *mut Event) -> tresult {
+        match self.events.get(index as usize) {
+            Some(event) => {
+                ptr::copy_nonoverlapping(event, e, 1);
+                kResultOk
+            }
+            None => kInvalidArgument,
+        }
+    }
+
+    unsafe fn add_event(&self, _e: *mut Event) -> tresult {
+        kResultFalse
+    }
+}
+
+/// With `SAMPLE_ACCURATE_EVENTS` the block should be split at every note event, so a synth that
+/// handles all of its events at the start of the block still starts its notes at the exact
+/// sample.
+#[test]
+fn sample_accurate_events() {
+    unsafe {
+        let wrapper = Fixture::<ClickSynth>::new().activate();
+
+        let offsets = [0, 37, 38, 100, 127];
+        let events = TestEventList::note_ons(&offsets);
+        let channel = process_events(&wrapper, &events);
+
+        let click_positions: Vec<i32> = channel
+            .iter()
+            .enumerate()
+            .filter(|(_, sample)| **sample == 1.0)
+            .map(|(sample_idx, _)| sample_idx as i32)
+            .collect();
+        assert_eq!(click_positions, offsets);
+        assert_eq!(channel[1], 0.5);
+        assert_eq!(channel[39], 0.5);
+
+        deactivate(&wrapper);
+    }
+}
+
+/// Note expression value events only carry a note ID, so the wrapper needs to associate them with
+/// the channel and note from the matching note on event. Expressions the plugin doesn't support,
+/// text expressions, and expressions for unknown notes are dropped, and everything else arrives in
+/// the order and at the timing the host sent it.
+#[test]
+fn note_expression_events() {
+    unsafe {
+        let wrapper = Fixture::<ExpressionSynth>::new().activate();
+
+        let events = TestEventList::new(vec![
+            note_on(0, 0, 60, 1),
+            expression_value(0, TUNING_EXPRESSION_ID, 1, 0.75),
+            note_on(10, 3, 64, 2),
+            expression_value(12, BRIGHTNESS_EXPRESSION_ID, 2, 0.25),
+            // Not in `ExpressionSynth::SUPPORTED_NOTE_EXPRESSIONS`
+            expression_value(12, VOLUME_EXPRESSION_ID, 1, 0.5),
+            expression_text(20, TEXT_EXPRESSION_ID, 2),
+            expression_value(30, TUNING_EXPRESSION_ID, 2, 0.5),
+            note_off(40, 0, 60, 1),
+            // There is no note with this ID
+            expression_value(50, BRIGHTNESS_EXPRESSION_ID, 99, 1.0),
+        ]);
+        process_events(&wrapper, &events);
+
+        // The note ID association should also carry over to the next block
+        let events = TestEventList::new(vec![
+            expression_value(5, BRIGHTNESS_EXPRESSION_ID, 2, 1.0),
+            note_off(64, 3, 64, 2),
+        ]);
+        process_events(&wrapper, &events);
+
+        let received = mem::take(&mut wrapper.inner.plugin.lock().events);
+        assert_eq!(
+            received,
+            [
+                NoteEvent::NoteOn {
+                    timing: 0,
+                    voice_id: Some(1),
+                    channel: 0,
+                    note: 60,
+                    velocity: 1.0,
+                },
+                NoteEvent::PolyTuning {
+                    timing: 0,
+                    voice_id: Some(1),
+                    channel: 0,
+                    note: 60,
+                    tuning: 60.0,
+                },
+                NoteEvent::NoteOn {
+                    timing: 10,
+                    voice_id: Some(2),
+                    channel: 3,
+                    note: 64,
+                    velocity: 1.0,
+                },
+                NoteEvent::PolyBrightness {
+                    timing: 12,
+                    voice_id: Some(2),
+                    channel: 3,
+                    note: 64,
+                    brightness: 0.25,
+                },
+                NoteEvent::PolyTuning {
+                    timing: 30,
+                    voice_id: Some(2),
+                    channel: 3,
+                    note: 64,
+                    tuning: 0.0,
+                },
+                NoteEvent::NoteOff {
+                    timing: 40,
+                    voice_id: Some(1),
+                    channel: 0,
+                    note: 60,
+                    velocity: 0.0,
+                },
+                NoteEvent::PolyBrightness {
+                    timing: 5,
+                    voice_id: Some(2),
+                    channel: 3,
+                    note: 64,
+                    brightness: 1.0,
+                },
+                NoteEvent::NoteOff {
+                    timing: 64,
+                    voice_id: Some(2),
+                    channel: 3,
+                    note: 64,
+                    velocity: 0.0,
+                },
+            ]
+        );
+
+        deactivate(&wrapper);
+    }
+}
+
+/// Only the note expressions from `SUPPORTED_NOTE_EXPRESSIONS` should be declared to the host.
+#[test]
+fn declared_note_expressions() {
+    unsafe {
+        let wrapper = Fixture::<ExpressionSynth>::new().build();
+
+        assert_eq!(wrapper.get_note_expression_count(0, 0), 2);
+        assert_eq!(wrapper.get_note_expression_count(1, 0), 0);
+
+        let mut info: NoteExpressionTypeInfo = mem::zeroed();
+        assert_eq!(
+            wrapper.get_note_expression_info(0, 0, 0, &mut info),
+            kResultOk
+        );
+        assert_eq!(info.type_id, TUNING_EXPRESSION_ID);
+        assert_eq!(
+            wrapper.get_note_expression_info(0, 0, 1, &mut info),
+            kResultOk
+        );
+        assert_eq!(info.type_id, BRIGHTNESS_EXPRESSION_ID);
+        assert_eq!(
+            wrapper.get_note_expression_info(0, 0, 2, &mut info),
+            kInvalidArgument
+        );
+        assert_eq!(
+            wrapper.get_note_expression_info(0, 0, -1, &mut info),
+            kInvalidArgument
+        );
+    }
+}
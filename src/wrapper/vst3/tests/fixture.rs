@@ -0,0 +1,696 @@
+//! The host side of the VST3 wrapper's unit tests. These COM objects and helpers stand in for the
+//! host, and [`Fixture`] creates and activates wrappers the same way for every test. The simple
+//! plugins shared by several test modules are also defined here.
+
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult, IBStream, TBool};
+use vst3_sys::utils::SharedVstPtr;
+use vst3_sys::vst::{
+    IAudioProcessor, IComponent, IComponentHandler, IComponentHandler2, IEditController,
+    IParamValueQueue, IParameterChanges, ProcessModes,
+};
+use vst3_sys::VST3;
+
+use crate::buffer::Buffer;
+use crate::context::process::ProcessContext;
+use crate::params::internals::ParamPtr;
+use crate::params::range::FloatRange;
+use crate::params::smoothing::SmoothingStyle;
+use crate::params::{FloatParam, Param, Params};
+use crate::plugin::{AuxiliaryBuffers, ChannelAdaptation, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::util::hash_param_id;
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+// Alias needed for the VST3 attribute macro
+use vst3_sys as vst3_com;
+
+/// The maximum block size the plugins are activated with, and the size of the blocks processed by
+/// [`process_block()`].
+pub const BLOCK_SIZE: usize = 128;
+pub const SAMPLE_RATE: f32 = 44_100.0;
+
+/// Creates a wrapper for `P` and runs it through the host's setup sequence. By default the plugin
+/// is activated in realtime mode at [`SAMPLE_RATE`] with a maximum block size of [`BLOCK_SIZE`],
+/// using the plugin's default channel layout.
+pub struct Fixture<P: Vst3Plugin> {
+    process_mode: ProcessModes,
+    sample_rate: f64,
+    max_block_size: usize,
+    /// The main `(input, output)` channel counts to propose to the wrapper, if any.
+    main_channels: Option<(u32, u32)>,
+    /// `(hash, normalized_value)` pairs that are set before activating the plugin.
+    params: Vec<(u32, f64)>,
+
+    _phantom: PhantomData<P>,
+}
+
+/// A call made to a [`TestComponentHandler`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandlerCall {
+    BeginEdit(u32),
+    PerformEdit(u32, f64),
+    EndEdit(u32),
+    StartGroupEdit,
+    FinishGroupEdit,
+    RestartComponent(i32),
+    SetDirty(bool),
+}
+
+/// A component handler that records the calls the plugin makes to it.
+#[VST3(implements(IComponentHandler, IComponentHandler2))]
+pub struct TestComponentHandler {
+    pub calls: Mutex<Vec<HandlerCall>>,
+}
+
+/// A stream the plugin can save its state to and load its state from. Writes are always
+/// appended to the end of the stream.
+#[VST3(implements(IBStream))]
+pub struct TestStream {
+    pub data: Mutex<Vec<u8>>,
+    /// The read position.
+    position: Mutex<i64>,
+}
+
+/// The parameter changes for a single process call, as the host would send them. This is also
+/// used for the plugin's output parameter changes.
+#[VST3(implements(IParameterChanges))]
+pub struct TestParameterChanges {
+    queues: Mutex<Vec<Box<TestParamValueQueue>>>,
+}
+
+/// A single parameter's value changes within a process call.
+#[VST3(implements(IParamValueQueue))]
+struct TestParamValueQueue {
+    hash: u32,
+    /// `(sample_offset, normalized_value)` pairs.
+    points: Mutex<Vec<(i32, f64)>>,
+}
+
+impl<P: Vst3Plugin> Fixture<P> {
+    pub fn new() -> Self {
+        Self {
+            process_mode: ProcessModes::kRealtime,
+            sample_rate: SAMPLE_RATE as f64,
+            max_block_size: BLOCK_SIZE,
+            main_channels: None,
+            params: Vec::new(),
+
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn process_mode(mut self, process_mode: ProcessModes) -> Self {
+        self.process_mode = process_mode;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn max_block_size(mut self, max_block_size: usize) -> Self {
+        self.max_block_size = max_block_size;
+        self
+    }
+
+    /// Propose a main input and output layout with these channel counts before activating the
+    /// plugin. The wrapper needs to accept the layout.
+    pub fn main_channels(mut self, num_input_channels: u32, num_output_channels: u32) -> Self {
+        self.main_channels = Some((num_input_channels, num_output_channels));
+        self
+    }
+
+    /// Set the parameter with ID `param_id` to a normalized value through
+    /// `IEditController::setParamNormalized()` before activating the plugin.
+    pub fn param(mut self, param_id: &str, normalized: f64) -> Self {
+        self.params.push((hash_param_id(param_id), normalized));
+        self
+    }
+
+    /// Create the wrapper and apply the configured layout and parameter values, without activating
+    /// the plugin.
+    pub unsafe fn build(&self) -> Box<Wrapper<P>> {
+        let wrapper = Wrapper::<P>::new();
+        if let Some((num_input_channels, num_output_channels)) = self.main_channels {
+            assert_eq!(
+                set_main_channels(&wrapper, num_input_channels, num_output_channels),
+                kResultOk
+            );
+        }
+        for &(hash, normalized) in &self.params {
+            assert_eq!(wrapper.set_param_normalized(hash, normalized), kResultOk);
+        }
+
+        wrapper
+    }
+
+    /// The same as [`build()`][Self::build()], but the plugin is also set up, activated, and
+    /// started.
+    pub unsafe fn activate(&self) -> Box<Wrapper<P>> {
+        let wrapper = self.build();
+        assert_eq!(
+            setup_and_activate(
+                &wrapper,
+                self.process_mode,
+                self.max_block_size,
+                self.sample_rate
+            ),
+            kResultOk
+        );
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+
+        wrapper
+    }
+}
+
+impl TestComponentHandler {
+    pub fn new() -> Box<Self> {
+        Self::allocate(Mutex::new(Vec::new()))
+    }
+}
+
+impl IComponentHandler for TestComponentHandler {
+    unsafe fn begin_edit(&self, id: u32) -> tresult {
+        self.calls.lock().push(HandlerCall::BeginEdit(id));
+        kResultOk
+    }
+
+    unsafe fn perform_edit(&self, id: u32, value_normalized: f64) -> tresult {
+        self.calls
+            .lock()
+            .push(HandlerCall::PerformEdit(id, value_normalized));
+        kResultOk
+    }
+
+    unsafe fn end_edit(&self, id: u32) -> tresult {
+        self.calls.lock().push(HandlerCall::EndEdit(id));
+        kResultOk
+    }
+
+    unsafe fn restart_component(&self, flags: i32) -> tresult {
+        self.calls.lock().push(HandlerCall::RestartComponent(flags));
+        kResultOk
+    }
+}
+
+impl IComponentHandler2 for TestComponentHandler {
+    unsafe fn set_dirty(&self, state: TBool) -> tresult {
+        self.calls.lock().push(HandlerCall::SetDirty(state != 0));
+        kResultOk
+    }
+
+    unsafe fn request_open_editor(&self, _name: vst3_sys::base::FIDString) -> tresult {
+        kResultFalse
+    }
+
+    unsafe fn start_group_edit(&self) -> tresult {
+        self.calls.lock().push(HandlerCall::StartGroupEdit);
+        kResultOk
+    }
+
+    unsafe fn finish_group_edit(&self) -> tresult {
+        self.calls.lock().push(HandlerCall::FinishGroupEdit);
+        kResultOk
+    }
+}
+
+impl TestStream {
+    pub fn new(data: Vec<u8>) -> Box<Self> {
+        Self::allocate(Mutex::new(data), Mutex::new(0))
+    }
+}
+
+impl IBStream for TestStream {
+    unsafe fn read(
+        &self,
+        buffer: *mut c_void,
+        num_bytes: i32,
+        num_bytes_read: *mut i32,
+    ) -> tresult {
+        let data = self.data.lock();
+        let mut position = self.position.lock();
+        let start = (*position as usize).min(data.len());
+        let end = (start + num_bytes.max(0) as usize).min(data.len());
+        ptr::copy_nonoverlapping(data[start..end].as_ptr(), buffer as *mut u8, end - start);
+        *position = end as i64;
+        if !num_bytes_read.is_null() {
+            *num_bytes_read = (end - start) as i32;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn write(
+        &self,
+        buffer: *const c_void,
+        num_bytes: i32,
+        num_bytes_written: *mut i32,
+    ) -> tresult {
+        self.data
+            .lock()
+            .extend_from_slice(std::slice::from_raw_parts(
+                buffer as *const u8,
+                num_bytes as usize,
+            ));
+        if !num_bytes_written.is_null() {
+            *num_bytes_written = num_bytes;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn seek(&self, pos: i64, mode: i32, result: *mut i64) -> tresult {
+        let len = self.data.lock().len() as i64;
+        let mut position = self.position.lock();
+        *position = match mode {
+            vst3_sys::base::kIBSeekSet => pos,
+            vst3_sys::base::kIBSeekCur => *position + pos,
+            vst3_sys::base::kIBSeekEnd => len + pos,
+            _ => return kInvalidArgument,
+        };
+        if !result.is_null() {
+            *result = *position;
+        }
+
+        kResultOk
+    }
+
+    unsafe fn tell(&self, pos: *mut i64) -> tresult {
+        check_null_ptr!(pos);
+
+        *pos = *self.position.lock();
+        kResultOk
+    }
+}
+
+impl TestParameterChanges {
+    /// Create parameter changes containing a single change at the start of the block for every
+    /// `(hash, normalized_value)` pair.
+    pub fn new(changes: &[(u32, f64)]) -> Box<Self> {
+        Self::allocate(Mutex::new(
+            changes
+                .iter()
+                .map(|&(hash, value)| {
+                    TestParamValueQueue::allocate(hash, Mutex::new(vec![(0, value)]))
+                })
+                .collect(),
+        ))
+    }
+
+    /// Create parameter changes with a queue containing the specified
+    /// `(sample_offset, normalized_value)` points for every `(hash, points)` pair.
+    pub fn with_points(queues: &[(u32, &[(i32, f64)])]) -> Box<Self> {
+        Self::allocate(Mutex::new(
+            queues
+                .iter()
+                .map(|&(hash, points)| {
+                    TestParamValueQueue::allocate(hash, Mutex::new(points.to_vec()))
+                })
+                .collect(),
+        ))
+    }
+
+    /// All `(hash, sample_offset, normalized_value)` points added to these changes.
+    pub fn points(&self) -> Vec<(u32, i32, f64)> {
+        self.queues
+            .lock()
+            .iter()
+            .flat_map(|queue| {
+                queue
+                    .points
+                    .lock()
+                    .iter()
+                    .map(|&(sample_offset, value)| (queue.hash, sample_offset, value))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+impl IParameterChanges for TestParameterChanges {
+    unsafe fn get_parameter_count(&self) -> i32 {
+        self.queues.lock().len() as i32
+    }
+
+    unsafe fn get_parameter_data(&self, index: i32) -> SharedVstPtr<dyn IParamValueQueue> {
+        match self.queues.lock().get(index as usize) {
+            Some(queue) => mem::transmute(&**queue as *const TestParamValueQueue),
+            None => mem::transmute(ptr::null::<c_void>()),
+        }
+    }
+
+    unsafe fn add_parameter_data(
+        &self,
+        id: *const u32,
+        index: *mut i32,
+    ) -> SharedVstPtr<dyn IParamValueQueue> {
+        let mut queues = self.queues.lock();
+        let queue_idx = match queues.iter().position(|queue| queue.hash == *id) {
+            Some(queue_idx) => queue_idx,
+            None => {
+                queues.push(TestParamValueQueue::allocate(*id, Mutex::new(Vec::new())));
+                queues.len() - 1
+            }
+        };
+        *index = queue_idx as i32;
+
+        mem::transmute(&*queues[queue_idx] as *const TestParamValueQueue)
+    }
+}
+
+impl IParamValueQueue for TestParamValueQueue {
+    unsafe fn get_parameter_id(&self) -> u32 {
+        self.hash
+    }
+
+    unsafe fn get_point_count(&self) -> i32 {
+        self.points.lock().len() as i32
+    }
+
+    unsafe fn get_point(&self, index: i32, sample_offset: *mut i32, value: *mut f64) -> tresult {
+        match self.points.lock().get(index as usize) {
+            Some(&(point_sample_offset, point_value)) => {
+                *sample_offset = point_sample_offset;
+                *value = point_value;
+                kResultOk
+            }
+            None => kInvalidArgument,
+        }
+    }
+
+    unsafe fn add_point(&self, sample_offset: i32, value: f64, index: *mut i32) -> tresult {
+        let mut points = self.points.lock();
+        points.push((sample_offset, value));
+        *index = points.len() as i32 - 1;
+
+        kResultOk
+    }
+}
+
+/// Save the plugin's state through `IComponent::getState()`.
+pub unsafe fn save_state<P: Vst3Plugin>(wrapper: &Wrapper<P>) -> Vec<u8> {
+    let stream = TestStream::new(Vec::new());
+    assert_eq!(
+        IComponent::get_state(wrapper, mem::transmute(&*stream as *const TestStream)),
+        kResultOk
+    );
+
+    let data = stream.data.lock().clone();
+    data
+}
+
+/// Load state into the plugin through `IComponent::setState()`, and return the result.
+pub unsafe fn load_state<P: Vst3Plugin>(wrapper: &Wrapper<P>, data: Vec<u8>) -> tresult {
+    let stream = TestStream::new(data);
+    IComponent::set_state(wrapper, mem::transmute(&*stream as *const TestStream))
+}
+
+/// Set up processing with the given maximum block size and sample rate, and then activate the
+/// plugin, returning the activation's result.
+pub unsafe fn setup_and_activate<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    process_mode: ProcessModes,
+    max_block_size: usize,
+    sample_rate: f64,
+) -> tresult {
+    let mut setup: vst3_sys::vst::ProcessSetup = mem::zeroed();
+    setup.process_mode = process_mode as i32;
+    setup.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+    setup.max_samples_per_block = max_block_size as i32;
+    setup.sample_rate = sample_rate;
+    assert_eq!(wrapper.setup_processing(&setup), kResultOk);
+
+    wrapper.set_active(1)
+}
+
+/// Run a wrapper through the usual activation sequence.
+pub unsafe fn activate<P: Vst3Plugin>(wrapper: &Wrapper<P>) {
+    activate_in_mode(wrapper, ProcessModes::kRealtime);
+}
+
+/// The same as [`activate()`], but with a different processing mode.
+pub unsafe fn activate_in_mode<P: Vst3Plugin>(wrapper: &Wrapper<P>, process_mode: ProcessModes) {
+    assert_eq!(
+        setup_and_activate(wrapper, process_mode, BLOCK_SIZE, SAMPLE_RATE as f64),
+        kResultOk
+    );
+    assert_eq!(wrapper.set_processing(1), kResultOk);
+}
+
+/// Stop processing and deactivate the plugin.
+pub unsafe fn deactivate<P: Vst3Plugin>(wrapper: &Wrapper<P>) {
+    assert_eq!(wrapper.set_processing(0), kResultOk);
+    assert_eq!(wrapper.set_active(0), kResultOk);
+}
+
+/// Process a single block of audio on a plugin with a single mono output and append the output
+/// to `output`. The output starts out filled with -1.0 so it's clear when the plugin was not
+/// processed.
+pub unsafe fn process_block<P: Vst3Plugin>(wrapper: &Wrapper<P>, output: &mut Vec<f32>) {
+    process_block_with(wrapper, output, ptr::null_mut(), None);
+}
+
+/// The same as [`process_block()`], but with transport information and parameter changes from
+/// the host.
+pub unsafe fn process_block_with<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    output: &mut Vec<f32>,
+    context: *mut vst3_sys::vst::ProcessContext,
+    param_changes: Option<&TestParameterChanges>,
+) {
+    assert_eq!(
+        try_process_block_with(wrapper, output, context, param_changes),
+        kResultOk
+    );
+}
+
+/// The same as [`process_block_with()`], but returns the process call's result instead of
+/// asserting that it succeeded.
+pub unsafe fn try_process_block_with<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    output: &mut Vec<f32>,
+    context: *mut vst3_sys::vst::ProcessContext,
+    param_changes: Option<&TestParameterChanges>,
+) -> tresult {
+    let mut channel = [-1.0f32; BLOCK_SIZE];
+    let mut channel_ptrs = [channel.as_mut_ptr()];
+    let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+    output_bus.num_channels = 1;
+    output_bus.buffers = channel_ptrs.as_mut_ptr() as _;
+
+    // All of the event pointers are left null
+    let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+    data.process_mode = ProcessModes::kRealtime as i32;
+    data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+    data.num_samples = BLOCK_SIZE as i32;
+    data.num_outputs = 1;
+    data.outputs = &mut output_bus;
+    data.context = context;
+    if let Some(param_changes) = param_changes {
+        data.input_param_changes = mem::transmute(param_changes as *const TestParameterChanges);
+    }
+    let result = wrapper.process(&mut data);
+
+    output.extend_from_slice(&channel);
+    result
+}
+
+/// Process a single block on a plugin with a single mono output, and return the output parameter
+/// changes the plugin sent to the host as `(hash, sample_offset, normalized_value)` points.
+pub unsafe fn process_block_output_params<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    output: &mut Vec<f32>,
+    context: *mut vst3_sys::vst::ProcessContext,
+) -> Vec<(u32, i32, f64)> {
+    let mut channel = [-1.0f32; BLOCK_SIZE];
+    let mut channel_ptrs = [channel.as_mut_ptr()];
+    let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+    output_bus.num_channels = 1;
+    output_bus.buffers = channel_ptrs.as_mut_ptr() as _;
+    let output_param_changes = TestParameterChanges::new(&[]);
+
+    let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+    data.process_mode = ProcessModes::kRealtime as i32;
+    data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+    data.num_samples = BLOCK_SIZE as i32;
+    data.num_outputs = 1;
+    data.outputs = &mut output_bus;
+    data.context = context;
+    data.output_param_changes =
+        mem::transmute(&*output_param_changes as *const TestParameterChanges);
+    assert_eq!(wrapper.process(&mut data), kResultOk);
+
+    output.extend_from_slice(&channel);
+    output_param_changes.points()
+}
+
+/// Propose a main input and output layout with the given channel counts to the wrapper.
+pub unsafe fn set_main_channels<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    num_input_channels: u32,
+    num_output_channels: u32,
+) -> tresult {
+    let mut input = (1u64 << num_input_channels) - 1;
+    let mut output = (1u64 << num_output_channels) - 1;
+    wrapper.set_bus_arrangements(&mut input, 1, &mut output, 1)
+}
+
+/// Process a single block with the given main input channels and return the host's main output
+/// channels. The output channels start out filled with NaNs so any samples the wrapper did not
+/// write to can be detected. Without any inputs the block is `BLOCK_SIZE` samples long.
+pub unsafe fn process_channels<P: Vst3Plugin>(
+    wrapper: &Wrapper<P>,
+    inputs: &[Vec<f32>],
+    num_output_channels: usize,
+) -> Vec<Vec<f32>> {
+    let num_samples = inputs.first().map_or(BLOCK_SIZE, Vec::len);
+    let mut input_ptrs: Vec<*const f32> = inputs.iter().map(|channel| channel.as_ptr()).collect();
+    let mut input_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+    input_bus.num_channels = inputs.len() as i32;
+    input_bus.buffers = input_ptrs.as_mut_ptr() as _;
+
+    let mut outputs = vec![vec![f32::NAN; num_samples]; num_output_channels];
+    let mut output_ptrs: Vec<*mut f32> = outputs
+        .iter_mut()
+        .map(|channel| channel.as_mut_ptr())
+        .collect();
+    let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+    output_bus.num_channels = num_output_channels as i32;
+    output_bus.buffers = output_ptrs.as_mut_ptr() as _;
+
+    let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+    data.process_mode = ProcessModes::kRealtime as i32;
+    data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+    data.num_samples = num_samples as i32;
+    data.num_inputs = 1;
+    data.inputs = &mut input_bus;
+    data.num_outputs = 1;
+    data.outputs = &mut output_bus;
+    assert_eq!(wrapper.process(&mut data), kResultOk);
+
+    outputs
+}
+
+/// Outputs its smoothed gain parameter's value as a DC signal, so the output can be traced back
+/// to the instance's parameters.
+#[derive(Default)]
+pub struct DcPlugin {
+    pub params: Arc<DcParams>,
+}
+
+pub struct DcParams {
+    pub gain: FloatParam,
+}
+
+impl Default for DcParams {
+    fn default() -> Self {
+        Self {
+            gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(1.0)),
+        }
+    }
+}
+
+unsafe impl Params for DcParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+    }
+}
+
+impl Plugin for DcPlugin {
+    const NAME: &'static str = "DC Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            let gain = self.params.gain.smoothed.next();
+            for sample in channel_samples {
+                *sample = gain;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for DcPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugDcTestPlg";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Parameters for plugins that don't need any.
+pub struct NoParams;
+
+unsafe impl Params for NoParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        Vec::new()
+    }
+}
+
+/// Halves its input, with `CHANNELS` input and output channels. The wrapper needs to adapt any
+/// other mono or stereo layouts to this.
+#[derive(Default)]
+pub struct HalfGainPlugin<const CHANNELS: u32>;
+
+impl<const CHANNELS: u32> Plugin for HalfGainPlugin<CHANNELS> {
+    const NAME: &'static str = "Half Gain Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = CHANNELS;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = CHANNELS;
+    const CHANNEL_ADAPTATION: ChannelAdaptation = ChannelAdaptation::DuplicateDownmix;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples {
+                *sample *= 0.5;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl<const CHANNELS: u32> Vst3Plugin for HalfGainPlugin<CHANNELS> {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugHalfGain0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
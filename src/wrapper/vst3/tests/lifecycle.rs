@@ -0,0 +1,485 @@
+//! Tests for how the wrapper sets up, activates, and deactivates the plugin, and for how it deals
+//! with hosts using it from multiple threads.
+
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use vst3_sys::base::{kResultFalse, kResultOk};
+use vst3_sys::vst::{IAudioProcessor, IComponent, ProcessModes};
+
+use super::fixture::{
+    deactivate, load_state, process_block, save_state, set_main_channels, setup_and_activate,
+    DcPlugin, Fixture, NoParams, BLOCK_SIZE, SAMPLE_RATE,
+};
+use crate::buffer::Buffer;
+use crate::context::init::InitContext;
+use crate::context::process::ProcessContext;
+use crate::params::Params;
+use crate::plugin::{
+    AuxiliaryBuffers, BufferConfig, BusConfig, InitError, Plugin, ProcessMode, ProcessStatus,
+    Vst3Plugin,
+};
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// Records the buffer config it was initialized with, and the largest block it was asked to
+/// process.
+#[derive(Default)]
+struct BufferConfigPlugin {
+    /// The buffer config from the last `initialize()` call.
+    buffer_config: Option<BufferConfig>,
+    /// The largest block the plugin has processed so far.
+    largest_block_size: usize,
+}
+
+impl Plugin for BufferConfigPlugin {
+    const NAME: &'static str = "Buffer Config Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.buffer_config = Some(*buffer_config);
+        true
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        self.largest_block_size = self.largest_block_size.max(buffer.len());
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for BufferConfigPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugBufConfig";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Only supports a 48 kHz sample rate.
+#[derive(Default)]
+struct FailingPlugin;
+
+impl Plugin for FailingPlugin {
+    const NAME: &'static str = "Failing Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn try_initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> Result<(), InitError> {
+        if buffer_config.sample_rate == 48_000.0 {
+            Ok(())
+        } else {
+            Err(InitError::unsupported_sample_rate(
+                buffer_config.sample_rate,
+                vec![48_000.0],
+            ))
+        }
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for FailingPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugFailingPl";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Allocates a one second delay line for the maximum expected sample rate, and keeps track of
+/// how it was initialized.
+#[derive(Default)]
+struct PreallocatingPlugin {
+    delay_line: Vec<f32>,
+    sample_rate: f32,
+    num_initializations: usize,
+    /// The number of samples processed since the last reset.
+    samples_since_reset: usize,
+}
+
+impl Plugin for PreallocatingPlugin {
+    const NAME: &'static str = "Preallocating Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const MAX_SUPPORTED_SAMPLE_RATE: f32 = 96_000.0;
+    const PREALLOCATES_FOR_MAX_SAMPLE_RATE: bool = true;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.delay_line
+            .resize(context.max_expected_sample_rate() as usize, 0.0);
+        self.sample_rate = buffer_config.sample_rate;
+        self.num_initializations += 1;
+        true
+    }
+
+    fn reset(&mut self) {
+        self.samples_since_reset = 0;
+    }
+
+    fn sample_rate_changed(&mut self, new_sample_rate: f32) {
+        self.sample_rate = new_sample_rate;
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        self.samples_since_reset += buffer.len();
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for PreallocatingPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugPrealloc0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Outputs a constant signal of ones and lets the wrapper fade in its output.
+#[derive(Default)]
+struct FadeInPlugin;
+
+impl Plugin for FadeInPlugin {
+    const NAME: &'static str = "Fade In Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const ACTIVATION_FADE_MS: f32 = 2.0;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples {
+                *sample = 1.0;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for FadeInPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugFadeIn000";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Instances of the same plugin should not share any state, even when they're being used from
+/// different threads at the same time.
+#[test]
+fn instances_are_independent() {
+    const NUM_INSTANCES: usize = 16;
+    const NUM_BLOCKS: usize = 64;
+
+    let gains: Vec<f32> = (0..NUM_INSTANCES)
+        .map(|instance_idx| instance_idx as f32 / NUM_INSTANCES as f32)
+        .collect();
+    let handles: Vec<_> = gains
+        .iter()
+        .map(|&gain| {
+            thread::spawn(move || unsafe {
+                let wrapper = Fixture::<DcPlugin>::new()
+                    .param("gain", gain as f64)
+                    .activate();
+                let mut output = Vec::with_capacity(NUM_BLOCKS * BLOCK_SIZE);
+                for _ in 0..NUM_BLOCKS {
+                    process_block(&wrapper, &mut output);
+                }
+                deactivate(&wrapper);
+
+                output
+            })
+        })
+        .collect();
+
+    for (handle, gain) in handles.into_iter().zip(gains) {
+        let output = handle.join().expect("An instance panicked");
+        assert_eq!(output.len(), NUM_BLOCKS * BLOCK_SIZE);
+        assert!(
+            output.iter().all(|sample| *sample == gain),
+            "An instance with gain {gain} did not output its own gain value"
+        );
+    }
+}
+
+/// Some hosts call `IAudioProcessor::setBusArrangements()` and `IComponent::setState()` from
+/// the GUI thread while the audio thread is processing audio, and the editor may restore state
+/// through `GuiContext::set_state()` at the same time. That should neither panic nor deadlock,
+/// and the plugin should end up in a consistent state.
+#[test]
+fn host_calls_during_processing() {
+    const NUM_ITERATIONS: usize = 200;
+
+    /// The wrapper itself is not `Sync` because of the COM vtable pointers, but the host can
+    /// still call it from multiple threads.
+    struct SendPtr(*const Wrapper<DcPlugin>);
+    unsafe impl Send for SendPtr {}
+
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 1.0).activate();
+        let state = save_state(&*wrapper);
+
+        let host_ptr = SendPtr(&*wrapper);
+        let host_thread = thread::spawn(move || {
+            let host_ptr = host_ptr;
+            let wrapper = &*host_ptr.0;
+            for _ in 0..NUM_ITERATIONS {
+                assert_eq!(set_main_channels(wrapper, 0, 1), kResultOk);
+                assert_eq!(load_state(wrapper, state.clone()), kResultOk);
+            }
+        });
+
+        // The audio thread restores the editor's state at the end of a process call. If the
+        // host thread is using the plugin at that point, then the state should stay queued
+        // instead of blocking the audio thread.
+        let editor_state = wrapper.inner.get_state_object();
+        let editor_ptr = SendPtr(&*wrapper);
+        let editor_thread = thread::spawn(move || {
+            let editor_ptr = editor_ptr;
+            let wrapper = &*editor_ptr.0;
+            for _ in 0..NUM_ITERATIONS {
+                wrapper.inner.set_state_object(editor_state.clone());
+            }
+        });
+
+        let mut output = Vec::new();
+        while !host_thread.is_finished() || !editor_thread.is_finished() {
+            process_block(&wrapper, &mut output);
+        }
+        host_thread.join().unwrap();
+        editor_thread.join().unwrap();
+
+        // Blocks are either processed as usual or they are silent. Postponed editor state
+        // restores also count as contention, but they don't silence the block.
+        let num_silent_samples = output.iter().filter(|sample| **sample == 0.0).count();
+        assert!(output.iter().all(|sample| *sample == 0.0 || *sample == 1.0));
+        assert_eq!(num_silent_samples % BLOCK_SIZE, 0);
+        assert!(
+            num_silent_samples
+                <= wrapper
+                    .inner
+                    .num_contended_process_blocks
+                    .load(Ordering::SeqCst) as usize
+                    * BLOCK_SIZE
+        );
+        assert!(!wrapper.inner.updated_state_pending.load(Ordering::SeqCst));
+
+        assert_eq!(
+            wrapper.inner.current_bus_config.load().num_output_channels,
+            1
+        );
+        assert!(wrapper.inner.is_processing());
+        output.clear();
+        process_block(&wrapper, &mut output);
+        assert!(output.iter().all(|sample| *sample == 1.0));
+
+        deactivate(&wrapper);
+    }
+}
+
+/// VST3 only tells the plugin about the maximum block size, and the blocks the host sends
+/// should never exceed that.
+#[test]
+fn buffer_config_matches_processing() {
+    unsafe {
+        let wrapper = Fixture::<BufferConfigPlugin>::new().activate();
+
+        let mut output = Vec::new();
+        for _ in 0..8 {
+            process_block(&wrapper, &mut output);
+        }
+        let (buffer_config, largest_block_size) = {
+            let plugin = wrapper.inner.plugin.lock();
+            (plugin.buffer_config, plugin.largest_block_size)
+        };
+        deactivate(&wrapper);
+
+        assert_eq!(
+            buffer_config,
+            Some(BufferConfig {
+                sample_rate: SAMPLE_RATE,
+                min_buffer_size: None,
+                max_buffer_size: BLOCK_SIZE as u32,
+                process_mode: ProcessMode::Realtime,
+            })
+        );
+        assert_eq!(largest_block_size, BLOCK_SIZE);
+    }
+}
+
+/// A plugin that fails to initialize should cause `set_active()` to fail, and it should be
+/// possible to activate it again at a supported sample rate afterwards.
+#[test]
+fn failed_initialization() {
+    unsafe {
+        let wrapper = Wrapper::<FailingPlugin>::new();
+        assert_eq!(
+            setup_and_activate(
+                &wrapper,
+                ProcessModes::kRealtime,
+                BLOCK_SIZE,
+                SAMPLE_RATE as f64
+            ),
+            kResultFalse
+        );
+        assert_eq!(
+            setup_and_activate(&wrapper, ProcessModes::kRealtime, BLOCK_SIZE, 48_000.0),
+            kResultOk
+        );
+        assert_eq!(wrapper.set_active(0), kResultOk);
+    }
+}
+
+/// Reactivating a plugin that preallocates for the maximum sample rate at a different sample
+/// rate should keep its state, unless the new sample rate exceeds that maximum.
+#[test]
+fn sample_rate_change_in_place() {
+    unsafe fn reactivate(wrapper: &Wrapper<PreallocatingPlugin>, sample_rate: f64) {
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(wrapper.set_active(0), kResultOk);
+        assert_eq!(
+            setup_and_activate(wrapper, ProcessModes::kRealtime, BLOCK_SIZE, sample_rate),
+            kResultOk
+        );
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+    }
+
+    unsafe {
+        let wrapper = Wrapper::<PreallocatingPlugin>::new();
+        reactivate(&wrapper, 48_000.0);
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+        {
+            let plugin = wrapper.inner.plugin.lock();
+            assert_eq!(plugin.num_initializations, 1);
+            assert_eq!(plugin.delay_line.len(), 96_000);
+            assert_eq!(plugin.samples_since_reset, BLOCK_SIZE);
+        }
+
+        reactivate(&wrapper, 96_000.0);
+        process_block(&wrapper, &mut output);
+        {
+            let plugin = wrapper.inner.plugin.lock();
+            assert_eq!(plugin.num_initializations, 1);
+            assert_eq!(plugin.sample_rate, 96_000.0);
+            assert_eq!(plugin.samples_since_reset, BLOCK_SIZE * 2);
+        }
+
+        // This exceeds the maximum, so the plugin needs to be reinitialized
+        reactivate(&wrapper, 192_000.0);
+        {
+            let plugin = wrapper.inner.plugin.lock();
+            assert_eq!(plugin.num_initializations, 2);
+            assert_eq!(plugin.delay_line.len(), 192_000);
+            assert_eq!(plugin.sample_rate, 192_000.0);
+            assert_eq!(plugin.samples_since_reset, 0);
+        }
+
+        deactivate(&wrapper);
+    }
+}
+
+/// The plugin's output should follow a linear ramp for `ACTIVATION_FADE_MS` after the host
+/// starts processing audio, and the fade should start over when processing is restarted.
+#[test]
+fn activation_fade() {
+    unsafe {
+        let wrapper = Fixture::<FadeInPlugin>::new().activate();
+
+        let fade_length =
+            (SAMPLE_RATE * FadeInPlugin::ACTIVATION_FADE_MS / 1000.0).round() as usize;
+        assert!(fade_length < BLOCK_SIZE);
+
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+        process_block(&wrapper, &mut output);
+        for (sample_idx, sample) in output.iter().enumerate() {
+            let expected = (sample_idx as f32 / fade_length as f32).min(1.0);
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "Expected {expected} at sample {sample_idx}, got {sample}"
+            );
+        }
+
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+        assert_eq!(output[0], 0.0);
+        assert!(output[fade_length / 2] > 0.0 && output[fade_length / 2] < 1.0);
+        assert_eq!(output[fade_length..], [1.0; BLOCK_SIZE][fade_length..]);
+
+        deactivate(&wrapper);
+    }
+}
@@ -0,0 +1,220 @@
+//! Tests for catching panics from the plugin and poisoning it afterwards.
+
+use parking_lot::Mutex;
+use std::any::Any;
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use vst3_sys::base::{kResultFalse, kResultOk};
+use vst3_sys::vst::{IAudioProcessor, IComponent, IEditController, ProcessModes};
+
+use super::fixture::{
+    activate, load_state, process_block, save_state, setup_and_activate, try_process_block_with,
+    BLOCK_SIZE, SAMPLE_RATE,
+};
+use crate::buffer::Buffer;
+use crate::context::gui::GuiContext;
+use crate::context::process::ProcessContext;
+use crate::editor::{Editor, ParentWindowHandle};
+use crate::params::internals::ParamPtr;
+use crate::params::{BoolParam, Param, Params};
+use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::state::{ParamValue, PluginState};
+use crate::wrapper::util::hash_param_id;
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// Outputs a DC signal of 1.0, and panics while processing audio once its `panic_in_process`
+/// parameter is enabled. Its `panic_in_callback` parameter panics as soon as it gets enabled.
+#[derive(Default)]
+struct PanickingPlugin {
+    params: Arc<PanickingParams>,
+}
+
+struct PanickingParams {
+    panic_in_process: BoolParam,
+    panic_in_callback: BoolParam,
+}
+
+impl Default for PanickingParams {
+    fn default() -> Self {
+        Self {
+            panic_in_process: BoolParam::new("Panic in process", false),
+            panic_in_callback: BoolParam::new("Panic in callback", false).with_callback(Arc::new(
+                |value| {
+                    if value {
+                        panic!("The parameter callback panicked")
+                    }
+                },
+            )),
+        }
+    }
+}
+
+unsafe impl Params for PanickingParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![
+            (
+                String::from("panic_in_process"),
+                self.panic_in_process.as_ptr(),
+                String::new(),
+            ),
+            (
+                String::from("panic_in_callback"),
+                self.panic_in_callback.as_ptr(),
+                String::new(),
+            ),
+        ]
+    }
+}
+
+impl Plugin for PanickingPlugin {
+    const NAME: &'static str = "Panicking Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        if self.params.panic_in_process.value() {
+            panic!("The plugin panicked while processing audio");
+        }
+
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples {
+                *sample = 1.0;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for PanickingPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugPanicking";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// An editor that counts how often it was told that the plugin panicked.
+struct PanicCountingEditor {
+    num_panics: Arc<AtomicUsize>,
+}
+
+impl Editor for PanicCountingEditor {
+    fn spawn(
+        &self,
+        _parent: ParentWindowHandle,
+        _context: Arc<dyn GuiContext>,
+    ) -> Box<dyn Any + Send> {
+        Box::new(())
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (640, 480)
+    }
+
+    fn set_scale_factor(&self, _factor: f32) -> bool {
+        false
+    }
+
+    fn param_values_changed(&self) {}
+
+    fn plugin_panicked(&self) {
+        self.num_panics.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// A plugin that panicked while processing audio can't be trusted anymore. The wrapper should
+/// output silence from then on, refuse to reactivate the plugin or to load state into it, and
+/// let the editor know what happened.
+#[test]
+fn panicking_plugin_is_poisoned() {
+    unsafe {
+        let wrapper = Wrapper::<PanickingPlugin>::new();
+        let num_panics = Arc::new(AtomicUsize::new(0));
+        *wrapper.inner.editor.borrow_mut() =
+            Some(Arc::new(Mutex::new(Box::new(PanicCountingEditor {
+                num_panics: num_panics.clone(),
+            }) as Box<dyn Editor>)));
+        activate(&*wrapper);
+
+        let mut output = Vec::new();
+        process_block(&*wrapper, &mut output);
+        assert!(output.iter().all(|sample| *sample == 1.0));
+        let saved_state = save_state(&*wrapper);
+
+        // The parameter change is applied at the start of the next block
+        assert_eq!(
+            wrapper.set_param_normalized(hash_param_id("panic_in_process"), 1.0),
+            kResultOk
+        );
+        for _ in 0..4 {
+            output.clear();
+            assert_eq!(
+                try_process_block_with(&*wrapper, &mut output, ptr::null_mut(), None),
+                kResultFalse
+            );
+            assert!(output.iter().all(|sample| *sample == 0.0));
+        }
+        assert!(wrapper.inner.is_poisoned.load(Ordering::SeqCst));
+        assert_eq!(num_panics.load(Ordering::SeqCst), 1);
+
+        // The host needs to reload the plugin, but it can still save its state
+        assert_eq!(load_state(&*wrapper, saved_state), kResultFalse);
+        save_state(&*wrapper);
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(wrapper.set_active(0), kResultOk);
+        assert_eq!(wrapper.set_active(1), kResultFalse);
+        assert_eq!(num_panics.load(Ordering::SeqCst), 1);
+    }
+}
+
+/// Panics in a parameter's callback should be caught and poison the plugin, both when the host
+/// sets the parameter and when it loads state.
+#[test]
+fn panicking_param_callback_is_poisoned() {
+    unsafe {
+        let set_wrapper = Wrapper::<PanickingPlugin>::new();
+        assert_eq!(
+            set_wrapper.set_param_normalized(hash_param_id("panic_in_callback"), 1.0),
+            kResultFalse
+        );
+        assert!(set_wrapper.inner.is_poisoned.load(Ordering::SeqCst));
+        assert_eq!(
+            setup_and_activate(
+                &*set_wrapper,
+                ProcessModes::kRealtime,
+                BLOCK_SIZE,
+                SAMPLE_RATE as f64
+            ),
+            kResultFalse
+        );
+
+        let load_wrapper = Wrapper::<PanickingPlugin>::new();
+        let state = PluginState {
+            version: String::from(PanickingPlugin::VERSION),
+            params: [(String::from("panic_in_callback"), ParamValue::Bool(true))].into(),
+            fields: Default::default(),
+            instance_id: None,
+        };
+        assert_eq!(
+            load_state(&*load_wrapper, serde_json::to_vec(&state).unwrap()),
+            kResultFalse
+        );
+        assert!(load_wrapper.inner.is_poisoned.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,1051 @@
+//! Tests for the parameters the wrapper exposes to the host, and for the ways their values can be
+//! changed.
+
+use std::mem;
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk};
+use vst3_sys::vst::{
+    kNoParamId, IAudioProcessor, IEditController, IMidiMapping, ParameterFlags, ProcessModes, TChar,
+};
+use widestring::U16CStr;
+
+use super::fixture::{
+    activate, deactivate, process_block, process_block_with, process_channels, DcParams, DcPlugin,
+    Fixture, TestParameterChanges, BLOCK_SIZE,
+};
+use crate::buffer::Buffer;
+use crate::context::gui::ParamSetter;
+use crate::context::process::ProcessContext;
+use crate::params::internals::ParamPtr;
+use crate::params::range::FloatRange;
+use crate::params::{BoolParam, FloatParam, Param, ParamMut, Params};
+use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+use crate::util::permit_alloc;
+use crate::wrapper::state::{ParamValue, PluginState};
+use crate::wrapper::util::{hash_param_id, BYPASS_PARAM_ID};
+use crate::wrapper::vst3::wrapper::{Wrapper, LINEAR_PLAIN_AUTOMATION_INTERVAL};
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// Maps the mod wheel on the first MIDI channel to its gain parameter.
+#[derive(Default)]
+struct MidiCcPlugin {
+    params: Arc<DcParams>,
+}
+
+impl Plugin for MidiCcPlugin {
+    const NAME: &'static str = "MIDI CC Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for MidiCcPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugMidiCcMap";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+
+    fn vst3_midi_cc_mapping(channel: u8, cc: u8) -> Option<&'static str> {
+        match (channel, cc) {
+            (0, 1) => Some("gain"),
+            (0, 2) => Some("does_not_exist"),
+            _ => None,
+        }
+    }
+}
+
+/// Has a single decibel gain parameter.
+#[derive(Default)]
+struct GainDbPlugin {
+    params: Arc<GainDbParams>,
+}
+
+struct GainDbParams {
+    gain: FloatParam,
+}
+
+impl Default for GainDbParams {
+    fn default() -> Self {
+        Self {
+            gain: FloatParam::new_gain_db("Gain", 0.0, f32::NEG_INFINITY, 12.0),
+        }
+    }
+}
+
+unsafe impl Params for GainDbParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+    }
+}
+
+impl Plugin for GainDbPlugin {
+    const NAME: &'static str = "Gain dB Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for GainDbPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugGainDbTst";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Mixes regular, non-automatable, and hidden parameters. The hidden parameter sits in between
+/// the other two so it shifts the indices of the parameters that come after it.
+#[derive(Default)]
+struct VisibilityPlugin {
+    params: Arc<VisibilityParams>,
+}
+
+struct VisibilityParams {
+    visible: FloatParam,
+    hidden: FloatParam,
+    non_automatable: FloatParam,
+}
+
+impl Default for VisibilityParams {
+    fn default() -> Self {
+        let range = FloatRange::Linear { min: 0.0, max: 1.0 };
+
+        Self {
+            visible: FloatParam::new("Visible", 0.1, range),
+            hidden: FloatParam::new("Hidden", 0.2, range).hide(),
+            non_automatable: FloatParam::new("Non-automatable", 0.3, range).non_automatable(),
+        }
+    }
+}
+
+unsafe impl Params for VisibilityParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![
+            (
+                String::from("visible"),
+                self.visible.as_ptr(),
+                String::new(),
+            ),
+            (String::from("hidden"), self.hidden.as_ptr(), String::new()),
+            (
+                String::from("non_automatable"),
+                self.non_automatable.as_ptr(),
+                String::new(),
+            ),
+        ]
+    }
+}
+
+impl Plugin for VisibilityPlugin {
+    const NAME: &'static str = "Visibility Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for VisibilityPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugVisibilty";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Uses the same parameters as [`VisibilityPlugin`], but their values have already been
+/// changed by the time the wrapper gets to see them, like when a plugin constructs its
+/// parameters from a saved state.
+struct ModifiedParamsPlugin {
+    params: Arc<VisibilityParams>,
+}
+
+impl Default for ModifiedParamsPlugin {
+    fn default() -> Self {
+        let params = VisibilityParams::default();
+        params.visible.set_plain_value(0.9);
+        params.non_automatable.set_plain_value(0.8);
+
+        Self {
+            params: Arc::new(params),
+        }
+    }
+}
+
+impl Plugin for ModifiedParamsPlugin {
+    const NAME: &'static str = "Modified Params Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ModifiedParamsPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugModParams";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Outputs the plain value of a parameter with a skewed range that follows automation ramps in
+/// plain value space. The parameter is not smoothed, so the output shows exactly when and how
+/// the wrapper updated it.
+#[derive(Default)]
+struct PlainAutomationPlugin {
+    params: Arc<PlainAutomationParams>,
+}
+
+struct PlainAutomationParams {
+    curved: FloatParam,
+}
+
+impl Default for PlainAutomationParams {
+    fn default() -> Self {
+        Self {
+            curved: FloatParam::new(
+                "Curved",
+                0.25,
+                FloatRange::Skewed {
+                    min: 0.25,
+                    max: 4.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_linear_plain_automation(),
+        }
+    }
+}
+
+unsafe impl Params for PlainAutomationParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("curved"), self.curved.as_ptr(), String::new())]
+    }
+}
+
+impl Plugin for PlainAutomationPlugin {
+    const NAME: &'static str = "Plain Automation Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let value = self.params.curved.value();
+        for channel_samples in buffer.iter_samples() {
+            for sample in channel_samples {
+                *sample = value;
+            }
+        }
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for PlainAutomationPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugPlainAuto";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Records the length of every block it processes together with its parameters' values, so the
+/// tests can check how the wrapper split up the host's blocks. Changes to `exact` should keep
+/// their sample offsets.
+#[derive(Default)]
+struct DenseAutomationPlugin {
+    params: Arc<DenseAutomationParams>,
+    /// `(num_samples, [a, b, exact])` for every call to `process()`.
+    blocks: Vec<(usize, [f32; 3])>,
+}
+
+struct DenseAutomationParams {
+    a: FloatParam,
+    b: FloatParam,
+    exact: FloatParam,
+}
+
+impl Default for DenseAutomationParams {
+    fn default() -> Self {
+        let range = FloatRange::Linear { min: 0.0, max: 1.0 };
+        Self {
+            a: FloatParam::new("A", 0.0, range),
+            b: FloatParam::new("B", 0.0, range),
+            exact: FloatParam::new("Exact", 0.0, range).with_exact_automation(),
+        }
+    }
+}
+
+unsafe impl Params for DenseAutomationParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![
+            (String::from("a"), self.a.as_ptr(), String::new()),
+            (String::from("b"), self.b.as_ptr(), String::new()),
+            (String::from("exact"), self.exact.as_ptr(), String::new()),
+        ]
+    }
+}
+
+impl Plugin for DenseAutomationPlugin {
+    const NAME: &'static str = "Dense Automation Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    const SAMPLE_ACCURATE_AUTOMATION: bool = true;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let values = [
+            self.params.a.value(),
+            self.params.b.value(),
+            self.params.exact.value(),
+        ];
+        permit_alloc(|| self.blocks.push((buffer.len(), values)));
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for DenseAutomationPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugDenseAuto";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Has a read-only clip indicator that gets set whenever the input exceeds 0 dBFS.
+#[derive(Default)]
+struct ClipPlugin {
+    params: Arc<ClipParams>,
+}
+
+struct ClipParams {
+    clip: BoolParam,
+}
+
+impl Default for ClipParams {
+    fn default() -> Self {
+        Self {
+            clip: BoolParam::new("Input clip", false).read_only(),
+        }
+    }
+}
+
+unsafe impl Params for ClipParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("clip"), self.clip.as_ptr(), String::new())]
+    }
+}
+
+impl Plugin for ClipPlugin {
+    const NAME: &'static str = "Clip Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 1;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let clipping = buffer.as_slice_immutable()[0]
+            .iter()
+            .any(|sample| sample.abs() > 1.0);
+        context.set_output_parameter(&self.params.clip, clipping);
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for ClipPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugClipTest0";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// The values hosts, editors, and state objects may send that should never end up in a
+/// parameter, and the gain parameter's normalized value after trying to set them starting
+/// from 0.5.
+const INVALID_PARAM_VALUES: [(f32, f32); 4] =
+    [(f32::NAN, 0.5), (-1.0, 0.0), (2.0, 1.0), (1e30, 1.0)];
+
+/// Invalid values sent by the host and the editor should be clamped or ignored, so the plugin
+/// only ever sees valid parameter values.
+#[test]
+fn invalid_param_values() {
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 0.5).activate();
+        let gain_hash = hash_param_id("gain");
+        let gain_ptr = wrapper.inner.param_by_hash[&gain_hash];
+        let params = wrapper.inner.plugin.lock().params.clone();
+        let gui_context = wrapper.inner.clone().make_gui_context();
+        let setter = ParamSetter::new(&*gui_context);
+
+        let mut output = Vec::new();
+        let mut check_path = |set_value: &dyn Fn(f32)| {
+            for (value, expected) in INVALID_PARAM_VALUES {
+                assert_eq!(wrapper.set_param_normalized(gain_hash, 0.5), kResultOk);
+                process_block(&wrapper, &mut output);
+
+                set_value(value);
+                process_block(&wrapper, &mut output);
+                assert_eq!(gain_ptr.unmodulated_normalized_value(), expected, "{value}");
+                assert!(
+                    output.iter().all(|sample| (0.0..=1.0).contains(sample)),
+                    "{value}"
+                );
+                output.clear();
+            }
+        };
+
+        check_path(&|value| {
+            wrapper.set_param_normalized(gain_hash, value as f64);
+        });
+        check_path(&|value| {
+            let param_changes = TestParameterChanges::new(&[(gain_hash, value as f64)]);
+            process_block_with(
+                &wrapper,
+                &mut Vec::new(),
+                ptr::null_mut(),
+                Some(&param_changes),
+            );
+        });
+        check_path(&|value| setter.set_parameter_with_gesture(&params.gain, value));
+        check_path(&|value| {
+            setter.begin_set_parameter(&params.gain);
+            setter.set_parameter_normalized(&params.gain, value);
+            setter.end_set_parameter(&params.gain);
+        });
+
+        // The editor's NaNs are already caught by the `ParamSetter`
+        assert_eq!(wrapper.inner.param_value_validator.num_rejected(), 2);
+
+        deactivate(&wrapper);
+    }
+}
+
+/// The same as [`invalid_param_values()`], but for values loaded from a state object.
+#[test]
+fn invalid_state_param_values() {
+    unsafe {
+        for (value, expected) in INVALID_PARAM_VALUES {
+            let wrapper = Wrapper::<DcPlugin>::new();
+            let gain_hash = hash_param_id("gain");
+            let gain_ptr = wrapper.inner.param_by_hash[&gain_hash];
+            assert_eq!(wrapper.set_param_normalized(gain_hash, 0.5), kResultOk);
+
+            wrapper.inner.set_state_object(PluginState {
+                version: String::from(DcPlugin::VERSION),
+                params: [(String::from("gain"), ParamValue::F32(value))].into(),
+                fields: Default::default(),
+                instance_id: None,
+            });
+            assert_eq!(gain_ptr.unmodulated_normalized_value(), expected, "{value}");
+            assert_eq!(
+                wrapper.inner.param_value_validator.num_rejected(),
+                value.is_nan() as u32
+            );
+
+            activate(&wrapper);
+            let mut output = Vec::new();
+            process_block(&wrapper, &mut output);
+            assert!(output.iter().all(|sample| *sample == expected), "{value}");
+            deactivate(&wrapper);
+        }
+    }
+}
+
+#[test]
+fn midi_cc_mapping() {
+    unsafe {
+        let wrapper = Wrapper::<MidiCcPlugin>::new();
+        let mut param_id = kNoParamId;
+        assert_eq!(
+            wrapper.get_midi_controller_assignment(0, 0, 1, &mut param_id),
+            kResultOk
+        );
+        assert_eq!(param_id, hash_param_id("gain"));
+
+        // The plugin doesn't accept MIDI CCs, so everything else is left unmapped
+        for (channel, cc) in [(1, 1), (0, 2), (0, 64), (0, 129)] {
+            assert_eq!(
+                wrapper.get_midi_controller_assignment(0, channel, cc, &mut param_id),
+                kResultFalse
+            );
+        }
+    }
+}
+
+/// Changing a parameter while the plugin is not processing audio should also reset its
+/// smoother, so the next process call starts at the new value instead of ramping towards it.
+#[test]
+fn set_param_while_not_processing() {
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 0.0).activate();
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(
+            wrapper.set_param_normalized(hash_param_id("gain"), 0.5),
+            kResultOk
+        );
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+
+        output.clear();
+        process_block(&wrapper, &mut output);
+        deactivate(&wrapper);
+
+        assert!(output.iter().all(|sample| *sample == 0.5));
+    }
+}
+
+/// Changes made through `setParamNormalized()` while processing should be applied at the start
+/// of the next block, unless that block's parameter queues also change the same parameter.
+#[test]
+fn set_param_while_processing() {
+    let gain_hash = hash_param_id("gain");
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 0.0).activate();
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+
+        // A change that is only sent through the edit controller
+        assert_eq!(wrapper.set_param_normalized(gain_hash, 0.5), kResultOk);
+        output.clear();
+        process_block(&wrapper, &mut output);
+        assert_eq!(*output.last().unwrap(), 0.5);
+
+        // A change that is sent through both channels, with the parameter queue winning
+        assert_eq!(wrapper.set_param_normalized(gain_hash, 0.25), kResultOk);
+        let param_changes = TestParameterChanges::new(&[(gain_hash, 1.0)]);
+        output.clear();
+        process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+        assert_eq!(*output.last().unwrap(), 1.0);
+
+        // The discarded change should also not be applied later
+        output.clear();
+        process_block(&wrapper, &mut output);
+        assert!(output.iter().all(|sample| *sample == 1.0));
+
+        // Changes to other parameters in the same block don't affect edit controller changes
+        assert_eq!(wrapper.set_param_normalized(gain_hash, 0.75), kResultOk);
+        let param_changes = TestParameterChanges::new(&[(hash_param_id(BYPASS_PARAM_ID), 0.0)]);
+        output.clear();
+        process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+        assert_eq!(*output.last().unwrap(), 0.75);
+
+        // Unknown parameters are still rejected
+        assert_eq!(
+            wrapper.set_param_normalized(hash_param_id("does_not_exist"), 0.5),
+            kInvalidArgument
+        );
+
+        deactivate(&wrapper);
+    }
+}
+
+/// Changes made right before processing stops should not get lost.
+#[test]
+fn set_param_before_processing_stops() {
+    unsafe {
+        let wrapper = Fixture::<DcPlugin>::new().param("gain", 0.0).activate();
+        let mut output = Vec::new();
+        process_block(&wrapper, &mut output);
+
+        assert_eq!(
+            wrapper.set_param_normalized(hash_param_id("gain"), 0.5),
+            kResultOk
+        );
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+
+        output.clear();
+        process_block(&wrapper, &mut output);
+        deactivate(&wrapper);
+
+        assert!(output.iter().all(|sample| *sample == 0.5));
+    }
+}
+
+/// Decibel gain parameters should round trip their edge values through the host's string
+/// conversion functions.
+#[test]
+fn gain_db_string_conversion() {
+    unsafe fn value_to_string(wrapper: &Wrapper<GainDbPlugin>, normalized: f64) -> String {
+        let mut string = [0 as TChar; 128];
+        assert_eq!(
+            wrapper.get_param_string_by_value(
+                hash_param_id("gain"),
+                normalized,
+                string.as_mut_ptr()
+            ),
+            kResultOk
+        );
+
+        U16CStr::from_ptr_str(string.as_ptr() as *const u16).to_string_lossy()
+    }
+
+    unsafe fn string_to_value(wrapper: &Wrapper<GainDbPlugin>, string: &str) -> Option<f64> {
+        let string: Vec<u16> = string.encode_utf16().chain([0]).collect();
+        let mut normalized = 0.0;
+        match wrapper.get_param_value_by_string(
+            hash_param_id("gain"),
+            string.as_ptr() as *const TChar,
+            &mut normalized,
+        ) {
+            n if n == kResultOk => Some(normalized),
+            _ => None,
+        }
+    }
+
+    unsafe {
+        let wrapper = Wrapper::<GainDbPlugin>::new();
+
+        assert_eq!(value_to_string(&wrapper, 0.0), "-inf");
+        assert_eq!(value_to_string(&wrapper, 1.0), "12.00");
+        for string in ["-inf", "-inf dB", "-∞", "−∞ dB"] {
+            assert_eq!(string_to_value(&wrapper, string), Some(0.0), "{string}");
+        }
+
+        let max = string_to_value(&wrapper, "12 dB").unwrap();
+        assert!((max - 1.0).abs() < 1e-6);
+        let unity = string_to_value(&wrapper, "0").unwrap();
+        assert_eq!(value_to_string(&wrapper, unity), "0.00");
+        assert_eq!(string_to_value(&wrapper, "loud"), None);
+    }
+}
+
+/// Hidden parameters should not be enumerated to the host, but they should still be settable by
+/// ID and they should be included in the plugin's state.
+#[test]
+fn hidden_and_non_automatable_params() {
+    unsafe {
+        let wrapper = Wrapper::<VisibilityPlugin>::new();
+        let visible_hash = hash_param_id("visible");
+        let hidden_hash = hash_param_id("hidden");
+        let non_automatable_hash = hash_param_id("non_automatable");
+        let bypass_hash = hash_param_id(BYPASS_PARAM_ID);
+
+        // The wrapper also adds its own bypass parameter
+        assert_eq!(wrapper.get_parameter_count(), 3);
+        let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+        let mut enumerated = Vec::new();
+        for idx in 0..wrapper.get_parameter_count() {
+            assert_eq!(wrapper.get_parameter_info(idx, &mut info), kResultOk);
+            enumerated.push((info.id, info.flags));
+        }
+        assert_eq!(
+            wrapper.get_parameter_info(wrapper.get_parameter_count(), &mut info),
+            kInvalidArgument
+        );
+
+        let can_automate = ParameterFlags::kCanAutomate as i32;
+        let ids: Vec<u32> = enumerated.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [visible_hash, non_automatable_hash, bypass_hash]);
+        assert_ne!(enumerated[0].1 & can_automate, 0);
+        assert_eq!(enumerated[1].1 & can_automate, 0);
+
+        // The indices should line up with the parameters' own defaults despite the gap left by
+        // the hidden parameter
+        for idx in 0..2 {
+            assert_eq!(wrapper.get_parameter_info(idx, &mut info), kResultOk);
+            let param_ptr = wrapper.inner.param_by_hash[&info.id];
+            assert_eq!(
+                info.default_normalized_value,
+                param_ptr.default_normalized_value() as f64
+            );
+        }
+
+        let hidden_ptr = wrapper.inner.param_by_hash[&hidden_hash];
+        assert_eq!(wrapper.set_param_normalized(hidden_hash, 0.75), kResultOk);
+        assert_eq!(hidden_ptr.unmodulated_normalized_value(), 0.75);
+        assert_eq!(
+            wrapper.set_param_normalized(non_automatable_hash, 0.5),
+            kResultOk
+        );
+
+        // Both kinds of parameters need to survive a state round trip
+        let state = wrapper.inner.get_state_object();
+        assert!(
+            matches!(state.params.get("hidden"), Some(ParamValue::F32(value)) if *value == 0.75)
+        );
+        assert!(matches!(
+            state.params.get("non_automatable"),
+            Some(ParamValue::F32(value)) if *value == 0.5
+        ));
+
+        let restored = Wrapper::<VisibilityPlugin>::new();
+        restored.inner.set_state_object(state);
+        assert_eq!(
+            restored.inner.param_by_hash[&hidden_hash].unmodulated_normalized_value(),
+            0.75
+        );
+        assert_eq!(
+            restored.inner.param_by_hash[&non_automatable_hash].unmodulated_normalized_value(),
+            0.5
+        );
+    }
+}
+
+#[test]
+fn defaults_ignore_values_at_construction() {
+    unsafe {
+        let wrapper = Wrapper::<ModifiedParamsPlugin>::new();
+        let visible_hash = hash_param_id("visible");
+        let non_automatable_hash = hash_param_id("non_automatable");
+
+        // The host should see the parameters' current values, but their declared defaults
+        let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+        for (idx, hash, value, default) in [
+            (0, visible_hash, 0.9, 0.1),
+            (1, non_automatable_hash, 0.8, 0.3),
+        ] {
+            assert_eq!(wrapper.get_parameter_info(idx, &mut info), kResultOk);
+            assert_eq!(info.id, hash);
+            assert!((info.default_normalized_value - default).abs() < 1e-6);
+            assert!((wrapper.get_param_normalized(hash) - value).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn linear_plain_automation() {
+    unsafe {
+        let wrapper = Fixture::<PlainAutomationPlugin>::new().activate();
+        let hash = hash_param_id("curved");
+        let param_ptr = wrapper.inner.param_by_hash[&hash];
+
+        // The host ramps the parameter from its minimum to its maximum over the first 96
+        // samples. The plugin should follow a straight line in plain values along the way,
+        // updated every `LINEAR_PLAIN_AUTOMATION_INTERVAL` samples.
+        const RAMP_END: usize = 96;
+        let param_changes = TestParameterChanges::with_points(&[(hash, &[(0, 0.0), (96, 1.0)])]);
+        let mut output = Vec::new();
+        process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+        for (sample_idx, &sample) in output.iter().enumerate() {
+            let update_idx = if sample_idx < RAMP_END {
+                sample_idx / LINEAR_PLAIN_AUTOMATION_INTERVAL as usize
+                    * LINEAR_PLAIN_AUTOMATION_INTERVAL as usize
+            } else {
+                RAMP_END
+            };
+            let expected = 0.25 + (4.0 - 0.25) * (update_idx as f32 / RAMP_END as f32);
+            assert!(
+                (sample - expected).abs() < 1e-4,
+                "Sample {sample_idx}: expected {expected}, got {sample}"
+            );
+        }
+
+        // Halfway through the ramp, interpolating the normalized values would have resulted
+        // in a very different value
+        assert!((param_ptr.preview_plain(0.5) - output[RAMP_END / 2]).abs() > 0.5);
+
+        // With only a single point there's no ramp to follow
+        let param_changes = TestParameterChanges::with_points(&[(hash, &[(64, 0.0)])]);
+        let mut output = Vec::new();
+        process_block_with(&wrapper, &mut output, ptr::null_mut(), Some(&param_changes));
+        assert!(output[..64].iter().all(|&sample| sample == 4.0));
+        assert!(output[64..].iter().all(|&sample| sample == 0.25));
+
+        deactivate(&wrapper);
+    }
+}
+
+/// A host sending 1000 automation points across three parameters in a single 512 sample block
+/// should result in a bounded number of sub-blocks that each use the values the parameters
+/// had at their last sample.
+#[test]
+fn dense_automation_is_coalesced() {
+    const NUM_SAMPLES: usize = 512;
+
+    unsafe {
+        let wrapper = Fixture::<DenseAutomationPlugin>::new()
+            .max_block_size(NUM_SAMPLES)
+            .activate();
+
+        let a_points: Vec<(i32, f64)> = (0..490)
+            .map(|offset| (offset, offset as f64 / 1000.0))
+            .collect();
+        let b_points: Vec<(i32, f64)> = (22..512)
+            .map(|offset| (offset, 1.0 - offset as f64 / 512.0))
+            .collect();
+        let exact_points: Vec<(i32, f64)> =
+            (1..=20).map(|idx| (idx * 24, idx as f64 / 20.0)).collect();
+        let param_changes = TestParameterChanges::with_points(&[
+            (hash_param_id("a"), &a_points),
+            (hash_param_id("b"), &b_points),
+            (hash_param_id("exact"), &exact_points),
+        ]);
+        assert_eq!(param_changes.points().len(), 1000);
+
+        let mut channel = [0.0f32; NUM_SAMPLES];
+        let mut channel_ptrs = [channel.as_mut_ptr()];
+        let mut output_bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+        output_bus.num_channels = 1;
+        output_bus.buffers = channel_ptrs.as_mut_ptr() as _;
+        let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+        data.process_mode = ProcessModes::kRealtime as i32;
+        data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+        data.num_samples = NUM_SAMPLES as i32;
+        data.num_outputs = 1;
+        data.outputs = &mut output_bus;
+        data.input_param_changes = mem::transmute(&*param_changes as *const _);
+        assert_eq!(wrapper.process(&mut data), kResultOk);
+
+        // The value each parameter should have at a sample, with the parameters starting out
+        // at zero
+        let value_at = |points: &[(i32, f64)], sample: usize| {
+            points
+                .iter()
+                .take_while(|&&(offset, _)| offset as usize <= sample)
+                .last()
+                .map_or(0.0, |&(_, value)| value as f32)
+        };
+
+        let blocks = wrapper.inner.plugin.lock().blocks.clone();
+        assert!(blocks.len() <= DenseAutomationPlugin::MAX_AUTOMATION_SPLITS as usize + 1);
+        let mut block_start = 0;
+        for &(num_samples, values) in &blocks {
+            let last_sample = block_start + num_samples - 1;
+            let expected = [
+                value_at(&a_points, last_sample),
+                value_at(&b_points, last_sample),
+                value_at(&exact_points, last_sample),
+            ];
+            assert_eq!(values, expected, "{blocks:?}");
+            // The exact parameter's changes should not have moved
+            assert_eq!(
+                values[2],
+                value_at(&exact_points, block_start),
+                "{blocks:?}"
+            );
+
+            block_start += num_samples;
+        }
+        assert_eq!(block_start, NUM_SAMPLES);
+
+        // All parameters end up at their final values
+        let params = wrapper.inner.plugin.lock().params.clone();
+        assert_eq!(params.a.value(), value_at(&a_points, NUM_SAMPLES));
+        assert_eq!(params.b.value(), value_at(&b_points, NUM_SAMPLES));
+        assert_eq!(params.exact.value(), 1.0);
+        assert!(
+            wrapper
+                .inner
+                .num_coalesced_param_changes
+                .load(Ordering::SeqCst)
+                >= 1000 - 3 * blocks.len() as u32
+        );
+
+        deactivate(&wrapper);
+    }
+}
+
+#[test]
+fn read_only_output_param() {
+    /// Process a block with a constant `input` level, and return the parameter changes the
+    /// plugin sent back to the host.
+    unsafe fn process_clip_block(
+        wrapper: &Wrapper<ClipPlugin>,
+        input: f32,
+        input_param_changes: &TestParameterChanges,
+    ) -> Vec<(u32, i32, f64)> {
+        let mut channel = [input; BLOCK_SIZE];
+        let mut channel_ptrs = [channel.as_mut_ptr()];
+        let mut bus: vst3_sys::vst::AudioBusBuffers = mem::zeroed();
+        bus.num_channels = 1;
+        bus.buffers = channel_ptrs.as_mut_ptr() as _;
+        let bus_ptr: *mut vst3_sys::vst::AudioBusBuffers = &mut bus;
+        let output_param_changes = TestParameterChanges::new(&[]);
+
+        // The input and output share the same buffer, like most hosts do
+        let mut data: vst3_sys::vst::ProcessData = mem::zeroed();
+        data.process_mode = ProcessModes::kRealtime as i32;
+        data.symbolic_sample_size = vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32;
+        data.num_samples = BLOCK_SIZE as i32;
+        data.num_inputs = 1;
+        data.inputs = bus_ptr;
+        data.num_outputs = 1;
+        data.outputs = bus_ptr;
+        data.input_param_changes =
+            mem::transmute(input_param_changes as *const TestParameterChanges);
+        data.output_param_changes =
+            mem::transmute(&*output_param_changes as *const TestParameterChanges);
+        assert_eq!(wrapper.process(&mut data), kResultOk);
+
+        output_param_changes.points()
+    }
+
+    unsafe {
+        let wrapper = Wrapper::<ClipPlugin>::new();
+        let clip_hash = hash_param_id("clip");
+        let clip_param = wrapper.inner.param_by_hash[&clip_hash];
+
+        // The wrapper also adds its own bypass parameter
+        let mut info: vst3_sys::vst::ParameterInfo = mem::zeroed();
+        let clip_param_idx = (0..wrapper.get_parameter_count())
+            .find(|&idx| {
+                assert_eq!(wrapper.get_parameter_info(idx, &mut info), kResultOk);
+                info.id == clip_hash
+            })
+            .unwrap();
+        assert_eq!(
+            wrapper.get_parameter_info(clip_param_idx, &mut info),
+            kResultOk
+        );
+        assert_ne!(info.flags & ParameterFlags::kIsReadOnly as i32, 0);
+        assert_eq!(info.flags & ParameterFlags::kCanAutomate as i32, 0);
+
+        // The host cannot change the parameter, neither before nor during processing
+        assert_eq!(wrapper.set_param_normalized(clip_hash, 1.0), kResultFalse);
+        assert_eq!(clip_param.modulated_normalized_value(), 0.0);
+        activate(&*wrapper);
+        assert_eq!(wrapper.set_param_normalized(clip_hash, 1.0), kResultFalse);
+
+        let changes = process_clip_block(
+            &wrapper,
+            0.5,
+            &TestParameterChanges::new(&[(clip_hash, 1.0)]),
+        );
+        assert_eq!(clip_param.modulated_normalized_value(), 0.0);
+        assert_eq!(changes, [(clip_hash, 0, 0.0)]);
+
+        // But the plugin can, and those changes are sent to the host
+        let changes = process_clip_block(&wrapper, 1.5, &TestParameterChanges::new(&[]));
+        assert_eq!(clip_param.modulated_normalized_value(), 1.0);
+        assert_eq!(changes, [(clip_hash, 0, 1.0)]);
+
+        deactivate(&wrapper);
+    }
+}
+
+/// Read-only parameters are set by the plugin itself, so they should neither be saved nor
+/// restored as part of the plugin's state.
+#[test]
+fn read_only_params_not_saved() {
+    unsafe {
+        let wrapper = Fixture::<ClipPlugin>::new().activate();
+        let clip_param = wrapper.inner.param_by_hash[&hash_param_id("clip")];
+
+        process_channels(&*wrapper, &[vec![1.5; BLOCK_SIZE]], 1);
+        assert_eq!(clip_param.modulated_normalized_value(), 1.0);
+
+        let state = wrapper.inner.get_state_object();
+        assert!(!state.params.contains_key("clip"));
+
+        // States saved by older versions may still contain the parameter's value
+        process_channels(&*wrapper, &[vec![0.5; BLOCK_SIZE]], 1);
+        assert_eq!(clip_param.modulated_normalized_value(), 0.0);
+        wrapper.inner.set_state_object(PluginState {
+            version: String::from(ClipPlugin::VERSION),
+            params: [(String::from("clip"), ParamValue::Bool(true))].into(),
+            fields: Default::default(),
+            instance_id: None,
+        });
+        assert_eq!(clip_param.modulated_normalized_value(), 0.0);
+
+        deactivate(&wrapper);
+    }
+}
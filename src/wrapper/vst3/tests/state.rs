@@ -0,0 +1,254 @@
+//! Tests for saving and restoring the plugin's state.
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::thread;
+use vst3_sys::base::kResultOk;
+
+use super::fixture::{activate, deactivate, load_state, save_state, Fixture};
+use crate::buffer::Buffer;
+use crate::context::init::InitContext;
+use crate::context::process::ProcessContext;
+use crate::params::internals::ParamPtr;
+use crate::params::range::FloatRange;
+use crate::params::smoothing::SmoothingStyle;
+use crate::params::{FloatParam, Param, Params};
+use crate::plugin::{AuxiliaryBuffers, BufferConfig, BusConfig, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::state::{ParamValue, PluginState};
+use crate::wrapper::vst3::wrapper::Wrapper;
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// Has a persistent field and records when its state was restored, relative to when it was
+/// initialized.
+#[derive(Default)]
+struct StateRestorePlugin {
+    params: Arc<StateRestoreParams>,
+    /// The calls to `state_restored()` and `initialize()`, in the order they were made.
+    calls: Vec<&'static str>,
+    /// The persistent field's value during the last `initialize()` call.
+    initialized_with: Option<String>,
+}
+
+struct StateRestoreParams {
+    gain: FloatParam,
+    mode: Mutex<String>,
+}
+
+impl Default for StateRestoreParams {
+    fn default() -> Self {
+        Self {
+            gain: FloatParam::new("Gain", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_smoother(SmoothingStyle::Linear(10.0)),
+            mode: Mutex::new(String::from("default")),
+        }
+    }
+}
+
+unsafe impl Params for StateRestoreParams {
+    fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+        vec![(String::from("gain"), self.gain.as_ptr(), String::new())]
+    }
+
+    fn serialize_fields(&self) -> BTreeMap<String, String> {
+        [(
+            String::from("mode"),
+            serde_json::to_string(&*self.mode.lock()).unwrap(),
+        )]
+        .into()
+    }
+
+    fn deserialize_fields(&self, serialized: &BTreeMap<String, String>) {
+        if let Some(mode) = serialized.get("mode") {
+            *self.mode.lock() = serde_json::from_str(mode).unwrap();
+        }
+    }
+}
+
+impl Plugin for StateRestorePlugin {
+    const NAME: &'static str = "State Restore Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        self.params.clone()
+    }
+
+    fn state_restored(&mut self) {
+        self.calls.push("state_restored");
+    }
+
+    fn initialize(
+        &mut self,
+        _bus_config: &BusConfig,
+        _buffer_config: &BufferConfig,
+        _context: &mut impl InitContext<Self>,
+    ) -> bool {
+        self.calls.push("initialize");
+        self.initialized_with = Some(self.params.mode.lock().clone());
+        true
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        _context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for StateRestorePlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugStateRest";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+/// Load a state with the given gain and persistent field through `IComponent::setState()`.
+unsafe fn load_restore_state(wrapper: &Wrapper<StateRestorePlugin>, gain: f32, mode: &str) {
+    let state = PluginState {
+        version: String::from(StateRestorePlugin::VERSION),
+        params: [(String::from("gain"), ParamValue::F32(gain))].into(),
+        fields: [(String::from("mode"), serde_json::to_string(mode).unwrap())].into(),
+        instance_id: None,
+    };
+    assert_eq!(
+        load_state(wrapper, serde_json::to_vec(&state).unwrap()),
+        kResultOk
+    );
+}
+
+/// Hosts load state before activating the plugin, after activating it, or both. The plugin
+/// should end up in the same state regardless, and it should be told about the restored state
+/// right before it gets initialized.
+#[test]
+fn state_restore_order() {
+    unsafe {
+        let before = Wrapper::<StateRestorePlugin>::new();
+        load_restore_state(&before, 0.75, "loaded");
+        activate(&*before);
+
+        let after = Fixture::<StateRestorePlugin>::new().activate();
+        load_restore_state(&after, 0.75, "loaded");
+
+        let both = Wrapper::<StateRestorePlugin>::new();
+        load_restore_state(&both, 0.25, "first");
+        activate(&*both);
+        load_restore_state(&both, 0.75, "loaded");
+
+        // Loading state twice before initializing the plugin only notifies the plugin once
+        let twice = Wrapper::<StateRestorePlugin>::new();
+        load_restore_state(&twice, 0.25, "first");
+        load_restore_state(&twice, 0.75, "loaded");
+        activate(&*twice);
+
+        let expected_smoother = before.inner.plugin.lock().params.gain.smoothed.snapshot();
+        assert_eq!(expected_smoother.current(), 0.75);
+        assert_eq!(expected_smoother.steps_left(), 0);
+        for (wrapper, expected_calls) in [
+            (&before, &["state_restored", "initialize"][..]),
+            (&after, &["initialize", "state_restored", "initialize"][..]),
+            (
+                &both,
+                &[
+                    "state_restored",
+                    "initialize",
+                    "state_restored",
+                    "initialize",
+                ][..],
+            ),
+            (&twice, &["state_restored", "initialize"][..]),
+        ] {
+            let plugin = wrapper.inner.plugin.lock();
+            assert_eq!(plugin.calls, expected_calls);
+            assert_eq!(plugin.initialized_with.as_deref(), Some("loaded"));
+            assert_eq!(*plugin.params.mode.lock(), "loaded");
+            assert_eq!(plugin.params.gain.value(), 0.75);
+
+            let smoother = plugin.params.gain.smoothed.snapshot();
+            assert_eq!(smoother.current(), expected_smoother.current());
+            assert_eq!(smoother.target(), expected_smoother.target());
+            assert_eq!(smoother.steps_left(), expected_smoother.steps_left());
+        }
+
+        for wrapper in [before, after, both, twice] {
+            deactivate(&wrapper);
+        }
+    }
+}
+
+/// Hosts may load state from multiple threads at the same time. The parameters and persistent
+/// fields should always end up coming from the same state.
+#[test]
+fn concurrent_state_loads() {
+    const NUM_ITERATIONS: usize = 100;
+
+    /// The wrapper itself is not `Sync` because of the COM vtable pointers, but the host can
+    /// still call it from multiple threads.
+    struct SendPtr(*const Wrapper<StateRestorePlugin>);
+    unsafe impl Send for SendPtr {}
+
+    unsafe {
+        let wrapper = Fixture::<StateRestorePlugin>::new().activate();
+
+        let threads: Vec<_> = [(0.25, "a"), (0.75, "b")]
+            .into_iter()
+            .map(|(gain, mode)| {
+                let host_ptr = SendPtr(&*wrapper);
+                thread::spawn(move || {
+                    let host_ptr = host_ptr;
+                    for _ in 0..NUM_ITERATIONS {
+                        load_restore_state(&*host_ptr.0, gain, mode);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        {
+            let plugin = wrapper.inner.plugin.lock();
+            let state = (
+                plugin.params.gain.value(),
+                plugin.params.mode.lock().clone(),
+            );
+            assert!(
+                state == (0.25, String::from("a")) || state == (0.75, String::from("b")),
+                "Inconsistent state after concurrent loads: {state:?}"
+            );
+            assert_eq!(plugin.initialized_with.as_ref(), Some(&state.1));
+        }
+
+        deactivate(&wrapper);
+    }
+}
+
+/// States saved before instance IDs were added don't contain an `instance_id` field. Those should
+/// still load, and the instance should then keep its own ID. States that do contain an ID should
+/// restore it.
+#[test]
+fn instance_id_restore() {
+    unsafe {
+        let old_state =
+            br#"{"version":"0.0.0","params":{"gain":{"f32":0.5}},"fields":{"mode":"\"old\""}}"#;
+        let wrapper = Wrapper::<StateRestorePlugin>::new();
+        let own_instance_id = wrapper.inner.instance_id.load();
+        assert_eq!(load_state(&wrapper, old_state.to_vec()), kResultOk);
+        assert_eq!(wrapper.inner.instance_id.load(), own_instance_id);
+        assert_eq!(wrapper.inner.plugin.lock().params.gain.value(), 0.5);
+        assert_eq!(*wrapper.inner.plugin.lock().params.mode.lock(), "old");
+
+        let other = Wrapper::<StateRestorePlugin>::new();
+        assert_ne!(other.inner.instance_id.load(), own_instance_id);
+        assert_eq!(load_state(&other, save_state(&wrapper)), kResultOk);
+        assert_eq!(other.inner.instance_id.load(), own_instance_id);
+    }
+}
@@ -0,0 +1,256 @@
+//! Tests for the transport information the wrapper passes on to the plugin.
+
+use std::mem;
+use std::sync::Arc;
+use vst3_sys::base::kResultOk;
+use vst3_sys::vst::{IAudioProcessor, IProcessContextRequirementsFlags};
+
+use super::fixture::{deactivate, process_block_with, Fixture, NoParams, BLOCK_SIZE, SAMPLE_RATE};
+use crate::buffer::Buffer;
+use crate::context::process::ProcessContext;
+use crate::params::Params;
+use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus, Vst3Plugin};
+use crate::wrapper::vst3::wrapper::{process_context_requirements, Wrapper};
+use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+/// Records the transport information it receives.
+#[derive(Default)]
+struct TransportPlugin {
+    /// The transport information from the last processed block.
+    last_transport: Option<TransportFields>,
+}
+
+/// The raw fields of a [`Transport`][crate::context::process::Transport], without any of the
+/// values the getters compute from other fields.
+#[derive(Debug, PartialEq)]
+struct TransportFields {
+    playing: bool,
+    recording: bool,
+    tempo: Option<f64>,
+    time_sig: Option<(i32, i32)>,
+    continuous_pos_samples: i64,
+    position_jumped: bool,
+    pos_samples: Option<i64>,
+    pos_beats: Option<f64>,
+    bar_start_pos_beats: Option<f64>,
+    loop_range_beats: Option<(f64, f64)>,
+}
+
+impl Plugin for TransportPlugin {
+    const NAME: &'static str = "Transport Test Plugin";
+    const VENDOR: &'static str = "NIH-plug";
+    const URL: &'static str = "";
+    const EMAIL: &'static str = "";
+    const VERSION: &'static str = "0.0.0";
+
+    const DEFAULT_INPUT_CHANNELS: u32 = 0;
+    const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+    type BackgroundTask = ();
+
+    fn params(&self) -> Arc<dyn Params> {
+        Arc::new(NoParams)
+    }
+
+    fn process(
+        &mut self,
+        _buffer: &mut Buffer,
+        _aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
+    ) -> ProcessStatus {
+        let transport = context.transport();
+        self.last_transport = Some(TransportFields {
+            playing: transport.playing,
+            recording: transport.recording,
+            tempo: transport.tempo,
+            time_sig: transport
+                .time_sig_numerator
+                .zip(transport.time_sig_denominator),
+            continuous_pos_samples: transport.continuous_pos_samples,
+            position_jumped: transport.position_jumped,
+            pos_samples: transport.pos_samples,
+            pos_beats: transport.pos_beats,
+            bar_start_pos_beats: transport.bar_start_pos_beats,
+            loop_range_beats: transport.loop_range_beats,
+        });
+
+        ProcessStatus::Normal
+    }
+}
+
+impl Vst3Plugin for TransportPlugin {
+    const VST3_CLASS_ID: [u8; 16] = *b"NihPlugTransport";
+    const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+}
+
+#[test]
+fn default_process_context_requirements() {
+    assert_eq!(
+        process_context_requirements(TransportPlugin::REQUIRED_TRANSPORT),
+        IProcessContextRequirementsFlags::kNeedTransportState
+            | IProcessContextRequirementsFlags::kNeedTempo
+            | IProcessContextRequirementsFlags::kNeedProjectTimeMusic
+    );
+}
+
+/// Even if the host provides more transport information than the plugin asked for, the plugin
+/// should only ever see the information it requested.
+#[test]
+fn unrequested_transport_is_stripped() {
+    unsafe {
+        let wrapper = Fixture::<TransportPlugin>::new().activate();
+
+        let mut context: vst3_sys::vst::ProcessContext = mem::zeroed();
+        // kPlaying | kRecording | kCycleActive | kProjectTimeMusicValid | kTempoValid |
+        // kBarPositionValid | kCycleValid | kTimeSigValid
+        context.state = (1 << 1)
+            | (1 << 3)
+            | (1 << 2)
+            | (1 << 9)
+            | (1 << 10)
+            | (1 << 11)
+            | (1 << 12)
+            | (1 << 13);
+        context.sample_rate = SAMPLE_RATE as f64;
+        context.project_time_samples = 88_200;
+        context.project_time_music = 4.0;
+        context.bar_position_music = 4.0;
+        context.cycle_start_music = 0.0;
+        context.cycle_end_music = 16.0;
+        context.tempo = 120.0;
+        context.time_sig_num = 7;
+        context.time_sig_den = 8;
+
+        let mut output = Vec::new();
+        process_block_with(&wrapper, &mut output, &mut context, None);
+        let last_transport = wrapper.inner.plugin.lock().last_transport.take();
+        deactivate(&wrapper);
+
+        assert_eq!(
+            last_transport,
+            Some(TransportFields {
+                playing: true,
+                recording: true,
+                tempo: Some(120.0),
+                time_sig: None,
+                continuous_pos_samples: 0,
+                position_jumped: false,
+                pos_samples: Some(88_200),
+                pos_beats: Some(4.0),
+                bar_start_pos_beats: None,
+                loop_range_beats: None,
+            })
+        );
+    }
+}
+
+/// Process a block with the given song position and return the continuous sample counter and
+/// position jump flag the plugin saw for that block.
+unsafe fn process_block_at_position(
+    wrapper: &Wrapper<TransportPlugin>,
+    playing: bool,
+    project_time_samples: i64,
+) -> (i64, bool) {
+    let mut context: vst3_sys::vst::ProcessContext = mem::zeroed();
+    context.state = if playing { 1 << 1 } else { 0 }; // kPlaying
+    context.sample_rate = SAMPLE_RATE as f64;
+    context.project_time_samples = project_time_samples;
+
+    let mut output = Vec::new();
+    process_block_with(wrapper, &mut output, &mut context, None);
+    let last_transport = wrapper.inner.plugin.lock().last_transport.take().unwrap();
+
+    (
+        last_transport.continuous_pos_samples,
+        last_transport.position_jumped,
+    )
+}
+
+/// A loop of four blocks that the host cycles through twice. Only the blocks right after the
+/// loop wraps around should be marked as jumps, while the continuous sample counter keeps
+/// counting.
+#[test]
+fn position_jumped_loop_cycling() {
+    const LOOP_START: i64 = 44_100;
+    const BLOCK: i64 = BLOCK_SIZE as i64;
+
+    unsafe {
+        let wrapper = Fixture::<TransportPlugin>::new().activate();
+
+        let mut results = Vec::new();
+        for _ in 0..3 {
+            for block_idx in 0..4 {
+                results.push(process_block_at_position(
+                    &wrapper,
+                    true,
+                    LOOP_START + block_idx * BLOCK,
+                ));
+            }
+        }
+        deactivate(&wrapper);
+
+        for (block_idx, (continuous_pos_samples, position_jumped)) in
+            results.into_iter().enumerate()
+        {
+            assert_eq!(continuous_pos_samples, block_idx as i64 * BLOCK);
+            assert_eq!(
+                position_jumped,
+                block_idx == 4 || block_idx == 8,
+                "Unexpected jump flag for block {block_idx}"
+            );
+        }
+    }
+}
+
+/// Relocating the playhead should be reported both while playing and while the transport is
+/// stopped. The song position doesn't move while stopped, and starting playback from there is
+/// not a jump.
+#[test]
+fn position_jumped_relocation() {
+    const BLOCK: i64 = BLOCK_SIZE as i64;
+
+    unsafe {
+        let wrapper = Fixture::<TransportPlugin>::new().activate();
+
+        // `(playing, project_time_samples, expected_jump)`
+        let steps = [
+            // The very first block is never a jump, even if it doesn't start at zero
+            (true, 1_000, false),
+            (true, 1_000 + BLOCK, false),
+            // Relocate forwards while playing
+            (true, 100_000, true),
+            (true, 100_000 + BLOCK, false),
+            // Stop the transport, the position stays where it was
+            (false, 100_000 + (2 * BLOCK), false),
+            (false, 100_000 + (2 * BLOCK), false),
+            // Relocate backwards while stopped
+            (false, 0, true),
+            (false, 0, false),
+            // And start playback from there again
+            (true, 0, false),
+            (true, BLOCK, false),
+            (true, 2 * BLOCK, false),
+        ];
+        for (step_idx, (playing, project_time_samples, expected_jump)) in
+            steps.into_iter().enumerate()
+        {
+            let (continuous_pos_samples, position_jumped) =
+                process_block_at_position(&wrapper, playing, project_time_samples);
+            assert_eq!(continuous_pos_samples, step_idx as i64 * BLOCK);
+            assert_eq!(
+                position_jumped, expected_jump,
+                "Unexpected jump flag for step {step_idx}"
+            );
+        }
+
+        // Restarting processing starts counting from zero again
+        assert_eq!(wrapper.set_processing(0), kResultOk);
+        assert_eq!(wrapper.set_processing(1), kResultOk);
+        assert_eq!(
+            process_block_at_position(&wrapper, true, 50_000),
+            (0, false)
+        );
+
+        deactivate(&wrapper);
+    }
+}
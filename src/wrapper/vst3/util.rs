@@ -36,27 +36,39 @@ macro_rules! check_null_ptr_msg {
     };
 }
 
-/// The same as [`strlcpy()`], but for VST3's fun UTF-16 strings instead.
-pub fn u16strlcpy(dest: &mut [TChar], src: &str) {
+/// The same as [`strlcpy()`], but for VST3's fun UTF-16 strings instead. When the string needs to
+/// be truncated, this never leaves a lone high surrogate at the end of the string. Returns `true`
+/// if `src` had to be truncated.
+pub fn u16strlcpy(dest: &mut [TChar], src: &str) -> bool {
     if dest.is_empty() {
-        return;
+        return !src.is_empty();
     }
 
     let src_utf16 = match U16CString::from_str(src) {
         Ok(s) => s,
         Err(err) => {
             nih_debug_assert_failure!("Invalid UTF-16 string: {}", err);
-            return;
+            dest[0] = 0;
+            return true;
         }
     };
     let src_utf16_chars = src_utf16.as_slice();
     let src_utf16_chars_signed: &[TChar] =
         unsafe { &*(src_utf16_chars as *const [u16] as *const [TChar]) };
 
-    // Make sure there's always room for a null terminator
-    let copy_len = cmp::min(dest.len() - 1, src_utf16_chars_signed.len());
+    // Make sure there's always room for a null terminator, and don't split surrogate pairs
+    let mut copy_len = cmp::min(dest.len() - 1, src_utf16_chars.len());
+    if copy_len < src_utf16_chars.len()
+        && copy_len > 0
+        && (0xd800..0xdc00).contains(&src_utf16_chars[copy_len - 1])
+    {
+        copy_len -= 1;
+    }
+
     dest[..copy_len].copy_from_slice(&src_utf16_chars_signed[..copy_len]);
     dest[copy_len] = 0;
+
+    copy_len < src_utf16_chars.len()
 }
 
 /// Send+Sync wrapper for these interface pointers.
@@ -94,6 +106,17 @@ impl<T: vst3_sys::ComInterface + ?Sized> From<vst3_sys::VstPtr<T>> for VstPtr<T>
     }
 }
 
+impl<T: IUnknown> ObjectPtr<T> {
+    /// Take ownership of a newly allocated object. Objects start out with a reference count of one,
+    /// and that reference now belongs to the smart pointer. This is used for objects we hand out
+    /// to the host while still needing access to them ourselves.
+    pub fn from_box(obj: Box<T>) -> Self {
+        Self {
+            ptr: Box::into_raw(obj),
+        }
+    }
+}
+
 impl<T: IUnknown> From<&T> for ObjectPtr<T> {
     /// Create a smart pointer for an existing reference counted object.
     fn from(obj: &T) -> Self {
@@ -147,4 +170,87 @@ mod miri {
             "Hello"
         );
     }
+
+    /// Bus names are stored in VST3's 128 character `String128` fields.
+    #[test]
+    fn u16strlcpy_bus_name() {
+        let mut dest = [0; 128];
+        let bus_name = "Sidechain Input ".repeat(10);
+        u16strlcpy(&mut dest, &bus_name);
+
+        assert_eq!(
+            unsafe { U16CStr::from_ptr_str(dest.as_ptr() as *const u16) }
+                .to_string()
+                .unwrap(),
+            bus_name[..127]
+        );
+    }
+
+    #[test]
+    fn u16strlcpy_surrogate_pair_overflow() {
+        // The emoji takes up two UTF-16 code units, and the first one would normally end up in the
+        // last non-null position
+        let mut dest = [0; 8];
+        assert!(u16strlcpy(&mut dest, "Volume 🔊"));
+
+        assert_eq!(
+            unsafe { U16CStr::from_ptr_str(dest.as_ptr() as *const u16) }
+                .to_string()
+                .unwrap(),
+            "Volume "
+        );
+    }
+
+    #[test]
+    fn u16strlcpy_exact_fit() {
+        let mut dest = [0; 10];
+        assert!(!u16strlcpy(&mut dest, "Volume 🔊"));
+        assert!(u16strlcpy(&mut dest, "Volume 🔊!"));
+
+        assert_eq!(
+            unsafe { U16CStr::from_ptr_str(dest.as_ptr() as *const u16) }
+                .to_string()
+                .unwrap(),
+            "Volume 🔊"
+        );
+    }
+
+    /// Every prefix of these strings, copied into every field size used in the VST3 structs, should
+    /// result in a valid null-terminated UTF-16 prefix of the original string.
+    #[test]
+    fn u16strlcpy_always_valid() {
+        const STRINGS: &[&str] = &[
+            "Teodor Woźniak",
+            "Delay Time (µs)",
+            "混响 リバーブ 리버브",
+            "Emoji 🎛️🎚️🔊 Bus",
+            "𝄞𝄢",
+            // An 'e' followed by a combining acute accent, and a family emoji made out of several
+            // code points joined with zero width joiners
+            "Cafe\u{301} 👨‍👩‍👧‍👦",
+        ];
+        const FIELD_SIZES: &[usize] = &[64, 128];
+
+        for src in STRINGS {
+            let src = src.repeat(20);
+            let src_utf16_len = src.encode_utf16().count();
+            for &field_size in FIELD_SIZES {
+                for dest_len in 1..=field_size {
+                    let mut dest = vec![0x55 as TChar; dest_len];
+                    let truncated = u16strlcpy(&mut dest, &src);
+
+                    let result = unsafe { U16CStr::from_ptr_str(dest.as_ptr() as *const u16) }
+                        .to_string()
+                        .expect("Invalid UTF-16");
+                    let result_utf16_len = result.encode_utf16().count();
+                    assert!(src.starts_with(&result));
+                    assert!(result_utf16_len < dest_len);
+                    assert_eq!(truncated, result_utf16_len < src_utf16_len);
+                    if truncated {
+                        assert!(dest_len - 1 - result_utf16_len < 2);
+                    }
+                }
+            }
+        }
+    }
 }
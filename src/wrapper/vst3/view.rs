@@ -1,20 +1,25 @@
 use atomic_float::AtomicF32;
 use parking_lot::{Mutex, RwLock};
 use raw_window_handle::RawWindowHandle;
-use std::any::Any;
 use std::ffi::{c_void, CStr};
 use std::mem;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
-use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, tresult, TBool};
+use std::time::{Duration, Instant};
+use vst3_sys::base::{kInvalidArgument, kResultFalse, kResultOk, kResultTrue, tresult, TBool};
 use vst3_sys::gui::{IPlugFrame, IPlugView, IPlugViewContentScaleSupport, ViewRect};
 use vst3_sys::utils::SharedVstPtr;
+use vst3_sys::vst::IComponentHandler3;
 use vst3_sys::VST3;
 
+use super::context_menu;
 use super::inner::{Task, WrapperInner};
 use super::util::{ObjectPtr, VstPtr};
+use crate::context::gui::HostContextMenu;
 use crate::editor::{Editor, ParentWindowHandle};
 use crate::plugin::Vst3Plugin;
+use crate::wrapper::alloc_stats::AllocPhase;
+use crate::wrapper::editor_lifecycle::EditorLifecycle;
 
 // Alias needed for the VST3 attribute macro
 use vst3_sys as vst3_com;
@@ -41,6 +46,11 @@ const VST3_PLATFORM_UIVIEW: &str = "UIView";
 #[allow(unused)]
 const VST3_PLATFORM_X11_WINDOW: &str = "X11EmbedWindowID";
 
+/// How long to wait for the host to confirm a resize request with a call to
+/// [`IPlugView::on_size()`] before assuming it never will. Until then
+/// [`IPlugView::get_size()`] keeps reporting the size from before the request.
+const RESIZE_CONFIRMATION_TIMEOUT: Duration = Duration::from_millis(500);
+
 /// FIXME: vst3-sys does not allow you to conditionally define fields with #[cfg()], so this is a
 ///        workaround to define the field outside of the struct
 #[cfg(target_os = "linux")]
@@ -54,7 +64,10 @@ struct RunLoopEventHandlerWrapper<P: Vst3Plugin>(std::marker::PhantomData<P>);
 pub(crate) struct WrapperView<P: Vst3Plugin> {
     inner: Arc<WrapperInner<P>>,
     editor: Arc<Mutex<Box<dyn Editor>>>,
-    editor_handle: RwLock<Option<Box<dyn Any>>>,
+    /// Owns the editor's handle between [`IPlugView::attached()`] and [`IPlugView::removed()`].
+    /// If the host releases the view without removing it first, then the editor is closed when the
+    /// view is dropped.
+    editor_lifecycle: EditorLifecycle,
 
     /// The `IPlugFrame` instance passed by the host during [IPlugView::set_frame()].
     plug_frame: RwLock<Option<VstPtr<dyn IPlugFrame>>>,
@@ -68,6 +81,19 @@ pub(crate) struct WrapperView<P: Vst3Plugin> {
     /// the sizes communicated to and from the DAW should be scaled by this factor since NIH-plug's
     /// APIs only deal in logical pixels.
     scaling_factor: AtomicF32,
+    /// Keeps track of resize requests made through [`WrapperView::request_resize()`] that the host
+    /// has not yet confirmed.
+    resize_state: Mutex<ResizeState>,
+}
+
+/// The state of a resize negotiation with the host. Sizes are in physical pixels.
+#[derive(Debug, Default)]
+struct ResizeState {
+    /// The last size the host set through [`IPlugView::on_size()`], if any.
+    confirmed_size: Option<(i32, i32)>,
+    /// The size passed to `IPlugFrame::resize_view()` and the time that happened, if the host has
+    /// not yet called `on_size()` with that size.
+    pending: Option<((i32, i32), Instant)>,
 }
 
 /// Allow handling tasks on the host's GUI thread on Linux. This doesn't need to be a separate
@@ -104,40 +130,42 @@ impl<P: Vst3Plugin> WrapperView<P> {
         Self::allocate(
             inner,
             editor,
-            RwLock::new(None),
+            EditorLifecycle::default(),
             RwLock::new(None),
             #[cfg(target_os = "linux")]
             RunLoopEventHandlerWrapper(RwLock::new(None)),
             #[cfg(not(target_os = "linux"))]
             RunLoopEventHandlerWrapper(Default::default()),
             AtomicF32::new(1.0),
+            Mutex::new(ResizeState::default()),
         )
     }
 
+    /// Whether the editor is currently attached to a window provided by the host.
+    pub fn is_editor_open(&self) -> bool {
+        self.editor_lifecycle.is_open()
+    }
+
     /// Ask the host to resize the view to the size specified by [Editor::size()]. Will return false
-    /// if the host doesn't like you. This **needs** to be run from the GUI thread.
+    /// if the host doesn't like you. This **needs** to be run from the GUI thread. This does not
+    /// wait for the host to call [`IPlugView::on_size()`], since some hosts do that asynchronously
+    /// and others never do it at all. The editor is expected to already have resized itself.
     ///
     /// # Safety
     ///
     /// May cause memory corruption in Linux REAPER when called from outside of the `IRunLoop`.
     pub unsafe fn request_resize(&self) -> bool {
         // Don't do anything if the editor is not open, because that would be strange
-        if self
-            .editor_handle
-            .try_read()
-            .map(|e| e.is_none())
-            .unwrap_or(true)
-        {
+        if !self.editor_lifecycle.is_open() {
             return false;
         }
 
         match &*self.plug_frame.read() {
             Some(plug_frame) => {
-                let (unscaled_width, unscaled_height) = self.editor.lock().size();
-                let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
+                let (width, height) = self.editor_size();
                 let mut size = ViewRect {
-                    right: (unscaled_width as f32 * scaling_factor).round() as i32,
-                    bottom: (unscaled_height as f32 * scaling_factor).round() as i32,
+                    right: width,
+                    bottom: height,
                     ..Default::default()
                 };
 
@@ -145,12 +173,15 @@ impl<P: Vst3Plugin> WrapperView<P> {
                 // `SharedVstPtr`. This _should_ work however.
                 let plug_view: SharedVstPtr<dyn IPlugView> =
                     mem::transmute(&self.__iplugviewvptr as *const *const _);
+                // The host may call `on_size()` from within `resize_view()`, so the lock can't be
+                // held here
+                self.resize_state.lock().pending =
+                    Some(((size.right, size.bottom), Instant::now()));
                 let result = plug_frame.resize_view(plug_view, &mut size);
-
-                debug_assert_eq!(
-                    result, kResultOk,
-                    "The host denied the resize, we currently don't handle this for VST3 plugins"
-                );
+                if result != kResultOk {
+                    nih_debug_assert_failure!("The host denied the resize request");
+                    self.resize_state.lock().pending = None;
+                }
 
                 result == kResultOk
             }
@@ -158,6 +189,22 @@ impl<P: Vst3Plugin> WrapperView<P> {
         }
     }
 
+    /// Ask the host to create the context menu for the parameter with ID `param_hash`. Returns
+    /// `None` if the host does not implement `IComponentHandler3` or if it did not create a menu.
+    pub fn param_context_menu(&self, param_hash: u32) -> Option<HostContextMenu> {
+        let handler = self.inner.component_handler3.borrow();
+        let handler = handler.as_ref()?;
+
+        unsafe {
+            // See `request_resize()`
+            let plug_view: SharedVstPtr<dyn IPlugView> =
+                mem::transmute(&self.__iplugviewvptr as *const *const _);
+            let menu = handler.create_context_menu(plug_view, &param_hash);
+
+            context_menu::wrap_host_context_menu(menu, self.scaling_factor.load(Ordering::Relaxed))
+        }
+    }
+
     /// If the host supports `IRunLoop`, then this will post the task to a task queue that will be
     /// run on the host's UI thread. If not, then this will return an `Err` value containing the
     /// task so it can be run elsewhere.
@@ -177,6 +224,30 @@ impl<P: Vst3Plugin> WrapperView<P> {
     pub fn do_maybe_in_run_loop(&self, task: Task<P>) -> Result<(), Task<P>> {
         Err(task)
     }
+
+    /// The editor's current size in physical pixels.
+    fn editor_size(&self) -> (i32, i32) {
+        let (unscaled_width, unscaled_height) = self.editor.lock().size();
+        self.logical_to_physical(unscaled_width, unscaled_height)
+    }
+
+    /// Convert a size in logical pixels to physical pixels using the scaling factor set by the host.
+    fn logical_to_physical(&self, width: u32, height: u32) -> (i32, i32) {
+        let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
+        (
+            (width as f32 * scaling_factor).round() as i32,
+            (height as f32 * scaling_factor).round() as i32,
+        )
+    }
+
+    /// Convert a size in physical pixels to logical pixels using the scaling factor set by the host.
+    fn physical_to_logical(&self, width: i32, height: i32) -> (u32, u32) {
+        let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
+        (
+            (width as f32 / scaling_factor).round() as u32,
+            (height as f32 / scaling_factor).round() as u32,
+        )
+    }
 }
 
 #[cfg(target_os = "linux")]
@@ -287,8 +358,7 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
     }
 
     unsafe fn attached(&self, parent: *mut c_void, type_: vst3_sys::base::FIDString) -> tresult {
-        let mut editor_handle = self.editor_handle.write();
-        if editor_handle.is_none() {
+        if !self.editor_lifecycle.is_open() {
             let type_ = CStr::from_ptr(type_);
             let handle = match type_.to_str() {
                 #[cfg(all(target_family = "unix", not(target_os = "macos")))]
@@ -315,10 +385,19 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
                 }
             };
 
-            *editor_handle = Some(self.editor.lock().spawn(
-                ParentWindowHandle { handle },
-                self.inner.clone().make_gui_context(),
-            ));
+            self.inner.editor_repaint.request();
+            let opened = self.inner.catch_plugin_panic("opening the editor", || {
+                self.editor_lifecycle.open(|| {
+                    let _alloc_phase = self.inner.alloc_stats.enter(AllocPhase::EditorOpen);
+                    self.editor.lock().spawn(
+                        ParentWindowHandle { handle },
+                        self.inner.clone().make_gui_context(),
+                    )
+                })
+            });
+            if opened.is_none() {
+                return kResultFalse;
+            }
             *self.inner.plug_view.write() = Some(ObjectPtr::from(self));
 
             kResultOk
@@ -332,12 +411,16 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
     }
 
     unsafe fn removed(&self) -> tresult {
-        let mut editor_handle = self.editor_handle.write();
-        if editor_handle.is_some() {
+        if self.editor_lifecycle.is_open() {
             *self.inner.plug_view.write() = None;
-            *editor_handle = None;
-
-            kResultOk
+            // Dropping the editor's handle runs the editor's own cleanup code
+            match self
+                .inner
+                .catch_plugin_panic("closing the editor", || self.editor_lifecycle.close())
+            {
+                Some(_) => kResultOk,
+                None => kResultFalse,
+            }
         } else {
             nih_debug_assert_failure!("Host tried to remove the editor without an active editor");
 
@@ -374,16 +457,30 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
 
         *size = mem::zeroed();
 
-        // TODO: This is technically incorrect during resizing, this should still report the old
-        //       size until `.on_size()` has been called. We should probably only bother fixing this
-        //       if it turns out to be an issue.
-        let (unscaled_width, unscaled_height) = self.editor.lock().size();
-        let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
+        // While a resize request is pending, the host expects the old size to be reported until it
+        // calls `on_size()`. If the host never does that we'll report the editor's new size.
+        let (width, height) = {
+            let resize_state = self.resize_state.lock();
+            match (resize_state.pending, resize_state.confirmed_size) {
+                (Some((_, requested_at)), Some(confirmed_size))
+                    if requested_at.elapsed() < RESIZE_CONFIRMATION_TIMEOUT =>
+                {
+                    confirmed_size
+                }
+                _ => match self
+                    .inner
+                    .catch_plugin_panic("querying the editor's size", || self.editor_size())
+                {
+                    Some(size) => size,
+                    None => return kResultFalse,
+                },
+            }
+        };
         let size = &mut *size;
         size.left = 0;
-        size.right = (unscaled_width as f32 * scaling_factor).round() as i32;
+        size.right = width;
         size.top = 0;
-        size.bottom = (unscaled_height as f32 * scaling_factor).round() as i32;
+        size.bottom = height;
 
         kResultOk
     }
@@ -391,17 +488,41 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
     unsafe fn on_size(&self, new_size: *mut ViewRect) -> tresult {
         check_null_ptr!(new_size);
 
-        // TODO: Implement Host->Plugin resizing
-        let (unscaled_width, unscaled_height) = self.editor.lock().size();
-        let scaling_factor = self.scaling_factor.load(Ordering::Relaxed);
-        let (editor_width, editor_height) = (
-            (unscaled_width as f32 * scaling_factor).round() as i32,
-            (unscaled_height as f32 * scaling_factor).round() as i32,
-        );
-
         let width = (*new_size).right - (*new_size).left;
         let height = (*new_size).bottom - (*new_size).top;
-        if width == editor_width && height == editor_height {
+        {
+            let mut resize_state = self.resize_state.lock();
+            resize_state.confirmed_size = Some((width, height));
+
+            // If this confirms our own resize request then the editor already has this size
+            if matches!(resize_state.pending, Some((size, _)) if size == (width, height)) {
+                resize_state.pending = None;
+                return kResultOk;
+            }
+            resize_state.pending = None;
+        }
+
+        // Otherwise this is a host driven resize, which we'll forward to the editor if it supports
+        // that. The size should already have been snapped through `check_size_constraint()`, but
+        // hosts aren't required to do that.
+        let resized = self.inner.catch_plugin_panic("resizing the editor", || {
+            if (width, height) == self.editor_size() {
+                return true;
+            }
+
+            let editor = self.editor.lock();
+            match editor.size_constraints() {
+                Some(constraints) => {
+                    let (logical_width, logical_height) = self.physical_to_logical(width, height);
+                    let (logical_width, logical_height) =
+                        constraints.constrain(logical_width, logical_height);
+                    editor.set_size(logical_width, logical_height)
+                }
+                None => false,
+            }
+        });
+
+        if resized == Some(true) {
             kResultOk
         } else {
             kResultFalse
@@ -440,18 +561,47 @@ impl<P: Vst3Plugin> IPlugView for WrapperView<P> {
     }
 
     unsafe fn can_resize(&self) -> tresult {
-        // TODO: Implement Host->Plugin resizing
-        kResultFalse
+        let can_resize = self
+            .inner
+            .catch_plugin_panic("querying the editor's size constraints", || {
+                self.editor.lock().size_constraints().is_some()
+            });
+
+        if can_resize == Some(true) {
+            kResultTrue
+        } else {
+            kResultFalse
+        }
     }
 
     unsafe fn check_size_constraint(&self, rect: *mut ViewRect) -> tresult {
         check_null_ptr!(rect);
 
-        // TODO: Implement Host->Plugin resizing
-        if (*rect).right - (*rect).left > 0 && (*rect).bottom - (*rect).top > 0 {
-            kResultOk
-        } else {
-            kResultFalse
+        let rect = &mut *rect;
+        let width = rect.right - rect.left;
+        let height = rect.bottom - rect.top;
+        let constraints = match self
+            .inner
+            .catch_plugin_panic("querying the editor's size constraints", || {
+                self.editor.lock().size_constraints()
+            }) {
+            Some(constraints) => constraints,
+            None => return kResultFalse,
+        };
+        match constraints {
+            Some(constraints) => {
+                let (logical_width, logical_height) =
+                    self.physical_to_logical(width.max(0), height.max(0));
+                let (logical_width, logical_height) =
+                    constraints.constrain(logical_width, logical_height);
+                let (width, height) = self.logical_to_physical(logical_width, logical_height);
+                rect.right = rect.left + width;
+                rect.bottom = rect.top + height;
+
+                kResultOk
+            }
+            None if width > 0 && height > 0 => kResultOk,
+            None => kResultFalse,
         }
     }
 }
@@ -471,7 +621,12 @@ impl<P: Vst3Plugin> IPlugViewContentScaleSupport for WrapperView<P> {
             return kResultFalse;
         }
 
-        if self.editor.lock().set_scale_factor(factor) {
+        let scale_factor_set = self
+            .inner
+            .catch_plugin_panic("setting the editor's scale factor", || {
+                self.editor.lock().set_scale_factor(factor)
+            });
+        if scale_factor_set == Some(true) {
             self.scaling_factor.store(factor, Ordering::Relaxed);
             kResultOk
         } else {
@@ -486,7 +641,7 @@ impl<P: Vst3Plugin> IEventHandler for RunLoopEventHandler<P> {
         // This gets called from the host's UI thread because we wrote some bytes to the Unix domain
         // socket. We'll read that data from the socket again just to make REAPER happy.
         while let Some(task) = self.tasks.pop() {
-            self.inner.execute(task, true);
+            self.inner.execute_gui(task);
 
             let mut notify_value = 1i8;
             const NOTIFY_VALUE_SIZE: usize = std::mem::size_of::<i8>();
@@ -535,3 +690,178 @@ impl<P: Vst3Plugin> Drop for RunLoopEventHandler<P> {
         unsafe { self.run_loop.unregister_event_handler(event_handler) };
     }
 }
+
+// The test attaches the editor to a fake X11 window
+#[cfg(all(test, target_family = "unix", not(target_os = "macos")))]
+mod tests {
+    use std::any::Any;
+    use std::os::raw::c_char;
+    use std::ptr;
+    use std::sync::atomic::AtomicUsize;
+    use vst3_sys::vst::IEditController;
+
+    use super::*;
+    use crate::buffer::Buffer;
+    use crate::context::gui::GuiContext;
+    use crate::context::process::ProcessContext;
+    use crate::params::internals::ParamPtr;
+    use crate::params::range::FloatRange;
+    use crate::params::{FloatParam, Param, Params};
+    use crate::plugin::{AuxiliaryBuffers, Plugin, ProcessStatus};
+    use crate::wrapper::util::hash_param_id;
+    use crate::wrapper::vst3::tests::fixture::{deactivate, process_block, Fixture};
+    use crate::wrapper::vst3_subcategories::Vst3SubCategory;
+
+    /// Has a single parameter that can be changed while the editor is closed.
+    #[derive(Default)]
+    struct EditorPlugin {
+        params: Arc<EditorParams>,
+    }
+
+    struct EditorParams {
+        param: FloatParam,
+    }
+
+    impl Default for EditorParams {
+        fn default() -> Self {
+            Self {
+                param: FloatParam::new("Param", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 }),
+            }
+        }
+    }
+
+    unsafe impl Params for EditorParams {
+        fn param_map(&self) -> Vec<(String, ParamPtr, String)> {
+            vec![(String::from("param"), self.param.as_ptr(), String::new())]
+        }
+    }
+
+    impl Plugin for EditorPlugin {
+        const NAME: &'static str = "Editor Test Plugin";
+        const VENDOR: &'static str = "NIH-plug";
+        const URL: &'static str = "";
+        const EMAIL: &'static str = "";
+        const VERSION: &'static str = "0.0.0";
+
+        const DEFAULT_INPUT_CHANNELS: u32 = 0;
+        const DEFAULT_OUTPUT_CHANNELS: u32 = 1;
+
+        type BackgroundTask = ();
+
+        fn params(&self) -> Arc<dyn Params> {
+            self.params.clone()
+        }
+
+        fn process(
+            &mut self,
+            _buffer: &mut Buffer,
+            _aux: &mut AuxiliaryBuffers,
+            _context: &mut impl ProcessContext<Self>,
+        ) -> ProcessStatus {
+            ProcessStatus::Normal
+        }
+    }
+
+    impl Vst3Plugin for EditorPlugin {
+        const VST3_CLASS_ID: [u8; 16] = *b"NihPlugEditorTst";
+        const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] = &[Vst3SubCategory::Fx];
+    }
+
+    /// An editor that counts how many of its handles are alive. The handles hold on to the GUI
+    /// context like a real editor would.
+    struct CountingEditor {
+        live_handles: Arc<AtomicUsize>,
+    }
+
+    struct CountingEditorHandle {
+        live_handles: Arc<AtomicUsize>,
+        _context: Arc<dyn GuiContext>,
+    }
+
+    impl Drop for CountingEditorHandle {
+        fn drop(&mut self) {
+            self.live_handles.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Editor for CountingEditor {
+        fn spawn(
+            &self,
+            _parent: ParentWindowHandle,
+            context: Arc<dyn GuiContext>,
+        ) -> Box<dyn Any + Send> {
+            self.live_handles.fetch_add(1, Ordering::SeqCst);
+            Box::new(CountingEditorHandle {
+                live_handles: self.live_handles.clone(),
+                _context: context,
+            })
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (640, 480)
+        }
+
+        fn set_scale_factor(&self, _factor: f32) -> bool {
+            false
+        }
+
+        fn param_values_changed(&self) {}
+    }
+
+    /// Opening and closing the editor many times should not leak editor handles or GUI contexts,
+    /// and parameter changes and resize requests made while the editor is closed should be handled
+    /// gracefully.
+    #[test]
+    fn editor_reopen_cycles() {
+        const X11_WINDOW: &[u8] = b"X11EmbedWindowID\0";
+
+        unsafe {
+            let wrapper = Fixture::<EditorPlugin>::new().activate();
+
+            let live_handles = Arc::new(AtomicUsize::new(0));
+            let view = WrapperView::new(
+                wrapper.inner.clone(),
+                Arc::new(Mutex::new(Box::new(CountingEditor {
+                    live_handles: live_handles.clone(),
+                }) as Box<dyn Editor>)),
+            );
+            let gui_context = wrapper.inner.clone().make_gui_context();
+            let inner_refs = Arc::strong_count(&wrapper.inner);
+            let param_hash = hash_param_id("param");
+
+            let mut output = Vec::new();
+            for cycle in 0..100 {
+                assert!(!wrapper.inner.is_editor_open());
+                assert!(!gui_context.request_resize());
+                assert_eq!(view.removed(), kResultFalse);
+                assert_eq!(
+                    wrapper.set_param_normalized(param_hash, (cycle % 2) as f64),
+                    kResultOk
+                );
+                process_block(&wrapper, &mut output);
+
+                assert_eq!(
+                    view.attached(ptr::null_mut(), X11_WINDOW.as_ptr() as *const c_char),
+                    kResultOk
+                );
+                assert!(wrapper.inner.is_editor_open());
+                assert_eq!(live_handles.load(Ordering::SeqCst), 1);
+
+                // Attaching the editor twice should not spawn a second editor
+                assert_eq!(
+                    view.attached(ptr::null_mut(), X11_WINDOW.as_ptr() as *const c_char),
+                    kResultFalse
+                );
+                assert_eq!(live_handles.load(Ordering::SeqCst), 1);
+
+                assert_eq!(view.removed(), kResultOk);
+                assert_eq!(live_handles.load(Ordering::SeqCst), 0);
+                assert_eq!(Arc::strong_count(&wrapper.inner), inner_refs);
+            }
+
+            drop(gui_context);
+            drop(view);
+            deactivate(&wrapper);
+        }
+    }
+}
@@ -5,35 +5,46 @@ use std::ptr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use vst3_com::vst::{IProcessContextRequirementsFlags, ProcessModes};
-use vst3_sys::base::{kInvalidArgument, kNoInterface, kResultFalse, kResultOk, tresult, TBool};
+use vst3_sys::base::{
+    kInvalidArgument, kNoInterface, kNotInitialized, kResultFalse, kResultOk, tresult, TBool,
+};
 use vst3_sys::base::{IBStream, IPluginBase};
 use vst3_sys::utils::SharedVstPtr;
 use vst3_sys::vst::{
-    kNoParamId, kNoParentUnitId, kNoProgramListId, kRootUnitId, Event, EventTypes, IAudioProcessor,
-    IComponent, IEditController, IEventList, IMidiMapping, INoteExpressionController,
-    IParamValueQueue, IParameterChanges, IProcessContextRequirements, IUnitInfo,
-    LegacyMidiCCOutEvent, NoteExpressionTypeInfo, NoteExpressionValueDescription, NoteOffEvent,
-    NoteOnEvent, ParameterFlags, PolyPressureEvent, ProgramListInfo, TChar, UnitInfo,
+    kNoParamId, kNoParentUnitId, kNoProgramListId, kRootUnitId, Event, EventTypes, IAttributeList,
+    IAudioProcessor, IComponent, IComponentHandler2, IComponentHandler3, IEditController,
+    IEventList, IInfoListener, IMidiMapping, INoteExpressionController, IParamValueQueue,
+    IParameterChanges, IProcessContextRequirements, IUnitInfo, LegacyMidiCCOutEvent,
+    NoteExpressionTypeInfo, NoteExpressionValueDescription, NoteOffEvent, NoteOnEvent,
+    ParameterFlags, PolyPressureEvent, ProgramListInfo, RestartFlags, TChar, UnitInfo,
 };
 use vst3_sys::VST3;
 use widestring::U16CStr;
 
-use super::inner::WrapperInner;
+use super::channel_adapter::{self, ChannelAdapter};
+use super::channel_context;
+use super::inner::{Task, WrapperInner};
+use super::param_coalescing;
 use super::util::{
     u16strlcpy, VstPtr, VST3_MIDI_CCS, VST3_MIDI_NUM_PARAMS, VST3_MIDI_PARAMS_START,
 };
 use super::view::WrapperView;
 use crate::buffer::Buffer;
-use crate::context::process::Transport;
+use crate::context::process::{Transport, TransportRequirements};
 use crate::midi::{MidiConfig, NoteEvent};
+use crate::params::changes::ParamChangeSource;
 use crate::params::ParamFlags;
 use crate::plugin::{
     AuxiliaryBuffers, AuxiliaryIOConfig, BufferConfig, BusConfig, ProcessMode, ProcessStatus,
     Vst3Plugin,
 };
 use crate::util::permit_alloc;
+use crate::wrapper::alloc_stats::AllocPhase;
 use crate::wrapper::state;
-use crate::wrapper::util::process_wrapper;
+use crate::wrapper::util::{
+    aux_input_name, aux_output_name, can_change_sample_rate_in_place, main_input_name,
+    main_output_name, process_wrapper, LifecycleState,
+};
 use crate::wrapper::vst3::inner::ProcessEvent;
 use crate::wrapper::vst3::note_expressions::{self, NoteExpressionController};
 use crate::wrapper::vst3::util::{VST3_MIDI_CHANNELS, VST3_MIDI_PARAMS_END};
@@ -41,6 +52,10 @@ use crate::wrapper::vst3::util::{VST3_MIDI_CHANNELS, VST3_MIDI_PARAMS_END};
 // Alias needed for the VST3 attribute macro
 use vst3_sys as vst3_com;
 
+/// The interval in samples at which parameters with [`ParamFlags::LINEAR_PLAIN_AUTOMATION`] are
+/// updated while following an automation ramp.
+pub(super) const LINEAR_PLAIN_AUTOMATION_INTERVAL: u32 = 32;
+
 #[VST3(implements(
     IComponent,
     IEditController,
@@ -48,10 +63,11 @@ use vst3_sys as vst3_com;
     IMidiMapping,
     INoteExpressionController,
     IProcessContextRequirements,
-    IUnitInfo
+    IUnitInfo,
+    IInfoListener
 ))]
 pub(crate) struct Wrapper<P: Vst3Plugin> {
-    inner: Arc<WrapperInner<P>>,
+    pub(super) inner: Arc<WrapperInner<P>>,
 }
 
 impl<P: Vst3Plugin> Wrapper<P> {
@@ -60,6 +76,27 @@ impl<P: Vst3Plugin> Wrapper<P> {
     }
 }
 
+/// Runs when the host releases the last reference to this object. The event loop and the editor's
+/// async executor hold on to the [`WrapperInner`], so those reference cycles need to be broken
+/// here or the plugin would never be dropped. The editor goes first since it can still post tasks
+/// to the event loop, then the event loop's worker thread is joined, and the plugin itself is
+/// dropped together with the last reference to the `WrapperInner` at the end of this function.
+/// Hosts release all views before releasing the plugin, so nothing else should be holding on to
+/// the `WrapperInner` at this point.
+impl<P: Vst3Plugin> Drop for Wrapper<P> {
+    fn drop(&mut self) {
+        drop(self.inner.editor.borrow_mut().take());
+        drop(self.inner.event_loop.borrow_mut().take());
+        self.inner.alloc_stats.log_summary(P::NAME);
+
+        nih_debug_assert_eq!(
+            Arc::strong_count(&self.inner),
+            1,
+            "The wrapper was released while something else still held a reference to it"
+        );
+    }
+}
+
 impl<P: Vst3Plugin> IPluginBase for Wrapper<P> {
     unsafe fn initialize(&self, _context: *mut c_void) -> tresult {
         // We currently don't need or allow any initialization logic
@@ -99,7 +136,7 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
             x if x == vst3_sys::vst::MediaTypes::kAudio as i32
                 && dir == vst3_sys::vst::BusDirections::kInput as i32 =>
             {
-                let main_busses = if P::DEFAULT_INPUT_CHANNELS > 0 { 1 } else { 0 };
+                let main_busses = if has_main_input_bus::<P>() { 1 } else { 0 };
                 let aux_busses = P::DEFAULT_AUX_INPUTS.unwrap_or_default().num_busses as i32;
 
                 main_busses + aux_busses
@@ -158,13 +195,14 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                 // This is fun since main IO is optional
                 let bus_config = self.inner.current_bus_config.load();
                 if dir == vst3_sys::vst::BusDirections::kInput as i32 {
-                    let aux_inputs_only =
-                        P::DEFAULT_INPUT_CHANNELS == 0 && P::DEFAULT_AUX_INPUTS.is_some();
-                    let aux_input_start_idx = if aux_inputs_only { 0 } else { 1 };
-                    if !aux_inputs_only && index == 0 {
+                    // Plugins without a main input don't have an empty main input bus, querying
+                    // it should fail like it would for any other bus that doesn't exist
+                    let has_main_input = has_main_input_bus::<P>();
+                    let aux_input_start_idx = if has_main_input { 1 } else { 0 };
+                    if has_main_input && index == 0 {
                         info.bus_type = vst3_sys::vst::BusTypes::kMain as i32;
                         info.channel_count = bus_config.num_input_channels as i32;
-                        u16strlcpy(&mut info.name, P::PORT_NAMES.main_input.unwrap_or("Input"));
+                        u16strlcpy(&mut info.name, main_input_name::<P>());
 
                         kResultOk
                     } else if (aux_input_start_idx
@@ -174,22 +212,14 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                         info.bus_type = vst3_sys::vst::BusTypes::kAux as i32;
                         info.channel_count = bus_config.aux_input_busses.num_channels as i32;
 
-                        let aux_input_idx = index - aux_input_start_idx;
-                        let custom_port_name = P::PORT_NAMES
-                            .aux_inputs
-                            .map(|names| names[aux_input_idx as usize]);
-                        if bus_config.aux_input_busses.num_busses <= 1 {
-                            u16strlcpy(
-                                &mut info.name,
-                                custom_port_name.unwrap_or("Sidechain Input"),
-                            );
-                        } else {
-                            u16strlcpy(
-                                &mut info.name,
-                                custom_port_name
-                                    .unwrap_or(&format!("Sidechain Input {}", aux_input_idx + 1)),
-                            );
-                        }
+                        let aux_input_idx = (index - aux_input_start_idx) as usize;
+                        u16strlcpy(
+                            &mut info.name,
+                            &aux_input_name::<P>(
+                                aux_input_idx,
+                                bus_config.aux_input_busses.num_busses as usize,
+                            ),
+                        );
 
                         kResultOk
                     } else {
@@ -202,10 +232,7 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                     if (!aux_outputs_only || no_main_audio_io) && index == 0 {
                         info.bus_type = vst3_sys::vst::BusTypes::kMain as i32;
                         info.channel_count = bus_config.num_output_channels as i32;
-                        u16strlcpy(
-                            &mut info.name,
-                            P::PORT_NAMES.main_output.unwrap_or("Output"),
-                        );
+                        u16strlcpy(&mut info.name, main_output_name::<P>());
 
                         kResultOk
                     } else if (aux_output_start_idx
@@ -215,22 +242,14 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                         info.bus_type = vst3_sys::vst::BusTypes::kAux as i32;
                         info.channel_count = bus_config.aux_output_busses.num_channels as i32;
 
-                        let aux_output_idx = index - aux_output_start_idx;
-                        let custom_port_name = P::PORT_NAMES
-                            .aux_outputs
-                            .map(|names| names[aux_output_idx as usize]);
-                        if bus_config.aux_output_busses.num_busses <= 1 {
-                            u16strlcpy(
-                                &mut info.name,
-                                custom_port_name.unwrap_or("Auxiliary Output"),
-                            );
-                        } else {
-                            u16strlcpy(
-                                &mut info.name,
-                                custom_port_name
-                                    .unwrap_or(&format!("Auxiliary Output {}", aux_output_idx + 1)),
-                            );
-                        }
+                        let aux_output_idx = (index - aux_output_start_idx) as usize;
+                        u16strlcpy(
+                            &mut info.name,
+                            &aux_output_name::<P>(
+                                aux_output_idx,
+                                bus_config.aux_output_busses.num_busses as usize,
+                            ),
+                        );
 
                         kResultOk
                     } else {
@@ -333,7 +352,7 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
                 if t == vst3_sys::vst::MediaTypes::kAudio as i32
                     && d == vst3_sys::vst::BusDirections::kInput as i32 =>
             {
-                let main_busses = if P::DEFAULT_INPUT_CHANNELS > 0 { 1 } else { 0 };
+                let main_busses = if has_main_input_bus::<P>() { 1 } else { 0 };
                 let aux_busses = P::DEFAULT_AUX_INPUTS.unwrap_or_default().num_busses as i32;
 
                 if (0..main_busses + aux_busses).contains(&index) {
@@ -381,85 +400,171 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
         // We could call initialize in `IAudioProcessor::setup_processing()`, but REAPER will set
         // the bus arrangements between that function and this function. So to be able to handle
         // custom channel layout overrides we need to initialize here.
+        let lifecycle_state = self.inner.lifecycle_state.load();
         match (state != 0, self.inner.current_buffer_config.load()) {
-            (true, Some(buffer_config)) => {
+            // See `WrapperInner::is_poisoned`, the plugin instance needs to be reloaded by the host
+            (true, _) if self.inner.is_poisoned.load(Ordering::SeqCst) => kResultFalse,
+            (true, Some(host_buffer_config)) => {
+                // The state is updated before initializing the plugin because Ardour calls
+                // `IAudioProcessor::setProcessing(true)` from within this function
+                self.inner.set_lifecycle_state(LifecycleState::Active);
+
+                // A new oversampling factor is only applied here. The plugin sees the oversampled
+                // sample rate and block sizes, and the oversampling filters' latency needs to be
+                // reported to the host even if the plugin doesn't set a new latency during
+                // initialization.
+                self.inner.oversampling.update_factor();
+                self.inner
+                    .update_latency(self.inner.oversampling.host_latency());
+                let buffer_config = self.inner.oversampling.buffer_config(&host_buffer_config);
+
                 // Before initializing the plugin, make sure all smoothers are set the the default values
-                for param in self.inner.param_by_hash.values() {
+                for param in self.inner.param_ptr_to_hash.keys() {
                     param.update_smoother(buffer_config.sample_rate, true);
                 }
 
                 // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
                 let mut init_context = self.inner.make_init_context();
-                let bus_config = self.inner.current_bus_config.load();
+                let bus_config = self.inner.plugin_bus_config.load();
                 let mut plugin = self.inner.plugin.lock();
-                if plugin.initialize(&bus_config, &buffer_config, &mut init_context) {
-                    // NOTE: We don't call `Plugin::reset()` here. The call is done in `set_process()`
-                    //       instead. Otherwise we would call the function twice, and `set_process()` needs
-                    //       to be called after this function before the plugin may process audio again.
-
-                    // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
-                    // f32` to a `&mut [&mut f32]` in the process call
-                    self.inner
-                        .output_buffer
-                        .borrow_mut()
-                        .with_raw_vec(|output_slices| {
-                            output_slices
-                                .resize_with(bus_config.num_output_channels as usize, || &mut [])
-                        });
-
-                    // Also allocate both the buffers and the slices pointing to those buffers for
-                    // sidechain inputs. The slices will be assigned in the process function as this
-                    // object may have been moved before then.
-                    let mut aux_input_storage = self.inner.aux_input_storage.borrow_mut();
-                    aux_input_storage
-                        .resize_with(bus_config.aux_input_busses.num_busses as usize, Vec::new);
-                    for bus_storage in aux_input_storage.iter_mut() {
-                        bus_storage.resize_with(
-                            bus_config.aux_input_busses.num_channels as usize,
-                            Vec::new,
-                        );
-                        for channel_storage in bus_storage {
-                            channel_storage.resize(buffer_config.max_buffer_size as usize, 0.0);
+                let initialized = self.inner.catch_plugin_panic("initializing", || {
+                    if can_change_sample_rate_in_place::<P>(
+                        self.inner.initialized_config.load(),
+                        &bus_config,
+                        &buffer_config,
+                    ) {
+                        plugin.sample_rate_changed(buffer_config.sample_rate);
+                        self.inner.skip_next_reset.store(true, Ordering::SeqCst);
+                        Ok(())
+                    } else {
+                        let _alloc_phase = self.inner.alloc_stats.enter(AllocPhase::Initialize);
+                        self.inner.state_restored.notify(&mut *plugin);
+                        let result =
+                            plugin.try_initialize(&bus_config, &buffer_config, &mut init_context);
+                        if result.is_ok() {
+                            self.inner
+                                .initialized_config
+                                .store(Some((bus_config, buffer_config)));
                         }
-                    }
 
-                    let mut aux_input_buffers = self.inner.aux_input_buffers.borrow_mut();
-                    aux_input_buffers.resize_with(
-                        bus_config.aux_input_busses.num_busses as usize,
-                        Buffer::default,
-                    );
-                    for buffer in aux_input_buffers.iter_mut() {
-                        buffer.with_raw_vec(|channel_slices| {
-                            channel_slices.resize_with(
-                                bus_config.aux_input_busses.num_channels as usize,
-                                || &mut [],
-                            )
-                        });
+                        result
+                    }
+                });
+                if !matches!(initialized, Some(Ok(()))) {
+                    if let Some(Err(err)) = initialized {
+                        nih_error!("Failed to initialize the plugin: {}", err);
                     }
 
-                    // And the same thing for the output buffers
-                    let mut aux_output_buffers = self.inner.aux_output_buffers.borrow_mut();
-                    aux_output_buffers.resize_with(
-                        bus_config.aux_output_busses.num_busses as usize,
-                        Buffer::default,
-                    );
-                    for buffer in aux_output_buffers.iter_mut() {
-                        buffer.with_raw_vec(|channel_slices| {
-                            channel_slices.resize_with(
-                                bus_config.aux_output_busses.num_channels as usize,
-                                || &mut [],
-                            )
-                        });
+                    self.inner.initialized_config.store(None);
+                    self.inner.set_lifecycle_state(LifecycleState::Setup);
+                    return kResultFalse;
+                }
+
+                // NOTE: We don't call `Plugin::reset()` here. The call is done in `set_process()`
+                //       instead. Otherwise we would call the function twice, and `set_process()` needs
+                //       to be called after this function before the plugin may process audio again.
+
+                // Preallocate enough room in the output slices vector so we can convert a `*mut *mut
+                // f32` to a `&mut [&mut f32]` in the process call
+                self.inner
+                    .output_buffer
+                    .borrow_mut()
+                    .with_raw_vec(|output_slices| {
+                        output_slices
+                            .resize_with(bus_config.num_output_channels as usize, || &mut [])
+                    });
+                *self.inner.channel_adapter.borrow_mut() = ChannelAdapter::new(
+                    &self.inner.current_bus_config.load(),
+                    &bus_config,
+                    host_buffer_config.max_buffer_size as usize,
+                );
+                self.inner.reserve_event_buffers();
+
+                // Also allocate both the buffers and the slices pointing to those buffers for
+                // sidechain inputs. The slices will be assigned in the process function as this
+                // object may have been moved before then.
+                let mut aux_input_storage = self.inner.aux_input_storage.borrow_mut();
+                aux_input_storage
+                    .resize_with(bus_config.aux_input_busses.num_busses as usize, Vec::new);
+                for bus_storage in aux_input_storage.iter_mut() {
+                    bus_storage
+                        .resize_with(bus_config.aux_input_busses.num_channels as usize, Vec::new);
+                    for channel_storage in bus_storage {
+                        channel_storage.resize(host_buffer_config.max_buffer_size as usize, 0.0);
                     }
+                }
 
-                    kResultOk
-                } else {
-                    kResultFalse
+                let mut aux_input_buffers = self.inner.aux_input_buffers.borrow_mut();
+                aux_input_buffers.resize_with(
+                    bus_config.aux_input_busses.num_busses as usize,
+                    Buffer::default,
+                );
+                for buffer in aux_input_buffers.iter_mut() {
+                    buffer.with_raw_vec(|channel_slices| {
+                        channel_slices
+                            .resize_with(bus_config.aux_input_busses.num_channels as usize, || {
+                                &mut []
+                            })
+                    });
+                }
+
+                // And the same thing for the output buffers
+                let mut aux_output_buffers = self.inner.aux_output_buffers.borrow_mut();
+                aux_output_buffers.resize_with(
+                    bus_config.aux_output_busses.num_busses as usize,
+                    Buffer::default,
+                );
+                for buffer in aux_output_buffers.iter_mut() {
+                    buffer.with_raw_vec(|channel_slices| {
+                        channel_slices
+                            .resize_with(bus_config.aux_output_busses.num_channels as usize, || {
+                                &mut []
+                            })
+                    });
                 }
+
+                // The latency set during initialization is only applied when the init context is
+                // dropped, and the bypass's delay lines need to be sized for that latency
+                drop(plugin);
+                drop(init_context);
+                if self.inner.wrapper_bypass_param.is_some() {
+                    self.inner.bypass_processor.borrow_mut().setup(
+                        bus_config.num_output_channels as usize,
+                        host_buffer_config.max_buffer_size as usize,
+                        self.inner.current_latency.load(Ordering::SeqCst) as usize,
+                        host_buffer_config.sample_rate,
+                    );
+                }
+                self.inner
+                    .oversampling
+                    .setup(&bus_config, host_buffer_config.max_buffer_size as usize);
+
+                kResultOk
+            }
+            (true, None) => {
+                nih_debug_assert_failure!(
+                    "The host tried to activate the plugin before setting up processing"
+                );
+                kNotInitialized
             }
-            (true, None) => kResultFalse,
             (false, _) => {
-                self.inner.plugin.lock().deactivate();
+                // The plugin should not be deactivated if it was never initialized
+                if matches!(
+                    lifecycle_state,
+                    LifecycleState::Active | LifecycleState::Processing
+                ) {
+                    self.inner.set_lifecycle_state(LifecycleState::Setup);
+                    if !self.inner.is_poisoned.load(Ordering::SeqCst) {
+                        let mut plugin = self.inner.plugin.lock();
+                        self.inner
+                            .catch_plugin_panic("deactivating", || plugin.deactivate());
+                    }
+                    self.inner.alloc_stats.log_summary(P::NAME);
+                } else {
+                    nih_trace!(
+                        "Ignoring IComponent::setActive(false) in state {lifecycle_state:?}"
+                    );
+                }
 
                 kResultOk
             }
@@ -469,7 +574,14 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
     unsafe fn set_state(&self, state: SharedVstPtr<dyn IBStream>) -> tresult {
         check_null_ptr!(state);
 
+        // See `WrapperInner::is_poisoned`, the plugin instance needs to be reloaded by the host
+        if self.inner.is_poisoned.load(Ordering::SeqCst) {
+            return kResultFalse;
+        }
+
         let state = state.upgrade().unwrap();
+        let _state_load_lock = self.inner.state_load_lock.lock();
+        let _alloc_phase = self.inner.alloc_stats.enter(AllocPhase::StateLoad);
 
         // We need to know how large the state is before we can read it. The current position can be
         // zero, but it can also be something else. Bitwig prepends the preset header in the stream,
@@ -502,29 +614,57 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
             return kResultFalse;
         }
 
-        let success = state::deserialize_json::<P>(
-            &read_buffer,
-            self.inner.params.clone(),
-            state::make_params_getter(&self.inner.param_by_hash, &self.inner.param_id_to_hash),
-            self.inner.current_buffer_config.load().as_ref(),
-        );
-        if !success {
+        // Restoring the parameter values also runs the parameters' callbacks
+        let success = self.inner.catch_plugin_panic("loading state", || {
+            state::deserialize_json::<P>(
+                &read_buffer,
+                self.inner.params.clone(),
+                state::make_params_getter(
+                    &self.inner.param_by_hash,
+                    &self.inner.param_id_to_hash,
+                    &self.inner.legacy_param_id_to_hash,
+                ),
+                self.inner.plugin_buffer_config().as_ref(),
+                self.inner.is_processing(),
+                &self.inner.instance_id,
+                &self.inner.param_value_validator,
+            )
+        });
+        if success != Some(true) {
             return kResultFalse;
         }
+        self.inner.state_dirty.store(false, Ordering::SeqCst);
+        self.inner.state_restored.mark();
 
         // Reinitialize the plugin after loading state so it can respond to the new parameter values
+        self.inner
+            .param_changes
+            .record_all(ParamChangeSource::HostStateLoad);
         self.inner.notify_param_values_changed();
 
-        if let Some(buffer_config) = self.inner.current_buffer_config.load() {
+        if let Some(buffer_config) = self.inner.plugin_buffer_config() {
             // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
             let mut init_context = self.inner.make_init_context();
-            let bus_config = self.inner.current_bus_config.load();
+            let bus_config = self.inner.plugin_bus_config.load();
             let mut plugin = self.inner.plugin.lock();
-            plugin.initialize(&bus_config, &buffer_config, &mut init_context);
-            // TODO: This also goes for the CLAP version, but should we call reset here? Won't the
-            //       host always restart playback? Check this with a couple of hosts and remove the
-            //       duplicate reset if it's not needed.
-            process_wrapper(|| plugin.reset());
+            let reinitialized = self.inner.catch_plugin_panic("loading state", || {
+                self.inner.state_restored.notify(&mut *plugin);
+                if let Err(err) =
+                    plugin.try_initialize(&bus_config, &buffer_config, &mut init_context)
+                {
+                    nih_error!(
+                        "Failed to reinitialize the plugin after loading state: {}",
+                        err
+                    );
+                }
+                // TODO: This also goes for the CLAP version, but should we call reset here? Won't
+                //       the host always restart playback? Check this with a couple of hosts and
+                //       remove the duplicate reset if it's not needed.
+                process_wrapper(|| plugin.reset());
+            });
+            if reinitialized.is_none() {
+                return kResultFalse;
+            }
         }
 
         nih_trace!("Loaded state ({} bytes)", read_buffer.len());
@@ -537,12 +677,16 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
 
         let state = state.upgrade().unwrap();
 
-        let serialized = state::serialize_json::<P>(
-            self.inner.params.clone(),
-            state::make_params_iter(&self.inner.param_by_hash, &self.inner.param_id_to_hash),
-        );
+        // Serializing the plugin's persistent fields calls into the plugin's own code
+        let serialized = self.inner.catch_plugin_panic("saving state", || {
+            state::serialize_json::<P>(
+                self.inner.params.clone(),
+                state::make_params_iter(&self.inner.param_by_hash, &self.inner.param_id_to_hash),
+                self.inner.instance_id.load(),
+            )
+        });
         match serialized {
-            Ok(serialized) => {
+            Some(Ok(serialized)) => {
                 let mut num_bytes_written = 0;
                 let result = state.write(
                     serialized.as_ptr() as *const c_void,
@@ -552,15 +696,17 @@ impl<P: Vst3Plugin> IComponent for Wrapper<P> {
 
                 nih_debug_assert_eq!(result, kResultOk);
                 nih_debug_assert_eq!(num_bytes_written as usize, serialized.len());
+                self.inner.state_dirty.store(false, Ordering::SeqCst);
 
                 nih_trace!("Saved state ({} bytes)", serialized.len());
 
                 kResultOk
             }
-            Err(err) => {
+            Some(Err(err)) => {
                 nih_debug_assert_failure!("Could not save state: {:#}", err);
                 kResultFalse
             }
+            None => kResultFalse,
         }
     }
 }
@@ -599,7 +745,7 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
     ) -> tresult {
         check_null_ptr!(info);
 
-        if param_index < 0 || param_index > self.get_parameter_count() {
+        if param_index < 0 || param_index >= self.get_parameter_count() {
             return kInvalidArgument;
         }
 
@@ -636,8 +782,8 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
             let param_ptr = &self.inner.param_by_hash[param_hash];
             let default_value = param_ptr.default_normalized_value();
             let flags = param_ptr.flags();
-            let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE);
-            let hidden = flags.contains(ParamFlags::HIDDEN);
+            let read_only = flags.contains(ParamFlags::READ_ONLY);
+            let automatable = !flags.contains(ParamFlags::NON_AUTOMATABLE) && !read_only;
             let is_bypass = flags.contains(ParamFlags::BYPASS);
 
             info.id = *param_hash;
@@ -648,11 +794,12 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
             info.default_normalized_value = default_value as f64;
             info.unit_id = *param_unit;
             info.flags = 0;
-            if automatable && !hidden {
+            // Hidden parameters are not part of `param_hashes`, so they never end up here
+            if automatable {
                 info.flags |= ParameterFlags::kCanAutomate as i32;
             }
-            if hidden {
-                info.flags |= ParameterFlags::kIsReadOnly as i32 | (1 << 4); // kIsHidden
+            if read_only {
+                info.flags |= ParameterFlags::kIsReadOnly as i32;
             }
             if is_bypass {
                 info.flags |= ParameterFlags::kIsBypass as i32;
@@ -676,10 +823,16 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
         //       should be fine right? They should be hidden anyways.
         match self.inner.param_by_hash.get(&id) {
             Some(param_ptr) => {
-                u16strlcpy(
-                    dest,
-                    &param_ptr.normalized_value_to_string(value_normalized as f32, false),
-                );
+                // The parameter's formatter is the plugin's own code
+                let value_string = match self
+                    .inner
+                    .catch_plugin_panic("formatting a parameter value", || {
+                        param_ptr.normalized_value_to_string(value_normalized as f32, false)
+                    }) {
+                    Some(value_string) => value_string,
+                    None => return kResultFalse,
+                };
+                u16strlcpy(dest, &value_string);
 
                 kResultOk
             }
@@ -702,7 +855,13 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
 
         match self.inner.param_by_hash.get(&id) {
             Some(param_ptr) => {
-                let value = match param_ptr.string_to_normalized_value(&string) {
+                let value = match self
+                    .inner
+                    .catch_plugin_panic("parsing a parameter value", || {
+                        param_ptr.string_to_normalized_value(&string)
+                    })
+                    .flatten()
+                {
                     Some(v) => v as f64,
                     None => return kResultFalse,
                 };
@@ -736,20 +895,40 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
     }
 
     unsafe fn set_param_normalized(&self, id: u32, value: f64) -> tresult {
-        // If the plugin is currently processing audio, then this parameter change will also be sent
-        // to the process function
-        if self.inner.is_processing.load(Ordering::SeqCst) {
+        // If the plugin is currently processing audio, then the change is applied at the start of
+        // the next process call. The host will often also send the same change through the process
+        // function's parameter queues, and in that case the queued value takes precedence. Hosts
+        // may also use this function for changes that won't end up in those queues though, so
+        // these changes can't simply be ignored.
+        if self.inner.is_processing() {
+            match self.inner.param_by_hash.get(&id) {
+                Some(param_ptr) if param_ptr.flags().contains(ParamFlags::READ_ONLY) => {
+                    return kResultFalse
+                }
+                Some(_) => (),
+                None => return kInvalidArgument,
+            }
+
+            if self
+                .inner
+                .controller_param_changes
+                .push((id, value as f32))
+                .is_err()
+            {
+                nih_debug_assert_failure!(
+                    "The queue for parameter changes made during processing is full, dropping \
+                     the change"
+                );
+            }
+
             return kResultOk;
         }
 
-        let sample_rate = self
-            .inner
-            .current_buffer_config
-            .load()
-            .map(|c| c.sample_rate);
-        let result = self
-            .inner
-            .set_normalized_value_by_hash(id, value as f32, sample_rate);
+        let result = self.inner.set_normalized_value_by_hash_unsmoothed(
+            id,
+            value as f32,
+            ParamChangeSource::HostAutomation,
+        );
         self.inner.notify_param_values_changed();
 
         result
@@ -759,7 +938,16 @@ impl<P: Vst3Plugin> IEditController for Wrapper<P> {
         &self,
         handler: SharedVstPtr<dyn vst3_sys::vst::IComponentHandler>,
     ) -> tresult {
-        *self.inner.component_handler.borrow_mut() = handler.upgrade().map(VstPtr::from);
+        let handler = handler.upgrade().map(VstPtr::from);
+        *self.inner.component_handler2.borrow_mut() = handler
+            .as_ref()
+            .and_then(|handler| handler.cast::<dyn IComponentHandler2>())
+            .map(VstPtr::from);
+        *self.inner.component_handler3.borrow_mut() = handler
+            .as_ref()
+            .and_then(|handler| handler.cast::<dyn IComponentHandler3>())
+            .map(VstPtr::from);
+        *self.inner.component_handler.borrow_mut() = handler;
 
         kResultOk
     }
@@ -795,18 +983,25 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             return kInvalidArgument;
         }
 
+        // The host cannot add input busses the plugin doesn't have, like an input for a generator
+        let has_main_input = has_main_input_bus::<P>();
+        let max_input_busses = if has_main_input { 1 } else { 0 }
+            + P::DEFAULT_AUX_INPUTS.unwrap_or_default().num_busses as i32;
+        if num_ins > max_input_busses {
+            return kResultFalse;
+        }
+
         // Every auxiliary input or output needs to have the same number of channels. In order to
         // support plugins with no main IO but with auxiliary IO, we'll need to take that into
         // account when asserting this. If that's the case, then the first bus for that direction
         // will have been marked auxiliary.
-        let aux_inputs_only = P::DEFAULT_INPUT_CHANNELS == 0 && P::DEFAULT_AUX_INPUTS.is_some();
-        let num_input_channels = if aux_inputs_only || num_ins < 1 {
+        let num_input_channels = if !has_main_input || num_ins < 1 {
             0
         } else {
             (*inputs).count_ones()
         };
 
-        let aux_input_start_idx = if aux_inputs_only { 0 } else { 1 };
+        let aux_input_start_idx = if has_main_input { 1 } else { 0 };
         let num_aux_input_busses = (num_ins as u32).saturating_sub(aux_input_start_idx);
         let num_aux_input_channels = if num_aux_input_busses == 0 {
             0
@@ -857,17 +1052,19 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 num_channels: num_aux_output_channels,
             },
         };
-        if self
+        let plugin_config = self
             .inner
-            .plugin
-            .lock()
-            .accepts_bus_config(&proposed_config)
-        {
-            self.inner.current_bus_config.store(proposed_config);
+            .catch_plugin_panic("checking a bus configuration", || {
+                channel_adapter::plugin_bus_config(&*self.inner.plugin.lock(), &proposed_config)
+            });
+        match plugin_config.flatten() {
+            Some(plugin_config) => {
+                self.inner.current_bus_config.store(proposed_config);
+                self.inner.plugin_bus_config.store(plugin_config);
 
-            kResultOk
-        } else {
-            kResultFalse
+                kResultOk
+            }
+            None => kResultFalse,
         }
     }
 
@@ -903,9 +1100,9 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
 
         let bus_config = self.inner.current_bus_config.load();
         let num_channels = if dir == vst3_sys::vst::BusDirections::kInput as i32 {
-            let aux_inputs_only = P::DEFAULT_INPUT_CHANNELS == 0 && P::DEFAULT_AUX_INPUTS.is_some();
-            let aux_input_start_idx = if aux_inputs_only { 0 } else { 1 };
-            if !aux_inputs_only && index == 0 {
+            let has_main_input = has_main_input_bus::<P>();
+            let aux_input_start_idx = if has_main_input { 1 } else { 0 };
+            if has_main_input && index == 0 {
                 bus_config.num_input_channels
             } else if (aux_input_start_idx
                 ..(aux_input_start_idx + bus_config.aux_input_busses.num_busses as i32))
@@ -955,16 +1152,26 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
     unsafe fn setup_processing(&self, setup: *const vst3_sys::vst::ProcessSetup) -> tresult {
         check_null_ptr!(setup);
 
+        // The sample rate and block size may not change while the plugin is processing audio
+        if self.inner.is_processing() {
+            nih_debug_assert_failure!(
+                "The host tried to set up processing while the plugin is processing audio"
+            );
+            return kResultFalse;
+        }
+
         // There's no special handling for offline processing at the moment
         let setup = &*setup;
-        nih_debug_assert_eq!(
-            setup.symbolic_sample_size,
-            vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32
-        );
+        // Hosts are supposed to check `can_process_sample_size()` first, but some of them will
+        // still try to set up double precision processing
+        if setup.symbolic_sample_size != vst3_sys::vst::SymbolicSampleSizes::kSample32 as i32 {
+            return kResultFalse;
+        }
 
         // This is needed when activating the plugin and when restoring state
         self.inner.current_buffer_config.store(Some(BufferConfig {
             sample_rate: setup.sample_rate as f32,
+            // VST3 does not tell us anything about the smallest block the host may send
             min_buffer_size: None,
             max_buffer_size: setup.max_samples_per_block as u32,
             process_mode: self.inner.current_process_mode.load(),
@@ -980,6 +1187,9 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             }
         };
         self.inner.current_process_mode.store(mode);
+        if self.inner.lifecycle_state.load() == LifecycleState::Created {
+            self.inner.set_lifecycle_state(LifecycleState::Setup);
+        }
 
         // Initializing the plugin happens in `IAudioProcessor::set_active()` because the host may
         // still change the channel layouts at this point
@@ -990,13 +1200,57 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
     unsafe fn set_processing(&self, state: TBool) -> tresult {
         let state = state != 0;
 
+        // The plugin can only process audio after it has been initialized
+        match (state, self.inner.lifecycle_state.load()) {
+            (true, LifecycleState::Active | LifecycleState::Processing) => {
+                self.inner.set_lifecycle_state(LifecycleState::Processing)
+            }
+            (true, lifecycle_state) => {
+                nih_debug_assert_failure!(
+                    "The host tried to start processing audio in state {:?}",
+                    lifecycle_state
+                );
+                return kNotInitialized;
+            }
+            (false, LifecycleState::Processing) => {
+                self.inner.set_lifecycle_state(LifecycleState::Active)
+            }
+            (false, _) => (),
+        }
+
         // Always reset the processing status when the plugin gets activated or deactivated
         self.inner.last_process_status.store(ProcessStatus::Normal);
-        self.inner.is_processing.store(state, Ordering::SeqCst);
+
+        // Parameter changes made during the last process call that have not yet been picked up by
+        // the audio thread should not get lost
+        if !state {
+            let mut parameter_values_changed = false;
+            while let Some((hash, normalized_value)) = self.inner.controller_param_changes.pop() {
+                self.inner.set_normalized_value_by_hash_unsmoothed(
+                    hash,
+                    normalized_value,
+                    ParamChangeSource::HostAutomation,
+                );
+                parameter_values_changed = true;
+            }
+            for (hash, normalized_value) in self.inner.pending_param_batch.lock().drain(..) {
+                self.inner.set_normalized_value_by_hash_unsmoothed(
+                    hash,
+                    normalized_value,
+                    ParamChangeSource::GuiGesture,
+                );
+                parameter_values_changed = true;
+            }
+
+            if parameter_values_changed {
+                self.inner.notify_param_values_changed();
+            }
+        }
 
         // This function is also used to reset buffers on the plugin, so we should do the same
-        // thing. We don't call `reset()` in `setup_processing()` for that same reason.
-        if state {
+        // thing. We don't call `reset()` in `setup_processing()` for that same reason. A plugin
+        // that panicked while processing audio should not be touched again.
+        if state && !self.inner.is_poisoned.load(Ordering::SeqCst) {
             // HACK: See the comment in `IComponent::setActive()`. This is needed to work around
             //       Ardour bugs.
             let mut plugin = match self.inner.plugin.try_lock() {
@@ -1013,7 +1267,25 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 }
             };
 
-            process_wrapper(|| plugin.reset());
+            self.inner.midi_input_router.borrow_mut().reset();
+            self.inner.transport_tracker.borrow_mut().reset();
+            if let Some(buffer_config) = self.inner.current_buffer_config.load() {
+                self.inner
+                    .activation_fade
+                    .borrow_mut()
+                    .start::<P>(buffer_config.sample_rate);
+            }
+            self.inner
+                .bypass_processor
+                .borrow_mut()
+                .reset(self.inner.wrapper_bypassed());
+            self.inner.oversampling.reset();
+            // The plugin keeps its state when only the sample rate was changed, see
+            // `Plugin::PREALLOCATES_FOR_MAX_SAMPLE_RATE`
+            if !self.inner.skip_next_reset.swap(false, Ordering::SeqCst) {
+                self.inner
+                    .catch_plugin_panic("resetting", || process_wrapper(|| plugin.reset()));
+            }
         }
 
         // We don't have any special handling for suspending and resuming plugins, yet
@@ -1028,8 +1300,20 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
         // Panic on allocations if the `assert_process_allocs` feature has been enabled, and make
         // sure that FTZ is set up correctly
         process_wrapper(|| {
-            // We need to handle incoming automation first
             let data = &*data;
+
+            // The plugin can only process audio after it has been initialized. Some hosts never
+            // call `IAudioProcessor::setProcessing()`, so processing is also allowed while the
+            // plugin is merely active. In any other state the host gets silence instead.
+            if !matches!(
+                self.inner.lifecycle_state.load(),
+                LifecycleState::Active | LifecycleState::Processing
+            ) {
+                clear_outputs(data, 0);
+                return kNotInitialized;
+            }
+
+            // We need to handle incoming automation first
             let sample_rate = self
                 .inner
                 .current_buffer_config
@@ -1044,6 +1328,13 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             );
             nih_debug_assert!(data.num_samples >= 0);
 
+            // If the plugin panicked during an earlier process call then we can't trust its state
+            // anymore, so we'll just output silence until the host reloads the plugin
+            if self.inner.is_poisoned.load(Ordering::SeqCst) {
+                clear_outputs(data, 0);
+                return kResultFalse;
+            }
+
             // Before doing anything, clear out any auxiliary outputs since they may contain
             // uninitialized data when the host assumes that we'll always write something there
             let current_bus_config = self.inner.current_bus_config.load();
@@ -1051,19 +1342,43 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             // HACK: Bitwig requires VST3 plugins to always have a main output. We'll however still
             //       use this variable here to maintain consistency between the backends.
             let has_main_output = true;
-            if !data.outputs.is_null() {
-                for output_idx in if has_main_output { 1 } else { 0 }..data.num_outputs as isize {
-                    let host_output = data.outputs.offset(output_idx);
-                    if !(*host_output).buffers.is_null() {
-                        for channel_idx in 0..(*host_output).num_channels as isize {
-                            ptr::write_bytes(
-                                *((*host_output).buffers.offset(channel_idx)) as *mut f32,
-                                0,
-                                data.num_samples as usize,
-                            );
-                        }
-                    }
-                }
+            clear_outputs(data, if has_main_output { 1 } else { 0 });
+
+            // The host should always pass the main channel counts negotiated through
+            // `IAudioProcessor::setBusArrangements()`, but not every host does. Some hosts pass
+            // fewer channels while the plugin is bypassed, and a host could also change the layout
+            // without renegotiating it. The plugin is not processed in that case, the host gets
+            // silence, and the host is asked to renegotiate the layout. A missing main input is
+            // treated the same as a silent input.
+            let num_host_input_channels = if has_main_input {
+                host_main_channel_count(data.inputs, data.num_inputs)
+            } else {
+                0
+            };
+            let num_host_output_channels = host_main_channel_count(data.outputs, data.num_outputs);
+            let host_channels_mismatched = data.num_samples > 0
+                && ((current_bus_config.num_output_channels > 0
+                    && num_host_output_channels
+                        != current_bus_config.num_output_channels as usize)
+                    || (num_host_input_channels > 0
+                        && num_host_input_channels
+                            != current_bus_config.num_input_channels as usize));
+            if self
+                .inner
+                .set_host_channel_mismatch(host_channels_mismatched)
+            {
+                permit_alloc(|| {
+                    nih_log!(
+                        "The host passed {num_host_input_channels} input and \
+                         {num_host_output_channels} output channels while {} and {} channels \
+                         were negotiated, outputting silence until the layout is renegotiated",
+                        current_bus_config.num_input_channels,
+                        current_bus_config.num_output_channels
+                    )
+                });
+            }
+            if host_channels_mismatched {
+                clear_outputs(data, 0);
             }
 
             // If `P::SAMPLE_ACCURATE_AUTOMATION` is set, then we'll split up the audio buffer into
@@ -1076,6 +1391,21 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
             let mut parameter_values_changed = false;
             process_events.clear();
 
+            // Parameter changes made from the GUI as part of a batch are all applied at the start of
+            // the same block. If the GUI thread is currently adding a batch, then that batch will
+            // be applied during the next process call instead.
+            if let Some(mut pending_param_batch) = self.inner.pending_param_batch.try_lock() {
+                for (hash, normalized_value) in pending_param_batch.drain(..) {
+                    self.inner.set_normalized_value_by_hash(
+                        hash,
+                        normalized_value,
+                        Some(sample_rate),
+                        ParamChangeSource::GuiGesture,
+                    );
+                    parameter_values_changed = true;
+                }
+            }
+
             // First we'll go through the parameter changes. This may also include MIDI CC messages
             // if the plugin supports those
             if let Some(param_changes) = data.input_param_changes.upgrade() {
@@ -1090,6 +1420,22 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                             continue;
                         }
 
+                        // The ramps between the queue's points are followed in plain value space
+                        // for parameters that opted in to that
+                        let linear_plain_param_ptr = if P::SAMPLE_ACCURATE_AUTOMATION {
+                            self.inner
+                                .param_by_hash
+                                .get(&param_hash)
+                                .filter(|param_ptr| {
+                                    param_ptr
+                                        .flags()
+                                        .contains(ParamFlags::LINEAR_PLAIN_AUTOMATION)
+                                })
+                        } else {
+                            None
+                        };
+                        let mut previous_point: Option<(u32, f32)> = None;
+
                         let mut sample_offset = 0i32;
                         let mut value = 0.0f64;
                         for change_idx in 0..num_changes {
@@ -1141,16 +1487,51 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                         },
                                     });
                                 } else if P::SAMPLE_ACCURATE_AUTOMATION {
+                                    // If there's only a single point then there's no ramp to
+                                    // follow, and the change is applied at its sample offset like
+                                    // any other change. The intermediate changes are dropped if
+                                    // they would cause the event buffer to reallocate.
+                                    if let (
+                                        Some(param_ptr),
+                                        Some((previous_timing, previous_value)),
+                                    ) = (linear_plain_param_ptr, previous_point)
+                                    {
+                                        let previous_plain =
+                                            param_ptr.preview_plain(previous_value);
+                                        let plain = param_ptr.preview_plain(value);
+                                        let mut intermediate_timing =
+                                            previous_timing + LINEAR_PLAIN_AUTOMATION_INTERVAL;
+                                        while intermediate_timing < timing
+                                            && process_events.len() + 1 < process_events.capacity()
+                                        {
+                                            let t = (intermediate_timing - previous_timing) as f32
+                                                / (timing - previous_timing) as f32;
+                                            process_events.push(ProcessEvent::ParameterChange {
+                                                timing: intermediate_timing,
+                                                hash: param_hash,
+                                                normalized_value: param_ptr.preview_normalized(
+                                                    previous_plain + (plain - previous_plain) * t,
+                                                ),
+                                                source: ParamChangeSource::HostAutomation,
+                                            });
+
+                                            intermediate_timing += LINEAR_PLAIN_AUTOMATION_INTERVAL;
+                                        }
+                                    }
+                                    previous_point = Some((timing, value));
+
                                     process_events.push(ProcessEvent::ParameterChange {
                                         timing,
                                         hash: param_hash,
                                         normalized_value: value,
+                                        source: ParamChangeSource::HostAutomation,
                                     });
                                 } else {
                                     self.inner.set_normalized_value_by_hash(
                                         param_hash,
                                         value,
                                         Some(sample_rate),
+                                        ParamChangeSource::HostAutomation,
                                     );
                                     parameter_values_changed = true;
                                 }
@@ -1160,6 +1541,75 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 }
             }
 
+            // Parameter changes the host made through `IEditController::setParamNormalized()` while
+            // we were processing are applied now, unless this block's parameter queues also contain
+            // a change for that same parameter
+            while let Some((hash, normalized_value)) = self.inner.controller_param_changes.pop() {
+                let in_param_queues = data
+                    .input_param_changes
+                    .upgrade()
+                    .map(|param_changes| {
+                        (0..param_changes.get_parameter_count()).any(|change_queue_idx| {
+                            param_changes
+                                .get_parameter_data(change_queue_idx)
+                                .upgrade()
+                                .map(|param_change_queue| {
+                                    param_change_queue.get_parameter_id() == hash
+                                })
+                                .unwrap_or(false)
+                        })
+                    })
+                    .unwrap_or(false);
+                if !in_param_queues {
+                    self.inner.set_normalized_value_by_hash(
+                        hash,
+                        normalized_value,
+                        Some(sample_rate),
+                        ParamChangeSource::HostAutomation,
+                    );
+                    parameter_values_changed = true;
+                }
+            }
+
+            // Changes the editor scheduled for the next beat or bar are applied at the first
+            // sample of that beat or bar. Without sample accurate automation they're applied at the
+            // start of the block containing the boundary instead. The host is informed of the new
+            // values through the output parameter changes.
+            self.inner.musical_scheduler.take_due(
+                &host_transport(data, sample_rate, 0),
+                data.num_samples.max(0) as u32,
+                |hash, timing, normalized_value| {
+                    if P::SAMPLE_ACCURATE_AUTOMATION
+                        && process_events.len() < process_events.capacity()
+                    {
+                        process_events.push(ProcessEvent::ParameterChange {
+                            timing,
+                            hash,
+                            normalized_value,
+                            source: ParamChangeSource::GuiGesture,
+                        });
+                    } else {
+                        self.inner.set_normalized_value_by_hash(
+                            hash,
+                            normalized_value,
+                            Some(sample_rate),
+                            ParamChangeSource::GuiGesture,
+                        );
+                        parameter_values_changed = true;
+                    }
+
+                    let success = self
+                        .inner
+                        .output_param_changes
+                        .push((hash, normalized_value));
+                    nih_debug_assert!(
+                        success.is_ok(),
+                        "The output parameter queue is full, the new value will not be sent to \
+                         the host"
+                    );
+                },
+            );
+
             // Then we'll add all of our input events
             if P::MIDI_INPUT >= MidiConfig::Basic {
                 let mut note_expression_controller =
@@ -1229,18 +1679,40 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                             });
                         } else if event.type_ == EventTypes::kNoteExpressionValueEvent as u16 {
                             let event = event.event.note_expression_value;
-                            match note_expression_controller.translate_event(timing, &event) {
-                                Some(translated_event) => {
-                                    process_events.push(ProcessEvent::NoteEvent {
-                                        timing,
-                                        event: translated_event,
-                                    })
+                            match note_expressions::note_expression_for_type_id(event.type_id) {
+                                // Hosts may still send expressions the plugin didn't declare, and
+                                // those should not reach the plugin
+                                Some(expression)
+                                    if !P::SUPPORTED_NOTE_EXPRESSIONS.contains(expression) => {}
+                                Some(_) => {
+                                    // This returns `None` if the note ID is unknown, in which case
+                                    // there's no note to apply the expression to
+                                    if let Some(translated_event) =
+                                        note_expression_controller.translate_event(timing, &event)
+                                    {
+                                        process_events.push(ProcessEvent::NoteEvent {
+                                            timing,
+                                            event: translated_event,
+                                        })
+                                    }
                                 }
                                 None => nih_debug_assert_failure!(
                                     "Unhandled note expression type: {}",
                                     event.type_id
                                 ),
                             }
+                        } else if event.type_ == EventTypes::kNoteExpressionTextEvent as u16 {
+                            // Text and phoneme expressions (e.g. lyrics for vocal synths) can't be
+                            // represented as a `NoteEvent` without allocating on the audio thread,
+                            // so these are explicitly ignored instead of being treated as unknown
+                            // events
+                            let event = event.event.note_expression_text;
+                            nih_debug_assert!(
+                                event.type_id == note_expressions::TEXT_EXPRESSION_ID
+                                    || event.type_id == note_expressions::PHONEME_EXPRESSION_ID,
+                                "Unhandled note expression text type: {}",
+                                event.type_id
+                            );
                         }
                     }
                 }
@@ -1260,6 +1732,42 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 })
             });
 
+            // Dense automation would otherwise split the block into hundreds of tiny sub-blocks.
+            // Changes from the editor are always kept at their sample offsets.
+            if P::SAMPLE_ACCURATE_AUTOMATION {
+                let num_coalesced = param_coalescing::coalesce_param_changes(
+                    &mut process_events,
+                    &mut self.inner.automation_split_points.borrow_mut(),
+                    data.num_samples as u32,
+                    P::MAX_AUTOMATION_SPLITS,
+                    P::MIN_AUTOMATION_SPLIT_LENGTH,
+                    |hash, source| {
+                        source != ParamChangeSource::HostAutomation
+                            || self
+                                .inner
+                                .param_by_hash
+                                .get(&hash)
+                                .map_or(false, |param_ptr| {
+                                    param_ptr.flags().contains(ParamFlags::EXACT_AUTOMATION)
+                                })
+                    },
+                );
+                if num_coalesced > 0
+                    && self
+                        .inner
+                        .num_coalesced_param_changes
+                        .fetch_add(num_coalesced as u32, Ordering::Relaxed)
+                        == 0
+                {
+                    permit_alloc(|| {
+                        nih_log!(
+                            "The host sent more parameter changes than the block can be split \
+                             for, merging {num_coalesced} parameter changes"
+                        )
+                    });
+                }
+            }
+
             let mut block_start = 0usize;
             let mut block_end;
             let mut event_start_idx = 0;
@@ -1273,6 +1781,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 // The extra scope is here to make sure we release the borrow on input_events
                 {
                     let mut input_events = self.inner.input_events.borrow_mut();
+                    let mut midi_input_router = self.inner.midi_input_router.borrow_mut();
                     input_events.clear();
 
                     block_end = data.num_samples as usize;
@@ -1282,6 +1791,7 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                 timing,
                                 hash,
                                 normalized_value,
+                                source,
                             } => {
                                 // If this parameter change happens after the start of this block, then
                                 // we'll split the block here and handle this parameter change after
@@ -1296,17 +1806,27 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                                     hash,
                                     normalized_value,
                                     Some(sample_rate),
+                                    source,
                                 );
                                 parameter_values_changed = true;
                             }
-                            ProcessEvent::NoteEvent {
-                                timing: _,
-                                mut event,
-                            } => {
+                            ProcessEvent::NoteEvent { timing, mut event } => {
+                                // With sample accurate events the block is also split just before
+                                // every note event that happens after the start of this block
+                                if P::SAMPLE_ACCURATE_EVENTS
+                                    && timing > block_start as u32
+                                    && (timing as i32) < data.num_samples
+                                {
+                                    event_start_idx = event_idx;
+                                    block_end = timing as usize;
+                                    break;
+                                }
+
                                 // We need to make sure to compensate the event for any block splitting,
                                 // since we had to create the event object beforehand
                                 event.subtract_timing(block_start as u32);
-                                input_events.push_back(event);
+                                midi_input_router
+                                    .route(event, |event| input_events.push_back(event));
                             }
                         }
                     }
@@ -1319,27 +1839,61 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 }
 
                 // This vector has been preallocated to contain enough slices as there are output
-                // channels. In case the does does not provide an output or if they don't provide
-                // all of the channels (this should not happen, but Ableton Live might do it) then
-                // we'll skip the process function.
+                // channels. In case the does does not provide an output or if the host's channel
+                // counts don't match the negotiated layout, then we'll skip the process function.
                 let mut output_buffer = self.inner.output_buffer.borrow_mut();
+                let mut channel_adapter = self.inner.channel_adapter.borrow_mut();
                 let mut buffer_is_valid = false;
                 output_buffer.with_raw_vec(|output_slices| {
+                    if host_channels_mismatched {
+                        // There should never be any dangling references to previous data
+                        output_slices.fill_with(|| &mut []);
+                        return;
+                    }
+
                     // Buffers for zero-channel plugins like note effects should always be allowed
                     buffer_is_valid = output_slices.is_empty();
 
-                    if !data.outputs.is_null() {
-                        let num_output_channels = (*data.outputs).num_channels as usize;
-                        buffer_is_valid = num_output_channels == output_slices.len();
-                        nih_debug_assert_eq!(num_output_channels, output_slices.len());
+                    // If the host's main channel layout is being adapted, then the plugin processes
+                    // the adapter's buffers instead. The host's main input is copied to those
+                    // buffers here, and the results are copied back to the host after processing.
+                    if let Some(channel_adapter) = channel_adapter.as_mut() {
+                        buffer_is_valid = num_host_output_channels > 0;
+
+                        if num_host_input_channels > 0 {
+                            channel_adapter.read_inputs(
+                                (*data.inputs).buffers as *const *const f32,
+                                num_host_input_channels,
+                                block_start,
+                                block_end - block_start,
+                            );
+                        } else {
+                            channel_adapter.read_inputs(
+                                ptr::null(),
+                                0,
+                                block_start,
+                                block_end - block_start,
+                            );
+                        }
+
+                        for (output_channel_slice, channel_storage) in output_slices
+                            .iter_mut()
+                            .zip(channel_adapter.storage_mut().iter_mut())
+                        {
+                            // SAFETY: The same reasoning as for the auxiliary input buffers below
+                            //         applies here
+                            *output_channel_slice = &mut *(&mut channel_storage
+                                [block_start..block_end]
+                                as *mut [f32]);
+                        }
+                    } else if num_host_output_channels > 0 {
+                        // The channel counts have been checked above, so this only fails for
+                        // plugins without a main output
+                        buffer_is_valid = num_host_output_channels == output_slices.len();
 
-                        // In case the host does provide fewer output channels than we expect, we
-                        // should still try to handle that gracefully. This happens when the plugin
-                        // is bypassed in Ableton Live and a parameter is modified. In that case the
-                        // above assertion will still trigger.
                         for (output_channel_idx, output_channel_slice) in output_slices
                             .iter_mut()
-                            .take(num_output_channels)
+                            .take(num_host_output_channels)
                             .enumerate()
                         {
                             // If `P::SAMPLE_ACCURATE_AUTOMATION` is set, then we may be iterating
@@ -1361,9 +1915,9 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 // Some hosts process data in place, in which case we don't need to do any copying
                 // ourselves. If the pointers do not alias, then we'll do the copy here and then the
                 // plugin can just do normal in place processing.
-                if !data.outputs.is_null() && !data.inputs.is_null() {
-                    let num_output_channels = (*data.outputs).num_channels as usize;
-                    let num_input_channels = (*data.inputs).num_channels as usize;
+                if channel_adapter.is_none() && !host_channels_mismatched {
+                    let num_output_channels = num_host_output_channels;
+                    let num_input_channels = num_host_input_channels;
                     nih_debug_assert!(
                         num_input_channels <= num_output_channels,
                         "Stereo to mono and similar configurations are not supported"
@@ -1381,6 +1935,18 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                             );
                         }
                     }
+
+                    // Output channels without a matching input channel would otherwise still
+                    // contain whatever the host left in them
+                    for output_channel_idx in num_input_channels..num_output_channels {
+                        let output_channel_ptr =
+                            *((*data.outputs).buffers as *mut *mut f32).add(output_channel_idx);
+                        ptr::write_bytes(
+                            output_channel_ptr.add(block_start),
+                            0,
+                            block_end - block_start,
+                        );
+                    }
                 }
 
                 // We'll need to do the same thing for auxiliary input sidechain buffers. Since we
@@ -1394,7 +1960,9 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     .zip(aux_input_buffers.iter_mut())
                     .enumerate()
                 {
-                    let host_input_idx = if has_main_input {
+                    // The main input bus still exists if the host negotiated zero channels for
+                    // it, so this needs to look at the bus layout instead of the channel count
+                    let host_input_idx = if has_main_input_bus::<P>() {
                         auxiliary_input_idx as isize + 1
                     } else {
                         auxiliary_input_idx as isize
@@ -1463,10 +2031,12 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                         || data.outputs.is_null()
                         || (*host_output).buffers.is_null()
                         || buffer.channels() == 0
+                        || (*host_output).num_channels != buffer.channels() as i32
                     {
                         nih_debug_assert!(host_output_idx < data.num_outputs as isize);
                         nih_debug_assert!(!data.outputs.is_null());
                         nih_debug_assert!(!(*host_output).buffers.is_null());
+                        nih_debug_assert_eq!((*host_output).num_channels, buffer.channels() as i32);
 
                         // If the host passes weird data then we need to be very sure that there are
                         // no dangling references to previous data
@@ -1486,69 +2056,31 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     });
                 }
 
-                // Some of the fields are left empty because VST3 does not provide this
-                // information, but the methods on [`Transport`] can reconstruct these values
-                // from the other fields
-                let mut transport = Transport::new(sample_rate);
-                if !data.context.is_null() {
-                    let context = &*data.context;
-
-                    // These constants are missing from vst3-sys, see:
-                    // https://steinbergmedia.github.io/vst3_doc/vstinterfaces/structSteinberg_1_1Vst_1_1ProcessContext.html
-                    transport.playing = context.state & (1 << 1) != 0; // kPlaying
-                    transport.recording = context.state & (1 << 3) != 0; // kRecording
-                    if context.state & (1 << 10) != 0 {
-                        // kTempoValid
-                        transport.tempo = Some(context.tempo);
-                    }
-                    if context.state & (1 << 13) != 0 {
-                        // kTimeSigValid
-                        transport.time_sig_numerator = Some(context.time_sig_num);
-                        transport.time_sig_denominator = Some(context.time_sig_den);
-                    }
-
-                    // We need to compensate for the block splitting here
-                    transport.pos_samples = Some(context.project_time_samples + block_start as i64);
-                    if context.state & (1 << 9) != 0 {
-                        // kProjectTimeMusicValid
-                        if P::SAMPLE_ACCURATE_AUTOMATION
-                            && block_start > 0
-                            && (context.state & (1 << 10) != 0)
-                        {
-                            // kTempoValid
-                            transport.pos_beats = Some(
-                                context.project_time_music
-                                    + (block_start as f64 / sample_rate as f64 / 60.0
-                                        * context.tempo),
-                            );
-                        } else {
-                            transport.pos_beats = Some(context.project_time_music);
-                        }
-                    }
-
-                    if context.state & (1 << 11) != 0 {
-                        // kBarPositionValid
-                        if P::SAMPLE_ACCURATE_AUTOMATION && block_start > 0 {
-                            // The transport object knows how to recompute this from the other information
-                            transport.bar_start_pos_beats = match transport.bar_start_pos_beats() {
-                                Some(updated) => Some(updated),
-                                None => Some(context.bar_position_music),
-                            };
-                        } else {
-                            transport.bar_start_pos_beats = Some(context.bar_position_music);
-                        }
-                    }
-                    if context.state & (1 << 2) != 0 && context.state & (1 << 12) != 0 {
-                        // kCycleActive && kCycleValid
-                        transport.loop_range_beats =
-                            Some((context.cycle_start_music, context.cycle_end_music));
-                    }
-                }
-
-                let result = if buffer_is_valid {
-                    // NOTE: `parking_lot`'s mutexes sometimes allocate because of their use of
-                    //       thread locals
-                    let mut plugin = permit_alloc(|| self.inner.plugin.lock());
+                let mut transport = host_transport(data, sample_rate, block_start);
+                self.inner
+                    .transport_tracker
+                    .borrow_mut()
+                    .update(&mut transport, block_end - block_start);
+
+                // The wrapper's own bypass parameter crossfades to the latency compensated input
+                // in realtime, and switches to it instantly when rendering offline. The plugin
+                // isn't processed once the bypass is fully engaged.
+                let mut bypass_processor = self.inner.bypass_processor.borrow_mut();
+                let should_process = buffer_is_valid
+                    && bypass_processor.begin_block(
+                        &output_buffer,
+                        self.inner.wrapper_bypassed(),
+                        self.inner.current_process_mode.load().bypass_mode(),
+                    );
+                // The audio thread never waits for another thread that's using the plugin. The
+                // block is silent instead.
+                let plugin = if should_process {
+                    self.inner.try_lock_plugin_for_processing()
+                } else {
+                    None
+                };
+                let plugin_contended = should_process && plugin.is_none();
+                let result = if let Some(mut plugin) = plugin {
                     // SAFETY: Shortening these borrows is safe as even if the plugin overwrites the
                     //         slices (which it cannot do without using unsafe code), then they
                     //         would still be reset on the next iteration
@@ -1557,12 +2089,58 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                         outputs: &mut *(aux_output_buffers.as_mut_slice() as *mut [Buffer]),
                     };
                     let mut context = self.inner.make_process_context(transport);
-                    let result = plugin.process(&mut output_buffer, &mut aux, &mut context);
+                    // `is_poisoned` makes sure the plugin doesn't process any more audio after a
+                    // panic
+                    let result = match self.inner.catch_plugin_panic("processing audio", || {
+                        self.inner.oversampling.process(
+                            &mut output_buffer,
+                            &mut aux,
+                            |buffer, aux| plugin.process(buffer, aux, &mut context),
+                        )
+                    }) {
+                        Some(result) => result,
+                        None => {
+                            clear_outputs(data, 0);
+                            return kResultFalse;
+                        }
+                    };
                     self.inner.last_process_status.store(result);
+
+                    // This is applied to the plugin's output before it's converted to the host's
+                    // channel layout
+                    self.inner
+                        .activation_fade
+                        .borrow_mut()
+                        .apply(&mut output_buffer);
+
                     result
                 } else {
+                    if plugin_contended {
+                        for channel in output_buffer.as_slice() {
+                            channel.fill(0.0);
+                        }
+                    }
+                    self.inner
+                        .activation_fade
+                        .borrow_mut()
+                        .skip(block_end - block_start);
+
                     ProcessStatus::Normal
                 };
+                if buffer_is_valid {
+                    bypass_processor.end_block(&mut output_buffer);
+                }
+
+                if let Some(channel_adapter) = channel_adapter.as_ref() {
+                    if buffer_is_valid && num_host_output_channels > 0 {
+                        channel_adapter.write_outputs(
+                            (*data.outputs).buffers as *const *mut f32,
+                            num_host_output_channels,
+                            block_start,
+                            block_end - block_start,
+                        );
+                    }
+                }
 
                 // Send any events output by the plugin during the process cycle
                 if let Some(events) = data.output_events.upgrade() {
@@ -1761,6 +2339,48 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                     }
                 }
 
+                // The values the plugin set for its read-only output parameters are sent to the
+                // host the same way. The queue is always drained, even if the host did not pass an
+                // output parameter changes object.
+                let output_param_changes = data.output_param_changes.upgrade();
+                let add_output_point = |hash: u32, normalized_value: f32| {
+                    if let Some(output_param_changes) = &output_param_changes {
+                        let mut queue_idx = 0;
+                        match output_param_changes
+                            .add_parameter_data(&hash, &mut queue_idx)
+                            .upgrade()
+                        {
+                            Some(queue) => {
+                                let mut point_idx = 0;
+                                let result = queue.add_point(
+                                    block_start as i32,
+                                    normalized_value as f64,
+                                    &mut point_idx,
+                                );
+                                nih_debug_assert_eq!(result, kResultOk);
+                            }
+                            None => nih_debug_assert_failure!(
+                                "The host did not provide an output parameter queue"
+                            ),
+                        }
+                    }
+                };
+                let mut output_param_values_changed = false;
+                while let Some((hash, normalized_value)) = self.inner.output_param_changes.pop() {
+                    output_param_values_changed = true;
+                    add_output_point(hash, normalized_value);
+                }
+                if output_param_values_changed {
+                    self.inner.notify_param_values_changed();
+                }
+
+                // Parameters the editor armed for writing modulation to the host get their
+                // modulated values sent as automation. This is only done once per process call to
+                // avoid flooding the host's automation lanes.
+                if block_end as i32 == data.num_samples && output_param_changes.is_some() {
+                    self.inner.modulation_writer.write_changed(add_output_point);
+                }
+
                 let result = match result {
                     ProcessStatus::Error(err) => {
                         nih_debug_assert_failure!("Process error: {}", err);
@@ -1780,46 +2400,86 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
                 }
             };
 
+            // A new oversampling factor can only be applied by reactivating the plugin. Hosts do
+            // that when the latency changes, which it does with the new factor.
+            if self.inner.oversampling.factor_changed() {
+                let task_posted = self
+                    .inner
+                    .schedule_gui(Task::TriggerRestart(RestartFlags::kLatencyChanged as i32));
+                nih_debug_assert!(task_posted, "The task queue is full, dropping task...");
+            }
+
             // After processing audio, we'll check if the editor has sent us updated plugin state.
             // We'll restore that here on the audio thread to prevent changing the values during the
             // process call and also to prevent inconsistent state when the host also wants to load
-            // plugin state.
-            // FIXME: Zero capacity channels allocate on receiving, find a better alternative that
-            //        doesn't do that
-            let updated_state = permit_alloc(|| self.inner.updated_state_receiver.try_recv());
-            if let Ok(mut state) = updated_state {
-                state::deserialize_object::<P>(
-                    &mut state,
-                    self.inner.params.clone(),
-                    state::make_params_getter(
-                        &self.inner.param_by_hash,
-                        &self.inner.param_id_to_hash,
-                    ),
-                    self.inner.current_buffer_config.load().as_ref(),
-                );
-
-                self.inner.notify_param_values_changed();
-
+            // plugin state. If another thread is using the plugin, then the state is left in the
+            // channel and we'll try again at the end of the next process call.
+            if self.inner.updated_state_pending.load(Ordering::SeqCst)
+                && !self.inner.is_poisoned.load(Ordering::SeqCst)
+            {
                 // NOTE: This needs to be dropped after the `plugin` lock to avoid deadlocks
                 let mut init_context = self.inner.make_init_context();
-                let bus_config = self.inner.current_bus_config.load();
-                let buffer_config = self.inner.current_buffer_config.load().unwrap();
-                let mut plugin = self.inner.plugin.lock();
-                // FIXME: This is obviously not realtime-safe, but loading presets without doing
-                //         this could lead to inconsistencies. It's the plugin's responsibility to
-                //         not perform any realtime-unsafe work when the initialize function is
-                //         called a second time if it supports runtime preset loading.
-                permit_alloc(|| plugin.initialize(&bus_config, &buffer_config, &mut init_context));
-                plugin.reset();
-
-                // We'll pass the state object back to the GUI thread so deallocation can happen
-                // there without potentially blocking the audio thread
-                if let Err(err) = self.inner.updated_state_sender.send(state) {
-                    nih_debug_assert_failure!(
-                        "Failed to send state object back to GUI thread: {}",
-                        err
-                    );
-                };
+                if let Some(mut plugin) = self.inner.try_lock_plugin_for_processing() {
+                    // FIXME: Zero capacity channels allocate on receiving, find a better
+                    //        alternative that doesn't do that
+                    let updated_state =
+                        permit_alloc(|| self.inner.updated_state_receiver.try_recv());
+                    if let Ok(mut state) = updated_state {
+                        // The state is still passed back to the GUI thread if the plugin panics
+                        self.inner.catch_plugin_panic("loading state", || {
+                            state::deserialize_object::<P>(
+                                &mut state,
+                                self.inner.params.clone(),
+                                state::make_params_getter(
+                                    &self.inner.param_by_hash,
+                                    &self.inner.param_id_to_hash,
+                                    &self.inner.legacy_param_id_to_hash,
+                                ),
+                                self.inner.plugin_buffer_config().as_ref(),
+                                true,
+                                &self.inner.param_value_validator,
+                            );
+
+                            self.inner.state_restored.mark();
+                            self.inner
+                                .param_changes
+                                .record_all(ParamChangeSource::Internal);
+                            self.inner.notify_param_values_changed();
+
+                            let bus_config = self.inner.plugin_bus_config.load();
+                            let buffer_config = self.inner.plugin_buffer_config().unwrap();
+                            // FIXME: This is obviously not realtime-safe, but loading presets
+                            //         without doing this could lead to inconsistencies. It's the
+                            //         plugin's responsibility to not perform any realtime-unsafe
+                            //         work when the initialize function is called a second time if
+                            //         it supports runtime preset loading.
+                            permit_alloc(|| {
+                                self.inner.state_restored.notify(&mut *plugin);
+                                if let Err(err) = plugin.try_initialize(
+                                    &bus_config,
+                                    &buffer_config,
+                                    &mut init_context,
+                                ) {
+                                    nih_error!(
+                                        "Failed to reinitialize the plugin after loading state: \
+                                         {}",
+                                        err
+                                    );
+                                }
+                            });
+                            plugin.reset();
+                        });
+
+                        // We'll pass the state object back to the GUI thread so deallocation can
+                        // happen there without potentially blocking the audio thread
+                        if let Err(err) = self.inner.updated_state_sender.send(state) {
+                            nih_debug_assert_failure!(
+                                "Failed to send state object back to GUI thread: {}",
+                                err
+                            );
+                        };
+                    }
+                }
             }
 
             result
@@ -1836,6 +2496,109 @@ impl<P: Vst3Plugin> IAudioProcessor for Wrapper<P> {
     }
 }
 
+/// Build the [`Transport`] for the block starting at `block_start` from the host's process context.
+/// The song position is advanced to the start of the block when the buffer has been split.
+unsafe fn host_transport(
+    data: &vst3_sys::vst::ProcessData,
+    sample_rate: f32,
+    block_start: usize,
+) -> Transport {
+    // Some of the fields are left empty because VST3 does not provide this information, but the
+    // methods on [`Transport`] can reconstruct these values from the other fields
+    let mut transport = Transport::new(sample_rate);
+    if !data.context.is_null() {
+        let context = &*data.context;
+
+        // These constants are missing from vst3-sys, see:
+        // https://steinbergmedia.github.io/vst3_doc/vstinterfaces/structSteinberg_1_1Vst_1_1ProcessContext.html
+        transport.playing = context.state & (1 << 1) != 0; // kPlaying
+        transport.recording = context.state & (1 << 3) != 0; // kRecording
+        if context.state & (1 << 10) != 0 {
+            // kTempoValid
+            transport.tempo = Some(context.tempo);
+        }
+        if context.state & (1 << 13) != 0 {
+            // kTimeSigValid
+            transport.time_sig_numerator = Some(context.time_sig_num);
+            transport.time_sig_denominator = Some(context.time_sig_den);
+        }
+
+        // We need to compensate for the block splitting here
+        transport.pos_samples = Some(context.project_time_samples + block_start as i64);
+        if context.state & (1 << 9) != 0 {
+            // kProjectTimeMusicValid
+            // Blocks can be split for both sample accurate automation and events
+            if block_start > 0 && (context.state & (1 << 10) != 0) {
+                // kTempoValid
+                transport.pos_beats = Some(
+                    context.project_time_music
+                        + (block_start as f64 / sample_rate as f64 / 60.0 * context.tempo),
+                );
+            } else {
+                transport.pos_beats = Some(context.project_time_music);
+            }
+        }
+
+        if context.state & (1 << 11) != 0 {
+            // kBarPositionValid
+            if block_start > 0 {
+                // The transport object knows how to recompute this from the other information
+                transport.bar_start_pos_beats = match transport.bar_start_pos_beats() {
+                    Some(updated) => Some(updated),
+                    None => Some(context.bar_position_music),
+                };
+            } else {
+                transport.bar_start_pos_beats = Some(context.bar_position_music);
+            }
+        }
+        if context.state & (1 << 2) != 0 && context.state & (1 << 12) != 0 {
+            // kCycleActive && kCycleValid
+            transport.loop_range_beats = Some((context.cycle_start_music, context.cycle_end_music));
+        }
+    }
+
+    transport
+}
+
+/// Fill all of the host's output buffers starting at output bus `start_idx` with silence.
+unsafe fn clear_outputs(data: &vst3_sys::vst::ProcessData, start_idx: isize) {
+    if data.outputs.is_null() || data.num_samples <= 0 {
+        return;
+    }
+
+    for output_idx in start_idx..data.num_outputs as isize {
+        let host_output = data.outputs.offset(output_idx);
+        if !(*host_output).buffers.is_null() {
+            for channel_idx in 0..(*host_output).num_channels as isize {
+                let channel_ptr = *((*host_output).buffers.offset(channel_idx)) as *mut f32;
+                if !channel_ptr.is_null() {
+                    ptr::write_bytes(channel_ptr, 0, data.num_samples as usize);
+                }
+            }
+        }
+    }
+}
+
+/// The number of channels in the first of the host's `num_busses` audio busses, which is the main
+/// bus if the plugin has one. This is zero if the host did not pass any busses or channel pointers.
+unsafe fn host_main_channel_count(
+    busses: *const vst3_sys::vst::AudioBusBuffers,
+    num_busses: i32,
+) -> usize {
+    if busses.is_null() || num_busses <= 0 || (*busses).buffers.is_null() {
+        0
+    } else {
+        (*busses).num_channels.max(0) as usize
+    }
+}
+
+/// Whether the plugin exposes a main audio input bus to the host. Plugins without a default main
+/// input, like generators and instruments, don't get an empty main input bus. Their auxiliary
+/// inputs start at bus index 0 instead.
+fn has_main_input_bus<P: Vst3Plugin>() -> bool {
+    P::DEFAULT_INPUT_CHANNELS > 0
+}
+
 impl<P: Vst3Plugin> IMidiMapping for Wrapper<P> {
     unsafe fn get_midi_controller_assignment(
         &self,
@@ -1844,8 +2607,7 @@ impl<P: Vst3Plugin> IMidiMapping for Wrapper<P> {
         midi_cc_number: vst3_com::vst::CtrlNumber,
         param_id: *mut vst3_com::vst::ParamID,
     ) -> tresult {
-        if P::MIDI_INPUT < MidiConfig::MidiCCs
-            || bus_index != 0
+        if bus_index != 0
             || !(0..VST3_MIDI_CHANNELS as i16).contains(&channel)
             || !(0..VST3_MIDI_CCS as i16).contains(&midi_cc_number)
         {
@@ -1854,6 +2616,32 @@ impl<P: Vst3Plugin> IMidiMapping for Wrapper<P> {
 
         check_null_ptr!(param_id);
 
+        // Mappings to the plugin's own parameters take precedence over the MIDI CC parameters
+        let mapped_param_id = match self.inner.catch_plugin_panic("mapping a MIDI CC", || {
+            P::vst3_midi_cc_mapping(channel as u8, midi_cc_number as u8)
+        }) {
+            Some(mapped_param_id) => mapped_param_id,
+            None => return kResultFalse,
+        };
+        if let Some(mapped_param_id) = mapped_param_id {
+            match self.inner.param_id_to_hash.get(mapped_param_id) {
+                Some(hash) => {
+                    *param_id = *hash;
+                    return kResultOk;
+                }
+                None => nih_debug_assert_failure!(
+                    "MIDI CC {} on channel {} is mapped to unknown parameter '{}'",
+                    midi_cc_number,
+                    channel,
+                    mapped_param_id
+                ),
+            }
+        }
+
+        if P::MIDI_INPUT < MidiConfig::MidiCCs {
+            return kResultFalse;
+        }
+
         // We reserve a contiguous parameter range right at the end of the allowed parameter indices
         // for these MIDI CC parameters
         *param_id =
@@ -1867,7 +2655,8 @@ impl<P: Vst3Plugin> INoteExpressionController for Wrapper<P> {
     unsafe fn get_note_expression_count(&self, bus_idx: i32, _channel: i16) -> i32 {
         // Apparently you need to define the predefined note expressions. Thanks VST3.
         if P::MIDI_INPUT >= MidiConfig::Basic && bus_idx == 0 {
-            note_expressions::KNOWN_NOTE_EXPRESSIONS.len() as i32
+            note_expressions::supported_note_expressions(P::SUPPORTED_NOTE_EXPRESSIONS).count()
+                as i32
         } else {
             0
         }
@@ -1880,21 +2669,22 @@ impl<P: Vst3Plugin> INoteExpressionController for Wrapper<P> {
         note_expression_idx: i32,
         info: *mut NoteExpressionTypeInfo,
     ) -> tresult {
-        if P::MIDI_INPUT < MidiConfig::Basic
-            || bus_idx != 0
-            || !(0..note_expressions::KNOWN_NOTE_EXPRESSIONS.len() as i32)
-                .contains(&note_expression_idx)
-        {
+        if P::MIDI_INPUT < MidiConfig::Basic || bus_idx != 0 || note_expression_idx < 0 {
             return kInvalidArgument;
         }
+        let note_expression_info =
+            match note_expressions::supported_note_expressions(P::SUPPORTED_NOTE_EXPRESSIONS)
+                .nth(note_expression_idx as usize)
+            {
+                Some(note_expression_info) => note_expression_info,
+                None => return kInvalidArgument,
+            };
 
         check_null_ptr!(info);
 
         *info = mem::zeroed();
 
         let info = &mut *info;
-        let note_expression_info =
-            &note_expressions::KNOWN_NOTE_EXPRESSIONS[note_expression_idx as usize];
         info.type_id = note_expression_info.type_id;
         u16strlcpy(&mut info.title, note_expression_info.title);
         u16strlcpy(&mut info.short_title, note_expression_info.title);
@@ -1939,15 +2729,36 @@ impl<P: Vst3Plugin> INoteExpressionController for Wrapper<P> {
 
 impl<P: Vst3Plugin> IProcessContextRequirements for Wrapper<P> {
     unsafe fn get_process_context_requirements(&self) -> u32 {
-        IProcessContextRequirementsFlags::kNeedProjectTimeMusic
-            | IProcessContextRequirementsFlags::kNeedBarPositionMusic
-            | IProcessContextRequirementsFlags::kNeedCycleMusic
-            | IProcessContextRequirementsFlags::kNeedTimeSignature
-            | IProcessContextRequirementsFlags::kNeedTempo
-            | IProcessContextRequirementsFlags::kNeedTransportState
+        process_context_requirements(P::REQUIRED_TRANSPORT)
     }
 }
 
+/// Convert the plugin's transport requirements to VST3's `IProcessContextRequirements` flags. The
+/// position in samples is always provided by the host.
+pub(super) fn process_context_requirements(requirements: TransportRequirements) -> u32 {
+    let mut flags = 0;
+    if requirements.contains(TransportRequirements::TRANSPORT_STATE) {
+        flags |= IProcessContextRequirementsFlags::kNeedTransportState;
+    }
+    if requirements.contains(TransportRequirements::TEMPO) {
+        flags |= IProcessContextRequirementsFlags::kNeedTempo;
+    }
+    if requirements.contains(TransportRequirements::TIME_SIGNATURE) {
+        flags |= IProcessContextRequirementsFlags::kNeedTimeSignature;
+    }
+    if requirements.contains(TransportRequirements::PROJECT_TIME) {
+        flags |= IProcessContextRequirementsFlags::kNeedProjectTimeMusic;
+    }
+    if requirements.contains(TransportRequirements::BAR_POSITION) {
+        flags |= IProcessContextRequirementsFlags::kNeedBarPositionMusic;
+    }
+    if requirements.contains(TransportRequirements::LOOP_RANGE) {
+        flags |= IProcessContextRequirementsFlags::kNeedCycleMusic;
+    }
+
+    flags
+}
+
 impl<P: Vst3Plugin> IUnitInfo for Wrapper<P> {
     unsafe fn get_unit_count(&self) -> i32 {
         self.inner.param_units.len() as i32
@@ -2049,3 +2860,22 @@ impl<P: Vst3Plugin> IUnitInfo for Wrapper<P> {
         kInvalidArgument
     }
 }
+
+impl<P: Vst3Plugin> IInfoListener for Wrapper<P> {
+    unsafe fn set_channel_context_infos(&self, list: SharedVstPtr<dyn IAttributeList>) -> tresult {
+        let list = match list.upgrade() {
+            Some(list) => VstPtr::from(list),
+            None => return kInvalidArgument,
+        };
+
+        // Every call replaces the previous information, since hosts send all of the track's
+        // information at once
+        let mut channel_info = self.inner.channel_info.lock();
+        let change_count = channel_info
+            .as_ref()
+            .map_or(0, |info| info.change_count.wrapping_add(1));
+        *channel_info = Some(channel_context::read_channel_info(&list, change_count));
+
+        kResultOk
+    }
+}
@@ -0,0 +1,261 @@
+//! Subcategories for VST3 plugins. This is the VST3 equivalent of
+//! [`ClapFeature`][super::clap::features::ClapFeature]. Hosts use these to sort plugins in their
+//! plugin browsers, and some hosts treat plugins differently depending on whether they're
+//! instruments or effects.
+//!
+//! This module is not behind the `vst3` feature because
+//! [`Vst3Plugin`][crate::prelude::Vst3Plugin] refers to it.
+
+/// The size of the `subCategories` field in the VST3 class info structs, including the null
+/// terminator.
+pub(crate) const SUBCATEGORIES_FIELD_SIZE: usize = 128;
+
+/// The tokens from the VST3 SDK's list of subcategories that can't be expressed through
+/// [`Vst3SubCategory`], either because the wrappers add them themselves or because they don't
+/// apply to NIH-plug plugins. These are still accepted in
+/// [`Vst3Plugin::VST3_CATEGORIES`][crate::prelude::Vst3Plugin::VST3_CATEGORIES].
+const OTHER_KNOWN_TOKENS: &[&str] = &[
+    "OnlyRT",
+    "OnlyOfflineProcess",
+    "NoOfflineProcess",
+    "OnlyARA",
+];
+
+/// A subcategory for a VST3 plugin. The first subcategory should be either
+/// [`Fx`][Self::Fx], [`Instrument`][Self::Instrument], or [`Spatial`][Self::Spatial], followed by
+/// one or more of the more specific subcategories. See
+/// <https://github.com/steinbergmedia/vst3_pluginterfaces/blob/2ad397ade5b51007860bedb3b01b8afd2c5f6fba/vst/ivstaudioprocessor.h#L49-L90>
+/// for the combinations used by the VST3 SDK.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vst3SubCategory {
+    // These are the main categories, every plugin should have at least one of these
+    Fx,
+    Instrument,
+    Spatial,
+    // These are optional
+    Analyzer,
+    Delay,
+    Distortion,
+    Drum,
+    Dynamics,
+    Eq,
+    External,
+    Filter,
+    Generator,
+    Mastering,
+    Modulation,
+    Network,
+    Piano,
+    PitchShift,
+    Restoration,
+    Reverb,
+    Sampler,
+    Synth,
+    Tools,
+    UpDownmix,
+    // These are used for plugins that _only_ support this channel configuration, they're also
+    // optional
+    Mono,
+    Stereo,
+    Surround,
+    Ambisonics,
+    /// A non-predefined subcategory. Hosts may display this among its plugin categories. This is
+    /// not checked against the list of known subcategories, and it cannot contain a pipe
+    /// character.
+    Custom(&'static str),
+}
+
+impl Vst3SubCategory {
+    /// Every subcategory except for [`Custom`][Self::Custom].
+    pub const ALL: &'static [Vst3SubCategory] = &[
+        Vst3SubCategory::Fx,
+        Vst3SubCategory::Instrument,
+        Vst3SubCategory::Spatial,
+        Vst3SubCategory::Analyzer,
+        Vst3SubCategory::Delay,
+        Vst3SubCategory::Distortion,
+        Vst3SubCategory::Drum,
+        Vst3SubCategory::Dynamics,
+        Vst3SubCategory::Eq,
+        Vst3SubCategory::External,
+        Vst3SubCategory::Filter,
+        Vst3SubCategory::Generator,
+        Vst3SubCategory::Mastering,
+        Vst3SubCategory::Modulation,
+        Vst3SubCategory::Network,
+        Vst3SubCategory::Piano,
+        Vst3SubCategory::PitchShift,
+        Vst3SubCategory::Restoration,
+        Vst3SubCategory::Reverb,
+        Vst3SubCategory::Sampler,
+        Vst3SubCategory::Synth,
+        Vst3SubCategory::Tools,
+        Vst3SubCategory::UpDownmix,
+        Vst3SubCategory::Mono,
+        Vst3SubCategory::Stereo,
+        Vst3SubCategory::Surround,
+        Vst3SubCategory::Ambisonics,
+    ];
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Vst3SubCategory::Fx => "Fx",
+            Vst3SubCategory::Instrument => "Instrument",
+            Vst3SubCategory::Spatial => "Spatial",
+            Vst3SubCategory::Analyzer => "Analyzer",
+            Vst3SubCategory::Delay => "Delay",
+            Vst3SubCategory::Distortion => "Distortion",
+            Vst3SubCategory::Drum => "Drum",
+            Vst3SubCategory::Dynamics => "Dynamics",
+            Vst3SubCategory::Eq => "EQ",
+            Vst3SubCategory::External => "External",
+            Vst3SubCategory::Filter => "Filter",
+            Vst3SubCategory::Generator => "Generator",
+            Vst3SubCategory::Mastering => "Mastering",
+            Vst3SubCategory::Modulation => "Modulation",
+            Vst3SubCategory::Network => "Network",
+            Vst3SubCategory::Piano => "Piano",
+            Vst3SubCategory::PitchShift => "Pitch Shift",
+            Vst3SubCategory::Restoration => "Restoration",
+            Vst3SubCategory::Reverb => "Reverb",
+            Vst3SubCategory::Sampler => "Sampler",
+            Vst3SubCategory::Synth => "Synth",
+            Vst3SubCategory::Tools => "Tools",
+            Vst3SubCategory::UpDownmix => "Up-Downmix",
+            Vst3SubCategory::Mono => "Mono",
+            Vst3SubCategory::Stereo => "Stereo",
+            Vst3SubCategory::Surround => "Surround",
+            Vst3SubCategory::Ambisonics => "Ambisonics",
+            Vst3SubCategory::Custom(s) => s,
+        }
+    }
+}
+
+/// Join `subcategories` into the pipe separated string stored in the VST3 class info structs.
+/// `OnlyRT` is appended when `hard_realtime_only` is set. If the result does not fit in the VST3
+/// struct's field, whole subcategories are removed from the end of the string until it does fit, so
+/// hosts never see a subcategory that has been cut in half. The second value is `true` if that
+/// happened.
+pub(crate) fn compose_subcategories(
+    subcategories: &[&str],
+    hard_realtime_only: bool,
+) -> (String, bool) {
+    let mut result = String::new();
+    let mut truncated = false;
+    let only_rt = if hard_realtime_only && !subcategories.contains(&"OnlyRT") {
+        Some("OnlyRT")
+    } else {
+        None
+    };
+
+    for subcategory in subcategories.iter().copied().chain(only_rt) {
+        // There needs to be room for the separator and the null terminator
+        let separator_len = usize::from(!result.is_empty());
+        if result.len() + separator_len + subcategory.len() >= SUBCATEGORIES_FIELD_SIZE {
+            truncated = true;
+            break;
+        }
+
+        if !result.is_empty() {
+            result.push('|');
+        }
+        result.push_str(subcategory);
+    }
+
+    (result, truncated)
+}
+
+/// Whether `token` is one of the subcategories from the VST3 SDK.
+pub(crate) fn is_known_subcategory(token: &str) -> bool {
+    Vst3SubCategory::ALL
+        .iter()
+        .any(|subcategory| subcategory.as_str() == token)
+        || OTHER_KNOWN_TOKENS.contains(&token)
+}
+
+/// A comma separated list of all subcategories accepted by [`is_known_subcategory()`], for use in
+/// error messages.
+pub(crate) fn known_subcategories_list() -> String {
+    Vst3SubCategory::ALL
+        .iter()
+        .map(|subcategory| subcategory.as_str())
+        .chain(OTHER_KNOWN_TOKENS.iter().copied())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compose() {
+        let subcategories: Vec<_> = [Vst3SubCategory::Fx, Vst3SubCategory::PitchShift]
+            .iter()
+            .map(Vst3SubCategory::as_str)
+            .collect();
+        assert_eq!(
+            compose_subcategories(&subcategories, false),
+            (String::from("Fx|Pitch Shift"), false)
+        );
+        assert_eq!(
+            compose_subcategories(&subcategories, true),
+            (String::from("Fx|Pitch Shift|OnlyRT"), false)
+        );
+
+        // `OnlyRT` should not be added twice
+        assert_eq!(
+            compose_subcategories(&["Fx", "OnlyRT"], true),
+            (String::from("Fx|OnlyRT"), false)
+        );
+        assert_eq!(compose_subcategories(&[], false), (String::new(), false));
+    }
+
+    #[test]
+    fn compose_truncates_whole_subcategories() {
+        // 11 of these with 10 separators take up 120 bytes, so a 12th subcategory no longer fits
+        let long = "Subcategor";
+        let (composed, truncated) = compose_subcategories(&[long; 12], false);
+        assert!(truncated);
+        assert_eq!(composed, [long; 11].join("|"));
+
+        // But `|OnlyRT` does, with one byte left for the null terminator
+        let (composed, truncated) = compose_subcategories(&[long; 11], true);
+        assert!(!truncated);
+        assert_eq!(composed, format!("{}|OnlyRT", [long; 11].join("|")));
+        assert_eq!(composed.len(), SUBCATEGORIES_FIELD_SIZE - 1);
+
+        // Subcategories are removed from the end, so `OnlyRT` is dropped along with them
+        let (composed, truncated) = compose_subcategories(&[long; 12], true);
+        assert!(truncated);
+        assert_eq!(composed, [long; 11].join("|"));
+    }
+
+    #[test]
+    fn compose_exact_fit() {
+        // This leaves exactly one byte for the null terminator
+        let exact = "a".repeat(SUBCATEGORIES_FIELD_SIZE - 1 - "|OnlyRT".len());
+        assert_eq!(
+            compose_subcategories(&[&exact], true),
+            (format!("{exact}|OnlyRT"), false)
+        );
+
+        let too_long = format!("{exact}a");
+        assert_eq!(
+            compose_subcategories(&[&too_long], true),
+            (too_long.clone(), true)
+        );
+    }
+
+    #[test]
+    fn known_subcategories() {
+        for subcategory in Vst3SubCategory::ALL {
+            assert!(is_known_subcategory(subcategory.as_str()));
+            assert!(!subcategory.as_str().contains('|'));
+        }
+        assert!(is_known_subcategory("OnlyRT"));
+        assert!(!is_known_subcategory("Delya"));
+        assert!(!is_known_subcategory("fx"));
+        assert!(known_subcategories_list().starts_with("Fx, Instrument, Spatial, "));
+    }
+}